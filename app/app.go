@@ -36,6 +36,7 @@ import (
 	v1_11 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.11"
 	v1_12 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.12"
 	v1_13 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.13"
+	v1_14 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.14"
 	v1_3 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.3"
 	v1_4 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.4"
 	v1_5 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.5"
@@ -43,6 +44,7 @@ import (
 	v1_7 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.7"
 	v1_8 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.8"
 	v1_9 "github.com/scrtlabs/SecretNetwork/app/upgrades/v1.9"
+	enclaveapi "github.com/scrtlabs/SecretNetwork/go-cosmwasm/api"
 
 	icaauthtypes "github.com/scrtlabs/SecretNetwork/x/mauth/types"
 
@@ -109,6 +111,7 @@ var (
 		v1_11.Upgrade,
 		v1_12.Upgrade,
 		v1_13.Upgrade,
+		v1_14.Upgrade,
 	}
 )
 
@@ -293,6 +296,11 @@ func NewSecretNetworkApp(
 	app.BaseApp.SetBeginBlocker(app.BeginBlocker)
 	app.BaseApp.SetEndBlocker(app.EndBlocker)
 
+	// Queries at past heights prove their reads against the committed state
+	if querier, ok := app.BaseApp.CommitMultiStore().(compute.StateQuerier); ok {
+		app.AppKeepers.ComputeKeeper.SetStateQuerier(querier)
+	}
+
 	if manager := app.BaseApp.SnapshotManager(); manager != nil {
 		err := manager.RegisterExtensions(
 			compute.NewWasmSnapshotter(app.BaseApp.CommitMultiStore(), app.AppKeepers.ComputeKeeper, filepath.Join(homePath, ".compute", "wasm", "wasm")),
@@ -326,6 +334,14 @@ func (app *SecretNetworkApp) EndBlocker(ctx sdk.Context, req abci.RequestEndBloc
 	return app.mm.EndBlock(ctx, req)
 }
 
+// Commit commits the block, and then acknowledges the write intents of its executions to the
+// enclave
+func (app *SecretNetworkApp) Commit() abci.ResponseCommit {
+	res := app.BaseApp.Commit()
+	app.AppKeepers.ComputeKeeper.AcknowledgeWriteIntents()
+	return res
+}
+
 // InitChainer application update at chain initialization
 func (app *SecretNetworkApp) InitChainer(ctx sdk.Context, req abci.RequestInitChain) abci.ResponseInitChain {
 	var genesisState simapp.GenesisState
@@ -380,6 +396,23 @@ func (app *SecretNetworkApp) RegisterAPIRoutes(apiSvr *api.Server, apiConfig con
 	if apiConfig.Swagger {
 		RegisterSwaggerAPI(clientCtx, apiSvr.Router)
 	}
+
+	RegisterEnclaveMetrics(apiSvr.Router)
+}
+
+// RegisterEnclaveMetrics serves the metrics of the enclave in the Prometheus text format, for
+// node operators to scrape next to the telemetry of the node
+func RegisterEnclaveMetrics(rtr *mux.Router) {
+	rtr.HandleFunc("/enclave/metrics", func(w http.ResponseWriter, _ *http.Request) {
+		metrics, err := enclaveapi.GetEnclaveMetrics()
+		if err != nil {
+			http.Error(w, err.Error(), http.StatusInternalServerError)
+			return
+		}
+
+		w.Header().Set("Content-Type", "text/plain; version=0.0.4")
+		_, _ = w.Write(metrics)
+	}).Methods(http.MethodGet)
 }
 
 // RegisterSwaggerAPI registers swagger route with API Server
@@ -0,0 +1,52 @@
+package v1_14
+
+import (
+	"encoding/json"
+	"fmt"
+
+	store "github.com/cosmos/cosmos-sdk/store/types"
+	sdk "github.com/cosmos/cosmos-sdk/types"
+	"github.com/cosmos/cosmos-sdk/types/module"
+	upgradetypes "github.com/cosmos/cosmos-sdk/x/upgrade/types"
+	ibctransfertypes "github.com/cosmos/ibc-go/v4/modules/apps/transfer/types"
+	"github.com/scrtlabs/SecretNetwork/app/keepers"
+	"github.com/scrtlabs/SecretNetwork/app/upgrades"
+)
+
+const upgradeName = "v1.14"
+
+var Upgrade = upgrades.Upgrade{
+	UpgradeName:          upgradeName,
+	CreateUpgradeHandler: createUpgradeHandler,
+	StoreUpgrades:        store.StoreUpgrades{},
+}
+
+func createUpgradeHandler(mm *module.Manager, appKeepers *keepers.SecretAppKeepers, configurator module.Configurator,
+) upgradetypes.UpgradeHandler {
+	return func(ctx sdk.Context, _ upgradetypes.Plan, vm module.VersionMap) (module.VersionMap, error) {
+		// From this upgrade on, the enclave only delivers the ibc-hooks callbacks of outgoing
+		// transfers that the contract sent. The packets sent before it have no binding, so the
+		// enclave delivers their callbacks as before.
+		sequences := make(map[string]uint64)
+		channelKeeper := appKeepers.IbcKeeper.ChannelKeeper
+		for _, channel := range channelKeeper.GetAllChannels(ctx) {
+			if channel.PortId != ibctransfertypes.PortID {
+				continue
+			}
+			if sequence, found := channelKeeper.GetNextSequenceSend(ctx, channel.PortId, channel.ChannelId); found {
+				sequences[channel.ChannelId] = sequence
+			}
+		}
+
+		bz, err := json.Marshal(sequences)
+		if err != nil {
+			return nil, err
+		}
+		params := appKeepers.ComputeKeeper.GetParams(ctx)
+		params.CallbackBindingSequences = string(bz)
+		appKeepers.ComputeKeeper.SetParams(ctx, params)
+
+		ctx.Logger().Info(fmt.Sprintf("Running module migrations for %s...", upgradeName))
+		return mm.RunMigrations(ctx, configurator, vm)
+	}
+}
@@ -5,6 +5,7 @@ package main
 
 import (
 	"bytes"
+	"compress/gzip"
 	"encoding/base64"
 	"encoding/hex"
 	"encoding/json"
@@ -14,6 +15,7 @@ import (
 	"net/http"
 	"os"
 	"path/filepath"
+	"time"
 
 	"github.com/cosmos/cosmos-sdk/client"
 	"github.com/cosmos/cosmos-sdk/client/flags"
@@ -32,6 +34,8 @@ const (
 	flagCustomRegistrationService = "registration-service"
 	flag_no_epid                  = "no-epid"
 	flag_no_dcap                  = "no-dcap"
+	flagRenew                     = "renew"
+	flagIterations                = "iterations"
 )
 
 const (
@@ -370,6 +374,168 @@ func HealthCheck() *cobra.Command {
 	return cmd
 }
 
+func AnalyzeContract() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "analyze-contract [wasm file]",
+		Short: "Check a contract against the rules of the enclave",
+		Long: `Run the checks the enclave runs before instantiating a contract (imports, exports, api version and
+floats) on a wasm file, and print the JSON report. The file may be gzipped, like the ones "tx compute store" takes.
+Use it to validate contracts before storing them.`,
+		Args: cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			code, err := os.ReadFile(args[0])
+			if err != nil {
+				return err
+			}
+			if bytes.HasPrefix(code, []byte("\x1F\x8B\x08")) {
+				zr, err := gzip.NewReader(bytes.NewReader(code))
+				if err != nil {
+					return err
+				}
+				code, err = io.ReadAll(zr)
+				if err != nil {
+					return fmt.Errorf("failed to uncompress %s: %w", args[0], err)
+				}
+			}
+
+			report, err := api.AnalyzeContract(code)
+			if err != nil {
+				return fmt.Errorf("failed to analyze the contract. Enclave returned: %s", err)
+			}
+
+			fmt.Println(string(report))
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func EnclaveManifest() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "enclave-manifest",
+		Short: "Print the capability manifest of the enclave",
+		Long: `Print the capabilities of the running enclave, such as the handle types, sign modes, host functions
+and contract features it supports. The manifest is printed as base64 encoded protobuf, see the EnclaveManifest
+message in secret/compute/v1beta1/enclave_manifest.proto.`,
+		Args: cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			manifest, err := api.GetEnclaveManifest()
+			if err != nil {
+				return fmt.Errorf("failed to get the enclave manifest. Enclave returned: %s", err)
+			}
+
+			fmt.Println(base64.StdEncoding.EncodeToString(manifest))
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func ExecutionReceiptKey() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "execution-receipt-key",
+		Short: "Print the public key that the enclaves of the network sign execution receipts with",
+		Long: `Print the Ed25519 public key that the enclaves of the network sign the receipts of executions with, as
+base64. Every enclave of the network derives the same key from the consensus seed, so verifiers of the receipts
+in the execution_receipt events of transactions only need this key.`,
+		Args: cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			key, err := api.GetExecutionReceiptKey()
+			if err != nil {
+				return fmt.Errorf("failed to get the execution receipt key. Enclave returned: %s", err)
+			}
+
+			fmt.Println(base64.StdEncoding.EncodeToString(key))
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func CalibrateCryptoGas() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "calibrate-crypto-gas",
+		Short: "Benchmark the crypto host functions of the enclave and suggest their gas costs",
+		Long: `Time the crypto host functions of the enclave on this machine, and print the gas costs they should have
+relative to external_ed25519_verify as JSON. The suggested_costs of the report can be merged into the gas_costs
+param of the compute module by a governance proposal, so run this on reference hardware.`,
+		Args: cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			iterations, err := cmd.Flags().GetUint32(flagIterations)
+			if err != nil {
+				return fmt.Errorf("error with iterations flag: %s", err)
+			}
+
+			report, err := api.CalibrateCryptoGas(iterations)
+			if err != nil {
+				return fmt.Errorf("failed to calibrate the crypto gas costs. Enclave returned: %s", err)
+			}
+
+			fmt.Println(string(report))
+			return nil
+		},
+	}
+	cmd.Flags().Uint32(flagIterations, 1000, "The number of times each host function is timed, at most 10000")
+
+	return cmd
+}
+
+func CheckAttestation() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "check-attestation",
+		Short: "Show when the attestation of the node expires",
+		Long: `Show when the last attestation report of the enclave expires. With --renew, a new report is created
+if the last one expires within a week, and the node must then be registered again with it.`,
+		Args: cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			renew, err := cmd.Flags().GetBool(flagRenew)
+			if err != nil {
+				return fmt.Errorf("error with renew flag: %s", err)
+			}
+
+			if renew {
+				apiKeyFile, err := reg.GetApiKey()
+				if err != nil {
+					return fmt.Errorf("failed to renew attestation: %w", err)
+				}
+
+				no_epid, _ := cmd.Flags().GetBool(flag_no_epid)
+				no_dcap, _ := cmd.Flags().GetBool(flag_no_dcap)
+
+				renewed, err := api.RenewAttestationIfDue(apiKeyFile, no_epid, no_dcap)
+				if err != nil {
+					return fmt.Errorf("failed to renew attestation: %w", err)
+				}
+				if renewed {
+					fmt.Println("Created a new attestation report. Register the node again to renew its attestation")
+				}
+			}
+
+			expiresAt, remainingSecs, err := api.GetAttestationValidity()
+			if err != nil {
+				return fmt.Errorf("failed to get attestation validity. Enclave returned: %s", err)
+			}
+			if expiresAt == 0 {
+				fmt.Println("The enclave has no attestation report")
+				return nil
+			}
+
+			fmt.Printf("Attestation expires at %s (%s left)\n",
+				time.Unix(int64(expiresAt), 0).UTC().Format(time.RFC3339),
+				time.Duration(remainingSecs)*time.Second)
+			return nil
+		},
+	}
+	cmd.Flags().Bool(flagRenew, false, "Optional flag to create a new attestation report if the current one expires soon")
+	cmd.Flags().Bool(flag_no_epid, false, "Optional flag to disable EPID attestation")
+	cmd.Flags().Bool(flag_no_dcap, false, "Optional flag to disable DCAP attestation")
+
+	return cmd
+}
+
 func ResetEnclave() *cobra.Command {
 	cmd := &cobra.Command{
 		Use:   "reset-enclave",
@@ -123,6 +123,76 @@ func HealthCheck() *cobra.Command {
 	return cmd
 }
 
+func AnalyzeContract() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "analyze-contract [wasm file]",
+		Short: "Check a contract against the rules of the enclave",
+		Args:  cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func EnclaveManifest() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "enclave-manifest",
+		Short: "Print the capability manifest of the enclave",
+		Args:  cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func ExecutionReceiptKey() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "execution-receipt-key",
+		Short: "Print the public key that the enclaves of the network sign execution receipts with",
+		Args:  cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func CalibrateCryptoGas() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "calibrate-crypto-gas",
+		Short: "Benchmark the crypto host functions of the enclave and suggest their gas costs",
+		Args:  cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
+func CheckAttestation() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "check-attestation",
+		Short: "Show when the attestation of the node expires",
+		Args:  cobra.ExactArgs(0),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			println("This is a secretd only function, yo")
+			return nil
+		},
+	}
+
+	return cmd
+}
+
 func ResetEnclave() *cobra.Command {
 	cmd := &cobra.Command{
 		Use:   "reset-enclave",
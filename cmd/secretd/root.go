@@ -182,6 +182,11 @@ func initRootCmd(rootCmd *cobra.Command, encodingConfig app.EncodingConfig) {
 		MigrateSealings(),
 		ConfigureSecret(),
 		HealthCheck(),
+		EnclaveManifest(),
+		AnalyzeContract(),
+		ExecutionReceiptKey(),
+		CalibrateCryptoGas(),
+		CheckAttestation(),
 		ResetEnclave(),
 		AutoRegisterNode(),
 		keys.Commands(app.DefaultNodeHome),
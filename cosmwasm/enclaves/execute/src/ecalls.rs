@@ -17,6 +17,9 @@ pub unsafe extern "C" fn ecall_submit_block_signatures(
     in_encrypted_random_len: u32,
     decrypted_random: &mut [u8; 32],
 ) -> sgx_status_t {
+    // Queries are served at the height of the new block from now on
+    enclave_contract_engine::clear_query_result_cache();
+
     #[cfg(feature = "light-client-validation")]
     {
         block_verifier::submit_block_signatures::submit_block_signatures_impl(
@@ -45,3 +48,45 @@ pub unsafe extern "C" fn ecall_submit_block_signatures(
         sgx_status_t::SGX_ERROR_ECALL_NOT_ALLOWED
     }
 }
+
+/// Sets the max gas of a block whose signatures were submitted, from its consensus params
+#[no_mangle]
+#[allow(unused_variables)]
+pub extern "C" fn ecall_submit_block_gas_limit(
+    block_height: u64,
+    block_max_bytes: i64,
+    block_max_gas: i64,
+) -> sgx_status_t {
+    #[cfg(feature = "light-client-validation")]
+    {
+        block_verifier::block_gas::submit_block_gas_limit_impl(
+            block_height,
+            block_max_bytes,
+            block_max_gas,
+        )
+    }
+
+    #[cfg(not(feature = "light-client-validation"))]
+    {
+        // without the light client there's no verified header to check the params against
+        sgx_status_t::SGX_SUCCESS
+    }
+}
+
+/// Ends the gas accounting of a block, no executions can run in it afterwards
+#[no_mangle]
+#[allow(unused_variables)]
+pub extern "C" fn ecall_end_block(block_height: u64) -> sgx_status_t {
+    // Before the host commits the writes of the block
+    enclave_contract_engine::seal_write_intents();
+
+    #[cfg(feature = "light-client-validation")]
+    {
+        block_verifier::block_gas::end_block_impl(block_height)
+    }
+
+    #[cfg(not(feature = "light-client-validation"))]
+    {
+        sgx_status_t::SGX_SUCCESS
+    }
+}
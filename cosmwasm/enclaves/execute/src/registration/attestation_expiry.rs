@@ -0,0 +1,194 @@
+//! Tracks when the attestation of this node was created, so that operators can see how long it
+//! is still valid and renew it before the network stops accepting it.
+//!
+//! These functions run off chain. The time is read from the host, so they are only meant to warn
+//! the host in time, not to enforce anything.
+
+use log::*;
+use sgx_types::sgx_status_t;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, path};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use enclave_crypto::consts::{DEFAULT_SGX_SECRET_PATH, SCRT_SGX_STORAGE_ENV_VAR};
+use enclave_utils::storage::{seal, unseal};
+use enclave_utils::validate_mut_ptr;
+
+use super::offchain::ecall_get_attestation_report;
+
+const ATTESTATION_RECORD_FILE_NAME: &str = "attestation_record.sealed";
+
+/// The collateral that DCAP quotes are verified against, like the TCB info, is issued for 30
+/// days, after which a new quote must be created
+pub const ATTESTATION_VALIDITY_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Attestations are renewed when they have less than this left
+pub const RENEWAL_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
+
+lazy_static! {
+    static ref ATTESTATION_RECORD_SEALING_PATH: String = path::Path::new(
+        &env::var(SCRT_SGX_STORAGE_ENV_VAR).unwrap_or_else(|_| DEFAULT_SGX_SECRET_PATH.to_string()),
+    )
+    .join(ATTESTATION_RECORD_FILE_NAME)
+    .to_str()
+    .unwrap_or(DEFAULT_SGX_SECRET_PATH)
+    .to_string();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct AttestationRecord {
+    pub created_at: u64,
+    pub epid: bool,
+    pub dcap: bool,
+}
+
+impl AttestationRecord {
+    pub fn expires_at(&self) -> u64 {
+        self.created_at.saturating_add(ATTESTATION_VALIDITY_SECS)
+    }
+
+    pub fn remaining_secs(&self, now: u64) -> u64 {
+        self.expires_at().saturating_sub(now)
+    }
+
+    pub fn renewal_due(&self, now: u64) -> bool {
+        self.remaining_secs(now) < RENEWAL_WINDOW_SECS
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+fn load_record() -> Option<AttestationRecord> {
+    let sealed = unseal(&ATTESTATION_RECORD_SEALING_PATH).ok()?;
+    serde_json::from_slice(&sealed)
+        .map_err(|err| error!("failed to parse the attestation record: {:?}", err))
+        .ok()
+}
+
+/// Records an attestation that was just created
+pub fn record_attestation(epid: bool, dcap: bool) {
+    let record = AttestationRecord {
+        created_at: now_secs(),
+        epid,
+        dcap,
+    };
+    debug!("recording attestation: {:?}", record);
+
+    let result = serde_json::to_vec(&record)
+        .map_err(|err| error!("failed to serialize the attestation record: {:?}", err))
+        .and_then(|sealed| {
+            seal(&sealed, &ATTESTATION_RECORD_SEALING_PATH)
+                .map_err(|err| error!("failed to seal the attestation record: {:?}", err))
+        });
+    if result.is_err() {
+        warn!("the expiry of the attestation won't be tracked");
+    }
+}
+
+///
+/// `ecall_get_attestation_validity`
+///
+/// Returns when the last attestation of this node expires, and how many seconds it has left.
+/// Both are 0 if this enclave didn't create an attestation.
+///
+/// # Safety
+/// The pointers must be valid
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_attestation_validity(
+    expires_at: *mut u64,
+    remaining_secs: *mut u64,
+) -> sgx_status_t {
+    validate_mut_ptr!(
+        expires_at as _,
+        std::mem::size_of::<u64>(),
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+    validate_mut_ptr!(
+        remaining_secs as _,
+        std::mem::size_of::<u64>(),
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+
+    let (expires, remaining) = match load_record() {
+        Some(record) => (record.expires_at(), record.remaining_secs(now_secs())),
+        None => (0, 0),
+    };
+    *expires_at = expires;
+    *remaining_secs = remaining;
+
+    sgx_status_t::SGX_SUCCESS
+}
+
+///
+/// `ecall_renew_attestation_if_due`
+///
+/// Creates a new attestation report, like `ecall_get_attestation_report`, if the last one expires
+/// within `RENEWAL_WINDOW_SECS` or was never created. The new report is the payload that the node
+/// registers with again.
+///
+/// # Safety
+/// The pointers must be valid
+#[no_mangle]
+pub unsafe extern "C" fn ecall_renew_attestation_if_due(
+    api_key: *const u8,
+    api_key_len: u32,
+    flags: u32,
+    renewed: *mut u8,
+) -> sgx_status_t {
+    validate_mut_ptr!(
+        renewed,
+        std::mem::size_of::<u8>(),
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    );
+    *renewed = 0;
+
+    if let Some(record) = load_record() {
+        if !record.renewal_due(now_secs()) {
+            debug!(
+                "attestation is valid until {}, not renewing it",
+                record.expires_at()
+            );
+            return sgx_status_t::SGX_SUCCESS;
+        }
+    }
+
+    info!("renewing the attestation of the node");
+    let status = ecall_get_attestation_report(api_key, api_key_len, flags);
+    if status == sgx_status_t::SGX_SUCCESS {
+        *renewed = 1;
+    }
+
+    status
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_attestation_validity() {
+        let record = AttestationRecord {
+            created_at: 1_000,
+            epid: false,
+            dcap: true,
+        };
+
+        assert_eq!(record.expires_at(), 1_000 + ATTESTATION_VALIDITY_SECS);
+        assert_eq!(record.remaining_secs(1_000), ATTESTATION_VALIDITY_SECS);
+        assert!(!record.renewal_due(1_000));
+
+        let renewal_starts = record.expires_at() - RENEWAL_WINDOW_SECS;
+        assert!(!record.renewal_due(renewal_starts));
+        assert!(record.renewal_due(renewal_starts + 1));
+
+        // Expired attestations have no time left
+        assert_eq!(record.remaining_secs(record.expires_at() + 1), 0);
+        assert!(record.renewal_due(record.expires_at() + 1));
+    }
+}
@@ -1,8 +1,10 @@
 pub use attestation::create_attestation_certificate;
+pub use attestation_expiry::{ecall_get_attestation_validity, ecall_renew_attestation_if_due};
 pub use offchain::{ecall_get_attestation_report, ecall_init_bootstrap, ecall_init_node};
 pub use onchain::ecall_authenticate_new_node;
 
 mod attestation;
+mod attestation_expiry;
 mod cert;
 mod hex;
 mod offchain;
@@ -39,6 +41,7 @@ pub mod tests {
             report::tests::test_attestation_dcap_temper();
             cert::tests::test_certificate_valid();
             cert::tests::test_certificate_invalid_configuration_needed();
+            attestation_expiry::tests::test_attestation_validity();
         });
 
         if failures != 0 {
@@ -27,6 +27,7 @@ use enclave_utils::{validate_const_ptr, validate_mut_ptr};
 use enclave_ffi_types::SINGLE_ENCRYPTED_SEED_SIZE;
 
 use super::attestation::{create_attestation_certificate, get_quote_ecdsa};
+use super::attestation_expiry::record_attestation;
 
 use super::seed_service::get_next_consensus_seed_from_service;
 
@@ -488,6 +489,8 @@ pub unsafe extern "C" fn ecall_get_attestation_report(
         return sgx_status_t::SGX_ERROR_UNEXPECTED;
     }
 
+    record_attestation(size_epid != 0, size_dcap_q != 0);
+
     sgx_status_t::SGX_SUCCESS
 }
 
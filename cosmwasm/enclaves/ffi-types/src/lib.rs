@@ -21,3 +21,15 @@ pub const SINGLE_ENCRYPTED_SEED_SIZE: usize = 48;
 pub const NEWLY_FORMED_SINGLE_ENCRYPTED_SEED_SIZE: usize = SINGLE_ENCRYPTED_SEED_SIZE + 1;
 pub const NEWLY_FORMED_DOUBLE_ENCRYPTED_SEED_SIZE: usize = (2 * SINGLE_ENCRYPTED_SEED_SIZE) + 1;
 pub const PUBLIC_KEY_SIZE: usize = 32;
+
+/// Code that was pinned in the enclave with `ecall_pin_contract_code` can be passed to the
+/// execution ecalls as this prefix followed by its code hash, instead of the whole binary.
+/// Wasm binaries always start with `\0asm`, so a reference can't be mistaken for code.
+pub const PINNED_CODE_REFERENCE_PREFIX: &[u8] = b"\0pin";
+pub const PINNED_CODE_REFERENCE_SIZE: usize = PINNED_CODE_REFERENCE_PREFIX.len() + 32;
+
+/// The version of the interface between the node and the enclave that this enclave speaks. The
+/// node announces its own version in `RuntimeConfiguration::ffi_version`.
+pub const FFI_VERSION: u32 = 2;
+/// From this version on, execution results are protobuf `WasmOutputEnvelope`s instead of JSON
+pub const FFI_VERSION_PROTOBUF_RESULTS: u32 = 2;
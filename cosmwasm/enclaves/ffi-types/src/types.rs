@@ -41,6 +41,23 @@ pub struct RuntimeConfiguration {
     /// This speeds up the execution of recently used modules, but has a significant
     /// memory overhead.
     pub module_cache_size: u32,
+    /// Whether cache flushes are recorded in the sealed state audit log. The log only describes
+    /// the encrypted keys and values that are written to the chain state.
+    pub state_audit_mode: bool,
+    /// How long, in milliseconds, a query that the node serves may run before it's aborted with
+    /// `EnclaveError::ExecutionTimeout`. 0 disables the deadline.
+    pub execution_deadline_millis: u64,
+    /// Whether successful handles return a receipt signed by the enclave, which proves to
+    /// off-chain verifiers that the execution ran in an enclave of the network
+    pub execution_receipts: bool,
+    /// Whether executions export the timing of their stages to the host, see `execution_trace`
+    pub execution_tracing: bool,
+    /// How many bytes of contract code the enclave may keep pinned, see `pinned_code`.
+    /// 0 disables pinning.
+    pub pinned_code_bytes: u64,
+    /// The version of the interface that the node speaks. Results are encoded for the lower of
+    /// it and `FFI_VERSION`.
+    pub ffi_version: u32,
 }
 
 /// This struct holds a pointer to memory in userspace, that contains the storage
@@ -88,10 +105,19 @@ pub enum EnclaveError {
     FailedGasMeteringInjection,
     #[display(fmt = "internal error during execution")]
     InternalError,
+    /// The code was referenced by its hash, but it wasn't pinned in the enclave.
+    #[display(fmt = "referenced contract code is not pinned in the enclave")]
+    CodePinNotFound,
     // runtime issues with the module
     /// Ran out of gas
     #[display(fmt = "execution ran out of gas")]
     OutOfGas,
+    /// Ran past the execution deadline the node configured
+    #[display(fmt = "execution ran past its deadline")]
+    ExecutionTimeout,
+    /// The execution doesn't fit in the max gas of its block
+    #[display(fmt = "execution exceeds the gas limit of the block")]
+    BlockGasExceeded,
     /// Calling a function in the contract failed.
     #[display(fmt = "calling a function in the contract failed for an unexpected reason")]
     FailedFunctionCall,
@@ -117,6 +143,12 @@ pub enum EnclaveError {
     /// The contract has run out of space on the stack.
     #[display(fmt = "the contract has run out of space on the stack")]
     ContractPanicStackOverflow,
+    /// The calls of the contract were nested deeper than the max call depth of the gas costs
+    #[display(
+        fmt = "the contract exceeded the max call depth, at a depth of {}",
+        depth
+    )]
+    CallStackExceeded { depth: u32 },
     /// The contract performed integer overflow.
     #[display(fmt = "the contract has run out of space on the stack")]
     ContractPanicIntegerOverflow,
@@ -125,6 +157,10 @@ pub enum EnclaveError {
         fmt = "the contract tried to call a function but expected an incorrect function signature"
     )]
     ContractPanicUnexpectedSignature,
+    /// A host function or the engine panicked while the contract was executing. The execution
+    /// was aborted and what it wrote was discarded.
+    #[display(fmt = "the execution of the contract panicked")]
+    ContractPanicked,
 
     // Errors in contract ABI:
     /// Failed to seal data
@@ -138,10 +174,20 @@ pub enum EnclaveError {
     FailedToDeserialize,
     #[display(fmt = "failed to serialize data")]
     FailedToSerialize,
+    #[display(fmt = "invalid address: wrong bech32 prefix")]
+    InvalidAddressPrefix,
+    #[display(fmt = "invalid address: checksum mismatch, the address may have a typo")]
+    InvalidAddressChecksum,
+    #[display(fmt = "invalid address: unsupported length")]
+    InvalidAddressLength,
     #[display(fmt = "failed to encrypt data")]
     EncryptionError,
     #[display(fmt = "failed to decrypt data")]
     DecryptionError,
+    /// The queries of the connection failed to decrypt too often recently, see
+    /// `decryption_limit`
+    #[display(fmt = "too many queries of this connection failed to decrypt, try again later")]
+    TooManyDecryptionFailures,
     #[display(fmt = "failed to allocate memory")]
     MemoryAllocationError,
     #[display(fmt = "failed to allocate minimal safety buffer")]
@@ -154,8 +200,23 @@ pub enum EnclaveError {
     NotImplemented,
     #[display(fmt = "failed to verify transaction signature")]
     FailedTxVerification,
+    /// The ibc-hooks callback of the packet was already processed, see `ibc_callback_guard`
+    #[display(fmt = "the ibc callback of this packet was already processed")]
+    DuplicateIbcCallback,
     #[display(fmt = "contract tried to write to storage during a query")]
     UnauthorizedWrite,
+    /// The contract called a host function that isn't available in the operation, e.g.
+    /// `migration_checkpoint` outside of a migration
+    #[display(fmt = "contract called a host function that isn't available in this operation")]
+    ForbiddenHostFunction,
+    #[display(fmt = "contract tried to store more than its storage quota")]
+    StorageQuotaExceeded,
+    /// The execution was already executed with the same idempotency key, see `idempotency_keys`
+    #[display(fmt = "the execution with this idempotency key was already executed")]
+    DuplicateExecution,
+    /// The contract has a pending migration, see `migration_checkpoint`
+    #[display(fmt = "the contract can't be executed until its pending migration completes")]
+    MigrationPending,
 
     // serious issues
     /// The host was caught trying to disrupt the enclave.
@@ -303,6 +364,9 @@ pub enum InitResult {
         /// The contract_key for this contract.
         contract_key: [u8; 64],
         admin_proof: [u8; 32],
+        /// A pointer to the intent of the writes of the execution, which the host acknowledges
+        /// once it committed them. Empty if the execution didn't write anything.
+        write_intent: UserSpaceBuffer,
     },
     Failure {
         /// The error that happened in the enclave
@@ -317,6 +381,14 @@ pub enum HandleResult {
     Success {
         /// A pointer to the output of the calculation
         output: UserSpaceBuffer,
+        /// A pointer to the JSON of the salted hashes of the storage keys that were touched
+        touched_keys: UserSpaceBuffer,
+        /// A pointer to the JSON of the signed receipt of the execution, empty if receipts are
+        /// disabled
+        receipt: UserSpaceBuffer,
+        /// A pointer to the intent of the writes of the execution, which the host acknowledges
+        /// once it committed them. Empty if the execution didn't write anything.
+        write_intent: UserSpaceBuffer,
     },
     Failure {
         /// The error that happened in the enclave
@@ -331,6 +403,9 @@ pub enum MigrateResult {
         output: UserSpaceBuffer,
         new_contract_key: [u8; 64],
         new_contract_key_proof: [u8; 32],
+        /// A pointer to the intent of the writes of the execution, which the host acknowledges
+        /// once it committed them. Empty if the execution didn't write anything.
+        write_intent: UserSpaceBuffer,
     },
     Failure {
         /// The error that happened in the enclave
@@ -0,0 +1,273 @@
+//! Accounting of the gas that executions use in a block, so that a host can't make the enclave
+//! execute more than the consensus params of the chain allow in a block.
+//!
+//! The accounting of a block starts when its signatures are submitted, and ends with
+//! `end_block_impl`. Only a block above the current one starts a new accounting, so the host
+//! can't reset the gas of a block by submitting its signatures again. The host submits the max gas
+//! of the block with `submit_block_gas_limit_impl`, which is only accepted if it hashes to the
+//! consensus hash of the verified header. Until then, no execution can run in the block.
+//!
+//! Only the executions of delivered transactions are accounted, since CheckTx and simulations
+//! don't use the gas of the block.
+
+use lazy_static::lazy_static;
+use log::{debug, error, warn};
+
+use std::sync::SgxMutex;
+
+use sgx_types::sgx_status_t;
+
+use enclave_crypto::sha_256;
+
+use crate::verified_headers::VERIFIED_HEADERS;
+
+/// Gas of the enclave per unit of sdk gas, which the max gas of a block is measured in
+pub const WASM_GAS_PER_SDK_GAS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockGasLimit {
+    /// The max gas of the block wasn't verified yet
+    Unknown,
+    Unlimited,
+    /// The max gas of the block in gas of the enclave
+    Limited(u64),
+}
+
+impl Default for BlockGasLimit {
+    fn default() -> Self {
+        BlockGasLimit::Unknown
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockGas {
+    height: u64,
+    limit: BlockGasLimit,
+    used: u64,
+    ended: bool,
+}
+
+impl BlockGas {
+    /// Starts the accounting of a new block, where nothing can run until its max gas is set.
+    /// Returns false if the block isn't above the current one, which keeps its accounting.
+    pub fn begin(&mut self, height: u64) -> bool {
+        if height <= self.height {
+            return false;
+        }
+
+        *self = BlockGas {
+            height,
+            ..Default::default()
+        };
+        true
+    }
+
+    /// Sets the max gas of the block, in sdk gas. Negative values mean that it's unlimited,
+    /// like in the consensus params.
+    pub fn set_max_gas(&mut self, height: u64, max_gas: i64) -> bool {
+        if height != self.height || self.ended {
+            return false;
+        }
+
+        self.limit = if max_gas < 0 {
+            BlockGasLimit::Unlimited
+        } else {
+            BlockGasLimit::Limited((max_gas as u64).saturating_mul(WASM_GAS_PER_SDK_GAS))
+        };
+        true
+    }
+
+    /// Ends the accounting of the block. Returns false if the block isn't the current one.
+    pub fn end(&mut self, height: u64) -> bool {
+        if height != self.height {
+            return false;
+        }
+
+        self.ended = true;
+        true
+    }
+
+    /// Whether executions can still run in the block. Executions in another block than the
+    /// current one, or before its max gas is verified, can't.
+    pub fn has_room(&self, height: u64) -> bool {
+        if height != self.height || self.ended {
+            return false;
+        }
+
+        match self.limit {
+            BlockGasLimit::Unknown => false,
+            BlockGasLimit::Unlimited => true,
+            BlockGasLimit::Limited(limit) => self.used < limit,
+        }
+    }
+
+    /// Adds the gas of an execution to the block. Returns false if the block can't fit it.
+    pub fn use_gas(&mut self, height: u64, gas: u64) -> bool {
+        if height != self.height || self.ended {
+            return false;
+        }
+
+        self.used = self.used.saturating_add(gas);
+        match self.limit {
+            BlockGasLimit::Unknown => false,
+            BlockGasLimit::Unlimited => true,
+            BlockGasLimit::Limited(limit) => self.used <= limit,
+        }
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn limit(&self) -> BlockGasLimit {
+        self.limit
+    }
+}
+
+lazy_static! {
+    pub static ref BLOCK_GAS: SgxMutex<BlockGas> = SgxMutex::new(BlockGas::default());
+}
+
+fn encode_varint(mut value: u64, bytes: &mut Vec<u8>) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// The protobuf of tendermint's `HashedParams`, which the consensus hash of a header is the hash of
+fn hashed_params(block_max_bytes: i64, block_max_gas: i64) -> Vec<u8> {
+    let mut bytes = vec![];
+    // Fields 1 and 2 are varints. Like any proto3 field, they are omitted when they are zero.
+    for (tag, value) in [(0x08u8, block_max_bytes), (0x10u8, block_max_gas)] {
+        if value != 0 {
+            bytes.push(tag);
+            encode_varint(value as u64, &mut bytes);
+        }
+    }
+    bytes
+}
+
+pub fn submit_block_gas_limit_impl(
+    block_height: u64,
+    block_max_bytes: i64,
+    block_max_gas: i64,
+) -> sgx_status_t {
+    let consensus_hash = match VERIFIED_HEADERS.lock().unwrap().get(block_height) {
+        Some(header) => header.consensus_hash.clone(),
+        None => {
+            error!(
+                "cannot set the gas limit of block {}, its header wasn't verified",
+                block_height
+            );
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+        }
+    };
+
+    if sha_256(&hashed_params(block_max_bytes, block_max_gas)).to_vec() != consensus_hash {
+        error!(
+            "consensus params of block {} don't match its consensus hash",
+            block_height
+        );
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    if !BLOCK_GAS
+        .lock()
+        .unwrap()
+        .set_max_gas(block_height, block_max_gas)
+    {
+        error!(
+            "cannot set the gas limit of block {}, it isn't the current block",
+            block_height
+        );
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+
+    debug!("gas limit of block {} is {}", block_height, block_max_gas);
+    sgx_status_t::SGX_SUCCESS
+}
+
+pub fn end_block_impl(block_height: u64) -> sgx_status_t {
+    let mut block_gas = BLOCK_GAS.lock().unwrap();
+
+    // Blocks whose signatures weren't submitted were never begun
+    if !block_gas.end(block_height) {
+        warn!("ending block {} which wasn't begun", block_height);
+        return sgx_status_t::SGX_SUCCESS;
+    }
+
+    debug!(
+        "block {} used {} gas out of {:?}",
+        block_height,
+        block_gas.used(),
+        block_gas.limit()
+    );
+    sgx_status_t::SGX_SUCCESS
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_block_gas_accounting() {
+        let mut block_gas = BlockGas::default();
+        assert!(block_gas.begin(10));
+
+        // Nothing runs until the max gas of the block is set
+        assert_eq!(block_gas.limit(), BlockGasLimit::Unknown);
+        assert!(!block_gas.has_room(10));
+        assert!(!block_gas.use_gas(10, 1));
+
+        assert!(block_gas.begin(11));
+        assert!(!block_gas.set_max_gas(10, 100));
+        assert!(block_gas.set_max_gas(11, 100));
+        assert_eq!(block_gas.limit(), BlockGasLimit::Limited(100_000));
+
+        assert!(block_gas.use_gas(11, 60_000));
+        assert!(block_gas.has_room(11));
+        assert!(block_gas.use_gas(11, 40_000));
+        assert!(!block_gas.has_room(11));
+        assert!(!block_gas.use_gas(11, 1));
+        // Nor in blocks other than the current one
+        assert!(!block_gas.has_room(12));
+        assert!(!block_gas.use_gas(10, 1));
+
+        // Submitting the signatures of the block again doesn't reset its gas, and neither do
+        // those of an older block
+        assert!(!block_gas.begin(11));
+        assert!(!block_gas.begin(10));
+        assert!(!block_gas.has_room(11));
+
+        // A new block starts from zero
+        assert!(block_gas.begin(12));
+        assert!(block_gas.set_max_gas(12, 100));
+        assert_eq!(block_gas.used(), 0);
+        assert!(block_gas.use_gas(12, 1));
+
+        // No executions after the end of the block
+        assert!(block_gas.end(12));
+        assert!(!block_gas.has_room(12));
+        assert!(!block_gas.use_gas(12, 1));
+        assert!(!block_gas.set_max_gas(12, 200));
+
+        // A max gas of -1 is unlimited
+        assert!(block_gas.begin(13));
+        assert!(block_gas.set_max_gas(13, -1));
+        assert!(block_gas.use_gas(13, u64::MAX));
+    }
+
+    pub fn test_hashed_params() {
+        assert_eq!(hashed_params(0, 0), Vec::<u8>::new());
+        assert_eq!(hashed_params(1, 150), vec![0x08, 0x01, 0x10, 0x96, 0x01]);
+        // Negative values are encoded as 10 byte varints
+        assert_eq!(
+            hashed_params(22020096, -1),
+            vec![
+                0x08, 0x80, 0x80, 0xc0, 0x0a, 0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+                0xff, 0x01
+            ]
+        );
+    }
+}
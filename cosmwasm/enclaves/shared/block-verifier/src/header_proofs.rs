@@ -0,0 +1,68 @@
+//! Headers that transactions carry, with the commits that sign them, to prove the chain state
+//! that their app hash commits to. They are verified against the validator set of the current
+//! block alone, so every node reaches the same verdict whichever blocks it verified itself.
+
+use log::error;
+use sgx_types::sgx_status_t;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::Header;
+use tendermint::validator::Set;
+use tendermint_light_client_verifier::types::UntrustedBlockState;
+use tendermint_proto::Protobuf;
+
+use crate::verify::block::verify_block;
+use crate::verify::validator_set::get_validator_set_for_height;
+
+/// A header that the validator set of the current block signed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvenHeader {
+    pub height: u64,
+    /// Commits to the state after the block before this one
+    pub app_hash: Vec<u8>,
+}
+
+/// Verifies a header and the commit that signs it against the validator set of the current
+/// block. Headers signed by a previous validator set aren't accepted.
+pub fn verify_header_proof(header: &[u8], commit: &[u8]) -> Result<ProvenHeader, sgx_status_t> {
+    let validator_set_for_height = get_validator_set_for_height()?;
+
+    let validator_set =
+        Set::decode(validator_set_for_height.validator_set.as_slice()).map_err(|e| {
+            error!("Error parsing validator set from proto: {:?}", e);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        })?;
+
+    let commit = crate::verify::commit::decode(commit)?;
+
+    let header = Header::decode(header).map_err(|e| {
+        error!("Error parsing header from proto: {:?}", e);
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    })?;
+
+    let signed_header = SignedHeader::new(header, commit).map_err(|e| {
+        error!("Error creating signed header: {:?}", e);
+        sgx_status_t::SGX_ERROR_INVALID_PARAMETER
+    })?;
+
+    let height = signed_header.header.height.value();
+    if height > validator_set_for_height.height {
+        error!("Header proof at height {} is of a future block", height);
+        return Err(sgx_status_t::SGX_ERROR_INVALID_PARAMETER);
+    }
+
+    let untrusted_block = UntrustedBlockState {
+        signed_header: &signed_header,
+        validators: &validator_set,
+        next_validators: None,
+    };
+
+    if !verify_block(&untrusted_block) {
+        error!("Error verifying header proof at height {}", height);
+        return Err(sgx_status_t::SGX_ERROR_INVALID_SIGNATURE);
+    }
+
+    Ok(ProvenHeader {
+        height,
+        app_hash: signed_header.header.app_hash.as_bytes().to_vec(),
+    })
+}
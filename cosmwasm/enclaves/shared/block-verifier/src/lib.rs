@@ -10,6 +10,18 @@ pub mod wasm_messages;
 
 pub use wasm_messages::VERIFIED_BLOCK_MESSAGES;
 
+pub mod verified_headers;
+
+pub use verified_headers::VERIFIED_HEADERS;
+
+pub mod block_gas;
+
+pub use block_gas::BLOCK_GAS;
+
+pub mod header_proofs;
+
+pub use header_proofs::{verify_header_proof, ProvenHeader};
+
 mod txs;
 
 #[cfg(any(feature = "verify-validator-whitelist", feature = "test"))]
@@ -55,6 +67,9 @@ pub mod tests {
             crate::wasm_messages::tests::test_wasm_msg_tracker();
             crate::wasm_messages::tests::test_mix_wasm_bank_msg_tracker_multiple_msgs();
             crate::validator_whitelist::tests::test_parse_validators();
+            crate::verified_headers::tests::test_verified_headers_are_pruned();
+            crate::block_gas::tests::test_block_gas_accounting();
+            crate::block_gas::tests::test_hashed_params();
         });
 
         if failures != 0 {
@@ -5,7 +5,7 @@ use tendermint_proto::Protobuf;
 use sgx_types::sgx_status_t;
 
 use enclave_utils::{validate_const_ptr, validate_input_length, validate_mut_ptr};
-use log::error;
+use log::{error, warn};
 
 use log::debug;
 
@@ -20,7 +20,9 @@ macro_rules! unwrap_or_return {
     };
 }
 
+use crate::block_gas::BLOCK_GAS;
 use crate::txs::tx_from_bytes;
+use crate::verified_headers::{VerifiedHeader, VERIFIED_HEADERS};
 use crate::wasm_messages::VERIFIED_BLOCK_MESSAGES;
 
 use crate::verify::validator_set::get_validator_set_for_height;
@@ -110,8 +112,30 @@ pub unsafe fn submit_block_signatures_impl(
     message_verifier.set_block_info(
         header.header.height.value(),
         header.header.time.unix_timestamp_nanos(),
+        header.header.hash().as_bytes().to_vec(),
+        header.header.proposer_address.as_bytes().to_vec(),
     );
 
+    VERIFIED_HEADERS.lock().unwrap().insert(
+        header.header.height.value(),
+        VerifiedHeader {
+            time: header.header.time.unix_timestamp_nanos(),
+            app_hash: header.header.app_hash.as_bytes().to_vec(),
+            consensus_hash: header.header.consensus_hash.as_bytes().to_vec(),
+        },
+    );
+
+    if !BLOCK_GAS
+        .lock()
+        .unwrap()
+        .begin(header.header.height.value())
+    {
+        warn!(
+            "gas of block {} was already accounted",
+            header.header.height.value()
+        );
+    }
+
     #[cfg(feature = "random")]
     {
         let encrypted_random_slice =
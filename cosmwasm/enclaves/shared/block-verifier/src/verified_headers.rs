@@ -0,0 +1,69 @@
+use alloc::collections::BTreeMap;
+use lazy_static::lazy_static;
+
+use std::sync::SgxMutex;
+
+/// Amount of verified headers that are kept around to serve queries at past heights
+pub const MAX_VERIFIED_HEADERS: usize = 1000;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifiedHeader {
+    pub time: i128,
+    pub app_hash: Vec<u8>,
+    /// Hash of the consensus params of the block
+    pub consensus_hash: Vec<u8>,
+}
+
+/// Headers of recent blocks that were verified by the light client, indexed by height
+#[derive(Debug, Clone, Default)]
+pub struct VerifiedHeaders {
+    headers: BTreeMap<u64, VerifiedHeader>,
+}
+
+impl VerifiedHeaders {
+    pub fn insert(&mut self, height: u64, header: VerifiedHeader) {
+        self.headers.insert(height, header);
+
+        while self.headers.len() > MAX_VERIFIED_HEADERS {
+            let oldest = *self.headers.keys().next().unwrap();
+            self.headers.remove(&oldest);
+        }
+    }
+
+    pub fn get(&self, height: u64) -> Option<&VerifiedHeader> {
+        self.headers.get(&height)
+    }
+
+    pub fn oldest_height(&self) -> Option<u64> {
+        self.headers.keys().next().copied()
+    }
+}
+
+lazy_static! {
+    pub static ref VERIFIED_HEADERS: SgxMutex<VerifiedHeaders> =
+        SgxMutex::new(VerifiedHeaders::default());
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_verified_headers_are_pruned() {
+        let mut headers = VerifiedHeaders::default();
+
+        for height in 1..=(MAX_VERIFIED_HEADERS as u64 + 5) {
+            headers.insert(
+                height,
+                VerifiedHeader {
+                    time: height as i128,
+                    app_hash: vec![],
+                    consensus_hash: vec![],
+                },
+            );
+        }
+
+        assert_eq!(headers.oldest_height(), Some(6));
+        assert!(headers.get(5).is_none());
+        assert_eq!(headers.get(6).unwrap().time, 6);
+    }
+}
@@ -16,6 +16,9 @@ pub struct VerifiedBlockMessages {
     messages: VecDeque<Vec<u8>>,
     height: u64,
     time: i128,
+    hash: Vec<u8>,
+    /// Consensus address of the validator that proposed the block
+    proposer: Vec<u8>,
 }
 
 impl VerifiedBlockMessages {
@@ -33,9 +36,11 @@ impl VerifiedBlockMessages {
         }
     }
 
-    pub fn set_block_info(&mut self, height: u64, time: i128) {
+    pub fn set_block_info(&mut self, height: u64, time: i128, hash: Vec<u8>, proposer: Vec<u8>) {
         self.height = height;
         self.time = time;
+        self.hash = hash;
+        self.proposer = proposer;
     }
 
     pub fn height(&self) -> u64 {
@@ -44,6 +49,12 @@ impl VerifiedBlockMessages {
     pub fn time(&self) -> i128 {
         self.time
     }
+    pub fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+    pub fn proposer(&self) -> &[u8] {
+        &self.proposer
+    }
 
     pub fn clear(&mut self) {
         self.messages.clear()
@@ -0,0 +1,342 @@
+//! Admins and system contracts that governance adds after the lists of `hardcoded_admins` were
+//! frozen. The registry is the `admin_registry` compute param, so every node executes a block
+//! with the registry of its state. The enclave only takes it if its root is signed by one of
+//! `ADMIN_REGISTRY_SIGNERS`, and a registry that isn't signed grants nothing.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::{Arc, SgxMutex};
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_v010::encoding::Binary;
+use enclave_crypto::{sha_256, HASH_SIZE};
+use enclave_ffi_types::EnclaveError;
+
+use crate::panic_isolation::LockOrRecover;
+
+/// Separates the hashes of registries from other hashes of the same bytes
+const REGISTRY_DOMAIN: &[u8] = b"secret_admin_registry";
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Ed25519 keys that sign admin registries. Until a key is added here, registries are ignored
+/// and only the admins and code hashes compiled into `hardcoded_admins` apply.
+const ADMIN_REGISTRY_SIGNERS: &[[u8; 32]] = &[];
+
+lazy_static! {
+    /// The last registry that was verified, with the hash of its params. The registry only
+    /// changes with a governance proposal, so executions don't verify it again.
+    static ref VERIFIED_REGISTRY: SgxMutex<Option<([u8; HASH_SIZE], Arc<AdminRegistry>)>> =
+        SgxMutex::new(None);
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// `value` is the admin that can migrate the contract with an empty admin proof
+    Admin,
+    /// `value` is the hex code hash of a system contract, like a core DEX or a bridge. The module
+    /// cache never evicts the code, and hosts run its executions in a dedicated lane.
+    SystemContract,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RegistryEntry {
+    pub kind: EntryKind,
+    /// Bech32 address of the contract
+    pub contract: String,
+    pub value: String,
+}
+
+impl RegistryEntry {
+    fn leaf_hash(&self) -> [u8; HASH_SIZE] {
+        let mut data = vec![LEAF_PREFIX, self.kind as u8];
+        for field in &[&self.contract, &self.value] {
+            data.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            data.extend_from_slice(field.as_bytes());
+        }
+        sha_256(&data)
+    }
+}
+
+/// An entry and the hashes of its siblings on the path to the root of the registry
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProvenEntry {
+    pub entry: RegistryEntry,
+    pub proof: Vec<Binary>,
+}
+
+impl ProvenEntry {
+    /// Pairs of nodes are hashed in sorted order, so proofs don't need the position of the entry
+    fn verify(&self, root: &[u8; HASH_SIZE]) -> bool {
+        let mut hash = self.entry.leaf_hash();
+        for sibling in &self.proof {
+            let sibling = match <[u8; HASH_SIZE]>::try_from(sibling.as_slice()) {
+                Ok(sibling) => sibling,
+                Err(_) => return false,
+            };
+            let (left, right) = if hash <= sibling {
+                (hash, sibling)
+            } else {
+                (sibling, hash)
+            };
+
+            let mut data = vec![NODE_PREFIX];
+            data.extend_from_slice(&left);
+            data.extend_from_slice(&right);
+            hash = sha_256(&data);
+        }
+
+        hash == *root
+    }
+}
+
+/// The `admin_registry` compute param: the Merkle root of the registry signed by one of
+/// `ADMIN_REGISTRY_SIGNERS`, and the entries with their proofs
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SignedAdminRegistry {
+    /// Signed with the root, so a proposal can only set a registry as it was signed
+    pub version: u64,
+    pub root: Binary,
+    pub signature: Binary,
+    pub entries: Vec<ProvenEntry>,
+}
+
+impl SignedAdminRegistry {
+    fn signed_bytes(version: u64, root: &[u8]) -> Vec<u8> {
+        let mut data = REGISTRY_DOMAIN.to_vec();
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(root);
+        data
+    }
+
+    fn verify_signature(&self, signers: &[[u8; 32]]) -> bool {
+        let signature = match ed25519_zebra::Signature::try_from(self.signature.as_slice()) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        let signed_bytes = Self::signed_bytes(self.version, self.root.as_slice());
+
+        signers.iter().any(|signer| {
+            ed25519_zebra::VerificationKey::try_from(&signer[..])
+                .and_then(|key| key.verify(&signature, &signed_bytes))
+                .is_ok()
+        })
+    }
+
+    /// Verifies the signature of the registry and the proofs of its entries
+    pub fn verify(&self) -> Result<Arc<AdminRegistry>, EnclaveError> {
+        let serialized = serde_json::to_vec(self).map_err(|err| {
+            warn!("failed to serialize the admin registry: {:?}", err);
+            EnclaveError::FailedToSerialize
+        })?;
+        let params_hash = sha_256(&serialized);
+
+        let mut verified = VERIFIED_REGISTRY.lock_or_recover();
+        if let Some((verified_hash, registry)) = verified.as_ref() {
+            if *verified_hash == params_hash {
+                return Ok(registry.clone());
+            }
+        }
+
+        let registry = Arc::new(AdminRegistry::from_signed(self, ADMIN_REGISTRY_SIGNERS)?);
+        info!(
+            "verified admin registry {} with {} entries",
+            self.version,
+            self.entries.len()
+        );
+        *verified = Some((params_hash, registry.clone()));
+
+        Ok(registry)
+    }
+}
+
+/// A registry whose signature was verified. The proofs of its entries are verified again
+/// against the signed root on every lookup.
+#[derive(Debug)]
+pub struct AdminRegistry {
+    root: [u8; HASH_SIZE],
+    entries: HashMap<(EntryKind, String), ProvenEntry>,
+    /// The code hashes of the `SystemContract` entries, which are looked up on every execution
+    system_codes: HashSet<[u8; HASH_SIZE]>,
+}
+
+impl AdminRegistry {
+    fn from_signed(
+        signed: &SignedAdminRegistry,
+        signers: &[[u8; 32]],
+    ) -> Result<Self, EnclaveError> {
+        if !signed.verify_signature(signers) {
+            warn!(
+                "admin registry {} is not signed by a registry signer",
+                signed.version
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        let root = <[u8; HASH_SIZE]>::try_from(signed.root.as_slice()).map_err(|_| {
+            warn!("admin registry {} has a malformed root", signed.version);
+            EnclaveError::ValidationFailure
+        })?;
+
+        let mut entries = HashMap::new();
+        let mut system_codes = HashSet::new();
+        for proven in &signed.entries {
+            if !proven.verify(&root) {
+                warn!(
+                    "admin registry {} has an entry with an invalid proof: {:?}",
+                    signed.version, proven.entry
+                );
+                return Err(EnclaveError::ValidationFailure);
+            }
+            let key = (proven.entry.kind, proven.entry.contract.clone());
+            if entries.insert(key, proven.clone()).is_some() {
+                warn!(
+                    "admin registry {} has more than one {:?} entry for {}",
+                    signed.version, proven.entry.kind, proven.entry.contract
+                );
+                return Err(EnclaveError::ValidationFailure);
+            }
+            if proven.entry.kind == EntryKind::SystemContract {
+                let code_hash = hex::decode(&proven.entry.value)
+                    .ok()
+                    .and_then(|hash| <[u8; HASH_SIZE]>::try_from(hash.as_slice()).ok())
+                    .ok_or_else(|| {
+                        warn!(
+                            "admin registry {} has a malformed system code hash for {}",
+                            signed.version, proven.entry.contract
+                        );
+                        EnclaveError::ValidationFailure
+                    })?;
+                system_codes.insert(code_hash);
+            }
+        }
+
+        Ok(AdminRegistry {
+            root,
+            entries,
+            system_codes,
+        })
+    }
+
+    fn contains(&self, kind: EntryKind, contract: &str, value: &str) -> bool {
+        match self.entries.get(&(kind, contract.to_string())) {
+            Some(proven) => proven.entry.value == value && proven.verify(&self.root),
+            None => false,
+        }
+    }
+
+    /// Whether the registry makes `admin` the admin of the contract
+    pub fn is_admin(&self, contract: &str, admin: &str) -> bool {
+        self.contains(EntryKind::Admin, contract, admin)
+    }
+}
+
+/// Whether the code is the code of a system contract in the last verified registry. The system
+/// contracts only decide what the module cache keeps, so they can lag behind the registry of
+/// the block.
+pub fn is_system_code(code_hash: &[u8; HASH_SIZE]) -> bool {
+    VERIFIED_REGISTRY
+        .lock_or_recover()
+        .as_ref()
+        .map_or(false, |(_, registry)| {
+            registry.system_codes.contains(code_hash)
+        })
+}
+
+/// The concatenated code hashes of the system contracts in the last verified registry, in order
+pub fn system_code_hashes() -> Vec<u8> {
+    let registry = VERIFIED_REGISTRY.lock_or_recover();
+    let mut hashes: Vec<&[u8; HASH_SIZE]> =
+        registry.as_ref().map_or_else(Vec::new, |(_, registry)| {
+            registry.system_codes.iter().collect()
+        });
+    hashes.sort();
+
+    hashes.into_iter().flatten().copied().collect()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn node(a: [u8; HASH_SIZE], b: [u8; HASH_SIZE]) -> [u8; HASH_SIZE] {
+        let (left, right) = if a <= b { (a, b) } else { (b, a) };
+        let mut data = vec![NODE_PREFIX];
+        data.extend_from_slice(&left);
+        data.extend_from_slice(&right);
+        sha_256(&data)
+    }
+
+    pub fn test_admin_registry() {
+        let entries = vec![
+            RegistryEntry {
+                kind: EntryKind::Admin,
+                contract: "secret1contract".to_string(),
+                value: "secret1admin".to_string(),
+            },
+            RegistryEntry {
+                kind: EntryKind::Admin,
+                contract: "secret1other".to_string(),
+                value: "secret1admin".to_string(),
+            },
+            RegistryEntry {
+                kind: EntryKind::SystemContract,
+                contract: "secret1dex".to_string(),
+                value: "cd".repeat(32),
+            },
+        ];
+        let leaves: Vec<_> = entries.iter().map(RegistryEntry::leaf_hash).collect();
+        let root = node(node(leaves[0], leaves[1]), leaves[2]);
+        let proofs = vec![
+            vec![leaves[1], leaves[2]],
+            vec![leaves[0], leaves[2]],
+            vec![node(leaves[0], leaves[1])],
+        ];
+
+        let signing_key = ed25519_zebra::SigningKey::from([7u8; 32]);
+        let signer: [u8; 32] = ed25519_zebra::VerificationKey::from(&signing_key).into();
+        let signed = SignedAdminRegistry {
+            version: 1,
+            root: Binary(root.to_vec()),
+            signature: Binary(
+                <[u8; 64]>::from(signing_key.sign(&SignedAdminRegistry::signed_bytes(1, &root)))
+                    .to_vec(),
+            ),
+            entries: entries
+                .into_iter()
+                .zip(proofs)
+                .map(|(entry, proof)| ProvenEntry {
+                    entry,
+                    proof: proof.iter().map(|hash| Binary(hash.to_vec())).collect(),
+                })
+                .collect(),
+        };
+
+        let registry = AdminRegistry::from_signed(&signed, &[signer]).unwrap();
+        assert!(registry.is_admin("secret1contract", "secret1admin"));
+        assert!(registry.is_admin("secret1other", "secret1admin"));
+        assert!(!registry.is_admin("secret1contract", "secret1other"));
+        assert!(!registry.is_admin("secret1dex", &"cd".repeat(32)));
+        assert!(registry.system_codes.contains(&[0xcd; HASH_SIZE]));
+        assert!(!registry.system_codes.contains(&[0xab; HASH_SIZE]));
+
+        // Not signed by a signer
+        assert!(AdminRegistry::from_signed(&signed, &[[1u8; 32]]).is_err());
+
+        // None of the registry signers signed it, so the compute param grants nothing
+        assert!(signed.verify().is_err());
+
+        // An entry that isn't in the signed tree
+        let mut forged = signed.clone();
+        forged.entries[0].entry.value = "secret1attacker".to_string();
+        assert!(AdminRegistry::from_signed(&forged, &[signer]).is_err());
+
+        // A version that wasn't signed
+        let mut forged = signed;
+        forged.version = 2;
+        assert!(AdminRegistry::from_signed(&forged, &[signer]).is_err());
+    }
+}
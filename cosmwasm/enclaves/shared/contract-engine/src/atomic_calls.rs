@@ -0,0 +1,167 @@
+use log::*;
+use serde::Deserialize;
+use serde_json::Value;
+
+use cw_types_v010::encoding::Binary;
+use cw_types_v1::coins::Coin;
+use cw_types_v1::results::{CosmosMsg, ReplyOn, SubMsg, WasmMsg};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::atomic_calls::MAX_CALLS_PER_EXECUTION;
+
+/// A call a coordinator contract makes through `execute_contracts_atomic`
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct AtomicCall {
+    pub contract_addr: String,
+    pub code_hash: String,
+    pub msg: Binary,
+    #[serde(default)]
+    pub funds: Vec<Coin>,
+    /// The gas budget of the callee. The whole transaction fails if the callee exceeds it.
+    pub gas_limit: u64,
+}
+
+impl AtomicCall {
+    /// The callee is executed as a submessage that is never replied to, so if it fails the
+    /// error propagates and the state changes of the coordinator and all of the callees are
+    /// rolled back together.
+    fn into_sub_msg(self) -> SubMsg {
+        SubMsg {
+            id: 0,
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: self.contract_addr,
+                code_hash: self.code_hash,
+                msg: self.msg,
+                funds: self.funds,
+                callback_sig: None,
+            }),
+            gas_limit: Some(self.gas_limit),
+            reply_on: ReplyOn::Never,
+            was_msg_encrypted: false,
+            payload: Binary::default(),
+        }
+    }
+}
+
+/// Parses the calls passed to `execute_contracts_atomic`, as a JSON array of `AtomicCall`s.
+/// `already_requested` is the amount of calls the contract already made in this execution.
+/// Errors are returned to the contract as messages.
+pub fn parse_atomic_calls(
+    calls: &[u8],
+    already_requested: usize,
+) -> Result<Vec<AtomicCall>, String> {
+    let calls: Vec<AtomicCall> =
+        serde_json::from_slice(calls).map_err(|err| format!("Invalid calls: {}", err))?;
+
+    if calls.is_empty() {
+        return Err("No calls were given".to_string());
+    }
+    if already_requested + calls.len() > MAX_CALLS_PER_EXECUTION {
+        return Err(format!(
+            "Too many atomic calls, the limit is {}",
+            MAX_CALLS_PER_EXECUTION
+        ));
+    }
+
+    for call in &calls {
+        if call.contract_addr.is_empty() {
+            return Err("Contract address is empty".to_string());
+        }
+        if call.gas_limit == 0 {
+            return Err(format!("Gas limit of {} is 0", call.contract_addr));
+        }
+    }
+
+    Ok(calls)
+}
+
+/// Appends the atomic calls a contract made to the messages in its raw output.
+/// If the contract returned an error, the calls are dropped along with its other messages.
+pub fn append_atomic_calls(
+    output: Vec<u8>,
+    calls: Vec<AtomicCall>,
+) -> Result<Vec<u8>, EnclaveError> {
+    if calls.is_empty() {
+        return Ok(output);
+    }
+
+    let mut value: Value = serde_json::from_slice(&output).map_err(|err| {
+        warn!("failed to parse output to append atomic calls: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if value.get("Err").is_some() {
+        return Ok(output);
+    }
+
+    let messages = match value
+        .get_mut("Ok")
+        .and_then(|ok| ok.get_mut("messages"))
+        .and_then(|messages| messages.as_array_mut())
+    {
+        Some(messages) => messages,
+        None => {
+            warn!("made atomic calls from a contract whose output has no messages");
+            return Err(EnclaveError::FailedToDeserialize);
+        }
+    };
+
+    for call in calls {
+        let sub_msg = serde_json::to_value(call.into_sub_msg()).map_err(|err| {
+            warn!("failed to serialize atomic call: {}", err);
+            EnclaveError::FailedToSerialize
+        })?;
+        messages.push(sub_msg);
+    }
+
+    serde_json::to_vec(&value).map_err(|err| {
+        warn!("failed to serialize output with atomic calls: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    const CALL: &str =
+        r#"{"contract_addr":"secret1callee","code_hash":"ab","msg":"e30=","gas_limit":100000}"#;
+
+    pub fn test_parse_atomic_calls() {
+        let calls = parse_atomic_calls(format!("[{}]", CALL).as_bytes(), 0).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].gas_limit, 100_000);
+        assert!(calls[0].funds.is_empty());
+
+        assert!(parse_atomic_calls(b"[]", 0).is_err());
+        assert!(parse_atomic_calls(b"{}", 0).is_err());
+        assert!(
+            parse_atomic_calls(format!("[{}]", CALL).as_bytes(), MAX_CALLS_PER_EXECUTION).is_err()
+        );
+        assert!(
+            parse_atomic_calls(format!("[{}]", CALL.replace("100000", "0")).as_bytes(), 0).is_err()
+        );
+    }
+
+    pub fn test_append_atomic_calls() {
+        let calls = parse_atomic_calls(format!("[{}]", CALL).as_bytes(), 0).unwrap();
+
+        let output = br#"{"Ok":{"messages":[],"attributes":[],"events":[],"data":null}}"#.to_vec();
+        let output = append_atomic_calls(output, calls.clone()).unwrap();
+        let value: Value = serde_json::from_slice(&output).unwrap();
+        let messages = value["Ok"]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["gas_limit"], 100_000);
+        assert_eq!(messages[0]["reply_on"], "never");
+        assert_eq!(
+            messages[0]["msg"]["wasm"]["execute"]["contract_addr"],
+            "secret1callee"
+        );
+
+        let err_output = br#"{"Err":{"generic_err":{"msg":"failed"}}}"#.to_vec();
+        assert_eq!(
+            append_atomic_calls(err_output.clone(), calls).unwrap(),
+            err_output
+        );
+    }
+}
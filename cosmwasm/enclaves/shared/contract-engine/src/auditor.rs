@@ -0,0 +1,82 @@
+use std::convert::TryFrom;
+
+use log::*;
+
+use enclave_crypto::{Ed25519PublicKey, SIVEncryptable};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::{output_padding, reserved_keys};
+use crate::io::{calc_encryption_key, PaddingBuckets};
+use crate::types::IoNonce;
+use crate::wasm3::Engine;
+
+/// Parses the x25519 public key passed to `set_auditor_key`. Errors are returned to the contract
+/// as messages.
+pub fn parse_auditor_key(key: &[u8]) -> Result<Ed25519PublicKey, String> {
+    Ed25519PublicKey::try_from(key)
+        .map_err(|_| format!("Invalid auditor key: length is {}, should be 32", key.len()))
+}
+
+/// Stores the auditor the contract set while it was instantiated. The auditor can't be changed
+/// afterwards, so users can check in the code of a contract who can read its outputs.
+pub fn store_auditor_key(engine: &mut Engine, auditor_key: Option<Ed25519PublicKey>) {
+    if let Some(auditor_key) = auditor_key {
+        engine.write_reserved_key(reserved_keys::AUDITOR_KEY, &auditor_key);
+    }
+}
+
+/// Returns the auditor of the contract, if it has one
+pub fn read_auditor_key(engine: &mut Engine) -> Result<Option<Ed25519PublicKey>, EnclaveError> {
+    match engine.read_reserved_key(reserved_keys::AUDITOR_KEY)? {
+        Some(stored) => Ed25519PublicKey::try_from(stored.as_slice())
+            .map(Some)
+            .map_err(|_| {
+                warn!("stored auditor key is malformed");
+                EnclaveError::FailedToDeserialize
+            }),
+        None => Ok(None),
+    }
+}
+
+/// Encrypts the plaintext output of the contract for its auditor, with the same nonce as the
+/// output that is encrypted for the user. The result is the base64 of the nonce followed by the
+/// ciphertext, which the auditor decrypts with the consensus IO exchange key like a user would.
+/// The plaintext is padded like other outputs, and the padding is added to `padded_bytes`.
+pub fn encrypt_for_auditor(
+    output: &[u8],
+    nonce: &IoNonce,
+    auditor_key: &Ed25519PublicKey,
+    buckets: &PaddingBuckets,
+    padded_bytes: &mut u64,
+) -> Result<String, EnclaveError> {
+    let padding = buckets.padded_len(output.len()) - output.len();
+    *padded_bytes = padded_bytes.saturating_add(padding as u64);
+
+    let mut plaintext = output.to_vec();
+    plaintext.resize(output.len() + padding, output_padding::PADDING_BYTE);
+
+    let key = calc_encryption_key(nonce, auditor_key);
+    let ciphertext = key.encrypt_siv(&plaintext, None).map_err(|err| {
+        debug!(
+            "got an error while encrypting the output for the auditor: {:?}",
+            err
+        );
+        EnclaveError::EncryptionError
+    })?;
+
+    let mut audit_output = nonce.to_vec();
+    audit_output.extend_from_slice(&ciphertext);
+    Ok(base64::encode(audit_output))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_parse_auditor_key() {
+        assert_eq!(parse_auditor_key(&[3u8; 32]), Ok([3u8; 32]));
+        assert!(parse_auditor_key(&[3u8; 31]).is_err());
+        assert!(parse_auditor_key(&[3u8; 33]).is_err());
+        assert!(parse_auditor_key(b"").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! Bech32 and bech32m (BIP-350) with any human readable part, for the addresses of other chains.
+//! The `bech32` crate the enclave uses for its own addresses doesn't support bech32m.
+
+use serde::Serialize;
+
+use cw_types_v010::encoding::Binary;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const SEPARATOR: char = '1';
+const CHECKSUM_LENGTH: usize = 6;
+/// Like the Cosmos SDK, which allows longer strings than BIP-173
+const MAX_LENGTH: usize = 1023;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+impl Variant {
+    pub fn from_u32(variant: u32) -> Option<Self> {
+        match variant {
+            0 => Some(Variant::Bech32),
+            1 => Some(Variant::Bech32m),
+            _ => None,
+        }
+    }
+
+    fn checksum_constant(self) -> u32 {
+        match self {
+            Variant::Bech32 => 1,
+            Variant::Bech32m => 0x2bc8_30a3,
+        }
+    }
+}
+
+/// The errors of the bech32 host functions, which `bech32_encode` and `bech32_decode` return in
+/// the high half of their results
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum Bech32Error {
+    InvalidLength = 1,
+    InvalidHrp = 2,
+    InvalidChar = 3,
+    MixedCase = 4,
+    InvalidChecksum = 5,
+    InvalidPadding = 6,
+    InvalidVariant = 7,
+}
+
+impl Bech32Error {
+    pub fn message(self) -> &'static str {
+        match self {
+            Bech32Error::InvalidLength => "invalid length",
+            Bech32Error::InvalidHrp => "invalid human readable part",
+            Bech32Error::InvalidChar => "invalid character",
+            Bech32Error::MixedCase => "mixed case",
+            Bech32Error::InvalidChecksum => "invalid checksum",
+            Bech32Error::InvalidPadding => "invalid padding",
+            Bech32Error::InvalidVariant => "invalid variant",
+        }
+    }
+}
+
+/// A decoded bech32 string, as returned by `bech32_decode`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Decoded {
+    pub hrp: String,
+    pub data: Binary,
+    pub variant: Variant,
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATORS: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x01ff_ffff) << 5) ^ u32::from(*value);
+        for (i, generator) in GENERATORS.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn hrp_expand(hrp: &[u8]) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.iter().map(|c| c >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.iter().map(|c| c & 0x1f));
+    expanded
+}
+
+fn checksum(hrp: &[u8], data: &[u8], variant: Variant) -> Vec<u8> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0; CHECKSUM_LENGTH]);
+    let polymod = polymod(&values) ^ variant.checksum_constant();
+
+    (0..CHECKSUM_LENGTH)
+        .map(|i| ((polymod >> (5 * (5 - i))) & 0x1f) as u8)
+        .collect()
+}
+
+/// Regroups bits, from bytes to 5 bit groups when encoding and back when decoding
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Bech32Error> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_value: u32 = (1 << to) - 1;
+    let mut converted = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+
+    for value in data {
+        acc = (acc << from) | u32::from(*value);
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((acc >> bits) & max_value) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            converted.push(((acc << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max_value) != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+
+    Ok(converted)
+}
+
+fn validate_hrp(hrp: &str) -> Result<(), Bech32Error> {
+    if hrp.is_empty() || hrp.len() > 83 {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if !hrp.bytes().all(|c| (33..=126).contains(&c)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if hrp.bytes().any(|c| c.is_ascii_uppercase()) {
+        return Err(Bech32Error::MixedCase);
+    }
+
+    Ok(())
+}
+
+/// Encodes `data` with a lowercase human readable part
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> Result<String, Bech32Error> {
+    validate_hrp(hrp)?;
+
+    let data = convert_bits(data, 8, 5, true)?;
+    let checksum = checksum(hrp.as_bytes(), &data, variant);
+    if hrp.len() + 1 + data.len() + CHECKSUM_LENGTH > MAX_LENGTH {
+        return Err(Bech32Error::InvalidLength);
+    }
+
+    let mut encoded = String::with_capacity(hrp.len() + 1 + data.len() + CHECKSUM_LENGTH);
+    encoded.push_str(hrp);
+    encoded.push(SEPARATOR);
+    encoded.extend(
+        data.iter()
+            .chain(checksum.iter())
+            .map(|value| CHARSET[*value as usize] as char),
+    );
+
+    Ok(encoded)
+}
+
+/// Decodes a bech32 or bech32m string. Uppercase strings are accepted, like BIP-173 requires,
+/// and the human readable part is returned in lowercase.
+pub fn decode(encoded: &str) -> Result<Decoded, Bech32Error> {
+    if encoded.len() > MAX_LENGTH {
+        return Err(Bech32Error::InvalidLength);
+    }
+    let has_lowercase = encoded.bytes().any(|c| c.is_ascii_lowercase());
+    let has_uppercase = encoded.bytes().any(|c| c.is_ascii_uppercase());
+    if has_lowercase && has_uppercase {
+        return Err(Bech32Error::MixedCase);
+    }
+    let encoded = encoded.to_ascii_lowercase();
+
+    let separator = encoded.rfind(SEPARATOR).ok_or(Bech32Error::InvalidChar)?;
+    let (hrp, data) = (&encoded[..separator], &encoded[separator + 1..]);
+    validate_hrp(hrp)?;
+    if data.len() < CHECKSUM_LENGTH {
+        return Err(Bech32Error::InvalidLength);
+    }
+
+    let data = data
+        .bytes()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|charset_c| *charset_c == c)
+                .map(|value| value as u8)
+                .ok_or(Bech32Error::InvalidChar)
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let mut values = hrp_expand(hrp.as_bytes());
+    values.extend_from_slice(&data);
+    let variant = match polymod(&values) {
+        constant if constant == Variant::Bech32.checksum_constant() => Variant::Bech32,
+        constant if constant == Variant::Bech32m.checksum_constant() => Variant::Bech32m,
+        _ => return Err(Bech32Error::InvalidChecksum),
+    };
+
+    let data = convert_bits(&data[..data.len() - CHECKSUM_LENGTH], 5, 8, false)?;
+
+    Ok(Decoded {
+        hrp: hrp.to_string(),
+        data: Binary(data),
+        variant,
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_bech32_vectors() {
+        // From BIP-173 and BIP-350
+        assert_eq!(decode("A12UEL5L").unwrap().variant, Variant::Bech32);
+        assert_eq!(decode("a12uel5l").unwrap().hrp, "a");
+        assert_eq!(decode("A1LQFN3A").unwrap().variant, Variant::Bech32m);
+        let decoded = decode("abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx").unwrap();
+        assert_eq!(decoded.hrp, "abcdef");
+        assert_eq!(decoded.variant, Variant::Bech32m);
+        assert_eq!(
+            encode(&decoded.hrp, decoded.data.as_slice(), decoded.variant).unwrap(),
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx"
+        );
+
+        assert_eq!(decode("A1G7SGD8"), Err(Bech32Error::InvalidChecksum));
+        assert_eq!(decode("a1LQFN3A"), Err(Bech32Error::MixedCase));
+        assert_eq!(decode("1qzzfhee"), Err(Bech32Error::InvalidHrp));
+        assert_eq!(decode("a1b2c3d4"), Err(Bech32Error::InvalidChar));
+        assert_eq!(decode("pzry9x0s0muk"), Err(Bech32Error::InvalidChar));
+    }
+
+    pub fn test_bech32_roundtrip() {
+        let data = [7u8; 20];
+        for variant in [Variant::Bech32, Variant::Bech32m].iter().copied() {
+            let encoded = encode("osmo", &data, variant).unwrap();
+            assert!(encoded.starts_with("osmo1"));
+            assert_eq!(
+                decode(&encoded).unwrap(),
+                Decoded {
+                    hrp: "osmo".to_string(),
+                    data: Binary(data.to_vec()),
+                    variant,
+                }
+            );
+        }
+
+        // The same address the enclave encodes for its own prefix
+        let canonical = [1u8; 20];
+        assert_eq!(
+            encode("secret", &canonical, Variant::Bech32).unwrap(),
+            bech32::encode("secret", bech32::ToBase32::to_base32(&canonical)).unwrap()
+        );
+
+        assert_eq!(
+            encode("Osmo", &data, Variant::Bech32),
+            Err(Bech32Error::MixedCase)
+        );
+        assert_eq!(
+            encode("", &data, Variant::Bech32),
+            Err(Bech32Error::InvalidHrp)
+        );
+    }
+}
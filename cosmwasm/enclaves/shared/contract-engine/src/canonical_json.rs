@@ -0,0 +1,114 @@
+use serde_json::Value;
+
+/// The errors `canonical_json` returns in the high half of its result
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum CanonicalJsonError {
+    InvalidJson = 1,
+    /// Numbers that aren't 64 bit integers, which can't be canonicalized without losing
+    /// precision
+    UnsupportedNumber = 2,
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // Escaped like Go's encoding/json, which the Amino JSON of wallets follows
+            '<' | '>' | '&' | '\u{2028}' | '\u{2029}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_value(value: &Value, out: &mut String) -> Result<(), CanonicalJsonError> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(boolean) => out.push_str(if *boolean { "true" } else { "false" }),
+        Value::Number(number) => {
+            if number.is_f64() {
+                return Err(CanonicalJsonError::UnsupportedNumber);
+            }
+            out.push_str(&number.to_string());
+        }
+        Value::String(string) => write_string(string, out),
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                write_value(value, out)?;
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(value, out)?;
+            }
+            out.push('}');
+        }
+    }
+
+    Ok(())
+}
+
+/// Canonicalizes JSON like the Amino JSON sign docs the enclave verifies signatures of: object
+/// keys are sorted by their bytes, there's no whitespace, and `<`, `>` and `&` are escaped.
+pub fn canonicalize_json(json: &[u8]) -> Result<Vec<u8>, CanonicalJsonError> {
+    let value: Value = serde_json::from_slice(json).map_err(|_| CanonicalJsonError::InvalidJson)?;
+
+    let mut canonical = String::with_capacity(json.len());
+    write_value(&value, &mut canonical)?;
+
+    Ok(canonical.into_bytes())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_canonicalize_json() {
+        let canonical = canonicalize_json(
+            br#" { "msgs": [ {"value": {"b": 1, "a": -2}, "type": "x"} ], "chain_id": "secret-4",
+                "memo": "<a&b>\n\u0001\u2028", "fee": {"gas": "1", "amount": []}, "ok": true, "n": null } "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(canonical).unwrap(),
+            r#"{"chain_id":"secret-4","fee":{"amount":[],"gas":"1"},"memo":"\u003ca\u0026b\u003e\n\u0001\u2028","msgs":[{"type":"x","value":{"a":-2,"b":1}}],"n":null,"ok":true}"#
+        );
+
+        assert_eq!(
+            canonicalize_json(br#"{"a":"b""#),
+            Err(CanonicalJsonError::InvalidJson)
+        );
+        assert_eq!(
+            canonicalize_json(br#"{"a":1.5}"#),
+            Err(CanonicalJsonError::UnsupportedNumber)
+        );
+        assert_eq!(
+            canonicalize_json(b"18446744073709551615").unwrap(),
+            b"18446744073709551615".to_vec()
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! A summary of the sensitive capabilities that a contract exercised in an execution.
+//!
+//! A client requests the summary of an init, execution or migration by putting `MAGIC_BYTES` in
+//! front of the message it encrypts, after the code hash and before an idempotency key:
+//!
+//! ```text
+//! code_hash | MAGIC_BYTES | msg
+//! ```
+//!
+//! The contract can't tell that a summary was requested, nor opt out of it. The summary is added
+//! to the output of a successful execution as an attribute that's encrypted for the sender like
+//! the contract's own encrypted attributes. Clients and auditors can watch for changes in what a
+//! contract does over time, e.g. after a migration, without relying on the attributes the
+//! contract chooses to emit.
+//!
+//! The summary counts the calls the contract made to each host function, e.g. to `unique_id` or
+//! `random_commit` for randomness, and what it emitted and sent in its output. It's JSON like
+//! `{"host_functions":{"db_read":3,"unique_id":1},"plaintext_attributes":2,"stargate_messages":0}`.
+
+use std::collections::BTreeMap;
+
+use log::*;
+use serde::Serialize;
+
+use cw_types_v010::types::LogAttribute;
+use cw_types_v1::results::{CosmosMsg, Event, SubMsg};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::capability_summary::{MAGIC_BYTES, SUMMARY_ATTRIBUTE};
+use crate::io::RawWasmOutput;
+
+/// The calls of an execution to each host function
+pub type HostCalls = BTreeMap<&'static str, u64>;
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CapabilitySummary {
+    /// The host functions the contract called, with the number of calls to each
+    pub host_functions: HostCalls,
+    /// The attributes, including those of custom events, that the contract emitted in plaintext
+    pub plaintext_attributes: u64,
+    /// The Stargate messages the contract sent, which can call any module of the chain
+    pub stargate_messages: u64,
+}
+
+fn count_plaintext(attributes: &[LogAttribute]) -> u64 {
+    attributes.iter().filter(|attr| !attr.encrypted).count() as u64
+}
+
+fn count_plaintext_in_events(attributes: &[LogAttribute], events: &[Event]) -> u64 {
+    events
+        .iter()
+        .map(|event| count_plaintext(&event.attributes))
+        .sum::<u64>()
+        + count_plaintext(attributes)
+}
+
+fn count_stargate<T>(messages: &[SubMsg<T>]) -> u64
+where
+    T: Clone + std::fmt::Debug + PartialEq,
+{
+    messages
+        .iter()
+        .filter(|sub_msg| matches!(sub_msg.msg, CosmosMsg::Stargate { .. }))
+        .count() as u64
+}
+
+/// Removes the request of a capability summary from the start of a decrypted message, and
+/// returns whether it had one
+pub fn take_capability_summary_request(msg: &mut Vec<u8>) -> bool {
+    if !is_active(Feature::CapabilitySummary) || !msg.starts_with(MAGIC_BYTES) {
+        return false;
+    }
+
+    *msg = msg[MAGIC_BYTES.len()..].to_vec();
+    true
+}
+
+impl CapabilitySummary {
+    pub fn new(host_functions: HostCalls) -> Self {
+        Self {
+            host_functions,
+            ..Self::default()
+        }
+    }
+
+    /// Counts what the contract emitted and sent in its output, before it's encrypted
+    pub fn count_output(&mut self, raw_output: &RawWasmOutput) {
+        let (plaintext_attributes, stargate_messages) = match raw_output {
+            RawWasmOutput::OkV010 { ok, .. } => (count_plaintext(&ok.log), 0),
+            RawWasmOutput::OkV1 { ok, .. } => (
+                count_plaintext_in_events(&ok.attributes, &ok.events),
+                count_stargate(&ok.messages),
+            ),
+            RawWasmOutput::OkIBCPacketReceive { ok } => (
+                count_plaintext_in_events(&ok.attributes, &ok.events),
+                count_stargate(&ok.messages),
+            ),
+            RawWasmOutput::Err { .. }
+            | RawWasmOutput::QueryOkV010 { .. }
+            | RawWasmOutput::QueryOkV1 { .. }
+            | RawWasmOutput::OkIBCOpenChannel { .. } => (0, 0),
+        };
+        self.plaintext_attributes = plaintext_attributes;
+        self.stargate_messages = stargate_messages;
+    }
+
+    /// The attribute that carries the summary, which is encrypted with the rest of the output
+    pub fn to_attribute(&self) -> Result<LogAttribute, EnclaveError> {
+        let value = serde_json::to_string(self).map_err(|err| {
+            warn!("failed to serialize the capability summary: {}", err);
+            EnclaveError::FailedToSerialize
+        })?;
+
+        Ok(LogAttribute {
+            key: SUMMARY_ATTRIBUTE.to_string(),
+            value,
+            encrypted: true,
+        })
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cw_types_v010::encoding::Binary;
+    use cw_types_v1::results::{ReplyOn, Response};
+    use enclave_cosmos_types::feature_activation::set_active_block;
+
+    fn attribute(key: &str, encrypted: bool) -> LogAttribute {
+        LogAttribute {
+            key: key.to_string(),
+            value: "value".to_string(),
+            encrypted,
+        }
+    }
+
+    pub fn test_take_capability_summary_request() {
+        let mut msg = [MAGIC_BYTES, b"{}"].concat();
+        assert!(take_capability_summary_request(&mut msg));
+        assert_eq!(msg, b"{}");
+
+        // Only the start of the message is a request
+        let mut msg = [b"{}", MAGIC_BYTES].concat();
+        assert!(!take_capability_summary_request(&mut msg));
+        assert_eq!(msg, [b"{}", MAGIC_BYTES].concat());
+
+        // Messages aren't changed before the feature activates
+        let mut msg = [MAGIC_BYTES, b"{}"].concat();
+        set_active_block("secret-4", 1);
+        assert!(!take_capability_summary_request(&mut msg));
+        set_active_block("secretdev-1", 1);
+        assert_eq!(msg, [MAGIC_BYTES, b"{}"].concat());
+    }
+
+    pub fn test_capability_summary() {
+        let response = Response {
+            messages: vec![SubMsg {
+                id: 0,
+                msg: CosmosMsg::Stargate {
+                    type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(),
+                    value: Binary(vec![]),
+                },
+                gas_limit: None,
+                reply_on: ReplyOn::Never,
+                was_msg_encrypted: true,
+                payload: Binary(vec![]),
+            }],
+            attributes: vec![attribute("action", false), attribute("secret", true)],
+            events: vec![Event {
+                ty: "transfer".to_string(),
+                attributes: vec![attribute("sender", false)],
+            }],
+            data: None,
+        };
+        let raw_output = RawWasmOutput::OkV1 {
+            ok: response,
+            internal_reply_enclave_sig: None,
+            internal_msg_id: None,
+        };
+
+        let host_functions: HostCalls =
+            vec![("db_read", 3), ("unique_id", 2)].into_iter().collect();
+        let mut summary = CapabilitySummary::new(host_functions.clone());
+        summary.count_output(&raw_output);
+        assert_eq!(
+            summary,
+            CapabilitySummary {
+                host_functions,
+                plaintext_attributes: 2,
+                stargate_messages: 1,
+            }
+        );
+
+        let attribute = summary.to_attribute().unwrap();
+        assert_eq!(attribute.key, SUMMARY_ATTRIBUTE);
+        assert!(attribute.encrypted);
+        assert_eq!(
+            attribute.value,
+            r#"{"host_functions":{"db_read":3,"unique_id":2},"plaintext_attributes":2,"stargate_messages":1}"#
+        );
+
+        // Errors have nothing to count
+        let mut summary = CapabilitySummary::new(HostCalls::new());
+        summary.count_output(&RawWasmOutput::Err {
+            err: serde_json::json!({"generic_err": {"msg": "failed"}}),
+            internal_msg_id: None,
+            internal_reply_enclave_sig: None,
+        });
+        assert_eq!(summary, CapabilitySummary::default());
+    }
+}
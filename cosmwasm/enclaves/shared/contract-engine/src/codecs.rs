@@ -0,0 +1,68 @@
+use log::*;
+
+/// The error `base64_decode` and `hex_decode` return in the high half of their results
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum DecodeError {
+    InvalidEncoding = 1,
+}
+
+/// The encodings contracts can encode and decode with the codec host functions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    /// Standard base64 with padding, like `Binary` serializes to
+    Base64,
+    /// Lowercase hex. Uppercase hex is accepted when decoding.
+    Hex,
+}
+
+impl Codec {
+    pub fn encode(self, data: &[u8]) -> String {
+        match self {
+            Codec::Base64 => base64::encode(data),
+            Codec::Hex => hex::encode(data),
+        }
+    }
+
+    pub fn decode(self, encoded: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let decoded = match self {
+            Codec::Base64 => base64::decode(encoded).map_err(|err| format!("{:?}", err)),
+            Codec::Hex => hex::decode(encoded).map_err(|err| format!("{:?}", err)),
+        };
+
+        decoded.map_err(|err| {
+            debug!("failed to decode {:?}: {}", self, err);
+            DecodeError::InvalidEncoding
+        })
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_codecs() {
+        let data = b"\x00\xffsecret";
+
+        assert_eq!(Codec::Base64.encode(data), "AP9zZWNyZXQ=");
+        assert_eq!(
+            Codec::Base64.decode(b"AP9zZWNyZXQ=").unwrap(),
+            data.to_vec()
+        );
+        assert_eq!(
+            Codec::Base64.decode(b"AP9z!WNyZXQ="),
+            Err(DecodeError::InvalidEncoding)
+        );
+
+        assert_eq!(Codec::Hex.encode(data), "00ff736563726574");
+        assert_eq!(
+            Codec::Hex.decode(b"00FF736563726574").unwrap(),
+            data.to_vec()
+        );
+        assert_eq!(Codec::Hex.decode(b"00f"), Err(DecodeError::InvalidEncoding));
+        assert_eq!(Codec::Hex.decode(b"zz"), Err(DecodeError::InvalidEncoding));
+
+        assert_eq!(Codec::Base64.decode(b"").unwrap(), Vec::<u8>::new());
+        assert_eq!(Codec::Hex.decode(b"").unwrap(), Vec::<u8>::new());
+    }
+}
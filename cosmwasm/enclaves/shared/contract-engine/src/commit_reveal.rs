@@ -0,0 +1,178 @@
+//! Commit-reveal randomness that spans blocks.
+//!
+//! The randomness in the env of an execution is known to whoever sends the transaction as soon
+//! as it's executed, so a contract that draws a lottery in the same transaction that sells the
+//! tickets can be gamed by simulating it. Instead, a contract commits with `random_commit` in one
+//! block, e.g. to the hash of the tickets it sold, and reveals with `random_reveal` in a later
+//! block.
+//!
+//! The randomness is fixed when the first commitment is made: it's derived from the seed of the
+//! block and the contract key, like `derive_random` derives the randomness of executions, and
+//! stored under a reserved key per height. The state is encrypted, so it's only known once a
+//! later block reveals it, and every reveal of the height returns the same randomness. All the
+//! commitments of the contract in a block share it.
+//!
+//! The commitments are stored next to the randomness, and a reveal must name the commitment it
+//! reveals, so a contract can't reveal randomness for something it didn't commit to, e.g. for
+//! tickets that were sold after the commitment.
+
+use enclave_crypto::{hkdf_sha_256, HASH_SIZE};
+
+use crate::contract_validation::ContractKey;
+use crate::cosmwasm_config::reserved_keys::RANDOM_COMMITMENTS_PREFIX;
+
+/// Separates the committed randomness from the randomness of executions, which is derived from
+/// the same seed
+const COMMIT_DOMAIN: &[u8] = b"secret_random_commit";
+
+pub const COMMITMENT_SIZE: usize = HASH_SIZE;
+
+/// The errors `random_commit` and `random_reveal` return in the high half of their result
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum CommitRevealError {
+    /// The commitment isn't 32 bytes, e.g. a sha256 digest
+    InvalidCommitment = 1,
+    /// The execution has no randomness, because the contract didn't declare the `random` feature
+    NoRandomness = 2,
+    /// There's no commitment at the height
+    NotCommitted = 3,
+    /// Commitments can only be revealed in a block after the one they were made in
+    TooEarly = 4,
+    /// The commitment wasn't made at the height
+    CommitmentMismatch = 5,
+}
+
+/// The reserved key of the randomness committed at the height
+pub fn commitment_key(height: u64) -> Vec<u8> {
+    let mut key = RANDOM_COMMITMENTS_PREFIX.to_vec();
+    key.extend_from_slice(&height.to_be_bytes());
+    key
+}
+
+/// Derives the randomness that the commitments of a contract at `height` reveal, from the seed of
+/// the block. Unlike `derive_random`, it doesn't depend on the position of the execution in the
+/// block, so all the commitments of the contract in the block reveal the same randomness.
+pub fn derive_committed_random(
+    block_seed: &[u8],
+    contract_key: &ContractKey,
+    height: u64,
+) -> [u8; HASH_SIZE] {
+    let height_bytes = height.to_be_bytes();
+    let data = vec![
+        COMMIT_DOMAIN,
+        height_bytes.as_slice(),
+        contract_key.as_slice(),
+    ];
+
+    let mut committed = [0u8; HASH_SIZE];
+    committed.copy_from_slice(hkdf_sha_256(block_seed, data.as_slice()).get());
+    committed
+}
+
+/// Adds the commitment to the record of the commitments at a height, which holds the randomness
+/// they reveal followed by the commitments. The first commitment creates the record with the
+/// randomness.
+pub fn record_commitment(
+    record: Option<Vec<u8>>,
+    commitment: &[u8],
+    committed_random: impl FnOnce() -> [u8; HASH_SIZE],
+) -> Vec<u8> {
+    let mut record = record.unwrap_or_else(|| committed_random().to_vec());
+    if !record[HASH_SIZE..]
+        .chunks(COMMITMENT_SIZE)
+        .any(|committed| committed == commitment)
+    {
+        record.extend_from_slice(commitment);
+    }
+    record
+}
+
+/// The randomness that the record reveals for the commitment, if the commitment is in it
+pub fn reveal_commitment<'r>(
+    record: &'r [u8],
+    commitment: &[u8],
+) -> Result<&'r [u8], CommitRevealError> {
+    if record.len() < HASH_SIZE {
+        return Err(CommitRevealError::NotCommitted);
+    }
+    let (random, commitments) = record.split_at(HASH_SIZE);
+    if !commitments
+        .chunks(COMMITMENT_SIZE)
+        .any(|committed| committed == commitment)
+    {
+        return Err(CommitRevealError::CommitmentMismatch);
+    }
+
+    Ok(random)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::contract_validation::CONTRACT_KEY_LENGTH;
+
+    pub fn test_commitment_key() {
+        assert_ne!(commitment_key(1), commitment_key(2));
+        assert!(commitment_key(1).starts_with(RANDOM_COMMITMENTS_PREFIX));
+    }
+
+    pub fn test_derive_committed_random() {
+        let contract_key = [1u8; CONTRACT_KEY_LENGTH];
+        let committed = derive_committed_random(b"seed", &contract_key, 10);
+
+        assert_eq!(
+            derive_committed_random(b"seed", &contract_key, 10),
+            committed
+        );
+        assert_ne!(
+            derive_committed_random(b"other", &contract_key, 10),
+            committed
+        );
+        assert_ne!(
+            derive_committed_random(b"seed", &contract_key, 11),
+            committed
+        );
+        assert_ne!(
+            derive_committed_random(b"seed", &[2u8; CONTRACT_KEY_LENGTH], 10),
+            committed
+        );
+    }
+
+    pub fn test_reveal_commitment() {
+        let random = [7u8; HASH_SIZE];
+        let tickets = [1u8; COMMITMENT_SIZE];
+        let more_tickets = [2u8; COMMITMENT_SIZE];
+
+        let record = record_commitment(None, &tickets, || random);
+        assert_eq!(reveal_commitment(&record, &tickets), Ok(&random[..]));
+
+        // A reveal with a different commitment
+        assert_eq!(
+            reveal_commitment(&record, &more_tickets),
+            Err(CommitRevealError::CommitmentMismatch)
+        );
+
+        // Later commitments of the block share the randomness, and committing twice stores the
+        // commitment once
+        let record = record_commitment(Some(record), &more_tickets, || unreachable!());
+        let record = record_commitment(Some(record), &tickets, || unreachable!());
+        assert_eq!(record.len(), HASH_SIZE + 2 * COMMITMENT_SIZE);
+        assert_eq!(reveal_commitment(&record, &tickets), Ok(&random[..]));
+        assert_eq!(reveal_commitment(&record, &more_tickets), Ok(&random[..]));
+
+        // A commitment that spans two committed ones
+        let mut spanning = tickets[16..].to_vec();
+        spanning.extend_from_slice(&more_tickets[..16]);
+        assert_eq!(
+            reveal_commitment(&record, &spanning),
+            Err(CommitRevealError::CommitmentMismatch)
+        );
+
+        assert_eq!(
+            reveal_commitment(&random[..16], &tickets),
+            Err(CommitRevealError::NotCommitted)
+        );
+    }
+}
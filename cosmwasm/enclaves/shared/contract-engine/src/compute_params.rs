@@ -0,0 +1,271 @@
+//! The parameters of the compute module that configure the enclave, as set by governance.
+//!
+//! The host reads the parameters from the params store of the compute module, and passes them to
+//! every execution in `env.compute_params`. Every node executes a block with the parameters of its
+//! state, so a change takes effect at the height of the proposal that changed it. A parameter
+//! that isn't set, or whose feature isn't active at the height of the block, keeps its default.
+//! The host validates the params when governance changes them, so params that the enclave
+//! refuses fail every execution the same way on every node.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_generic::BaseEnv;
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_ffi_types::EnclaveError;
+
+use crate::admin_registry::{AdminRegistry, SignedAdminRegistry};
+use crate::gas::WasmCosts;
+use crate::io::{OutputPaddingParams, PlaintextAttributeDenylist};
+use crate::memory_limits::{MemoryLimits, DEFAULT_MAX_MEMORY_PAGES};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ComputeParams {
+    /// The gas cost table. Costs missing from it keep their defaults.
+    #[serde(default)]
+    pub gas_costs: Option<WasmCosts>,
+    /// The limits of the memory of contract instances
+    #[serde(default)]
+    pub memory_limits: Option<MemoryLimits>,
+    /// The buckets encrypted outputs are padded to
+    #[serde(default)]
+    pub output_padding: Option<OutputPaddingParams>,
+    /// The next sequence of each transfer channel at the upgrade that bound the ibc-hooks
+    /// callbacks of outgoing transfers to the transfers sent by the contract, see
+    /// `outgoing_transfers`. It's recorded by the upgrade handler.
+    #[serde(default)]
+    pub callback_binding_sequences: Option<BTreeMap<String, u64>>,
+    /// The admins and system contracts that were added after the hardcoded lists were frozen,
+    /// see `admin_registry`
+    #[serde(default)]
+    pub admin_registry: Option<SignedAdminRegistry>,
+    /// The attribute keys contracts may not emit in plaintext, replacing the default list
+    #[serde(default)]
+    pub plaintext_attribute_denylist: Option<PlaintextAttributeDenylist>,
+}
+
+impl ComputeParams {
+    pub fn from_env(base_env: &BaseEnv) -> Result<Self, EnclaveError> {
+        let params: Self = match &base_env.0.compute_params {
+            Some(params) => serde_json::from_value(params.clone()).map_err(|err| {
+                warn!("failed to parse the compute params: {:?}", err);
+                EnclaveError::FailedToDeserialize
+            })?,
+            None => Self::default(),
+        };
+
+        if let Some(memory_limits) = &params.memory_limits {
+            memory_limits.validate()?;
+        }
+        if let Some(output_padding) = &params.output_padding {
+            output_padding.validate()?;
+        }
+        if let Some(denylist) = &params.plaintext_attribute_denylist {
+            denylist.validate()?;
+        }
+        // Verifying the registry also makes the module cache keep its system contracts
+        params.admin_registry();
+        Ok(params)
+    }
+
+    /// The costs that contracts are charged with
+    pub fn wasm_costs(&self) -> WasmCosts {
+        match &self.gas_costs {
+            Some(costs) if is_active(Feature::GovernanceGasTable) => costs.clone(),
+            _ => WasmCosts::default(),
+        }
+    }
+
+    /// The number of pages instances of the code can grow their memory to
+    pub fn max_memory_pages(&self, code_hash: &[u8]) -> u32 {
+        match &self.memory_limits {
+            Some(limits) if is_active(Feature::GovernanceMemoryLimits) => {
+                limits.pages_for(code_hash)
+            }
+            _ => DEFAULT_MAX_MEMORY_PAGES,
+        }
+    }
+
+    /// Whether the callback of an outgoing transfer was sent before the upgrade that bound
+    /// callbacks to transfers, so there's no binding to check. Channels opened after the upgrade
+    /// always have bindings.
+    pub fn is_unbound_callback(&self, source_channel: &str, sequence: u64) -> bool {
+        self.callback_binding_sequences
+            .as_ref()
+            .and_then(|sequences| sequences.get(source_channel))
+            .map_or(false, |first_bound| sequence < *first_bound)
+    }
+
+    /// The admin registry, if it's signed by a registry signer. A registry that isn't is ignored
+    /// the same way on every node, so governance can't grant admins without a signer.
+    pub fn admin_registry(&self) -> Option<Arc<AdminRegistry>> {
+        match self.admin_registry.as_ref()?.verify() {
+            Ok(registry) => Some(registry),
+            Err(err) => {
+                warn!(
+                    "ignoring the admin registry in the compute params: {:?}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// The buckets that contracts pick their padding from
+    pub fn output_padding(&self) -> OutputPaddingParams {
+        match &self.output_padding {
+            Some(buckets) if is_active(Feature::GovernanceOutputPadding) => buckets.clone(),
+            _ => OutputPaddingParams::default(),
+        }
+    }
+
+    /// The attribute keys that contracts may not emit in plaintext
+    pub fn plaintext_attribute_denylist(&self) -> PlaintextAttributeDenylist {
+        self.plaintext_attribute_denylist
+            .clone()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use enclave_cosmos_types::feature_activation::set_active_block;
+
+    fn env_with_params(params: &str) -> BaseEnv {
+        let env = format!(
+            r#"{{
+                "block": {{"height": 1, "time": 1, "chain_id": "secretdev-1"}},
+                "message": {{"sender": "sender", "sent_funds": []}},
+                "contract": {{"address": "contract"}},
+                "contract_key": null
+                {}
+            }}"#,
+            params
+        );
+        serde_json::from_str(&env).unwrap()
+    }
+
+    pub fn test_compute_params() {
+        set_active_block("secretdev-1", 1);
+        let params = ComputeParams::from_env(&env_with_params("")).unwrap();
+        assert!(params.gas_costs.is_none());
+        assert_eq!(params.wasm_costs().regular, WasmCosts::default().regular);
+
+        let params = ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"gas_costs": {"regular": 2, "external_humanize_address": 10000}}"#,
+        ))
+        .unwrap();
+        let costs = params.wasm_costs();
+        assert_eq!(costs.regular, 2);
+        assert_eq!(costs.external_humanize_address, 10000);
+        assert_eq!(costs.div, WasmCosts::default().div);
+
+        assert!(ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"gas_costs": {"regular": "cheap"}}"#
+        ))
+        .is_err());
+    }
+
+    pub fn test_compute_params_memory_limits() {
+        set_active_block("secretdev-1", 1);
+        let code_hash = [7u8; 32];
+        let params = ComputeParams::from_env(&env_with_params("")).unwrap();
+        assert_eq!(
+            params.max_memory_pages(&code_hash),
+            DEFAULT_MAX_MEMORY_PAGES
+        );
+
+        let params = ComputeParams::from_env(&env_with_params(&format!(
+            r#", "compute_params": {{"memory_limits": {{"max_memory_pages": 256, "code_overrides": {{"{}": 480}}}}}}"#,
+            hex::encode(code_hash)
+        )))
+        .unwrap();
+        assert_eq!(params.max_memory_pages(&code_hash), 480);
+        assert_eq!(params.max_memory_pages(&[8u8; 32]), 256);
+
+        // Limits above the ceiling fail every execution instead of being ignored
+        assert!(ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"memory_limits": {"max_memory_pages": 513}}"#
+        ))
+        .is_err());
+    }
+
+    pub fn test_compute_params_output_padding() {
+        set_active_block("secretdev-1", 1);
+        let params = ComputeParams::from_env(&env_with_params("")).unwrap();
+        assert_eq!(params.output_padding(), OutputPaddingParams::default());
+
+        let params = ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"output_padding": {"bucket_sizes": [512, 2048], "large_output_multiple": 2048}}"#,
+        ))
+        .unwrap();
+        let buckets = params.output_padding();
+        assert_eq!(buckets.bucket_sizes, vec![512, 2048]);
+        assert_eq!(buckets.large_output_multiple, 2048);
+        assert_eq!(
+            buckets.coarse_bucket_sizes,
+            OutputPaddingParams::default().coarse_bucket_sizes
+        );
+
+        assert!(ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"output_padding": {"bucket_sizes": [2048, 512]}}"#
+        ))
+        .is_err());
+    }
+
+    pub fn test_compute_params_callback_binding_sequences() {
+        let params = ComputeParams::from_env(&env_with_params("")).unwrap();
+        assert!(!params.is_unbound_callback("channel-0", 1));
+
+        let params = ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"callback_binding_sequences": {"channel-0": 100}}"#,
+        ))
+        .unwrap();
+        assert!(params.is_unbound_callback("channel-0", 99));
+        assert!(!params.is_unbound_callback("channel-0", 100));
+        // Channels opened after the upgrade are always bound
+        assert!(!params.is_unbound_callback("channel-1", 1));
+    }
+
+    pub fn test_compute_params_admin_registry() {
+        let params = ComputeParams::from_env(&env_with_params("")).unwrap();
+        assert!(params.admin_registry().is_none());
+
+        // A registry that none of the registry signers signed grants nothing
+        let params = ComputeParams::from_env(&env_with_params(&format!(
+            r#", "compute_params": {{"admin_registry": {{"version": 1, "root": "{}", "signature": "{}", "entries": []}}}}"#,
+            base64::encode(&[1u8; 32][..]),
+            base64::encode(&[2u8; 64][..])
+        )))
+        .unwrap();
+        assert!(params.admin_registry.is_some());
+        assert!(params.admin_registry().is_none());
+    }
+
+    pub fn test_compute_params_plaintext_attribute_denylist() {
+        let params = ComputeParams::from_env(&env_with_params("")).unwrap();
+        let denylist = params.plaintext_attribute_denylist();
+        assert_eq!(denylist, PlaintextAttributeDenylist::default());
+        assert!(denylist.is_denied("amount"));
+
+        let params = ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"plaintext_attribute_denylist": {"denied_keys": ["balance", "Owner"]}}"#,
+        ))
+        .unwrap();
+        let denylist = params.plaintext_attribute_denylist();
+        assert!(denylist.is_denied("balance"));
+        assert!(denylist.is_denied("owner"));
+        // The list replaces the default one
+        assert!(!denylist.is_denied("amount"));
+
+        assert!(ComputeParams::from_env(&env_with_params(
+            r#", "compute_params": {"plaintext_attribute_denylist": {"denied_keys": [""]}}"#
+        ))
+        .is_err());
+    }
+}
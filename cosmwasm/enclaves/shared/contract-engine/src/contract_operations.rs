@@ -1,50 +1,102 @@
+use std::convert::TryInto;
+use std::time::Instant;
+use std::untrusted::time::InstantEx;
+
 use serde::{Deserialize, Serialize};
 
-#[cfg(feature = "random")]
-use cw_types_generic::{ContractFeature, CwEnv};
+#[cfg(any(feature = "random", feature = "light-client-validation"))]
+use cw_types_generic::CwEnv;
 
-use cw_types_generic::{BaseAddr, BaseEnv};
+use cw_types_generic::{BaseAddr, BaseEnv, ContractFeature, ShapedEntrypoint};
 
 use cw_types_v010::encoding::Binary;
 use cw_types_v010::types::CanonicalAddr;
+use cw_types_v1::types::{Addr, MigrateInfo};
 
+use enclave_cosmos_types::feature_activation::{is_active, set_active_block, Feature};
 use enclave_cosmos_types::types::{ContractCode, HandleType, SigInfo, VerifyParamsType};
-use enclave_crypto::Ed25519PublicKey;
+use enclave_crypto::{Ed25519PublicKey, HASH_SIZE};
 use enclave_ffi_types::{Ctx, EnclaveError};
 use log::*;
 
-use crate::cosmwasm_config::ContractOperation;
+use crate::atomic_calls::append_atomic_calls;
+use crate::auditor::{read_auditor_key, store_auditor_key};
+use crate::capability_summary::take_capability_summary_request;
+use crate::cosmwasm_config::{reserved_keys, ContractOperation};
+use crate::decryption_limit::decrypt_query_msg;
+use crate::enclave_manifest::missing_capabilities;
+use crate::event_schema::{take_schema_registration, validate_output_events, EventSchema};
+use crate::execution_receipts::execution_receipt;
+use crate::execution_trace::{begin_trace, trace_span};
 
 #[cfg(feature = "light-client-validation")]
-use crate::contract_validation::verify_block_info;
+use crate::contract_validation::{
+    check_block_gas, is_delivered_tx, use_block_gas, verified_block_header, verify_block_info,
+};
+#[cfg(feature = "light-client-validation")]
+use crate::ibc_callback_guard::{check_callback_not_processed, record_processed_callback};
 
 use crate::contract_validation::{
-    generate_admin_proof, generate_contract_key_proof, ReplyParams, ValidatedMessage,
+    generate_admin_proof, generate_contract_key_proof, get_outgoing_transfer_packet,
+    validate_old_code_hash, verify_historical_block_info, verify_ibc_packet_timeout_elapsed,
+    ContractKey, ReplyParams, ValidatedMessage,
 };
+use crate::custom_query_auth::CustomQueryAuth;
 use crate::external::results::{
     HandleSuccess, InitSuccess, MigrateSuccess, QuerySuccess, UpdateAdminSuccess,
 };
-use crate::message::{is_ibc_msg, parse_message};
+use crate::group_authority::GroupAuthority;
+use crate::handle_routes::{handle_route, HandleRoute, SenderPolicy};
+use crate::historical_state::{enter_historical_state, is_historical, HistoricalState};
+use crate::idempotency_keys::{
+    already_executed_output, check_idempotency_key, idempotency_key_hash, record_idempotency_key,
+    take_idempotency_key,
+};
+use crate::message::parse_message;
+use crate::metrics::record_engine_start;
+use crate::migration_checkpoint::{
+    check_migration_checkpoint, check_no_pending_migration, store_migration_checkpoint,
+    MigrationResume,
+};
+use crate::outgoing_transfers::{consume_outgoing_transfer, record_outgoing_transfers};
+use crate::panic_isolation::isolate_execution;
+use crate::pending_replies::{record_pending_replies, take_pending_siblings, TxPosition};
+use crate::pinned_code::resolve_contract_code;
+use crate::previous_contract_key::record_previous_contract_key;
+use crate::query_cache::{split_query_cache_scope, QueryCacheScope, QueryOrigin};
+use crate::query_result_cache::{
+    cache_query_result, get_cached_query_result, is_cacheable_query, query_result_key,
+    CachedQueryResult,
+};
+use crate::transient_storage::{
+    commit_transient_writes, revert_transient_writes, TransientInfo, TransientScope,
+};
 use crate::types::ParsedMessage;
+use crate::upgrade_policy::{
+    check_self_migration, check_upgrade_policy, clear_migration_announcement,
+    store_migration_announcement, store_upgrade_policy,
+};
 
 use crate::random::update_msg_counter;
 
 #[cfg(feature = "random")]
 use crate::random::derive_random;
-#[cfg(feature = "random")]
 use crate::wasm3::Engine;
 
 use crate::hardcoded_admins::is_hardcoded_contract_admin;
 
+use super::compute_params::ComputeParams;
 use super::contract_validation::{
-    generate_contract_key, validate_contract_key, validate_msg, verify_params, ContractKey,
+    generate_contract_key, validate_contract_key, validate_msg, verify_params, VerifiedParams,
 };
+use super::db::StateKeys;
 use super::gas::WasmCosts;
 use super::io::{
-    finalize_raw_output, manipulate_callback_sig_for_plaintext, post_process_output,
-    set_all_logs_to_plaintext,
+    finalize_raw_output, format_generic_error_message, manipulate_callback_sig_for_plaintext,
+    output_normalization_version, post_process_output, set_all_logs_to_plaintext,
+    OutputPaddingParams, PaddingBuckets,
 };
-use super::types::{IoNonce, SecretMessage};
+use super::types::{EnvelopeVersion, IoNonce, SecretMessage};
 
 /*
 Each contract is compiled with these functions already implemented in wasm:
@@ -73,31 +125,34 @@ pub fn init(
     admin: &[u8],       // admin's canonical address or null if no admin
 ) -> Result<InitSuccess, EnclaveError> {
     trace!("Starting init");
+    let _trace = begin_trace(&context, "init");
 
-    //let start = Instant::now();
-    let contract_code = ContractCode::new(contract);
+    // Before any gas is used, so that the host can retry with the whole code if it isn't pinned
+    let contract = resolve_contract_code(contract)?;
+
+    let contract_code = contract.contract_code();
     let contract_hash = contract_code.hash();
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in ContractCode::new is: {:?}", duration);
     debug!(
         "******************** init RUNNING WITH CODE: {:x?}",
         contract_hash
     );
 
-    //let start = Instant::now();
     let base_env: BaseEnv = extract_base_env(env)?;
+    let compute_params = ComputeParams::from_env(&base_env)?;
+    let parsed_sig_info: SigInfo = extract_sig_info(sig_info)?;
+    let input_gas = use_input_gas(
+        used_gas,
+        gas_limit,
+        &charged_inputs(env, msg, sig_info, &parsed_sig_info),
+        &compute_params,
+    )?;
 
     #[cfg(feature = "light-client-validation")]
     verify_block_info(&base_env)?;
 
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in extract_base_env is: {:?}", duration);
     let query_depth = extract_query_depth(env)?;
 
-    //let start = Instant::now();
     let (sender, contract_address, block_height, sent_funds) = base_env.get_verification_params();
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in get_verification_paramsis: {:?}", duration);
 
     let canonical_contract_address = to_canonical(contract_address)?;
     let canonical_sender_address = to_canonical(sender)?;
@@ -114,34 +169,32 @@ pub fn init(
         None,
     )?;
 
-    let parsed_sig_info: SigInfo = extract_sig_info(sig_info)?;
-
     let secret_msg = SecretMessage::from_slice(msg)?;
 
-    //let start = Instant::now();
-    verify_params(
+    let VerifiedParams { tx_info, funds } = verify_params(
         &parsed_sig_info,
         sent_funds,
         &canonical_sender_address,
         contract_address,
         &secret_msg,
         true,
-        true,
+        block_height,
         VerifyParamsType::Init,
         Some(&canonical_admin_address),
         None,
+        &GroupAuthority::from_env(env, block_height),
     )?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in verify_params: {:?}", duration);
 
-    //let start = Instant::now();
+    // After the transaction was found in the verified block, which the deliver flag is derived from
+    let deliver_tx = is_deliver_tx(env, &base_env, &parsed_sig_info)?;
+
+    #[cfg(feature = "light-client-validation")]
+    check_block_gas(block_height, deliver_tx)?;
+
     let decrypted_msg = secret_msg.decrypt()?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in decrypt: {:?}", duration);
 
-    //let start = Instant::now();
     let ValidatedMessage {
-        validated_msg,
+        mut validated_msg,
         reply_params,
     } = validate_msg(
         &canonical_contract_address,
@@ -150,29 +203,78 @@ pub fn init(
         None,
         None,
     )?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in validate_msg: {:?}", duration);
+    let summary_requested = take_capability_summary_request(&mut validated_msg);
+
+    if let Some(error_output) = check_required_capabilities(&contract_code)? {
+        *used_gas = input_gas;
 
-    //let start = Instant::now();
+        let mut padded_bytes: u64 = 0;
+        let output = post_process_output(
+            error_output,
+            &secret_msg,
+            &canonical_contract_address,
+            &hex::encode(contract_hash),
+            reply_params,
+            &canonical_sender_address,
+            false,
+            false,
+            None,
+            None,
+            None,
+            output_normalization_version(&[]),
+            &PaddingBuckets::for_features(&[], &compute_params.output_padding()),
+            &mut padded_bytes,
+        )?;
+        use_padding_gas(used_gas, gas_limit, padded_bytes, &compute_params)?;
+
+        let admin_proof = generate_admin_proof(&canonical_admin_address.0 .0, &og_contract_key);
+        return Ok(InitSuccess {
+            output,
+            contract_key: og_contract_key,
+            admin_proof,
+            write_intent: vec![],
+        });
+    }
+
+    let state_keys = StateKeys::at_height(&context, &og_contract_key, base_env.0.block.height)?;
     let mut engine = start_engine(
         context,
-        gas_limit,
+        gas_limit - input_gas,
         &contract_code,
-        &og_contract_key,
+        state_keys,
         ContractOperation::Init,
         query_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
+        QueryCacheScope::new(base_env.0.block.height)?,
+        compute_params.wasm_costs(),
+        compute_params.max_memory_pages(&contract_code.hash()),
     )?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in start_engine: {:?}", duration);
 
     let mut versioned_env = base_env
         .clone()
         .into_versioned_env(&engine.get_api_version());
 
     versioned_env.set_contract_hash(&contract_hash);
+    engine.set_verified_fee(tx_info.as_ref().and_then(|tx_info| tx_info.fee.clone()));
+    engine.set_oracle_proofs(
+        tx_info
+            .as_ref()
+            .map_or_else(Vec::new, |tx_info| tx_info.oracle_proofs.clone()),
+    );
+    engine.set_custom_query_auth(CustomQueryAuth::from_env(env, base_env.0.block.height));
+    engine.set_tx_hash(base_env.0.transaction.as_ref().map(|tx| tx.hash.clone()));
+    engine.set_deliver_tx(deliver_tx);
+    engine.set_transient_scope(TransientScope::from_env(
+        &base_env,
+        &extract_transient_info(env)?,
+    ));
+    versioned_env.set_verified_tx_info(tx_info.filter(|_| is_active(Feature::TxInfoInEnv)));
+    versioned_env.set_verified_funds(funds);
+
+    #[cfg(feature = "light-client-validation")]
+    set_block_header_in_env(block_height, &mut versioned_env)?;
 
     #[cfg(feature = "random")]
     set_random_in_env(
@@ -183,29 +285,42 @@ pub fn init(
     );
 
     update_msg_counter(block_height);
-    //let start = Instant::now();
-    let result = engine.init(&versioned_env, validated_msg);
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in engine.init: {:?}", duration);
+    let result = isolate_execution(|| engine.init(&versioned_env, validated_msg));
 
-    *used_gas = engine.gas_used();
+    *used_gas = input_gas + engine.gas_used();
+
+    #[cfg(feature = "light-client-validation")]
+    use_block_gas(block_height, deliver_tx, *used_gas)?;
 
     let output = result?;
+    let output = register_event_schema(&mut engine, output)?;
+    let output = append_atomic_calls(output, engine.take_atomic_calls())?;
+    record_outgoing_transfers(&mut engine, &output, contract_address)?;
+    record_pending_replies(&mut engine, TxPosition::from_env(&base_env), &output)?;
+    commit_transient_writes(&mut engine, &output);
+    let upgrade_policy = engine.take_upgrade_policy();
+    store_upgrade_policy(&mut engine, upgrade_policy, block_height)?;
+    let auditor_key = engine.take_auditor_key();
+    store_auditor_key(&mut engine, auditor_key);
+    let viewer_key = engine.take_designated_viewer();
 
     #[cfg(not(feature = "random"))]
     let random: Option<Binary> = None;
     #[cfg(feature = "random")]
     let random = versioned_env.get_random();
+    let capability_summary = engine.capability_summary(summary_requested);
 
     engine
-        .flush_cache(random)
+        .flush_cache(random, contract_address, block_height)
         .map_err(|_| EnclaveError::FailedFunctionCall)?;
+    // The storage the execution grew by is surcharged once its writes are flushed
+    *used_gas += engine.account_storage()?;
 
     // TODO: copy cosmwasm's structures to enclave
     // TODO: ref: https://github.com/CosmWasm/cosmwasm/blob/b971c037a773bf6a5f5d08a88485113d9b9e8e7b/packages/std/src/init_handle.rs#L129
     // TODO: ref: https://github.com/CosmWasm/cosmwasm/blob/b971c037a773bf6a5f5d08a88485113d9b9e8e7b/packages/std/src/query.rs#L13
-    //let start = Instant::now();
 
+    let mut padded_bytes: u64 = 0;
     let output = post_process_output(
         output,
         &secret_msg,
@@ -215,10 +330,17 @@ pub fn init(
         &canonical_sender_address,
         false,
         false,
+        auditor_key.as_ref(),
+        viewer_key.as_ref(),
+        capability_summary,
+        output_normalization_version(engine.supported_features()),
+        &PaddingBuckets::for_features(
+            engine.supported_features(),
+            &compute_params.output_padding(),
+        ),
+        &mut padded_bytes,
     )?;
-
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in encrypt_output: {:?}", duration);
+    use_padding_gas(used_gas, gas_limit, padded_bytes, &compute_params)?;
 
     // todo: can move the key to somewhere in the output message if we want
 
@@ -228,6 +350,7 @@ pub fn init(
         output,
         contract_key: og_contract_key,
         admin_proof,
+        write_intent: engine.take_write_intent(),
     })
 }
 
@@ -254,7 +377,7 @@ fn to_canonical(contract_address: &BaseAddr) -> Result<CanonicalAddr, EnclaveErr
             "error while trying to deserialize address from bech32 string to bytes {:?}: {}",
             contract_address, err
         );
-        EnclaveError::FailedToDeserialize
+        EnclaveError::from(err)
     })
 }
 
@@ -271,31 +394,25 @@ pub fn migrate(
     admin_proof: &[u8],
 ) -> Result<MigrateSuccess, EnclaveError> {
     debug!("Starting migrate");
+    let _trace = begin_trace(&context, "migrate");
 
-    //let start = Instant::now();
-    let contract_code = ContractCode::new(contract);
+    let contract = resolve_contract_code(contract)?;
+    let contract_code = contract.contract_code();
     let contract_hash = contract_code.hash();
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in ContractCode::new is: {:?}", duration);
     debug!(
         "******************** migrate RUNNING WITH CODE: {:x?}",
         contract_hash
     );
 
-    //let start = Instant::now();
     let base_env: BaseEnv = extract_base_env(env)?;
+    let compute_params = ComputeParams::from_env(&base_env)?;
 
     #[cfg(feature = "light-client-validation")]
     verify_block_info(&base_env)?;
 
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in extract_base_env is: {:?}", duration);
     let query_depth = extract_query_depth(env)?;
 
-    //let start = Instant::now();
     let (sender, contract_address, block_height, sent_funds) = base_env.get_verification_params();
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in get_verification_paramsis: {:?}", duration);
 
     let canonical_contract_address = to_canonical(contract_address)?;
     let canonical_sender_address = to_canonical(sender)?;
@@ -303,10 +420,16 @@ pub fn migrate(
 
     let og_contract_key = base_env.get_og_contract_key()?;
 
-    if is_hardcoded_contract_admin(
+    // A contract that migrates itself doesn't need to be its admin, its upgrade policy decides
+    // instead. Only the contract can send a message as itself, with a callback signature.
+    let is_self_migration = canonical_sender_address == canonical_contract_address;
+    if is_self_migration {
+        debug!("Contract is migrating itself, its upgrade policy is checked instead of the admin");
+    } else if is_hardcoded_contract_admin(
         &canonical_contract_address,
         &canonical_admin_address,
         admin_proof,
+        &compute_params,
     ) {
         debug!("Found hardcoded admin for migrate");
     } else {
@@ -324,30 +447,30 @@ pub fn migrate(
 
     let secret_msg = SecretMessage::from_slice(msg)?;
 
-    //let start = Instant::now();
-    verify_params(
+    let VerifiedParams { tx_info, funds } = verify_params(
         &parsed_sig_info,
         sent_funds,
         &canonical_sender_address,
         contract_address,
         &secret_msg,
         true,
-        true,
+        block_height,
         VerifyParamsType::Migrate,
         Some(&canonical_admin_address),
         None,
+        &GroupAuthority::from_env(env, block_height),
     )?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in verify_params: {:?}", duration);
 
-    //let start = Instant::now();
+    // After the transaction was found in the verified block, which the deliver flag is derived from
+    let deliver_tx = is_deliver_tx(env, &base_env, &parsed_sig_info)?;
+
+    #[cfg(feature = "light-client-validation")]
+    check_block_gas(block_height, deliver_tx)?;
+
     let decrypted_msg = secret_msg.decrypt()?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in decrypt: {:?}", duration);
 
-    //let start = Instant::now();
     let ValidatedMessage {
-        validated_msg,
+        mut validated_msg,
         reply_params,
     } = validate_msg(
         &canonical_contract_address,
@@ -356,27 +479,101 @@ pub fn migrate(
         None,
         None,
     )?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in validate_msg: {:?}", duration);
+    let summary_requested = take_capability_summary_request(&mut validated_msg);
+
+    if let Some(error_output) = check_required_capabilities(&contract_code)? {
+        *used_gas = 0;
+
+        return failed_migration(
+            error_output,
+            &secret_msg,
+            &canonical_contract_address,
+            &contract_hash,
+            reply_params,
+            &canonical_sender_address,
+            og_contract_key,
+            used_gas,
+            gas_limit,
+            &compute_params,
+        );
+    }
 
-    //let start = Instant::now();
+    let state_keys = StateKeys::at_height(&context, &og_contract_key, base_env.0.block.height)?;
     let mut engine = start_engine(
         context,
         gas_limit,
         &contract_code,
-        &og_contract_key,
+        state_keys,
         ContractOperation::Migrate,
         query_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
+        QueryCacheScope::new(base_env.0.block.height)?,
+        compute_params.wasm_costs(),
+        compute_params.max_memory_pages(&contract_code.hash()),
     )?;
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in start_engine: {:?}", duration);
 
-    let mut versioned_env = base_env.into_versioned_env(&engine.get_api_version());
+    // A migration that resumes a checkpointed one was already allowed by the upgrade policy
+    let mut policy_violation = None;
+    let mut resumed = false;
+    match check_migration_checkpoint(&mut engine, &contract_code)? {
+        MigrationResume::Resume(cursor) => {
+            debug!("migration resumes a checkpointed migration");
+            engine.set_migration_cursor(cursor);
+            resumed = true;
+        }
+        MigrationResume::Refused(reason) => policy_violation = Some(reason),
+        MigrationResume::None => {
+            if is_self_migration {
+                policy_violation = check_self_migration(&mut engine, &contract_code)?;
+            }
+            if policy_violation.is_none() {
+                policy_violation = check_upgrade_policy(&mut engine, &contract_code, block_height)?;
+            }
+        }
+    }
+    if let Some(reason) = policy_violation {
+        warn!("migration is not allowed by the upgrade policy: {}", reason);
+        *used_gas = engine.gas_used();
+
+        return failed_migration(
+            generic_error_output(format!("migration is not allowed: {}", reason))?,
+            &secret_msg,
+            &canonical_contract_address,
+            &contract_hash,
+            reply_params,
+            &canonical_sender_address,
+            og_contract_key,
+            used_gas,
+            gas_limit,
+            &compute_params,
+        );
+    }
+
+    let mut versioned_env = base_env
+        .clone()
+        .into_versioned_env(&engine.get_api_version());
 
     versioned_env.set_contract_hash(&contract_hash);
+    engine.set_verified_fee(tx_info.as_ref().and_then(|tx_info| tx_info.fee.clone()));
+    engine.set_oracle_proofs(
+        tx_info
+            .as_ref()
+            .map_or_else(Vec::new, |tx_info| tx_info.oracle_proofs.clone()),
+    );
+    engine.set_custom_query_auth(CustomQueryAuth::from_env(env, base_env.0.block.height));
+    engine.set_tx_hash(base_env.0.transaction.as_ref().map(|tx| tx.hash.clone()));
+    engine.set_deliver_tx(deliver_tx);
+    engine.set_transient_scope(TransientScope::from_env(
+        &base_env,
+        &extract_transient_info(env)?,
+    ));
+    versioned_env.set_verified_tx_info(tx_info.filter(|_| is_active(Feature::TxInfoInEnv)));
+    versioned_env.set_verified_funds(funds);
+
+    #[cfg(feature = "light-client-validation")]
+    set_block_header_in_env(block_height, &mut versioned_env)?;
 
     let new_contract_key = generate_contract_key(
         &canonical_sender_address,
@@ -395,18 +592,72 @@ pub fn migrate(
     );
 
     update_msg_counter(block_height);
-    let result = engine.migrate(&versioned_env, validated_msg);
+    let supports_migrate_info = engine
+        .supported_features()
+        .contains(&ContractFeature::MigrateInfo);
+    // Contracts that don't read the old code hash can still be migrated without it, but then the
+    // key the migration replaces isn't accepted during the grace window
+    let old_code_hash = match verified_old_code_hash(env, &base_env, &canonical_contract_address) {
+        Ok(old_code_hash) => Some(old_code_hash),
+        Err(err) if supports_migrate_info => return Err(err),
+        Err(_) => None,
+    };
+    let migrate_info = match old_code_hash {
+        Some(old_code_hash) if supports_migrate_info => build_migrate_info(&old_code_hash, sender)?,
+        _ => vec![],
+    };
+
+    let result = isolate_execution(|| engine.migrate(&versioned_env, validated_msg, &migrate_info));
 
     *used_gas = engine.gas_used();
 
+    #[cfg(feature = "light-client-validation")]
+    use_block_gas(block_height, deliver_tx, *used_gas)?;
+
     let output = result?;
+    let output = register_event_schema(&mut engine, output)?;
+    let output = append_atomic_calls(output, engine.take_atomic_calls())?;
+    record_outgoing_transfers(&mut engine, &output, contract_address)?;
+    record_pending_replies(&mut engine, TxPosition::from_env(&base_env), &output)?;
+    commit_transient_writes(&mut engine, &output);
+    let upgrade_policy = engine.take_upgrade_policy();
+    store_upgrade_policy(&mut engine, upgrade_policy, block_height)?;
+    clear_migration_announcement(&mut engine)?;
+    if let Some(old_code_hash) = old_code_hash {
+        // The key the migration replaces, which was verified with the old code hash
+        let previous_contract_key = if base_env.was_migrated() {
+            base_env.get_current_contract_key()?
+        } else {
+            og_contract_key
+        };
+        record_previous_contract_key(
+            &mut engine,
+            &previous_contract_key,
+            &old_code_hash,
+            block_height,
+        )?;
+    }
+    let migration_checkpoint = engine.take_migration_checkpoint();
+    store_migration_checkpoint(
+        &mut engine,
+        migration_checkpoint,
+        contract_hash,
+        block_height,
+        resumed,
+    )?;
+    let auditor_key = read_auditor_key(&mut engine)?;
+    let viewer_key = engine.take_designated_viewer();
 
     let random = versioned_env.get_random();
+    let capability_summary = engine.capability_summary(summary_requested);
 
     engine
-        .flush_cache(random)
+        .flush_cache(random, contract_address, block_height)
         .map_err(|_| EnclaveError::FailedFunctionCall)?;
+    // The storage the execution grew by is surcharged once its writes are flushed
+    *used_gas += engine.account_storage()?;
 
+    let mut padded_bytes: u64 = 0;
     let output = post_process_output(
         output,
         &secret_msg,
@@ -416,10 +667,17 @@ pub fn migrate(
         &canonical_sender_address,
         false,
         false,
+        auditor_key.as_ref(),
+        viewer_key.as_ref(),
+        capability_summary,
+        output_normalization_version(engine.supported_features()),
+        &PaddingBuckets::for_features(
+            engine.supported_features(),
+            &compute_params.output_padding(),
+        ),
+        &mut padded_bytes,
     )?;
-
-    // let duration = start.elapsed();
-    // trace!("Time elapsed in encrypt_output: {:?}", duration);
+    use_padding_gas(used_gas, gas_limit, padded_bytes, &compute_params)?;
 
     // todo: can move the key to somewhere in the output message if we want
 
@@ -439,6 +697,7 @@ pub fn migrate(
         output,
         new_contract_key,
         new_contract_key_proof,
+        write_intent: engine.take_write_intent(),
     })
 }
 
@@ -452,11 +711,12 @@ pub fn update_admin(
     debug!("Starting update_admin");
 
     let base_env: BaseEnv = extract_base_env(env)?;
+    let compute_params = ComputeParams::from_env(&base_env)?;
 
     #[cfg(feature = "light-client-validation")]
     verify_block_info(&base_env)?;
 
-    let (sender, contract_address, _block_height, sent_funds) = base_env.get_verification_params();
+    let (sender, contract_address, block_height, sent_funds) = base_env.get_verification_params();
 
     let canonical_sender_address = to_canonical(sender)?;
     let canonical_current_admin_address = CanonicalAddr::from_vec(current_admin.to_vec());
@@ -468,6 +728,7 @@ pub fn update_admin(
         &canonical_contract_address,
         &canonical_current_admin_address,
         current_admin_proof,
+        &compute_params,
     ) {
         debug!(
             "Found hardcoded admin for update_admin. Cannot update admin for hardcoded contracts."
@@ -496,12 +757,14 @@ pub fn update_admin(
             nonce: [0; 32],
             user_public_key: [0; 32],
             msg: vec![], // must be empty vec for callback_sig verification
+            version: EnvelopeVersion::Unversioned,
         },
-        true,
-        true,
+        false,
+        block_height,
         VerifyParamsType::UpdateAdmin,
         Some(&canonical_current_admin_address),
         Some(&canonical_new_admin_address),
+        &GroupAuthority::from_env(env, block_height),
     )?;
 
     let new_admin_proof = generate_admin_proof(&canonical_new_admin_address.0 .0, &og_contract_key);
@@ -523,8 +786,12 @@ pub fn handle(
     handle_type: u8,
 ) -> Result<HandleSuccess, EnclaveError> {
     trace!("Starting handle");
+    let _trace = begin_trace(&context, "handle");
+
+    // Before any gas is used, so that the host can retry with the whole code if it isn't pinned
+    let contract = resolve_contract_code(contract)?;
 
-    let contract_code = ContractCode::new(contract);
+    let contract_code = contract.contract_code();
     let contract_hash = contract_code.hash();
 
     debug!(
@@ -533,6 +800,14 @@ pub fn handle(
     );
 
     let base_env: BaseEnv = extract_base_env(env)?;
+    let compute_params = ComputeParams::from_env(&base_env)?;
+    let parsed_sig_info: SigInfo = extract_sig_info(sig_info)?;
+    let input_gas = use_input_gas(
+        used_gas,
+        gas_limit,
+        &charged_inputs(env, msg, sig_info, &parsed_sig_info),
+        &compute_params,
+    )?;
 
     #[cfg(feature = "light-client-validation")]
     verify_block_info(&base_env)?;
@@ -543,19 +818,21 @@ pub fn handle(
 
     let canonical_contract_address = to_canonical(contract_address)?;
 
-    validate_contract_key(&base_env, &canonical_contract_address, &contract_code)?;
-
-    let parsed_sig_info: SigInfo = extract_sig_info(sig_info)?;
+    validate_contract_key(
+        &context,
+        &base_env,
+        &canonical_contract_address,
+        &contract_code,
+    )?;
 
     // The flow of handle is now used for multiple messages (such ash Handle, Reply, IBC)
     // When the message is handle, we expect it always to be encrypted while in Reply & IBC it might be plaintext
     let parsed_handle_type = HandleType::try_from(handle_type)?;
+    let route = handle_route(parsed_handle_type);
 
     trace!("Handle type is {:?}", parsed_handle_type);
 
     let ParsedMessage {
-        should_verify_sig_info,
-        should_verify_input,
         was_msg_encrypted,
         should_encrypt_output,
         secret_msg,
@@ -574,19 +851,35 @@ pub fn handle(
     // - Plaintext replies (resulting from an IBC call)
     // - IBC WASM Hooks
     // - (In the future:) ICA
-    verify_params(
+    let VerifiedParams { tx_info, funds } = verify_params(
         &parsed_sig_info,
         sent_funds,
         &canonical_sender_address,
         contract_address,
         &secret_msg,
-        should_verify_sig_info,
-        should_verify_input,
+        was_msg_encrypted,
+        block_height,
         VerifyParamsType::HandleType(parsed_handle_type),
         None,
         None,
+        &GroupAuthority::from_env(env, block_height),
     )?;
 
+    // After the transaction was found in the verified block, which the deliver flag is derived from
+    let deliver_tx = is_deliver_tx(env, &base_env, &parsed_sig_info)?;
+
+    #[cfg(feature = "light-client-validation")]
+    check_block_gas(block_height, deliver_tx)?;
+
+    if route.requires_elapsed_timeout && is_active(Feature::IbcTimeoutElapsed) {
+        verify_ibc_packet_timeout_elapsed(
+            &parsed_sig_info,
+            &secret_msg,
+            parsed_handle_type,
+            base_env.0.block.time,
+        )?;
+    }
+
     let mut validated_msg = decrypted_msg.clone();
     let mut reply_params: Option<Vec<ReplyParams>> = None;
     if was_msg_encrypted {
@@ -601,23 +894,56 @@ pub fn handle(
         reply_params = x.reply_params;
     }
 
+    // Only the executions that senders encrypt themselves can request a capability summary, or
+    // carry an idempotency key
+    let mut summary_requested = false;
+    let mut idempotency_key = None;
+    if was_msg_encrypted && parsed_handle_type == HandleType::HANDLE_TYPE_EXECUTE {
+        summary_requested = take_capability_summary_request(&mut validated_msg);
+        idempotency_key = take_idempotency_key(&mut validated_msg)?
+            .map(|key| idempotency_key_hash(&canonical_sender_address, &key));
+    }
+
     let og_contract_key = base_env.get_og_contract_key()?;
 
     // Although the operation here is not always handle it is irrelevant in this case
-    // because it only helps to decide whether to check floating points or not
+    // because it only helps to decide whether unsupported declared features fail the contract
     // In this case we want to do the same as in Handle both for Reply and for others so we can always pass "Handle".
+    let state_keys = StateKeys::at_height(&context, &og_contract_key, base_env.0.block.height)?;
     let mut engine = start_engine(
         context,
-        gas_limit,
+        gas_limit - input_gas,
         &contract_code,
-        &og_contract_key,
+        state_keys,
         ContractOperation::Handle,
         query_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
+        QueryCacheScope::new(base_env.0.block.height)?,
+        compute_params.wasm_costs(),
+        compute_params.max_memory_pages(&contract_code.hash()),
     )?;
 
+    // Replies may answer the submessages of a migration that checkpointed
+    if parsed_handle_type != HandleType::HANDLE_TYPE_REPLY {
+        check_no_pending_migration(&mut engine)?;
+    }
+
+    // Callbacks are only accepted for transfers that the contract itself sent, once
+    if route.consumes_outgoing_transfer && is_active(Feature::BoundTransferCallbacks) {
+        let packet =
+            get_outgoing_transfer_packet(&parsed_sig_info, &secret_msg, parsed_handle_type)?;
+        consume_outgoing_transfer(&mut engine, &packet, contract_address, &compute_params)?;
+        #[cfg(feature = "light-client-validation")]
+        if is_active(Feature::ProcessedCallbacks) {
+            check_callback_not_processed(&mut engine, &packet)?;
+            if deliver_tx {
+                record_processed_callback(&mut engine, &packet, block_height);
+            }
+        }
+    }
+
     let mut versioned_env = base_env
         .clone()
         .into_versioned_env(&engine.get_api_version());
@@ -626,23 +952,8 @@ pub fn handle(
     // even though the sender of an IBC packet cannot be verified.
     // But we don't want malicious actors using this enclave setting to fake any sender they want.
     // Therefore we'll use a null sender if it cannot be verified.
-    match parsed_handle_type {
-        // Execute: msg.sender was already verified
-        HandleType::HANDLE_TYPE_EXECUTE => {}
-        // Reply & IBC stuff: no msg.sender, set it to null just in case
-        // WASM Hooks: cannot verify sender, set it to null
-        HandleType::HANDLE_TYPE_REPLY
-        | HandleType::HANDLE_TYPE_IBC_CHANNEL_OPEN
-        | HandleType::HANDLE_TYPE_IBC_CHANNEL_CONNECT
-        | HandleType::HANDLE_TYPE_IBC_CHANNEL_CLOSE
-        | HandleType::HANDLE_TYPE_IBC_PACKET_RECEIVE
-        | HandleType::HANDLE_TYPE_IBC_PACKET_ACK
-        | HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT
-        | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER
-        | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK
-        | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT => {
-            versioned_env.set_msg_sender("")
-        }
+    if route.sender == SenderPolicy::Null {
+        versioned_env.set_msg_sender("");
     }
 
     #[cfg(feature = "random")]
@@ -657,28 +968,89 @@ pub fn handle(
     }
 
     versioned_env.set_contract_hash(&contract_hash);
+    engine.set_verified_fee(tx_info.as_ref().and_then(|tx_info| tx_info.fee.clone()));
+    engine.set_oracle_proofs(
+        tx_info
+            .as_ref()
+            .map_or_else(Vec::new, |tx_info| tx_info.oracle_proofs.clone()),
+    );
+    engine.set_custom_query_auth(CustomQueryAuth::from_env(env, base_env.0.block.height));
+    engine.set_tx_hash(base_env.0.transaction.as_ref().map(|tx| tx.hash.clone()));
+    let transient_info = extract_transient_info(env)?;
+    let transient_scope = TransientScope::from_env(&base_env, &transient_info);
+    if parsed_handle_type == HandleType::HANDLE_TYPE_REPLY {
+        if let Some(reverted_execution) = transient_info.reverted_execution {
+            revert_transient_writes(&transient_scope, reverted_execution);
+        }
+    }
+    engine.set_deliver_tx(deliver_tx);
+    engine.set_transient_scope(transient_scope);
+    versioned_env.set_verified_tx_info(tx_info.filter(|_| is_active(Feature::TxInfoInEnv)));
+    versioned_env.set_verified_funds(funds);
+
+    #[cfg(feature = "light-client-validation")]
+    set_block_header_in_env(block_height, &mut versioned_env)?;
+
+    let already_executed = match &idempotency_key {
+        Some(key_hash) => {
+            check_idempotency_key(&mut engine, key_hash, block_height, !sent_funds.is_empty())?
+        }
+        None => false,
+    };
+
+    if parsed_handle_type == HandleType::HANDLE_TYPE_REPLY {
+        let siblings =
+            take_pending_siblings(&mut engine, TxPosition::from_env(&base_env), &validated_msg)?;
+        engine.set_pending_replies(siblings);
+    }
 
     update_msg_counter(block_height);
 
-    let result = engine.handle(&versioned_env, validated_msg, &parsed_handle_type);
+    let result = if already_executed {
+        already_executed_output(engine.get_api_version())
+    } else {
+        isolate_execution(|| engine.handle(&versioned_env, validated_msg, route))
+    };
 
-    *used_gas = engine.gas_used();
+    *used_gas = input_gas + engine.gas_used();
 
-    let mut output = result?;
+    #[cfg(feature = "light-client-validation")]
+    use_block_gas(block_height, deliver_tx, *used_gas)?;
+
+    let mut output = append_atomic_calls(result?, engine.take_atomic_calls())?;
+    validate_event_schema(&mut engine, &output)?;
+    record_outgoing_transfers(&mut engine, &output, contract_address)?;
+    record_pending_replies(&mut engine, TxPosition::from_env(&base_env), &output)?;
+    commit_transient_writes(&mut engine, &output);
+    if let Some(code_hash) = engine.take_migration_announcement() {
+        store_migration_announcement(&mut engine, code_hash, block_height)?;
+    }
+    if let Some(key_hash) = idempotency_key.filter(|_| !already_executed) {
+        record_idempotency_key(&mut engine, key_hash, block_height)?;
+    }
+    let auditor_key = read_auditor_key(&mut engine)?;
+    let viewer_key = engine.take_designated_viewer();
+    let touched_keys = engine
+        .touched_keys()
+        .to_hashed(block_height, &canonical_contract_address)?;
 
     let random = versioned_env.get_random();
+    let capability_summary = engine.capability_summary(summary_requested);
 
     // This gets refunded because it will get charged later by the sdk
     let refund_cache_gas = engine
-        .flush_cache(random)
+        .flush_cache(random, contract_address, block_height)
         .map_err(|_| EnclaveError::FailedFunctionCall)?;
     *used_gas = used_gas.saturating_sub(refund_cache_gas);
+    // The storage the execution grew by is surcharged once its writes are flushed
+    *used_gas += engine.account_storage()?;
 
     debug!(
         "(2) nonce just before encrypt_output: nonce = {:x?} pubkey = {:x?}",
         secret_msg.nonce, secret_msg.user_public_key
     );
     if should_encrypt_output {
+        let mut padded_bytes: u64 = 0;
         output = post_process_output(
             output,
             &secret_msg,
@@ -687,17 +1059,66 @@ pub fn handle(
             reply_params,
             &canonical_sender_address,
             false,
-            is_ibc_msg(parsed_handle_type),
+            route.ibc_entrypoint,
+            auditor_key.as_ref(),
+            viewer_key.as_ref(),
+            capability_summary,
+            output_normalization_version(engine.supported_features()),
+            &handle_output_padding(
+                engine.supported_features(),
+                route,
+                &compute_params.output_padding(),
+            ),
+            &mut padded_bytes,
         )?;
+        use_padding_gas(used_gas, gas_limit, padded_bytes, &compute_params)?;
     } else {
-        let mut raw_output =
-            manipulate_callback_sig_for_plaintext(&canonical_contract_address, output)?;
-        set_all_logs_to_plaintext(&mut raw_output);
+        let mut raw_output = manipulate_callback_sig_for_plaintext(
+            &canonical_contract_address,
+            output,
+            &reply_params,
+            &canonical_sender_address,
+            output_normalization_version(engine.supported_features()),
+        )?;
+        set_all_logs_to_plaintext(
+            &mut raw_output,
+            &compute_params.plaintext_attribute_denylist(),
+        )?;
 
-        output = finalize_raw_output(raw_output, false, is_ibc_msg(parsed_handle_type), false)?;
+        output = finalize_raw_output(raw_output, false, route.ibc_entrypoint, false)?;
     }
 
-    Ok(HandleSuccess { output })
+    let receipt = execution_receipt(
+        contract_address.as_str(),
+        &contract_hash,
+        msg,
+        &output,
+        block_height,
+        Some(parsed_sig_info.tx_bytes.as_slice()).filter(|_| deliver_tx),
+    )?;
+
+    Ok(HandleSuccess {
+        output,
+        touched_keys,
+        receipt,
+        write_intent: engine.take_write_intent(),
+    })
+}
+
+#[cfg(feature = "light-client-validation")]
+fn set_block_header_in_env(
+    block_height: u64,
+    versioned_env: &mut CwEnv,
+) -> Result<(), EnclaveError> {
+    if !is_active(Feature::BlockHeaderInEnv) {
+        return Ok(());
+    }
+
+    if let Some((hash, proposer)) = verified_block_header(block_height)? {
+        versioned_env.set_verified_block_header(hash, proposer);
+    }
+
+    Ok(())
 }
 
 #[cfg(feature = "random")]
@@ -713,6 +1134,8 @@ fn set_random_in_env(
             .contains(&ContractFeature::Random)
         {
             debug!("random is enabled by contract");
+            // Commit-reveal randomness is derived from the seed of the block itself
+            engine.set_block_random(block_height, versioned_env.get_random());
             update_random_with_msg_counter(block_height, contract_key, versioned_env);
         } else {
             versioned_env.set_random(None);
@@ -740,25 +1163,92 @@ pub fn query(
     msg: &[u8],
 ) -> Result<QuerySuccess, EnclaveError> {
     trace!("Entered query");
+    let _trace = begin_trace(&context, "query");
+
+    // Before any gas is used, so that the host can retry with the whole code if it isn't pinned
+    let contract = resolve_contract_code(contract)?;
 
-    let contract_code = ContractCode::new(contract);
+    let contract_code = contract.contract_code();
     let contract_hash = contract_code.hash();
 
-    let base_env: BaseEnv = extract_base_env(env)?;
+    let mut base_env: BaseEnv = extract_base_env(env)?;
+    let compute_params = ComputeParams::from_env(&base_env)?;
+    let input_gas = use_input_gas(used_gas, gas_limit, &[env, msg], &compute_params)?;
     let query_depth = extract_query_depth(env)?;
+    let query_origin = extract_query_origin(env)?;
+
+    let historical_app_hash = match extract_query_height(env)? {
+        Some(query_height) => verify_historical_block_info(&mut base_env, query_height)?,
+        // The queries that a query at a past height makes must read the same height
+        None if is_historical() => {
+            warn!("query at the latest height was made by a query at a past height");
+            return Err(EnclaveError::ValidationFailure);
+        }
+        None => None,
+    };
 
-    let (_, contract_address, _, _) = base_env.get_verification_params();
+    let (_, contract_address, block_height, _) = base_env.get_verification_params();
 
     let canonical_contract_address = to_canonical(contract_address)?;
 
-    validate_contract_key(&base_env, &canonical_contract_address, &contract_code)?;
+    // The reads of a query at a past height are proven against the app hash that the light client
+    // verified, instead of trusting the host to provide the state as of that height
+    let _historical_state = historical_app_hash.map(|app_hash| {
+        enter_historical_state(HistoricalState::new(
+            app_hash,
+            &canonical_contract_address.0 .0,
+        ))
+    });
+
+    validate_contract_key(
+        &context,
+        &base_env,
+        &canonical_contract_address,
+        &contract_code,
+    )?;
 
     let secret_msg = SecretMessage::from_slice(msg)?;
-    let decrypted_msg = secret_msg.decrypt()?;
+    // Only the top-level queries that the node serves to its clients are limited, per connection
+    let decrypted_msg = match (query_origin, extract_query_connection(env)?) {
+        (Some(QueryOrigin::Node), Some(connection)) => decrypt_query_msg(&secret_msg, &connection)?,
+        _ => secret_msg.decrypt()?,
+    };
+
+    // Queries made by contracts share the query cache of the execution that made them. Any other
+    // query that carries a scope got it from its sender rather than from an execution.
+    let (query_cache_id, decrypted_msg) = split_query_cache_scope(&decrypted_msg);
+    let query_cache = match (query_cache_id, query_origin) {
+        (Some(id), Some(QueryOrigin::Contract)) => QueryCacheScope::join(id, block_height),
+        (Some(_), _) => {
+            warn!("query that wasn't made by a contract carries a query cache scope");
+            return Err(EnclaveError::ValidationFailure);
+        }
+        (None, _) => QueryCacheScope::new(block_height)?,
+    };
+
+    // Only the queries that the node serves are answered from the results of earlier ones
+    let result_key = if is_cacheable_query(query_origin, is_flagged_deliver_tx(env, &base_env)?) {
+        Some(query_result_key(
+            &canonical_contract_address.0 .0,
+            &contract_hash,
+            msg,
+            block_height,
+        ))
+    } else {
+        None
+    };
+    if let Some(cached) = result_key.as_ref().and_then(get_cached_query_result) {
+        if input_gas.saturating_add(cached.used_gas) <= gas_limit {
+            *used_gas = input_gas + cached.used_gas;
+            return Ok(QuerySuccess {
+                output: cached.output,
+            });
+        }
+    }
 
     let ValidatedMessage { validated_msg, .. } = validate_msg(
         &canonical_contract_address,
-        &decrypted_msg,
+        decrypted_msg,
         &contract_hash,
         None,
         None,
@@ -766,28 +1256,39 @@ pub fn query(
 
     let og_contract_key = base_env.get_og_contract_key()?;
 
+    let state_keys = StateKeys::at_height(&context, &og_contract_key, base_env.0.block.height)?;
     let mut engine = start_engine(
         context,
-        gas_limit,
+        gas_limit - input_gas,
         &contract_code,
-        &og_contract_key,
+        state_keys,
         ContractOperation::Query,
         query_depth,
         secret_msg.nonce,
         secret_msg.user_public_key,
         base_env.0.block.time,
+        query_cache,
+        compute_params.wasm_costs(),
+        compute_params.max_memory_pages(&contract_code.hash()),
     )?;
 
+    // Only the queries that the node serves may depend on its clock
+    if query_origin == Some(QueryOrigin::Node) {
+        engine.start_deadline();
+    }
+    engine.set_custom_query_auth(CustomQueryAuth::from_env(env, base_env.0.block.height));
+
     let mut versioned_env = base_env
         .clone()
         .into_versioned_env(&engine.get_api_version());
 
     versioned_env.set_contract_hash(&contract_hash);
 
-    let result = engine.query(&versioned_env, validated_msg);
-    *used_gas = engine.gas_used();
+    let result = isolate_execution(|| engine.query(&versioned_env, validated_msg));
+    *used_gas = input_gas + engine.gas_used();
     let output = result?;
 
+    let mut padded_bytes: u64 = 0;
     let output = post_process_output(
         output,
         &secret_msg,
@@ -797,38 +1298,275 @@ pub fn query(
         &CanonicalAddr(Binary(Vec::new())), // Not used for queries (used only for replies)
         true,
         false,
+        None, // Not used for queries (queries don't emit attributes)
+        None, // Not used for queries (queries can't designate viewers)
+        None, // Not used for queries (queries don't summarize their capabilities)
+        output_normalization_version(engine.supported_features()),
+        &PaddingBuckets::for_entrypoint(
+            engine.supported_features(),
+            ShapedEntrypoint::Query,
+            &compute_params.output_padding(),
+        ),
+        &mut padded_bytes,
     )?;
+    use_padding_gas(used_gas, gas_limit, padded_bytes, &compute_params)?;
+
+    if let Some(key) = result_key {
+        cache_query_result(
+            key,
+            CachedQueryResult {
+                output: output.clone(),
+                used_gas: *used_gas - input_gas,
+            },
+        );
+    }
 
     Ok(QuerySuccess { output })
 }
 
+/// Stores the event schema a contract registered in its output, if any, and validates the output
+/// against the contract's event schema
+fn register_event_schema(engine: &mut Engine, output: Vec<u8>) -> Result<Vec<u8>, EnclaveError> {
+    let (output, schema) = take_schema_registration(output)?;
+
+    match schema {
+        Some(schema) => {
+            let serialized_schema = serde_json::to_vec(&schema).map_err(|err| {
+                warn!("failed to serialize event schema: {}", err);
+                EnclaveError::FailedToSerialize
+            })?;
+            engine.write_reserved_key(reserved_keys::EVENT_SCHEMA, &serialized_schema);
+
+            validate_output_events(&output, &schema)?;
+        }
+        None => validate_event_schema(engine, &output)?,
+    }
+
+    Ok(output)
+}
+
+/// Validates the output against the event schema the contract registered, if any
+fn validate_event_schema(engine: &mut Engine, output: &[u8]) -> Result<(), EnclaveError> {
+    let serialized_schema = match engine.read_reserved_key(reserved_keys::EVENT_SCHEMA)? {
+        Some(serialized_schema) => serialized_schema,
+        None => return Ok(()),
+    };
+
+    let schema: EventSchema = serde_json::from_slice(&serialized_schema).map_err(|err| {
+        warn!("failed to parse stored event schema: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    validate_output_events(output, &schema)
+}
+
+/// Charges gas for deserializing the inputs of an execution, proportionally to their size.
+/// This happens before the msg is parsed, so large inputs can't be used to waste the resources
+/// of the node for free. Only the env and sig_info are parsed before: the env's block activates
+/// the charge and carries the costs, and the sig_info tells which inputs are charged (see
+/// `charged_inputs`). Returns the amount of gas that was charged.
+fn use_input_gas(
+    used_gas: &mut u64,
+    gas_limit: u64,
+    inputs: &[&[u8]],
+    compute_params: &ComputeParams,
+) -> Result<u64, EnclaveError> {
+    if !is_active(Feature::InputGas) {
+        return Ok(0);
+    }
+
+    let input_len: u64 = inputs.iter().map(|input| input.len() as u64).sum();
+    let input_gas =
+        input_len.saturating_mul(compute_params.wasm_costs().input_deserialization_per_byte as u64);
+    *used_gas = input_gas;
+
+    debug!(
+        "gas breakdown: {} for deserializing {} bytes of input",
+        input_gas, input_len
+    );
+
+    if input_gas > gas_limit {
+        warn!(
+            "ran out of gas while deserializing the input: used {} out of {}",
+            input_gas, gas_limit
+        );
+        return Err(EnclaveError::OutOfGas);
+    }
+
+    Ok(input_gas)
+}
+
+/// The inputs of an init or handle that are charged by `use_input_gas`. Every call of a tx
+/// carries the sig_info of the tx, with the whole tx in it, so it's only charged for the calls
+/// that the tx made itself. Submessages and replies are signed by the enclave instead of the
+/// sender, so they carry a callback signature, and the tx was already paid for by their caller.
+fn charged_inputs<'a>(
+    env: &'a [u8],
+    msg: &'a [u8],
+    sig_info: &'a [u8],
+    parsed_sig_info: &SigInfo,
+) -> Vec<&'a [u8]> {
+    if parsed_sig_info.callback_sig.is_some() {
+        vec![env, msg]
+    } else {
+        vec![env, msg, sig_info]
+    }
+}
+
+/// Checks the capabilities the contract requires in its requirements section against the enclave
+/// manifest. If some are missing, returns an error output listing them, so the contract fails to
+/// init or migrate instead of silently misbehaving on an enclave that doesn't support them.
+fn check_required_capabilities(
+    contract_code: &ContractCode,
+) -> Result<Option<Vec<u8>>, EnclaveError> {
+    let required_capabilities = contract_code.required_capabilities().map_err(|err| {
+        warn!("contract has a malformed requirements section: {}", err);
+        EnclaveError::InvalidWasm
+    })?;
+
+    let missing = missing_capabilities(&required_capabilities);
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    warn!(
+        "contract requires capabilities that the enclave doesn't support: {:?}",
+        missing
+    );
+    generic_error_output(format!(
+        "contract requires enclave capabilities that are not supported by this node: {}",
+        missing.join(", ")
+    ))
+    .map(Some)
+}
+
+/// A raw contract output with a generic error, for operations that fail before the contract runs
+fn generic_error_output(error_message: String) -> Result<Vec<u8>, EnclaveError> {
+    let error_output =
+        serde_json::json!({ "Err": format_generic_error_message(error_message.into()) });
+
+    serde_json::to_vec(&error_output).map_err(|err| {
+        warn!("failed to serialize generic error output: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Returns the encrypted `error_output` of a migration that failed before the new code ran.
+/// The migration fails, so the contract keeps its current key.
+#[allow(clippy::too_many_arguments)]
+fn failed_migration(
+    error_output: Vec<u8>,
+    secret_msg: &SecretMessage,
+    contract_address: &CanonicalAddr,
+    contract_hash: &[u8; HASH_SIZE],
+    reply_params: Option<Vec<ReplyParams>>,
+    sender: &CanonicalAddr,
+    og_contract_key: ContractKey,
+    used_gas: &mut u64,
+    gas_limit: u64,
+    compute_params: &ComputeParams,
+) -> Result<MigrateSuccess, EnclaveError> {
+    let mut padded_bytes: u64 = 0;
+    let output = post_process_output(
+        error_output,
+        secret_msg,
+        contract_address,
+        &hex::encode(contract_hash),
+        reply_params,
+        sender,
+        false,
+        false,
+        None,
+        None,
+        None,
+        output_normalization_version(&[]),
+        &PaddingBuckets::for_features(&[], &compute_params.output_padding()),
+        &mut padded_bytes,
+    )?;
+    use_padding_gas(used_gas, gas_limit, padded_bytes, compute_params)?;
+
+    Ok(MigrateSuccess {
+        output,
+        new_contract_key: og_contract_key,
+        new_contract_key_proof: [0; 32],
+        write_intent: vec![],
+    })
+}
+
+/// Charges gas for the padding that was added to the encrypted output, proportionally to the
+/// number of padded bytes. This happens after the contract has finished executing, so the gas
+/// limit is enforced here.
+fn use_padding_gas(
+    used_gas: &mut u64,
+    gas_limit: u64,
+    padded_bytes: u64,
+    compute_params: &ComputeParams,
+) -> Result<(), EnclaveError> {
+    let padding_gas =
+        padded_bytes.saturating_mul(compute_params.wasm_costs().output_padding_per_byte as u64);
+    *used_gas = used_gas.saturating_add(padding_gas);
+
+    if *used_gas > gas_limit {
+        warn!(
+            "ran out of gas while padding the output: used {} out of {}",
+            used_gas, gas_limit
+        );
+        return Err(EnclaveError::OutOfGas);
+    }
+
+    Ok(())
+}
+
+/// Only the execute and reply entry points of `handle` can have a constant shape
+fn handle_output_padding(
+    features: &[ContractFeature],
+    route: &HandleRoute,
+    params: &OutputPaddingParams,
+) -> PaddingBuckets {
+    match route.shaped_entrypoint {
+        Some(entrypoint) => PaddingBuckets::for_entrypoint(features, entrypoint, params),
+        None => PaddingBuckets::for_features(features, params),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn start_engine(
     context: Ctx,
     gas_limit: u64,
     contract_code: &ContractCode,
-    og_contract_key: &ContractKey,
+    state_keys: StateKeys,
     operation: ContractOperation,
     query_depth: u32,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
     timestamp: u64,
+    query_cache: QueryCacheScope,
+    gas_costs: WasmCosts,
+    max_memory_pages: u32,
 ) -> Result<crate::wasm3::Engine, EnclaveError> {
-    crate::wasm3::Engine::new(
+    let _span = trace_span("engine_setup");
+    let start = Instant::now();
+    let engine = crate::wasm3::Engine::new(
         context,
         gas_limit,
-        WasmCosts::default(),
+        gas_costs,
         contract_code,
-        *og_contract_key,
+        state_keys,
         operation,
         nonce,
         user_public_key,
         query_depth,
         timestamp,
-    )
+        query_cache,
+        max_memory_pages,
+    );
+    record_engine_start(start.elapsed());
+
+    engine
 }
 
 fn extract_base_env(env: &[u8]) -> Result<BaseEnv, EnclaveError> {
+    let _span = trace_span("extract_env");
     serde_json::from_slice(env)
         .map_err(|err| {
             warn!(
@@ -840,6 +1578,7 @@ fn extract_base_env(env: &[u8]) -> Result<BaseEnv, EnclaveError> {
         })
         .map(|base_env| {
             trace!("base env: {:?}", base_env);
+            set_active_block(&base_env.0.block.chain_id, base_env.0.block.height);
             base_env
         })
 }
@@ -869,3 +1608,162 @@ fn extract_query_depth(env: &[u8]) -> Result<u32, EnclaveError> {
             env.query_depth
         })
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvWithOldCodeHash {
+    old_code_hash: Option<String>,
+}
+
+/// The hash of the code the contract is migrated from. It is provided by the host in env, and it
+/// is verified against the contract key of the contract before the migration.
+fn verified_old_code_hash(
+    env: &[u8],
+    base_env: &BaseEnv,
+    canonical_contract_address: &CanonicalAddr,
+) -> Result<[u8; HASH_SIZE], EnclaveError> {
+    let old_code_hash = serde_json::from_slice::<EnvWithOldCodeHash>(env)
+        .map_err(|err| {
+            warn!(
+                "error while deserializing env into json {:?}: {}",
+                String::from_utf8_lossy(env),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })?
+        .old_code_hash
+        .ok_or_else(|| {
+            warn!("migrate was called without the old code hash");
+            EnclaveError::ValidationFailure
+        })?;
+
+    let old_code_hash_bytes: [u8; HASH_SIZE] = hex::decode(&old_code_hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            warn!("got an invalid old code hash: {:?}", old_code_hash);
+            EnclaveError::ValidationFailure
+        })?;
+    validate_old_code_hash(base_env, canonical_contract_address, &old_code_hash_bytes)?;
+
+    Ok(old_code_hash_bytes)
+}
+
+/// Builds the serialized `MigrateInfo` for the migrate entry point
+fn build_migrate_info(
+    old_code_hash: &[u8; HASH_SIZE],
+    sender: &BaseAddr,
+) -> Result<Vec<u8>, EnclaveError> {
+    let migrate_info = MigrateInfo {
+        sender: Addr(sender.0.clone()),
+        old_migrate_version: None,
+        old_code_hash: hex::encode(old_code_hash),
+    };
+
+    serde_json::to_vec(&migrate_info).map_err(|err| {
+        warn!("failed to serialize migrate info: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Extract where an execution is in its transaction, as the host flagged it
+fn extract_transient_info(env: &[u8]) -> Result<TransientInfo, EnclaveError> {
+    serde_json::from_slice::<TransientInfo>(env).map_err(|err| {
+        warn!(
+            "error while deserializing env into json {:?}: {}",
+            String::from_utf8_lossy(env),
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+/// Whether the execution is part of a transaction that is delivered in a block, as the host
+/// flagged it, rather than of CheckTx or a simulation
+fn is_flagged_deliver_tx(env: &[u8], base_env: &BaseEnv) -> Result<bool, EnclaveError> {
+    Ok(extract_transient_info(env)?.deliver_tx && base_env.0.transaction.is_some())
+}
+
+/// Whether the execution is part of a transaction that is delivered in a block. The host can't
+/// clear its flag for a transaction of the verified block, which would exempt its executions from
+/// the gas of the block, see `is_delivered_tx`.
+#[cfg(feature = "light-client-validation")]
+fn is_deliver_tx(env: &[u8], base_env: &BaseEnv, sig_info: &SigInfo) -> Result<bool, EnclaveError> {
+    Ok(is_delivered_tx(
+        base_env.0.block.height,
+        sig_info.tx_bytes.as_slice(),
+        is_flagged_deliver_tx(env, base_env)?,
+    ))
+}
+
+/// Without the light client nothing is verified, so the flag of the host is trusted
+#[cfg(not(feature = "light-client-validation"))]
+fn is_deliver_tx(
+    env: &[u8],
+    base_env: &BaseEnv,
+    _sig_info: &SigInfo,
+) -> Result<bool, EnclaveError> {
+    is_flagged_deliver_tx(env, base_env)
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvWithQueryOrigin {
+    query_origin: Option<QueryOrigin>,
+}
+
+/// Extract where a query comes from, as the host flagged it
+fn extract_query_origin(env: &[u8]) -> Result<Option<QueryOrigin>, EnclaveError> {
+    serde_json::from_slice::<EnvWithQueryOrigin>(env)
+        .map_err(|err| {
+            warn!(
+                "error while deserializing env into json {:?}: {}",
+                String::from_utf8_lossy(env),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })
+        .map(|env| env.query_origin)
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvWithQueryConnection {
+    query_connection: Option<String>,
+}
+
+/// Extract the connection of the client of a query that the node serves, as the host flagged it
+fn extract_query_connection(env: &[u8]) -> Result<Option<String>, EnclaveError> {
+    serde_json::from_slice::<EnvWithQueryConnection>(env)
+        .map_err(|err| {
+            warn!(
+                "error while deserializing env into json {:?}: {}",
+                String::from_utf8_lossy(env),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })
+        .map(|env| env.query_connection)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EnvWithQueryHeight {
+    query_height: Option<u64>,
+}
+
+/// Extract the height that a query should be executed at, if it's not the latest one.
+///
+/// When this is set, the host provides the state as of that height with a proof of every read,
+/// and the block info in env must match headers that were verified by the light client.
+fn extract_query_height(env: &[u8]) -> Result<Option<u64>, EnclaveError> {
+    serde_json::from_slice::<EnvWithQueryHeight>(env)
+        .map_err(|err| {
+            warn!(
+                "error while deserializing env into json {:?}: {}",
+                String::from_utf8_lossy(env),
+                err
+            );
+            EnclaveError::FailedToDeserialize
+        })
+        .map(|env| {
+            trace!("env.query_height: {:?}", env.query_height);
+            env.query_height
+        })
+}
@@ -0,0 +1,98 @@
+use enclave_crypto::{AESKey, Kdf, SymmetricKey, KEY_MANAGER};
+use log::*;
+
+use crate::contract_validation::ContractKey;
+
+/// Separates the subkeys of contracts from the other keys derived from the consensus state ikm
+const CONTRACT_SUBKEY_DERIVATION_INFO: &[u8] = b"secret_contract_subkey";
+
+pub const MAX_SUBKEY_LABEL_LENGTH: usize = 256;
+
+/// The errors `derive_contract_subkey` returns in the high half of its result
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum ContractSubkeyError {
+    EmptyLabel = 1,
+    LabelTooLong = 2,
+    /// The enclave has no consensus seed, which shouldn't happen on a running node
+    KeyUnavailable = 3,
+}
+
+/// The label is length prefixed so that no pair of contract key and label derives the same
+/// subkey as another pair
+fn derive_subkey(
+    consensus_state_ikm: &AESKey,
+    og_contract_key: &ContractKey,
+    label: &[u8],
+) -> SymmetricKey {
+    let mut derivation_data = CONTRACT_SUBKEY_DERIVATION_INFO.to_vec();
+    derivation_data.extend_from_slice(og_contract_key);
+    derivation_data.extend_from_slice(&(label.len() as u32).to_be_bytes());
+    derivation_data.extend_from_slice(label);
+
+    *consensus_state_ikm
+        .derive_key_from_this(&derivation_data)
+        .get()
+}
+
+/// Derives a secret that only the contract can get, for the given label. Subkeys are derived
+/// from the og contract key and the genesis consensus state ikm, so they stay the same when the
+/// state of the contract is re-encrypted, when the consensus seed is rotated and when the
+/// contract is migrated. Contracts can use them for viewing keys, HMACs and deterministic
+/// encryption instead of storing secrets in their state.
+pub fn derive_contract_subkey(
+    og_contract_key: &ContractKey,
+    label: &[u8],
+) -> Result<SymmetricKey, ContractSubkeyError> {
+    if label.is_empty() {
+        return Err(ContractSubkeyError::EmptyLabel);
+    }
+    if label.len() > MAX_SUBKEY_LABEL_LENGTH {
+        return Err(ContractSubkeyError::LabelTooLong);
+    }
+
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|err| {
+        error!("failed to get the consensus state ikm: {:?}", err);
+        ContractSubkeyError::KeyUnavailable
+    })?;
+
+    Ok(derive_subkey(
+        &consensus_state_ikm.genesis,
+        og_contract_key,
+        label,
+    ))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::contract_validation::CONTRACT_KEY_LENGTH;
+
+    pub fn test_derive_contract_subkey() {
+        let ikm = AESKey::new_from_slice(&[1u8; 32]);
+        let contract_key = [2u8; CONTRACT_KEY_LENGTH];
+        let subkey = derive_subkey(&ikm, &contract_key, b"viewing_keys");
+
+        assert_eq!(derive_subkey(&ikm, &contract_key, b"viewing_keys"), subkey);
+        assert_ne!(derive_subkey(&ikm, &contract_key, b"hmac"), subkey);
+        assert_ne!(
+            derive_subkey(&ikm, &[3u8; CONTRACT_KEY_LENGTH], b"viewing_keys"),
+            subkey
+        );
+        let other_ikm = AESKey::new_from_slice(&[4u8; 32]);
+        assert_ne!(
+            derive_subkey(&other_ikm, &contract_key, b"viewing_keys"),
+            subkey
+        );
+
+        assert_eq!(
+            derive_contract_subkey(&contract_key, b""),
+            Err(ContractSubkeyError::EmptyLabel)
+        );
+        assert_eq!(
+            derive_contract_subkey(&contract_key, &[b'a'; MAX_SUBKEY_LABEL_LENGTH + 1]),
+            Err(ContractSubkeyError::LabelTooLong)
+        );
+    }
+}
@@ -1,31 +1,56 @@
+use std::convert::{TryFrom, TryInto};
+
 use cw_types_v1::ibc::IbcPacketReceiveMsg;
-use cw_types_v1::results::REPLY_ENCRYPTION_MAGIC_BYTES;
+use cw_types_v1::results::{REPLY_ENCRYPTION_MAGIC_BYTES, REPLY_PAYLOAD_MAGIC_BYTES};
 use log::*;
 
-use cw_types_generic::BaseEnv;
+use cw_types_generic::{BaseEnv, VerifiedFee, VerifiedFunds, VerifiedTxInfo};
 
 use cw_types_v010::types::{CanonicalAddr, Coin, HumanAddr};
 use enclave_cosmos_types::traits::CosmosAminoPubkey;
 use enclave_cosmos_types::types::{
-    ContractCode, CosmosPubKey, DirectSdkMsg, HandleType, SigInfo, SignDoc, StdSignDoc, TxBody,
-    VerifyParamsType,
+    AuthInfo, ContractCode, CosmosPubKey, DirectSdkMsg, HandleType, Height, Packet, SigInfo,
+    SignDoc, StdFee, StdSignDoc, TxBody, VerifyParamsType,
 };
 use enclave_crypto::traits::VerifyingKey;
 use enclave_crypto::{sha_256, AESKey, Hmac, Kdf, HASH_SIZE, KEY_MANAGER};
-use enclave_ffi_types::EnclaveError;
+use enclave_ffi_types::{Ctx, EnclaveError};
 use protobuf::Message;
 
+use crate::cosmwasm_config::ibc::MAX_COUNTERPARTY_CLOCK_DRIFT_NANOS;
+use crate::execution_trace::trace_span;
+use crate::group_authority::GroupAuthority;
 use crate::hardcoded_admins::is_code_hash_allowed;
 use crate::input_validation::contract_address_validation::verify_contract_address;
-use crate::input_validation::msg_validation::verify_and_get_sdk_msg;
-use crate::input_validation::send_funds_validations::verify_sent_funds;
+use crate::input_validation::msg_validation::{
+    verify_and_get_sdk_msg, verify_ibc_packet_timeout, verify_ibc_wasm_hooks_outgoing_transfer_ack,
+    verify_ibc_wasm_hooks_outgoing_transfer_timeout,
+};
+use crate::input_validation::send_funds_validations::{verify_funds_denoms, verify_sent_funds};
 use crate::input_validation::sender_validation::verify_sender;
 use crate::io::create_callback_signature;
 use crate::message::is_ibc_msg;
+use crate::previous_contract_key::is_accepted_previous_contract_key;
+use crate::sig_verification_cache::{is_signature_verified, record_verified_signature};
 use crate::types::SecretMessage;
+use crate::verification_profiles::verification_profile;
+
+#[cfg(feature = "light-client-validation")]
+use block_verifier::{BLOCK_GAS, VERIFIED_BLOCK_MESSAGES, VERIFIED_HEADERS};
+#[cfg(feature = "light-client-validation")]
+use cw_types_v010::encoding::Binary;
+#[cfg(feature = "light-client-validation")]
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+#[cfg(feature = "light-client-validation")]
+use lazy_static::lazy_static;
+#[cfg(feature = "light-client-validation")]
+use std::sync::SgxMutex;
+
+#[cfg(feature = "light-client-validation")]
+use crate::panic_isolation::LockOrRecover;
 
 #[cfg(feature = "light-client-validation")]
-use block_verifier::VERIFIED_BLOCK_MESSAGES;
+use crate::sequence_tracker::observe_signed_sequence;
 
 extern crate hex;
 
@@ -35,6 +60,7 @@ pub const CONTRACT_KEY_LENGTH: usize = HASH_SIZE + HASH_SIZE;
 
 const HEX_ENCODED_HASH_SIZE: usize = HASH_SIZE * 2;
 const SIZE_OF_U64: usize = 8;
+const SIZE_OF_U32: usize = 4;
 
 #[cfg(feature = "light-client-validation")]
 fn is_subslice(larger: &[u8], smaller: &[u8]) -> bool {
@@ -53,7 +79,14 @@ fn is_subslice(larger: &[u8], smaller: &[u8]) -> bool {
 }
 
 #[cfg(feature = "light-client-validation")]
-pub fn verify_block_info(base_env: &BaseEnv) -> Result<(), EnclaveError> {
+lazy_static! {
+    /// The height of the verified block and the hash of its transaction whose message was found
+    /// last, see `check_tx_in_current_block`
+    static ref DELIVERED_TX: SgxMutex<Option<(u64, [u8; HASH_SIZE])>> = SgxMutex::new(None);
+}
+
+#[cfg(feature = "light-client-validation")]
+fn is_light_client_validation_skipped() -> bool {
     #[cfg(feature = "go-tests")]
     {
         // allow skipping light client validation in go-tests
@@ -65,11 +98,20 @@ pub fn verify_block_info(base_env: &BaseEnv) -> Result<(), EnclaveError> {
             .to_uppercase()
             == "TRUE"
         {
-            return Ok(());
+            return true;
         }
     }
 
-    let verified_msgs = VERIFIED_BLOCK_MESSAGES.lock().unwrap();
+    false
+}
+
+#[cfg(feature = "light-client-validation")]
+pub fn verify_block_info(base_env: &BaseEnv) -> Result<(), EnclaveError> {
+    if is_light_client_validation_skipped() {
+        return Ok(());
+    }
+
+    let verified_msgs = VERIFIED_BLOCK_MESSAGES.lock_or_recover();
     if verified_msgs.height() != base_env.0.block.height {
         error!("wrong height for this block - 0xF6AC");
         return Err(EnclaveError::ValidationFailure);
@@ -83,6 +125,175 @@ pub fn verify_block_info(base_env: &BaseEnv) -> Result<(), EnclaveError> {
     Ok(())
 }
 
+/// Whether an execution is part of a transaction that is delivered in the verified block at
+/// `block_height`, rather than of CheckTx or a simulation. The transaction whose message was found
+/// in the block last is, and so are the other executions with its bytes, e.g. of its submessages,
+/// whatever the host flagged. Only the flag of the host says whether other executions are, e.g.
+/// the ones whose input isn't verified.
+#[cfg(feature = "light-client-validation")]
+pub fn is_delivered_tx(block_height: u64, tx_bytes: &[u8], flagged_by_host: bool) -> bool {
+    if flagged_by_host || is_light_client_validation_skipped() {
+        return flagged_by_host;
+    }
+
+    match *DELIVERED_TX.lock_or_recover() {
+        Some((height, tx_hash)) => height == block_height && tx_hash == sha_256(tx_bytes),
+        None => false,
+    }
+}
+
+/// Refuses to start an execution of a delivered transaction in a block whose gas is used up, or
+/// whose max gas wasn't verified. The executions of CheckTx and simulations aren't limited.
+#[cfg(feature = "light-client-validation")]
+pub fn check_block_gas(block_height: u64, deliver_tx: bool) -> Result<(), EnclaveError> {
+    if !is_active(Feature::BlockGasLimit) || !deliver_tx || is_light_client_validation_skipped() {
+        return Ok(());
+    }
+
+    if !BLOCK_GAS.lock_or_recover().has_room(block_height) {
+        warn!(
+            "block {} has no gas left for executions, or its max gas wasn't verified",
+            block_height
+        );
+        return Err(EnclaveError::BlockGasExceeded);
+    }
+
+    Ok(())
+}
+
+/// Adds the gas of an execution of a delivered transaction to its block, and refuses the
+/// execution if it doesn't fit
+#[cfg(feature = "light-client-validation")]
+pub fn use_block_gas(
+    block_height: u64,
+    deliver_tx: bool,
+    used_gas: u64,
+) -> Result<(), EnclaveError> {
+    if !is_active(Feature::BlockGasLimit) || !deliver_tx || is_light_client_validation_skipped() {
+        return Ok(());
+    }
+
+    if !BLOCK_GAS.lock_or_recover().use_gas(block_height, used_gas) {
+        warn!(
+            "execution that used {} gas exceeds the gas limit of block {}",
+            used_gas, block_height
+        );
+        return Err(EnclaveError::BlockGasExceeded);
+    }
+
+    Ok(())
+}
+
+/// Returns the hash and proposer of the block that is executed, from its header that the light
+/// client verified, so contracts don't have to trust the host for them. Like the rest of the
+/// block info, they're set for every execution, and it fails if the block wasn't verified.
+#[cfg(feature = "light-client-validation")]
+pub fn verified_block_header(block_height: u64) -> Result<Option<(Binary, Binary)>, EnclaveError> {
+    if is_light_client_validation_skipped() {
+        return Ok(None);
+    }
+
+    let verified_msgs = VERIFIED_BLOCK_MESSAGES.lock_or_recover();
+    if verified_msgs.height() != block_height {
+        error!("header of block {} was not verified", block_height);
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(Some((
+        Binary(verified_msgs.hash().to_vec()),
+        Binary(verified_msgs.proposer().to_vec()),
+    )))
+}
+
+/// Verifies the block info of a query that is executed against the state of a past height, and
+/// returns the app hash that the reads of the query are proven against.
+/// The headers at that height and the next one must have been verified by the light client:
+/// the time of the block comes from the first, and the state after the block is committed to by
+/// the app hash of the next one. Otherwise we can't trust the host to provide the state as of
+/// that height and the query is refused.
+#[cfg(feature = "light-client-validation")]
+pub fn verify_historical_block_info(
+    base_env: &mut BaseEnv,
+    query_height: u64,
+) -> Result<Option<Vec<u8>>, EnclaveError> {
+    if base_env.0.block.height != query_height {
+        error!("env height does not match the requested query height");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    #[cfg(feature = "go-tests")]
+    {
+        // allow skipping light client validation in go-tests
+        // if the env variable SKIP_LIGHT_CLIENT_VALIDATION is set to TRUE
+        let is_skip_light_client_validation = std::env::var("SKIP_LIGHT_CLIENT_VALIDATION");
+
+        if is_skip_light_client_validation
+            .unwrap_or_default()
+            .to_uppercase()
+            == "TRUE"
+        {
+            return Ok(None);
+        }
+    }
+
+    let verified_headers = VERIFIED_HEADERS.lock_or_recover();
+    let (time, app_hash) = historical_block_state(
+        query_height,
+        verified_headers.get(query_height).map(|header| header.time),
+        query_height
+            .checked_add(1)
+            .and_then(|next_height| verified_headers.get(next_height))
+            .map(|header| header.app_hash.as_slice()),
+    )?;
+    base_env.0.block.time = time;
+
+    Ok(Some(app_hash))
+}
+
+/// Without the light client the enclave has no way to verify past headers, so
+/// queries at past heights are always refused.
+#[cfg(not(feature = "light-client-validation"))]
+pub fn verify_historical_block_info(
+    _base_env: &mut BaseEnv,
+    query_height: u64,
+) -> Result<Option<Vec<u8>>, EnclaveError> {
+    warn!(
+        "cannot query at height {} without light client validation",
+        query_height
+    );
+    Err(EnclaveError::ValidationFailure)
+}
+
+/// The time of a past block and the app hash that commits to the state after it, from the time
+/// of its verified header and the app hash of the verified header after it
+#[cfg_attr(not(feature = "light-client-validation"), allow(dead_code))]
+fn historical_block_state(
+    query_height: u64,
+    header_time: Option<i128>,
+    next_app_hash: Option<&[u8]>,
+) -> Result<(u64, Vec<u8>), EnclaveError> {
+    let (header_time, next_app_hash) = match (header_time, next_app_hash) {
+        (Some(header_time), Some(next_app_hash)) => (header_time, next_app_hash),
+        _ => {
+            warn!(
+                "cannot query at height {}, it or the next height was not verified by this enclave",
+                query_height
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    };
+
+    let time = u64::try_from(header_time).map_err(|_| {
+        error!(
+            "verified header at height {} has a negative time",
+            query_height
+        );
+        EnclaveError::ValidationFailure
+    })?;
+
+    Ok((time, next_app_hash.to_vec()))
+}
+
 #[cfg(feature = "light-client-validation")]
 /// WARNING: this function must be called at most once per message!
 /// Checks if there's a msg in the light client that's contained in tx_sign_bytes
@@ -104,7 +315,7 @@ pub fn check_tx_in_current_block(tx_sign_bytes: &[u8]) -> bool {
 
     info!("Verifying message in signed block...");
 
-    let mut verified_msgs = VERIFIED_BLOCK_MESSAGES.lock().unwrap();
+    let mut verified_msgs = VERIFIED_BLOCK_MESSAGES.lock_or_recover();
     let remaining_msgs = verified_msgs.remaining();
 
     if remaining_msgs == 0 {
@@ -120,6 +331,8 @@ pub fn check_tx_in_current_block(tx_sign_bytes: &[u8]) -> bool {
             trace!("input tx_sign_bytes: {:?}", hex::encode(tx_sign_bytes));
             trace!("light client msg: {:?}", hex::encode(&verified_msg));
             if is_subslice(tx_sign_bytes, &verified_msg) {
+                *DELIVERED_TX.lock_or_recover() =
+                    Some((verified_msgs.height(), sha_256(tx_sign_bytes)));
                 return true;
             }
         }
@@ -152,7 +365,7 @@ pub fn check_cert_in_current_block(cert: &[u8]) -> bool {
         }
     }
 
-    let mut verified_msgs = VERIFIED_BLOCK_MESSAGES.lock().unwrap();
+    let mut verified_msgs = VERIFIED_BLOCK_MESSAGES.lock_or_recover();
     let remaining_msgs = verified_msgs.remaining();
 
     if remaining_msgs == 0 {
@@ -248,6 +461,20 @@ pub fn validate_current_contract_key(
     contract_address: &CanonicalAddr,
     contract_code: &ContractCode,
     og_contract_key: Option<&[u8; CONTRACT_KEY_LENGTH]>,
+) -> Result<(), EnclaveError> {
+    validate_contract_key_for_code_hash(
+        contract_key,
+        contract_address,
+        &contract_code.hash(),
+        og_contract_key,
+    )
+}
+
+fn validate_contract_key_for_code_hash(
+    contract_key: &[u8; CONTRACT_KEY_LENGTH],
+    contract_address: &CanonicalAddr,
+    code_hash: &[u8; HASH_SIZE],
+    og_contract_key: Option<&[u8; CONTRACT_KEY_LENGTH]>,
 ) -> Result<(), EnclaveError> {
     // parse contract key -> < signer_id || authentication_code >
     let mut signer_id: [u8; HASH_SIZE] = [0u8; HASH_SIZE];
@@ -270,7 +497,7 @@ pub fn validate_current_contract_key(
     let calculated_authentication_id = generate_contract_id(
         &enclave_key,
         &signer_id,
-        &contract_code.hash(),
+        code_hash,
         contract_address.as_slice(),
         og_contract_key,
     );
@@ -285,41 +512,29 @@ pub fn validate_current_contract_key(
 }
 
 /// validate_contract_key validates the contract key against the contract address and code hash. If the contract was previously migrated, it also validates the contract key proof against the original contract key.
+/// If the contract was migrated in the last `CONTRACT_KEY_GRACE_BLOCKS` blocks, the contract key that the migration replaced is accepted as well.
 pub fn validate_contract_key(
+    context: &Ctx,
     base_env: &BaseEnv,
     canonical_contract_address: &CanonicalAddr,
     contract_code: &ContractCode,
 ) -> Result<(), EnclaveError> {
     let og_contract_key: [u8; CONTRACT_KEY_LENGTH] = base_env.get_og_contract_key()?;
 
-    if base_env.was_migrated() {
+    let result = if base_env.was_migrated() {
         trace!("Contract was migrated, validating proof");
 
         let current_contract_key: [u8; CONTRACT_KEY_LENGTH] =
             base_env.get_current_contract_key()?;
+        let sent_contract_key_proof = base_env.get_current_contract_key_proof()?;
 
-        validate_current_contract_key(
+        validate_migrated_contract_key(
             &current_contract_key,
+            &sent_contract_key_proof,
             canonical_contract_address,
-            contract_code,
-            Some(&og_contract_key),
-        )?;
-
-        let sent_contract_key_proof = base_env.get_current_contract_key_proof()?;
-
-        let contract_key_proof = generate_contract_key_proof(
-            &canonical_contract_address.0 .0,
             &contract_code.hash(),
             &og_contract_key,
-            &current_contract_key, // this is already validated
-        );
-
-        if sent_contract_key_proof != contract_key_proof {
-            error!("Failed to validate contract key proof for a migrated contract");
-            return Err(EnclaveError::ValidationFailure);
-        }
-
-        Ok(())
+        )
     } else {
         trace!("Contract still has original code, validating contract_key");
 
@@ -328,9 +543,85 @@ pub fn validate_contract_key(
             canonical_contract_address,
             contract_code,
             None,
-        )?;
+        )
+    };
 
-        Ok(())
+    if let Err(err) = result {
+        if !is_active(Feature::PreviousContractKeyGrace)
+            || !matches!(
+                is_accepted_previous_contract_key(context, base_env, &og_contract_key),
+                Ok(true)
+            )
+        {
+            return Err(err);
+        }
+
+        info!(
+            "accepted the previous contract key of {} during the migration grace window",
+            canonical_contract_address
+        );
+    }
+
+    Ok(())
+}
+
+fn validate_migrated_contract_key(
+    current_contract_key: &[u8; CONTRACT_KEY_LENGTH],
+    sent_contract_key_proof: &[u8],
+    canonical_contract_address: &CanonicalAddr,
+    code_hash: &[u8; HASH_SIZE],
+    og_contract_key: &[u8; CONTRACT_KEY_LENGTH],
+) -> Result<(), EnclaveError> {
+    validate_contract_key_for_code_hash(
+        current_contract_key,
+        canonical_contract_address,
+        code_hash,
+        Some(og_contract_key),
+    )?;
+
+    let contract_key_proof = generate_contract_key_proof(
+        &canonical_contract_address.0 .0,
+        code_hash,
+        og_contract_key,
+        current_contract_key, // this is already validated
+    );
+
+    if sent_contract_key_proof != contract_key_proof.as_slice() {
+        error!("Failed to validate contract key proof for a migrated contract");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(())
+}
+
+/// Validates that the contract is currently running the code with the given hash, by
+/// authenticating its contract key (from before the migration) against that code hash
+pub fn validate_old_code_hash(
+    base_env: &BaseEnv,
+    canonical_contract_address: &CanonicalAddr,
+    old_code_hash: &[u8; HASH_SIZE],
+) -> Result<(), EnclaveError> {
+    let og_contract_key: [u8; CONTRACT_KEY_LENGTH] = base_env.get_og_contract_key()?;
+
+    if base_env.was_migrated() {
+        let current_contract_key: [u8; CONTRACT_KEY_LENGTH] =
+            base_env.get_current_contract_key()?;
+        let sent_contract_key_proof = base_env.get_current_contract_key_proof()?;
+
+        validate_migrated_contract_key(
+            &current_contract_key,
+            &sent_contract_key_proof,
+            canonical_contract_address,
+            old_code_hash,
+            &og_contract_key,
+        )
+    } else {
+        validate_contract_key_for_code_hash(
+            &og_contract_key,
+            canonical_contract_address,
+            old_code_hash,
+            None,
+        )
     }
 }
 
@@ -370,6 +661,43 @@ pub struct ValidatedMessage {
 pub struct ReplyParams {
     pub recipient_contract_hash: Vec<u8>,
     pub sub_msg_id: u64,
+    /// The payload of the submessage, which travels encrypted in the reply headers
+    pub payload: Vec<u8>,
+}
+
+/// The length of the payload section at the start of `msg`, or 0 if a reply header isn't
+/// followed by a payload
+pub fn reply_payload_section_len(msg: &[u8]) -> Result<usize, EnclaveError> {
+    if !msg.starts_with(REPLY_PAYLOAD_MAGIC_BYTES) {
+        return Ok(0);
+    }
+
+    let header_len = REPLY_PAYLOAD_MAGIC_BYTES.len() + SIZE_OF_U32;
+    if msg.len() < header_len {
+        warn!("Malformed reply payload header");
+        return Err(EnclaveError::ValidationFailure);
+    }
+    let mut payload_len: [u8; SIZE_OF_U32] = [0u8; SIZE_OF_U32];
+    payload_len.copy_from_slice(&msg[REPLY_PAYLOAD_MAGIC_BYTES.len()..header_len]);
+    let section_len = header_len + u32::from_be_bytes(payload_len) as usize;
+    if msg.len() < section_len {
+        warn!("Reply payload is longer than the message");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(section_len)
+}
+
+/// Removes the payload section from the start of `msg` and returns the payload
+pub fn take_reply_payload(msg: &mut Vec<u8>) -> Result<Vec<u8>, EnclaveError> {
+    let section_len = reply_payload_section_len(msg)?;
+    if section_len == 0 {
+        return Ok(vec![]);
+    }
+
+    let payload = msg[REPLY_PAYLOAD_MAGIC_BYTES.len() + SIZE_OF_U32..section_len].to_vec();
+    *msg = msg[section_len..].to_vec();
+    Ok(payload)
 }
 
 /// Validate that the message sent to the enclave (after decryption) was actually addressed to this contract.
@@ -473,13 +801,13 @@ pub fn validate_basic_msg(
                     [0u8; HEX_ENCODED_HASH_SIZE];
                 reply_recipient_contract_hash
                     .copy_from_slice(&partial_msg[0..HEX_ENCODED_HASH_SIZE]);
+                partial_msg = partial_msg[HEX_ENCODED_HASH_SIZE..].to_vec();
 
                 reply_params.as_mut().unwrap().push(ReplyParams {
                     recipient_contract_hash: reply_recipient_contract_hash.to_vec(),
                     sub_msg_id,
+                    payload: take_reply_payload(&mut partial_msg)?,
                 });
-
-                partial_msg = partial_msg[HEX_ENCODED_HASH_SIZE..].to_vec();
             }
 
             validated_msg = msg.to_vec();
@@ -525,13 +853,13 @@ pub fn validate_basic_msg(
         let mut reply_recipient_contract_hash: [u8; HEX_ENCODED_HASH_SIZE] =
             [0u8; HEX_ENCODED_HASH_SIZE];
         reply_recipient_contract_hash.copy_from_slice(&validated_msg[0..HEX_ENCODED_HASH_SIZE]);
+        validated_msg = validated_msg[HEX_ENCODED_HASH_SIZE..].to_vec();
 
         reply_params.as_mut().unwrap().push(ReplyParams {
             recipient_contract_hash: reply_recipient_contract_hash.to_vec(),
             sub_msg_id,
+            payload: take_reply_payload(&mut validated_msg)?,
         });
-
-        validated_msg = validated_msg[HEX_ENCODED_HASH_SIZE..].to_vec();
     }
 
     Ok(ValidatedMessage {
@@ -540,6 +868,13 @@ pub fn validate_basic_msg(
     })
 }
 
+/// What `verify_params` verified about the message that is passed to the contract
+pub struct VerifiedParams {
+    /// The transaction info is only passed to the contract when it was signed by the sender
+    pub tx_info: Option<VerifiedTxInfo>,
+    pub funds: VerifiedFunds,
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn verify_params(
     sig_info: &SigInfo,
@@ -547,28 +882,75 @@ pub fn verify_params(
     sender: &CanonicalAddr,
     contract_address: &HumanAddr,
     secret_msg: &SecretMessage,
-    should_verify_sig_info: bool,
-    should_verify_input: bool,
+    was_msg_encrypted: bool,
+    block_height: u64,
     verify_params_type: VerifyParamsType,
     current_admin: Option<&CanonicalAddr>,
     new_admin: Option<&CanonicalAddr>,
-) -> Result<(), EnclaveError> {
+    group_authority: &GroupAuthority,
+) -> Result<VerifiedParams, EnclaveError> {
+    let _span = trace_span("verify_params");
+    let profile = verification_profile(verify_params_type, block_height)?;
+    let should_verify_sig_info = profile.should_verify_sig_info(was_msg_encrypted);
+    let should_verify_input = profile.should_verify_input(was_msg_encrypted);
+
+    let mut funds = verify_funds_denoms(sent_funds)?;
+
     if should_verify_sig_info {
         debug!("Verifying message signatures for: {:?}", sig_info);
 
         if let Some(callback_sig) = &sig_info.callback_sig {
             // We return here if there's a callback signature.
             // The sender is another contract in the same transaction, so there aren't any signed_bytes to verify or tx_bytes to check in the signed block.
-            return verify_callback_sig(callback_sig.as_slice(), sender, secret_msg, sent_funds);
+            return verify_callback_sig(callback_sig.as_slice(), sender, secret_msg, sent_funds)
+                .map(|_| VerifiedParams {
+                    tx_info: None,
+                    funds,
+                });
+        }
+
+        // Group policies don't sign, the messages they send are verified through their proposals
+        if group_authority.is_group_policy(sender) {
+            let tx_info = verify_group_input(
+                sig_info,
+                &mut funds,
+                sender,
+                contract_address,
+                secret_msg,
+                block_height,
+                verify_params_type,
+                current_admin,
+                new_admin,
+                group_authority,
+            )?;
+            return Ok(VerifiedParams {
+                tx_info: Some(tx_info),
+                funds,
+            });
+        }
+
+        // The other messages of a transaction are verified with the same signatures
+        if is_signature_verified(sig_info, sender, block_height) {
+            trace!("signatures were already verified in this block");
+        } else {
+            verify_signature(sig_info, sender)?;
+            if is_active(Feature::AllSignersVerified) {
+                verify_other_signers(sig_info, sender)?;
+            }
+            record_verified_signature(sig_info, sender, block_height);
         }
 
-        verify_signature(sig_info, sender)?;
+        #[cfg(feature = "light-client-validation")]
+        if let Some(sequence) = signed_sequence(sig_info, sender) {
+            observe_signed_sequence(sender, sequence, block_height);
+        }
     }
 
+    let mut tx_info = None;
     if should_verify_input {
-        verify_input(
+        let verified_tx_info = verify_input(
             sig_info,
-            sent_funds,
+            &mut funds,
             sender,
             contract_address,
             secret_msg,
@@ -576,11 +958,14 @@ pub fn verify_params(
             current_admin,
             new_admin,
         )?;
+        if should_verify_sig_info {
+            tx_info = Some(verified_tx_info);
+        }
     }
 
     info!("Parameters verified successfully");
 
-    Ok(())
+    Ok(VerifiedParams { tx_info, funds })
 }
 
 fn verify_signature(sig_info: &SigInfo, sender: &CanonicalAddr) -> Result<(), EnclaveError> {
@@ -611,18 +996,98 @@ fn verify_signature(sig_info: &SigInfo, sender: &CanonicalAddr) -> Result<(), En
     Ok(())
 }
 
+/// Verifies the signatures of the signers of the transaction other than the sender, e.g. a fee
+/// payer, and that every message in the transaction was signed by its sender. Otherwise the host
+/// could pass off a transaction that was only partially signed.
+fn verify_other_signers(sig_info: &SigInfo, sender: &CanonicalAddr) -> Result<(), EnclaveError> {
+    let tx_raw = cosmos_proto::tx::tx::TxRaw::parse_from_bytes(sig_info.tx_bytes.as_slice())
+        .map_err(|err| {
+            warn!("failed to parse TxRaw from tx_bytes: {:?}", err);
+            EnclaveError::FailedTxVerification
+        })?;
+    let auth_info = AuthInfo::from_bytes(&tx_raw.auth_info_bytes)?;
+    let signers: Vec<CanonicalAddr> = auth_info
+        .signer_infos
+        .iter()
+        .map(|signer_info| signer_info.public_key.get_address())
+        .collect();
+
+    let sender_index = signers
+        .iter()
+        .position(|signer| signer == sender)
+        .ok_or_else(|| {
+            warn!("Couldn't find message sender in the signers of the transaction");
+            EnclaveError::FailedTxVerification
+        })?;
+    if signers.len() != sig_info.other_signers.len() + 1 {
+        warn!(
+            "Transaction has {} signers but {} signatures were provided",
+            signers.len(),
+            sig_info.other_signers.len() + 1
+        );
+        return Err(EnclaveError::FailedTxVerification);
+    }
+
+    let other_signers = signers
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != sender_index)
+        .map(|(_, signer)| signer);
+    for (signer, signer_sig_info) in other_signers.zip(&sig_info.other_signers) {
+        let signer_sig_info = SigInfo {
+            sign_bytes: signer_sig_info.sign_bytes.clone(),
+            sign_mode: signer_sig_info.sign_mode,
+            public_key: signer_sig_info.public_key.clone(),
+            signature: signer_sig_info.signature.clone(),
+            callback_sig: None,
+            other_signers: vec![],
+            ..sig_info.clone()
+        };
+        verify_signature(&signer_sig_info, signer)?;
+
+        // The signer must have signed this transaction and not another one
+        let (sdk_messages, _) = get_signed_tx_from_sign_bytes(&signer_sig_info)?;
+        verify_tx_bytes(&signer_sig_info, &sdk_messages)?;
+    }
+
+    let sdk_messages = TxBody::from_bytes(&tx_raw.body_bytes)?.messages;
+    if let Some(msg_sender) = unsigned_msg_sender(&sdk_messages, &signers) {
+        warn!("Message sender verification failed!");
+        trace!(
+            "Message sender {:?} is not one of the signers {:?}",
+            msg_sender,
+            signers
+        );
+        return Err(EnclaveError::FailedTxVerification);
+    }
+
+    Ok(())
+}
+
+/// Returns the sender of a message that isn't one of the signers, if there is one. Messages
+/// without a sender, like the core IBC messages, are signed by whoever relays them.
+fn unsigned_msg_sender<'a>(
+    sdk_messages: &'a [DirectSdkMsg],
+    signers: &[CanonicalAddr],
+) -> Option<&'a CanonicalAddr> {
+    sdk_messages
+        .iter()
+        .filter_map(|sdk_msg| sdk_msg.sender())
+        .find(|msg_sender| !signers.contains(msg_sender))
+}
+
 #[allow(clippy::too_many_arguments)]
 fn verify_input(
     sig_info: &SigInfo,
-    sent_funds: &[Coin],
+    funds: &mut VerifiedFunds,
     sender: &CanonicalAddr,
     contract_address: &HumanAddr,
     secret_msg: &SecretMessage,
     verify_params_types: VerifyParamsType,
     current_admin: Option<&CanonicalAddr>,
     new_admin: Option<&CanonicalAddr>,
-) -> Result<(), EnclaveError> {
-    let sdk_messages = get_sdk_messages_from_sign_bytes(sig_info)?;
+) -> Result<VerifiedTxInfo, EnclaveError> {
+    let (sdk_messages, tx_info) = get_signed_tx_from_sign_bytes(sig_info)?;
 
     verify_tx_bytes(sig_info, &sdk_messages)?;
 
@@ -631,7 +1096,7 @@ fn verify_input(
         sig_info,
         &sdk_messages,
         sender,
-        sent_funds,
+        funds,
         contract_address,
         secret_msg,
         verify_params_types,
@@ -644,7 +1109,203 @@ fn verify_input(
         return Err(EnclaveError::FailedTxVerification);
     }
 
-    Ok(())
+    Ok(tx_info)
+}
+
+/// Verifies a message that a group policy sends through one of its proposals. The policy doesn't
+/// sign, so the transaction must have a `MsgExec` of the proposal, signed by its executor, and the
+/// message must be one of the proven messages of the proposal.
+#[allow(clippy::too_many_arguments)]
+fn verify_group_input(
+    sig_info: &SigInfo,
+    funds: &mut VerifiedFunds,
+    sender: &CanonicalAddr,
+    contract_address: &HumanAddr,
+    secret_msg: &SecretMessage,
+    block_height: u64,
+    verify_params_types: VerifyParamsType,
+    current_admin: Option<&CanonicalAddr>,
+    new_admin: Option<&CanonicalAddr>,
+    group_authority: &GroupAuthority,
+) -> Result<VerifiedTxInfo, EnclaveError> {
+    let (sdk_messages, tx_info) = get_signed_tx_from_sign_bytes(sig_info)?;
+
+    let (executor, proposal) = sdk_messages
+        .iter()
+        .find_map(|msg| match msg {
+            DirectSdkMsg::MsgGroupExec {
+                proposal_id,
+                executor,
+            } => group_authority
+                .proposal_of(*proposal_id, sender)
+                .map(|proposal| (executor, proposal)),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            warn!("Couldn't find a signed MsgExec of a proposal of the group policy");
+            EnclaveError::FailedTxVerification
+        })?;
+
+    if !is_signature_verified(sig_info, executor, block_height) {
+        verify_signature(sig_info, executor)?;
+        if is_active(Feature::AllSignersVerified) {
+            verify_other_signers(sig_info, executor)?;
+        }
+        record_verified_signature(sig_info, executor, block_height);
+    }
+
+    #[cfg(feature = "light-client-validation")]
+    if let Some(sequence) = signed_sequence(sig_info, executor) {
+        observe_signed_sequence(executor, sequence, block_height);
+    }
+
+    verify_tx_bytes(sig_info, &sdk_messages)?;
+
+    let is_verified = verify_input_params(
+        #[cfg(feature = "light-client-validation")]
+        sig_info,
+        &proposal.messages,
+        sender,
+        funds,
+        contract_address,
+        secret_msg,
+        verify_params_types,
+        current_admin,
+        new_admin,
+    )?;
+
+    if !is_verified {
+        warn!(
+            "Parameter verification of group proposal {} failed",
+            proposal.id
+        );
+        return Err(EnclaveError::FailedTxVerification);
+    }
+
+    Ok(tx_info)
+}
+
+/// Returns the signed packet of the outgoing transfer whose ack or timeout is passed to an
+/// ibc-hooks callback. `verify_params` must have already verified the message against it.
+pub fn get_outgoing_transfer_packet(
+    sig_info: &SigInfo,
+    secret_msg: &SecretMessage,
+    handle_type: HandleType,
+) -> Result<Packet, EnclaveError> {
+    let (sdk_messages, _) = get_signed_tx_from_sign_bytes(sig_info)?;
+
+    for sdk_msg in &sdk_messages {
+        let is_verified = match (sdk_msg, handle_type) {
+            (
+                DirectSdkMsg::MsgAcknowledgement {
+                    packet,
+                    acknowledgement,
+                    ..
+                },
+                HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK,
+            ) => verify_ibc_wasm_hooks_outgoing_transfer_ack(secret_msg, packet, acknowledgement),
+            (
+                DirectSdkMsg::MsgTimeout { packet, .. },
+                HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT,
+            ) => verify_ibc_wasm_hooks_outgoing_transfer_timeout(secret_msg, packet),
+            _ => false,
+        };
+
+        if is_verified {
+            if let DirectSdkMsg::MsgAcknowledgement { packet, .. }
+            | DirectSdkMsg::MsgTimeout { packet, .. } = sdk_msg
+            {
+                return Ok(packet.clone());
+            }
+        }
+    }
+
+    warn!("couldn't find the signed packet of the ibc-hooks callback");
+    Err(EnclaveError::FailedTxVerification)
+}
+
+/// Whether a packet's timeout elapsed. Like in ibc-go, a timeout by height is only accepted with
+/// a proof from a counterparty height at or after it. The counterparty's time isn't known to the
+/// enclave, so a timeout by time is checked against the verified time of the current block,
+/// allowing for the clocks of the chains to drift apart.
+fn ibc_timeout_elapsed(
+    timeout_height: Option<&Height>,
+    timeout_timestamp: u64,
+    proof_height: Option<&Height>,
+    block_time: u64,
+) -> bool {
+    let elapsed_by_height = match (timeout_height, proof_height) {
+        (Some(timeout_height), Some(proof_height)) => proof_height.is_at_least(timeout_height),
+        _ => false,
+    };
+    let elapsed_by_time = timeout_timestamp != 0
+        && block_time.saturating_add(MAX_COUNTERPARTY_CLOCK_DRIFT_NANOS) >= timeout_timestamp;
+
+    elapsed_by_height || elapsed_by_time
+}
+
+/// Verifies that the signed packet that is passed to the contract as timed out actually timed
+/// out, so that the host can't make contracts handle a timeout before it happened.
+/// `verify_params` must have already verified the message against the packet.
+pub fn verify_ibc_packet_timeout_elapsed(
+    sig_info: &SigInfo,
+    secret_msg: &SecretMessage,
+    handle_type: HandleType,
+    block_time: u64,
+) -> Result<(), EnclaveError> {
+    let (sdk_messages, _) = get_signed_tx_from_sign_bytes(sig_info)?;
+
+    for sdk_msg in &sdk_messages {
+        if let DirectSdkMsg::MsgTimeout {
+            packet,
+            timeout_height,
+            timeout_timestamp,
+            proof_height,
+            signer,
+            ..
+        } = sdk_msg
+        {
+            let is_verified = match handle_type {
+                HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT => verify_ibc_packet_timeout(
+                    secret_msg,
+                    packet,
+                    timeout_height,
+                    *timeout_timestamp,
+                    signer,
+                ),
+                HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT => {
+                    verify_ibc_wasm_hooks_outgoing_transfer_timeout(secret_msg, packet)
+                }
+                _ => false,
+            };
+            if !is_verified {
+                continue;
+            }
+
+            if ibc_timeout_elapsed(
+                timeout_height.as_ref(),
+                *timeout_timestamp,
+                proof_height.as_ref(),
+                block_time,
+            ) {
+                return Ok(());
+            }
+
+            warn!(
+                "packet {} on channel {} didn't time out yet: timeout height {:?}, timeout timestamp {}, proof height {:?}, block time {}",
+                packet.sequence,
+                packet.source_channel,
+                timeout_height,
+                timeout_timestamp,
+                proof_height,
+                block_time
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    }
+
+    warn!("couldn't find the signed packet of the timeout");
+    Err(EnclaveError::FailedTxVerification)
 }
 
 fn get_signer(sign_info: &SigInfo, sender: &CanonicalAddr) -> Result<CosmosPubKey, EnclaveError> {
@@ -704,18 +1365,54 @@ fn get_signer(sign_info: &SigInfo, sender: &CanonicalAddr) -> Result<CosmosPubKe
     }
 }
 
-// extract sdk_messages from sign_bytes
+/// The sequence that the sender signed, to detect replays of old transactions. Only the sign
+/// modes whose sign bytes are plain sign docs are supported.
+#[cfg(feature = "light-client-validation")]
+fn signed_sequence(sign_info: &SigInfo, sender: &CanonicalAddr) -> Option<u64> {
+    use cosmos_proto::tx::signing::SignMode::*;
+
+    match sign_info.sign_mode {
+        SIGN_MODE_DIRECT => SignDoc::from_bytes(sign_info.sign_bytes.as_slice())
+            .ok()?
+            .auth_info
+            .signer_infos
+            .iter()
+            .find(|signer_info| &signer_info.public_key.get_address() == sender)
+            .map(|signer_info| signer_info.sequence),
+        SIGN_MODE_LEGACY_AMINO_JSON => {
+            serde_json::from_slice::<StdSignDoc>(sign_info.sign_bytes.as_slice())
+                .ok()?
+                .sequence
+                .parse()
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+// extract sdk_messages and the verified transaction info from sign_bytes
 // sign_byte might be in Amino format
-fn get_sdk_messages_from_sign_bytes(
+fn get_signed_tx_from_sign_bytes(
     sign_info: &SigInfo,
-) -> Result<Vec<DirectSdkMsg>, EnclaveError> {
+) -> Result<(Vec<DirectSdkMsg>, VerifiedTxInfo), EnclaveError> {
     use cosmos_proto::tx::signing::SignMode::*;
     match sign_info.sign_mode {
         SIGN_MODE_DIRECT => {
             let sign_doc = SignDoc::from_bytes(sign_info.sign_bytes.as_slice())?;
             trace!("direct sign doc: {:?}", sign_doc);
 
-            Ok(sign_doc.body.messages)
+            let fee_payer = sign_doc.auth_info.fee_payer()?;
+            let fee = VerifiedFee {
+                amount: sign_doc.auth_info.fee.amount,
+                gas_limit: sign_doc.auth_info.fee.gas_limit,
+            };
+            let tx_info = VerifiedTxInfo {
+                memo: sign_doc.body.memo,
+                fee_payer: HumanAddr::from_canonical(&fee_payer).ok(),
+                fee: Some(fee),
+                oracle_proofs: sign_doc.body.oracle_proofs,
+            };
+            Ok((sign_doc.body.messages, tx_info))
         }
         SIGN_MODE_LEGACY_AMINO_JSON => {
             let sign_doc: StdSignDoc = serde_json::from_slice(sign_info.sign_bytes.as_slice())
@@ -724,12 +1421,19 @@ fn get_sdk_messages_from_sign_bytes(
                     EnclaveError::FailedTxVerification
                 })?;
             trace!("amino sign doc: {:?}", sign_doc);
-            let messages: Result<Vec<DirectSdkMsg>, _> = sign_doc
+            let messages: Vec<DirectSdkMsg> = sign_doc
                 .msgs
                 .iter()
                 .map(|x| x.clone().into_direct_msg())
-                .collect();
-            Ok(messages?)
+                .collect::<Result<_, _>>()?;
+            let tx_info = VerifiedTxInfo {
+                fee_payer: amino_fee_payer(&sign_doc.fee, &messages),
+                fee: amino_fee(&sign_doc.fee),
+                memo: sign_doc.memo,
+                // Amino sign docs have no extension options
+                oracle_proofs: vec![],
+            };
+            Ok((messages, tx_info))
         }
         SIGN_MODE_EIP_191 => {
             let sign_bytes_as_string = String::from_utf8_lossy(&sign_info.sign_bytes.0).to_string();
@@ -763,12 +1467,19 @@ fn get_sdk_messages_from_sign_bytes(
 
             trace!("eip191 sign doc: {:?}", sign_doc);
 
-            let messages: Result<Vec<DirectSdkMsg>, _> = sign_doc
+            let messages: Vec<DirectSdkMsg> = sign_doc
                 .msgs
                 .iter()
                 .map(|x| x.clone().into_direct_msg())
-                .collect();
-            Ok(messages?)
+                .collect::<Result<_, _>>()?;
+            let tx_info = VerifiedTxInfo {
+                fee_payer: amino_fee_payer(&sign_doc.fee, &messages),
+                fee: amino_fee(&sign_doc.fee),
+                memo: sign_doc.memo,
+                // Amino sign docs have no extension options
+                oracle_proofs: vec![],
+            };
+            Ok((messages, tx_info))
         }
         _ => {
             warn!(
@@ -780,11 +1491,67 @@ fn get_sdk_messages_from_sign_bytes(
     }
 }
 
+/// Amino sign docs don't list the signers, so the fee payer can only be verified against the
+/// senders of the messages the enclave can parse
+fn amino_fee_payer(fee: &StdFee, messages: &[DirectSdkMsg]) -> Option<HumanAddr> {
+    let fee_payer = match &fee.payer {
+        Some(payer) => {
+            let payer = CanonicalAddr::from_human(payer).ok()?;
+            if !messages.iter().any(|msg| msg.sender() == Some(&payer)) {
+                warn!("Couldn't verify that the fee payer is one of the signers");
+                return None;
+            }
+            payer
+        }
+        None => messages.first()?.sender()?.clone(),
+    };
+
+    HumanAddr::from_canonical(&fee_payer).ok()
+}
+
+/// Amino sign docs encode the gas limit as a string
+fn amino_fee(fee: &StdFee) -> Option<VerifiedFee> {
+    let gas_limit = fee
+        .gas
+        .parse()
+        .map_err(|err| warn!("failed to parse the gas limit of the fee: {}", err))
+        .ok()?;
+
+    Some(VerifiedFee {
+        amount: fee.amount.clone(),
+        gas_limit,
+    })
+}
+
+/// Amino sign docs can't contain the messages that have no Amino encoding, like the core IBC
+/// messages, so they are parsed as `Other` from them. Such a placeholder matches any message
+/// without an Amino encoding in the same position of the transaction, but only the signed
+/// messages are verified against, so a message that isn't in the sign doc never is.
+fn amino_msgs_match_tx_msgs(
+    sdk_messages_from_sign_bytes: &[DirectSdkMsg],
+    sdk_messages_from_tx_bytes: &[DirectSdkMsg],
+) -> bool {
+    sdk_messages_from_sign_bytes.len() == sdk_messages_from_tx_bytes.len()
+        && sdk_messages_from_sign_bytes
+            .iter()
+            .zip(sdk_messages_from_tx_bytes)
+            .all(|(signed_msg, tx_msg)| {
+                signed_msg == tx_msg
+                    || (*signed_msg == DirectSdkMsg::Other && !tx_msg.has_amino_encoding())
+            })
+}
+
 /// in order to use tx_bytes in the light client verification, we need to verify tx_bytes against sign_bytes which is verified against the sender's signature
+///
+/// With Amino, the transaction may also have messages that couldn't be signed, see
+/// `amino_msgs_match_tx_msgs`. They don't fail the verification of the signed messages, but they
+/// are never taken from tx_bytes, which aren't signed.
 fn verify_tx_bytes(
     sig_info: &SigInfo,
-    sdk_messages_from_sign_bytes: &Vec<DirectSdkMsg>,
+    sdk_messages_from_sign_bytes: &[DirectSdkMsg],
 ) -> Result<(), EnclaveError> {
+    use cosmos_proto::tx::signing::SignMode::*;
+
     trace!("Verifying tx_bytes against sign_bytes...");
 
     let tx_raw_from_tx_bytes = cosmos_proto::tx::tx::TxRaw::parse_from_bytes(
@@ -797,7 +1564,12 @@ fn verify_tx_bytes(
 
     let sdk_messages_from_tx_bytes = TxBody::from_bytes(&tx_raw_from_tx_bytes.body_bytes)?.messages;
 
-    let is_verified = sdk_messages_from_sign_bytes == &sdk_messages_from_tx_bytes;
+    let is_verified = match sig_info.sign_mode {
+        SIGN_MODE_LEGACY_AMINO_JSON | SIGN_MODE_EIP_191 if is_active(Feature::AminoIbcMessages) => {
+            amino_msgs_match_tx_msgs(sdk_messages_from_sign_bytes, &sdk_messages_from_tx_bytes)
+        }
+        _ => sdk_messages_from_sign_bytes == sdk_messages_from_tx_bytes.as_slice(),
+    };
 
     if is_verified {
         Ok(())
@@ -863,7 +1635,7 @@ fn verify_input_params(
     #[cfg(feature = "light-client-validation")] sig_info: &SigInfo,
     sdk_messages: &[DirectSdkMsg],
     sender: &CanonicalAddr,
-    sent_funds: &[Coin],
+    funds: &mut VerifiedFunds,
     contract_address: &HumanAddr,
     sent_wasm_input: &SecretMessage,
     verify_params_types: VerifyParamsType,
@@ -914,10 +1686,241 @@ fn verify_input_params(
     }
 
     info!("Verifying sent funds...");
-    if !verify_sent_funds(sdk_msg, sent_funds) {
+    if !verify_sent_funds(sdk_msg, funds) {
         warn!("Funds verification failed!");
         return Ok(false);
     }
 
     Ok(true)
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cw_types_v010::encoding::Binary;
+
+    #[cfg(feature = "light-client-validation")]
+    pub fn test_deliver_tx_is_derived_from_the_verified_block() {
+        use cosmos_proto::tx::tx::{Tx, TxBody};
+        use protobuf::well_known_types::Any;
+
+        let height = 1_000_000;
+        let msg = b"message of the verified block".to_vec();
+        let tx_bytes = [&b"tx with the "[..], &msg, b" in it"].concat();
+
+        let mut any = Any::new();
+        any.value = msg;
+        let mut body = TxBody::new();
+        body.messages.push(any);
+        let mut tx = Tx::new();
+        tx.set_body(body);
+        {
+            let mut verified_msgs = VERIFIED_BLOCK_MESSAGES.lock().unwrap();
+            verified_msgs.clear();
+            verified_msgs.set_block_info(height, 0, vec![], vec![]);
+            verified_msgs.append_msg_from_tx(tx);
+        }
+        {
+            let mut block_gas = BLOCK_GAS.lock().unwrap();
+            assert!(block_gas.begin(height));
+            assert!(block_gas.set_max_gas(height, 100));
+        }
+
+        assert!(!is_delivered_tx(height, &tx_bytes, false));
+        assert!(check_tx_in_current_block(&tx_bytes));
+
+        // The host cleared its flag, and the execution is counted in the gas of the block anyway
+        let deliver_tx = is_delivered_tx(height, &tx_bytes, false);
+        assert!(deliver_tx);
+        check_block_gas(height, deliver_tx).unwrap();
+        use_block_gas(height, deliver_tx, 1_000).unwrap();
+        assert_eq!(BLOCK_GAS.lock().unwrap().used(), 1_000);
+
+        // Other transactions and blocks only have the flag of the host
+        assert!(!is_delivered_tx(height, b"another tx", false));
+        assert!(is_delivered_tx(height, b"another tx", true));
+        assert!(!is_delivered_tx(height + 1, &tx_bytes, false));
+    }
+
+    pub fn test_historical_block_state() {
+        let app_hash = [7u8; HASH_SIZE];
+
+        // Refused unless both the block and the next one were verified
+        assert!(historical_block_state(10, None, None).is_err());
+        assert!(historical_block_state(10, Some(1_000), None).is_err());
+        assert!(historical_block_state(10, None, Some(&app_hash)).is_err());
+        assert!(historical_block_state(10, Some(-1), Some(&app_hash)).is_err());
+
+        assert_eq!(
+            historical_block_state(10, Some(1_000), Some(&app_hash)).unwrap(),
+            (1_000, app_hash.to_vec())
+        );
+    }
+
+    pub fn test_ibc_timeout_elapsed() {
+        let height = |revision_number, revision_height| Height {
+            revision_number,
+            revision_height,
+        };
+        let second = 1_000_000_000;
+        let block_time = 1_700_000_000 * second;
+
+        // By height, only with a proof at or after the timeout height
+        assert!(ibc_timeout_elapsed(
+            Some(&height(1, 100)),
+            0,
+            Some(&height(1, 100)),
+            block_time
+        ));
+        assert!(ibc_timeout_elapsed(
+            Some(&height(1, 100)),
+            0,
+            Some(&height(2, 1)),
+            block_time
+        ));
+        assert!(!ibc_timeout_elapsed(
+            Some(&height(1, 100)),
+            0,
+            Some(&height(1, 99)),
+            block_time
+        ));
+        assert!(!ibc_timeout_elapsed(
+            Some(&height(1, 100)),
+            0,
+            None,
+            block_time
+        ));
+
+        // By time, allowing for clock drift
+        assert!(ibc_timeout_elapsed(None, block_time, None, block_time));
+        assert!(ibc_timeout_elapsed(
+            None,
+            block_time + MAX_COUNTERPARTY_CLOCK_DRIFT_NANOS,
+            None,
+            block_time
+        ));
+        assert!(!ibc_timeout_elapsed(
+            None,
+            block_time + MAX_COUNTERPARTY_CLOCK_DRIFT_NANOS + 1,
+            None,
+            block_time
+        ));
+
+        // Either is enough
+        assert!(ibc_timeout_elapsed(
+            Some(&height(1, 100)),
+            block_time,
+            Some(&height(1, 50)),
+            block_time
+        ));
+
+        // A packet without a timeout never times out
+        assert!(!ibc_timeout_elapsed(
+            None,
+            0,
+            Some(&height(1, 100)),
+            block_time
+        ));
+    }
+
+    pub fn test_amino_msgs_match_tx_msgs() {
+        let clear_admin = DirectSdkMsg::MsgClearAdmin {
+            sender: CanonicalAddr(Binary(vec![1u8; 20])),
+            contract: HumanAddr("secret1contract".to_string()),
+        };
+        let recv_packet = DirectSdkMsg::MsgRecvPacket {
+            packet: Packet {
+                sequence: 1,
+                source_port: "transfer".to_string(),
+                source_channel: "channel-0".to_string(),
+                destination_port: "transfer".to_string(),
+                destination_channel: "channel-1".to_string(),
+                data: vec![],
+            },
+            proof_commitment: vec![],
+            proof_height: None,
+            signer: "secret1relayer".to_string(),
+        };
+
+        let signed = [clear_admin.clone(), DirectSdkMsg::Other];
+        assert!(amino_msgs_match_tx_msgs(
+            &signed,
+            &[clear_admin.clone(), recv_packet.clone()]
+        ));
+        assert!(amino_msgs_match_tx_msgs(
+            &signed,
+            &[clear_admin.clone(), DirectSdkMsg::Other]
+        ));
+
+        // Placeholders can't stand for messages that can be signed with Amino
+        assert!(!amino_msgs_match_tx_msgs(
+            &[DirectSdkMsg::Other, DirectSdkMsg::Other],
+            &[clear_admin.clone(), recv_packet.clone()]
+        ));
+        assert!(!amino_msgs_match_tx_msgs(
+            &signed,
+            &[recv_packet.clone(), clear_admin.clone()]
+        ));
+        assert!(!amino_msgs_match_tx_msgs(&signed, &[clear_admin]));
+    }
+
+    pub fn test_unsigned_msg_sender() {
+        let clear_admin = |sender: u8| DirectSdkMsg::MsgClearAdmin {
+            sender: CanonicalAddr(Binary(vec![sender; 20])),
+            contract: HumanAddr("secret1contract".to_string()),
+        };
+        let signers = [
+            CanonicalAddr(Binary(vec![1u8; 20])),
+            CanonicalAddr(Binary(vec![2u8; 20])),
+        ];
+
+        assert_eq!(
+            unsigned_msg_sender(&[clear_admin(1), clear_admin(2)], &signers),
+            None
+        );
+        // Messages without a sender don't need a signer
+        assert_eq!(
+            unsigned_msg_sender(&[clear_admin(2), DirectSdkMsg::Other], &signers[1..]),
+            None
+        );
+        assert_eq!(
+            unsigned_msg_sender(&[clear_admin(1), clear_admin(3)], &signers),
+            Some(&CanonicalAddr(Binary(vec![3u8; 20])))
+        );
+        assert_eq!(
+            unsigned_msg_sender(&[clear_admin(1), clear_admin(2)], &signers[..1]),
+            Some(&signers[1])
+        );
+    }
+
+    pub fn test_reply_payload_headers() {
+        let hash = "ab".repeat(HASH_SIZE);
+        let mut msg = REPLY_ENCRYPTION_MAGIC_BYTES.to_vec();
+        msg.extend_from_slice(&7u64.to_be_bytes());
+        msg.extend_from_slice(hash.as_bytes());
+        msg.extend_from_slice(REPLY_PAYLOAD_MAGIC_BYTES);
+        msg.extend_from_slice(&3u32.to_be_bytes());
+        msg.extend_from_slice(b"ctx");
+        msg.extend_from_slice(br#"{"count":{}}"#);
+
+        let mut rest =
+            msg[REPLY_ENCRYPTION_MAGIC_BYTES.len() + SIZE_OF_U64 + hash.len()..].to_vec();
+        assert_eq!(
+            reply_payload_section_len(&rest).unwrap(),
+            REPLY_PAYLOAD_MAGIC_BYTES.len() + SIZE_OF_U32 + 3
+        );
+        assert_eq!(take_reply_payload(&mut rest).unwrap(), b"ctx".to_vec());
+        assert_eq!(rest, br#"{"count":{}}"#.to_vec());
+
+        // Headers without a payload are left as is
+        assert!(take_reply_payload(&mut rest).unwrap().is_empty());
+        assert_eq!(rest, br#"{"count":{}}"#.to_vec());
+
+        // A payload that is longer than the message
+        let mut truncated = REPLY_PAYLOAD_MAGIC_BYTES.to_vec();
+        truncated.extend_from_slice(&10u32.to_be_bytes());
+        truncated.extend_from_slice(b"ctx");
+        assert!(take_reply_payload(&mut truncated).is_err());
+    }
+}
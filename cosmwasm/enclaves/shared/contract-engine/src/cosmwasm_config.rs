@@ -7,6 +7,159 @@ pub mod api_marker {
 
 pub mod features {
     pub const RANDOM: &str = "requires_random";
+    pub const IBC_EXPORT_PREFIX: &str = "ibc_";
+
+    /// Names of the features contracts can declare in their features section.
+    /// Other features (e.g. "iterator") are not supported by the enclave yet.
+    pub mod declared {
+        pub const RANDOM: &str = "random";
+        pub const IBC: &str = "ibc";
+        pub const COARSE_PADDING: &str = "coarse_padding";
+        pub const CONSTANT_SHAPE_EXECUTE: &str = "constant_shape_execute";
+        pub const CONSTANT_SHAPE_REPLY: &str = "constant_shape_reply";
+        pub const CONSTANT_SHAPE_QUERY: &str = "constant_shape_query";
+        pub const PADDED_STORAGE_ACCESS: &str = "padded_storage_access";
+        pub const ORDERED_KEYS: &str = "ordered_keys";
+        pub const NORMALIZED_OUTPUT_V1: &str = "normalized_output_v1";
+    }
+}
+
+/// Bucket sizes (in bytes) that encrypted result fields (query results, errors, `data` and IBC
+/// acknowledgements) are padded to before encryption, so that the ciphertext length only reveals
+/// the bucket and not the exact plaintext length.
+/// Plaintexts larger than the biggest bucket are padded to a multiple of `LARGE_OUTPUT_MULTIPLE`.
+/// These values are part of consensus and must be identical on all nodes.
+pub mod output_padding {
+    pub const BUCKET_SIZES: &[usize] = &[64, 128, 256, 512, 1024, 2048, 4096, 8192];
+    pub const LARGE_OUTPUT_MULTIPLE: usize = 8192;
+    /// The buckets of contracts that declare the `coarse_padding` feature. Fewer buckets reveal
+    /// less about the outputs, e.g. the number of digits of a balance, at the cost of the gas
+    /// charged for the extra padding.
+    pub const COARSE_BUCKET_SIZES: &[usize] = &[256, 1024, 4096, 16384];
+    pub const COARSE_LARGE_OUTPUT_MULTIPLE: usize = 16384;
+    /// The largest bucket, or multiple of large outputs, that the compute params can set
+    pub const MAX_BUCKET_SIZE: usize = 1024 * 1024;
+    /// The byte used for padding. Whitespace is ignored by JSON parsers, and clients are
+    /// expected to trim it from non-JSON (e.g. base64) results after decryption.
+    pub const PADDING_BYTE: u8 = b' ';
+}
+
+/// The shape of the outputs of entry points that contracts declared a `constant_shape_*` feature
+/// for. Every successful output has a data field, no messages and exactly `ATTRIBUTE_COUNT`
+/// attributes: `CONTRACT_ATTRIBUTE_COUNT` encrypted attributes of fixed sizes, then the copies of
+/// the output for the auditor and for the designated viewer, each padded to `COPY_SIZE`. A copy is
+/// encrypted for the sender when the contract didn't set that key. The encrypted fields (data,
+/// errors and query results) are padded to `OUTPUT_SIZE`, so observers can't tell the branches
+/// the contract took apart by the shape of the output. The number of messages can't be padded,
+/// so like custom events they aren't allowed. Outputs that don't fit are rejected, except for
+/// errors and query results, which are padded to a multiple of `OUTPUT_SIZE`.
+/// These values are part of consensus and must be identical on all nodes.
+pub mod constant_shape {
+    pub const OUTPUT_SIZE: usize = 1024;
+    pub const ATTRIBUTE_COUNT: usize = 8;
+    pub const CONTRACT_ATTRIBUTE_COUNT: usize = ATTRIBUTE_COUNT - 2;
+    pub const ATTRIBUTE_KEY_SIZE: usize = 64;
+    pub const ATTRIBUTE_VALUE_SIZE: usize = 256;
+    pub const COPY_SIZE: usize = 4 * OUTPUT_SIZE;
+}
+
+/// The storage accesses of contracts that declare the `padded_storage_access` feature. After a
+/// successful execution the enclave makes dummy reads and writes, so that the host sees
+/// `READS` reads (cache misses) and `WRITES` flushed writes, or the next multiples of them for
+/// executions that access more keys. The dummies are charged like the contract's own accesses,
+/// and have the sizes of them, see `storage_padding`. Executions that made no accesses of a kind
+/// get dummies of the default sizes.
+/// These values are part of consensus and must be identical on all nodes.
+pub mod storage_access_padding {
+    pub const READS: u64 = 16;
+    pub const WRITES: u64 = 16;
+    pub const DEFAULT_KEY_SIZE: usize = 32;
+    pub const DEFAULT_VALUE_SIZE: usize = 64;
+}
+
+/// The path ORAM behind `oblivious_read`, `oblivious_write` and `oblivious_remove`. An access
+/// reads and rewrites the `TREE_HEIGHT + 1` buckets of a path and the metadata, so it costs
+/// about `TREE_HEIGHT + 2` (11) times the gas of a regular read followed by a write, and each
+/// stored entry takes about `BUCKET_SIZE` times the space of a value in the tree.
+/// These values are part of consensus and must be identical on all nodes.
+pub mod oblivious_storage {
+    pub const TREE_HEIGHT: u32 = 9;
+    /// Entries per bucket
+    pub const BUCKET_SIZE: usize = 4;
+    pub const BLOCK_VALUE_SIZE: usize = 128;
+    pub const MAX_ENTRIES: usize = 512;
+    /// Entries that didn't fit back into the tree after an access
+    pub const MAX_STASH: usize = 32;
+    /// The contract subkey that seeds the leaves of the entries
+    pub const SUBKEY_LABEL: &[u8] = b"\xffsecret_enclave/oblivious_storage";
+}
+
+/// Versions of the normalization applied to contract outputs before they are encrypted:
+/// - 0: outputs are used as the contract returned them
+/// - 1: attributes are sorted by key, events by type, and the coins of messages by denom.
+///   Messages themselves keep their order, as they are executed in that order.
+///
+/// Contracts opt into a version by declaring the `normalized_output_v<N>` feature, and the outputs
+/// of contracts that declare none are left as they are. `VERSION` is the highest version the
+/// enclave supports. This is part of consensus and must be identical on all nodes.
+pub mod output_normalization {
+    pub const VERSION: u8 = 1;
+}
+
+/// Attribute keys that may never be emitted in plaintext, i.e. in the outputs of messages that
+/// are not encrypted (such as IBC packets). Keys are compared case-insensitively.
+/// This is the default list, which governance can replace with the `plaintext_attribute_denylist`
+/// param of the compute module (see `io::PlaintextAttributeDenylist`).
+pub mod plaintext_attribute_denylist {
+    pub const DENIED_KEYS: &[&str] = &["amount", "recipient", "viewing_key", "memo"];
+}
+
+pub mod ibc {
+    /// How far the clock of a counterparty chain may be ahead of the verified time of the
+    /// current block, when checking that a packet timed out by its timestamp
+    pub const MAX_COUNTERPARTY_CLOCK_DRIFT_NANOS: u64 = 10 * 60 * 1_000_000_000;
+}
+
+/// Storage keys that are used by the enclave itself and can't be written or removed by contracts
+pub mod reserved_keys {
+    pub const PREFIX: &[u8] = b"\xffsecret_enclave/";
+    pub const EVENT_SCHEMA: &[u8] = b"\xffsecret_enclave/event_schema";
+    /// Followed by the hash of an outgoing transfer that asked for an ibc-hooks callback
+    pub const OUTGOING_TRANSFERS_PREFIX: &[u8] = b"\xffsecret_enclave/outgoing_transfers/";
+    /// Followed by the hash of a packet whose callback the contract processed, see
+    /// `ibc_callback_guard`
+    pub const PROCESSED_CALLBACKS_PREFIX: &[u8] = b"\xffsecret_enclave/processed_callbacks/";
+    pub const UPGRADE_POLICY: &[u8] = b"\xffsecret_enclave/upgrade_policy";
+    pub const MIGRATION_ANNOUNCEMENT: &[u8] = b"\xffsecret_enclave/migration_announcement";
+    /// The cursor of a migration that the contract checkpointed, see `migration_checkpoint`
+    pub const MIGRATION_CHECKPOINT: &[u8] = b"\xffsecret_enclave/migration_checkpoint";
+    pub const AUDITOR_KEY: &[u8] = b"\xffsecret_enclave/auditor_key";
+    pub const PENDING_REPLIES: &[u8] = b"\xffsecret_enclave/pending_replies";
+    /// Followed by the buckets and the metadata of the contract's oblivious storage
+    pub const OBLIVIOUS_STORAGE_PREFIX: &[u8] = b"\xffsecret_enclave/oblivious_storage/";
+    /// The namespaces that the contract ordered with `db_write_ordered`
+    pub const ORDERED_NAMESPACES: &[u8] = b"\xffsecret_enclave/ordered_namespaces";
+    /// The bytes the contract stores, see `storage_quota`
+    pub const STORAGE_USAGE: &[u8] = b"\xffsecret_enclave/storage_usage";
+    /// Followed by the height of a commitment made with `random_commit`
+    pub const RANDOM_COMMITMENTS_PREFIX: &[u8] = b"\xffsecret_enclave/random_commitments/";
+    /// Followed by the hash of an idempotency key that the contract remembers, see
+    /// `idempotency_keys`
+    pub const IDEMPOTENCY_KEYS_PREFIX: &[u8] = b"\xffsecret_enclave/idempotency_keys/";
+    /// Followed by the height an idempotency key was recorded at and its hash, the index of the
+    /// keys in the order they expire
+    pub const IDEMPOTENCY_EXPIRY_PREFIX: &[u8] = b"\xffsecret_enclave/idempotency_expiry/";
+    /// The first and the last entries of the expiry index of the idempotency keys
+    pub const IDEMPOTENCY_EXPIRY_CURSOR: &[u8] = b"\xffsecret_enclave/idempotency_expiry_cursor";
+    /// The contract key that the last migration replaced, see `previous_contract_key`
+    pub const PREVIOUS_CONTRACT_KEY: &[u8] = b"\xffsecret_enclave/previous_contract_key";
+    /// The last rotation of the state key, see `state_reencryption`. It's always encrypted with
+    /// the og contract key.
+    pub const STATE_KEY_ROTATION: &[u8] = b"\xffsecret_enclave/state_key_rotation";
+
+    pub fn is_reserved(key: &[u8]) -> bool {
+        key.starts_with(PREFIX)
+    }
 }
 
 /// Right now ContractOperation is used to detect queris and prevent state changes
@@ -38,3 +191,117 @@ impl ContractOperation {
 }
 
 //pub const MAX_LOG_LENGTH: usize = 8192;
+
+/// The modules of system contracts, which are cached apart from the LRU module cache. Once the
+/// limit is reached, the modules of further system codes go to the LRU cache like any other.
+pub mod system_modules {
+    pub const MAX_SYSTEM_MODULES: usize = 16;
+}
+
+/// Limits of the failures to decrypt the queries of a client's connection, see
+/// `decryption_limit`
+pub mod decryption_limit {
+    pub const WINDOW_SECS: u64 = 60;
+    /// Queries are refused once their connection failed more often than this in the window
+    pub const MAX_FAILURES_PER_WINDOW: usize = 32;
+    pub const MAX_TRACKED_KEYS: usize = 10_000;
+}
+
+/// Limits of re-encrypting the state of a contract to a new state key, which the chain does in
+/// chunks across blocks
+pub mod state_reencryption {
+    pub const MAX_ENTRIES_PER_CHUNK: usize = 1_000;
+}
+
+/// Exporting the state entries of a user, which both the user and the admin of the contract
+/// have to sign
+pub mod state_export {
+    pub const MAX_EXPORTED_KEYS: usize = 1_000;
+    /// The type of the message in the signed permit
+    pub const PERMIT_MSG_TYPE: &str = "export_user_state";
+}
+
+/// Limits of the calls a coordinator contract can make with `execute_contracts_atomic`
+pub mod atomic_calls {
+    pub const MAX_CALLS_PER_EXECUTION: usize = 16;
+}
+
+/// Contracts with an auditor, which they set with `set_auditor_key` while they are instantiated,
+/// emit a copy of each output encrypted for the auditor under this plaintext attribute
+pub mod auditing {
+    pub const AUDIT_OUTPUT_ATTRIBUTE: &str = "audit_output";
+}
+
+/// Contracts that designate a viewer with `designate_viewer` emit a copy of the output of the
+/// execution, encrypted for the viewer, under this plaintext attribute
+pub mod designated_viewer {
+    pub const VIEWER_OUTPUT_ATTRIBUTE: &str = "viewer_output";
+}
+
+/// Clients that put `MAGIC_BYTES` in front of their encrypted messages get a summary of the
+/// capabilities the contract exercised under this attribute, encrypted for them
+pub mod capability_summary {
+    pub const MAGIC_BYTES: &[u8] = b"\xffcapability_summary/";
+    pub const SUMMARY_ATTRIBUTE: &str = "capability_summary";
+}
+
+/// The cache of answers to contract queries, which an execution shares with the queries nested in
+/// it. The execution passes its scope to nested queries by prepending `SCOPE_PREFIX` and the ID of
+/// the scope to their encrypted messages.
+pub mod query_cache {
+    pub const SCOPE_PREFIX: &[u8] = b"\xffquery_cache/";
+    pub const MAX_CACHED_BYTES_PER_EXECUTION: usize = 4 * 1024 * 1024;
+    /// The size of the results of the queries the node served in a block, see
+    /// `query_result_cache`
+    pub const MAX_CACHED_QUERY_RESULT_BYTES: usize = 8 * 1024 * 1024;
+    pub const MAX_CACHED_QUERY_RESULT_SIZE: usize = 256 * 1024;
+}
+
+/// The entries contracts keep in the memory of the enclave for the rest of a transaction with
+/// `transient_write`
+pub mod transient_storage {
+    /// The size of the keys and values of all contracts in a transaction
+    pub const MAX_TRANSIENT_BYTES_PER_TX: usize = 1024 * 1024;
+}
+
+/// The intents of the writes that executions flushed to the host, which the host has to
+/// acknowledge once it committed them, see `write_intents`
+pub mod write_intents {
+    /// Older intents are dropped, and reported as unacknowledged, past this many. The host
+    /// acknowledges the intents of a block once it's committed, so this bounds the executions
+    /// of a block that are tracked.
+    pub const MAX_PENDING_WRITE_INTENTS: usize = 4096;
+}
+
+/// The keys that clients put in front of their encrypted messages, so that a duplicate of an
+/// execution is answered instead of executed again, see `idempotency_keys`
+pub mod idempotency_keys {
+    pub const MAGIC_BYTES: &[u8] = b"\xffidempotency_key/";
+    pub const MAX_KEY_SIZE: usize = 64;
+    /// About a day of blocks
+    pub const WINDOW_BLOCKS: u64 = 14_400;
+    /// How many expired keys an execution that records a key removes at most, more than the one
+    /// it records so that the removals catch up
+    pub const SWEPT_KEYS_PER_EXECUTION: usize = 2;
+    /// The attribute of the output of a duplicate execution
+    pub const ALREADY_EXECUTED_ATTRIBUTE: &str = "already_executed";
+}
+
+/// The versions of the envelopes of encrypted messages, see `types::EnvelopeVersion`. Versioned
+/// envelopes start with `VERSIONED_MAGIC` and the version. Envelopes of the original version start
+/// with their random nonce instead, which is only the same by chance.
+pub mod io_envelope {
+    pub const VERSIONED_MAGIC: &[u8] = b"\xffsecret_envelope/";
+    pub const VERSION_AES_SIV: u8 = 1;
+    pub const VERSION_XCHACHA20_POLY1305: u8 = 2;
+    /// Derives the XChaCha20-Poly1305 key of an envelope from its AES-SIV key
+    pub const XCHACHA20_POLY1305_KEY_INFO: &[u8] = b"xchacha20poly1305";
+}
+
+/// Where the chain keeps the storage of contracts, which proofs of reads of it refer to
+pub mod state_layout {
+    /// The name of the store of the compute module in the multistore
+    pub const COMPUTE_STORE_NAME: &[u8] = b"compute";
+    /// The prefix of the keys of the storage of a contract, followed by its address
+    pub const CONTRACT_STORE_PREFIX: u8 = 0x03;
+}
@@ -0,0 +1,244 @@
+//! Microbenchmarks of the crypto host functions, so that governance proposals that reprice them,
+//! or price new curves and hashes, are grounded in measurements from reference hardware.
+//!
+//! Each benchmark times the crypto operation of a host function on fixed inputs, without the
+//! reads and writes of wasm memory around it. The measured times are turned into gas at the rate
+//! of a reference entry of the cost table, `external_ed25519_verify`, so that the suggested costs
+//! stay on the scale of the default table.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::Instant;
+use std::untrusted::time::InstantEx;
+
+use log::*;
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+use serde::Serialize;
+
+use enclave_crypto::sha_256;
+use enclave_ffi_types::EnclaveError;
+
+use crate::gas::WasmCosts;
+
+/// Bounds the time the calibration ecall can keep the enclave busy
+pub const MAX_CALIBRATION_ITERATIONS: u32 = 10_000;
+
+/// The entry of the cost table that the other costs are suggested relative to
+const REFERENCE_COST: &str = "external_ed25519_verify";
+
+/// The number of signatures in the larger batch passed to `ed25519_batch_verify`. The cost of
+/// each signature is the difference with a batch of one.
+const BATCH_SIZE: u32 = 8;
+
+#[derive(Serialize)]
+struct Measurement {
+    cost: &'static str,
+    nanos_per_call: u64,
+    current_cost: u32,
+    suggested_cost: u32,
+}
+
+#[derive(Serialize)]
+struct CalibrationReport {
+    iterations: u32,
+    reference: &'static str,
+    measurements: Vec<Measurement>,
+    /// The suggested entries of `WasmCosts`, to be merged into the `gas_costs` param of the
+    /// compute module
+    suggested_costs: BTreeMap<&'static str, u32>,
+}
+
+/// The gas of an operation that takes `nanos`, at the rate of the reference cost
+fn suggested_cost(nanos: u64, reference_nanos: u64, reference_cost: u32) -> u32 {
+    let cost = nanos as u128 * reference_cost as u128 / reference_nanos.max(1) as u128;
+    cost.clamp(1, u32::MAX as u128) as u32
+}
+
+/// Splits the times of a batch of one and of a batch of `BATCH_SIZE` into a base cost and a cost
+/// per signature
+fn split_batch_nanos(single_nanos: u64, batch_nanos: u64) -> (u64, u64) {
+    let each = batch_nanos.saturating_sub(single_nanos) / (BATCH_SIZE as u64 - 1);
+    (single_nanos.saturating_sub(each), each)
+}
+
+/// Runs `operation` `iterations` times and returns the average time of a run. The operation
+/// returns whether it succeeded, which is checked so that it can't be optimized away.
+fn nanos_per_call<F: FnMut() -> bool>(
+    name: &str,
+    iterations: u32,
+    mut operation: F,
+) -> Result<u64, EnclaveError> {
+    let start = Instant::now();
+    let succeeded = (0..iterations).filter(|_| operation()).count();
+    let elapsed = Instant::now().duration_since(start);
+
+    if succeeded != iterations as usize {
+        error!("crypto calibration of {} failed", name);
+        return Err(EnclaveError::FailedFunctionCall);
+    }
+
+    Ok((elapsed.as_nanos() / iterations as u128) as u64)
+}
+
+fn fixture_err<E: std::fmt::Debug>(err: E) -> EnclaveError {
+    error!(
+        "failed to create the crypto calibration fixtures: {:?}",
+        err
+    );
+    EnclaveError::FailedFunctionCall
+}
+
+/// Times the crypto host functions and returns a JSON report with suggested costs for them
+pub fn calibrate_crypto_gas(iterations: u32) -> Result<Vec<u8>, EnclaveError> {
+    if iterations == 0 || iterations > MAX_CALIBRATION_ITERATIONS {
+        warn!(
+            "crypto calibration iterations must be between 1 and {}, got {}",
+            MAX_CALIBRATION_ITERATIONS, iterations
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let message = b"crypto calibration".to_vec();
+    let message_hash = sha_256(&message);
+
+    let secp = secp256k1::Secp256k1::new();
+    let secp256k1_msg = secp256k1::Message::from_slice(&message_hash).map_err(fixture_err)?;
+    let secp256k1_key = secp256k1::SecretKey::from_slice(&[1u8; 32]).map_err(fixture_err)?;
+    let secp256k1_pubkey = secp256k1::PublicKey::from_secret_key(&secp, &secp256k1_key);
+    let secp256k1_sig = secp.sign_ecdsa(&secp256k1_msg, &secp256k1_key);
+    let secp256k1_recoverable_sig = secp.sign_ecdsa_recoverable(&secp256k1_msg, &secp256k1_key);
+
+    let ed25519_key = ed25519_zebra::SigningKey::try_from(&[2u8; 32][..]).map_err(fixture_err)?;
+    let ed25519_pubkey = ed25519_zebra::VerificationKey::from(&ed25519_key);
+    let ed25519_sig = ed25519_key.sign(&message);
+
+    let ed25519_batch_nanos = |batch_size: u32| {
+        nanos_per_call("ed25519_batch_verify", iterations, || {
+            let mut batch = ed25519_zebra::batch::Verifier::new();
+            for _ in 0..batch_size {
+                batch.queue((
+                    ed25519_zebra::VerificationKeyBytes::from(ed25519_pubkey),
+                    ed25519_sig,
+                    &message[..],
+                ));
+            }
+            batch.verify(ChaChaRng::from_seed([0u8; 32])).is_ok()
+        })
+    };
+
+    let mut nanos: Vec<(&'static str, u64)> = vec![
+        (
+            "external_secp256k1_verify",
+            nanos_per_call("secp256k1_verify", iterations, || {
+                secp.verify_ecdsa(&secp256k1_msg, &secp256k1_sig, &secp256k1_pubkey)
+                    .is_ok()
+            })?,
+        ),
+        (
+            "external_secp256k1_recover_pubkey",
+            nanos_per_call("secp256k1_recover_pubkey", iterations, || {
+                secp.recover_ecdsa(&secp256k1_msg, &secp256k1_recoverable_sig)
+                    == Ok(secp256k1_pubkey)
+            })?,
+        ),
+        (
+            REFERENCE_COST,
+            nanos_per_call("ed25519_verify", iterations, || {
+                ed25519_pubkey.verify(&ed25519_sig, &message).is_ok()
+            })?,
+        ),
+        (
+            "external_secp256k1_sign",
+            nanos_per_call("secp256k1_sign", iterations, || {
+                secp256k1::Message::from_slice(&sha_256(&message))
+                    .map(|msg| secp.sign_ecdsa(&msg, &secp256k1_key) == secp256k1_sig)
+                    .unwrap_or(false)
+            })?,
+        ),
+        (
+            "external_ed25519_sign",
+            nanos_per_call("ed25519_sign", iterations, || {
+                ed25519_key.sign(&message) == ed25519_sig
+            })?,
+        ),
+    ];
+
+    let (batch_base_nanos, batch_each_nanos) =
+        split_batch_nanos(ed25519_batch_nanos(1)?, ed25519_batch_nanos(BATCH_SIZE)?);
+    nanos.push(("external_ed25519_batch_verify_base", batch_base_nanos));
+    nanos.push(("external_ed25519_batch_verify_each", batch_each_nanos));
+
+    let current_costs = serde_json::to_value(WasmCosts::default()).map_err(|err| {
+        warn!("failed to serialize the default wasm costs: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+    let current_cost = |cost: &str| {
+        current_costs
+            .get(cost)
+            .and_then(|value| value.as_u64())
+            .unwrap_or_default() as u32
+    };
+
+    let reference_nanos = nanos
+        .iter()
+        .find(|(cost, _)| *cost == REFERENCE_COST)
+        .map_or(0, |(_, nanos)| *nanos);
+    let reference_cost = current_cost(REFERENCE_COST);
+
+    let measurements: Vec<Measurement> = nanos
+        .into_iter()
+        .map(|(cost, nanos_per_call)| Measurement {
+            cost,
+            nanos_per_call,
+            current_cost: current_cost(cost),
+            suggested_cost: suggested_cost(nanos_per_call, reference_nanos, reference_cost),
+        })
+        .collect();
+
+    let report = CalibrationReport {
+        iterations,
+        reference: REFERENCE_COST,
+        suggested_costs: measurements
+            .iter()
+            .map(|measurement| (measurement.cost, measurement.suggested_cost))
+            .collect(),
+        measurements,
+    };
+
+    serde_json::to_vec(&report).map_err(|err| {
+        warn!(
+            "failed to serialize the crypto calibration report: {:?}",
+            err
+        );
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_suggested_cost() {
+        assert_eq!(suggested_cost(100, 100, 73728), 73728);
+        assert_eq!(suggested_cost(200, 100, 73728), 147456);
+        assert_eq!(suggested_cost(50, 100, 73728), 36864);
+        // Costs are never free, even for operations faster than the clock resolution
+        assert_eq!(suggested_cost(0, 100, 73728), 1);
+        assert_eq!(suggested_cost(100, 0, 73728), 7372800);
+        assert_eq!(suggested_cost(u64::MAX, 1, u32::MAX), u32::MAX);
+    }
+
+    pub fn test_split_batch_nanos() {
+        // 1000 for a batch of one and 8000 for a batch of eight: 1000 per signature, no base
+        assert_eq!(split_batch_nanos(1000, 8000), (0, 1000));
+        assert_eq!(split_batch_nanos(1500, 8500), (500, 1000));
+        // Noisy measurements can't make the costs underflow
+        assert_eq!(split_batch_nanos(1000, 900), (1000, 0));
+    }
+
+    pub fn test_calibration_iterations_are_bounded() {
+        assert!(calibrate_crypto_gas(0).is_err());
+        assert!(calibrate_crypto_gas(MAX_CALIBRATION_ITERATIONS + 1).is_err());
+    }
+}
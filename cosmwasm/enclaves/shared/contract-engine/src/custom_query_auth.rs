@@ -0,0 +1,321 @@
+//! Signatures of the answers to `QueryRequest::Custom` queries.
+//!
+//! Governance can set the Ed25519 key of the responder of custom queries in the
+//! `CustomQueryResponderKey` compute param. Once it's set, the enclave only passes answers to
+//! custom queries to contracts if the responder signed them, so they can't be changed on their
+//! way from the responder to the enclave.
+//!
+//! The host can't be trusted to tell the enclave whether a key is set, so the key is never read
+//! from the compute params of the env. Executions carry a `ReadProof` of the param in the params
+//! store instead, at the height before their block, and the enclave verifies it against the app
+//! hash of the header of their block, which it verified itself. Without a valid proof the
+//! enclave can't tell whether answers must be signed, so custom queries fail.
+
+use std::convert::TryFrom;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::std_error::StdResult;
+use cw_types_v010::system_error::{SystemError, SystemResult};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_crypto::sha_256;
+
+use crate::state_proofs::{verified_app_hash, ReadProof};
+
+/// Where the param is in the params store: the subspace of the compute module and the key of
+/// the param
+const PARAMS_STORE: &[u8] = b"params";
+const RESPONDER_KEY_PARAM: &[u8] = b"compute/CustomQueryResponderKey";
+
+/// Separates the signatures of answers from other signatures of the same key
+const RESPONSE_DOMAIN: &[u8] = b"secret_custom_query_response";
+
+/// Whether the answers to the custom queries of an execution must be signed, and by which key
+#[derive(Clone, Debug, PartialEq)]
+pub enum CustomQueryKey {
+    /// No key is set, answers are passed to contracts as they are
+    Unsigned,
+    Registered([u8; 32]),
+    /// The execution doesn't prove whether a key is set, so custom queries fail
+    Unproven,
+}
+
+/// The answer of a responder to a custom query, as the enclave expects it once a key is set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignedCustomResponse {
+    pub response: Binary,
+    pub signature: Binary,
+}
+
+#[derive(Deserialize)]
+struct EnvWithCustomQueryKeyProof {
+    #[serde(default)]
+    custom_query_key_proof: Option<ReadProof>,
+}
+
+/// How the answers to the custom queries of an execution are authenticated
+#[derive(Clone, Debug)]
+pub struct CustomQueryAuth {
+    pub key: CustomQueryKey,
+    /// The block of the execution, which the signatures of answers commit to
+    pub block_height: u64,
+}
+
+impl CustomQueryAuth {
+    /// Until an execution proves the key, its custom queries fail
+    pub fn unproven() -> Self {
+        Self {
+            key: CustomQueryKey::Unproven,
+            block_height: 0,
+        }
+    }
+
+    /// The responder key of the executions of a block, as the proof in their env proves it
+    pub fn from_env(env: &[u8], block_height: u64) -> Self {
+        let key = if is_active(Feature::ProvenCustomQueryKey) {
+            let proof = match serde_json::from_slice::<EnvWithCustomQueryKeyProof>(env) {
+                Ok(env) => env.custom_query_key_proof,
+                Err(err) => {
+                    warn!("failed to parse the custom query key proof: {:?}", err);
+                    None
+                }
+            };
+
+            // The header of a block commits to the state after the block before it
+            key_from_proof(proof.as_ref(), verified_app_hash(block_height).as_deref())
+        } else {
+            CustomQueryKey::Unsigned
+        };
+
+        Self { key, block_height }
+    }
+}
+
+fn key_from_proof(proof: Option<&ReadProof>, app_hash: Option<&[u8]>) -> CustomQueryKey {
+    let (proof, app_hash) = match (proof, app_hash) {
+        (Some(proof), Some(app_hash)) => (proof, app_hash),
+        _ => {
+            warn!("the responder key of custom queries is not proven");
+            return CustomQueryKey::Unproven;
+        }
+    };
+
+    match proof.verify(PARAMS_STORE, RESPONDER_KEY_PARAM, app_hash) {
+        Ok(Some(value)) => key_from_param(value),
+        Ok(None) => CustomQueryKey::Unsigned,
+        Err(_) => CustomQueryKey::Unproven,
+    }
+}
+
+/// Params are stored as the Amino JSON of their value, here the hex of the key or an empty string
+fn key_from_param(value: &[u8]) -> CustomQueryKey {
+    let key = match serde_json::from_slice::<String>(value) {
+        Ok(key) if key.is_empty() => return CustomQueryKey::Unsigned,
+        Ok(key) => key,
+        Err(_) => {
+            warn!("the responder key of custom queries is not a string");
+            return CustomQueryKey::Unproven;
+        }
+    };
+
+    match hex::decode(&key)
+        .ok()
+        .and_then(|key| <[u8; 32]>::try_from(key.as_slice()).ok())
+    {
+        Some(key) if ed25519_zebra::VerificationKey::try_from(&key[..]).is_ok() => {
+            CustomQueryKey::Registered(key)
+        }
+        _ => {
+            warn!("the responder key of custom queries is not a valid Ed25519 key");
+            CustomQueryKey::Unproven
+        }
+    }
+}
+
+fn verify(key: &[u8; 32], signature: &[u8], signed_bytes: &[u8]) -> bool {
+    let signature = match ed25519_zebra::Signature::try_from(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+
+    ed25519_zebra::VerificationKey::try_from(&key[..])
+        .and_then(|key| key.verify(&signature, signed_bytes))
+        .is_ok()
+}
+
+/// The answer is bound to the query it answers and to the block it was given in, so answers
+/// can't be replayed to other queries or in later blocks
+fn response_signed_bytes(block_height: u64, request: &[u8], response: &[u8]) -> Vec<u8> {
+    let mut data = RESPONSE_DOMAIN.to_vec();
+    data.extend_from_slice(&block_height.to_be_bytes());
+    data.extend_from_slice(&sha_256(request));
+    data.extend_from_slice(response);
+    data
+}
+
+fn invalid_response(response: &[u8], error: &str) -> SystemResult<StdResult<Binary>> {
+    warn!("invalid answer to a custom query: {}", error);
+    Err(SystemError::InvalidResponse {
+        error: error.to_string(),
+        response: Binary(response.to_vec()),
+    })
+}
+
+/// Verifies the signature of the answer to a custom query, and unwraps it. `request` is the
+/// JSON of the custom query as the host received it. Errors of the host are passed to the
+/// contract as they are.
+pub fn authenticate_custom_response(
+    key: &[u8; 32],
+    block_height: u64,
+    request: &[u8],
+    response: &[u8],
+) -> SystemResult<StdResult<Binary>> {
+    let signed_binary = match serde_json::from_slice::<SystemResult<StdResult<Binary>>>(response) {
+        Ok(Ok(Ok(signed_binary))) => signed_binary,
+        Ok(host_error) => return host_error,
+        Err(_) => return invalid_response(response, "failed to parse the answer"),
+    };
+    let signed: SignedCustomResponse = match serde_json::from_slice(signed_binary.as_slice()) {
+        Ok(signed) => signed,
+        Err(_) => return invalid_response(response, "the answer is not signed"),
+    };
+
+    let signed_bytes = response_signed_bytes(block_height, request, signed.response.as_slice());
+    if !verify(key, signed.signature.as_slice(), &signed_bytes) {
+        return invalid_response(response, "the signature of the answer is invalid");
+    }
+
+    Ok(Ok(signed.response))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::state_proofs::tests::{prove, prove_absent};
+    use crate::state_proofs::KeyProof;
+
+    fn signed_answer(
+        signing_key: &ed25519_zebra::SigningKey,
+        block_height: u64,
+        request: &[u8],
+        response: &[u8],
+    ) -> Vec<u8> {
+        let signature: [u8; 64] = signing_key
+            .sign(&response_signed_bytes(block_height, request, response))
+            .into();
+        let signed = SignedCustomResponse {
+            response: Binary(response.to_vec()),
+            signature: Binary(signature.to_vec()),
+        };
+        let answer: SystemResult<StdResult<Binary>> =
+            Ok(Ok(Binary(serde_json::to_vec(&signed).unwrap())));
+        serde_json::to_vec(&answer).unwrap()
+    }
+
+    fn read_proof_of(value: &[u8]) -> (ReadProof, Vec<u8>) {
+        let (proof, app_hash) = prove(PARAMS_STORE, RESPONDER_KEY_PARAM, value);
+        let proof = ReadProof {
+            store_proof: KeyProof::Exist(proof.store_proof),
+            multistore_proof: proof.multistore_proof,
+        };
+        (proof, app_hash)
+    }
+
+    pub fn test_proven_custom_query_key() {
+        let signing_key = ed25519_zebra::SigningKey::from([9u8; 32]);
+        let key: [u8; 32] = ed25519_zebra::VerificationKey::from(&signing_key).into();
+        let param = serde_json::to_vec(&hex::encode(key)).unwrap();
+
+        let (proof, app_hash) = read_proof_of(&param);
+        assert_eq!(
+            key_from_proof(Some(&proof), Some(&app_hash)),
+            CustomQueryKey::Registered(key)
+        );
+
+        // The param is set to an empty string
+        let (proof, app_hash) = read_proof_of(br#""""#);
+        assert_eq!(
+            key_from_proof(Some(&proof), Some(&app_hash)),
+            CustomQueryKey::Unsigned
+        );
+
+        // The param was never set
+        let (proof, app_hash) =
+            prove_absent(PARAMS_STORE, RESPONDER_KEY_PARAM, b"compute/AdminRegistry");
+        assert_eq!(
+            key_from_proof(Some(&proof), Some(&app_hash)),
+            CustomQueryKey::Unsigned
+        );
+
+        // Without a proof, or with one that the app hash doesn't commit to, custom queries fail
+        let (proof, app_hash) = read_proof_of(&param);
+        assert_eq!(
+            key_from_proof(None, Some(&app_hash)),
+            CustomQueryKey::Unproven
+        );
+        assert_eq!(key_from_proof(Some(&proof), None), CustomQueryKey::Unproven);
+        assert_eq!(
+            key_from_proof(Some(&proof), Some(&[0u8; 32])),
+            CustomQueryKey::Unproven
+        );
+
+        // A param that isn't a key
+        let (proof, app_hash) = read_proof_of(br#""1234""#);
+        assert_eq!(
+            key_from_proof(Some(&proof), Some(&app_hash)),
+            CustomQueryKey::Unproven
+        );
+    }
+
+    pub fn test_authenticate_custom_response() {
+        let signing_key = ed25519_zebra::SigningKey::from([9u8; 32]);
+        let key: [u8; 32] = ed25519_zebra::VerificationKey::from(&signing_key).into();
+        let request = br#"{"swap":{"offer":"uscrt"}}"#;
+
+        let answer = signed_answer(&signing_key, 10, request, b"42");
+        assert!(matches!(
+            authenticate_custom_response(&key, 10, request, &answer),
+            Ok(Ok(Binary(response))) if response == b"42"
+        ));
+
+        // An answer to another query, or given in another block
+        let other_request = br#"{"swap":{"offer":"uatom"}}"#;
+        assert!(matches!(
+            authenticate_custom_response(&key, 10, other_request, &answer),
+            Err(SystemError::InvalidResponse { .. })
+        ));
+        assert!(matches!(
+            authenticate_custom_response(&key, 11, request, &answer),
+            Err(SystemError::InvalidResponse { .. })
+        ));
+
+        // An answer signed by another key
+        let other_key = ed25519_zebra::SigningKey::from([10u8; 32]);
+        let answer = signed_answer(&other_key, 10, request, b"42");
+        assert!(matches!(
+            authenticate_custom_response(&key, 10, request, &answer),
+            Err(SystemError::InvalidResponse { .. })
+        ));
+
+        // An unsigned answer
+        let unsigned: SystemResult<StdResult<Binary>> = Ok(Ok(Binary(b"42".to_vec())));
+        let answer = serde_json::to_vec(&unsigned).unwrap();
+        assert!(matches!(
+            authenticate_custom_response(&key, 10, request, &answer),
+            Err(SystemError::InvalidResponse { .. })
+        ));
+
+        // Errors of the host reach the contract
+        let host_error: SystemResult<StdResult<Binary>> = Err(SystemError::UnsupportedRequest {
+            kind: "custom".to_string(),
+        });
+        let answer = serde_json::to_vec(&host_error).unwrap();
+        assert!(matches!(
+            authenticate_custom_response(&key, 10, request, &answer),
+            Err(SystemError::UnsupportedRequest { .. })
+        ));
+    }
+}
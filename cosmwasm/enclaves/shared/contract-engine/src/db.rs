@@ -6,17 +6,106 @@ use log::*;
 
 use sgx_types::sgx_status_t;
 
-use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError};
+use enclave_ffi_types::{Ctx, EnclaveBuffer, EnclaveError, OcallReturn, UntrustedVmError};
 
-use enclave_crypto::{sha_256, AESKey, Kdf, SIVEncryptable, KEY_MANAGER};
+use enclave_crypto::{sha_256, AESKey, Kdf, SIVEncryptable, HASH_SIZE, KEY_MANAGER};
 
 use crate::external::{ecalls, ocalls};
+use crate::historical_state::{is_historical, verify_historical_read};
 
 use enclave_utils::kv_cache::KvCache;
 
 use super::contract_validation::ContractKey;
+use super::cosmwasm_config::reserved_keys;
 use super::errors::WasmEngineError;
+use super::ordered_keys::{
+    decode_ordered_key, encode_ordered_key, is_ordered_key, namespace_of, ordered_namespace_prefix,
+    OrderedNamespaces,
+};
+use super::state_reencryption::{read_state_key_rotation, StateKeyRotation};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+/// Info used to derive the state keys of epochs after the first one
+const STATE_KEY_DERIVATION_INFO: &[u8] = b"secret_state_key";
+
+/// The number of raw keys that are read from the host at a time when scanning the keys
+const RAW_KEYS_PAGE_SIZE: u32 = 256;
+
+/// The bytes of raw entries, keys and values, that the host reported adding to and removing
+/// from the storage of a contract, see `storage_quota`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoredBytes {
+    pub added: u64,
+    pub removed: u64,
+}
+
+impl StoredBytes {
+    pub fn add(&mut self, other: StoredBytes) {
+        self.added = self.added.saturating_add(other.added);
+        self.removed = self.removed.saturating_add(other.removed);
+    }
+}
+
+/// A measurement of the bytes stored, see `measure_stored_bytes`
+struct Measurement {
+    stored: StoredBytes,
+    counted_until: Option<Vec<u8>>,
+}
+
+thread_local! {
+    /// The measurement that runs on this thread, if any
+    static MEASUREMENT: RefCell<Option<Measurement>> = RefCell::new(None);
+}
+
+/// Runs `f` and returns the bytes that the host reported storing and removing for the writes
+/// and removes it made. Measurements nest, e.g. for the contracts that an execution calls, and
+/// the outer one doesn't include what the inner one measured.
+///
+/// While the usage of a contract is counted, only the entries that the count already passed are
+/// measured, the ones up to the raw key `counted_until`, see `StorageUsage`. The others are
+/// counted as they are once the count gets to them.
+pub fn measure_stored_bytes<T, F: FnOnce() -> T>(
+    counted_until: Option<&[u8]>,
+    f: F,
+) -> (T, StoredBytes) {
+    let measurement = Measurement {
+        stored: StoredBytes::default(),
+        counted_until: counted_until.map(|raw_key| raw_key.to_vec()),
+    };
+    let outer = MEASUREMENT.with(|current| current.replace(Some(measurement)));
+    let result = f();
+    let measured = MEASUREMENT.with(|current| current.replace(outer));
+    (
+        result,
+        measured.map(|measured| measured.stored).unwrap_or_default(),
+    )
+}
+
+/// Whether what is stored for a raw key is measured
+fn is_measured(raw_key: &[u8]) -> bool {
+    MEASUREMENT.with(|current| match &*current.borrow() {
+        Some(Measurement {
+            counted_until: Some(counted_until),
+            ..
+        }) => raw_key <= counted_until.as_slice(),
+        _ => true,
+    })
+}
+
+fn record_stored_bytes(added: u64, removed: u64) {
+    MEASUREMENT.with(|current| {
+        if let Some(measured) = current.borrow_mut().as_mut() {
+            measured.stored.add(StoredBytes { added, removed });
+        }
+    });
+}
+
+/// The size of a raw entry as the host reports it
+fn raw_entry_size(key: &[u8], value: &[u8]) -> u64 {
+    (key.len() + value.len()) as u64
+}
 
 #[derive(Serialize, Deserialize)]
 struct EncryptedKey {
@@ -38,9 +127,118 @@ struct EncryptedValue {
     pub data: Vec<u8>,
 }
 
+/// How the plaintext keys of entries are turned into the keys the chain stores
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyScheme {
+    /// Encrypted with AES-SIV, see `encrypt_key_new`
+    Siv,
+    /// Encoded to keep the order of the keys of an ordered namespace, see `ordered_keys`
+    Ordered,
+}
+
+/// The keys the state of a contract is encrypted with
+#[derive(Clone, Copy)]
+pub struct StateKeys {
+    pub current: ContractKey,
+    /// The key of the previous epoch, which is only set while the state is being re-encrypted
+    pub previous: Option<ContractKey>,
+    /// The key of epoch 0, which the subkeys of the contract are derived from
+    pub og: ContractKey,
+}
+
+impl StateKeys {
+    /// The keys of epoch 0, which the rotation of the state key itself is stored with
+    pub fn first_epoch(og_contract_key: &ContractKey) -> Self {
+        Self {
+            current: *og_contract_key,
+            previous: None,
+            og: *og_contract_key,
+        }
+    }
+
+    /// The keys of the epoch the state is in at a verified block height. The epoch is derived
+    /// from the rotation recorded in the state of the contract, see `state_reencryption`, and
+    /// never taken from the host.
+    pub fn at_height(
+        context: &Ctx,
+        og_contract_key: &ContractKey,
+        block_height: u64,
+    ) -> Result<Self, EnclaveError> {
+        let rotation = read_state_key_rotation(context, og_contract_key)?;
+        Self::for_rotation(og_contract_key, rotation.as_ref(), block_height)
+            .map_err(EnclaveError::from)
+    }
+
+    pub fn for_rotation(
+        og_contract_key: &ContractKey,
+        rotation: Option<&StateKeyRotation>,
+        block_height: u64,
+    ) -> Result<Self, WasmEngineError> {
+        let rotation = match rotation {
+            Some(rotation) => rotation,
+            None => return Ok(Self::first_epoch(og_contract_key)),
+        };
+
+        let epoch = rotation.epoch_at(block_height);
+        let previous = if rotation.is_pending_at(block_height) {
+            Some(derive_state_key(og_contract_key, epoch - 1)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            current: derive_state_key(og_contract_key, epoch)?,
+            previous,
+            og: *og_contract_key,
+        })
+    }
+}
+
+/// Derives the key the state of a contract is encrypted with in the given epoch.
+/// Epoch 0 is the og contract key, so the state of contracts that were never re-encrypted
+/// is read as before.
+pub fn derive_state_key(
+    og_contract_key: &ContractKey,
+    epoch: u32,
+) -> Result<ContractKey, WasmEngineError> {
+    if epoch == 0 {
+        return Ok(*og_contract_key);
+    }
+
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|err| {
+        error!("failed to get the consensus state ikm: {:?}", err);
+        WasmEngineError::EncryptionError
+    })?;
+
+    let mut derivation_data = STATE_KEY_DERIVATION_INFO.to_vec();
+    derivation_data.extend_from_slice(og_contract_key);
+    derivation_data.extend_from_slice(&epoch.to_be_bytes());
+    let derived_key = consensus_state_ikm
+        .current
+        .derive_key_from_this(&derivation_data);
+
+    // Keep the sender id part of the contract key, and replace the authenticated part
+    let mut state_key = *og_contract_key;
+    state_key[HASH_SIZE..].copy_from_slice(derived_key.get());
+    Ok(state_key)
+}
+
 pub fn write_multiple_keys(
     context: &Ctx,
     keys: Vec<(Vec<u8>, Vec<u8>)>,
+) -> Result<u64, WasmEngineError> {
+    // The host reports the size that a batch replaces as a whole, so the entries that aren't
+    // measured are written in a batch of their own
+    let (measured, unmeasured): (Vec<_>, Vec<_>) =
+        keys.into_iter().partition(|(key, _)| is_measured(key));
+    let gas_used = write_batch(context, unmeasured, false)?;
+    Ok(gas_used + write_batch(context, measured, true)?)
+}
+
+fn write_batch(
+    context: &Ctx,
+    keys: Vec<(Vec<u8>, Vec<u8>)>,
+    measured: bool,
 ) -> Result<u64, WasmEngineError> {
     let mut ocall_return = OcallReturn::Success;
 
@@ -48,18 +246,24 @@ pub fn write_multiple_keys(
         return Ok(0);
     }
 
+    let added = keys
+        .iter()
+        .map(|(key, value)| raw_entry_size(key, value))
+        .sum();
     let x = serde_json::to_vec(&keys).unwrap();
     let len = x.len();
     let ptr = x.as_ptr();
 
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
+    let mut replaced_size = 0_u64;
     match unsafe {
         ocalls::ocall_multiple_write_db(
             (&mut ocall_return) as *mut _,
             context.unsafe_clone(),
             (&mut vm_err) as *mut _,
             (&mut gas_used) as *mut _,
+            (&mut replaced_size) as *mut _,
             ptr,
             len,
         )
@@ -69,19 +273,24 @@ pub fn write_multiple_keys(
     }
 
     match ocall_return {
-        OcallReturn::Success => Ok(gas_used),
+        OcallReturn::Success => {
+            if measured {
+                record_stored_bytes(added, replaced_size);
+            }
+            Ok(gas_used)
+        }
         OcallReturn::Failure => Err(WasmEngineError::FailedOcall(vm_err)),
         OcallReturn::Panic => Err(WasmEngineError::Panic),
     }
 }
 
-#[allow(dead_code)]
-fn write_to_encrypted_state(
+pub fn write_to_encrypted_state(
     plaintext_key: &[u8],
     plaintext_value: &[u8],
     context: &Ctx,
     contract_key: &ContractKey,
     encryption_salt: &[u8],
+    scheme: KeyScheme,
 ) -> Result<u64, WasmEngineError> {
     // Get the state key from the key manager
 
@@ -91,6 +300,7 @@ fn write_to_encrypted_state(
         context,
         contract_key,
         encryption_salt,
+        scheme,
     )?;
 
     // Write the new data as concat(ad, encrypted_val)
@@ -112,7 +322,18 @@ pub fn create_encrypted_key_value(
     context: &Ctx,
     contract_key: &ContractKey,
     encryption_salt: &[u8],
+    scheme: KeyScheme,
 ) -> Result<(Vec<u8>, u64, Vec<u8>), WasmEngineError> {
+    if scheme == KeyScheme::Ordered {
+        return create_ordered_key_value(
+            plaintext_key,
+            plaintext_value,
+            context,
+            contract_key,
+            encryption_salt,
+        );
+    }
+
     let scrambled_field_name = field_name_digest(plaintext_key, contract_key);
     let gas_used_remove = remove_db(context, &scrambled_field_name).map_err(|err| {
         warn!(
@@ -122,12 +343,7 @@ pub fn create_encrypted_key_value(
         err
     })?;
 
-    let encrypted_key = EncryptedKey {
-        magic_bytes: ENCRYPTED_KEY_MAGIC_BYTES.to_vec(),
-        consensus_seed_version: CONSENSUS_SEED_VERSION,
-        state_encryption_version: STATE_ENCRYPTION_VERSION,
-        data: encrypt_key_new(plaintext_key, contract_key)?,
-    };
+    let encrypted_key = encrypted_key(plaintext_key, contract_key)?;
     let encrypted_key_bytes = bincode2::serialize(&encrypted_key).unwrap();
 
     let encrypted_value = EncryptedValue {
@@ -149,6 +365,50 @@ pub fn create_encrypted_key_value(
     Ok((encrypted_key_bytes, gas_used_remove, encrypted_value_bytes))
 }
 
+fn encrypted_key(
+    plaintext_key: &[u8],
+    contract_key: &ContractKey,
+) -> Result<EncryptedKey, WasmEngineError> {
+    Ok(EncryptedKey {
+        magic_bytes: ENCRYPTED_KEY_MAGIC_BYTES.to_vec(),
+        consensus_seed_version: CONSENSUS_SEED_VERSION,
+        state_encryption_version: STATE_ENCRYPTION_VERSION,
+        data: encrypt_key_new(plaintext_key, contract_key)?,
+    })
+}
+
+/// The raw key that the host stores the entry of a plaintext key under, unless it's in an
+/// ordered namespace
+pub fn raw_state_key(
+    plaintext_key: &[u8],
+    contract_key: &ContractKey,
+) -> Result<Vec<u8>, WasmEngineError> {
+    Ok(bincode2::serialize(&encrypted_key(plaintext_key, contract_key)?).unwrap())
+}
+
+/// Like `create_encrypted_key_value`, for keys of ordered namespaces. The entry of the key from
+/// before its namespace was ordered is removed, so that it doesn't show up again once the key
+/// is removed.
+fn create_ordered_key_value(
+    plaintext_key: &[u8],
+    plaintext_value: &[u8],
+    context: &Ctx,
+    contract_key: &ContractKey,
+    encryption_salt: &[u8],
+) -> Result<(Vec<u8>, u64, Vec<u8>), WasmEngineError> {
+    let gas_used_remove =
+        remove_from_encrypted_state(plaintext_key, context, contract_key, KeyScheme::Siv)?;
+
+    let ordered_key = encode_ordered_key(&get_symmetrical_key_new(contract_key), plaintext_key)?;
+    let encrypted_value = EncryptedValue {
+        salt: encryption_salt.to_vec(),
+        data: encrypt_value_new(&ordered_key, plaintext_value, contract_key, encryption_salt)?,
+    };
+    let encrypted_value_bytes = bincode2::serialize(&encrypted_value).unwrap();
+
+    Ok((ordered_key, gas_used_remove, encrypted_value_bytes))
+}
+
 pub fn read_from_encrypted_state(
     plaintext_key: &[u8],
     context: &Ctx,
@@ -156,7 +416,19 @@ pub fn read_from_encrypted_state(
     has_write_permissions: bool,
     kv_cache: &mut KvCache,
     encryption_salt: &[u8],
+    scheme: KeyScheme,
 ) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    if scheme == KeyScheme::Ordered {
+        return read_from_ordered_state(
+            plaintext_key,
+            context,
+            contract_key,
+            has_write_permissions,
+            kv_cache,
+            encryption_salt,
+        );
+    }
+
     // Try reading with the new encryption format
     let encrypted_key = EncryptedKey {
         magic_bytes: ENCRYPTED_KEY_MAGIC_BYTES.to_vec(),
@@ -239,6 +511,7 @@ pub fn read_from_encrypted_state(
                 context,
                 contract_key,
                 encryption_salt,
+                KeyScheme::Siv,
             )?;
         }
     }
@@ -249,11 +522,88 @@ pub fn read_from_encrypted_state(
     ))
 }
 
+/// Like `read_from_encrypted_state`, for keys of ordered namespaces. Entries that were written
+/// before the namespace was ordered are read with the regular scheme, and moved to their ordered
+/// key if we have write permissions.
+fn read_from_ordered_state(
+    plaintext_key: &[u8],
+    context: &Ctx,
+    contract_key: &ContractKey,
+    has_write_permissions: bool,
+    kv_cache: &mut KvCache,
+    encryption_salt: &[u8],
+) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    let ordered_key = encode_ordered_key(&get_symmetrical_key_new(contract_key), plaintext_key)?;
+
+    let (maybe_encrypted_value_bytes, gas_used_read) = read_db(context, &ordered_key)?;
+    if let Some(encrypted_value_bytes) = maybe_encrypted_value_bytes {
+        let encrypted_value: EncryptedValue = bincode2::deserialize(&encrypted_value_bytes)
+            .map_err(|err| {
+                warn!(
+                    "read_db() got an error while trying to deserialize the value of an ordered key, stopping wasm: {:?}",
+                    err.to_string()
+                );
+                WasmEngineError::DecryptionError
+            })?;
+        let plaintext_value = decrypt_value_new(
+            &ordered_key,
+            &encrypted_value.data,
+            contract_key,
+            &encrypted_value.salt,
+        )?;
+        return Ok((Some(plaintext_value), gas_used_read));
+    }
+
+    let (value, gas_used_unordered_read) = read_from_encrypted_state(
+        plaintext_key,
+        context,
+        contract_key,
+        false,
+        kv_cache,
+        encryption_salt,
+        KeyScheme::Siv,
+    )?;
+
+    let mut gas_used_write = 0;
+    if has_write_permissions {
+        if let Some(ref plaintext_value) = value {
+            gas_used_write = write_to_encrypted_state(
+                plaintext_key,
+                plaintext_value,
+                context,
+                contract_key,
+                encryption_salt,
+                KeyScheme::Ordered,
+            )?;
+        }
+    }
+
+    Ok((
+        value,
+        gas_used_read + gas_used_unordered_read + gas_used_write,
+    ))
+}
+
 pub fn remove_from_encrypted_state(
     plaintext_key: &[u8],
     context: &Ctx,
     contract_key: &ContractKey,
+    scheme: KeyScheme,
 ) -> Result<u64, WasmEngineError> {
+    // Keys of ordered namespaces may also have entries from before the namespace was ordered
+    let mut gas_used_ordered_remove = 0;
+    if scheme == KeyScheme::Ordered {
+        let ordered_key =
+            encode_ordered_key(&get_symmetrical_key_new(contract_key), plaintext_key)?;
+        gas_used_ordered_remove = remove_db(context, &ordered_key).map_err(|err| {
+            warn!(
+                "remove_db() got an error from ocall_remove_db on ordered key remove, stopping wasm: {:?}",
+                err
+            );
+            err
+        })?;
+    }
+
     // TODO in the future we can check if all the state keys are of the new format
     // then skip removing the old key step
 
@@ -287,7 +637,522 @@ pub fn remove_from_encrypted_state(
         err
     })?;
 
-    Ok(gas_used_first_remove + gas_used_second_remove)
+    Ok(gas_used_ordered_remove + gas_used_first_remove + gas_used_second_remove)
+}
+
+/// Reads from the state with the current state key. While the state is being re-encrypted,
+/// entries that are not found are also looked up with the key of the previous epoch, and moved
+/// to the current key if we have write permissions.
+pub fn read_from_state(
+    plaintext_key: &[u8],
+    context: &Ctx,
+    state_keys: &StateKeys,
+    has_write_permissions: bool,
+    kv_cache: &mut KvCache,
+    encryption_salt: &[u8],
+    scheme: KeyScheme,
+) -> Result<(Option<Vec<u8>>, u64), WasmEngineError> {
+    let (value, gas_used_current) = read_from_encrypted_state(
+        plaintext_key,
+        context,
+        &state_keys.current,
+        has_write_permissions,
+        kv_cache,
+        encryption_salt,
+        scheme,
+    )?;
+
+    let previous_key = match (&value, &state_keys.previous) {
+        (None, Some(previous_key)) => previous_key,
+        _ => return Ok((value, gas_used_current)),
+    };
+
+    let (value, gas_used_previous) = read_from_encrypted_state(
+        plaintext_key,
+        context,
+        previous_key,
+        false,
+        kv_cache,
+        encryption_salt,
+        scheme,
+    )?;
+
+    let mut gas_used_move = 0;
+    if has_write_permissions {
+        if let Some(ref plaintext_value) = value {
+            gas_used_move = write_to_encrypted_state(
+                plaintext_key,
+                plaintext_value,
+                context,
+                &state_keys.current,
+                encryption_salt,
+                scheme,
+            )?;
+            gas_used_move +=
+                remove_from_encrypted_state(plaintext_key, context, previous_key, scheme)?;
+        }
+    }
+
+    Ok((value, gas_used_current + gas_used_previous + gas_used_move))
+}
+
+/// Removes a key from the state, including its entry from the previous epoch if the state is
+/// being re-encrypted
+pub fn remove_from_state(
+    plaintext_key: &[u8],
+    context: &Ctx,
+    state_keys: &StateKeys,
+    scheme: KeyScheme,
+) -> Result<u64, WasmEngineError> {
+    let mut gas_used =
+        remove_from_encrypted_state(plaintext_key, context, &state_keys.current, scheme)?;
+
+    if let Some(previous_key) = &state_keys.previous {
+        gas_used += remove_from_encrypted_state(plaintext_key, context, previous_key, scheme)?;
+    }
+
+    Ok(gas_used)
+}
+
+/// Removes every key of the contract's state that starts with `prefix`, including entries of the
+/// previous epoch while the state is being re-encrypted. Keys that are reserved for the enclave
+/// are kept.
+///
+/// The keys are encrypted, so the host can't find them by their prefix. Instead, all the raw keys
+/// of the contract are read from the host, page by page, and decrypted here. `charge` is called
+/// with the number of keys of each page before they are decrypted, so that the scan stops as soon
+/// as the contract runs out of gas.
+///
+/// Entries in the legacy format are keyed by a digest of their plaintext key, so they can't be
+/// matched and are left in place.
+///
+/// Returns the plaintext keys that were removed, and the gas used by the host.
+pub fn remove_prefix_from_state<F>(
+    prefix: &[u8],
+    context: &Ctx,
+    state_keys: &StateKeys,
+    charge: F,
+) -> Result<(Vec<Vec<u8>>, u64), WasmEngineError>
+where
+    F: FnMut(u64) -> Result<(), WasmEngineError>,
+{
+    scan_raw_keys(
+        prefix,
+        RAW_KEYS_PAGE_SIZE,
+        |start, limit| read_db_keys(context, start, limit),
+        |raw_key| decrypt_raw_key(raw_key, state_keys),
+        |raw_key| {
+            remove_db(context, raw_key).map_err(|err| {
+                warn!(
+                    "remove_prefix_from_state() got an error from ocall_remove_db, stopping wasm: {:?}",
+                    err
+                );
+                err
+            })
+        },
+        charge,
+    )
+}
+
+/// Counts the bytes of a page of the raw entries of the contract, the ones after the raw key
+/// `counted_until`, or from the first one if it's empty. Entries that the state keys can't
+/// decrypt, e.g. in the legacy format, are counted too, except the entry `skipped_raw_key`.
+///
+/// Returns the bytes, the last raw key of the page or `None` if there are no entries after it,
+/// and the gas used by the host.
+pub fn count_raw_entries_page(
+    context: &Ctx,
+    counted_until: &[u8],
+    skipped_raw_key: &[u8],
+) -> Result<(u64, Option<Vec<u8>>, u64), WasmEngineError> {
+    let mut start = counted_until.to_vec();
+    if !start.is_empty() {
+        start.push(0);
+    }
+
+    let (raw_keys, mut gas_used) = read_db_keys(context, &start, RAW_KEYS_PAGE_SIZE)?;
+
+    let mut counted_bytes = 0_u64;
+    for raw_key in raw_keys
+        .iter()
+        .filter(|raw_key| *raw_key != skipped_raw_key)
+    {
+        let (value, gas_used_value) = read_db(context, raw_key)?;
+        gas_used += gas_used_value;
+        if let Some(value) = value {
+            counted_bytes = counted_bytes.saturating_add(raw_entry_size(raw_key, &value));
+        }
+    }
+
+    let last_raw_key = match raw_keys.last() {
+        Some(last_raw_key) if raw_keys.len() == RAW_KEYS_PAGE_SIZE as usize => {
+            Some(last_raw_key.clone())
+        }
+        _ => None,
+    };
+
+    Ok((counted_bytes, last_raw_key, gas_used))
+}
+
+/// Reads the raw keys page by page with `read_page`, and calls `found` with each of them whose
+/// plaintext key starts with `prefix` and isn't reserved. Both return the gas the host used.
+fn scan_raw_keys<R, D, M, F>(
+    prefix: &[u8],
+    page_size: u32,
+    mut read_page: R,
+    decrypt: D,
+    mut found: M,
+    mut charge: F,
+) -> Result<(Vec<Vec<u8>>, u64), WasmEngineError>
+where
+    R: FnMut(&[u8], u32) -> Result<(Vec<Vec<u8>>, u64), WasmEngineError>,
+    D: Fn(&[u8]) -> Option<Vec<u8>>,
+    M: FnMut(&[u8]) -> Result<u64, WasmEngineError>,
+    F: FnMut(u64) -> Result<(), WasmEngineError>,
+{
+    let mut found_keys = vec![];
+    let mut gas_used = 0;
+    let mut start = vec![];
+
+    loop {
+        let (raw_keys, gas_used_read) = read_page(&start, page_size)?;
+        gas_used += gas_used_read;
+        charge(raw_keys.len() as u64)?;
+
+        for raw_key in &raw_keys {
+            let plaintext_key = match decrypt(raw_key) {
+                Some(plaintext_key) => plaintext_key,
+                None => continue,
+            };
+            if !plaintext_key.starts_with(prefix) || reserved_keys::is_reserved(&plaintext_key) {
+                continue;
+            }
+
+            gas_used += found(raw_key)?;
+            found_keys.push(plaintext_key);
+        }
+
+        // Keys are returned in order, so the next page starts right after the last key
+        match raw_keys.last() {
+            Some(last_key) if raw_keys.len() == page_size as usize => {
+                start = last_key.clone();
+                start.push(0);
+            }
+            _ => break,
+        }
+    }
+
+    Ok((found_keys, gas_used))
+}
+
+/// Adds the keys in the cache that start with `prefix` to the keys of the state that were removed
+/// by `remove_prefix_from_state`, including writes that weren't flushed yet. Keys that are
+/// reserved for the enclave are left out.
+pub fn with_cached_keys_with_prefix(
+    stored_keys: Vec<Vec<u8>>,
+    kv_cache: &KvCache,
+    prefix: &[u8],
+) -> BTreeSet<Vec<u8>> {
+    let mut keys: BTreeSet<Vec<u8>> = stored_keys.into_iter().collect();
+    keys.extend(
+        kv_cache
+            .keys_with_prefix(prefix)
+            .into_iter()
+            .filter(|key| !reserved_keys::is_reserved(key)),
+    );
+    keys
+}
+
+/// Reads the namespaces of the contract that are ordered, from the cache if they were changed in
+/// this execution
+pub fn read_ordered_namespaces(
+    context: &Ctx,
+    state_keys: &StateKeys,
+    has_write_permissions: bool,
+    kv_cache: &mut KvCache,
+    encryption_salt: &[u8],
+) -> Result<(OrderedNamespaces, u64), WasmEngineError> {
+    let (stored, gas_used) = match kv_cache.read(reserved_keys::ORDERED_NAMESPACES) {
+        Some(stored) => (Some(stored), 0),
+        None => read_from_state(
+            reserved_keys::ORDERED_NAMESPACES,
+            context,
+            state_keys,
+            has_write_permissions,
+            kv_cache,
+            encryption_salt,
+            KeyScheme::Siv,
+        )?,
+    };
+
+    let namespaces = match stored {
+        Some(stored) => OrderedNamespaces::parse(&stored)?,
+        None => OrderedNamespaces::default(),
+    };
+    Ok((namespaces, gas_used))
+}
+
+/// Reads the keys of an ordered namespace that are at least `start` and less than `end`, in
+/// order, including the keys of the previous epoch while the state is being re-encrypted. The
+/// namespace is the one of `start`.
+///
+/// The keys of an ordered namespace are stored next to each other and in order, so only the raw
+/// keys from `start` on are read from the host, page by page, until the end of the range.
+/// `charge` is called with the number of keys of each page before they are decoded.
+///
+/// Returns the plaintext keys, and the gas used by the host.
+pub fn read_ordered_keys<F>(
+    start: &[u8],
+    end: Option<&[u8]>,
+    context: &Ctx,
+    state_keys: &StateKeys,
+    mut charge: F,
+) -> Result<(BTreeSet<Vec<u8>>, u64), WasmEngineError>
+where
+    F: FnMut(u64) -> Result<(), WasmEngineError>,
+{
+    let namespace = namespace_of(start).ok_or_else(|| {
+        warn!("can't read the ordered keys of a range without a namespace");
+        WasmEngineError::DecryptionError
+    })?;
+
+    let mut keys = BTreeSet::new();
+    let mut gas_used = 0;
+
+    for contract_key in std::iter::once(&state_keys.current).chain(state_keys.previous.iter()) {
+        let symmetrical_key = get_symmetrical_key_new(contract_key);
+        let namespace_prefix = ordered_namespace_prefix(&symmetrical_key, namespace)?;
+        let mut from = encode_ordered_key(&symmetrical_key, start)?;
+
+        'pages: loop {
+            let (raw_keys, gas_used_read) = read_db_keys(context, &from, RAW_KEYS_PAGE_SIZE)?;
+            gas_used += gas_used_read;
+            charge(raw_keys.len() as u64)?;
+
+            for raw_key in &raw_keys {
+                if !raw_key.starts_with(&namespace_prefix) {
+                    break 'pages;
+                }
+                let key = match decode_ordered_key(&symmetrical_key, raw_key) {
+                    Some(key) => key,
+                    None => continue,
+                };
+                if end.map_or(false, |end| key.as_slice() >= end) {
+                    break 'pages;
+                }
+                keys.insert(key);
+            }
+
+            match raw_keys.last() {
+                Some(last_key) if raw_keys.len() == RAW_KEYS_PAGE_SIZE as usize => {
+                    from = last_key.clone();
+                    from.push(0);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    Ok((keys, gas_used))
+}
+
+/// Decrypts the plaintext key of a raw state entry, with the current state key or with the key
+/// of the previous epoch. Returns `None` for entries in the legacy format, and for entries that
+/// aren't encrypted with either key.
+fn decrypt_raw_key(raw_key: &[u8], state_keys: &StateKeys) -> Option<Vec<u8>> {
+    if is_ordered_key(raw_key) {
+        return std::iter::once(&state_keys.current)
+            .chain(state_keys.previous.iter())
+            .find_map(|contract_key| {
+                decode_ordered_key(&get_symmetrical_key_new(contract_key), raw_key)
+            });
+    }
+
+    let encrypted_key: EncryptedKey = match bincode2::deserialize(raw_key) {
+        Ok(encrypted_key) if encrypted_key.magic_bytes == ENCRYPTED_KEY_MAGIC_BYTES => {
+            encrypted_key
+        }
+        _ => return None,
+    };
+
+    std::iter::once(&state_keys.current)
+        .chain(state_keys.previous.iter())
+        .find_map(|contract_key| {
+            get_symmetrical_key_new(contract_key)
+                .decrypt_siv(&encrypted_key.data, Some(&[]))
+                .ok()
+        })
+}
+
+/// Re-encrypts raw state entries (as stored by the chain) from one state key to another.
+/// Entries that are already encrypted with the new key, e.g. because the contract wrote them
+/// during the re-encryption, are skipped.
+/// Returns the amount of entries that were re-encrypted.
+pub fn reencrypt_raw_entries(
+    context: &Ctx,
+    entries: &[(Vec<u8>, Vec<u8>)],
+    from_key: &ContractKey,
+    to_key: &ContractKey,
+) -> Result<u64, WasmEngineError> {
+    let mut reencrypted = vec![];
+
+    for (raw_key, raw_value) in entries {
+        let (new_raw_key, new_raw_value) =
+            match reencrypt_raw_entry(raw_key, raw_value, from_key, to_key)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+        remove_db(context, raw_key)?;
+
+        // The contract may have written a newer value since, which we must not overwrite
+        let (existing_value, _) = read_db(context, &new_raw_key)?;
+        if existing_value.is_none() {
+            reencrypted.push((new_raw_key, new_raw_value));
+        }
+    }
+
+    let reencrypted_count = reencrypted.len() as u64;
+    write_multiple_keys(context, reencrypted)?;
+
+    Ok(reencrypted_count)
+}
+
+fn reencrypt_raw_entry(
+    raw_key: &[u8],
+    raw_value: &[u8],
+    from_key: &ContractKey,
+    to_key: &ContractKey,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>, WasmEngineError> {
+    if is_ordered_key(raw_key) {
+        return reencrypt_ordered_entry(raw_key, raw_value, from_key, to_key);
+    }
+
+    let encrypted_key: EncryptedKey = match bincode2::deserialize(raw_key) {
+        Ok(encrypted_key) if encrypted_key.magic_bytes == ENCRYPTED_KEY_MAGIC_BYTES => {
+            encrypted_key
+        }
+        // The keys of the legacy format are digests, so we can't recover the plaintext key.
+        // Such entries are upgraded when the contract reads them.
+        _ => {
+            warn!("can't re-encrypt state entries in the legacy format");
+            return Err(WasmEngineError::DecryptionError);
+        }
+    };
+
+    let plaintext_key =
+        match get_symmetrical_key_new(from_key).decrypt_siv(&encrypted_key.data, Some(&[])) {
+            Ok(plaintext_key) => plaintext_key,
+            Err(_)
+                if get_symmetrical_key_new(to_key)
+                    .decrypt_siv(&encrypted_key.data, Some(&[]))
+                    .is_ok() =>
+            {
+                trace!("state entry was already re-encrypted, skipping");
+                return Ok(None);
+            }
+            Err(err) => {
+                warn!("failed to decrypt state key for re-encryption: {:?}", err);
+                return Err(WasmEngineError::DecryptionError);
+            }
+        };
+
+    let encrypted_value: EncryptedValue = bincode2::deserialize(raw_value).map_err(|err| {
+        warn!(
+            "failed to deserialize state value for re-encryption: {:?}",
+            err.to_string()
+        );
+        WasmEngineError::DecryptionError
+    })?;
+    let plaintext_value = decrypt_value_new(
+        &encrypted_key.data,
+        &encrypted_value.data,
+        from_key,
+        &encrypted_value.salt,
+    )?;
+
+    let new_encrypted_key = EncryptedKey {
+        magic_bytes: ENCRYPTED_KEY_MAGIC_BYTES.to_vec(),
+        consensus_seed_version: CONSENSUS_SEED_VERSION,
+        state_encryption_version: STATE_ENCRYPTION_VERSION,
+        data: encrypt_key_new(&plaintext_key, to_key)?,
+    };
+    let new_encrypted_value = EncryptedValue {
+        data: encrypt_value_new(
+            &new_encrypted_key.data,
+            &plaintext_value,
+            to_key,
+            &encrypted_value.salt,
+        )?,
+        salt: encrypted_value.salt,
+    };
+
+    Ok(Some((
+        serialize_reencrypted(&new_encrypted_key)?,
+        serialize_reencrypted(&new_encrypted_value)?,
+    )))
+}
+
+fn reencrypt_ordered_entry(
+    raw_key: &[u8],
+    raw_value: &[u8],
+    from_key: &ContractKey,
+    to_key: &ContractKey,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>, WasmEngineError> {
+    let from_symmetrical_key = get_symmetrical_key_new(from_key);
+    let to_symmetrical_key = get_symmetrical_key_new(to_key);
+
+    let plaintext_key = match decode_ordered_key(&from_symmetrical_key, raw_key) {
+        Some(plaintext_key) => plaintext_key,
+        None if decode_ordered_key(&to_symmetrical_key, raw_key).is_some() => {
+            trace!("ordered state entry was already re-encrypted, skipping");
+            return Ok(None);
+        }
+        None => {
+            warn!("failed to decode ordered state key for re-encryption");
+            return Err(WasmEngineError::DecryptionError);
+        }
+    };
+
+    let encrypted_value: EncryptedValue = bincode2::deserialize(raw_value).map_err(|err| {
+        warn!(
+            "failed to deserialize state value for re-encryption: {:?}",
+            err.to_string()
+        );
+        WasmEngineError::DecryptionError
+    })?;
+    let plaintext_value = decrypt_value_new(
+        raw_key,
+        &encrypted_value.data,
+        from_key,
+        &encrypted_value.salt,
+    )?;
+
+    let new_raw_key = encode_ordered_key(&to_symmetrical_key, &plaintext_key)?;
+    let new_encrypted_value = EncryptedValue {
+        data: encrypt_value_new(
+            &new_raw_key,
+            &plaintext_value,
+            to_key,
+            &encrypted_value.salt,
+        )?,
+        salt: encrypted_value.salt,
+    };
+
+    Ok(Some((
+        new_raw_key,
+        serialize_reencrypted(&new_encrypted_value)?,
+    )))
+}
+
+fn serialize_reencrypted<T: Serialize>(value: &T) -> Result<Vec<u8>, WasmEngineError> {
+    bincode2::serialize(value).map_err(|err| {
+        warn!(
+            "failed to serialize a re-encrypted state entry: {:?}",
+            err.to_string()
+        );
+        WasmEngineError::SerializationError
+    })
 }
 
 fn field_name_digest(field_name: &[u8], contract_key: &ContractKey) -> [u8; 32] {
@@ -337,20 +1202,103 @@ fn read_db(context: &Ctx, key: &[u8]) -> Result<(Option<Vec<u8>>, u64), WasmEngi
         }
     };
 
+    // In a query at a past height the host answers with the proof of the read instead of the
+    // value, and the value is taken from the proof
+    if is_historical() {
+        let proof = value.ok_or_else(|| {
+            warn!("read_db() got no proof for a read at a past height");
+            WasmEngineError::HostMisbehavior
+        })?;
+        return Ok((verify_historical_read(key, &proof)?, gas_used));
+    }
+
     Ok((value, gas_used))
 }
 
+/// Safe wrapper around reads of the raw keys of the contract storage. Returns up to `limit` keys,
+/// in order, starting from `start`.
+fn read_db_keys(
+    context: &Ctx,
+    start: &[u8],
+    limit: u32,
+) -> Result<(Vec<Vec<u8>>, u64), WasmEngineError> {
+    // Only the reads of single keys come with proofs
+    if is_historical() {
+        warn!("read_db_keys() can't be proven at a past height");
+        return Err(WasmEngineError::UnprovableRead);
+    }
+
+    let mut ocall_return = OcallReturn::Success;
+    let mut enclave_buffer = std::mem::MaybeUninit::<EnclaveBuffer>::uninit();
+    let mut vm_err = UntrustedVmError::default();
+    let mut gas_used = 0_u64;
+
+    let keys = unsafe {
+        let status = ocalls::ocall_read_db_keys(
+            (&mut ocall_return) as *mut _,
+            context.unsafe_clone(),
+            (&mut vm_err) as *mut _,
+            (&mut gas_used) as *mut _,
+            enclave_buffer.as_mut_ptr(),
+            start.as_ptr(),
+            start.len(),
+            limit,
+        );
+        match status {
+            sgx_status_t::SGX_SUCCESS => { /* continue */ }
+            error_status => {
+                warn!(
+                    "read_db_keys() got an error from ocall_read_db_keys, stopping wasm: {:?}",
+                    error_status
+                );
+                return Err(WasmEngineError::FailedOcall(vm_err));
+            }
+        }
+
+        match ocall_return {
+            OcallReturn::Success => {
+                let enclave_buffer = enclave_buffer.assume_init();
+                ecalls::recover_buffer(enclave_buffer)?
+            }
+            OcallReturn::Failure => {
+                return Err(WasmEngineError::FailedOcall(vm_err));
+            }
+            OcallReturn::Panic => return Err(WasmEngineError::Panic),
+        }
+    };
+
+    let keys: Vec<Vec<u8>> = match keys {
+        Some(keys) => serde_json::from_slice(&keys).map_err(|err| {
+            warn!("read_db_keys() got keys that can't be parsed: {:?}", err);
+            WasmEngineError::DeserializationError
+        })?,
+        None => vec![],
+    };
+    if keys.len() > limit as usize {
+        warn!(
+            "read_db_keys() got {} keys but asked for at most {}",
+            keys.len(),
+            limit
+        );
+        return Err(WasmEngineError::DeserializationError);
+    }
+
+    Ok((keys, gas_used))
+}
+
 /// Safe wrapper around reads from the contract storage
 fn remove_db(context: &Ctx, key: &[u8]) -> Result<u64, WasmEngineError> {
     let mut ocall_return = OcallReturn::Success;
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
+    let mut removed_size = 0_u64;
     match unsafe {
         ocalls::ocall_remove_db(
             (&mut ocall_return) as *mut _,
             context.unsafe_clone(),
             (&mut vm_err) as *mut _,
             (&mut gas_used) as *mut _,
+            (&mut removed_size) as *mut _,
             key.as_ptr(),
             key.len(),
         )
@@ -360,7 +1308,12 @@ fn remove_db(context: &Ctx, key: &[u8]) -> Result<u64, WasmEngineError> {
     }
 
     match ocall_return {
-        OcallReturn::Success => Ok(gas_used),
+        OcallReturn::Success => {
+            if is_measured(key) {
+                record_stored_bytes(0, removed_size);
+            }
+            Ok(gas_used)
+        }
         OcallReturn::Failure => Err(WasmEngineError::FailedOcall(vm_err)),
         OcallReturn::Panic => Err(WasmEngineError::Panic),
     }
@@ -372,12 +1325,14 @@ fn write_db(context: &Ctx, key: &[u8], value: &[u8]) -> Result<u64, WasmEngineEr
     let mut ocall_return = OcallReturn::Success;
     let mut vm_err = UntrustedVmError::default();
     let mut gas_used = 0_u64;
+    let mut replaced_size = 0_u64;
     match unsafe {
         ocalls::ocall_write_db(
             (&mut ocall_return) as *mut _,
             context.unsafe_clone(),
             (&mut vm_err) as *mut _,
             (&mut gas_used) as *mut _,
+            (&mut replaced_size) as *mut _,
             key.as_ptr(),
             key.len(),
             value.as_ptr(),
@@ -389,7 +1344,12 @@ fn write_db(context: &Ctx, key: &[u8], value: &[u8]) -> Result<u64, WasmEngineEr
     }
 
     match ocall_return {
-        OcallReturn::Success => Ok(gas_used),
+        OcallReturn::Success => {
+            if is_measured(key) {
+                record_stored_bytes(raw_entry_size(key, value), replaced_size);
+            }
+            Ok(gas_used)
+        }
         OcallReturn::Failure => Err(WasmEngineError::FailedOcall(vm_err)),
         OcallReturn::Panic => Err(WasmEngineError::Panic),
     }
@@ -490,3 +1450,212 @@ fn encrypt_key_new(
             WasmEngineError::EncryptionError
     })
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+
+    /// Raw keys as the host stores them. The "encryption" prepends `b"enc:"`, so that the keys
+    /// without it stand for entries in the legacy format.
+    fn raw_keys(plaintext_keys: &[Vec<u8>]) -> BTreeMap<Vec<u8>, ()> {
+        plaintext_keys
+            .iter()
+            .map(|key| ([&b"enc:"[..], key.as_slice()].concat(), ()))
+            .collect()
+    }
+
+    fn decrypt(raw_key: &[u8]) -> Option<Vec<u8>> {
+        raw_key.strip_prefix(&b"enc:"[..]).map(|key| key.to_vec())
+    }
+
+    /// Scans the raw keys like `remove_prefix_from_state`, and returns the keys it found, the
+    /// pages it read and the keys it charged for
+    fn scan(
+        stored: &mut BTreeMap<Vec<u8>, ()>,
+        prefix: &[u8],
+        remove: bool,
+    ) -> (Vec<Vec<u8>>, u64, u64) {
+        let pages = Cell::new(0);
+        let mut charged = 0;
+        let snapshot = stored.clone();
+        let mut removed = vec![];
+
+        let (found, gas_used) = scan_raw_keys(
+            prefix,
+            RAW_KEYS_PAGE_SIZE,
+            |start, limit| {
+                pages.set(pages.get() + 1);
+                let page: Vec<Vec<u8>> = snapshot
+                    .range(start.to_vec()..)
+                    .take(limit as usize)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                Ok((page, 1))
+            },
+            decrypt,
+            |raw_key| {
+                if remove {
+                    removed.push(raw_key.to_vec());
+                }
+                Ok(0)
+            },
+            |keys| {
+                charged += keys;
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(gas_used, pages.get());
+
+        for raw_key in removed {
+            stored.remove(&raw_key);
+        }
+        (found, pages.get(), charged)
+    }
+
+    pub fn test_scan_prefix_in_state_pages() {
+        let page_size = RAW_KEYS_PAGE_SIZE as usize;
+
+        // More keys than fit in two pages, half of them with the prefix
+        let keys: Vec<Vec<u8>> = (0..(2 * page_size + 10) as u32)
+            .map(|i| {
+                let namespace: &[u8] = if i % 2 == 0 { b"a/" } else { b"b/" };
+                [namespace, &i.to_be_bytes()[..]].concat()
+            })
+            .collect();
+        let mut stored = raw_keys(&keys);
+
+        let (found, pages, charged) = scan(&mut stored, b"a/", false);
+        assert_eq!(pages, 3);
+        assert_eq!(charged, keys.len() as u64);
+        assert_eq!(found.len(), page_size + 5);
+        assert!(found.iter().all(|key| key.starts_with(b"a/")));
+        assert_eq!(stored.len(), keys.len());
+
+        // Removing them leaves the other keys
+        let (removed, _, _) = scan(&mut stored, b"a/", true);
+        assert_eq!(removed, found);
+        assert_eq!(stored.len(), keys.len() - found.len());
+        assert!(scan(&mut stored, b"a/", false).0.is_empty());
+
+        // A store that fills its last page exactly reads one more, empty, page
+        let keys: Vec<Vec<u8>> = (0..(2 * page_size) as u32)
+            .map(|i| i.to_be_bytes().to_vec())
+            .collect();
+        let (found, pages, charged) = scan(&mut raw_keys(&keys), b"", false);
+        assert_eq!(found.len(), keys.len());
+        assert_eq!(pages, 3);
+        assert_eq!(charged, keys.len() as u64);
+
+        // The scan stops on the page the contract runs out of gas on
+        let mut pages = 0;
+        let result = scan_raw_keys(
+            b"",
+            RAW_KEYS_PAGE_SIZE,
+            |start, limit| {
+                pages += 1;
+                let page = raw_keys(&keys)
+                    .range(start.to_vec()..)
+                    .take(limit as usize)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                Ok((page, 0))
+            },
+            decrypt,
+            |_| Ok(0),
+            |_| Err(WasmEngineError::OutOfGas),
+        );
+        assert!(matches!(result, Err(WasmEngineError::OutOfGas)));
+        assert_eq!(pages, 1);
+    }
+
+    pub fn test_scan_prefix_in_state_skips_reserved_and_legacy_keys() {
+        let reserved_key = [reserved_keys::PREFIX, &b"a/"[..]].concat();
+        let mut stored = raw_keys(&[b"a/1".to_vec(), b"a/2".to_vec(), reserved_key.clone()]);
+        // Entries in the legacy format are keyed by a digest and can't be matched
+        stored.insert(b"legacy a/3".to_vec(), ());
+
+        let (removed, _, charged) = scan(&mut stored, b"a/", true);
+        assert_eq!(removed, vec![b"a/1".to_vec(), b"a/2".to_vec()]);
+        assert_eq!(charged, 4);
+        assert_eq!(stored.len(), 2);
+
+        // Not even an empty prefix or the reserved prefix itself finds reserved keys
+        assert!(scan(&mut stored, b"", true).0.is_empty());
+        assert!(scan(&mut stored, reserved_keys::PREFIX, true).0.is_empty());
+        assert!(stored.contains_key(&[&b"enc:"[..], reserved_key.as_slice()].concat()));
+    }
+
+    pub fn test_measure_stored_bytes() {
+        // Outside of a measurement, what the host reports isn't kept
+        record_stored_bytes(10, 0);
+        let ((), stored) = measure_stored_bytes(None, || {
+            record_stored_bytes(100, 20);
+            // A nested measurement, e.g. of a contract that the execution calls
+            let ((), inner) = measure_stored_bytes(None, || record_stored_bytes(7, 3));
+            assert_eq!(
+                inner,
+                StoredBytes {
+                    added: 7,
+                    removed: 3
+                }
+            );
+            record_stored_bytes(1, 0);
+        });
+        assert_eq!(
+            stored,
+            StoredBytes {
+                added: 101,
+                removed: 20
+            }
+        );
+        assert_eq!(measure_stored_bytes(None, || ()).1, StoredBytes::default());
+
+        // While the usage is counted, only the raw keys that the count passed are measured
+        let (measured, _) = measure_stored_bytes(Some(b"m"), || {
+            let inner = measure_stored_bytes(None, || is_measured(b"z")).0;
+            (
+                is_measured(b"a"),
+                is_measured(b"m"),
+                is_measured(b"n"),
+                inner,
+            )
+        });
+        assert_eq!(measured, (true, true, false, true));
+        assert!(!measure_stored_bytes(Some(b""), || is_measured(b"a")).0);
+        assert!(is_measured(b"z"));
+    }
+
+    pub fn test_with_cached_keys_with_prefix() {
+        let mut kv_cache = KvCache::new();
+        // A write that wasn't flushed yet, and one that overwrites a stored key
+        kv_cache.write(b"a/new", b"1");
+        kv_cache.write(b"a/stored", b"2");
+        kv_cache.store_in_ro_cache(b"a/read", b"3");
+        kv_cache.write(b"b/other", b"4");
+        kv_cache.write(&[reserved_keys::PREFIX, &b"a/"[..]].concat(), b"5");
+
+        let keys = with_cached_keys_with_prefix(
+            vec![b"a/stored".to_vec(), b"a/unread".to_vec()],
+            &kv_cache,
+            b"a/",
+        );
+        assert_eq!(
+            keys.into_iter().collect::<Vec<_>>(),
+            vec![
+                b"a/new".to_vec(),
+                b"a/read".to_vec(),
+                b"a/stored".to_vec(),
+                b"a/unread".to_vec(),
+            ]
+        );
+
+        // The empty prefix leaves the reserved keys out too
+        let keys = with_cached_keys_with_prefix(vec![], &kv_cache, b"");
+        assert_eq!(keys.len(), 4);
+        assert!(keys.iter().all(|key| !reserved_keys::is_reserved(key)));
+    }
+}
@@ -0,0 +1,178 @@
+//! Limits the failures to decrypt the messages of the queries a client sends to the node.
+//!
+//! Probing how the enclave handles variations of a ciphertext takes many queries, which cost the
+//! client nothing, unlike transactions. The failures to decrypt the messages of the top-level
+//! queries that the node serves are counted per connection of the client in a sliding window,
+//! and once a connection has failed too often, its queries are refused before they are
+//! decrypted, until the window slides past the failures. The host flags the connection of a
+//! query in env, as the address of the client without its port, so reconnecting doesn't reset
+//! the count, and a client can't lock out the queries of others by failing with their public key.
+//!
+//! Transactions, and the queries that contracts make, are neither counted nor refused: whether
+//! they fail has to be the same on every node, and the counts depend on what each node has seen.
+//! Queries whose connection the host doesn't know, like the ones of the ABCI query endpoint,
+//! aren't limited either.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::SgxMutex;
+use std::time::{Duration, Instant};
+use std::untrusted::time::InstantEx;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use enclave_crypto::{sha_256, HASH_SIZE};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::decryption_limit::{
+    MAX_FAILURES_PER_WINDOW, MAX_TRACKED_KEYS, WINDOW_SECS,
+};
+use crate::metrics::record_refused_decryption;
+use crate::panic_isolation::LockOrRecover;
+use crate::types::SecretMessage;
+
+/// The hash of the connection a query came from
+pub type ConnectionId = [u8; HASH_SIZE];
+
+#[derive(Default)]
+pub struct DecryptionFailures {
+    /// The times of the failures in the window, oldest first
+    failures: HashMap<ConnectionId, VecDeque<Instant>>,
+}
+
+impl DecryptionFailures {
+    fn window() -> Duration {
+        Duration::from_secs(WINDOW_SECS)
+    }
+
+    /// Forgets the failures that the window slid past
+    fn expire(failures: &mut VecDeque<Instant>, now: Instant) {
+        while let Some(oldest) = failures.front() {
+            if now.saturating_duration_since(*oldest) < Self::window() {
+                break;
+            }
+            failures.pop_front();
+        }
+    }
+
+    pub fn record_at(&mut self, key: &ConnectionId, now: Instant) {
+        if !self.failures.contains_key(key) && self.failures.len() >= MAX_TRACKED_KEYS {
+            self.evict(now);
+        }
+
+        let failures = self.failures.entry(*key).or_default();
+        Self::expire(failures, now);
+        // Failures past the limit don't change anything until the window slides
+        if failures.len() <= MAX_FAILURES_PER_WINDOW {
+            failures.push_back(now);
+        }
+    }
+
+    pub fn exceeds_limit_at(&mut self, key: &ConnectionId, now: Instant) -> bool {
+        match self.failures.get_mut(key) {
+            Some(failures) => {
+                Self::expire(failures, now);
+                failures.len() > MAX_FAILURES_PER_WINDOW
+            }
+            None => false,
+        }
+    }
+
+    /// Makes room for another key, by dropping the keys without failures in the window, or else
+    /// the key whose last failure is the oldest
+    fn evict(&mut self, now: Instant) {
+        self.failures.retain(|_, failures| {
+            Self::expire(failures, now);
+            !failures.is_empty()
+        });
+
+        if self.failures.len() < MAX_TRACKED_KEYS {
+            return;
+        }
+        let stalest = self
+            .failures
+            .iter()
+            .min_by_key(|(_, failures)| failures.back().copied())
+            .map(|(key, _)| *key);
+        if let Some(key) = stalest {
+            self.failures.remove(&key);
+        }
+    }
+
+    pub fn tracked_keys(&self) -> usize {
+        self.failures.len()
+    }
+}
+
+lazy_static! {
+    static ref DECRYPTION_FAILURES: SgxMutex<DecryptionFailures> =
+        SgxMutex::new(DecryptionFailures::default());
+}
+
+pub fn connection_id(connection: &str) -> ConnectionId {
+    sha_256(connection.as_bytes())
+}
+
+/// Decrypts the message of a query that the node serves to a client on `connection`, unless the
+/// connection failed too often recently, and counts the failure if it fails
+pub fn decrypt_query_msg(
+    secret_msg: &SecretMessage,
+    connection: &str,
+) -> Result<Vec<u8>, EnclaveError> {
+    let connection = connection_id(connection);
+    if DECRYPTION_FAILURES
+        .lock_or_recover()
+        .exceeds_limit_at(&connection, Instant::now())
+    {
+        warn!(
+            "refusing to decrypt a query of connection {}, which failed to decrypt too often",
+            hex::encode(connection)
+        );
+        record_refused_decryption();
+        return Err(EnclaveError::TooManyDecryptionFailures);
+    }
+
+    secret_msg.decrypt().map_err(|err| {
+        DECRYPTION_FAILURES
+            .lock_or_recover()
+            .record_at(&connection, Instant::now());
+        err
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_decryption_limit() {
+        let mut failures = DecryptionFailures::default();
+        let start = Instant::now();
+        let key = connection_id("10.0.0.1");
+        let other_key = connection_id("10.0.0.2");
+
+        for i in 0..MAX_FAILURES_PER_WINDOW {
+            assert!(!failures.exceeds_limit_at(&key, start));
+            failures.record_at(&key, start + Duration::from_millis(i as u64));
+        }
+        assert!(!failures.exceeds_limit_at(&key, start));
+
+        let last = start + Duration::from_millis(MAX_FAILURES_PER_WINDOW as u64);
+        failures.record_at(&key, last);
+        assert!(failures.exceeds_limit_at(&key, last));
+        assert!(!failures.exceeds_limit_at(&other_key, last));
+
+        // The limit lifts once the window slides past the first failures
+        let later = start + DecryptionFailures::window() + Duration::from_millis(1);
+        assert!(!failures.exceeds_limit_at(&key, later));
+        assert!(failures.failures.get(&key).unwrap().len() <= MAX_FAILURES_PER_WINDOW);
+
+        // The keys that are tracked are bounded
+        let mut failures = DecryptionFailures::default();
+        for i in 0..MAX_TRACKED_KEYS + 1 {
+            let key = connection_id(&i.to_string());
+            failures.record_at(&key, start + Duration::from_millis(i as u64));
+        }
+        assert_eq!(failures.tracked_keys(), MAX_TRACKED_KEYS);
+        assert!(!failures.failures.contains_key(&connection_id("0")));
+    }
+}
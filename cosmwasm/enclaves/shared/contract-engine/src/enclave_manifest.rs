@@ -0,0 +1,434 @@
+use log::*;
+use protobuf::{CodedOutputStream, ProtobufEnum, ProtobufResult};
+
+use cosmos_proto::tx::signing::SignMode;
+use enclave_cosmos_types::types::{
+    TYPE_URL_MULTISIG_LEGACY_AMINO_PUBKEY, TYPE_URL_SECP256K1_PUBKEY,
+};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::features;
+use crate::gas::{WasmCosts, READ_BASE_GAS, WRITE_BASE_GAS};
+use crate::handle_routes::handle_routes;
+
+/// Version of the manifest format, see `secret/compute/v1beta1/enclave_manifest.proto`
+const MANIFEST_VERSION: u32 = 1;
+
+const PUBKEY_TYPES: &[&str] = &[
+    TYPE_URL_SECP256K1_PUBKEY,
+    TYPE_URL_MULTISIG_LEGACY_AMINO_PUBKEY,
+];
+
+const SIGN_MODES: &[SignMode] = &[
+    SignMode::SIGN_MODE_DIRECT,
+    SignMode::SIGN_MODE_LEGACY_AMINO_JSON,
+    SignMode::SIGN_MODE_EIP_191,
+];
+
+const ENV_VERSIONS: &[&str] = &["v0.10", "v1"];
+
+const CONTRACT_FEATURES: &[&str] = &[
+    features::declared::RANDOM,
+    features::declared::IBC,
+    features::declared::COARSE_PADDING,
+    features::declared::CONSTANT_SHAPE_EXECUTE,
+    features::declared::CONSTANT_SHAPE_REPLY,
+    features::declared::CONSTANT_SHAPE_QUERY,
+    features::declared::PADDED_STORAGE_ACCESS,
+    features::declared::ORDERED_KEYS,
+];
+
+struct HostFunctionInfo {
+    name: &'static str,
+    gas_id: &'static str,
+    gas_cost: u64,
+}
+
+/// The host functions linked into contracts, see `Engine::link_host_functions`.
+/// Keep this list in sync when adding host functions.
+fn host_functions() -> Vec<HostFunctionInfo> {
+    let costs = WasmCosts::default();
+    let host_fn = |name, gas_id, gas_cost| HostFunctionInfo {
+        name,
+        gas_id,
+        gas_cost,
+    };
+
+    vec![
+        host_fn("db_read", "read_base_gas", READ_BASE_GAS),
+        host_fn("db_write", "write_base_gas", WRITE_BASE_GAS),
+        host_fn("db_remove", "", 0),
+        host_fn(
+            "db_remove_range",
+            "external_db_remove_range",
+            costs.external_db_remove_range as u64,
+        ),
+        host_fn(
+            "canonicalize_address",
+            "external_canonicalize_address",
+            costs.external_canonicalize_address as u64,
+        ),
+        host_fn(
+            "humanize_address",
+            "external_humanize_address",
+            costs.external_humanize_address as u64,
+        ),
+        host_fn("query_chain", "", 0),
+        host_fn(
+            "addr_canonicalize",
+            "external_canonicalize_address",
+            costs.external_canonicalize_address as u64,
+        ),
+        host_fn(
+            "addr_humanize",
+            "external_humanize_address",
+            costs.external_humanize_address as u64,
+        ),
+        host_fn(
+            "addr_validate",
+            "external_addr_validate",
+            costs.external_addr_validate as u64,
+        ),
+        host_fn("debug_print", "", 0),
+        host_fn("debug", "", 0),
+        host_fn(
+            "secp256k1_verify",
+            "external_secp256k1_verify",
+            costs.external_secp256k1_verify as u64,
+        ),
+        host_fn(
+            "secp256k1_recover_pubkey",
+            "external_secp256k1_recover_pubkey",
+            costs.external_secp256k1_recover_pubkey as u64,
+        ),
+        host_fn(
+            "ed25519_verify",
+            "external_ed25519_verify",
+            costs.external_ed25519_verify as u64,
+        ),
+        host_fn(
+            "ed25519_batch_verify",
+            "external_ed25519_batch_verify_base",
+            costs.external_ed25519_batch_verify_base as u64,
+        ),
+        host_fn(
+            "secp256k1_sign",
+            "external_secp256k1_sign",
+            costs.external_secp256k1_sign as u64,
+        ),
+        host_fn(
+            "ed25519_sign",
+            "external_ed25519_sign",
+            costs.external_ed25519_sign as u64,
+        ),
+        host_fn(
+            "check_gas",
+            "external_check_gas_used",
+            costs.external_check_gas_used as u64,
+        ),
+        host_fn(
+            "gas_evaporate",
+            "external_minimum_gas_evaporate",
+            costs.external_minimum_gas_evaporate as u64,
+        ),
+        host_fn(
+            "execute_contracts_atomic",
+            "external_execute_contracts_atomic",
+            costs.external_execute_contracts_atomic as u64,
+        ),
+        host_fn(
+            "set_upgrade_policy",
+            "external_set_upgrade_policy",
+            costs.external_set_upgrade_policy as u64,
+        ),
+        host_fn(
+            "announce_migration",
+            "external_announce_migration",
+            costs.external_announce_migration as u64,
+        ),
+        host_fn(
+            "migration_checkpoint",
+            "external_migration_checkpoint",
+            costs.external_migration_checkpoint as u64,
+        ),
+        host_fn(
+            "migration_cursor",
+            "external_migration_checkpoint",
+            costs.external_migration_checkpoint as u64,
+        ),
+        host_fn(
+            "set_auditor_key",
+            "external_set_auditor_key",
+            costs.external_set_auditor_key as u64,
+        ),
+        host_fn(
+            "fee_params",
+            "external_fee_params",
+            costs.external_fee_params as u64,
+        ),
+        host_fn(
+            "pending_replies",
+            "external_pending_replies",
+            costs.external_pending_replies as u64,
+        ),
+        host_fn(
+            "unique_id",
+            "external_unique_id",
+            costs.external_unique_id as u64,
+        ),
+        host_fn(
+            "canonical_json",
+            "external_canonical_json",
+            costs.external_canonical_json as u64,
+        ),
+        host_fn(
+            "transient_read",
+            "external_transient_read",
+            costs.external_transient_read as u64,
+        ),
+        host_fn(
+            "transient_write",
+            "external_transient_write",
+            costs.external_transient_write as u64,
+        ),
+        host_fn(
+            "bech32_encode",
+            "external_bech32_encode",
+            costs.external_bech32_encode as u64,
+        ),
+        host_fn(
+            "bech32_decode",
+            "external_bech32_decode",
+            costs.external_bech32_decode as u64,
+        ),
+        host_fn(
+            "bech32_validate",
+            "external_bech32_validate",
+            costs.external_bech32_validate as u64,
+        ),
+        host_fn(
+            "base64_encode",
+            "external_codec",
+            costs.external_codec as u64,
+        ),
+        host_fn(
+            "base64_decode",
+            "external_codec",
+            costs.external_codec as u64,
+        ),
+        host_fn("hex_encode", "external_codec", costs.external_codec as u64),
+        host_fn("hex_decode", "external_codec", costs.external_codec as u64),
+        host_fn(
+            "decode_sdk_response",
+            "external_decode_sdk_response",
+            costs.external_decode_sdk_response as u64,
+        ),
+        host_fn(
+            "decode_any",
+            "external_decode_sdk_response",
+            costs.external_decode_sdk_response as u64,
+        ),
+        host_fn(
+            "derive_contract_subkey",
+            "external_derive_contract_subkey",
+            costs.external_derive_contract_subkey as u64,
+        ),
+        host_fn(
+            "designate_viewer",
+            "external_designate_viewer",
+            costs.external_designate_viewer as u64,
+        ),
+        host_fn(
+            "oblivious_read",
+            "external_oblivious_access",
+            costs.external_oblivious_access as u64,
+        ),
+        host_fn(
+            "oblivious_write",
+            "external_oblivious_access",
+            costs.external_oblivious_access as u64,
+        ),
+        host_fn(
+            "oblivious_remove",
+            "external_oblivious_access",
+            costs.external_oblivious_access as u64,
+        ),
+        host_fn("db_write_ordered", "write_base_gas", WRITE_BASE_GAS),
+        host_fn("db_scan", "external_db_scan", costs.external_db_scan as u64),
+        host_fn("db_next", "external_db_next", costs.external_db_next as u64),
+        host_fn(
+            "random_commit",
+            "external_random_commit",
+            costs.external_random_commit as u64,
+        ),
+        host_fn(
+            "random_reveal",
+            "external_random_reveal",
+            costs.external_random_reveal as u64,
+        ),
+    ]
+}
+
+fn build_features() -> Vec<&'static str> {
+    let mut build_features = vec![];
+    if cfg!(feature = "production") {
+        build_features.push("production");
+    }
+    if cfg!(feature = "random") {
+        build_features.push("random");
+    }
+    if cfg!(feature = "light-client-validation") {
+        build_features.push("light-client-validation");
+    }
+    build_features
+}
+
+/// The capabilities contracts can require in their requirements section: the contract features,
+/// the env versions as `env_<version>` and the names of the host functions.
+fn capabilities() -> Vec<String> {
+    let mut capabilities: Vec<String> = CONTRACT_FEATURES.iter().map(|f| f.to_string()).collect();
+    capabilities.extend(ENV_VERSIONS.iter().map(|v| format!("env_{}", v)));
+    capabilities.extend(host_functions().iter().map(|f| f.name.to_string()));
+    capabilities
+}
+
+/// Whether contracts can import a host function called `name`
+pub fn is_host_function(name: &str) -> bool {
+    host_functions().iter().any(|f| f.name == name)
+}
+
+/// Returns the capabilities in `required` that this enclave doesn't support
+pub fn missing_capabilities(required: &[String]) -> Vec<String> {
+    let capabilities = capabilities();
+    let mut missing = vec![];
+    for capability in required {
+        if !capabilities.contains(capability) && !missing.contains(capability) {
+            missing.push(capability.clone());
+        }
+    }
+    missing
+}
+
+fn encode_message<F>(write_fields: F) -> ProtobufResult<Vec<u8>>
+where
+    F: FnOnce(&mut CodedOutputStream) -> ProtobufResult<()>,
+{
+    let mut bytes = vec![];
+    {
+        let mut os = CodedOutputStream::vec(&mut bytes);
+        write_fields(&mut os)?;
+        os.flush()?;
+    }
+    Ok(bytes)
+}
+
+fn encode_manifest() -> ProtobufResult<Vec<u8>> {
+    encode_message(|os| {
+        os.write_uint32(1, MANIFEST_VERSION)?;
+        os.write_string(2, env!("CARGO_PKG_VERSION"))?;
+
+        for route in handle_routes() {
+            let info = encode_message(|os| {
+                os.write_uint32(1, route.handle_type as u32)?;
+                os.write_string(2, route.export)
+            })?;
+            os.write_bytes(3, &info)?;
+        }
+
+        for pubkey_type in PUBKEY_TYPES {
+            os.write_string(4, pubkey_type)?;
+        }
+
+        for sign_mode in SIGN_MODES {
+            let info = encode_message(|os| {
+                os.write_int32(1, sign_mode.value())?;
+                os.write_string(2, &format!("{:?}", sign_mode))
+            })?;
+            os.write_bytes(5, &info)?;
+        }
+
+        for host_function in host_functions() {
+            let info = encode_message(|os| {
+                os.write_string(1, host_function.name)?;
+                os.write_string(2, host_function.gas_id)?;
+                os.write_uint64(3, host_function.gas_cost)
+            })?;
+            os.write_bytes(6, &info)?;
+        }
+
+        for env_version in ENV_VERSIONS {
+            os.write_string(7, env_version)?;
+        }
+
+        for feature in CONTRACT_FEATURES {
+            os.write_string(8, feature)?;
+        }
+
+        for feature in build_features() {
+            os.write_string(9, feature)?;
+        }
+
+        for capability in capabilities() {
+            os.write_string(10, &capability)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Returns the protobuf encoded `EnclaveManifest`, which describes the capabilities of this
+/// enclave so that the chain and clients can adapt to the version that is running.
+pub fn get_enclave_manifest() -> Result<Vec<u8>, EnclaveError> {
+    encode_manifest().map_err(|err| {
+        warn!("failed to encode the enclave manifest: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use protobuf::CodedInputStream;
+
+    pub fn test_encode_manifest() {
+        let manifest = get_enclave_manifest().unwrap();
+        let mut is = CodedInputStream::from_bytes(&manifest);
+
+        assert_eq!(is.read_raw_varint32().unwrap() >> 3, 1);
+        assert_eq!(is.read_uint32().unwrap(), MANIFEST_VERSION);
+        assert_eq!(is.read_raw_varint32().unwrap() >> 3, 2);
+        assert_eq!(is.read_string().unwrap(), env!("CARGO_PKG_VERSION"));
+
+        let mut handle_type_count = 0;
+        let mut host_function_count = 0;
+        while !is.eof().unwrap() {
+            let field_number = is.read_raw_varint32().unwrap() >> 3;
+            let _ = is.read_bytes().unwrap();
+            match field_number {
+                3 => handle_type_count += 1,
+                6 => host_function_count += 1,
+                _ => {}
+            }
+        }
+
+        assert_eq!(handle_type_count, handle_routes().len());
+        assert_eq!(host_function_count, host_functions().len());
+    }
+
+    pub fn test_missing_capabilities() {
+        let required = vec![
+            "random".to_string(),
+            "env_v1".to_string(),
+            "bls12_381".to_string(),
+            "ed25519_verify".to_string(),
+            "env_v2".to_string(),
+            "bls12_381".to_string(),
+        ];
+
+        assert_eq!(
+            missing_capabilities(&required),
+            vec!["bls12_381".to_string(), "env_v2".to_string()]
+        );
+        assert!(missing_capabilities(&[]).is_empty());
+    }
+}
@@ -10,6 +10,7 @@ use wasm3::Error as Wasm3RsError;
 
 use enclave_ffi_types::{EnclaveError, UntrustedVmError};
 
+use crate::cosmwasm_config::ContractOperation;
 use crate::external::ecalls::BufferRecoveryError;
 
 #[derive(Debug, Display)]
@@ -20,6 +21,8 @@ pub enum WasmEngineError {
     /// The untrusted host seems to be misbehaving
     HostMisbehavior,
     OutOfGas,
+    /// The execution ran past the deadline the node configured
+    ExecutionTimeout,
     Panic,
 
     EncryptionError,
@@ -34,9 +37,25 @@ pub enum WasmEngineError {
     MemoryWriteError,
     /// The contract attempted to write to storage during a query
     UnauthorizedWrite,
+    /// The contract called a host function that isn't available in the operation, see
+    /// `wasm3::host_availability`
+    #[display(fmt = "{} can't be called during {:?}", name, operation)]
+    ForbiddenHostFunction {
+        name: &'static str,
+        operation: ContractOperation,
+    },
+
+    /// The contract tried to store more than its storage quota
+    StorageQuotaExceeded,
+    /// The contract read the keys of its storage in a query at a past height, which can't be
+    /// proven against the app hash of that height
+    UnprovableRead,
 
     /// The contract tried calling an unrecognized function
     NonExistentImportFunction,
+    /// The contract ranged over keys that aren't in a namespace, or asked for the next entry of
+    /// an iterator it didn't open
+    InvalidIterator,
 }
 
 pub type WasmEngineResult<T> = Result<T, WasmEngineError>;
@@ -52,13 +71,22 @@ impl From<WasmEngineError> for EnclaveError {
                 vm_error: UntrustedVmError { ptr: vm_error.ptr },
             },
             OutOfGas => EnclaveError::OutOfGas,
+            ExecutionTimeout => EnclaveError::ExecutionTimeout,
             EncryptionError => EnclaveError::EncryptionError,
             DecryptionError => EnclaveError::DecryptionError,
             MemoryAllocationError => EnclaveError::MemoryAllocationError,
             MemoryReadError => EnclaveError::MemoryReadError,
             MemoryWriteError => EnclaveError::MemoryWriteError,
             UnauthorizedWrite => EnclaveError::UnauthorizedWrite,
+            StorageQuotaExceeded => EnclaveError::StorageQuotaExceeded,
+            UnprovableRead => EnclaveError::ValidationFailure,
+            // Every function that's unavailable in queries writes to the state, or acts like it
+            ForbiddenHostFunction { operation, .. } if operation.is_query() => {
+                EnclaveError::UnauthorizedWrite
+            }
+            ForbiddenHostFunction { .. } => EnclaveError::ForbiddenHostFunction,
             HostMisbehavior => EnclaveError::HostMisbehavior,
+            Panic => EnclaveError::ContractPanicked,
             // Unexpected WasmEngineError variant
             _other => EnclaveError::Unknown,
         }
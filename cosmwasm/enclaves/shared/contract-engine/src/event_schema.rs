@@ -0,0 +1,223 @@
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use cw_types_v010::types::LogAttribute;
+use cw_types_v1::results::Event;
+use enclave_ffi_types::EnclaveError;
+
+use crate::io::RawWasmOutput;
+
+/// The type of the event a contract emits from init (or migrate) to register its event schema.
+/// The event must contain a single attribute `schema` with the JSON encoded `EventSchema`.
+/// It is removed from the output before it leaves the enclave.
+pub const SCHEMA_EVENT_TYPE: &str = "secret_event_schema";
+pub const SCHEMA_ATTRIBUTE_KEY: &str = "schema";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AttributeSchema {
+    pub key: String,
+    /// Whether this attribute may be emitted as a plaintext attribute
+    #[serde(default)]
+    pub plaintext: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EventTypeSchema {
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub attributes: Vec<AttributeSchema>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct EventSchema {
+    /// In strict mode, attributes and events that are not part of the schema are rejected.
+    /// Otherwise only attributes that are leaked as plaintext against the schema are rejected.
+    #[serde(default)]
+    pub strict: bool,
+    /// Attributes of the main "wasm" event
+    #[serde(default)]
+    pub attributes: Vec<AttributeSchema>,
+    /// Custom events, which will have "wasm-" prepended to their type
+    #[serde(default)]
+    pub events: Vec<EventTypeSchema>,
+}
+
+/// Looks for a schema registration event in the raw output of a contract.
+/// If one is found, it is removed from the output and the parsed schema is returned.
+///
+/// This works on the JSON value of the output rather than on `RawWasmOutput`, because
+/// re-serializing `LogAttribute`s drops their `encrypted` flag.
+pub fn take_schema_registration(
+    output: Vec<u8>,
+) -> Result<(Vec<u8>, Option<EventSchema>), EnclaveError> {
+    let mut value: Value = match serde_json::from_slice(&output) {
+        Ok(value) => value,
+        // Let the output processing report malformed outputs
+        Err(_) => return Ok((output, None)),
+    };
+
+    let events = match value
+        .get_mut("Ok")
+        .and_then(|ok| ok.get_mut("events"))
+        .and_then(|events| events.as_array_mut())
+    {
+        Some(events) => events,
+        None => return Ok((output, None)),
+    };
+
+    let position = match events
+        .iter()
+        .position(|event| event.get("type").and_then(Value::as_str) == Some(SCHEMA_EVENT_TYPE))
+    {
+        Some(position) => position,
+        None => return Ok((output, None)),
+    };
+
+    let event: Event = serde_json::from_value(events.remove(position)).map_err(|err| {
+        warn!("failed to parse event schema registration: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let schema_attr = match event.attributes.as_slice() {
+        [attr] if attr.key == SCHEMA_ATTRIBUTE_KEY => attr,
+        _ => {
+            warn!("event schema registration must contain only the schema attribute");
+            return Err(EnclaveError::ValidationFailure);
+        }
+    };
+
+    let schema: EventSchema = serde_json::from_str(&schema_attr.value).map_err(|err| {
+        warn!("failed to parse event schema: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let output = serde_json::to_vec(&value).map_err(|err| {
+        warn!(
+            "failed to serialize output after removing event schema: {}",
+            err
+        );
+        EnclaveError::FailedToSerialize
+    })?;
+
+    Ok((output, Some(schema)))
+}
+
+/// Validates the events and attributes emitted by a contract against its registered schema
+pub fn validate_output_events(output: &[u8], schema: &EventSchema) -> Result<(), EnclaveError> {
+    let raw_output: RawWasmOutput = match serde_json::from_slice(output) {
+        Ok(raw_output) => raw_output,
+        // Let the output processing report malformed outputs
+        Err(_) => return Ok(()),
+    };
+
+    match &raw_output {
+        RawWasmOutput::OkV010 { ok, .. } => {
+            validate_attributes(&ok.log, &schema.attributes, schema.strict, "wasm")
+        }
+        RawWasmOutput::OkV1 { ok, .. } => {
+            validate_attributes(&ok.attributes, &schema.attributes, schema.strict, "wasm")?;
+            validate_events(&ok.events, schema)
+        }
+        RawWasmOutput::OkIBCPacketReceive { ok } => {
+            validate_attributes(&ok.attributes, &schema.attributes, schema.strict, "wasm")?;
+            validate_events(&ok.events, schema)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_events(events: &[Event], schema: &EventSchema) -> Result<(), EnclaveError> {
+    for event in events {
+        match schema.events.iter().find(|ev| ev.ty == event.ty) {
+            Some(event_schema) => validate_attributes(
+                &event.attributes,
+                &event_schema.attributes,
+                schema.strict,
+                &event.ty,
+            )?,
+            None if schema.strict => {
+                warn!(
+                    "contract emitted event {:?} which is not in its schema",
+                    event.ty
+                );
+                return Err(EnclaveError::ValidationFailure);
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_attributes(
+    attributes: &[LogAttribute],
+    attributes_schema: &[AttributeSchema],
+    strict: bool,
+    event_type: &str,
+) -> Result<(), EnclaveError> {
+    for attr in attributes {
+        match attributes_schema
+            .iter()
+            .find(|schema| schema.key == attr.key)
+        {
+            Some(attr_schema) => {
+                if !attr.encrypted && !attr_schema.plaintext {
+                    warn!(
+                        "contract emitted attribute {:?} of event {:?} as plaintext, but its schema requires it to be encrypted",
+                        attr.key, event_type
+                    );
+                    return Err(EnclaveError::ValidationFailure);
+                }
+            }
+            None if strict => {
+                warn!(
+                    "contract emitted attribute {:?} of event {:?} which is not in its schema",
+                    attr.key, event_type
+                );
+                return Err(EnclaveError::ValidationFailure);
+            }
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_take_schema_registration() {
+        let output = br#"{"Ok":{"messages":[],"attributes":[],"events":[{"type":"secret_event_schema","attributes":[{"key":"schema","value":"{\"strict\":true,\"attributes\":[{\"key\":\"action\",\"plaintext\":true}]}"}]}],"data":null}}"#;
+
+        let (output, schema) = take_schema_registration(output.to_vec()).unwrap();
+        let schema = schema.unwrap();
+
+        assert!(schema.strict);
+        assert_eq!(schema.attributes[0].key, "action");
+        assert!(!String::from_utf8(output)
+            .unwrap()
+            .contains(SCHEMA_EVENT_TYPE));
+    }
+
+    pub fn test_validate_output_events() {
+        let schema = EventSchema {
+            strict: true,
+            attributes: vec![AttributeSchema {
+                key: "amount".to_string(),
+                plaintext: false,
+            }],
+            events: vec![],
+        };
+
+        let encrypted = br#"{"Ok":{"messages":[],"attributes":[{"key":"amount","value":"1"}],"events":[],"data":null}}"#;
+        assert!(validate_output_events(encrypted, &schema).is_ok());
+
+        let plaintext = br#"{"Ok":{"messages":[],"attributes":[{"key":"amount","value":"1","encrypted":false}],"events":[],"data":null}}"#;
+        assert!(validate_output_events(plaintext, &schema).is_err());
+
+        let unknown = br#"{"Ok":{"messages":[],"attributes":[{"key":"memo","value":"1"}],"events":[],"data":null}}"#;
+        assert!(validate_output_events(unknown, &schema).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::message_utils::try_get_decrypted_secret_msg;
-use crate::types::{ParsedMessage, SecretMessage};
+use crate::types::{EnvelopeVersion, ParsedMessage, SecretMessage};
 use enclave_ffi_types::EnclaveError;
 use log::trace;
 
@@ -11,8 +11,6 @@ pub fn parse_execute_message(message: &[u8]) -> Result<ParsedMessage, EnclaveErr
         );
 
         return Ok(ParsedMessage {
-            should_verify_sig_info: true,
-            should_verify_input: true,
             was_msg_encrypted: true,
             should_encrypt_output: true,
             secret_msg: decrypted_secret_msg.secret_msg,
@@ -27,13 +25,12 @@ pub fn parse_execute_message(message: &[u8]) -> Result<ParsedMessage, EnclaveErr
         nonce: [0; 32],
         user_public_key: [0; 32],
         msg: message.into(),
+        version: EnvelopeVersion::Unversioned,
     };
 
     let decrypted_msg = secret_msg.msg.clone();
 
     Ok(ParsedMessage {
-        should_verify_sig_info: true,
-        should_verify_input: true,
         was_msg_encrypted: false,
         should_encrypt_output: false,
         secret_msg,
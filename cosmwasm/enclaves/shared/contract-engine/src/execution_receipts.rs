@@ -0,0 +1,241 @@
+//! Receipts that prove that an execution ran inside an enclave of the network.
+//!
+//! When the node enables receipts, every successful handle of a delivered transaction returns a
+//! receipt next to its output, which the chain adds to the events of the transaction. The receipt
+//! commits to the contract, its code, the input of the execution as the host sent it, the output
+//! as it was returned to the host, and the height and hash of the transaction, and is signed with
+//! an Ed25519 key that every enclave of the network derives from the consensus seed. Off-chain
+//! verifiers and rollup-style consumers only need the public key of the network, which
+//! `ecall_get_execution_receipt_key` returns, to check that a given output came out of a genuine
+//! enclave in a given transaction of the chain.
+//!
+//! Simulations and `CheckTx` don't get receipts, as their outputs were never committed, and
+//! neither do executions without the bytes of their transaction, such as the ones of submessages.
+//!
+//! Receipts are a local setting of the node and never affect the results of executions.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_v010::encoding::Binary;
+use enclave_crypto::{sha_256, Kdf, KEY_MANAGER};
+use enclave_ffi_types::EnclaveError;
+
+/// Separates the receipt key from the other keys derived from the consensus state ikm
+const RECEIPT_KEY_DERIVATION_INFO: &[u8] = b"secret_execution_receipt_key";
+
+/// Separates receipts from other signatures of the same key
+const RECEIPT_DOMAIN: &[u8] = b"secret_execution_receipt";
+
+static EXECUTION_RECEIPTS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn configure_execution_receipts(enabled: bool) {
+    debug!("configuring execution receipts: {}", enabled);
+    EXECUTION_RECEIPTS_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn are_execution_receipts_enabled() -> bool {
+    EXECUTION_RECEIPTS_ENABLED.load(Ordering::SeqCst)
+}
+
+/// The receipt of a handle, as it is returned to the host in JSON
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExecutionReceipt {
+    pub contract: String,
+    /// The hex of the hash of the code of the contract
+    pub code_hash: String,
+    /// The hash of the message as the host sent it, encrypted or not
+    pub input_commitment: Binary,
+    /// The hash of the output as it was returned to the host
+    pub output_commitment: Binary,
+    pub height: u64,
+    /// The hash of the transaction, i.e. the SHA-256 of its bytes
+    pub tx_hash: Binary,
+    pub signature: Binary,
+}
+
+impl ExecutionReceipt {
+    /// The bytes the signature is over. Variable length fields are length prefixed, so no two
+    /// receipts sign the same bytes.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut data = RECEIPT_DOMAIN.to_vec();
+        for field in [
+            self.contract.as_bytes(),
+            self.code_hash.as_bytes(),
+            self.input_commitment.as_slice(),
+            self.output_commitment.as_slice(),
+            self.tx_hash.as_slice(),
+        ] {
+            data.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            data.extend_from_slice(field);
+        }
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data
+    }
+}
+
+fn receipt_signing_key() -> Result<ed25519_zebra::SigningKey, EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|err| {
+        error!("failed to get the consensus state ikm: {:?}", err);
+        EnclaveError::FailedFunctionCall
+    })?;
+
+    // The genesis ikm never changes, so the key stays the same when the seed is rotated
+    let seed = *consensus_state_ikm
+        .genesis
+        .derive_key_from_this(RECEIPT_KEY_DERIVATION_INFO)
+        .get();
+    Ok(ed25519_zebra::SigningKey::from(seed))
+}
+
+fn sign_receipt(
+    signing_key: &ed25519_zebra::SigningKey,
+    contract: &str,
+    code_hash: &[u8],
+    input: &[u8],
+    output: &[u8],
+    height: u64,
+    tx_bytes: &[u8],
+) -> ExecutionReceipt {
+    let mut receipt = ExecutionReceipt {
+        contract: contract.to_string(),
+        code_hash: hex::encode(code_hash),
+        input_commitment: Binary(sha_256(input).to_vec()),
+        output_commitment: Binary(sha_256(output).to_vec()),
+        height,
+        tx_hash: Binary(sha_256(tx_bytes).to_vec()),
+        signature: Binary(vec![]),
+    };
+
+    let signature: [u8; 64] = signing_key.sign(&receipt.signed_bytes()).into();
+    receipt.signature = Binary(signature.to_vec());
+    receipt
+}
+
+/// Returns the JSON of the signed receipt of a handle, or nothing if receipts are disabled.
+/// `tx_bytes` are the verified bytes of the delivered transaction the handle is part of, and
+/// handles without them get no receipt.
+pub fn execution_receipt(
+    contract: &str,
+    code_hash: &[u8],
+    input: &[u8],
+    output: &[u8],
+    height: u64,
+    tx_bytes: Option<&[u8]>,
+) -> Result<Vec<u8>, EnclaveError> {
+    let tx_bytes = match tx_bytes {
+        Some(tx_bytes) if !tx_bytes.is_empty() && are_execution_receipts_enabled() => tx_bytes,
+        _ => return Ok(vec![]),
+    };
+
+    let receipt = sign_receipt(
+        &receipt_signing_key()?,
+        contract,
+        code_hash,
+        input,
+        output,
+        height,
+        tx_bytes,
+    );
+    serde_json::to_vec(&receipt).map_err(|err| {
+        warn!("failed to serialize the execution receipt: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Returns the public key that receipts are signed with
+pub fn get_execution_receipt_key() -> Result<Vec<u8>, EnclaveError> {
+    let public_key: [u8; 32] = ed25519_zebra::VerificationKey::from(&receipt_signing_key()?).into();
+    Ok(public_key.to_vec())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    fn verify(public_key: &[u8; 32], receipt: &ExecutionReceipt) -> bool {
+        let signature = match ed25519_zebra::Signature::try_from(receipt.signature.as_slice()) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+
+        ed25519_zebra::VerificationKey::try_from(&public_key[..])
+            .and_then(|key| key.verify(&signature, &receipt.signed_bytes()))
+            .is_ok()
+    }
+
+    pub fn test_execution_receipt_signature() {
+        let signing_key = ed25519_zebra::SigningKey::from([3u8; 32]);
+        let public_key: [u8; 32] = ed25519_zebra::VerificationKey::from(&signing_key).into();
+
+        let receipt = sign_receipt(
+            &signing_key,
+            "secret1contract",
+            &[1u8; 32],
+            b"input",
+            b"output",
+            10,
+            b"tx",
+        );
+        assert_eq!(receipt.code_hash, hex::encode([1u8; 32]));
+        assert_eq!(receipt.output_commitment.as_slice(), &sha_256(b"output"));
+        assert_eq!(receipt.tx_hash.as_slice(), &sha_256(b"tx"));
+        assert!(verify(&public_key, &receipt));
+
+        // Receipts can't be changed after they are signed
+        let mut other_output = receipt.clone();
+        other_output.output_commitment = Binary(sha_256(b"other output").to_vec());
+        assert!(!verify(&public_key, &other_output));
+
+        let mut other_height = receipt.clone();
+        other_height.height = 11;
+        assert!(!verify(&public_key, &other_height));
+
+        let mut other_tx = receipt.clone();
+        other_tx.tx_hash = Binary(sha_256(b"other tx").to_vec());
+        assert!(!verify(&public_key, &other_tx));
+
+        let other_key = ed25519_zebra::SigningKey::from([4u8; 32]);
+        let other_public_key: [u8; 32] = ed25519_zebra::VerificationKey::from(&other_key).into();
+        assert!(!verify(&other_public_key, &receipt));
+    }
+
+    pub fn test_execution_receipts_disabled() {
+        configure_execution_receipts(false);
+        assert_eq!(
+            execution_receipt(
+                "secret1contract",
+                &[1u8; 32],
+                b"input",
+                b"output",
+                10,
+                Some(b"tx")
+            )
+            .unwrap(),
+            Vec::<u8>::new()
+        );
+
+        // Executions outside of a delivered transaction get no receipt, even if receipts are
+        // enabled
+        configure_execution_receipts(true);
+        for tx_bytes in [None, Some(&b""[..])] {
+            assert_eq!(
+                execution_receipt(
+                    "secret1contract",
+                    &[1u8; 32],
+                    b"input",
+                    b"output",
+                    10,
+                    tx_bytes
+                )
+                .unwrap(),
+                Vec::<u8>::new()
+            );
+        }
+        configure_execution_receipts(false);
+    }
+}
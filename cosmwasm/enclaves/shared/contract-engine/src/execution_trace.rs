@@ -0,0 +1,248 @@
+//! Timing of the stages of contract executions, exported to the host.
+//!
+//! When the node enables tracing, every init, handle, query and migrate collects a span per
+//! stage: extracting the env, verifying the params, decrypting the message, setting up the engine,
+//! executing the contract and post-processing its output. When the execution ends, its spans are
+//! sent to the host with `ocall_export_trace`, which lets operators see where the time of a
+//! transaction goes, e.g. as a flamegraph, without recompiling the enclave with trace logs.
+//!
+//! A trace is a JSON object like
+//! `{"operation":"handle","spans":[{"name":"verify_params","depth":0,"start_micros":10,"duration_micros":250}]}`,
+//! where the start of a span is relative to the start of the execution, and spans that started
+//! inside another span are one level deeper. Queries that contracts make run on the same thread
+//! as the execution that made them, and are traced and exported on their own.
+//!
+//! Only the stages are timed, never anything about the contents of the execution, and the times
+//! are those of the host's clock, which the host can measure from the outside anyway.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use std::untrusted::time::InstantEx;
+
+use log::*;
+use serde::Serialize;
+
+use enclave_ffi_types::{Ctx, OcallReturn};
+use sgx_types::sgx_status_t;
+
+use crate::external::ocalls;
+
+/// The most spans that are collected for one execution. Later spans are dropped.
+pub const MAX_SPANS_PER_TRACE: usize = 256;
+
+static EXECUTION_TRACING: AtomicBool = AtomicBool::new(false);
+
+pub fn configure_execution_tracing(enabled: bool) {
+    debug!("configuring execution tracing: {}", enabled);
+    EXECUTION_TRACING.store(enabled, Ordering::SeqCst);
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub name: &'static str,
+    pub depth: u32,
+    pub start_micros: u64,
+    pub duration_micros: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExecutionTrace {
+    operation: &'static str,
+    spans: Vec<Span>,
+    #[serde(skip)]
+    started: Instant,
+    #[serde(skip)]
+    depth: u32,
+}
+
+impl ExecutionTrace {
+    pub fn new(operation: &'static str, started: Instant) -> Self {
+        Self {
+            operation,
+            spans: vec![],
+            started,
+            depth: 0,
+        }
+    }
+
+    fn micros_since_start(&self, at: Instant) -> u64 {
+        at.saturating_duration_since(self.started).as_micros() as u64
+    }
+
+    /// Opens a span, and returns its depth
+    pub fn enter(&mut self) -> u32 {
+        self.depth += 1;
+        self.depth - 1
+    }
+
+    pub fn exit(&mut self, name: &'static str, depth: u32, started: Instant, ended: Instant) {
+        self.depth = depth;
+        if self.spans.len() >= MAX_SPANS_PER_TRACE {
+            return;
+        }
+        self.spans.push(Span {
+            name,
+            depth,
+            start_micros: self.micros_since_start(started),
+            duration_micros: ended.saturating_duration_since(started).as_micros() as u64,
+        });
+    }
+
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+}
+
+thread_local! {
+    /// The traces of the executions that run on this thread, innermost last
+    static TRACES: RefCell<Vec<ExecutionTrace>> = RefCell::new(vec![]);
+}
+
+/// Ends the trace of an execution and exports it when it's dropped
+pub struct TraceGuard {
+    context: Option<Ctx>,
+}
+
+/// Starts tracing an execution, if the node enabled tracing
+pub fn begin_trace(context: &Ctx, operation: &'static str) -> TraceGuard {
+    if !EXECUTION_TRACING.load(Ordering::Relaxed) {
+        return TraceGuard { context: None };
+    }
+
+    TRACES.with(|traces| {
+        traces
+            .borrow_mut()
+            .push(ExecutionTrace::new(operation, Instant::now()))
+    });
+    TraceGuard {
+        context: Some(unsafe { context.unsafe_clone() }),
+    }
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        let context = match self.context.take() {
+            Some(context) => context,
+            None => return,
+        };
+
+        if let Some(trace) = TRACES.with(|traces| traces.borrow_mut().pop()) {
+            export_trace(&context, &trace);
+        }
+    }
+}
+
+/// Times a stage of the execution that's traced on this thread until it's dropped
+pub struct SpanGuard {
+    name: &'static str,
+    depth: u32,
+    started: Instant,
+}
+
+/// Opens a span, which does nothing if no execution is traced on this thread
+pub fn trace_span(name: &'static str) -> Option<SpanGuard> {
+    TRACES.with(|traces| {
+        traces.borrow_mut().last_mut().map(|trace| SpanGuard {
+            name,
+            depth: trace.enter(),
+            started: Instant::now(),
+        })
+    })
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        let ended = Instant::now();
+        TRACES.with(|traces| {
+            if let Some(trace) = traces.borrow_mut().last_mut() {
+                trace.exit(self.name, self.depth, self.started, ended);
+            }
+        });
+    }
+}
+
+fn export_trace(context: &Ctx, trace: &ExecutionTrace) {
+    let trace = match serde_json::to_vec(trace) {
+        Ok(trace) => trace,
+        Err(err) => {
+            warn!("failed to serialize the trace of the execution: {}", err);
+            return;
+        }
+    };
+
+    let mut ocall_return = OcallReturn::Success;
+    let status = unsafe {
+        ocalls::ocall_export_trace(
+            &mut ocall_return,
+            context.unsafe_clone(),
+            trace.as_ptr(),
+            trace.len(),
+        )
+    };
+    // A trace that the host didn't take is only logged, it never fails the execution
+    if status != sgx_status_t::SGX_SUCCESS || !matches!(ocall_return, OcallReturn::Success) {
+        debug!(
+            "failed to export the trace of the execution: {:?} {:?}",
+            status, ocall_return
+        );
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    pub fn test_execution_trace() {
+        let start = Instant::now();
+        let at = |micros| start + Duration::from_micros(micros);
+        let mut trace = ExecutionTrace::new("handle", start);
+
+        let outer = trace.enter();
+        let inner = trace.enter();
+        trace.exit("decrypt", inner, at(10), at(15));
+        trace.exit("verify_params", outer, at(5), at(30));
+        let next = trace.enter();
+        trace.exit("execution", next, at(40), at(100));
+
+        assert_eq!(
+            trace.spans(),
+            &[
+                Span {
+                    name: "decrypt",
+                    depth: 1,
+                    start_micros: 10,
+                    duration_micros: 5,
+                },
+                Span {
+                    name: "verify_params",
+                    depth: 0,
+                    start_micros: 5,
+                    duration_micros: 25,
+                },
+                Span {
+                    name: "execution",
+                    depth: 0,
+                    start_micros: 40,
+                    duration_micros: 60,
+                },
+            ]
+        );
+
+        let json = serde_json::to_string(&trace).unwrap();
+        assert!(json.starts_with("{\"operation\":\"handle\",\"spans\":[{\"name\":\"decrypt\""));
+
+        // Spans past the limit are dropped, but still close
+        for _ in 0..MAX_SPANS_PER_TRACE {
+            let depth = trace.enter();
+            trace.exit("db_read", depth, at(200), at(201));
+        }
+        assert_eq!(trace.spans().len(), MAX_SPANS_PER_TRACE);
+        assert_eq!(trace.enter(), 0);
+
+        // Without a trace on the thread, spans do nothing
+        assert!(trace_span("decrypt").is_none());
+    }
+}
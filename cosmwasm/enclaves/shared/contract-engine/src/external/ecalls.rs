@@ -1,3 +1,4 @@
+use std::convert::TryInto;
 use std::ffi::c_void;
 use std::panic;
 use std::sync::SgxMutex;
@@ -12,24 +13,27 @@ use enclave_ffi_types::{
     QueryResult, RuntimeConfiguration, UpdateAdminResult,
 };
 
+use enclave_cosmos_types::feature_activation::enter_active_block_scope;
 use enclave_utils::{oom_handler, validate_const_ptr, validate_input_length, validate_mut_ptr};
 
 use crate::external::results::{
     result_handle_success_to_handleresult, result_init_success_to_initresult,
     result_migrate_success_to_result, result_query_success_to_queryresult,
-    result_update_admin_success_to_result,
+    result_update_admin_success_to_result, QuerySuccess,
 };
+use crate::metrics::MeteredOperation;
 
 lazy_static! {
     static ref ECALL_ALLOCATE_STACK: SgxMutex<Vec<EnclaveBuffer>> = SgxMutex::new(Vec::new());
 }
 
-const MAX_ENV_LENGTH: usize = 10_240; // 10 KiB
+const MAX_ENV_LENGTH: usize = 102_400; // 100 KiB, includes the compute params
 const MAX_SIG_INFO_LENGTH: usize = 5_120_000; // 5 MiB, includes tx_bytes and sign_bytes
 const MAX_MSG_LENGTH: usize = 2_048_000; // 2 MiB
 const MAX_ADDRESS_LENGTH: usize = 65; // canonical can be 20 or 32 bytes, humanized can be 45 or 65
 const MAX_PROOF_LENGTH: usize = 32; // output of sha256
 const MAX_WASM_LENGHT: usize = 3_145_728; // 3 MiB, larger Wasm ATM is 1,990,361 bytes (1.6 MiB)
+const MAX_STATE_ENTRIES_LENGTH: usize = 20_480_000; // 20 MiB, a chunk of raw state entries
 
 /// # Safety
 /// Always use protection
@@ -105,6 +109,12 @@ fn ecall_configure_runtime_impl(config: RuntimeConfiguration) -> sgx_status_t {
         config.module_cache_size
     );
     crate::wasm3::module_cache::configure_module_cache(config.module_cache_size as usize);
+    crate::state_audit::configure_state_audit(config.state_audit_mode);
+    crate::wasm3::deadline::configure_execution_deadline(config.execution_deadline_millis);
+    crate::execution_receipts::configure_execution_receipts(config.execution_receipts);
+    crate::execution_trace::configure_execution_tracing(config.execution_tracing);
+    crate::pinned_code::configure_pinned_code(config.pinned_code_bytes as usize);
+    crate::result_encoding::configure_result_encoding(config.ffi_version);
     sgx_status_t::SGX_SUCCESS
 }
 
@@ -177,6 +187,7 @@ pub unsafe extern "C" fn ecall_init(
     let msg = std::slice::from_raw_parts(msg, msg_len);
     let sig_info = std::slice::from_raw_parts(sig_info, sig_info_len);
     let admin = std::slice::from_raw_parts(admin, admin_len);
+    let _active_block = enter_active_block_scope();
     let result = panic::catch_unwind(|| {
         let mut local_used_gas = *used_gas;
         let result = crate::contract_operations::init(
@@ -189,6 +200,14 @@ pub unsafe extern "C" fn ecall_init(
             sig_info,
             admin,
         );
+        if let Err(err) = &result {
+            crate::tx_journal::record_failure(env, msg, sig_info, err);
+        }
+        crate::metrics::record_execution(
+            MeteredOperation::Init,
+            local_used_gas,
+            result.as_ref().err(),
+        );
         *used_gas = local_used_gas;
         result_init_success_to_initresult(result)
     });
@@ -256,6 +275,7 @@ pub unsafe extern "C" fn ecall_handle(
     let env = std::slice::from_raw_parts(env, env_len);
     let msg = std::slice::from_raw_parts(msg, msg_len);
     let sig_info = std::slice::from_raw_parts(sig_info, sig_info_len);
+    let _active_block = enter_active_block_scope();
     let result = panic::catch_unwind(|| {
         let mut local_used_gas = *used_gas;
         let result = crate::contract_operations::handle(
@@ -268,6 +288,14 @@ pub unsafe extern "C" fn ecall_handle(
             sig_info,
             handle_type,
         );
+        if let Err(err) = &result {
+            crate::tx_journal::record_failure(env, msg, sig_info, err);
+        }
+        crate::metrics::record_execution(
+            MeteredOperation::Handle(handle_type),
+            local_used_gas,
+            result.as_ref().err(),
+        );
         *used_gas = local_used_gas;
         result_handle_success_to_handleresult(result)
     });
@@ -355,6 +383,7 @@ unsafe fn ecall_query_impl(
     let contract = std::slice::from_raw_parts(contract, contract_len);
     let env = std::slice::from_raw_parts(env, env_len);
     let msg = std::slice::from_raw_parts(msg, msg_len);
+    let _active_block = enter_active_block_scope();
     let result = panic::catch_unwind(|| {
         let mut local_used_gas = *used_gas;
         let result = crate::contract_operations::query(
@@ -365,6 +394,11 @@ unsafe fn ecall_query_impl(
             env,
             msg,
         );
+        crate::metrics::record_execution(
+            MeteredOperation::Query,
+            local_used_gas,
+            result.as_ref().err(),
+        );
         *used_gas = local_used_gas;
         result_query_success_to_queryresult(result)
     });
@@ -447,6 +481,7 @@ pub unsafe extern "C" fn ecall_migrate(
     let admin = std::slice::from_raw_parts(admin, admin_len);
     let admin_proof = std::slice::from_raw_parts(admin_proof, admin_proof_len);
 
+    let _active_block = enter_active_block_scope();
     let result = panic::catch_unwind(|| {
         let mut local_used_gas = *used_gas;
         let result = crate::contract_operations::migrate(
@@ -460,6 +495,11 @@ pub unsafe extern "C" fn ecall_migrate(
             admin,
             admin_proof,
         );
+        crate::metrics::record_execution(
+            MeteredOperation::Migrate,
+            local_used_gas,
+            result.as_ref().err(),
+        );
         *used_gas = local_used_gas;
         result_migrate_success_to_result(result)
     });
@@ -542,6 +582,7 @@ pub unsafe extern "C" fn ecall_update_admin(
         std::slice::from_raw_parts(current_admin_proof, current_admin_proof_len);
     let new_admin = std::slice::from_raw_parts(new_admin, new_admin_len);
 
+    let _active_block = enter_active_block_scope();
     let result = panic::catch_unwind(|| {
         let result = crate::contract_operations::update_admin(
             env,
@@ -573,6 +614,584 @@ pub unsafe extern "C" fn ecall_update_admin(
     }
 }
 
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_tx_failure_report(
+    tx_hash: *const u8,
+    tx_hash_len: usize,
+    nonce: *const u8,
+    nonce_len: usize,
+    user_public_key: *const u8,
+    user_public_key_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(tx_hash, tx_hash_len, failed_call());
+    validate_const_ptr!(nonce, nonce_len, failed_call());
+    validate_const_ptr!(user_public_key, user_public_key_len, failed_call());
+
+    let tx_hash: [u8; 32] = match std::slice::from_raw_parts(tx_hash, tx_hash_len).try_into() {
+        Ok(tx_hash) => tx_hash,
+        Err(_) => return failed_call(),
+    };
+    let nonce: [u8; 32] = match std::slice::from_raw_parts(nonce, nonce_len).try_into() {
+        Ok(nonce) => nonce,
+        Err(_) => return failed_call(),
+    };
+    let user_public_key: [u8; 32] =
+        match std::slice::from_raw_parts(user_public_key, user_public_key_len).try_into() {
+            Ok(user_public_key) => user_public_key,
+            Err(_) => return failed_call(),
+        };
+
+    let result = panic::catch_unwind(|| {
+        let result = crate::tx_journal::get_failure_report(&tx_hash, &nonce, &user_public_key)
+            .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_get_tx_failure_report failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_get_tx_failure_report panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_reencrypt_contract_state(
+    context: Ctx,
+    env: *const u8,
+    env_len: usize,
+    progress: *const u8,
+    progress_len: usize,
+    entries: *const u8,
+    entries_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(env, env_len, failed_call());
+    validate_const_ptr!(progress, progress_len, failed_call());
+    validate_const_ptr!(entries, entries_len, failed_call());
+
+    validate_input_length!(env_len, "env", MAX_ENV_LENGTH, failed_call());
+    validate_input_length!(
+        entries_len,
+        "entries",
+        MAX_STATE_ENTRIES_LENGTH,
+        failed_call()
+    );
+
+    let env = std::slice::from_raw_parts(env, env_len);
+    let progress = std::slice::from_raw_parts(progress, progress_len);
+    let entries = std::slice::from_raw_parts(entries, entries_len);
+    let result = panic::catch_unwind(|| {
+        let result =
+            crate::state_reencryption::reencrypt_state_chunk(context, env, progress, entries)
+                .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_reencrypt_contract_state failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_reencrypt_contract_state panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_export_user_state(
+    context: Ctx,
+    env: *const u8,
+    env_len: usize,
+    admin: *const u8,
+    admin_len: usize,
+    admin_proof: *const u8,
+    admin_proof_len: usize,
+    request: *const u8,
+    request_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(env, env_len, failed_call());
+    validate_const_ptr!(admin, admin_len, failed_call());
+    validate_const_ptr!(admin_proof, admin_proof_len, failed_call());
+    validate_const_ptr!(request, request_len, failed_call());
+
+    validate_input_length!(env_len, "env", MAX_ENV_LENGTH, failed_call());
+    validate_input_length!(admin_len, "admin", MAX_ADDRESS_LENGTH, failed_call());
+    validate_input_length!(
+        admin_proof_len,
+        "admin_proof",
+        MAX_PROOF_LENGTH,
+        failed_call()
+    );
+    validate_input_length!(request_len, "request", MAX_MSG_LENGTH, failed_call());
+
+    let env = std::slice::from_raw_parts(env, env_len);
+    let admin = std::slice::from_raw_parts(admin, admin_len);
+    let admin_proof = std::slice::from_raw_parts(admin_proof, admin_proof_len);
+    let request = std::slice::from_raw_parts(request, request_len);
+    let result = panic::catch_unwind(|| {
+        let result =
+            crate::state_export::export_user_state(context, env, admin, admin_proof, request)
+                .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_export_user_state failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_export_user_state panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_count_storage_usage(
+    context: Ctx,
+    env: *const u8,
+    env_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(env, env_len, failed_call());
+
+    validate_input_length!(env_len, "env", MAX_ENV_LENGTH, failed_call());
+
+    let env = std::slice::from_raw_parts(env, env_len);
+    let result = panic::catch_unwind(|| {
+        let result = crate::storage_quota::count_storage_usage(context, env)
+            .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_count_storage_usage failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_count_storage_usage panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_pin_contract_code(
+    contract: *const u8,
+    contract_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(contract, contract_len, failed_call());
+    validate_input_length!(contract_len, "contract", MAX_WASM_LENGHT, failed_call());
+
+    let contract = std::slice::from_raw_parts(contract, contract_len);
+    let result = panic::catch_unwind(|| {
+        let result =
+            crate::pinned_code::pin_contract_code(contract).map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_pin_contract_code failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_pin_contract_code panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_system_code_hashes() -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(|| {
+        let output = crate::admin_registry::system_code_hashes();
+        result_query_success_to_queryresult(Ok(QuerySuccess { output }))
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_get_system_code_hashes failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_get_system_code_hashes panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_analyze_contract(
+    contract: *const u8,
+    contract_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(contract, contract_len, failed_call());
+    validate_input_length!(contract_len, "contract", MAX_WASM_LENGHT, failed_call());
+
+    let contract = std::slice::from_raw_parts(contract, contract_len);
+    let result = panic::catch_unwind(|| {
+        let result = crate::wasm3::analysis::analyze_contract(contract)
+            .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_analyze_contract failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_analyze_contract panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_enclave_manifest() -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(|| {
+        let result =
+            crate::enclave_manifest::get_enclave_manifest().map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_get_enclave_manifest failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_get_enclave_manifest panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_execution_receipt_key() -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(|| {
+        let result = crate::execution_receipts::get_execution_receipt_key()
+            .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!(
+            "Call ecall_get_execution_receipt_key failed because the enclave ran out of memory!"
+        );
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_get_execution_receipt_key panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_get_enclave_metrics() -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(|| {
+        let result = crate::metrics::get_enclave_metrics().map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_get_enclave_metrics failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_get_enclave_metrics panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_calibrate_crypto_gas(iterations: u32) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(|| {
+        let result = crate::crypto_calibration::calibrate_crypto_gas(iterations)
+            .map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_calibrate_crypto_gas failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_calibrate_crypto_gas panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_export_state_audit_log() -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let result = panic::catch_unwind(|| {
+        let result =
+            crate::state_audit::export_state_audit_log().map(|output| QuerySuccess { output });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!("Call ecall_export_state_audit_log failed because the enclave ran out of memory!");
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_export_state_audit_log panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
+/// # Safety
+/// Always use protection
+#[no_mangle]
+pub unsafe extern "C" fn ecall_acknowledge_write_intents(
+    intents: *const u8,
+    intents_len: usize,
+) -> QueryResult {
+    if let Err(err) = oom_handler::register_oom_handler() {
+        error!("Could not register OOM handler!");
+        return QueryResult::Failure { err };
+    }
+
+    let failed_call = || result_query_success_to_queryresult(Err(EnclaveError::FailedFunctionCall));
+    validate_const_ptr!(intents, intents_len, failed_call());
+    validate_input_length!(
+        intents_len,
+        "intents",
+        crate::write_intents::MAX_ACKNOWLEDGED_INTENTS_LENGTH,
+        failed_call()
+    );
+
+    let intents = std::slice::from_raw_parts(intents, intents_len);
+    let result = panic::catch_unwind(|| {
+        let result = crate::write_intents::acknowledge_write_intents(intents)
+            .map(|_| QuerySuccess { output: vec![] });
+        result_query_success_to_queryresult(result)
+    });
+
+    if let Err(err) = oom_handler::restore_safety_buffer() {
+        error!("Could not restore OOM safety buffer!");
+        return QueryResult::Failure { err };
+    }
+
+    if let Ok(res) = result {
+        res
+    } else if oom_handler::get_then_clear_oom_happened() {
+        error!(
+            "Call ecall_acknowledge_write_intents failed because the enclave ran out of memory!"
+        );
+        QueryResult::Failure {
+            err: EnclaveError::OutOfMemory,
+        }
+    } else {
+        error!("Call ecall_acknowledge_write_intents panicked unexpectedly!");
+        QueryResult::Failure {
+            err: EnclaveError::Panic,
+        }
+    }
+}
+
 /// # Safety
 /// Always use protection
 #[no_mangle]
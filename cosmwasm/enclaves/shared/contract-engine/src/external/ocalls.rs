@@ -32,11 +32,23 @@ extern "C" {
         query_depth: u32,
     ) -> sgx_status_t;
 
+    pub fn ocall_read_db_keys(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        vm_error: *mut UntrustedVmError,
+        gas_used: *mut u64,
+        keys: *mut EnclaveBuffer,
+        start: *const u8,
+        start_len: usize,
+        limit: u32,
+    ) -> sgx_status_t;
+
     pub fn ocall_remove_db(
         retval: *mut OcallReturn,
         context: Ctx,
         vm_error: *mut UntrustedVmError,
         gas_used: *mut u64,
+        removed_size: *mut u64,
         key: *const u8,
         key_len: usize,
     ) -> sgx_status_t;
@@ -46,6 +58,7 @@ extern "C" {
         context: Ctx,
         vm_error: *mut UntrustedVmError,
         gas_used: *mut u64,
+        replaced_size: *mut u64,
         key: *const u8,
         key_len: usize,
         value: *const u8,
@@ -57,7 +70,22 @@ extern "C" {
         context: Ctx,
         vm_error: *mut UntrustedVmError,
         gas_used: *mut u64,
+        replaced_size: *mut u64,
         keys: *const u8,
         keys_len: usize,
     ) -> sgx_status_t;
+
+    pub fn ocall_stream_logs(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        logs: *const u8,
+        logs_len: usize,
+    ) -> sgx_status_t;
+
+    pub fn ocall_export_trace(
+        retval: *mut OcallReturn,
+        context: Ctx,
+        trace: *const u8,
+        trace_len: usize,
+    ) -> sgx_status_t;
 }
@@ -14,6 +14,8 @@ pub struct InitSuccess {
     /// The contract_key of this contract.
     pub contract_key: [u8; 64],
     pub admin_proof: [u8; 32],
+    /// The intent of the writes of the execution, empty if it didn't write anything
+    pub write_intent: Vec<u8>,
 }
 
 pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveError>) -> InitResult {
@@ -22,27 +24,19 @@ pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveErro
             output,
             contract_key,
             admin_proof,
-        }) => {
-            let user_buffer = unsafe {
-                let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
-                match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
-                    sgx_status_t::SGX_SUCCESS => { /* continue */ }
-                    _ => {
-                        return InitResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
-                    }
-                }
-                user_buffer.assume_init()
-            };
-            InitResult::Success {
-                output: user_buffer,
+            write_intent,
+        }) => match (
+            allocate_user_buffer(&output),
+            allocate_user_buffer(&write_intent),
+        ) {
+            (Ok(output), Ok(write_intent)) => InitResult::Success {
+                output,
                 contract_key,
                 admin_proof,
-            }
-        }
+                write_intent,
+            },
+            (Err(err), _) | (_, Err(err)) => InitResult::Failure { err },
+        },
         Err(err) => InitResult::Failure { err },
     }
 }
@@ -51,31 +45,55 @@ pub fn result_init_success_to_initresult(result: Result<InitSuccess, EnclaveErro
 pub struct HandleSuccess {
     /// The output of the calculation
     pub output: Vec<u8>,
+    /// The JSON of the salted hashes of the storage keys the contract read and wrote
+    pub touched_keys: Vec<u8>,
+    /// The JSON of the signed receipt of the execution, empty if receipts are disabled or the
+    /// execution isn't part of a delivered transaction
+    pub receipt: Vec<u8>,
+    /// The intent of the writes of the execution, empty if it didn't write anything
+    pub write_intent: Vec<u8>,
+}
+
+fn allocate_user_buffer(bytes: &[u8]) -> Result<UserSpaceBuffer, EnclaveError> {
+    unsafe {
+        let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
+        match ocall_allocate(user_buffer.as_mut_ptr(), bytes.as_ptr(), bytes.len()) {
+            sgx_status_t::SGX_SUCCESS => Ok(user_buffer.assume_init()),
+            _ => Err(EnclaveError::FailedOcall {
+                vm_error: UntrustedVmError::default(),
+            }),
+        }
+    }
 }
 
 pub fn result_handle_success_to_handleresult(
     result: Result<HandleSuccess, EnclaveError>,
 ) -> HandleResult {
     match result {
-        Ok(HandleSuccess { output }) => {
-            let user_buffer = unsafe {
-                let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
-                match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
-                    sgx_status_t::SGX_SUCCESS => { /* continue */ }
-                    _ => {
-                        return HandleResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
-                    }
+        Ok(HandleSuccess {
+            output,
+            touched_keys,
+            receipt,
+            write_intent,
+        }) => match (
+            allocate_user_buffer(&output),
+            allocate_user_buffer(&touched_keys),
+            allocate_user_buffer(&receipt),
+            allocate_user_buffer(&write_intent),
+        ) {
+            (Ok(output), Ok(touched_keys), Ok(receipt), Ok(write_intent)) => {
+                HandleResult::Success {
+                    output,
+                    touched_keys,
+                    receipt,
+                    write_intent,
                 }
-                user_buffer.assume_init()
-            };
-            HandleResult::Success {
-                output: user_buffer,
             }
-        }
+            (Err(err), _, _, _)
+            | (_, Err(err), _, _)
+            | (_, _, Err(err), _)
+            | (_, _, _, Err(err)) => HandleResult::Failure { err },
+        },
         Err(err) => HandleResult::Failure { err },
     }
 }
@@ -86,6 +104,8 @@ pub struct MigrateSuccess {
     pub output: Vec<u8>,
     pub new_contract_key: [u8; 64],
     pub new_contract_key_proof: [u8; 32],
+    /// The intent of the writes of the execution, empty if it didn't write anything
+    pub write_intent: Vec<u8>,
 }
 
 pub fn result_migrate_success_to_result(
@@ -96,27 +116,19 @@ pub fn result_migrate_success_to_result(
             output,
             new_contract_key,
             new_contract_key_proof,
-        }) => {
-            let user_buffer = unsafe {
-                let mut user_buffer = std::mem::MaybeUninit::<UserSpaceBuffer>::uninit();
-                match ocall_allocate(user_buffer.as_mut_ptr(), output.as_ptr(), output.len()) {
-                    sgx_status_t::SGX_SUCCESS => { /* continue */ }
-                    _ => {
-                        return MigrateResult::Failure {
-                            err: EnclaveError::FailedOcall {
-                                vm_error: UntrustedVmError::default(),
-                            },
-                        }
-                    }
-                }
-                user_buffer.assume_init()
-            };
-            MigrateResult::Success {
-                output: user_buffer,
+            write_intent,
+        }) => match (
+            allocate_user_buffer(&output),
+            allocate_user_buffer(&write_intent),
+        ) {
+            (Ok(output), Ok(write_intent)) => MigrateResult::Success {
+                output,
                 new_contract_key,
                 new_contract_key_proof,
-            }
-        }
+                write_intent,
+            },
+            (Err(err), _) | (_, Err(err)) => MigrateResult::Failure { err },
+        },
         Err(err) => MigrateResult::Failure { err },
     }
 }
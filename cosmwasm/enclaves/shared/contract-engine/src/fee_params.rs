@@ -0,0 +1,120 @@
+use log::*;
+use serde::Serialize;
+
+use cw_types_generic::VerifiedFee;
+use cw_types_v010::coins::Coin;
+
+use crate::errors::WasmEngineError;
+
+/// The amount of fractional digits in gas prices, like the `Decimal` type of contracts
+const GAS_PRICE_DECIMALS: usize = 18;
+
+/// A gas price, e.g. `{"denom":"uscrt","amount":"0.1"}`
+#[derive(Serialize, Debug, PartialEq)]
+struct DecCoin {
+    denom: String,
+    amount: String,
+}
+
+/// The fee parameters returned by `fee_params`. They are taken from the signed fee of the
+/// transaction, so contracts can rely on them to compute costs, e.g. to reimburse users.
+#[derive(Serialize, Debug)]
+struct FeeParams {
+    amount: Vec<Coin>,
+    gas_limit: String,
+    /// The price the transaction paid per unit of gas, in each of the denoms of the fee
+    gas_prices: Vec<DecCoin>,
+    /// The base fee of the fee market. The chain doesn't have a fee market yet, so it's always
+    /// `null`, but contracts can already handle it.
+    base_fee: Option<DecCoin>,
+}
+
+/// Divides `amount` by `gas_limit` and formats the result as a decimal without trailing zeros.
+/// Returns `None` if the gas limit is zero or the amount doesn't fit in 128 bits.
+fn gas_price(amount: u128, gas_limit: u64) -> Option<String> {
+    if gas_limit == 0 {
+        return None;
+    }
+
+    let gas_limit = gas_limit as u128;
+    let mut price = (amount / gas_limit).to_string();
+
+    let mut remainder = amount % gas_limit;
+    let mut fraction = String::with_capacity(GAS_PRICE_DECIMALS);
+    for _ in 0..GAS_PRICE_DECIMALS {
+        // The remainder is smaller than the gas limit, which fits in 64 bits, so this can't
+        // overflow
+        remainder *= 10;
+        fraction.push(char::from(b'0' + (remainder / gas_limit) as u8));
+        remainder %= gas_limit;
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    if !fraction.is_empty() {
+        price.push('.');
+        price.push_str(fraction);
+    }
+
+    Some(price)
+}
+
+/// Encodes the fee parameters of the transaction that is executed as JSON, or `null` if the
+/// fee couldn't be verified, e.g. because the contract was called by another contract.
+pub fn encode_fee_params(fee: Option<&VerifiedFee>) -> Result<Vec<u8>, WasmEngineError> {
+    let fee_params = fee.map(|fee| FeeParams {
+        amount: fee.amount.clone(),
+        gas_limit: fee.gas_limit.to_string(),
+        gas_prices: fee
+            .amount
+            .iter()
+            .filter_map(|coin| {
+                let amount = gas_price(coin.amount.to_u128()?, fee.gas_limit)?;
+                Some(DecCoin {
+                    denom: coin.denom.clone(),
+                    amount,
+                })
+            })
+            .collect(),
+        base_fee: None,
+    });
+
+    serde_json::to_vec(&fee_params).map_err(|err| {
+        debug!("failed to serialize fee params: {}", err);
+        WasmEngineError::SerializationError
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cw_types_v010::math::Uint256;
+
+    pub fn test_gas_price() {
+        assert_eq!(gas_price(25_000, 100_000).as_deref(), Some("0.25"));
+        assert_eq!(gas_price(300_000, 100_000).as_deref(), Some("3"));
+        assert_eq!(gas_price(1, 3).as_deref(), Some("0.333333333333333333"));
+        assert_eq!(gas_price(0, 100_000).as_deref(), Some("0"));
+        assert_eq!(gas_price(100, 0), None);
+        assert_eq!(
+            gas_price(u128::MAX, u64::MAX).as_deref(),
+            Some("18446744073709551617")
+        );
+    }
+
+    pub fn test_encode_fee_params() {
+        assert_eq!(encode_fee_params(None).unwrap(), b"null".to_vec());
+
+        let fee = VerifiedFee {
+            amount: vec![Coin {
+                denom: "uscrt".to_string(),
+                amount: Uint256::from(25_000u128),
+            }],
+            gas_limit: 100_000,
+        };
+        assert_eq!(
+            String::from_utf8(encode_fee_params(Some(&fee)).unwrap()).unwrap(),
+            r#"{"amount":[{"denom":"uscrt","amount":"25000"}],"gas_limit":"100000","gas_prices":[{"denom":"uscrt","amount":"0.25"}],"base_fee":null}"#
+        );
+    }
+}
@@ -1,11 +1,16 @@
 #[cfg(feature = "wasmi-engine")]
 pub use pwasm_utils::{inject_gas_counter, rules};
 
+use serde::{Deserialize, Serialize};
+
 //pub const OCALL_BASE_GAS: u64 = 2_000_000;
 pub const WRITE_BASE_GAS: u64 = 2_000;
 pub const READ_BASE_GAS: u64 = 1_000;
 
-/// Wasm cost table
+/// Wasm cost table. Governance can reprice it with the params of the compute module, see
+/// `compute_params`. Costs missing from the params keep their defaults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
 pub struct WasmCosts {
     /// Default opcode cost
     pub regular: u32,
@@ -53,6 +58,87 @@ pub struct WasmCosts {
     pub external_ed25519_sign: u32,
     pub external_check_gas_used: u32,
     pub external_minimum_gas_evaporate: u32,
+    /// Cost invoking execute_contracts_atomic from WASM
+    pub external_execute_contracts_atomic: u32,
+    /// Cost invoking set_upgrade_policy from WASM
+    pub external_set_upgrade_policy: u32,
+    /// Cost invoking announce_migration from WASM
+    pub external_announce_migration: u32,
+    /// Cost invoking migration_checkpoint or migration_cursor from WASM
+    pub external_migration_checkpoint: u32,
+    /// Cost invoking set_auditor_key from WASM
+    pub external_set_auditor_key: u32,
+    /// Cost of a query that was answered from the query cache of the execution
+    pub external_query_cache_hit: u32,
+    /// Cost invoking fee_params from WASM
+    pub external_fee_params: u32,
+    /// Cost invoking pending_replies from WASM
+    pub external_pending_replies: u32,
+    /// Cost invoking unique_id from WASM
+    pub external_unique_id: u32,
+    /// Cost invoking db_remove_range from WASM
+    pub external_db_remove_range: u32,
+    /// Cost per key of the contract that db_remove_range scans, on top of the removes
+    pub external_db_remove_range_per_key: u32,
+    /// Cost invoking canonical_json from WASM
+    pub external_canonical_json: u32,
+    /// Cost per byte of JSON passed to canonical_json
+    pub external_canonical_json_per_byte: u32,
+    /// Cost invoking transient_read from WASM
+    pub external_transient_read: u32,
+    /// Cost invoking transient_write from WASM
+    pub external_transient_write: u32,
+    /// Cost per byte of the keys and values written with transient_write
+    pub external_transient_write_per_byte: u32,
+    /// Cost invoking bech32_encode from WASM
+    pub external_bech32_encode: u32,
+    /// Cost invoking bech32_decode from WASM
+    pub external_bech32_decode: u32,
+    /// Cost invoking bech32_validate from WASM
+    pub external_bech32_validate: u32,
+    /// Cost invoking base64_encode, base64_decode, hex_encode or hex_decode from WASM
+    pub external_codec: u32,
+    /// Cost per byte of input passed to base64_encode, base64_decode, hex_encode or hex_decode
+    pub external_codec_per_byte: u32,
+    /// Cost invoking derive_contract_subkey from WASM
+    pub external_derive_contract_subkey: u32,
+    /// Cost invoking designate_viewer from WASM
+    pub external_designate_viewer: u32,
+    /// Cost invoking oblivious_read, oblivious_write or oblivious_remove from WASM, on top of
+    /// the reads and writes of the path
+    pub external_oblivious_access: u32,
+    /// Cost invoking decode_sdk_response or decode_any from WASM
+    pub external_decode_sdk_response: u32,
+    /// Cost per byte of the responses passed to decode_sdk_response or decode_any
+    pub external_decode_sdk_response_per_byte: u32,
+    /// Cost invoking db_scan from WASM
+    pub external_db_scan: u32,
+    /// Cost per key of the range that db_scan reads
+    pub external_db_scan_per_key: u32,
+    /// Cost invoking db_next from WASM, on top of the read of the value
+    pub external_db_next: u32,
+    /// Cost invoking random_commit from WASM, on top of the read of the commitment
+    pub external_random_commit: u32,
+    /// Cost invoking random_reveal from WASM, on top of the read of the commitment
+    pub external_random_reveal: u32,
+    /// Cost per byte of padding added to encrypted outputs
+    pub output_padding_per_byte: u32,
+    /// Cost per byte of env, msg and sig_info deserialized before executing a contract
+    pub input_deserialization_per_byte: u32,
+    /// Max gas of a query that a contract makes. The query is also limited by the remaining gas
+    /// of the contract.
+    pub query_gas_limit: u32,
+    /// Percentage of the max gas of a query that each query nested in it can use
+    pub query_gas_decay_percent: u32,
+    /// Max depth of the calls between the functions of a contract, see `wasm3::call_depth`
+    pub max_call_depth: u32,
+    /// Max bytes of encrypted keys and values that a contract can store, see `storage_quota`
+    pub storage_quota: u64,
+    /// Percentage of the storage quota past which growing the storage is surcharged
+    pub storage_surcharge_start_percent: u32,
+    /// Surcharge per byte of growing the storage up to the quota. It ramps up linearly from 0 at
+    /// the start of the surcharge.
+    pub storage_surcharge_per_byte: u32,
 }
 
 impl Default for WasmCosts {
@@ -82,6 +168,45 @@ impl Default for WasmCosts {
             external_ed25519_sign: 75000,
             external_check_gas_used: 8192,
             external_minimum_gas_evaporate: 8000,
+            external_execute_contracts_atomic: 8192,
+            external_set_upgrade_policy: 8192,
+            external_announce_migration: 8192,
+            external_migration_checkpoint: 8192,
+            external_set_auditor_key: 8192,
+            external_query_cache_hit: 8192,
+            external_fee_params: 8192,
+            external_pending_replies: 8192,
+            external_unique_id: 8192,
+            external_db_remove_range: 8192,
+            external_db_remove_range_per_key: 1000,
+            external_canonical_json: 8192,
+            external_canonical_json_per_byte: 30,
+            external_transient_read: 1000,
+            external_transient_write: 1000,
+            external_transient_write_per_byte: 3,
+            external_bech32_encode: 8192,
+            external_bech32_decode: 8192,
+            external_bech32_validate: 8192,
+            external_codec: 1000,
+            external_codec_per_byte: 2,
+            external_derive_contract_subkey: 8192,
+            external_designate_viewer: 8192,
+            external_oblivious_access: 8192,
+            external_decode_sdk_response: 8192,
+            external_decode_sdk_response_per_byte: 2,
+            external_db_scan: 8192,
+            external_db_scan_per_key: 1000,
+            external_db_next: 1000,
+            external_random_commit: 8192,
+            external_random_reveal: 8192,
+            output_padding_per_byte: 30,
+            input_deserialization_per_byte: 30,
+            query_gas_limit: 3_000_000_000,
+            query_gas_decay_percent: 50,
+            max_call_depth: 1024,
+            storage_quota: 64 * 1024 * 1024,
+            storage_surcharge_start_percent: 80,
+            storage_surcharge_per_byte: 1000,
         }
     }
 }
@@ -0,0 +1,300 @@
+//! Executions of contracts by group policies, through the proposals that `MsgExec` executes.
+//!
+//! A group policy has no keys, so it never signs the messages that it sends. Instead its members
+//! accept a proposal with the messages, and anyone can then execute them with a signed `MsgExec`.
+//! The authority of the policy comes from the proposal being accepted, which the host can't be
+//! trusted to tell the enclave. Executions of the messages of proposals carry a `ReadProof` of
+//! each proposal that the transaction executes, and of its group policy, at the height before
+//! their block, and the enclave verifies them against the app hash of the header of their block,
+//! which it verified itself.
+//!
+//! Proposals and policies are stored in the ORM tables of the group module:
+//!
+//! ```text
+//! 0x20 | policy address       -> GroupPolicyInfo
+//! 0x30 | proposal id (u64 BE) -> Proposal
+//! ```
+//!
+//! A proposal that the transaction itself accepts, e.g. with `EXEC_TRY`, or whose votes `MsgExec`
+//! tallies, isn't accepted yet in the proven state, so its messages fail verification.
+//! Governance proposals run at the end of their block without a transaction, so the messages
+//! they execute are never input-verified.
+
+use log::*;
+use serde::Deserialize;
+
+use cw_types_v010::types::CanonicalAddr;
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_cosmos_types::group_proposals::{parse_group_policy, GroupProposal};
+use enclave_ffi_types::EnclaveError;
+
+use crate::state_proofs::{verified_app_hash, ReadProof};
+
+const GROUP_STORE: &[u8] = b"group";
+const GROUP_POLICY_TABLE_PREFIX: u8 = 0x20;
+const PROPOSAL_TABLE_PREFIX: u8 = 0x30;
+
+/// The proofs of a proposal that the transaction executes, as the host sends them in the env
+#[derive(Deserialize, Clone, Debug)]
+pub struct GroupProposalProof {
+    pub proposal_id: u64,
+    pub proposal: ReadProof,
+    pub group_policy: ReadProof,
+}
+
+#[derive(Deserialize)]
+struct EnvWithGroupProposalProofs {
+    #[serde(default)]
+    group_proposal_proofs: Vec<GroupProposalProof>,
+}
+
+/// The accepted proposals of group policies that the transaction of an execution may execute
+#[derive(Clone, Debug, Default)]
+pub struct GroupAuthority {
+    proposals: Vec<GroupProposal>,
+}
+
+impl GroupAuthority {
+    /// The proposals that the proofs in the env prove accepted. Proposals that aren't proven are
+    /// left out.
+    pub fn from_env(env: &[u8], block_height: u64) -> Self {
+        if !is_active(Feature::ProvenGroupProposals) {
+            return Self::default();
+        }
+
+        let proofs = match serde_json::from_slice::<EnvWithGroupProposalProofs>(env) {
+            Ok(env) => env.group_proposal_proofs,
+            Err(err) => {
+                warn!("failed to parse the group proposal proofs: {:?}", err);
+                return Self::default();
+            }
+        };
+        if proofs.is_empty() {
+            return Self::default();
+        }
+
+        // The header of a block commits to the state after the block before it
+        let app_hash = match verified_app_hash(block_height) {
+            Some(app_hash) => app_hash,
+            None => {
+                warn!("group proposals can't be proven at height {}", block_height);
+                return Self::default();
+            }
+        };
+
+        Self {
+            proposals: proofs
+                .iter()
+                .filter_map(|proof| proven_proposal(proof, &app_hash))
+                .collect(),
+        }
+    }
+
+    /// Whether the sender is the group policy of a proven proposal
+    pub fn is_group_policy(&self, sender: &CanonicalAddr) -> bool {
+        self.proposals
+            .iter()
+            .any(|proposal| &proposal.group_policy_address == sender)
+    }
+
+    /// The proven proposal with the id, if the sender is its group policy
+    pub fn proposal_of(&self, proposal_id: u64, sender: &CanonicalAddr) -> Option<&GroupProposal> {
+        self.proposals
+            .iter()
+            .find(|proposal| proposal.id == proposal_id && &proposal.group_policy_address == sender)
+    }
+}
+
+fn proposal_key(proposal_id: u64) -> Vec<u8> {
+    let mut key = vec![PROPOSAL_TABLE_PREFIX];
+    key.extend_from_slice(&proposal_id.to_be_bytes());
+    key
+}
+
+fn group_policy_key(address: &CanonicalAddr) -> Vec<u8> {
+    let mut key = vec![GROUP_POLICY_TABLE_PREFIX];
+    key.extend_from_slice(address.as_slice());
+    key
+}
+
+/// The proposal, if the proofs prove that it's accepted, not executed yet, and that its group
+/// policy wasn't changed since it was submitted. Only the messages that the policy sends are
+/// kept, like the group module only executes proposals whose messages it sends.
+fn proven_proposal(proof: &GroupProposalProof, app_hash: &[u8]) -> Option<GroupProposal> {
+    let proposal =
+        match proof
+            .proposal
+            .verify(GROUP_STORE, &proposal_key(proof.proposal_id), app_hash)
+        {
+            Ok(Some(value)) => GroupProposal::from_bytes(value).ok()?,
+            Ok(None) => {
+                warn!("group proposal {} doesn't exist", proof.proposal_id);
+                return None;
+            }
+            Err(_) => return None,
+        };
+    if proposal.id != proof.proposal_id || !proposal.is_executable() {
+        warn!("group proposal {} can't be executed", proof.proposal_id);
+        return None;
+    }
+
+    let policy_key = group_policy_key(&proposal.group_policy_address);
+    let (policy_address, policy_version) =
+        match proof
+            .group_policy
+            .verify(GROUP_STORE, &policy_key, app_hash)
+        {
+            Ok(Some(value)) => parse_group_policy(value).ok()?,
+            _ => return None,
+        };
+    if policy_address != proposal.group_policy_address
+        || policy_version != proposal.group_policy_version
+    {
+        warn!(
+            "group policy of proposal {} changed since it was submitted",
+            proof.proposal_id
+        );
+        return None;
+    }
+
+    if proposal
+        .messages
+        .iter()
+        .any(|msg| msg.sender() != Some(&proposal.group_policy_address))
+    {
+        warn!(
+            "group proposal {} has messages that its policy doesn't send",
+            proof.proposal_id
+        );
+        return None;
+    }
+
+    Some(proposal)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use protobuf::{CodedOutputStream, ProtobufResult};
+
+    use cw_types_v010::encoding::Binary;
+    use cw_types_v010::types::HumanAddr;
+    use enclave_cosmos_types::types::DirectSdkMsg;
+
+    use crate::state_proofs::tests::prove_pair;
+
+    fn encode(write: impl FnOnce(&mut CodedOutputStream) -> ProtobufResult<()>) -> Vec<u8> {
+        let mut bytes = vec![];
+        {
+            let mut os = CodedOutputStream::vec(&mut bytes);
+            write(&mut os).unwrap();
+            os.flush().unwrap();
+        }
+        bytes
+    }
+
+    fn human(address: &CanonicalAddr) -> String {
+        HumanAddr::from_canonical(address).unwrap().0
+    }
+
+    /// A `MsgExecuteContract` of the sender, as an `Any`
+    fn execute_any(sender: &CanonicalAddr) -> Vec<u8> {
+        let execute = encode(|os| {
+            os.write_bytes(1, sender.as_slice())?;
+            os.write_bytes(2, &[2u8; 20])?;
+            os.write_bytes(3, b"msg")
+        });
+        encode(|os| {
+            os.write_string(1, "/secret.compute.v1beta1.MsgExecuteContract")?;
+            os.write_bytes(2, &execute)
+        })
+    }
+
+    /// A proposal of the policy at version 1, with the status and messages
+    fn group_proposal(policy: &CanonicalAddr, status: u64, messages: &[Vec<u8>]) -> Vec<u8> {
+        encode(|os| {
+            os.write_uint64(1, 7)?;
+            os.write_string(2, &human(policy))?;
+            os.write_uint64(7, 1)?;
+            os.write_uint64(8, status)?;
+            for message in messages {
+                os.write_bytes(12, message)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn group_policy(address: &CanonicalAddr, version: u64) -> Vec<u8> {
+        encode(|os| {
+            os.write_string(1, &human(address))?;
+            os.write_uint64(5, version)
+        })
+    }
+
+    /// Proofs of the proposal and policy in one state, and the app hash of that state
+    fn proofs_of(
+        proposal_id: u64,
+        proposal: &[u8],
+        policy_address: &CanonicalAddr,
+        policy: &[u8],
+    ) -> (GroupProposalProof, Vec<u8>) {
+        let (proposal, group_policy, app_hash) = prove_pair(
+            GROUP_STORE,
+            (&proposal_key(proposal_id), proposal),
+            (&group_policy_key(policy_address), policy),
+        );
+        let proof = GroupProposalProof {
+            proposal_id,
+            proposal,
+            group_policy,
+        };
+        (proof, app_hash)
+    }
+
+    pub fn test_proven_group_proposals() {
+        let policy = CanonicalAddr(Binary(vec![3u8; 32]));
+        let other = CanonicalAddr(Binary(vec![4u8; 20]));
+        let messages = [execute_any(&policy)];
+
+        let proposal = group_proposal(&policy, 2, &messages);
+        let (proof, app_hash) = proofs_of(7, &proposal, &policy, &group_policy(&policy, 1));
+        let proven = proven_proposal(&proof, &app_hash).unwrap();
+        assert_eq!(proven.id, 7);
+        assert!(matches!(
+            proven.messages.as_slice(),
+            [DirectSdkMsg::MsgExecuteContract { .. }]
+        ));
+
+        let authority = GroupAuthority {
+            proposals: vec![proven],
+        };
+        assert!(authority.is_group_policy(&policy));
+        assert!(!authority.is_group_policy(&other));
+        assert!(authority.proposal_of(7, &policy).is_some());
+        assert!(authority.proposal_of(8, &policy).is_none());
+        assert!(authority.proposal_of(7, &other).is_none());
+
+        // Proofs that the app hash doesn't commit to
+        assert!(proven_proposal(&proof, &[0u8; 32]).is_none());
+
+        // A proof of another proposal
+        let (mut forged, app_hash) = proofs_of(7, &proposal, &policy, &group_policy(&policy, 1));
+        forged.proposal_id = 8;
+        assert!(proven_proposal(&forged, &app_hash).is_none());
+
+        // A proposal that wasn't accepted
+        let submitted = group_proposal(&policy, 1, &messages);
+        let (proof, app_hash) = proofs_of(7, &submitted, &policy, &group_policy(&policy, 1));
+        assert!(proven_proposal(&proof, &app_hash).is_none());
+
+        // A proposal whose group policy changed since it was submitted
+        let (proof, app_hash) = proofs_of(7, &proposal, &policy, &group_policy(&policy, 2));
+        assert!(proven_proposal(&proof, &app_hash).is_none());
+
+        // A proposal with a message that another account sends
+        let foreign = group_proposal(&policy, 2, &[execute_any(&other)]);
+        let (proof, app_hash) = proofs_of(7, &foreign, &policy, &group_policy(&policy, 1));
+        assert!(proven_proposal(&proof, &app_hash).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+//! How `handle` processes each handle type.
+//!
+//! Every handle type has a `HandleRoute` in `HANDLE_ROUTES`, which decides how its message is
+//! decrypted and parsed, how it's verified, who the contract sees as the sender, which export of
+//! the contract is called and how the output is shaped. `handle`, `parse_message`, the output
+//! processing and the enclave manifest all read the route, so adding a handle type only takes a
+//! route here.
+
+use cw_types_generic::ShapedEntrypoint;
+use enclave_cosmos_types::types::HandleType;
+use enclave_ffi_types::EnclaveError;
+
+use crate::execute_message::parse_execute_message;
+use crate::ibc_message::{parse_ibc_receive_message, parse_plaintext_ibc_protocol_message};
+use crate::reply_message::parse_reply_message;
+use crate::types::ParsedMessage;
+use crate::verification_profiles::VerificationProfile;
+
+/// Whether the message is encrypted, which also decides whether the output is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncryptionPolicy {
+    /// The message is encrypted by the sender or plaintext, and the output is encrypted like it
+    Sender,
+    /// The output is encrypted like the message that the contract replies to
+    OriginalMessage,
+    /// The data of the packet may be encrypted, and the output is encrypted like it
+    PacketData,
+    /// The message comes from the IBC protocol or hooks, and is never encrypted
+    Plaintext,
+}
+
+impl EncryptionPolicy {
+    pub fn parse(self, message: &[u8]) -> Result<ParsedMessage, EnclaveError> {
+        match self {
+            EncryptionPolicy::Sender => parse_execute_message(message),
+            EncryptionPolicy::OriginalMessage => parse_reply_message(message),
+            EncryptionPolicy::PacketData => parse_ibc_receive_message(message),
+            EncryptionPolicy::Plaintext => parse_plaintext_ibc_protocol_message(message),
+        }
+    }
+}
+
+/// Who the contract sees as msg.sender
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SenderPolicy {
+    /// The sender that `verify_params` verified
+    Verified,
+    /// There's no sender, or it can't be verified, so the contract sees an empty sender rather
+    /// than one that anyone could fake
+    Null,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HandleRoute {
+    pub handle_type: HandleType,
+    /// The export of CosmWasm v1 contracts that is called
+    pub export: &'static str,
+    pub encryption: EncryptionPolicy,
+    pub sender: SenderPolicy,
+    /// How `verify_params` verifies the message, unless a later profile is scheduled in
+    /// `verification_profiles`
+    pub verification: VerificationProfile,
+    /// The message goes to an IBC entry point, so the contract must support IBC and the output
+    /// is an IBC response
+    pub ibc_entrypoint: bool,
+    /// The timeout of the packet must have elapsed at the time of the block
+    pub requires_elapsed_timeout: bool,
+    /// The message is the callback of a transfer that the contract sent, which it consumes
+    pub consumes_outgoing_transfer: bool,
+    /// The entry point whose output can have a constant shape
+    pub shaped_entrypoint: Option<ShapedEntrypoint>,
+}
+
+const fn ibc_protocol_route(
+    handle_type: HandleType,
+    export: &'static str,
+    verification: VerificationProfile,
+) -> HandleRoute {
+    HandleRoute {
+        handle_type,
+        export,
+        encryption: EncryptionPolicy::Plaintext,
+        sender: SenderPolicy::Null,
+        verification,
+        ibc_entrypoint: true,
+        requires_elapsed_timeout: false,
+        consumes_outgoing_transfer: false,
+        shaped_entrypoint: None,
+    }
+}
+
+const fn ibc_hooks_route(handle_type: HandleType, export: &'static str) -> HandleRoute {
+    HandleRoute {
+        handle_type,
+        export,
+        encryption: EncryptionPolicy::Plaintext,
+        sender: SenderPolicy::Null,
+        verification: VerificationProfile::Relayed,
+        ibc_entrypoint: false,
+        requires_elapsed_timeout: false,
+        consumes_outgoing_transfer: false,
+        shaped_entrypoint: None,
+    }
+}
+
+/// The routes of the handle types, in the order of their values
+static HANDLE_ROUTES: &[HandleRoute] = &[
+    HandleRoute {
+        handle_type: HandleType::HANDLE_TYPE_EXECUTE,
+        export: "execute",
+        encryption: EncryptionPolicy::Sender,
+        sender: SenderPolicy::Verified,
+        verification: VerificationProfile::Signed,
+        ibc_entrypoint: false,
+        requires_elapsed_timeout: false,
+        consumes_outgoing_transfer: false,
+        shaped_entrypoint: Some(ShapedEntrypoint::Execute),
+    },
+    HandleRoute {
+        handle_type: HandleType::HANDLE_TYPE_REPLY,
+        export: "reply",
+        encryption: EncryptionPolicy::OriginalMessage,
+        sender: SenderPolicy::Null,
+        verification: VerificationProfile::SignedIfEncrypted,
+        ibc_entrypoint: false,
+        requires_elapsed_timeout: false,
+        consumes_outgoing_transfer: false,
+        shaped_entrypoint: Some(ShapedEntrypoint::Reply),
+    },
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_OPEN,
+        "ibc_channel_open",
+        VerificationProfile::Unverified,
+    ),
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_CONNECT,
+        "ibc_channel_connect",
+        VerificationProfile::Unverified,
+    ),
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_CLOSE,
+        "ibc_channel_close",
+        VerificationProfile::Unverified,
+    ),
+    HandleRoute {
+        encryption: EncryptionPolicy::PacketData,
+        ..ibc_protocol_route(
+            HandleType::HANDLE_TYPE_IBC_PACKET_RECEIVE,
+            "ibc_packet_receive",
+            VerificationProfile::Relayed,
+        )
+    },
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_PACKET_ACK,
+        "ibc_packet_ack",
+        VerificationProfile::Relayed,
+    ),
+    HandleRoute {
+        requires_elapsed_timeout: true,
+        ..ibc_protocol_route(
+            HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT,
+            "ibc_packet_timeout",
+            VerificationProfile::Relayed,
+        )
+    },
+    ibc_hooks_route(
+        HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER,
+        "execute",
+    ),
+    HandleRoute {
+        consumes_outgoing_transfer: true,
+        ..ibc_hooks_route(
+            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK,
+            "sudo",
+        )
+    },
+    HandleRoute {
+        requires_elapsed_timeout: true,
+        consumes_outgoing_transfer: true,
+        ..ibc_hooks_route(
+            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT,
+            "sudo",
+        )
+    },
+    ibc_hooks_route(
+        HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER,
+        "execute",
+    ),
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_INIT,
+        "ibc_channel_upgrade_init",
+        VerificationProfile::Relayed,
+    ),
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_TRY,
+        "ibc_channel_upgrade_try",
+        VerificationProfile::Relayed,
+    ),
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_ACK,
+        "ibc_channel_upgrade_ack",
+        VerificationProfile::Relayed,
+    ),
+    ibc_protocol_route(
+        HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM,
+        "ibc_channel_upgrade_confirm",
+        VerificationProfile::Relayed,
+    ),
+];
+
+pub fn handle_route(handle_type: HandleType) -> &'static HandleRoute {
+    &HANDLE_ROUTES[handle_type as usize]
+}
+
+pub fn handle_routes() -> &'static [HandleRoute] {
+    HANDLE_ROUTES
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_handle_routes() {
+        // Routes are looked up by the value of their handle type
+        for (value, route) in HANDLE_ROUTES.iter().enumerate() {
+            assert_eq!(route.handle_type as usize, value);
+        }
+        assert_eq!(
+            HANDLE_ROUTES.len(),
+            HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM as usize + 1
+        );
+
+        let timeout = handle_route(HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT);
+        assert_eq!(timeout.export, "ibc_packet_timeout");
+        assert_eq!(timeout.encryption, EncryptionPolicy::Plaintext);
+        assert!(timeout.ibc_entrypoint && timeout.requires_elapsed_timeout);
+        assert!(!timeout.consumes_outgoing_transfer);
+
+        let hooks_timeout =
+            handle_route(HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT);
+        assert_eq!(hooks_timeout.export, "sudo");
+        assert!(!hooks_timeout.ibc_entrypoint);
+        assert!(hooks_timeout.requires_elapsed_timeout && hooks_timeout.consumes_outgoing_transfer);
+
+        // Channel upgrades are verified against the upgrade message of the transaction
+        let upgrade_ack = handle_route(HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_ACK);
+        assert_eq!(upgrade_ack.export, "ibc_channel_upgrade_ack");
+        assert!(upgrade_ack.ibc_entrypoint);
+        assert_eq!(upgrade_ack.verification, VerificationProfile::Relayed);
+
+        // Only executed messages keep their verified sender
+        for route in HANDLE_ROUTES {
+            assert_eq!(
+                route.sender == SenderPolicy::Verified,
+                route.handle_type == HandleType::HANDLE_TYPE_EXECUTE
+            );
+        }
+    }
+}
@@ -2,6 +2,9 @@ use log::trace;
 use std::collections::HashMap;
 use cw_types_v010::types::{CanonicalAddr, HumanAddr};
 
+use crate::compute_params::ComputeParams;
+
+// These lists are frozen: new admins go to the signed admin registry, see `admin_registry`
 lazy_static::lazy_static! {
     /// Current hardcoded contract admins
     static ref HARDCODED_CONTRACT_ADMINS: HashMap<&'static str, &'static str> = HashMap::from([
@@ -585,11 +588,12 @@ lazy_static::lazy_static! {
     ]);
 }
 
-/// Current hardcoded contract admins
+/// Current hardcoded contract admins, and the ones of the admin registry in the compute params
 pub fn is_hardcoded_contract_admin(
     contract: &CanonicalAddr,
     admin: &CanonicalAddr,
     admin_proof: &[u8],
+    compute_params: &ComputeParams,
 ) -> bool {
     if admin_proof != [0; enclave_crypto::HASH_SIZE] {
         return false;
@@ -616,6 +620,9 @@ pub fn is_hardcoded_contract_admin(
     let admin = admin.unwrap();
 
     HARDCODED_CONTRACT_ADMINS.get(contract.as_str()) == Some(&admin.as_str())
+        || compute_params
+            .admin_registry()
+            .map_or(false, |registry| registry.is_admin(contract.as_str(), admin.as_str()))
 }
 
 /// The entire history of contracts that were deployed before v1.10 and have been migrated using the hardcoded admin feature.
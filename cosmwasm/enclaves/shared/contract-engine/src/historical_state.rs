@@ -0,0 +1,181 @@
+//! The state that queries at past heights read. The host can't be trusted to provide the state
+//! as of a past height, so every read of such a query comes with a proof against the app hash
+//! that the light client verified for that height, and the enclave takes the value from the
+//! proof.
+
+use std::cell::RefCell;
+
+use log::*;
+
+use crate::cosmwasm_config::state_layout::{COMPUTE_STORE_NAME, CONTRACT_STORE_PREFIX};
+use crate::errors::WasmEngineError;
+use crate::state_proofs::ReadProof;
+
+/// The app hash that commits to the state a query at a past height reads, and the prefix of the
+/// keys of its contract in the compute store
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoricalState {
+    app_hash: Vec<u8>,
+    key_prefix: Vec<u8>,
+}
+
+impl HistoricalState {
+    pub fn new(app_hash: Vec<u8>, canonical_contract_address: &[u8]) -> Self {
+        let mut key_prefix = vec![CONTRACT_STORE_PREFIX];
+        key_prefix.extend_from_slice(canonical_contract_address);
+
+        Self {
+            app_hash,
+            key_prefix,
+        }
+    }
+
+    /// Verifies the proof of a read of a key of the contract, and returns its proven value
+    fn verify_read(&self, key: &[u8], proof: &[u8]) -> Result<Option<Vec<u8>>, WasmEngineError> {
+        let proof: ReadProof = serde_json::from_slice(proof).map_err(|err| {
+            warn!("host sent a read proof that can't be deserialized: {}", err);
+            WasmEngineError::HostMisbehavior
+        })?;
+
+        let mut store_key = self.key_prefix.clone();
+        store_key.extend_from_slice(key);
+        let value = proof
+            .verify(COMPUTE_STORE_NAME, &store_key, &self.app_hash)
+            .map_err(|_| WasmEngineError::HostMisbehavior)?;
+
+        Ok(value.map(|value| value.to_vec()))
+    }
+}
+
+thread_local! {
+    /// The state of the query at a past height that runs on this thread, if any
+    static HISTORICAL_STATE: RefCell<Option<HistoricalState>> = RefCell::new(None);
+}
+
+/// Restores the state that was read before the query at a past height when it's dropped, so that
+/// the queries it makes read the same height and the state is cleared once it's done
+pub struct HistoricalStateGuard {
+    previous: Option<HistoricalState>,
+}
+
+/// Makes the reads on this thread go through proofs against the app hash until the guard is
+/// dropped
+pub fn enter_historical_state(state: HistoricalState) -> HistoricalStateGuard {
+    let previous = HISTORICAL_STATE.with(|current| current.borrow_mut().replace(state));
+    HistoricalStateGuard { previous }
+}
+
+impl Drop for HistoricalStateGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        HISTORICAL_STATE.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Whether the reads on this thread are of a past height, and must be proven
+pub fn is_historical() -> bool {
+    HISTORICAL_STATE.with(|current| current.borrow().is_some())
+}
+
+/// Verifies the proof of a read against the state of the query at a past height on this thread
+pub fn verify_historical_read(
+    key: &[u8],
+    proof: &[u8],
+) -> Result<Option<Vec<u8>>, WasmEngineError> {
+    HISTORICAL_STATE.with(|current| match current.borrow().as_ref() {
+        Some(state) => state.verify_read(key, proof),
+        None => {
+            error!("got a read proof outside of a query at a past height");
+            Err(WasmEngineError::HostMisbehavior)
+        }
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cw_types_v010::encoding::Binary;
+    use enclave_crypto::sha_256;
+
+    use crate::state_proofs::{ExistenceProof, InnerOp, KeyProof};
+
+    /// A read proof of a key in a compute store with a single leaf, in a multistore with
+    /// another store
+    fn read_proof(store_key: &[u8], value: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut leaf = vec![0u8, 2, 10];
+        leaf.push(store_key.len() as u8);
+        leaf.extend_from_slice(store_key);
+        leaf.push(32);
+        leaf.extend_from_slice(&sha_256(value));
+        let store_root = sha_256(&leaf);
+
+        let multistore_proof = ExistenceProof {
+            key: Binary(COMPUTE_STORE_NAME.to_vec()),
+            value: Binary(store_root.to_vec()),
+            leaf_prefix: Binary(vec![]),
+            path: vec![InnerOp {
+                prefix: Binary(vec![1]),
+                suffix: Binary(vec![7u8; 32]),
+            }],
+        };
+        let mut multistore_leaf = vec![0u8, COMPUTE_STORE_NAME.len() as u8];
+        multistore_leaf.extend_from_slice(COMPUTE_STORE_NAME);
+        multistore_leaf.push(32);
+        multistore_leaf.extend_from_slice(&sha_256(&store_root));
+        let mut root = vec![1u8];
+        root.extend_from_slice(&sha_256(&multistore_leaf));
+        root.extend_from_slice(&[7u8; 32]);
+        let app_hash = sha_256(&root).to_vec();
+
+        let proof = ReadProof {
+            store_proof: KeyProof::Exist(ExistenceProof {
+                key: Binary(store_key.to_vec()),
+                value: Binary(value.to_vec()),
+                leaf_prefix: Binary(vec![0, 2, 10]),
+                path: vec![],
+            }),
+            multistore_proof,
+        };
+
+        (serde_json::to_vec(&proof).unwrap(), app_hash)
+    }
+
+    pub fn test_historical_reads_are_proven() {
+        let contract = [9u8; 20];
+        let mut store_key = vec![CONTRACT_STORE_PREFIX];
+        store_key.extend_from_slice(&contract);
+        store_key.extend_from_slice(b"key");
+        let (proof, app_hash) = read_proof(&store_key, b"value");
+
+        // Outside of a query at a past height proofs aren't expected
+        assert!(!is_historical());
+        assert!(verify_historical_read(b"key", &proof).is_err());
+
+        {
+            let _guard = enter_historical_state(HistoricalState::new(app_hash.clone(), &contract));
+            assert!(is_historical());
+            assert_eq!(
+                verify_historical_read(b"key", &proof).unwrap(),
+                Some(b"value".to_vec())
+            );
+
+            // The proof of another key, or of the key of another contract
+            assert!(verify_historical_read(b"other", &proof).is_err());
+            {
+                let _nested =
+                    enter_historical_state(HistoricalState::new(app_hash.clone(), &[8u8; 20]));
+                assert!(verify_historical_read(b"key", &proof).is_err());
+            }
+
+            // The state is restored once the nested query is done
+            assert!(verify_historical_read(b"key", &proof).is_ok());
+            assert!(verify_historical_read(b"key", b"not a proof").is_err());
+        }
+        assert!(!is_historical());
+
+        // A proof against another app hash
+        let _guard = enter_historical_state(HistoricalState::new(vec![0u8; 32], &contract));
+        assert!(verify_historical_read(b"key", &proof).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! Guards the ibc-hooks ack and timeout callbacks of outgoing transfers against replays.
+//!
+//! The host controls the state of IBC, so it could relay the ack or timeout of a packet again
+//! in a later block, and make a contract handle the callback twice. The enclave records the
+//! packets whose callbacks the contract processed in the contract's state, under reserved keys
+//! that the host can't forge, and refuses to process a callback of the same packet again. Only
+//! delivered transactions record callbacks, so that a simulation or a `CheckTx` can't make the
+//! callback of a later block fail.
+
+use std::convert::TryInto;
+
+use log::*;
+
+use enclave_cosmos_types::types::Packet;
+use enclave_crypto::sha_256;
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::reserved_keys;
+use crate::wasm3::Engine;
+
+/// A packet is identified by its source channel and sequence
+fn processed_callback_key(channel: &str, sequence: u64) -> Vec<u8> {
+    let packet_id = serde_json::json!([channel, sequence]).to_string();
+
+    let mut key = reserved_keys::PROCESSED_CALLBACKS_PREFIX.to_vec();
+    key.extend_from_slice(&sha_256(packet_id.as_bytes()));
+    key
+}
+
+/// Refuses the callback of a packet whose callback the contract already processed
+pub fn check_callback_not_processed(
+    engine: &mut Engine,
+    packet: &Packet,
+) -> Result<(), EnclaveError> {
+    let key = processed_callback_key(&packet.source_channel, packet.sequence);
+    if let Some(height) = engine.read_reserved_key(&key)? {
+        let height = height
+            .as_slice()
+            .try_into()
+            .map(u64::from_be_bytes)
+            .unwrap_or_default();
+        warn!(
+            "the callback of packet {} on channel {} was already processed at height {}",
+            packet.sequence, packet.source_channel, height
+        );
+        return Err(EnclaveError::DuplicateIbcCallback);
+    }
+
+    Ok(())
+}
+
+/// Records the callback of a packet in the contract's state. The record is only flushed if the
+/// execution succeeds, so a callback that failed can still be relayed again.
+pub fn record_processed_callback(engine: &mut Engine, packet: &Packet, block_height: u64) {
+    let key = processed_callback_key(&packet.source_channel, packet.sequence);
+    engine.write_reserved_key(&key, &block_height.to_be_bytes());
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_processed_callback_key() {
+        let key = processed_callback_key("channel-0", 1);
+        assert!(reserved_keys::is_reserved(&key));
+        assert_eq!(processed_callback_key("channel-0", 1), key);
+
+        // Other sequences and channels are other packets
+        assert_ne!(processed_callback_key("channel-0", 2), key);
+        assert_ne!(processed_callback_key("channel-1", 1), key);
+        // The channel and the sequence can't be shifted into each other
+        assert_ne!(
+            processed_callback_key("channel-1", 23),
+            processed_callback_key("channel-12", 3)
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use log::*;
 use sha2::{Digest, Sha256};
 
 /// ReceiverChainIsSource returns true if the denomination originally came
@@ -128,3 +129,58 @@ pub fn is_valid_channel_id(channel_id: &str) -> bool {
 pub fn parse_channel_sequence(channel_id: &str) -> Option<&str> {
     channel_id.strip_prefix("channel-")
 }
+
+/// Returns the local denom of tokens received in a packet, whose denom is the denom in the sender
+/// chain. ICS-721 class IDs are traced the same way, so this also returns the local class ID of
+/// received NFTs.
+/// Logic source: https://github.com/scrtlabs/SecretNetwork/blob/96b0ba7d6/x/ibc-hooks/wasm_hook.go#L483-L513
+pub fn received_denom(
+    source_port: &str,
+    source_channel: &str,
+    destination_port: &str,
+    destination_channel: &str,
+    packet_denom: &str,
+) -> Option<String> {
+    received_denom_trace(
+        source_port,
+        source_channel,
+        destination_port,
+        destination_channel,
+        packet_denom,
+    )
+    .map(|denom_trace| denom_trace.ibc_denom())
+}
+
+/// Returns the trace of tokens received in a packet, whose local denom is its `ibc_denom`. The
+/// trace of tokens that return to the chain they were minted in has an empty path.
+pub fn received_denom_trace(
+    source_port: &str,
+    source_channel: &str,
+    destination_port: &str,
+    destination_channel: &str,
+    packet_denom: &str,
+) -> Option<DenomTrace> {
+    if receiver_chain_is_source(source_port, source_channel, packet_denom) {
+        // remove prefix added by sender chain
+        let voucher_prefix = get_denom_prefix(source_port, source_channel);
+
+        let unprefixed_denom = match packet_denom.strip_prefix(&voucher_prefix) {
+            Some(unprefixed_denom) => unprefixed_denom,
+            None => {
+                trace!(
+                    "packet denom doesn't start with voucher_prefix: {:?} != {:?}",
+                    packet_denom,
+                    voucher_prefix,
+                );
+                return None;
+            }
+        };
+
+        // The denomination used to send the coins is either the native denom or the hash of the path
+        // if the denomination is not native.
+        Some(parse_denom_trace(unprefixed_denom))
+    } else {
+        let prefixed_denom = get_denom_prefix(destination_port, destination_channel) + packet_denom;
+        Some(parse_denom_trace(&prefixed_denom))
+    }
+}
@@ -1,5 +1,5 @@
 use crate::message_utils::try_get_decrypted_secret_msg;
-use crate::types::{ParsedMessage, SecretMessage};
+use crate::types::{EnvelopeVersion, ParsedMessage, SecretMessage};
 use cw_types_v1::ibc::IbcPacketReceiveMsg;
 use enclave_ffi_types::EnclaveError;
 use log::{trace, warn};
@@ -8,14 +8,13 @@ pub fn parse_plaintext_ibc_protocol_message(
     plaintext_message: &[u8],
 ) -> Result<ParsedMessage, EnclaveError> {
     Ok(ParsedMessage {
-        should_verify_sig_info: false,
-        should_verify_input: false,
         was_msg_encrypted: false,
         should_encrypt_output: false,
         secret_msg: SecretMessage {
             nonce: [0; 32],
             user_public_key: [0; 32],
             msg: plaintext_message.into(),
+            version: EnvelopeVersion::Unversioned,
         },
         decrypted_msg: plaintext_message.into(),
         data_for_validation: None,
@@ -62,14 +61,13 @@ pub fn parse_ibc_receive_message(message: &[u8]) -> Result<ParsedMessage, Enclav
                         nonce: [0; 32],
                         user_public_key: [0; 32],
                         msg: message.into(),
+                        version: EnvelopeVersion::Unversioned,
                     },
                 )
             }
         };
 
     Ok(ParsedMessage {
-        should_verify_sig_info: false,
-        should_verify_input: true,
         was_msg_encrypted,
         should_encrypt_output: was_msg_encrypted,
         secret_msg,
@@ -83,23 +81,3 @@ pub fn parse_ibc_receive_message(message: &[u8]) -> Result<ParsedMessage, Enclav
         data_for_validation: None,
     })
 }
-
-/// `parse_plaintext_ibc_validated_message()` is very similar to `parse_plaintext_ibc_protocol_message()`.
-/// The only difference is that it returns `should_verify_input: true`.
-pub fn parse_plaintext_ibc_validated_message(
-    plaintext_message: &[u8],
-) -> Result<ParsedMessage, EnclaveError> {
-    Ok(ParsedMessage {
-        should_verify_sig_info: false,
-        should_verify_input: true,
-        was_msg_encrypted: false,
-        should_encrypt_output: false,
-        secret_msg: SecretMessage {
-            nonce: [0; 32],
-            user_public_key: [0; 32],
-            msg: plaintext_message.into(),
-        },
-        decrypted_msg: plaintext_message.into(),
-        data_for_validation: None,
-    })
-}
@@ -0,0 +1,508 @@
+//! Idempotency keys of executions.
+//!
+//! Wallets that broadcast a transaction again, e.g. after it timed out in the mempool, can
+//! execute a contract twice. A client protects an execution by putting a key in front of the
+//! message it encrypts, after the code hash:
+//!
+//! ```text
+//! code_hash | MAGIC_BYTES | len(key) as u32 | key | msg
+//! ```
+//!
+//! The enclave remembers the hash of the sender and the key for `WINDOW_BLOCKS`: every hash has
+//! its own key in the contract's state, reserved for the enclave, which holds the height it was
+//! recorded at. An execution with a key that is remembered isn't executed again. It succeeds
+//! with the `ALREADY_EXECUTED_ATTRIBUTE` attribute, which is encrypted to the sender like any
+//! other output, unless it sent funds: the funds were already moved to the contract, so it fails
+//! instead and the transfer is reverted.
+//!
+//! Only successful executions are remembered, so a failed execution can be retried with the same
+//! key. A key that expired is removed from the state when it's checked again. Keys that are
+//! never checked again are removed by later executions: every key is also added to an index that
+//! is ordered by the height it was recorded at, and an execution that records a key removes up to
+//! `SWEPT_KEYS_PER_EXECUTION` expired keys from the start of the index. The host stores the keys
+//! in a random order, so the index is a list whose entries point to the next one.
+
+use std::convert::TryInto;
+
+use log::*;
+
+use cw_types_generic::CosmWasmApiVersion;
+use cw_types_v010::types::{CanonicalAddr, ContractResult, LogAttribute};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_crypto::{sha_256, HASH_SIZE};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::idempotency_keys::{
+    ALREADY_EXECUTED_ATTRIBUTE, MAGIC_BYTES, MAX_KEY_SIZE, SWEPT_KEYS_PER_EXECUTION, WINDOW_BLOCKS,
+};
+use crate::cosmwasm_config::reserved_keys::{
+    IDEMPOTENCY_EXPIRY_CURSOR, IDEMPOTENCY_EXPIRY_PREFIX, IDEMPOTENCY_KEYS_PREFIX,
+};
+use crate::io::RawWasmOutput;
+use crate::wasm3::Engine;
+
+const SIZE_OF_U32: usize = 4;
+const SIZE_OF_U64: usize = 8;
+const SIZE_OF_EXPIRY_ID: usize = SIZE_OF_U64 + HASH_SIZE;
+
+/// The hash that identifies the key of a sender, so that senders can't collide with each other
+pub type KeyHash = [u8; HASH_SIZE];
+
+/// Identifies an entry of the expiry index: the height the key was recorded at, big endian, and
+/// its hash
+type ExpiryId = [u8; SIZE_OF_EXPIRY_ID];
+
+/// Removes the idempotency key from the start of a decrypted message, if it has one
+pub fn take_idempotency_key(msg: &mut Vec<u8>) -> Result<Option<Vec<u8>>, EnclaveError> {
+    if !is_active(Feature::IdempotencyKeys) || !msg.starts_with(MAGIC_BYTES) {
+        return Ok(None);
+    }
+
+    let header_len = MAGIC_BYTES.len() + SIZE_OF_U32;
+    if msg.len() < header_len {
+        warn!("Malformed idempotency key header");
+        return Err(EnclaveError::ValidationFailure);
+    }
+    let key_len = u32::from_be_bytes(msg[MAGIC_BYTES.len()..header_len].try_into().unwrap());
+    let key_len = key_len as usize;
+    if key_len == 0 || key_len > MAX_KEY_SIZE || msg.len() < header_len + key_len {
+        warn!("Idempotency key has an invalid length: {}", key_len);
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let key = msg[header_len..header_len + key_len].to_vec();
+    *msg = msg[header_len + key_len..].to_vec();
+    Ok(Some(key))
+}
+
+pub fn idempotency_key_hash(sender: &CanonicalAddr, key: &[u8]) -> KeyHash {
+    let mut data = vec![sender.len() as u8];
+    data.extend_from_slice(sender.as_slice());
+    data.extend_from_slice(key);
+    sha_256(&data)
+}
+
+/// The reserved key that remembers the key hash
+fn idempotency_entry_key(key_hash: &KeyHash) -> Vec<u8> {
+    let mut key = IDEMPOTENCY_KEYS_PREFIX.to_vec();
+    key.extend_from_slice(key_hash);
+    key
+}
+
+/// The height a key was recorded at
+fn parse_recorded_height(stored: &[u8]) -> Result<u64, EnclaveError> {
+    let stored: [u8; SIZE_OF_U64] = stored.try_into().map_err(|_| {
+        warn!("stored idempotency key has a wrong size: {}", stored.len());
+        EnclaveError::FailedToDeserialize
+    })?;
+    Ok(u64::from_be_bytes(stored))
+}
+
+/// Whether a key recorded at `recorded_at` is still remembered at `height`
+fn is_remembered(recorded_at: u64, height: u64) -> bool {
+    recorded_at.saturating_add(WINDOW_BLOCKS) > height
+}
+
+fn expiry_id(height: u64, key_hash: &KeyHash) -> ExpiryId {
+    let mut id = [0u8; SIZE_OF_EXPIRY_ID];
+    id[..SIZE_OF_U64].copy_from_slice(&height.to_be_bytes());
+    id[SIZE_OF_U64..].copy_from_slice(key_hash);
+    id
+}
+
+fn parse_expiry_id(stored: &[u8]) -> Result<ExpiryId, EnclaveError> {
+    stored.try_into().map_err(|_| {
+        warn!(
+            "stored idempotency expiry entry has a wrong size: {}",
+            stored.len()
+        );
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+fn expiry_id_height(id: &ExpiryId) -> u64 {
+    u64::from_be_bytes(id[..SIZE_OF_U64].try_into().unwrap())
+}
+
+fn expiry_id_key_hash(id: &ExpiryId) -> KeyHash {
+    id[SIZE_OF_U64..].try_into().unwrap()
+}
+
+/// The reserved key of an entry of the expiry index, which holds the id of the next entry, or its
+/// own id if it's the last one
+fn expiry_entry_key(id: &ExpiryId) -> Vec<u8> {
+    let mut key = IDEMPOTENCY_EXPIRY_PREFIX.to_vec();
+    key.extend_from_slice(id);
+    key
+}
+
+/// The first and the last entries of the expiry index, if it has any
+fn read_expiry_cursor(
+    reserved_keys: &mut impl ReservedKeys,
+) -> Result<Option<(ExpiryId, ExpiryId)>, EnclaveError> {
+    match reserved_keys.read(IDEMPOTENCY_EXPIRY_CURSOR)? {
+        Some(stored) if stored.len() == 2 * SIZE_OF_EXPIRY_ID => Ok(Some((
+            parse_expiry_id(&stored[..SIZE_OF_EXPIRY_ID])?,
+            parse_expiry_id(&stored[SIZE_OF_EXPIRY_ID..])?,
+        ))),
+        Some(stored) => {
+            warn!(
+                "stored idempotency expiry cursor has a wrong size: {}",
+                stored.len()
+            );
+            Err(EnclaveError::FailedToDeserialize)
+        }
+        None => Ok(None),
+    }
+}
+
+/// The keys reserved for the enclave in the state of the contract, where the keys are remembered
+trait ReservedKeys {
+    fn read(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, EnclaveError>;
+    fn write(&mut self, key: &[u8], value: &[u8]);
+    fn remove(&mut self, key: &[u8]) -> Result<(), EnclaveError>;
+}
+
+impl ReservedKeys for Engine {
+    fn read(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, EnclaveError> {
+        self.read_reserved_key(key)
+    }
+
+    fn write(&mut self, key: &[u8], value: &[u8]) {
+        self.write_reserved_key(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Result<(), EnclaveError> {
+        self.remove_reserved_key(key)
+    }
+}
+
+/// Whether the sender already executed the contract with the key. Fails if it did, but sent
+/// funds again.
+pub fn check_idempotency_key(
+    engine: &mut Engine,
+    key_hash: &KeyHash,
+    height: u64,
+    sent_funds: bool,
+) -> Result<bool, EnclaveError> {
+    check_remembered_key(engine, key_hash, height, sent_funds)
+}
+
+fn check_remembered_key(
+    reserved_keys: &mut impl ReservedKeys,
+    key_hash: &KeyHash,
+    height: u64,
+    sent_funds: bool,
+) -> Result<bool, EnclaveError> {
+    let entry_key = idempotency_entry_key(key_hash);
+    let recorded_at = match reserved_keys.read(&entry_key)? {
+        Some(stored) => parse_recorded_height(&stored)?,
+        None => return Ok(false),
+    };
+    if !is_remembered(recorded_at, height) {
+        // Nothing reads the entry after it expires, so it would stay in the state forever
+        trace!("removing expired idempotency key {:x?}", key_hash);
+        reserved_keys.remove(&entry_key)?;
+        return Ok(false);
+    }
+
+    debug!(
+        "execution with idempotency key {:x?} was already executed",
+        key_hash
+    );
+    if sent_funds {
+        return Err(EnclaveError::DuplicateExecution);
+    }
+    Ok(true)
+}
+
+/// Remembers the key of a successful execution, and removes some of the keys that expired
+pub fn record_idempotency_key(
+    engine: &mut Engine,
+    key_hash: KeyHash,
+    height: u64,
+) -> Result<(), EnclaveError> {
+    record_remembered_key(engine, &key_hash, height)
+}
+
+fn record_remembered_key(
+    reserved_keys: &mut impl ReservedKeys,
+    key_hash: &KeyHash,
+    height: u64,
+) -> Result<(), EnclaveError> {
+    reserved_keys.write(&idempotency_entry_key(key_hash), &height.to_be_bytes());
+
+    // Keys are recorded in the order of the heights, so appending keeps the index ordered
+    let id = expiry_id(height, key_hash);
+    reserved_keys.write(&expiry_entry_key(&id), &id);
+    let first = match read_expiry_cursor(reserved_keys)? {
+        Some((first, last)) => {
+            reserved_keys.write(&expiry_entry_key(&last), &id);
+            first
+        }
+        None => id,
+    };
+
+    sweep_expired_keys(reserved_keys, first, id, height)
+}
+
+/// Removes up to `SWEPT_KEYS_PER_EXECUTION` expired keys from the start of the expiry index, and
+/// stores where the index starts and ends after them
+fn sweep_expired_keys(
+    reserved_keys: &mut impl ReservedKeys,
+    mut first: ExpiryId,
+    last: ExpiryId,
+    height: u64,
+) -> Result<(), EnclaveError> {
+    for _ in 0..SWEPT_KEYS_PER_EXECUTION {
+        let recorded_at = expiry_id_height(&first);
+        if is_remembered(recorded_at, height) {
+            break;
+        }
+
+        let entry_key = expiry_entry_key(&first);
+        let next = match reserved_keys.read(&entry_key)? {
+            Some(next) => parse_expiry_id(&next)?,
+            None => {
+                warn!("idempotency expiry entry is missing from the index");
+                return Err(EnclaveError::FailedToDeserialize);
+            }
+        };
+        reserved_keys.remove(&entry_key)?;
+
+        // The key may have been checked after it expired and recorded again since
+        let key_hash = expiry_id_key_hash(&first);
+        let idempotency_key = idempotency_entry_key(&key_hash);
+        if let Some(stored) = reserved_keys.read(&idempotency_key)? {
+            if parse_recorded_height(&stored)? == recorded_at {
+                trace!("removing expired idempotency key {:x?}", key_hash);
+                reserved_keys.remove(&idempotency_key)?;
+            }
+        }
+
+        if next == first {
+            return reserved_keys.remove(IDEMPOTENCY_EXPIRY_CURSOR);
+        }
+        first = next;
+    }
+
+    reserved_keys.write(IDEMPOTENCY_EXPIRY_CURSOR, &[first, last].concat());
+    Ok(())
+}
+
+/// The output of an execution that was already executed, which is the same for every contract
+/// of the API version
+pub fn already_executed_output(api_version: CosmWasmApiVersion) -> Result<Vec<u8>, EnclaveError> {
+    let attribute = LogAttribute {
+        key: ALREADY_EXECUTED_ATTRIBUTE.to_string(),
+        value: "true".to_string(),
+        encrypted: true,
+    };
+    let output = match api_version {
+        CosmWasmApiVersion::V010 => RawWasmOutput::OkV010 {
+            ok: ContractResult {
+                messages: vec![],
+                log: vec![attribute],
+                data: None,
+            },
+            internal_reply_enclave_sig: None,
+            internal_msg_id: None,
+        },
+        CosmWasmApiVersion::V1 => RawWasmOutput::OkV1 {
+            ok: cw_types_v1::results::Response {
+                messages: vec![],
+                attributes: vec![attribute],
+                events: vec![],
+                data: None,
+            },
+            internal_reply_enclave_sig: None,
+            internal_msg_id: None,
+        },
+        CosmWasmApiVersion::Invalid => return Err(EnclaveError::InvalidWasm),
+    };
+
+    serde_json::to_vec(&output).map_err(|err| {
+        warn!("failed to serialize the already executed output: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    impl ReservedKeys for BTreeMap<Vec<u8>, Vec<u8>> {
+        fn read(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, EnclaveError> {
+            Ok(self.get(key).cloned())
+        }
+
+        fn write(&mut self, key: &[u8], value: &[u8]) {
+            self.insert(key.to_vec(), value.to_vec());
+        }
+
+        fn remove(&mut self, key: &[u8]) -> Result<(), EnclaveError> {
+            BTreeMap::remove(self, key);
+            Ok(())
+        }
+    }
+
+    fn with_key(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        let mut with_key = MAGIC_BYTES.to_vec();
+        with_key.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        with_key.extend_from_slice(key);
+        with_key.extend_from_slice(msg);
+        with_key
+    }
+
+    pub fn test_take_idempotency_key() {
+        if !is_active(Feature::IdempotencyKeys) {
+            let mut msg = with_key(b"key", b"{}");
+            assert_eq!(take_idempotency_key(&mut msg).unwrap(), None);
+            assert_eq!(msg, with_key(b"key", b"{}"));
+            return;
+        }
+
+        let mut msg = with_key(b"key", br#"{"transfer":{}}"#);
+        assert_eq!(
+            take_idempotency_key(&mut msg).unwrap(),
+            Some(b"key".to_vec())
+        );
+        assert_eq!(msg, br#"{"transfer":{}}"#.to_vec());
+        // Messages without a key are left as is
+        assert_eq!(take_idempotency_key(&mut msg).unwrap(), None);
+        assert_eq!(msg, br#"{"transfer":{}}"#.to_vec());
+
+        assert!(take_idempotency_key(&mut with_key(b"", b"{}")).is_err());
+        assert!(take_idempotency_key(&mut with_key(&[1u8; MAX_KEY_SIZE + 1], b"{}")).is_err());
+        let mut truncated = with_key(b"key", b"");
+        truncated.pop();
+        assert!(take_idempotency_key(&mut truncated).is_err());
+    }
+
+    pub fn test_idempotency_keys_window() {
+        let sender = CanonicalAddr::from_vec(vec![1u8; 20]);
+        let first = idempotency_key_hash(&sender, b"first");
+        let second = idempotency_key_hash(&sender, b"second");
+        assert_ne!(
+            idempotency_key_hash(&CanonicalAddr::from_vec(vec![2u8; 20]), b"first"),
+            first
+        );
+
+        // Every key hash has its own reserved key
+        assert!(idempotency_entry_key(&first).starts_with(IDEMPOTENCY_KEYS_PREFIX));
+        assert_ne!(
+            idempotency_entry_key(&first),
+            idempotency_entry_key(&second)
+        );
+
+        assert!(is_remembered(100, 100));
+        assert!(is_remembered(100, 100 + WINDOW_BLOCKS - 1));
+        assert!(!is_remembered(100, 100 + WINDOW_BLOCKS));
+        assert!(is_remembered(u64::MAX, u64::MAX - 1));
+
+        assert_eq!(parse_recorded_height(&100u64.to_be_bytes()).unwrap(), 100);
+        assert!(parse_recorded_height(b"short").is_err());
+    }
+
+    pub fn test_already_executed_output() {
+        for &api_version in &[CosmWasmApiVersion::V010, CosmWasmApiVersion::V1] {
+            let output = already_executed_output(api_version).unwrap();
+            assert_eq!(already_executed_output(api_version).unwrap(), output);
+            let raw: RawWasmOutput = serde_json::from_slice(&output).unwrap();
+            assert!(matches!(
+                (api_version, raw),
+                (CosmWasmApiVersion::V010, RawWasmOutput::OkV010 { .. })
+                    | (CosmWasmApiVersion::V1, RawWasmOutput::OkV1 { .. })
+            ));
+        }
+        assert!(already_executed_output(CosmWasmApiVersion::Invalid).is_err());
+    }
+
+    pub fn test_expired_idempotency_keys_are_removed() {
+        let sender = CanonicalAddr::from_vec(vec![1u8; 20]);
+        let key_hash = idempotency_key_hash(&sender, b"key");
+        let entry_key = idempotency_entry_key(&key_hash);
+        let mut state = BTreeMap::new();
+        state.insert(entry_key.clone(), 100u64.to_be_bytes().to_vec());
+
+        let last_height = 100 + WINDOW_BLOCKS - 1;
+        assert!(check_remembered_key(&mut state, &key_hash, last_height, false).unwrap());
+        assert!(check_remembered_key(&mut state, &key_hash, last_height, true).is_err());
+        assert!(state.contains_key(&entry_key));
+
+        assert!(!check_remembered_key(&mut state, &key_hash, last_height + 1, false).unwrap());
+        assert!(!state.contains_key(&entry_key));
+        assert!(!check_remembered_key(&mut state, &key_hash, last_height + 1, false).unwrap());
+    }
+
+    pub fn test_unchecked_idempotency_keys_are_swept() {
+        let sender = CanonicalAddr::from_vec(vec![1u8; 20]);
+        let never_reused = idempotency_key_hash(&sender, b"never reused");
+        let mut state: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+
+        record_remembered_key(&mut state, &never_reused, 100).unwrap();
+        assert!(state.contains_key(&idempotency_entry_key(&never_reused)));
+        assert!(state.contains_key(&expiry_entry_key(&expiry_id(100, &never_reused))));
+
+        // Still remembered in the last block of the window
+        let other = idempotency_key_hash(&sender, b"other");
+        record_remembered_key(&mut state, &other, 100 + WINDOW_BLOCKS - 1).unwrap();
+        assert!(state.contains_key(&idempotency_entry_key(&never_reused)));
+
+        // Gone once the window passed, without being checked again
+        let later = idempotency_key_hash(&sender, b"later");
+        record_remembered_key(&mut state, &later, 100 + WINDOW_BLOCKS).unwrap();
+        assert!(!state.contains_key(&idempotency_entry_key(&never_reused)));
+        assert!(!state.contains_key(&expiry_entry_key(&expiry_id(100, &never_reused))));
+        assert!(state.contains_key(&idempotency_entry_key(&other)));
+        assert!(state.contains_key(&idempotency_entry_key(&later)));
+
+        // Every execution removes a bounded number of keys, and they catch up
+        let mut state: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+        let expired: Vec<KeyHash> = (0..2 * SWEPT_KEYS_PER_EXECUTION as u8)
+            .map(|i| idempotency_key_hash(&sender, &[i]))
+            .collect();
+        for key_hash in &expired {
+            record_remembered_key(&mut state, key_hash, 100).unwrap();
+        }
+        record_remembered_key(&mut state, &later, 100 + WINDOW_BLOCKS).unwrap();
+        let remaining = |state: &BTreeMap<Vec<u8>, Vec<u8>>| {
+            expired
+                .iter()
+                .filter(|key_hash| state.contains_key(&idempotency_entry_key(key_hash)))
+                .count()
+        };
+        assert_eq!(remaining(&state), SWEPT_KEYS_PER_EXECUTION);
+        record_remembered_key(&mut state, &other, 100 + WINDOW_BLOCKS).unwrap();
+        assert_eq!(remaining(&state), 0);
+
+        // A key that was checked after it expired and recorded again isn't removed by its old entry
+        let mut state: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+        record_remembered_key(&mut state, &never_reused, 100).unwrap();
+        let height = 100 + WINDOW_BLOCKS;
+        assert!(!check_remembered_key(&mut state, &never_reused, height, false).unwrap());
+        record_remembered_key(&mut state, &never_reused, height).unwrap();
+        assert!(check_remembered_key(&mut state, &never_reused, height, false).unwrap());
+        assert!(!state.contains_key(&expiry_entry_key(&expiry_id(100, &never_reused))));
+
+        // The index is removed with its last entry
+        record_remembered_key(&mut state, &later, height + WINDOW_BLOCKS).unwrap();
+        record_remembered_key(&mut state, &other, height + 2 * WINDOW_BLOCKS).unwrap();
+        assert_eq!(
+            read_expiry_cursor(&mut state).unwrap(),
+            Some((
+                expiry_id(height + 2 * WINDOW_BLOCKS, &other),
+                expiry_id(height + 2 * WINDOW_BLOCKS, &other)
+            ))
+        );
+        assert_eq!(
+            state
+                .keys()
+                .filter(|key| key.starts_with(IDEMPOTENCY_EXPIRY_PREFIX))
+                .count(),
+            1
+        );
+    }
+}
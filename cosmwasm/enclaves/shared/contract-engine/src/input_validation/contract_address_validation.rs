@@ -1,7 +1,7 @@
 use cw_types_v010::types::HumanAddr;
 use enclave_cosmos_types::types::{
     DirectSdkMsg, FungibleTokenPacketData, IbcHooksIncomingTransferMsg,
-    IbcHooksOutgoingTransferMemo, Packet,
+    IbcHooksOutgoingTransferMemo, NonFungibleTokenPacketData, Packet,
 };
 use log::*;
 
@@ -39,7 +39,12 @@ pub fn verify_contract_address(msg: &DirectSdkMsg, contract_address: &HumanAddr)
             },
             ..
         } => verify_contract_address_msg_ack_or_timeout(source_port, data, contract_address),
-        DirectSdkMsg::Other => false,
+        DirectSdkMsg::MsgChannelUpgrade { port_id, .. } => {
+            verify_contract_address_ibc_contract(port_id, contract_address)
+        }
+        DirectSdkMsg::Other
+        | DirectSdkMsg::MsgTransfer { .. }
+        | DirectSdkMsg::MsgGroupExec { .. } => false,
     }
 }
 
@@ -137,6 +142,9 @@ fn verify_contract_address_msg_recv_packet(
     if destination_port == "transfer" {
         // Packet was routed here through ibc-hooks
         verify_contract_address_ibc_wasm_hooks_incoming_transfer(data, contract_address)
+    } else if destination_port == "nft-transfer" {
+        // NFTs were routed here through ibc-hooks
+        verify_contract_address_ibc_wasm_hooks_incoming_nft_transfer(data, contract_address)
     } else {
         // Packet is for an IBC enabled contract
         verify_contract_address_ibc_contract(destination_port, contract_address)
@@ -187,8 +195,44 @@ fn verify_contract_address_ibc_wasm_hooks_incoming_transfer(
         }
     };
 
+    verify_contract_address_ibc_wasm_hooks_memo(
+        &packet_data.receiver,
+        packet_data.memo,
+        contract_address,
+    )
+}
+
+fn verify_contract_address_ibc_wasm_hooks_incoming_nft_transfer(
+    data: &Vec<u8>,
+    contract_address: &HumanAddr,
+) -> bool {
+    // Parse data as NonFungibleTokenPacketData JSON
+    let packet_data: NonFungibleTokenPacketData = match serde_json::from_slice(data.as_slice()) {
+        Ok(packet_data) => packet_data,
+        Err(err) => {
+            trace!(
+                "Contract was called via ibc-hooks but packet_data cannot be parsed as NonFungibleTokenPacketData: {:?} Error: {:?}",
+                String::from_utf8_lossy(data.as_slice()),
+                err,
+            );
+            return false;
+        }
+    };
+
+    verify_contract_address_ibc_wasm_hooks_memo(
+        &packet_data.receiver,
+        packet_data.memo,
+        contract_address,
+    )
+}
+
+fn verify_contract_address_ibc_wasm_hooks_memo(
+    receiver: &HumanAddr,
+    memo: Option<String>,
+    contract_address: &HumanAddr,
+) -> bool {
     // memo must be set in ibc-hooks
-    let memo = match packet_data.memo {
+    let memo = match memo {
         Some(memo) => memo,
         None => {
             trace!("Contract was called via ibc-hooks but packet_data.memo is empty");
@@ -210,13 +254,12 @@ fn verify_contract_address_ibc_wasm_hooks_incoming_transfer(
     };
 
     // In ibc-hooks contract_address == packet_data.memo.wasm.contract == packet_data.receiver
-    let is_verified =
-        *contract_address == packet_data.receiver && *contract_address == wasm_msg.wasm.contract;
+    let is_verified = contract_address == receiver && *contract_address == wasm_msg.wasm.contract;
     if !is_verified {
         trace!(
             "Contract address sent to enclave {:?} is not the same as in ibc-hooks packet receiver={:?} memo={:?}",
             contract_address,
-            packet_data.receiver,
+            receiver,
             wasm_msg.wasm.contract
         );
     }
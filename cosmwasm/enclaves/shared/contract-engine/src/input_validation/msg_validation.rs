@@ -2,15 +2,17 @@ use cw_types_v010::{
     encoding::Binary,
     types::{CanonicalAddr, HumanAddr},
 };
-use cw_types_v1::ibc::IbcPacketReceiveMsg;
+use cw_types_v1::ibc::{IbcChannelUpgradeMsg, IbcPacketReceiveMsg};
 use enclave_cosmos_types::types::{
-    is_transfer_ack_error, DirectSdkMsg, FungibleTokenPacketData, HandleType, IBCLifecycleComplete,
-    IBCLifecycleCompleteOptions, IBCPacketAckMsg, IBCPacketTimeoutMsg, IbcHooksIncomingTransferMsg,
-    IncentivizedAcknowledgement, Packet, VerifyParamsType,
+    is_transfer_ack_error, DirectSdkMsg, FungibleTokenPacketData, HandleType, Height,
+    IBCLifecycleComplete, IBCLifecycleCompleteOptions, IBCPacketAckMsg, IBCPacketTimeoutMsg,
+    IBCTimeout, IbcHooksIncomingNftTransferMsg, IbcHooksIncomingTransferMsg,
+    IncentivizedAcknowledgement, NonFungibleTokenPacketData, Packet, VerifyParamsType,
 };
 
 use log::*;
 
+use crate::ibc_denom_utils::received_denom;
 use crate::types::SecretMessage;
 
 /// Get the cosmwasm message that contains the encrypted message
@@ -26,7 +28,11 @@ pub fn verify_and_get_sdk_msg<'sd>(
     trace!("verify_and_get_sdk_msg: {:?}", sdk_messages);
 
     sdk_messages.iter().find(|&m| match m {
-        DirectSdkMsg::Other => false,
+        // Transfers don't call contracts, and the messages of group proposals are verified
+        // through their proposals
+        DirectSdkMsg::Other
+        | DirectSdkMsg::MsgTransfer { .. }
+        | DirectSdkMsg::MsgGroupExec { .. } => false,
         DirectSdkMsg::MsgInstantiateContract {
             init_msg: msg,
             sender,
@@ -99,6 +105,9 @@ pub fn verify_and_get_sdk_msg<'sd>(
             VerifyParamsType::HandleType(
                 HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER,
             ) => verify_ibc_wasm_hooks_incoming_transfer(sent_wasm_input, packet),
+            VerifyParamsType::HandleType(
+                HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER,
+            ) => verify_ibc_wasm_hooks_incoming_nft_transfer(sent_wasm_input, packet),
             _ => false,
         },
         DirectSdkMsg::MsgAcknowledgement {
@@ -119,18 +128,59 @@ pub fn verify_and_get_sdk_msg<'sd>(
             ),
             _ => false,
         },
-        DirectSdkMsg::MsgTimeout { packet, signer, .. } => match verify_params_types {
+        DirectSdkMsg::MsgTimeout {
+            packet,
+            timeout_height,
+            timeout_timestamp,
+            signer,
+            ..
+        } => match verify_params_types {
             VerifyParamsType::HandleType(HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT) => {
-                verify_ibc_packet_timeout(sent_wasm_input, packet, signer)
+                verify_ibc_packet_timeout(
+                    sent_wasm_input,
+                    packet,
+                    timeout_height,
+                    *timeout_timestamp,
+                    signer,
+                )
             }
             VerifyParamsType::HandleType(
                 HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT,
             ) => verify_ibc_wasm_hooks_outgoing_transfer_timeout(sent_wasm_input, packet),
             _ => false,
         },
+        DirectSdkMsg::MsgChannelUpgrade {
+            step,
+            port_id,
+            channel_id,
+            version,
+            ..
+        } => {
+            verify_params_types == VerifyParamsType::HandleType(step.handle_type())
+                && verify_ibc_channel_upgrade(sent_wasm_input, port_id, channel_id, version)
+        }
     })
 }
 
+pub fn verify_ibc_channel_upgrade(
+    sent_msg: &SecretMessage,
+    port_id: &str,
+    channel_id: &str,
+    version: &str,
+) -> bool {
+    let parsed = match serde_json::from_slice::<IbcChannelUpgradeMsg>(&sent_msg.msg) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            trace!("get_verified_msg HANDLE_TYPE_IBC_CHANNEL_UPGRADE_*: sent_msg.msg cannot be parsed as IbcChannelUpgradeMsg: {:?} Error: {:?}", String::from_utf8_lossy(&sent_msg.msg), err);
+            return false;
+        }
+    };
+
+    parsed.endpoint.port_id == port_id
+        && parsed.endpoint.channel_id == channel_id
+        && parsed.version == version
+}
+
 pub fn verify_ibc_packet_recv(sent_msg: &SecretMessage, packet: &Packet) -> bool {
     let Packet {
         sequence,
@@ -189,6 +239,57 @@ pub fn verify_ibc_wasm_hooks_incoming_transfer(sent_msg: &SecretMessage, packet:
     ibc_hooks_incoming_transfer_msg.wasm.msg == sent_msg_value.unwrap()
 }
 
+/// Verifies the message a contract receives NFTs with against the ICS-721 packet it received, so
+/// the contract can rely on the class and token IDs in it
+pub fn verify_ibc_wasm_hooks_incoming_nft_transfer(
+    sent_msg: &SecretMessage,
+    packet: &Packet,
+) -> bool {
+    let nft_packet_data = match serde_json::from_slice::<NonFungibleTokenPacketData>(&packet.data) {
+        Ok(nft_packet_data) => nft_packet_data,
+        Err(err) => {
+            trace!("get_verified_msg HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER: data cannot be parsed as NonFungibleTokenPacketData: {:?} Error: {:?}", String::from_utf8_lossy(&packet.data), err);
+            return false;
+        }
+    };
+
+    let memo = nft_packet_data.memo.clone().unwrap_or_default();
+    let memo_msg = match serde_json::from_slice::<IbcHooksIncomingTransferMsg>(memo.as_bytes()) {
+        Ok(memo_msg) => memo_msg,
+        Err(err) => {
+            trace!("get_verified_msg HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER: nft_packet_data.memo cannot be parsed as IbcHooksIncomingTransferMsg: {:?} Error: {:?}", nft_packet_data.memo, err);
+            return false;
+        }
+    };
+
+    let IbcHooksIncomingNftTransferMsg::ReceiveIbcNfts {
+        class_id,
+        token_ids,
+        sender,
+        msg,
+    } = match serde_json::from_slice::<IbcHooksIncomingNftTransferMsg>(&sent_msg.msg) {
+        Ok(sent_nft_transfer_msg) => sent_nft_transfer_msg,
+        Err(err) => {
+            trace!("get_verified_msg HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER: sent_msg.msg cannot be parsed as IbcHooksIncomingNftTransferMsg: {:?} Error: {:?}", String::from_utf8_lossy(&sent_msg.msg), err);
+            return false;
+        }
+    };
+
+    // The packet's class ID is the class ID in the sender chain, like the denoms of ICS-20
+    let local_class_id = received_denom(
+        &packet.source_port,
+        &packet.source_channel,
+        &packet.destination_port,
+        &packet.destination_channel,
+        &nft_packet_data.class_id,
+    );
+
+    local_class_id == Some(class_id)
+        && token_ids == nft_packet_data.token_ids
+        && sender == nft_packet_data.sender
+        && msg == memo_msg.wasm.msg
+}
+
 pub fn verify_ibc_packet_ack(
     sent_msg: &SecretMessage,
     packet: &Packet,
@@ -271,9 +372,34 @@ pub fn verify_ibc_wasm_hooks_outgoing_transfer_ack(
     }
 }
 
+/// Whether the timeout passed to the contract is the timeout of the signed packet
+fn is_same_timeout(
+    sent_timeout: &IBCTimeout,
+    timeout_height: &Option<Height>,
+    timeout_timestamp: u64,
+) -> bool {
+    let sent_height = sent_timeout
+        .block
+        .as_ref()
+        .filter(|block| block.revision != 0 || block.height != 0)
+        .map(|block| Height {
+            revision_number: block.revision,
+            revision_height: block.height,
+        });
+    let sent_timestamp = sent_timeout
+        .timestamp
+        .as_ref()
+        .map(|timestamp| timestamp.u128())
+        .unwrap_or_default();
+
+    sent_height == *timeout_height && sent_timestamp == timeout_timestamp as u128
+}
+
 pub fn verify_ibc_packet_timeout(
     sent_msg: &SecretMessage,
     packet: &Packet,
+    timeout_height: &Option<Height>,
+    timeout_timestamp: u64,
     signer: &String,
 ) -> bool {
     let send_msg_timeout_msg = serde_json::from_slice::<IBCPacketTimeoutMsg>(&sent_msg.msg);
@@ -289,6 +415,11 @@ pub fn verify_ibc_packet_timeout(
         && sent_msg_timeout_msg.packet.dest.port_id == packet.destination_port
         && sent_msg_timeout_msg.packet.sequence == packet.sequence
         && sent_msg_timeout_msg.packet.data.0 == packet.data
+        && is_same_timeout(
+            &sent_msg_timeout_msg.packet.timeout,
+            timeout_height,
+            timeout_timestamp,
+        )
         && sent_msg_timeout_msg.relayer == *signer
 }
 
@@ -311,3 +442,78 @@ pub fn verify_ibc_wasm_hooks_outgoing_transfer_timeout(
         _ => false,
     }
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::types::EnvelopeVersion;
+
+    pub fn test_verify_ibc_wasm_hooks_incoming_nft_transfer() {
+        let data = serde_json::json!({
+            "classId": "nft-transfer/channel-7/secret-punks",
+            "tokenIds": ["1", "2"],
+            "sender": "cosmos1sender",
+            "receiver": "secret1contract",
+            "memo": r#"{"wasm":{"contract":"secret1contract","msg":{"stake":{}}}}"#,
+        });
+        let packet = Packet {
+            sequence: 1,
+            source_port: "nft-transfer".to_string(),
+            source_channel: "channel-7".to_string(),
+            destination_port: "nft-transfer".to_string(),
+            destination_channel: "channel-3".to_string(),
+            data: serde_json::to_vec(&data).unwrap(),
+        };
+        let sent_msg = |msg: serde_json::Value| SecretMessage {
+            nonce: [0u8; 32],
+            user_public_key: [0u8; 32],
+            msg: serde_json::to_vec(&msg).unwrap(),
+            version: EnvelopeVersion::Unversioned,
+        };
+
+        // The class came from this chain, so the prefix of the sender chain is removed
+        let received = serde_json::json!({"receive_ibc_nfts": {
+            "class_id": "secret-punks",
+            "token_ids": ["1", "2"],
+            "sender": "cosmos1sender",
+            "msg": {"stake": {}},
+        }});
+        assert!(verify_ibc_wasm_hooks_incoming_nft_transfer(
+            &sent_msg(received),
+            &packet
+        ));
+
+        // Tokens, classes and messages that aren't in the packet
+        let other_tokens = serde_json::json!({"receive_ibc_nfts": {
+            "class_id": "secret-punks",
+            "token_ids": ["1", "3"],
+            "sender": "cosmos1sender",
+            "msg": {"stake": {}},
+        }});
+        assert!(!verify_ibc_wasm_hooks_incoming_nft_transfer(
+            &sent_msg(other_tokens),
+            &packet
+        ));
+        let other_class = serde_json::json!({"receive_ibc_nfts": {
+            "class_id": "nft-transfer/channel-7/secret-punks",
+            "token_ids": ["1", "2"],
+            "sender": "cosmos1sender",
+            "msg": {"stake": {}},
+        }});
+        assert!(!verify_ibc_wasm_hooks_incoming_nft_transfer(
+            &sent_msg(other_class),
+            &packet
+        ));
+        let other_msg = serde_json::json!({"receive_ibc_nfts": {
+            "class_id": "secret-punks",
+            "token_ids": ["1", "2"],
+            "sender": "cosmos1sender",
+            "msg": {"unstake": {}},
+        }});
+        assert!(!verify_ibc_wasm_hooks_incoming_nft_transfer(
+            &sent_msg(other_msg),
+            &packet
+        ));
+    }
+}
@@ -1,17 +1,98 @@
-use crate::ibc_denom_utils::{get_denom_prefix, parse_denom_trace, receiver_chain_is_source};
-use cw_types_v010::types::Coin;
+use crate::ibc_denom_utils::received_denom_trace;
+use cw_types_generic::{DenomKind, VerifiedCoin, VerifiedFunds};
+use cw_types_v010::types::{CanonicalAddr, Coin, HumanAddr};
+use cw_types_v1::types::DenomTrace;
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
 use enclave_cosmos_types::types::{DirectSdkMsg, FungibleTokenPacketData, Packet};
+use enclave_ffi_types::EnclaveError;
 use log::*;
 
+/// The length of the hex of the hash in `ibc/{hash}` denoms
+const IBC_DENOM_HASH_LENGTH: usize = 64;
+
+/// Whether the denom matches the denom regex of the SDK, `[a-zA-Z][a-zA-Z0-9/:._-]{2,127}`
+fn is_valid_sdk_denom(denom: &str) -> bool {
+    let mut chars = denom.chars();
+    let starts_with_letter = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic());
+
+    starts_with_letter
+        && (3..=128).contains(&denom.len())
+        && chars.all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c))
+}
+
+/// Returns the kind of a denom, or `None` if it isn't valid
+fn denom_kind(denom: &str) -> Option<DenomKind> {
+    if !is_valid_sdk_denom(denom) {
+        return None;
+    }
+
+    if let Some(hash) = denom.strip_prefix("ibc/") {
+        if hash.len() != IBC_DENOM_HASH_LENGTH || hex::decode(hash).is_err() {
+            return None;
+        }
+        return Some(DenomKind::Ibc { trace: None });
+    }
+
+    if let Some(rest) = denom.strip_prefix("factory/") {
+        let mut parts = rest.splitn(2, '/');
+        let creator = HumanAddr(parts.next().unwrap_or_default().to_string());
+        let subdenom = parts.next().unwrap_or_default();
+        if creator.is_empty() || subdenom.is_empty() || CanonicalAddr::from_human(&creator).is_err()
+        {
+            return None;
+        }
+        return Some(DenomKind::Factory { creator });
+    }
+
+    Some(DenomKind::Native)
+}
+
+/// Validates the denoms of the funds sent to a contract. The traces of IBC denoms are attached
+/// when the funds are verified against the message that sent them, see `verify_sent_funds`.
+/// Until `Feature::ValidatedDenoms` activates, every denom is accepted as a native one.
+pub fn verify_funds_denoms(sent_funds: &[Coin]) -> Result<VerifiedFunds, EnclaveError> {
+    if !is_active(Feature::ValidatedDenoms) {
+        return Ok(VerifiedFunds(
+            sent_funds
+                .iter()
+                .map(|coin| VerifiedCoin {
+                    coin: coin.clone(),
+                    kind: DenomKind::Native,
+                })
+                .collect(),
+        ));
+    }
+
+    sent_funds
+        .iter()
+        .map(|coin| match denom_kind(&coin.denom) {
+            Some(kind) => Ok(VerifiedCoin {
+                coin: coin.clone(),
+                kind,
+            }),
+            None => {
+                warn!(
+                    "Funds sent to the contract have an invalid denom: {:?}",
+                    coin.denom
+                );
+                Err(EnclaveError::FailedTxVerification)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(VerifiedFunds)
+}
+
 /// Check that the funds listed in the cosmwasm message matches the ones in env
-pub fn verify_sent_funds(msg: &DirectSdkMsg, sent_funds_msg: &[Coin]) -> bool {
+pub fn verify_sent_funds(msg: &DirectSdkMsg, funds: &mut VerifiedFunds) -> bool {
     match msg {
         DirectSdkMsg::MsgExecuteContract { sent_funds, .. }
         | DirectSdkMsg::MsgInstantiateContract {
             init_funds: sent_funds,
             ..
-        } => sent_funds_msg == sent_funds,
-        DirectSdkMsg::Other => false,
+        } => funds.0.iter().map(|verified| &verified.coin).eq(sent_funds),
+        DirectSdkMsg::Other
+        | DirectSdkMsg::MsgTransfer { .. }
+        | DirectSdkMsg::MsgGroupExec { .. } => false,
         DirectSdkMsg::MsgRecvPacket {
             packet:
                 Packet {
@@ -27,7 +108,7 @@ pub fn verify_sent_funds(msg: &DirectSdkMsg, sent_funds_msg: &[Coin]) -> bool {
             if destination_port == "transfer" {
                 // Packet was routed here through ibc-hooks
                 verify_sent_funds_ibc_wasm_hooks_incoming_transfer(
-                    sent_funds_msg,
+                    funds,
                     data,
                     source_port,
                     source_channel,
@@ -35,21 +116,25 @@ pub fn verify_sent_funds(msg: &DirectSdkMsg, sent_funds_msg: &[Coin]) -> bool {
                     destination_channel,
                 )
             } else {
-                // Packet is for an IBC enabled contract
+                // Packet is for an IBC enabled contract, or carries NFTs (see
+                // `verify_ibc_wasm_hooks_incoming_nft_transfer`)
                 // No funds should be sent
-                sent_funds_msg.is_empty()
+                funds.0.is_empty()
             }
         }
         DirectSdkMsg::MsgAcknowledgement { .. }
         | DirectSdkMsg::MsgTimeout { .. }
+        | DirectSdkMsg::MsgChannelUpgrade { .. }
         | DirectSdkMsg::MsgMigrateContract { .. }
         | DirectSdkMsg::MsgUpdateAdmin { .. }
-        | DirectSdkMsg::MsgClearAdmin { .. } => sent_funds_msg.is_empty(),
+        | DirectSdkMsg::MsgClearAdmin { .. } => funds.0.is_empty(),
     }
 }
 
+/// Verifies the funds of a contract called via ibc-hooks against the transfer in the packet, and
+/// attaches the trace of their denom
 fn verify_sent_funds_ibc_wasm_hooks_incoming_transfer(
-    sent_funds_msg: &[Coin],
+    funds: &mut VerifiedFunds,
     data: &Vec<u8>,
     source_port: &str,
     source_channel: &str,
@@ -57,15 +142,17 @@ fn verify_sent_funds_ibc_wasm_hooks_incoming_transfer(
     destination_channel: &str,
 ) -> bool {
     // Should be just one coin
-    if sent_funds_msg.len() != 1 {
-        trace!(
-            "Contract was called via ibc-hooks but sent_funds_msg.len() != 1: {:?}",
-            sent_funds_msg,
-        );
-        return false;
-    }
-
-    let sent_funds_msg_coin = &sent_funds_msg[0];
+    let sent_funds = match funds.0.as_mut_slice() {
+        [sent_funds] => sent_funds,
+        sent_funds_msg => {
+            trace!(
+                "Contract was called via ibc-hooks but sent_funds_msg.len() != 1: {:?}",
+                sent_funds_msg,
+            );
+            return false;
+        }
+    };
+    let sent_funds_msg_coin = &sent_funds.coin;
 
     // Parse data as FungibleTokenPacketData JSON
     let packet_data: FungibleTokenPacketData = match serde_json::from_slice(data.as_slice()) {
@@ -92,37 +179,20 @@ fn verify_sent_funds_ibc_wasm_hooks_incoming_transfer(
 
     // The packet's denom is the denom in the sender chain.
     // It needs to be converted to the local denom.
-    // Logic source: https://github.com/scrtlabs/SecretNetwork/blob/96b0ba7d6/x/ibc-hooks/wasm_hook.go#L483-L513
-    let denom: String = if receiver_chain_is_source(source_port, source_channel, &packet_data.denom)
-    {
-        // remove prefix added by sender chain
-        let voucher_prefix = get_denom_prefix(source_port, source_channel);
-
-        let unprefixed_denom: String = match packet_data.denom.strip_prefix(&voucher_prefix) {
-            Some(unprefixed_denom) => unprefixed_denom.to_string(),
-            None => {
-                trace!(
-                    "Contract was called via ibc-hooks but packet_data.denom doesn't start with voucher_prefix: {:?} != {:?}",
-                    packet_data.denom,
-                    voucher_prefix,
-                );
-                return false;
-            }
-        };
-
-        // The denomination used to send the coins is either the native denom or the hash of the path
-        // if the denomination is not native.
-        let denom_trace = parse_denom_trace(&unprefixed_denom);
-        if !denom_trace.path.is_empty() {
-            denom_trace.ibc_denom()
-        } else {
-            unprefixed_denom
+    let denom_trace = match received_denom_trace(
+        source_port,
+        source_channel,
+        destination_port,
+        destination_channel,
+        &packet_data.denom,
+    ) {
+        Some(denom_trace) => denom_trace,
+        None => {
+            trace!("Contract was called via ibc-hooks but packet_data.denom has an invalid prefix");
+            return false;
         }
-    } else {
-        let prefixed_denom =
-            get_denom_prefix(destination_port, destination_channel) + &packet_data.denom;
-        parse_denom_trace(&prefixed_denom).ibc_denom()
     };
+    let denom = denom_trace.ibc_denom();
 
     // Check denom
     if sent_funds_msg_coin.denom.to_lowercase() != denom.to_lowercase() {
@@ -134,5 +204,152 @@ fn verify_sent_funds_ibc_wasm_hooks_incoming_transfer(
         return false;
     }
 
+    // Tokens that return to the chain they were minted in have a native denom, without a trace
+    if let DenomKind::Ibc { trace } = &mut sent_funds.kind {
+        *trace = Some(DenomTrace {
+            denom: sent_funds.coin.denom.clone(),
+            path: denom_trace.path,
+            base_denom: denom_trace.base_denom,
+        });
+    }
+
     true
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cw_types_v010::encoding::Binary;
+    use cw_types_v010::math::Uint256;
+    use enclave_cosmos_types::feature_activation::set_active_block;
+
+    const ATOM_OVER_CHANNEL_1: &str =
+        "ibc/C4CFF46FD6DE35CA4CF4CE031E643C8FDC9BA4B99AE598E9B0ED98FE3A2319F9";
+
+    fn coin(denom: &str) -> Coin {
+        Coin {
+            denom: denom.to_string(),
+            amount: Uint256::from(100u128),
+        }
+    }
+
+    pub fn test_verify_funds_denoms() {
+        let funds = verify_funds_denoms(&[
+            coin("uscrt"),
+            coin(ATOM_OVER_CHANNEL_1),
+            coin("factory/secret1y277c499f44nxe7geeaqw8t6gpge68rcpla9lf/ushare"),
+        ])
+        .unwrap();
+        let kinds: Vec<_> = funds
+            .0
+            .iter()
+            .map(|verified| verified.kind.clone())
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DenomKind::Native,
+                DenomKind::Ibc { trace: None },
+                DenomKind::Factory {
+                    creator: HumanAddr("secret1y277c499f44nxe7geeaqw8t6gpge68rcpla9lf".to_string())
+                },
+            ]
+        );
+        assert_eq!(funds.denom_traces(), None);
+
+        for denom in &[
+            "",
+            "u",
+            "1uscrt",
+            "uscrt!",
+            "ibc/C4CFF46F",
+            "ibc/Z4CFF46FD6DE35CA4CF4CE031E643C8FDC9BA4B99AE598E9B0ED98FE3A2319F9",
+            "factory/secret1y277c499f44nxe7geeaqw8t6gpge68rcpla9lf",
+            "factory/secret1invalid/ushare",
+        ] {
+            assert!(
+                verify_funds_denoms(&[coin(denom)]).is_err(),
+                "{:?} is valid",
+                denom
+            );
+        }
+
+        // Before the feature activates any denom is accepted, as a native one
+        set_active_block("secret-4", 1);
+        let funds = verify_funds_denoms(&[coin("ibc/C4CFF46F"), coin(ATOM_OVER_CHANNEL_1)]);
+        set_active_block("secretdev-1", 1);
+        let kinds: Vec<_> = funds
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|verified| verified.kind)
+            .collect();
+        assert_eq!(kinds, vec![DenomKind::Native, DenomKind::Native]);
+    }
+
+    pub fn test_attach_received_denom_trace() {
+        let data = serde_json::json!({
+            "denom": "uatom",
+            "amount": "100",
+            "sender": "cosmos1sender",
+            "receiver": "secret1contract",
+            "memo": r#"{"wasm":{"contract":"secret1contract","msg":{}}}"#,
+        });
+        let msg = DirectSdkMsg::MsgRecvPacket {
+            packet: Packet {
+                sequence: 1,
+                source_port: "transfer".to_string(),
+                source_channel: "channel-0".to_string(),
+                destination_port: "transfer".to_string(),
+                destination_channel: "channel-1".to_string(),
+                data: serde_json::to_vec(&data).unwrap(),
+            },
+            proof_commitment: vec![],
+            proof_height: None,
+            signer: "secret1relayer".to_string(),
+        };
+
+        let mut funds = verify_funds_denoms(&[coin(ATOM_OVER_CHANNEL_1)]).unwrap();
+        assert!(verify_sent_funds(&msg, &mut funds));
+        assert_eq!(
+            funds.denom_traces(),
+            Some(vec![DenomTrace {
+                denom: ATOM_OVER_CHANNEL_1.to_string(),
+                path: "transfer/channel-1".to_string(),
+                base_denom: "uatom".to_string(),
+            }])
+        );
+
+        // Funds that don't match the packet don't get a trace
+        let mut other_funds = verify_funds_denoms(&[coin("uscrt")]).unwrap();
+        assert!(!verify_sent_funds(&msg, &mut other_funds));
+        assert_eq!(other_funds.denom_traces(), None);
+    }
+
+    pub fn test_verify_wide_sent_funds() {
+        // 2^128, which doesn't fit in a u128 but is a valid amount in the SDK
+        let above_u128 = "340282366920938463463374607431768211456";
+        let sent_funds: Vec<Coin> = serde_json::from_str(&format!(
+            r#"[{{"denom":"aevmos","amount":"{}"}}]"#,
+            above_u128
+        ))
+        .unwrap();
+        assert_eq!(sent_funds[0].amount.to_string(), above_u128);
+
+        let msg = |amount: &str| DirectSdkMsg::MsgExecuteContract {
+            sender: CanonicalAddr(Binary(vec![1; 20])),
+            contract: HumanAddr("secret1contract".to_string()),
+            msg: vec![],
+            sent_funds: vec![Coin {
+                denom: "aevmos".to_string(),
+                amount: amount.parse().unwrap(),
+            }],
+        };
+
+        let mut funds = verify_funds_denoms(&sent_funds).unwrap();
+        assert!(verify_sent_funds(&msg(above_u128), &mut funds));
+        // Amounts that only differ above 128 bits
+        assert!(!verify_sent_funds(&msg("0"), &mut funds));
+    }
+}
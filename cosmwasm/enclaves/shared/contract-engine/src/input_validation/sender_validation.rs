@@ -6,7 +6,8 @@ pub fn verify_sender(sdk_msg: &DirectSdkMsg, sent_sender: &CanonicalAddr) -> Opt
     match sdk_msg {
         DirectSdkMsg::MsgRecvPacket { .. }
         | DirectSdkMsg::MsgAcknowledgement { .. }
-        | DirectSdkMsg::MsgTimeout { .. } => {
+        | DirectSdkMsg::MsgTimeout { .. }
+        | DirectSdkMsg::MsgChannelUpgrade { .. } => {
             // No sender to verify.
             // Going to pass null sender to the contract if all other checks pass.
         }
@@ -15,6 +16,8 @@ pub fn verify_sender(sdk_msg: &DirectSdkMsg, sent_sender: &CanonicalAddr) -> Opt
         | DirectSdkMsg::MsgMigrateContract { .. }
         | DirectSdkMsg::MsgUpdateAdmin { .. }
         | DirectSdkMsg::MsgClearAdmin { .. }
+        | DirectSdkMsg::MsgTransfer { .. }
+        | DirectSdkMsg::MsgGroupExec { .. }
         | DirectSdkMsg::Other => {
             if sdk_msg.sender() != Some(sent_sender) {
                 trace!(
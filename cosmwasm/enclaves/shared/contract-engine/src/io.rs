@@ -1,15 +1,29 @@
+use crate::auditor::encrypt_for_auditor;
+use crate::capability_summary::CapabilitySummary;
 use crate::contract_validation::ReplyParams;
+use crate::cosmwasm_config::{
+    auditing, constant_shape, designated_viewer, output_normalization, output_padding,
+    plaintext_attribute_denylist,
+};
+use crate::execution_trace::trace_span;
+use crate::result_encoding::encode_wasm_output;
+use crate::viewer::encrypt_for_viewer;
 use core::fmt;
 
 /// This contains all the user-facing functions. In these functions we will be using
 /// the consensus_io_exchange_keypair and a user-generated key to create a symmetric key
 /// that is unique to the user and the enclave
 ///
-use super::types::{IoNonce, SecretMessage};
+use super::types::{IoKey, IoNonce, SecretMessage};
+use cw_types_generic::{ContractFeature, ShapedEntrypoint};
 use cw_types_v010::encoding::Binary;
 use cw_types_v010::types::{CanonicalAddr, Coin, LogAttribute};
-use cw_types_v1::results::{Event, Reply, ReplyOn, SubMsg, SubMsgResponse, SubMsgResult};
+use cw_types_v1::results::{
+    Event, Reply, ReplyOn, SubMsg, SubMsgResponse, SubMsgResult, TypedError,
+};
 
+use enclave_cosmos_types::callback_sig::callback_signature;
+use enclave_cosmos_types::feature_activation::{active_block, is_active, Feature};
 use enclave_ffi_types::EnclaveError;
 
 use enclave_crypto::{AESKey, Ed25519PublicKey, Kdf, SIVEncryptable, KEY_MANAGER};
@@ -18,7 +32,6 @@ use log::*;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::Value;
-use sha2::Digest;
 
 /// The internal_reply_enclave_sig is being passed with the reply (Only if the reply is wasm reply)
 /// This is used by the receiver of the reply to:
@@ -85,6 +98,10 @@ pub struct IBCOutput {
     pub ok: Option<cw_types_v1::ibc::IbcBasicResponse>,
     #[serde(rename = "Err")]
     pub err: Option<Value>,
+    /// The `data` of plaintext outputs, which `IbcBasicResponse` has no field for.
+    /// It's returned to the caller's reply, so IBC-enabled contracts can chain results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Binary>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -131,11 +148,183 @@ pub fn calc_encryption_key(nonce: &IoNonce, user_public_key: &Ed25519PublicKey)
     AESKey::new_from_slice(&tx_encryption_ikm).derive_key_from_this(nonce)
 }
 
+/// The sizes of the padding buckets, which governance can change with the `output_padding` param
+/// of the compute module (see `compute_params`). Sizes missing from the param keep the defaults
+/// of `cosmwasm_config::output_padding`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct OutputPaddingParams {
+    pub bucket_sizes: Vec<usize>,
+    pub large_output_multiple: usize,
+    pub coarse_bucket_sizes: Vec<usize>,
+    pub coarse_large_output_multiple: usize,
+}
+
+impl Default for OutputPaddingParams {
+    fn default() -> Self {
+        Self {
+            bucket_sizes: output_padding::BUCKET_SIZES.to_vec(),
+            large_output_multiple: output_padding::LARGE_OUTPUT_MULTIPLE,
+            coarse_bucket_sizes: output_padding::COARSE_BUCKET_SIZES.to_vec(),
+            coarse_large_output_multiple: output_padding::COARSE_LARGE_OUTPUT_MULTIPLE,
+        }
+    }
+}
+
+impl OutputPaddingParams {
+    /// Buckets must be ascending, and no bucket or multiple can be empty or larger than
+    /// `output_padding::MAX_BUCKET_SIZE`
+    pub fn validate(&self) -> Result<(), EnclaveError> {
+        let buckets = [
+            (&self.bucket_sizes, self.large_output_multiple),
+            (&self.coarse_bucket_sizes, self.coarse_large_output_multiple),
+        ];
+        for (sizes, multiple) in buckets.iter() {
+            let ascending = sizes.windows(2).all(|pair| pair[0] < pair[1]);
+            let in_bounds = std::iter::once(multiple)
+                .chain(sizes.iter())
+                .all(|size| *size > 0 && *size <= output_padding::MAX_BUCKET_SIZE);
+            if sizes.is_empty() || !ascending || !in_bounds {
+                warn!(
+                    "invalid padding buckets {:?} with a multiple of {}",
+                    sizes, multiple
+                );
+                return Err(EnclaveError::ValidationFailure);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The attribute keys that contracts may not emit in plaintext, which governance can replace with
+/// the `plaintext_attribute_denylist` param of the compute module (see `compute_params`). Chains
+/// that don't set the param use `cosmwasm_config::plaintext_attribute_denylist`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PlaintextAttributeDenylist {
+    pub denied_keys: Vec<String>,
+}
+
+impl Default for PlaintextAttributeDenylist {
+    fn default() -> Self {
+        Self {
+            denied_keys: plaintext_attribute_denylist::DENIED_KEYS
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl PlaintextAttributeDenylist {
+    /// Keys can't be empty, as no contract could emit an attribute that matches them
+    pub fn validate(&self) -> Result<(), EnclaveError> {
+        if self.denied_keys.iter().any(|key| key.is_empty()) {
+            warn!(
+                "invalid plaintext attribute denylist {:?}",
+                self.denied_keys
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        Ok(())
+    }
+
+    pub fn is_denied(&self, key: &str) -> bool {
+        self.denied_keys
+            .iter()
+            .any(|denied_key| denied_key.eq_ignore_ascii_case(key))
+    }
+}
+
+/// The buckets the encrypted result fields of a contract are padded to, which the contract picks
+/// with its features (see `cosmwasm_config::output_padding`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaddingBuckets {
+    sizes: Vec<usize>,
+    large_output_multiple: usize,
+    /// Pads to `constant_shape::OUTPUT_SIZE`, and gives the output a constant shape
+    constant_shape: bool,
+}
+
+impl PaddingBuckets {
+    pub fn for_features(features: &[ContractFeature], params: &OutputPaddingParams) -> Self {
+        if !is_active(Feature::OutputPadding) {
+            return PaddingBuckets::unpadded();
+        }
+
+        let (sizes, large_output_multiple) = if features.contains(&ContractFeature::CoarsePadding) {
+            (
+                &params.coarse_bucket_sizes,
+                params.coarse_large_output_multiple,
+            )
+        } else {
+            (&params.bucket_sizes, params.large_output_multiple)
+        };
+
+        PaddingBuckets {
+            sizes: sizes.clone(),
+            large_output_multiple,
+            constant_shape: false,
+        }
+    }
+
+    /// Like `for_features`, for the output of an entry point that can have a constant shape
+    pub fn for_entrypoint(
+        features: &[ContractFeature],
+        entrypoint: ShapedEntrypoint,
+        params: &OutputPaddingParams,
+    ) -> Self {
+        if features.contains(&ContractFeature::ConstantShape(entrypoint)) {
+            PaddingBuckets {
+                sizes: vec![constant_shape::OUTPUT_SIZE],
+                large_output_multiple: constant_shape::OUTPUT_SIZE,
+                constant_shape: true,
+            }
+        } else {
+            PaddingBuckets::for_features(features, params)
+        }
+    }
+
+    /// Leaves outputs as long as they are
+    fn unpadded() -> Self {
+        PaddingBuckets {
+            sizes: vec![],
+            large_output_multiple: 1,
+            constant_shape: false,
+        }
+    }
+
+    pub fn is_constant_shape(&self) -> bool {
+        self.constant_shape
+    }
+
+    /// The padding of the copies of an output with a constant shape for its auditor and viewer
+    fn for_shaped_copies() -> Self {
+        PaddingBuckets {
+            sizes: vec![constant_shape::COPY_SIZE],
+            large_output_multiple: constant_shape::COPY_SIZE,
+            constant_shape: true,
+        }
+    }
+
+    /// Returns the length that a plaintext of `len` bytes should be padded to
+    pub fn padded_len(&self, len: usize) -> usize {
+        let multiple = self.large_output_multiple;
+        match self.sizes.iter().find(|bucket| **bucket >= len) {
+            Some(bucket) => *bucket,
+            None => len.saturating_add(multiple - 1) / multiple * multiple,
+        }
+    }
+}
+
+/// Serializes and encrypts a value, padding the serialized value to the next bucket size
+/// before encrypting it.
+/// The amount of padding bytes added is accumulated into `padded_bytes` so it can be charged.
 fn encrypt_serializable<T>(
-    key: &AESKey,
+    key: &IoKey,
     val: &T,
     reply_params: &Option<Vec<ReplyParams>>,
-    should_append_all_reply_params: bool,
+    padding: &PaddingBuckets,
+    padded_bytes: &mut u64,
 ) -> Result<String, EnclaveError>
 where
     T: ?Sized + Serialize,
@@ -146,15 +335,35 @@ where
     })?;
 
     let trimmed = serialized.trim_start_matches('"').trim_end_matches('"');
+    let padded = pad_to_bucket(trimmed, padding, padded_bytes);
+
+    encrypt_preserialized_string(key, &padded, reply_params, false)
+}
 
-    encrypt_preserialized_string(key, trimmed, reply_params, should_append_all_reply_params)
+/// Removes the padding added by `pad_to_bucket` from a decrypted result
+pub fn strip_output_padding(plaintext: &[u8]) -> &[u8] {
+    let end = plaintext
+        .iter()
+        .rposition(|b| *b != output_padding::PADDING_BYTE)
+        .map_or(0, |pos| pos + 1);
+    &plaintext[..end]
+}
+
+fn pad_to_bucket(val: &str, buckets: &PaddingBuckets, padded_bytes: &mut u64) -> String {
+    let padding = buckets.padded_len(val.len()) - val.len();
+    *padded_bytes = padded_bytes.saturating_add(padding as u64);
+
+    let mut padded = String::with_capacity(val.len() + padding);
+    padded.push_str(val);
+    padded.extend(std::iter::repeat(output_padding::PADDING_BYTE as char).take(padding));
+    padded
 }
 
 // use this to encrypt a String that has already been serialized.  When that is the case, if
 // encrypt_serializable is called instead, it will get double serialized, and any escaped
 // characters will be double escaped
 fn encrypt_preserialized_string(
-    key: &AESKey,
+    key: &IoKey,
     val: &str,
     reply_params: &Option<Vec<ReplyParams>>,
     should_append_all_reply_params: bool,
@@ -164,10 +373,14 @@ fn encrypt_preserialized_string(
             let mut ser = vec![];
             ser.extend_from_slice(&v[0].recipient_contract_hash);
             if should_append_all_reply_params {
+                append_reply_payload(&mut ser, &v[0].payload);
                 for item in v.iter().skip(1) {
-                    ser.extend_from_slice(cw_types_v1::results::REPLY_ENCRYPTION_MAGIC_BYTES);
-                    ser.extend_from_slice(&item.sub_msg_id.to_be_bytes());
-                    ser.extend_from_slice(item.recipient_contract_hash.as_slice());
+                    append_reply_header(
+                        &mut ser,
+                        item.sub_msg_id,
+                        &item.recipient_contract_hash,
+                        &item.payload,
+                    );
                 }
             }
             ser.extend_from_slice(val.as_bytes());
@@ -175,19 +388,41 @@ fn encrypt_preserialized_string(
         }
         None => val.as_bytes().to_vec(),
     };
-    let encrypted_data = key
-        .encrypt_siv(serialized.as_slice(), None)
-        .map_err(|err| {
-            debug!(
-                "got an error while trying to encrypt output error {:?}: {}",
-                err, err
-            );
-            EnclaveError::EncryptionError
-        })?;
+    let encrypted_data = key.encrypt(serialized.as_slice(), None).map_err(|err| {
+        debug!(
+            "got an error while trying to encrypt output error {:?}: {}",
+            err, err
+        );
+        EnclaveError::EncryptionError
+    })?;
 
     Ok(b64_encode(encrypted_data.as_slice()))
 }
 
+/// Appends a reply header, see `attach_reply_headers_to_v1_wasm_msg`
+fn append_reply_header(
+    serialized: &mut Vec<u8>,
+    sub_msg_id: u64,
+    recipient_contract_hash: &[u8],
+    payload: &[u8],
+) {
+    serialized.extend_from_slice(cw_types_v1::results::REPLY_ENCRYPTION_MAGIC_BYTES);
+    serialized.extend_from_slice(&sub_msg_id.to_be_bytes());
+    serialized.extend_from_slice(recipient_contract_hash);
+    append_reply_payload(serialized, payload);
+}
+
+/// Appends the payload of a submessage after its reply header, if it has one
+fn append_reply_payload(serialized: &mut Vec<u8>, payload: &[u8]) {
+    if payload.is_empty() {
+        return;
+    }
+
+    serialized.extend_from_slice(cw_types_v1::results::REPLY_PAYLOAD_MAGIC_BYTES);
+    serialized.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    serialized.extend_from_slice(payload);
+}
+
 fn b64_encode(data: &[u8]) -> String {
     base64::encode(data)
 }
@@ -202,8 +437,47 @@ pub fn post_process_output(
     sender_addr: &CanonicalAddr,
     is_query_output: bool,
     is_ibc_output: bool,
+    auditor_key: Option<&Ed25519PublicKey>,
+    viewer_key: Option<&Ed25519PublicKey>,
+    capability_summary: Option<CapabilitySummary>,
+    normalization_version: u8,
+    padding: &PaddingBuckets,
+    padded_bytes: &mut u64,
 ) -> Result<Vec<u8>, EnclaveError> {
+    let _span = trace_span("post_process_output");
+    // Outputs with a constant shape always carry both copies, so that they don't reveal whether
+    // the contract set the keys
+    let (auditor_key, viewer_key, copy_padding) = if padding.is_constant_shape() {
+        (
+            Some(auditor_key.unwrap_or(&secret_msg.user_public_key)),
+            Some(viewer_key.unwrap_or(&secret_msg.user_public_key)),
+            PaddingBuckets::for_shaped_copies(),
+        )
+    } else {
+        (auditor_key, viewer_key, padding.clone())
+    };
+    let plaintext_output = if auditor_key.is_some() || viewer_key.is_some() {
+        Some(output.clone())
+    } else {
+        None
+    };
+
     let mut raw_output = deserialize_output(output)?;
+    normalize_output(&mut raw_output, normalization_version);
+    if let Some(capability_summary) = capability_summary {
+        attach_capability_summary(&mut raw_output, capability_summary)?;
+    }
+    if padding.is_constant_shape() {
+        shape_output(&mut raw_output, padded_bytes)?;
+        let copy_len = plaintext_output.as_ref().map_or(0, |copy| copy.len());
+        if output_attributes(&mut raw_output).is_some() && copy_len > constant_shape::COPY_SIZE {
+            warn!(
+                "output of an entry point with a constant shape is longer than {} bytes",
+                constant_shape::COPY_SIZE
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    }
     raw_output = attach_reply_headers_to_submsgs(raw_output, contract_hash, &reply_params)?;
     raw_output = encrypt_output(
         raw_output,
@@ -211,7 +485,29 @@ pub fn post_process_output(
         contract_addr,
         &reply_params,
         is_ibc_output,
+        padding,
+        padded_bytes,
     )?;
+    if let (Some(auditor_key), Some(plaintext_output)) = (auditor_key, &plaintext_output) {
+        attach_audit_output(
+            &mut raw_output,
+            plaintext_output,
+            &secret_msg.nonce,
+            auditor_key,
+            &copy_padding,
+            padded_bytes,
+        )?;
+    }
+    if let (Some(viewer_key), Some(plaintext_output)) = (viewer_key, &plaintext_output) {
+        attach_viewer_output(
+            &mut raw_output,
+            plaintext_output,
+            &secret_msg.nonce,
+            viewer_key,
+            &copy_padding,
+            padded_bytes,
+        )?;
+    }
     raw_output = create_callback_sig_for_submsgs(raw_output, contract_addr)?;
     raw_output = adapt_output_for_reply(raw_output, &reply_params, secret_msg, sender_addr)?;
 
@@ -219,6 +515,176 @@ pub fn post_process_output(
     Ok(output)
 }
 
+/// Gives a successful output the shape of `constant_shape`, before its fields are encrypted and
+/// the copies for the auditor and the viewer are attached. Errors and query results only need
+/// their padding.
+fn shape_output(
+    raw_output: &mut RawWasmOutput,
+    padded_bytes: &mut u64,
+) -> Result<(), EnclaveError> {
+    let (attributes, data, has_messages) = match raw_output {
+        RawWasmOutput::OkV010 { ok, .. } => (&mut ok.log, &mut ok.data, !ok.messages.is_empty()),
+        RawWasmOutput::OkV1 { ok, .. } => {
+            if !ok.events.is_empty() {
+                warn!("contract tried to emit custom events from an entry point with a constant shape");
+                return Err(EnclaveError::ValidationFailure);
+            }
+            (&mut ok.attributes, &mut ok.data, !ok.messages.is_empty())
+        }
+        _ => return Ok(()),
+    };
+    if has_messages {
+        warn!("contract tried to send messages from an entry point with a constant shape");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    shape_attributes(attributes, padded_bytes)?;
+
+    let data = data.get_or_insert_with(|| Binary(vec![]));
+    if b64_encode(data.as_slice()).len() > constant_shape::OUTPUT_SIZE {
+        warn!(
+            "data of an entry point with a constant shape is longer than {} bytes",
+            constant_shape::OUTPUT_SIZE
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(())
+}
+
+/// Pads the attributes to their fixed sizes and adds empty attributes up to the fixed count.
+/// Plaintext attributes would reveal what the contract did, so they aren't allowed.
+fn shape_attributes(
+    attributes: &mut Vec<LogAttribute>,
+    padded_bytes: &mut u64,
+) -> Result<(), EnclaveError> {
+    if attributes.len() > constant_shape::CONTRACT_ATTRIBUTE_COUNT {
+        warn!(
+            "entry point with a constant shape emitted more than {} attributes",
+            constant_shape::CONTRACT_ATTRIBUTE_COUNT
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+    attributes.resize(
+        constant_shape::CONTRACT_ATTRIBUTE_COUNT,
+        LogAttribute {
+            key: String::new(),
+            value: String::new(),
+            encrypted: true,
+        },
+    );
+
+    for attr in attributes.iter_mut() {
+        if !attr.encrypted {
+            warn!(
+                "contract tried to emit the plaintext attribute {:?} from an entry point with a constant shape",
+                attr.key
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+        attr.key = pad_to_size(&attr.key, constant_shape::ATTRIBUTE_KEY_SIZE, padded_bytes)?;
+        attr.value = pad_to_size(
+            &attr.value,
+            constant_shape::ATTRIBUTE_VALUE_SIZE,
+            padded_bytes,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn pad_to_size(val: &str, size: usize, padded_bytes: &mut u64) -> Result<String, EnclaveError> {
+    if val.len() > size {
+        warn!(
+            "attribute of an entry point with a constant shape is longer than {} bytes",
+            size
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    *padded_bytes = padded_bytes.saturating_add((size - val.len()) as u64);
+    let mut padded = String::with_capacity(size);
+    padded.push_str(val);
+    padded.extend(std::iter::repeat(output_padding::PADDING_BYTE as char).take(size - val.len()));
+    Ok(padded)
+}
+
+/// Adds a copy of the plaintext output, encrypted for the auditor of the contract, as a plaintext
+/// attribute so that it's emitted with the other attributes of the contract
+fn attach_audit_output(
+    raw_output: &mut RawWasmOutput,
+    plaintext_output: &[u8],
+    nonce: &IoNonce,
+    auditor_key: &Ed25519PublicKey,
+    padding: &PaddingBuckets,
+    padded_bytes: &mut u64,
+) -> Result<(), EnclaveError> {
+    let attributes = match output_attributes(raw_output) {
+        Some(attributes) => attributes,
+        None => return Ok(()),
+    };
+
+    attributes.push(LogAttribute {
+        key: auditing::AUDIT_OUTPUT_ATTRIBUTE.to_string(),
+        value: encrypt_for_auditor(plaintext_output, nonce, auditor_key, padding, padded_bytes)?,
+        encrypted: false,
+    });
+
+    Ok(())
+}
+
+/// Adds a copy of the plaintext output, encrypted for the viewer the contract designated, as a
+/// plaintext attribute so that it's emitted with the other attributes of the contract
+fn attach_viewer_output(
+    raw_output: &mut RawWasmOutput,
+    plaintext_output: &[u8],
+    nonce: &IoNonce,
+    viewer_key: &Ed25519PublicKey,
+    padding: &PaddingBuckets,
+    padded_bytes: &mut u64,
+) -> Result<(), EnclaveError> {
+    let attributes = match output_attributes(raw_output) {
+        Some(attributes) => attributes,
+        None => return Ok(()),
+    };
+
+    attributes.push(LogAttribute {
+        key: designated_viewer::VIEWER_OUTPUT_ATTRIBUTE.to_string(),
+        value: encrypt_for_viewer(plaintext_output, nonce, viewer_key, padding, padded_bytes)?,
+        encrypted: false,
+    });
+
+    Ok(())
+}
+
+/// Adds the summary of the capabilities the contract exercised as an encrypted attribute, which
+/// takes one of the attributes of outputs with a constant shape
+fn attach_capability_summary(
+    raw_output: &mut RawWasmOutput,
+    mut capability_summary: CapabilitySummary,
+) -> Result<(), EnclaveError> {
+    capability_summary.count_output(raw_output);
+    let attribute = capability_summary.to_attribute()?;
+
+    if let Some(attributes) = output_attributes(raw_output) {
+        attributes.push(attribute);
+    }
+    Ok(())
+}
+
+fn output_attributes(raw_output: &mut RawWasmOutput) -> Option<&mut Vec<LogAttribute>> {
+    match raw_output {
+        RawWasmOutput::OkV010 { ok, .. } => Some(&mut ok.log),
+        RawWasmOutput::OkV1 { ok, .. } => Some(&mut ok.attributes),
+        RawWasmOutput::OkIBCPacketReceive { ok } => Some(&mut ok.attributes),
+        // Errors, queries and channel handshakes don't emit attributes
+        RawWasmOutput::Err { .. }
+        | RawWasmOutput::QueryOkV010 { .. }
+        | RawWasmOutput::QueryOkV1 { .. }
+        | RawWasmOutput::OkIBCOpenChannel { .. } => None,
+    }
+}
+
 /// Converts a RawWasmOutput to the final interface that the go code expects, WasmOutput
 /// (serialized to bytes)
 ///
@@ -293,6 +759,13 @@ pub fn finalize_raw_output(
                         ok.attributes,
                         ok.events,
                     )),
+                    // The data of encrypted outputs reaches the caller through
+                    // `internal_reply_enclave_sig`
+                    data: if is_msg_encrypted || !is_active(Feature::PlaintextReplyData) {
+                        None
+                    } else {
+                        ok.data
+                    },
                 });
             } else {
                 wasm_output.v1 = Some(V1WasmOutput {
@@ -326,20 +799,15 @@ pub fn finalize_raw_output(
 
     trace!("WasmOutput: {:?}", wasm_output);
 
-    let serialized_output = serde_json::to_vec(&wasm_output).map_err(|err| {
-        debug!(
-            "got an error while trying to serialize wasm_output into json bytes {:?}: {}",
-            wasm_output, err
-        );
-        EnclaveError::FailedToSerialize
-    })?;
-
-    Ok(serialized_output)
+    encode_wasm_output(&wasm_output)
 }
 
 pub fn manipulate_callback_sig_for_plaintext(
     contract_addr: &CanonicalAddr,
     output: Vec<u8>,
+    reply_params: &Option<Vec<ReplyParams>>,
+    sender_addr: &CanonicalAddr,
+    normalization_version: u8,
 ) -> Result<RawWasmOutput, EnclaveError> {
     let mut raw_output: RawWasmOutput = serde_json::from_slice(&output).map_err(|err| {
         warn!("got an error while trying to deserialize output bytes into json");
@@ -347,6 +815,8 @@ pub fn manipulate_callback_sig_for_plaintext(
         EnclaveError::FailedToDeserialize
     })?;
 
+    normalize_output(&mut raw_output, normalization_version);
+
     match &mut raw_output {
         RawWasmOutput::OkV1 { ok, .. } => {
             for sub_msg in &mut ok.messages {
@@ -371,7 +841,7 @@ pub fn manipulate_callback_sig_for_plaintext(
                                     .iter()
                                     .map(|coin| cw_types_v010::types::Coin {
                                         denom: coin.denom.clone(),
-                                        amount: cw_types_v010::math::Uint128(coin.amount.u128()),
+                                        amount: coin.amount,
                                     })
                                     .collect::<Vec<cw_types_v010::types::Coin>>()[..],
                             ));
@@ -417,7 +887,7 @@ pub fn manipulate_callback_sig_for_plaintext(
                                     .iter()
                                     .map(|coin| Coin {
                                         denom: coin.denom.clone(),
-                                        amount: cw_types_v010::math::Uint128(coin.amount.u128()),
+                                        amount: coin.amount,
                                     })
                                     .collect::<Vec<Coin>>()[..],
                             ));
@@ -443,33 +913,192 @@ pub fn manipulate_callback_sig_for_plaintext(
         _ => {}
     }
 
+    adapt_plaintext_output_for_reply(&mut raw_output, reply_params, sender_addr)?;
+
     Ok(raw_output)
 }
 
-pub fn set_attributes_to_plaintext(attributes: &mut Vec<LogAttribute>) {
+/// The plaintext counterpart of `adapt_output_for_reply`: if the contract was called as a
+/// submessage, signs a plaintext `Reply` that carries the `data` of the output, so the caller's
+/// `reply` gets the data even though the output isn't encrypted.
+fn adapt_plaintext_output_for_reply(
+    output: &mut RawWasmOutput,
+    reply_params: &Option<Vec<ReplyParams>>,
+    sender_addr: &CanonicalAddr,
+) -> Result<(), EnclaveError> {
+    let sub_msg_id = match reply_params.as_ref().and_then(|params| params.first()) {
+        Some(params) if is_active(Feature::PlaintextReplyData) => params.sub_msg_id,
+        _ => return Ok(()),
+    };
+
+    if let RawWasmOutput::OkV1 {
+        ok,
+        internal_msg_id,
+        internal_reply_enclave_sig,
+    } = output
+    {
+        // Plaintext replies carry their id as a plain number, see `parse_plaintext_reply_message`
+        let id = Binary(sub_msg_id.to_string().into_bytes());
+        let reply = Reply {
+            id: id.clone(),
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: ok.data.clone(),
+                normalized_data: None,
+            }),
+            was_orig_msg_encrypted: false,
+            is_encrypted: false,
+            payload: Binary::default(),
+        };
+
+        let reply_json = serde_json::to_vec(&reply).map_err(|err| {
+            warn!(
+                "got an error while trying to serialize plaintext reply into bytes {:?}: {}",
+                reply, err
+            );
+            EnclaveError::FailedToSerialize
+        })?;
+
+        *internal_msg_id = Some(id);
+        *internal_reply_enclave_sig = Some(Binary::from(
+            create_callback_signature(sender_addr, &reply_json, &[]).as_slice(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn set_attributes_to_plaintext(
+    attributes: &mut Vec<LogAttribute>,
+    denylist: &PlaintextAttributeDenylist,
+) -> Result<(), EnclaveError> {
     for attr in attributes {
+        if is_active(Feature::SensitiveAttributeKeys) && denylist.is_denied(&attr.key) {
+            warn!(
+                "contract tried to emit the sensitive attribute {:?} in plaintext",
+                attr.key
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
         attr.encrypted = false;
     }
+
+    Ok(())
 }
 
-pub fn set_all_logs_to_plaintext(raw_output: &mut RawWasmOutput) {
+pub fn set_all_logs_to_plaintext(
+    raw_output: &mut RawWasmOutput,
+    denylist: &PlaintextAttributeDenylist,
+) -> Result<(), EnclaveError> {
     match raw_output {
         RawWasmOutput::OkV1 { ok, .. } => {
-            set_attributes_to_plaintext(&mut ok.attributes);
+            set_attributes_to_plaintext(&mut ok.attributes, denylist)?;
             for ev in &mut ok.events {
-                set_attributes_to_plaintext(&mut ev.attributes);
+                set_attributes_to_plaintext(&mut ev.attributes, denylist)?;
             }
         }
         RawWasmOutput::OkIBCPacketReceive { ok } => {
-            set_attributes_to_plaintext(&mut ok.attributes);
+            set_attributes_to_plaintext(&mut ok.attributes, denylist)?;
             for ev in &mut ok.events {
-                set_attributes_to_plaintext(&mut ev.attributes);
+                set_attributes_to_plaintext(&mut ev.attributes, denylist)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Brings the parts of an output whose order has no meaning to a canonical order, so that
+/// contracts that build their outputs from unordered collections (e.g. HashMaps) produce the
+/// same output on all nodes. Messages keep their order, as it's the order they are executed in.
+/// Outputs are only normalized with a version that the contract declared, since reordering them
+/// changes the results of contracts that were deployed before normalization existed.
+pub fn normalize_output(raw_output: &mut RawWasmOutput, version: u8) {
+    if version == 0 {
+        return;
+    }
+
+    match raw_output {
+        RawWasmOutput::OkV010 { ok, .. } => {
+            normalize_attributes(&mut ok.log);
+            for msg in &mut ok.messages {
+                normalize_v010_msg_funds(msg);
+            }
+        }
+        RawWasmOutput::OkV1 { ok, .. } => {
+            normalize_attributes(&mut ok.attributes);
+            normalize_events(&mut ok.events);
+            for sub_msg in &mut ok.messages {
+                normalize_v1_msg_funds(&mut sub_msg.msg);
+            }
+        }
+        RawWasmOutput::OkIBCPacketReceive { ok } => {
+            normalize_attributes(&mut ok.attributes);
+            normalize_events(&mut ok.events);
+            for sub_msg in &mut ok.messages {
+                normalize_v1_msg_funds(&mut sub_msg.msg);
             }
         }
         _ => {}
     }
 }
 
+/// The highest version of the normalization that the contract declared and the enclave supports,
+/// or 0 if its outputs are left as they are
+pub fn output_normalization_version(features: &[ContractFeature]) -> u8 {
+    features
+        .iter()
+        .filter_map(|feature| match feature {
+            ContractFeature::NormalizedOutput(version) => Some(*version),
+            _ => None,
+        })
+        .filter(|version| *version <= output_normalization::VERSION)
+        .max()
+        .unwrap_or(0)
+}
+
+fn normalize_attributes(attributes: &mut [LogAttribute]) {
+    // Stable sorts keep the relative order of attributes with the same key
+    attributes.sort_by(|a, b| a.key.cmp(&b.key));
+}
+
+fn normalize_events(events: &mut [Event]) {
+    for event in events.iter_mut() {
+        normalize_attributes(&mut event.attributes);
+    }
+    events.sort_by(|a, b| a.ty.cmp(&b.ty));
+}
+
+fn normalize_v010_msg_funds(msg: &mut cw_types_v010::types::CosmosMsg) {
+    use cw_types_v010::types::{BankMsg, CosmosMsg, WasmMsg};
+
+    match msg {
+        CosmosMsg::Bank(BankMsg::Send { amount, .. }) => {
+            amount.sort_by(|a, b| a.denom.cmp(&b.denom))
+        }
+        CosmosMsg::Wasm(WasmMsg::Execute { send, .. })
+        | CosmosMsg::Wasm(WasmMsg::Instantiate { send, .. }) => {
+            send.sort_by(|a, b| a.denom.cmp(&b.denom))
+        }
+        _ => {}
+    }
+}
+
+fn normalize_v1_msg_funds(msg: &mut cw_types_v1::results::CosmosMsg) {
+    use cw_types_v1::results::{BankMsg, CosmosMsg, WasmMsg};
+
+    match msg {
+        CosmosMsg::Bank(BankMsg::Send { amount, .. })
+        | CosmosMsg::Bank(BankMsg::Burn { amount }) => amount.sort_by(|a, b| a.denom.cmp(&b.denom)),
+        CosmosMsg::Wasm(WasmMsg::Execute { funds, .. })
+        | CosmosMsg::Wasm(WasmMsg::Instantiate { funds, .. }) => {
+            funds.sort_by(|a, b| a.denom.cmp(&b.denom))
+        }
+        _ => {}
+    }
+}
+
 fn deserialize_output(output: Vec<u8>) -> Result<RawWasmOutput, EnclaveError> {
     trace!(
         "output as received from contract: {:?}",
@@ -499,17 +1128,21 @@ fn deserialize_output(output: Vec<u8>) -> Result<RawWasmOutput, EnclaveError> {
 /// * `contract_addr` - The address of the contract whose output we are processing.
 /// * `reply_params` - An optional vector describing the caller chain. Needed because the
 ///         immediate caller to this contract will be appended to every field.
+/// * `padding` - The buckets result fields are padded to.
+/// * `padded_bytes` - Out-parameter accumulating the amount of padding added to result fields.
 fn encrypt_output(
     mut output: RawWasmOutput,
     secret_msg: &SecretMessage,
     contract_addr: &CanonicalAddr,
     reply_params: &Option<Vec<ReplyParams>>,
     is_ibc_output: bool,
+    padding: &PaddingBuckets,
+    padded_bytes: &mut u64,
 ) -> Result<RawWasmOutput, EnclaveError> {
     // The output we receive from a contract could be a reply to a caller contract (via the "reply" endpoint).
     // Therefore if reply_recipient_contract_hash is "Some", we append it to any encrypted data besides submessages that are irrelevant for replies.
     // More info in: https://github.com/CosmWasm/cosmwasm/blob/v1.0.0/packages/std/src/results/submessages.rs#L192-L198
-    let encryption_key = calc_encryption_key(&secret_msg.nonce, &secret_msg.user_public_key);
+    let encryption_key = secret_msg.io_key();
     trace!(
         "message nonce and public key for encryption: {:?} {:?}",
         secret_msg.nonce,
@@ -518,22 +1151,21 @@ fn encrypt_output(
 
     match &mut output {
         RawWasmOutput::Err { err, .. } => {
-            let encrypted_err = encrypt_serializable(&encryption_key, err, reply_params, false)?;
+            if let Some(typed_error) = typed_error_for_encryption(err) {
+                *err = typed_error;
+            }
+            let encrypted_err =
+                encrypt_serializable(&encryption_key, err, reply_params, padding, padded_bytes)?;
             *err = format_generic_error_message(Value::String(encrypted_err));
         }
         RawWasmOutput::QueryOkV010 { ok } | RawWasmOutput::QueryOkV1 { ok } => {
-            *ok = encrypt_serializable(&encryption_key, ok, reply_params, false)?;
+            *ok = encrypt_serializable(&encryption_key, ok, reply_params, padding, padded_bytes)?;
         }
         RawWasmOutput::OkV010 { ok, .. } => {
             for msg in &mut ok.messages {
                 // Encrypt all Wasm messages (keeps Bank, Staking, etc.. as is)
                 if let cw_types_v010::types::CosmosMsg::Wasm(wasm_msg) = msg {
-                    encrypt_v010_wasm_msg(
-                        wasm_msg,
-                        secret_msg.nonce,
-                        secret_msg.user_public_key,
-                        contract_addr,
-                    )?;
+                    encrypt_v010_wasm_msg(wasm_msg, secret_msg, contract_addr)?;
                 }
             }
 
@@ -549,7 +1181,8 @@ fn encrypt_output(
                     &encryption_key,
                     data,
                     reply_params,
-                    false,
+                    padding,
+                    padded_bytes,
                 )?)?;
             }
         }
@@ -570,7 +1203,8 @@ fn encrypt_output(
                     &encryption_key,
                     data,
                     reply_params,
-                    false,
+                    padding,
+                    padded_bytes,
                 )?)?;
             }
         }
@@ -586,7 +1220,8 @@ fn encrypt_output(
                 &encryption_key,
                 &ok.acknowledgement,
                 reply_params,
-                false,
+                padding,
+                padded_bytes,
             )?)?;
         }
         RawWasmOutput::OkIBCOpenChannel { ok: _ } => {}
@@ -601,10 +1236,27 @@ fn encrypt_v1_non_result_fields<T: Clone + fmt::Debug + PartialEq>(
     events: &mut [Event],
     secret_msg: &SecretMessage,
 ) -> Result<(), EnclaveError> {
-    let encryption_key = calc_encryption_key(&secret_msg.nonce, &secret_msg.user_public_key);
+    let encryption_key = secret_msg.io_key();
 
     for sub_msg in messages.iter_mut() {
         encrypt_wasm_submsg(sub_msg, secret_msg)?;
+
+        // The payloads of Wasm messages were moved into their reply headers, so these are the
+        // payloads of other messages. They are bound to the ID of their submessage, which the
+        // plaintext reply carries. Only the enclave decrypts them, so they stay in AES-SIV.
+        if !sub_msg.payload.is_empty() {
+            let encrypted_payload = secret_msg
+                .encryption_key()
+                .encrypt_siv(
+                    sub_msg.payload.as_slice(),
+                    Some(&[&sub_msg.id.to_be_bytes()]),
+                )
+                .map_err(|err| {
+                    debug!("got an error while trying to encrypt a payload: {}", err);
+                    EnclaveError::EncryptionError
+                })?;
+            sub_msg.payload = Binary(encrypted_payload);
+        }
     }
 
     // v1: The attributes that will be emitted as part of a "wasm" event.
@@ -624,6 +1276,22 @@ fn encrypt_v1_non_result_fields<T: Clone + fmt::Debug + PartialEq>(
     Ok(())
 }
 
+/// Encrypts the message of a submessage in an envelope of the same version as the message the
+/// contract executes, so that the sender decrypts the whole execution in the suite it chose
+fn encrypt_submsg_envelope(
+    secret_msg: &SecretMessage,
+    msg: Vec<u8>,
+) -> Result<SecretMessage, EnclaveError> {
+    let mut envelope = SecretMessage {
+        nonce: secret_msg.nonce,
+        user_public_key: secret_msg.user_public_key,
+        msg,
+        version: secret_msg.version,
+    };
+    envelope.encrypt_in_place()?;
+    Ok(envelope)
+}
+
 fn encrypt_wasm_submsg<T: Clone + fmt::Debug + PartialEq>(
     sub_msg: &mut SubMsg<T>,
     secret_msg: &SecretMessage,
@@ -634,12 +1302,7 @@ fn encrypt_wasm_submsg<T: Clone + fmt::Debug + PartialEq>(
             cw_types_v1::results::WasmMsg::Instantiate { msg, .. }
             | cw_types_v1::results::WasmMsg::Execute { msg, .. }
             | cw_types_v1::results::WasmMsg::Migrate { msg, .. } => {
-                let mut msg_to_encrypt = SecretMessage {
-                    msg: msg.as_slice().to_vec(),
-                    nonce: secret_msg.nonce,
-                    user_public_key: secret_msg.user_public_key,
-                };
-                msg_to_encrypt.encrypt_in_place()?;
+                let msg_to_encrypt = encrypt_submsg_envelope(secret_msg, msg.as_slice().to_vec())?;
                 *msg = Binary::from(msg_to_encrypt.to_vec().as_slice());
             }
             cw_types_v1::results::WasmMsg::ClearAdmin { .. }
@@ -678,13 +1341,15 @@ fn attach_reply_headers_to_submsgs(
                 wasm_msg,
                 &sub_msg.reply_on,
                 sub_msg.id,
+                sub_msg.payload.as_slice(),
                 contract_hash,
                 reply_params,
             )?;
 
-            // The ID can be extracted from the encrypted wasm msg
-            // We don't encrypt it here to remain with the same type (u64)
+            // The ID and the payload can be extracted from the encrypted wasm msg
+            // We don't encrypt the ID here to remain with the same type (u64)
             sub_msg.id = 0;
+            sub_msg.payload = Binary::default();
         }
 
         sub_msg.was_msg_encrypted = true;
@@ -733,7 +1398,7 @@ fn create_callback_sig_for_submsgs(
                             .iter()
                             .map(|coin| Coin {
                                 denom: coin.denom.clone(),
-                                amount: cw_types_v010::math::Uint128(coin.amount.u128()),
+                                amount: coin.amount,
                             })
                             .collect::<Vec<Coin>>()[..],
                     ));
@@ -783,7 +1448,7 @@ fn adapt_output_for_reply(
         return Ok(output);
     }
 
-    let encryption_key = calc_encryption_key(&secret_msg.nonce, &secret_msg.user_public_key);
+    let encryption_key = secret_msg.io_key();
 
     let output_result;
     let should_append_reply_params;
@@ -803,6 +1468,7 @@ fn adapt_output_for_reply(
             output_result = SubMsgResult::Ok(SubMsgResponse {
                 events: vec![],
                 data: ok.data.clone(),
+                normalized_data: None,
             });
 
             should_append_reply_params = false;
@@ -811,6 +1477,7 @@ fn adapt_output_for_reply(
             output_result = SubMsgResult::Ok(SubMsgResponse {
                 events: vec![],
                 data: ok.data.clone(),
+                normalized_data: None,
             });
 
             should_append_reply_params = true;
@@ -854,7 +1521,7 @@ fn adapt_output_for_reply(
 fn get_reply_info_for_output(
     output_result: SubMsgResult,
     reply_params: &Option<Vec<ReplyParams>>,
-    encryption_key: AESKey,
+    encryption_key: IoKey,
     sender_addr: &CanonicalAddr,
     should_append_all_reply_params: bool,
 ) -> Result<(Binary, Binary), EnclaveError> {
@@ -870,6 +1537,7 @@ fn get_reply_info_for_output(
         result: output_result,
         was_orig_msg_encrypted: true,
         is_encrypted: true,
+        payload: Binary::default(),
     };
 
     let reply_json = serde_json::to_vec(&reply).map_err(|err| {
@@ -893,8 +1561,7 @@ fn get_reply_info_for_output(
 
 fn encrypt_v010_wasm_msg(
     wasm_msg: &mut cw_types_v010::types::WasmMsg,
-    nonce: IoNonce,
-    user_public_key: Ed25519PublicKey,
+    secret_msg: &SecretMessage,
     contract_addr: &CanonicalAddr,
 ) -> Result<(), EnclaveError> {
     match wasm_msg {
@@ -915,13 +1582,7 @@ fn encrypt_v010_wasm_msg(
             let mut hash_appended_msg = callback_code_hash.as_bytes().to_vec();
             hash_appended_msg.extend_from_slice(msg.as_slice());
 
-            let mut msg_to_pass = SecretMessage::from_base64(
-                Binary(hash_appended_msg).to_base64(),
-                nonce,
-                user_public_key,
-            )?;
-
-            msg_to_pass.encrypt_in_place()?;
+            let msg_to_pass = encrypt_submsg_envelope(secret_msg, hash_appended_msg)?;
             *msg = Binary::from(msg_to_pass.to_vec().as_slice());
 
             *callback_sig = Some(create_callback_signature(
@@ -939,13 +1600,7 @@ fn encrypt_v010_wasm_msg(
             let mut hash_appended_msg = callback_code_hash.as_bytes().to_vec();
             hash_appended_msg.extend_from_slice(msg.as_slice());
 
-            let mut msg_to_pass = SecretMessage::from_base64(
-                Binary(hash_appended_msg).to_base64(),
-                nonce,
-                user_public_key,
-            )?;
-
-            msg_to_pass.encrypt_in_place()?;
+            let msg_to_pass = encrypt_submsg_envelope(secret_msg, hash_appended_msg)?;
             *msg = Binary::from(msg_to_pass.to_vec().as_slice());
 
             *callback_sig = Some(create_callback_signature(
@@ -972,6 +1627,7 @@ fn attach_reply_headers_to_v1_wasm_msg(
     wasm_msg: &mut cw_types_v1::results::WasmMsg,
     reply_on: &ReplyOn,
     msg_id: u64, // In every submessage there is a field called "id", currently used only by "reply".
+    payload: &[u8],
     reply_recipient_contract_hash: &str,
     reply_params: &Option<Vec<ReplyParams>>,
 ) -> Result<(), EnclaveError> {
@@ -985,20 +1641,26 @@ fn attach_reply_headers_to_v1_wasm_msg(
             // In this context, we prepend the message with both hashes to signal to the next wasm call that its output is going to be an input to this contract as a "Reply".
             // When decrypting the input, the enclave will try to parse the message as usual, but if the message (after reading the first code-hash) can't be parsed into json,
             // then it will treat the next 64 bytes as a recipient code-hash and prepend this code-hash to its output.
+            // The payload of the submessage follows its header, so it reaches the reply
+            // encrypted, the same way the ID does.
             let mut hash_appended_msg = code_hash.as_bytes().to_vec();
             if *reply_on != ReplyOn::Never {
-                hash_appended_msg
-                    .extend_from_slice(cw_types_v1::results::REPLY_ENCRYPTION_MAGIC_BYTES);
-                hash_appended_msg.extend_from_slice(&msg_id.to_be_bytes());
-                hash_appended_msg.extend_from_slice(reply_recipient_contract_hash.as_bytes());
+                append_reply_header(
+                    &mut hash_appended_msg,
+                    msg_id,
+                    reply_recipient_contract_hash.as_bytes(),
+                    payload,
+                );
             }
 
             if let Some(r) = reply_params {
                 for param in r.iter() {
-                    hash_appended_msg
-                        .extend_from_slice(cw_types_v1::results::REPLY_ENCRYPTION_MAGIC_BYTES);
-                    hash_appended_msg.extend_from_slice(&param.sub_msg_id.to_be_bytes());
-                    hash_appended_msg.extend_from_slice(param.recipient_contract_hash.as_slice());
+                    append_reply_header(
+                        &mut hash_appended_msg,
+                        param.sub_msg_id,
+                        &param.recipient_contract_hash,
+                        &param.payload,
+                    );
                 }
             }
 
@@ -1018,20 +1680,372 @@ pub fn create_callback_signature(
     msg_to_pass: &Vec<u8>,
     sent_funds: &[Coin],
 ) -> Vec<u8> {
-    // sha256(enclave_secret | msg_to_pass | sent_funds)
-    let mut callback_sig_bytes = KEY_MANAGER
-        .get_consensus_callback_secret()
-        .unwrap()
-        .current
-        .get()
-        .to_vec();
-
-    callback_sig_bytes.extend(msg_to_pass.as_slice());
-    callback_sig_bytes.extend(serde_json::to_vec(sent_funds).unwrap());
-
-    sha2::Sha256::digest(callback_sig_bytes.as_slice()).to_vec()
+    let callback_secret = KEY_MANAGER.get_consensus_callback_secret().unwrap();
+    let block = active_block().filter(|_| is_active(Feature::ChainBoundCallbackSig));
+
+    callback_signature(
+        callback_secret.current.get(),
+        block.as_ref().map(|block| block.chain_id.as_str()),
+        msg_to_pass,
+        sent_funds,
+    )
 }
 
 pub fn format_generic_error_message(encrypted_err: Value) -> Value {
     json!({"generic_err":{"msg":encrypted_err}})
 }
+
+/// The error message of a contract that returns a `TypedError`
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TypedErrorMessage {
+    typed_error: TypedError,
+}
+
+fn parse_typed_error_message(message: &str) -> Option<TypedErrorMessage> {
+    serde_json::from_str(message).ok()
+}
+
+/// Typed errors are encrypted as JSON rather than as a JSON string, so that they aren't escaped
+/// and the reply to the caller can parse them back. Returns the error to encrypt.
+fn typed_error_for_encryption(err: &Value) -> Option<Value> {
+    if !is_active(Feature::TypedContractErrors) {
+        return None;
+    }
+
+    let typed_error = parse_typed_error_message(err.as_str()?)?;
+    serde_json::to_value(typed_error).ok()
+}
+
+/// The typed error of a failed submessage, for its reply
+pub fn parse_typed_error(result: &SubMsgResult) -> Option<TypedError> {
+    match result {
+        SubMsgResult::Err(message) if is_active(Feature::TypedContractErrors) => {
+            parse_typed_error_message(message).map(|message| message.typed_error)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_pad_to_bucket() {
+        let mut padded_bytes = 0;
+
+        let params = OutputPaddingParams::default();
+        let buckets = PaddingBuckets::for_features(&[], &params);
+        let padded = pad_to_bucket("eyJiYWxhbmNlIjoiMTA4In0=", &buckets, &mut padded_bytes);
+        assert_eq!(padded.len(), output_padding::BUCKET_SIZES[0]);
+        assert_eq!(padded_bytes, (padded.len() - 24) as u64);
+        assert_eq!(
+            strip_output_padding(padded.as_bytes()),
+            b"eyJiYWxhbmNlIjoiMTA4In0="
+        );
+
+        let largest = *output_padding::BUCKET_SIZES.last().unwrap();
+        assert_eq!(buckets.padded_len(largest), largest);
+        assert_eq!(
+            buckets.padded_len(largest + 1),
+            largest + output_padding::LARGE_OUTPUT_MULTIPLE
+        );
+
+        // Contracts with coarse padding hide the difference between short outputs
+        let buckets = PaddingBuckets::for_features(&[ContractFeature::CoarsePadding], &params);
+        assert_eq!(buckets.padded_len(24), buckets.padded_len(200));
+        assert_eq!(
+            buckets.padded_len(24),
+            output_padding::COARSE_BUCKET_SIZES[0]
+        );
+        let largest = *output_padding::COARSE_BUCKET_SIZES.last().unwrap();
+        assert_eq!(
+            buckets.padded_len(largest + 1),
+            largest + output_padding::COARSE_LARGE_OUTPUT_MULTIPLE
+        );
+        assert_eq!(
+            PaddingBuckets::for_features(&[ContractFeature::Random], &params),
+            PaddingBuckets::for_features(&[], &params)
+        );
+
+        // Governance can change the buckets
+        let params = OutputPaddingParams {
+            bucket_sizes: vec![100, 1000],
+            large_output_multiple: 1000,
+            ..Default::default()
+        };
+        assert!(params.validate().is_ok());
+        let buckets = PaddingBuckets::for_features(&[], &params);
+        assert_eq!(buckets.padded_len(24), 100);
+        assert_eq!(buckets.padded_len(101), 1000);
+        assert_eq!(buckets.padded_len(1001), 2000);
+
+        let invalid = |params: OutputPaddingParams| assert!(params.validate().is_err());
+        invalid(OutputPaddingParams {
+            bucket_sizes: vec![],
+            ..Default::default()
+        });
+        invalid(OutputPaddingParams {
+            bucket_sizes: vec![1000, 100],
+            ..Default::default()
+        });
+        invalid(OutputPaddingParams {
+            coarse_bucket_sizes: vec![0, 100],
+            ..Default::default()
+        });
+        invalid(OutputPaddingParams {
+            large_output_multiple: 0,
+            ..Default::default()
+        });
+        invalid(OutputPaddingParams {
+            coarse_large_output_multiple: output_padding::MAX_BUCKET_SIZE + 1,
+            ..Default::default()
+        });
+    }
+
+    pub fn test_shape_output() {
+        let features = [
+            ContractFeature::CoarsePadding,
+            ContractFeature::ConstantShape(ShapedEntrypoint::Execute),
+        ];
+        let params = OutputPaddingParams::default();
+        let shaped = PaddingBuckets::for_entrypoint(&features, ShapedEntrypoint::Execute, &params);
+        assert!(shaped.is_constant_shape());
+        let query = PaddingBuckets::for_entrypoint(&features, ShapedEntrypoint::Query, &params);
+        assert!(!query.is_constant_shape());
+        assert_eq!(query, PaddingBuckets::for_features(&features, &params));
+        assert_eq!(
+            shaped.padded_len(1),
+            shaped.padded_len(constant_shape::OUTPUT_SIZE)
+        );
+
+        let attribute = |key: &str, encrypted| LogAttribute {
+            key: key.to_string(),
+            value: "value".to_string(),
+            encrypted,
+        };
+        let output = |attributes, data| RawWasmOutput::OkV1 {
+            ok: cw_types_v1::results::Response {
+                attributes,
+                data,
+                ..Default::default()
+            },
+            internal_reply_enclave_sig: None,
+            internal_msg_id: None,
+        };
+
+        // Outputs of different branches end up with the same shape
+        let mut padded_bytes: u64 = 0;
+        let mut shaped = vec![];
+        for (attributes, data) in vec![
+            (vec![attribute("action", true)], None),
+            (
+                vec![attribute("action", true), attribute("amount", true)],
+                Some(Binary(b"result".to_vec())),
+            ),
+        ] {
+            let mut raw_output = output(attributes, data);
+            shape_output(&mut raw_output, &mut padded_bytes).unwrap();
+            shaped.push(raw_output);
+        }
+        for raw_output in &shaped {
+            if let RawWasmOutput::OkV1 { ok, .. } = raw_output {
+                assert_eq!(
+                    ok.attributes.len(),
+                    constant_shape::CONTRACT_ATTRIBUTE_COUNT
+                );
+                for attr in &ok.attributes {
+                    assert!(attr.encrypted);
+                    assert_eq!(attr.key.len(), constant_shape::ATTRIBUTE_KEY_SIZE);
+                    assert_eq!(attr.value.len(), constant_shape::ATTRIBUTE_VALUE_SIZE);
+                }
+                assert!(ok.data.is_some());
+            }
+        }
+
+        // Outputs that would reveal the branch are rejected
+        let mut raw_output = output(vec![attribute("action", false)], None);
+        assert!(shape_output(&mut raw_output, &mut padded_bytes).is_err());
+        let too_many =
+            vec![attribute("action", true); constant_shape::CONTRACT_ATTRIBUTE_COUNT + 1];
+        let mut raw_output = output(too_many, None);
+        assert!(shape_output(&mut raw_output, &mut padded_bytes).is_err());
+        let mut raw_output = output(vec![], Some(Binary(vec![0u8; constant_shape::OUTPUT_SIZE])));
+        assert!(shape_output(&mut raw_output, &mut padded_bytes).is_err());
+        let mut raw_output = RawWasmOutput::OkV1 {
+            ok: cw_types_v1::results::Response {
+                messages: vec![SubMsg {
+                    id: 0,
+                    msg: cw_types_v1::results::CosmosMsg::Bank(
+                        cw_types_v1::results::BankMsg::Burn { amount: vec![] },
+                    ),
+                    gas_limit: None,
+                    reply_on: ReplyOn::Never,
+                    was_msg_encrypted: false,
+                    payload: Binary(vec![]),
+                }],
+                ..Default::default()
+            },
+            internal_reply_enclave_sig: None,
+            internal_msg_id: None,
+        };
+        assert!(shape_output(&mut raw_output, &mut padded_bytes).is_err());
+
+        // The copies for the auditor and the viewer have the same size whatever the output
+        let copy_padding = PaddingBuckets::for_shaped_copies();
+        assert_eq!(
+            copy_padding.padded_len(1),
+            copy_padding.padded_len(constant_shape::COPY_SIZE)
+        );
+    }
+
+    pub fn test_plaintext_attribute_denylist() {
+        let denylist = PlaintextAttributeDenylist::default();
+        let mut attributes = vec![LogAttribute {
+            key: "action".to_string(),
+            value: "transfer".to_string(),
+            encrypted: true,
+        }];
+        assert!(set_attributes_to_plaintext(&mut attributes, &denylist).is_ok());
+        assert!(!attributes[0].encrypted);
+
+        attributes.push(LogAttribute {
+            key: "Amount".to_string(),
+            value: "100".to_string(),
+            encrypted: true,
+        });
+        assert!(set_attributes_to_plaintext(&mut attributes, &denylist).is_err());
+
+        let mut attributes = vec![LogAttribute {
+            key: "memo".to_string(),
+            value: "hello".to_string(),
+            encrypted: true,
+        }];
+        assert!(set_attributes_to_plaintext(&mut attributes, &denylist).is_err());
+
+        // A chain can replace the default list
+        let denylist = PlaintextAttributeDenylist {
+            denied_keys: vec!["balance".to_string()],
+        };
+        assert!(set_attributes_to_plaintext(&mut attributes, &denylist).is_ok());
+        let mut attributes = vec![LogAttribute {
+            key: "BALANCE".to_string(),
+            value: "100".to_string(),
+            encrypted: true,
+        }];
+        assert!(set_attributes_to_plaintext(&mut attributes, &denylist).is_err());
+        assert!(PlaintextAttributeDenylist {
+            denied_keys: vec![String::new()]
+        }
+        .validate()
+        .is_err());
+    }
+
+    pub fn test_normalize_output() {
+        let output = br#"{"Ok":{"messages":[{"id":0,"msg":{"bank":{"send":{"to_address":"secret1x","amount":[{"denom":"uscrt","amount":"1"},{"denom":"uatom","amount":"2"}]}}},"gas_limit":null,"reply_on":"never"}],"attributes":[{"key":"b","value":"1"},{"key":"a","value":"2"},{"key":"b","value":"3"}],"events":[{"type":"z","attributes":[]},{"type":"y","attributes":[{"key":"d","value":"4"},{"key":"c","value":"5"}]}],"data":null}}"#;
+
+        let ok_of = |raw_output: RawWasmOutput| match raw_output {
+            RawWasmOutput::OkV1 { ok, .. } => ok,
+            _ => panic!("unexpected output type"),
+        };
+
+        // Contracts that didn't declare a version keep the order of their outputs
+        let mut raw_output = deserialize_output(output.to_vec()).unwrap();
+        normalize_output(&mut raw_output, 0);
+        let ok = ok_of(raw_output);
+        let keys: Vec<&str> = ok.attributes.iter().map(|attr| attr.key.as_str()).collect();
+        assert_eq!(keys, vec!["b", "a", "b"]);
+
+        let mut raw_output = deserialize_output(output.to_vec()).unwrap();
+        normalize_output(&mut raw_output, 1);
+        let ok = ok_of(raw_output);
+
+        let attributes: Vec<(&str, &str)> = ok
+            .attributes
+            .iter()
+            .map(|attr| (attr.key.as_str(), attr.value.as_str()))
+            .collect();
+        assert_eq!(attributes, vec![("a", "2"), ("b", "1"), ("b", "3")]);
+
+        let event_types: Vec<&str> = ok.events.iter().map(|ev| ev.ty.as_str()).collect();
+        assert_eq!(event_types, vec!["y", "z"]);
+        assert_eq!(ok.events[0].attributes[0].key, "c");
+
+        match &ok.messages[0].msg {
+            cw_types_v1::results::CosmosMsg::Bank(cw_types_v1::results::BankMsg::Send {
+                amount,
+                ..
+            }) => {
+                assert_eq!(amount[0].denom, "uatom");
+                assert_eq!(amount[1].denom, "uscrt");
+            }
+            _ => panic!("unexpected message type"),
+        }
+
+        // The version is the highest one declared that the enclave supports
+        assert_eq!(output_normalization_version(&[]), 0);
+        assert_eq!(
+            output_normalization_version(&[
+                ContractFeature::Random,
+                ContractFeature::NormalizedOutput(1)
+            ]),
+            1
+        );
+        assert_eq!(
+            output_normalization_version(&[ContractFeature::NormalizedOutput(
+                output_normalization::VERSION + 1
+            )]),
+            0
+        );
+    }
+
+    pub fn test_plaintext_ibc_output_keeps_data() {
+        let output = br#"{"Ok":{"messages":[],"attributes":[],"events":[],"data":"ZGF0YQ=="}}"#;
+
+        let finalize = |is_msg_encrypted| {
+            let raw_output = deserialize_output(output.to_vec()).unwrap();
+            let finalized = finalize_raw_output(raw_output, false, true, is_msg_encrypted).unwrap();
+            serde_json::from_slice::<WasmOutput>(&finalized)
+                .unwrap()
+                .ibc_basic
+                .unwrap()
+                .data
+        };
+
+        assert_eq!(finalize(false), Some(Binary(b"data".to_vec())));
+        assert_eq!(finalize(true), None);
+    }
+
+    pub fn test_typed_errors() {
+        let message =
+            r#"{"typed_error":{"kind":"insufficient_funds","data":"eyJuZWVkZWQiOiIxMCJ9"}}"#;
+
+        // Typed errors are encrypted as JSON objects, not as escaped strings
+        let encrypted = typed_error_for_encryption(&Value::String(message.to_string())).unwrap();
+        assert_eq!(serde_json::to_string(&encrypted).unwrap(), message);
+
+        let typed_error = parse_typed_error(&SubMsgResult::Err(message.to_string())).unwrap();
+        assert_eq!(typed_error.kind, "insufficient_funds");
+        assert_eq!(typed_error.data, Binary(br#"{"needed":"10"}"#.to_vec()));
+
+        let without_data = r#"{"typed_error":{"kind":"unauthorized"}}"#;
+        assert_eq!(
+            parse_typed_error(&SubMsgResult::Err(without_data.to_string())),
+            Some(TypedError {
+                kind: "unauthorized".to_string(),
+                data: Binary::default(),
+            })
+        );
+
+        // Other errors stay strings
+        for message in &[
+            "Unauthorized",
+            r#"{"kind":"unauthorized"}"#,
+            r#"{"typed_error":{"kind":"unauthorized"},"other":1}"#,
+        ] {
+            assert!(typed_error_for_encryption(&Value::String(message.to_string())).is_none());
+            assert!(parse_typed_error(&SubMsgResult::Err(message.to_string())).is_none());
+        }
+        assert!(typed_error_for_encryption(&json!({"generic_err":{"msg":"x"}})).is_none());
+    }
+}
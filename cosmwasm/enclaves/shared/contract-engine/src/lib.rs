@@ -1,42 +1,168 @@
 #![feature(stmt_expr_attributes)]
 #![feature(vec_into_raw_parts)]
 
-// Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
-#[cfg(not(target_env = "sgx"))]
+// Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX. Builds
+// with the `sim` feature run on the host and use its std, like the wasm32 builds of the other
+// shared crates.
+#[cfg(not(any(target_env = "sgx", feature = "sim")))]
 extern crate sgx_tstd as std;
 
 extern crate sgx_rand;
 extern crate sgx_types;
 
+mod admin_registry;
+mod atomic_calls;
+mod auditor;
+mod bech32_codec;
+mod canonical_json;
+mod capability_summary;
+mod codecs;
+mod commit_reveal;
+mod compute_params;
 mod contract_operations;
+mod contract_subkeys;
 mod contract_validation;
 mod cosmwasm_config;
+mod crypto_calibration;
+mod custom_query_auth;
 mod db;
+mod decryption_limit;
+mod enclave_manifest;
 mod errors;
+mod event_schema;
 mod execute_message;
+mod execution_receipts;
+mod execution_trace;
 pub mod external;
+mod fee_params;
 mod gas;
+mod group_authority;
+mod handle_routes;
+mod historical_state;
+#[cfg(feature = "light-client-validation")]
+mod ibc_callback_guard;
 mod ibc_denom_utils;
+mod idempotency_keys;
 mod ibc_message;
 mod input_validation;
 mod io;
 mod message;
 mod message_utils;
+mod memory_limits;
+mod metrics;
+mod migration_checkpoint;
+mod oblivious_storage;
+mod oracle_query;
+mod ordered_keys;
+mod outgoing_transfers;
+mod panic_isolation;
+mod pending_replies;
+mod pinned_code;
+mod previous_contract_key;
+mod query_cache;
+mod query_result_cache;
 mod query_chain;
 mod random;
+mod reply_data;
 mod reply_message;
+mod result_encoding;
+mod sdk_responses;
+#[cfg(feature = "light-client-validation")]
+mod sequence_tracker;
+mod sig_verification_cache;
+#[cfg(feature = "sim")]
+pub mod sim;
+mod state_audit;
+mod state_export;
+mod state_proofs;
+mod state_reencryption;
+mod storage_padding;
+mod storage_quota;
 mod hardcoded_admins;
+mod touched_keys;
+mod transient_storage;
+mod tx_journal;
+mod unique_id;
+mod upgrade_policy;
+mod verification_profiles;
+mod viewer;
+mod write_intents;
 pub(crate) mod types;
 #[cfg(feature = "wasm3")]
 pub mod wasm3;
 
 pub use contract_operations::{handle, init, query};
+pub use query_result_cache::clear_query_result_cache;
+pub use write_intents::seal_write_intents;
 #[cfg(feature = "light-client-validation")]
 pub use contract_validation::{check_cert_in_current_block, check_tx_in_current_block};
 
 #[cfg(feature = "test")]
 pub mod tests {
+    use crate::admin_registry;
+    use crate::atomic_calls;
+    use crate::auditor;
+    use crate::bech32_codec;
+    use crate::canonical_json;
+    use crate::capability_summary;
+    use crate::codecs;
+    use crate::commit_reveal;
+    use crate::compute_params;
+    use crate::contract_subkeys;
+    use crate::contract_validation;
+    use crate::crypto_calibration;
+    use crate::custom_query_auth;
+    use crate::db;
+    use crate::decryption_limit;
+    use crate::enclave_manifest;
+    use crate::event_schema;
+    use crate::execution_receipts;
+    use crate::execution_trace;
+    use crate::fee_params;
+    use crate::group_authority;
+    use crate::handle_routes;
+    use crate::historical_state;
+    #[cfg(feature = "light-client-validation")]
+    use crate::ibc_callback_guard;
+    use crate::idempotency_keys;
+    #[cfg(feature = "light-client-validation")]
+    use crate::sequence_tracker;
+    use crate::input_validation::msg_validation;
+    use crate::input_validation::send_funds_validations;
+    use crate::io;
+    use crate::memory_limits;
+    use crate::metrics;
+    use crate::migration_checkpoint;
+    use crate::oblivious_storage;
+    use crate::oracle_query;
+    use crate::ordered_keys;
+    use crate::outgoing_transfers;
+    use crate::panic_isolation;
+    use crate::pending_replies;
+    use crate::pinned_code;
+    use crate::previous_contract_key;
+    use crate::query_cache;
+    use crate::query_result_cache;
+    use crate::query_chain;
+    use crate::reply_data;
+    use crate::result_encoding;
+    use crate::sdk_responses;
+    use crate::sig_verification_cache;
+    use crate::state_audit;
+    use crate::state_export;
+    use crate::state_proofs;
+    use crate::state_reencryption;
+    use crate::storage_padding;
+    use crate::storage_quota;
+    use crate::touched_keys;
+    use crate::transient_storage;
+    use crate::tx_journal;
     use crate::types;
+    use crate::unique_id;
+    use crate::upgrade_policy;
+    use crate::verification_profiles;
+    use crate::viewer;
+    use crate::write_intents;
 
     /// Catch failures like the standard test runner, and print similar information per test.
     /// Tests can only fail by panicking, not by returning a `Result` type.
@@ -60,8 +186,138 @@ pub mod tests {
         println!();
         let mut failures = 0;
 
+        // Local networks activate every feature
+        enclave_cosmos_types::feature_activation::set_active_block("secretdev-1", 1);
+
         count_failures!(failures, {
             types::tests::test_new_from_slice();
+            types::tests::test_versioned_from_slice();
+            io::tests::test_pad_to_bucket();
+            io::tests::test_shape_output();
+            io::tests::test_plaintext_attribute_denylist();
+            io::tests::test_normalize_output();
+            io::tests::test_plaintext_ibc_output_keeps_data();
+            io::tests::test_typed_errors();
+            event_schema::tests::test_take_schema_registration();
+            event_schema::tests::test_validate_output_events();
+            crypto_calibration::tests::test_suggested_cost();
+            crypto_calibration::tests::test_split_batch_nanos();
+            crypto_calibration::tests::test_calibration_iterations_are_bounded();
+            enclave_manifest::tests::test_encode_manifest();
+            enclave_manifest::tests::test_missing_capabilities();
+            outgoing_transfers::tests::test_find_callback_transfers();
+            outgoing_transfers::tests::test_find_stargate_callback_transfers();
+            atomic_calls::tests::test_parse_atomic_calls();
+            atomic_calls::tests::test_append_atomic_calls();
+            pinned_code::tests::test_resolve_pinned_code();
+            previous_contract_key::tests::test_previous_contract_key_grace_window();
+            state_reencryption::tests::test_state_key_rotation_epochs();
+            state_reencryption::tests::test_check_chunk_order();
+            state_reencryption::tests::test_malformed_chunk_is_an_error();
+            upgrade_policy::tests::test_parse_upgrade_policy();
+            upgrade_policy::tests::test_check_migration_announcement();
+            upgrade_policy::tests::test_check_self_migration_code();
+            upgrade_policy::tests::test_verify_code_signature();
+            migration_checkpoint::tests::test_parse_migration_cursor();
+            migration_checkpoint::tests::test_check_migration_resume();
+            metrics::tests::test_encode_metrics();
+            contract_validation::tests::test_historical_block_state();
+            contract_validation::tests::test_ibc_timeout_elapsed();
+            contract_validation::tests::test_amino_msgs_match_tx_msgs();
+            contract_validation::tests::test_unsigned_msg_sender();
+            contract_validation::tests::test_reply_payload_headers();
+            msg_validation::tests::test_verify_ibc_wasm_hooks_incoming_nft_transfer();
+            send_funds_validations::tests::test_verify_funds_denoms();
+            send_funds_validations::tests::test_attach_received_denom_trace();
+            send_funds_validations::tests::test_verify_wide_sent_funds();
+            state_export::tests::test_belongs_to_user();
+            state_export::tests::test_permit_sign_bytes();
+            auditor::tests::test_parse_auditor_key();
+            capability_summary::tests::test_take_capability_summary_request();
+            capability_summary::tests::test_capability_summary();
+            viewer::tests::test_parse_viewer_key();
+            write_intents::tests::test_write_intents();
+            query_cache::tests::test_query_cache_scope();
+            query_cache::tests::test_split_query_cache_scope();
+            query_result_cache::tests::test_query_result_key();
+            query_result_cache::tests::test_is_cacheable_query();
+            query_result_cache::tests::test_query_result_cache();
+            query_chain::tests::test_query_gas_budget();
+            fee_params::tests::test_gas_price();
+            fee_params::tests::test_encode_fee_params();
+            pending_replies::tests::test_take_siblings();
+            pending_replies::tests::test_reply_ids_in_output();
+            panic_isolation::tests::test_isolate_execution();
+            panic_isolation::tests::test_lock_or_recover();
+            oblivious_storage::tests::test_bucket_index();
+            oblivious_storage::tests::test_oblivious_access();
+            db::tests::test_scan_prefix_in_state_pages();
+            db::tests::test_scan_prefix_in_state_skips_reserved_and_legacy_keys();
+            db::tests::test_with_cached_keys_with_prefix();
+            db::tests::test_measure_stored_bytes();
+            storage_padding::tests::test_padded_access_count();
+            storage_padding::tests::test_padding_slot_key();
+            storage_padding::tests::test_mirrored_sizes();
+            storage_padding::tests::test_derive_padding_key();
+            storage_quota::tests::test_storage_usage();
+            storage_quota::tests::test_storage_usage_count();
+            storage_quota::tests::test_storage_surcharge();
+            touched_keys::tests::test_hash_touched_keys();
+            unique_id::tests::test_derive_unique_id();
+            verification_profiles::tests::test_verification_profile();
+            verification_profiles::tests::test_height_gated_profiles();
+            canonical_json::tests::test_canonicalize_json();
+            transient_storage::tests::test_transient_storage();
+            transient_storage::tests::test_transient_writes_of_failed_submessages_are_reverted();
+            bech32_codec::tests::test_bech32_vectors();
+            bech32_codec::tests::test_bech32_roundtrip();
+            codecs::tests::test_codecs();
+            admin_registry::tests::test_admin_registry();
+            sdk_responses::tests::test_decode_sdk_responses();
+            compute_params::tests::test_compute_params();
+            compute_params::tests::test_compute_params_memory_limits();
+            compute_params::tests::test_compute_params_output_padding();
+            compute_params::tests::test_compute_params_callback_binding_sequences();
+            compute_params::tests::test_compute_params_admin_registry();
+            compute_params::tests::test_compute_params_plaintext_attribute_denylist();
+            reply_data::tests::test_normalize_reply_data();
+            state_audit::tests::test_flush_record();
+            state_proofs::tests::test_state_proof();
+            state_proofs::tests::test_read_proof();
+            oracle_query::tests::test_parse_oracle_query();
+            custom_query_auth::tests::test_proven_custom_query_key();
+            custom_query_auth::tests::test_authenticate_custom_response();
+            group_authority::tests::test_proven_group_proposals();
+            decryption_limit::tests::test_decryption_limit();
+            contract_subkeys::tests::test_derive_contract_subkey();
+            commit_reveal::tests::test_commitment_key();
+            commit_reveal::tests::test_derive_committed_random();
+            commit_reveal::tests::test_reveal_commitment();
+            idempotency_keys::tests::test_take_idempotency_key();
+            idempotency_keys::tests::test_idempotency_keys_window();
+            idempotency_keys::tests::test_already_executed_output();
+            idempotency_keys::tests::test_expired_idempotency_keys_are_removed();
+            idempotency_keys::tests::test_unchecked_idempotency_keys_are_swept();
+            sig_verification_cache::tests::test_sig_verification_cache();
+            execution_receipts::tests::test_execution_receipt_signature();
+            execution_receipts::tests::test_execution_receipts_disabled();
+            execution_trace::tests::test_execution_trace();
+            memory_limits::tests::test_memory_limits();
+            handle_routes::tests::test_handle_routes();
+            historical_state::tests::test_historical_reads_are_proven();
+            result_encoding::tests::test_result_envelope_round_trip();
+            result_encoding::tests::test_negotiate_result_encoding();
+            ordered_keys::tests::test_ordered_keys();
+            ordered_keys::tests::test_ordered_namespaces();
+            tx_journal::tests::test_record_failure();
+            tx_journal::tests::test_get_failure_report();
+        });
+
+        #[cfg(feature = "light-client-validation")]
+        count_failures!(failures, {
+            ibc_callback_guard::tests::test_processed_callback_key();
+            sequence_tracker::tests::test_account_sequences();
+            contract_validation::tests::test_deliver_tx_is_derived_from_the_verified_block();
         });
 
         if failures != 0 {
@@ -72,3 +328,6 @@ pub mod tests {
 
 #[cfg(all(feature = "go-tests", feature = "production"))]
 compile_error!("Cannot use 'go-tests' & 'production' features together.");
+
+#[cfg(all(feature = "sim", target_env = "sgx"))]
+compile_error!("Cannot use 'sim' in an enclave, it replaces the ocalls of the host.");
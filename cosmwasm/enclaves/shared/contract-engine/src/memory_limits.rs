@@ -0,0 +1,94 @@
+//! The limits of the linear memory of contract instances, as set by governance in the
+//! `memory_limits` param of the compute module, see `compute_params`.
+//!
+//! Besides the limit of every contract, the param can raise the limit of specific codes, like
+//! orderbooks that keep large structures in memory. Until the param is set, every contract gets
+//! `DEFAULT_MAX_MEMORY_PAGES`.
+
+use std::collections::BTreeMap;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use enclave_ffi_types::EnclaveError;
+
+/// 12 MiB of 64 KiB pages
+pub const DEFAULT_MAX_MEMORY_PAGES: u32 = 192;
+
+/// No limit can go above 32 MiB, so that every thread of the enclave can run an instance with
+/// the largest memory at the same time
+pub const MAX_MEMORY_PAGES_CEILING: u32 = 512;
+
+fn default_max_memory_pages() -> u32 {
+    DEFAULT_MAX_MEMORY_PAGES
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MemoryLimits {
+    #[serde(default = "default_max_memory_pages")]
+    pub max_memory_pages: u32,
+    /// The limits of specific codes, by the hex of their code hash
+    #[serde(default)]
+    pub code_overrides: BTreeMap<String, u32>,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            max_memory_pages: DEFAULT_MAX_MEMORY_PAGES,
+            code_overrides: BTreeMap::new(),
+        }
+    }
+}
+
+impl MemoryLimits {
+    pub fn validate(&self) -> Result<(), EnclaveError> {
+        let limits = std::iter::once(&self.max_memory_pages).chain(self.code_overrides.values());
+        for pages in limits {
+            if *pages == 0 || *pages > MAX_MEMORY_PAGES_CEILING {
+                warn!(
+                    "memory limit of {} pages is not between 1 and {}",
+                    pages, MAX_MEMORY_PAGES_CEILING
+                );
+                return Err(EnclaveError::ValidationFailure);
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of pages instances of the code can grow their memory to
+    pub fn pages_for(&self, code_hash: &[u8]) -> u32 {
+        self.code_overrides
+            .get(&hex::encode(code_hash))
+            .copied()
+            .unwrap_or(self.max_memory_pages)
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_memory_limits() {
+        let code_hash = [7u8; 32];
+        let limits = format!(
+            r#"{{"code_overrides":{{"{}":480}}}}"#,
+            hex::encode(code_hash)
+        );
+
+        let parsed: MemoryLimits = serde_json::from_str(&limits).unwrap();
+        assert!(parsed.validate().is_ok());
+        assert_eq!(parsed.pages_for(&code_hash), 480);
+        // Other codes get the limit of every contract
+        assert_eq!(parsed.pages_for(&[8u8; 32]), DEFAULT_MAX_MEMORY_PAGES);
+        assert_eq!(
+            MemoryLimits::default().pages_for(&code_hash),
+            DEFAULT_MAX_MEMORY_PAGES
+        );
+
+        let too_large: MemoryLimits = serde_json::from_str(r#"{"max_memory_pages":513}"#).unwrap();
+        assert!(too_large.validate().is_err());
+        let empty: MemoryLimits = serde_json::from_str(r#"{"max_memory_pages":0}"#).unwrap();
+        assert!(empty.validate().is_err());
+    }
+}
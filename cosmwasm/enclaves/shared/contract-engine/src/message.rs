@@ -3,12 +3,7 @@ use log::trace;
 use enclave_cosmos_types::types::HandleType;
 use enclave_ffi_types::EnclaveError;
 
-use crate::execute_message::parse_execute_message;
-use crate::ibc_message::{
-    parse_ibc_receive_message, parse_plaintext_ibc_protocol_message,
-    parse_plaintext_ibc_validated_message,
-};
-use crate::reply_message::parse_reply_message;
+use crate::handle_routes::handle_route;
 use crate::types::ParsedMessage;
 
 // Parse the message that was passed to handle (Based on the assumption that it might be a reply or IBC as well)
@@ -16,39 +11,19 @@ pub fn parse_message(
     message: &[u8],
     handle_type: &HandleType,
 ) -> Result<ParsedMessage, EnclaveError> {
-    return match handle_type {
-        HandleType::HANDLE_TYPE_EXECUTE => parse_execute_message(message),
-        HandleType::HANDLE_TYPE_REPLY => parse_reply_message(message),
-        HandleType::HANDLE_TYPE_IBC_CHANNEL_OPEN
-        | HandleType::HANDLE_TYPE_IBC_CHANNEL_CONNECT
-        | HandleType::HANDLE_TYPE_IBC_CHANNEL_CLOSE => {
-            trace!(
-                "parsing {} msg (Should always be plaintext): {:?}",
-                HandleType::get_export_name(handle_type),
-                base64::encode(message)
-            );
+    let route = handle_route(*handle_type);
+    trace!(
+        "parsing {} msg with {:?} encryption: {:?}",
+        route.export,
+        route.encryption,
+        base64::encode(message)
+    );
 
-            parse_plaintext_ibc_protocol_message(message)
-        }
-        HandleType::HANDLE_TYPE_IBC_PACKET_RECEIVE => parse_ibc_receive_message(message),
-        HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER
-        | HandleType::HANDLE_TYPE_IBC_PACKET_ACK
-        | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK
-        | HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT
-        | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT => {
-            parse_plaintext_ibc_validated_message(message)
-        }
-    };
+    // Relayed messages are verified against the relayed messages of the transaction, according
+    // to their verification profile
+    route.encryption.parse(message)
 }
 
 pub fn is_ibc_msg(handle_type: HandleType) -> bool {
-    matches!(
-        handle_type,
-        HandleType::HANDLE_TYPE_IBC_CHANNEL_OPEN
-            | HandleType::HANDLE_TYPE_IBC_CHANNEL_CONNECT
-            | HandleType::HANDLE_TYPE_IBC_CHANNEL_CLOSE
-            | HandleType::HANDLE_TYPE_IBC_PACKET_RECEIVE
-            | HandleType::HANDLE_TYPE_IBC_PACKET_ACK
-            | HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT
-    )
+    handle_route(handle_type).ibc_entrypoint
 }
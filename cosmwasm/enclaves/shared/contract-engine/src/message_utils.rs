@@ -1,4 +1,4 @@
-use crate::types::{DecryptedSecretMessage, SecretMessage};
+use crate::types::{DecryptedSecretMessage, EnvelopeVersion, SecretMessage};
 use log::trace;
 
 pub fn get_secret_msg(message: &[u8]) -> SecretMessage {
@@ -14,6 +14,7 @@ pub fn get_secret_msg(message: &[u8]) -> SecretMessage {
                 nonce: [0; 32],
                 user_public_key: [0; 32],
                 msg: message.into(),
+                version: EnvelopeVersion::Unversioned,
             }
         }
     }
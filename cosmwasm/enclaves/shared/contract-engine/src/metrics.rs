@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::SgxMutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use enclave_cosmos_types::types::HandleType;
+use enclave_ffi_types::EnclaveError;
+
+use crate::panic_isolation::LockOrRecover;
+
+/// The contract operation an execution metric is counted under
+pub enum MeteredOperation {
+    Init,
+    Handle(u8),
+    Query,
+    Migrate,
+}
+
+impl MeteredOperation {
+    fn label(&self) -> String {
+        match self {
+            MeteredOperation::Init => "init".to_string(),
+            MeteredOperation::Query => "query".to_string(),
+            MeteredOperation::Migrate => "migrate".to_string(),
+            MeteredOperation::Handle(handle_type) => match HandleType::try_from(*handle_type) {
+                Ok(handle_type) => format!("{:?}", handle_type)
+                    .trim_start_matches("HANDLE_TYPE_")
+                    .to_lowercase(),
+                Err(_) => "unknown".to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct ExecutionMetrics {
+    executions: u64,
+    failures: u64,
+    gas_used: u64,
+}
+
+#[derive(Default)]
+struct Metrics {
+    executions: BTreeMap<String, ExecutionMetrics>,
+    engine_starts: u64,
+    engine_start_time: Duration,
+    module_cache_hits: u64,
+    module_cache_misses: u64,
+    decryption_failures: u64,
+    refused_decryptions: u64,
+    sequence_regressions: u64,
+    unacknowledged_write_intents: u64,
+}
+
+lazy_static! {
+    /// Metrics since the enclave was started. They only count what the host can observe anyway,
+    /// and never include anything about the contents of executions.
+    static ref METRICS: SgxMutex<Metrics> = SgxMutex::new(Metrics::default());
+}
+
+pub fn record_execution(operation: MeteredOperation, used_gas: u64, error: Option<&EnclaveError>) {
+    let mut metrics = METRICS.lock_or_recover();
+
+    let execution = metrics.executions.entry(operation.label()).or_default();
+    execution.executions += 1;
+    execution.gas_used = execution.gas_used.saturating_add(used_gas);
+    if error.is_some() {
+        execution.failures += 1;
+    }
+
+    if let Some(EnclaveError::DecryptionError) = error {
+        metrics.decryption_failures += 1;
+    }
+}
+
+pub fn record_engine_start(elapsed: Duration) {
+    let mut metrics = METRICS.lock_or_recover();
+    metrics.engine_starts += 1;
+    metrics.engine_start_time += elapsed;
+}
+
+pub fn record_module_cache_lookup(hit: bool) {
+    let mut metrics = METRICS.lock_or_recover();
+    if hit {
+        metrics.module_cache_hits += 1;
+    } else {
+        metrics.module_cache_misses += 1;
+    }
+}
+
+/// Counts a query that wasn't decrypted because its connection failed to decrypt too often, see
+/// `decryption_limit`
+pub fn record_refused_decryption() {
+    METRICS.lock_or_recover().refused_decryptions += 1;
+}
+
+/// Counts a transaction whose sender signed a lower sequence than in a transaction that was
+/// already seen, see `sequence_tracker`
+#[cfg(feature = "light-client-validation")]
+pub fn record_sequence_regression() {
+    METRICS.lock_or_recover().sequence_regressions += 1;
+}
+
+/// Counts the writes whose commit the host never acknowledged, see `write_intents`
+pub fn record_unacknowledged_write_intents(count: u64) {
+    METRICS.lock_or_recover().unacknowledged_write_intents += count;
+}
+
+/// Writes a metric, with samples of the labels (or suffix) of the sample and its value
+fn write_metric(
+    output: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(String, String)],
+) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(output, "{}{} {}", name, labels, value);
+    }
+}
+
+fn encode_metrics(metrics: &Metrics) -> String {
+    let mut output = String::new();
+
+    let per_operation = |value: fn(&ExecutionMetrics) -> u64| -> Vec<(String, String)> {
+        metrics
+            .executions
+            .iter()
+            .map(|(operation, execution)| {
+                (
+                    format!("{{operation=\"{}\"}}", operation),
+                    value(execution).to_string(),
+                )
+            })
+            .collect()
+    };
+
+    write_metric(
+        &mut output,
+        "secret_enclave_executions_total",
+        "counter",
+        "Contract executions by operation",
+        &per_operation(|execution| execution.executions),
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_execution_failures_total",
+        "counter",
+        "Contract executions that returned an enclave error, by operation",
+        &per_operation(|execution| execution.failures),
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_gas_used_total",
+        "counter",
+        "Gas used by contract executions, by operation",
+        &per_operation(|execution| execution.gas_used),
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_engine_start_seconds",
+        "summary",
+        "Time spent starting the wasm engine for an execution",
+        &[
+            (
+                "_sum".to_string(),
+                metrics.engine_start_time.as_secs_f64().to_string(),
+            ),
+            ("_count".to_string(), metrics.engine_starts.to_string()),
+        ],
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_module_cache_lookups_total",
+        "counter",
+        "Lookups in the module cache by result",
+        &[
+            (
+                "{result=\"hit\"}".to_string(),
+                metrics.module_cache_hits.to_string(),
+            ),
+            (
+                "{result=\"miss\"}".to_string(),
+                metrics.module_cache_misses.to_string(),
+            ),
+        ],
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_decryption_failures_total",
+        "counter",
+        "Contract executions that failed to decrypt their input",
+        &[("".to_string(), metrics.decryption_failures.to_string())],
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_refused_decryptions_total",
+        "counter",
+        "Queries refused because their public key failed to decrypt too often recently",
+        &[("".to_string(), metrics.refused_decryptions.to_string())],
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_sequence_regressions_total",
+        "counter",
+        "Transactions whose sender signed a lower sequence than in an earlier transaction",
+        &[("".to_string(), metrics.sequence_regressions.to_string())],
+    );
+    write_metric(
+        &mut output,
+        "secret_enclave_unacknowledged_write_intents_total",
+        "counter",
+        "Flushed writes whose commit the host never acknowledged",
+        &[(
+            "".to_string(),
+            metrics.unacknowledged_write_intents.to_string(),
+        )],
+    );
+
+    output
+}
+
+/// Returns the metrics of the enclave in the Prometheus text exposition format
+pub fn get_enclave_metrics() -> Result<Vec<u8>, EnclaveError> {
+    let metrics = METRICS.lock_or_recover();
+    Ok(encode_metrics(&metrics).into_bytes())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_encode_metrics() {
+        let mut metrics = Metrics::default();
+        metrics.executions.insert(
+            MeteredOperation::Handle(0).label(),
+            ExecutionMetrics {
+                executions: 3,
+                failures: 1,
+                gas_used: 1000,
+            },
+        );
+        metrics.module_cache_hits = 2;
+        metrics.engine_starts = 3;
+        metrics.engine_start_time = Duration::from_millis(1500);
+        metrics.refused_decryptions = 4;
+        metrics.sequence_regressions = 2;
+        metrics.unacknowledged_write_intents = 1;
+
+        let output = encode_metrics(&metrics);
+        assert!(output.contains("secret_enclave_executions_total{operation=\"execute\"} 3\n"));
+        assert!(
+            output.contains("secret_enclave_execution_failures_total{operation=\"execute\"} 1\n")
+        );
+        assert!(output.contains("secret_enclave_gas_used_total{operation=\"execute\"} 1000\n"));
+        assert!(output.contains("secret_enclave_engine_start_seconds_sum 1.5\n"));
+        assert!(output.contains("secret_enclave_engine_start_seconds_count 3\n"));
+        assert!(output.contains("secret_enclave_module_cache_lookups_total{result=\"hit\"} 2\n"));
+        assert!(output.contains("# TYPE secret_enclave_decryption_failures_total counter\n"));
+        assert!(output.contains("secret_enclave_refused_decryptions_total 4\n"));
+        assert!(output.contains("secret_enclave_sequence_regressions_total 2\n"));
+        assert!(output.contains("secret_enclave_unacknowledged_write_intents_total 1\n"));
+        assert_eq!(MeteredOperation::Handle(255).label(), "unknown");
+    }
+}
@@ -0,0 +1,198 @@
+//! Migrations that span several transactions.
+//!
+//! A migration over a large state can't fit in the gas limit of a block. Instead, the contract's
+//! `migrate` can call `migration_checkpoint` with a cursor of its progress and return, and the
+//! admin resumes the migration by migrating the contract again, to the same code. The resumed
+//! `migrate` reads the cursor with `migration_cursor`, and the migration is complete once a
+//! `migrate` returns without a checkpoint.
+//!
+//! The checkpoint is stored in the contract's state under a reserved key, so the cursor is
+//! encrypted with the rest of the state and only the enclave can read or write it. While it's
+//! pending the contract can only be migrated to the code it's being migrated to, and resuming
+//! the migration doesn't check the upgrade policy again, since it already allowed migrating to
+//! that code. The contract can't be executed either, since its state is only partly migrated,
+//! except for the replies to the submessages of the migration itself. Queries still read the
+//! partly migrated state.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_v010::encoding::Binary;
+use enclave_cosmos_types::types::ContractCode;
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::reserved_keys;
+use crate::wasm3::Engine;
+
+/// The largest cursor a contract can checkpoint
+pub const MAX_MIGRATION_CURSOR_SIZE: usize = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct MigrationCheckpoint {
+    /// The hash of the code the contract is being migrated to
+    code_hash: Binary,
+    cursor: Binary,
+    /// The height of the block of the migration that set the checkpoint
+    height: u64,
+}
+
+/// Whether a migration resumes a pending one
+#[derive(Debug, PartialEq)]
+pub enum MigrationResume {
+    /// There is no pending migration
+    None,
+    /// The migration resumes the pending one, from this cursor
+    Resume(Vec<u8>),
+    /// There is a pending migration to other code, which has to complete first
+    Refused(String),
+}
+
+/// Parses the cursor passed to `migration_checkpoint`. Errors are returned to the contract as
+/// messages.
+pub fn parse_migration_cursor(cursor: &[u8]) -> Result<Vec<u8>, String> {
+    if cursor.is_empty() {
+        return Err("Invalid migration cursor: cursor is empty".to_string());
+    }
+    if cursor.len() > MAX_MIGRATION_CURSOR_SIZE {
+        return Err(format!(
+            "Invalid migration cursor: cursor is {} bytes, should be at most {}",
+            cursor.len(),
+            MAX_MIGRATION_CURSOR_SIZE
+        ));
+    }
+
+    Ok(cursor.to_vec())
+}
+
+fn read_migration_checkpoint(
+    engine: &mut Engine,
+) -> Result<Option<MigrationCheckpoint>, EnclaveError> {
+    match engine.read_reserved_key(reserved_keys::MIGRATION_CHECKPOINT)? {
+        Some(stored) => serde_json::from_slice(&stored).map(Some).map_err(|err| {
+            warn!("stored migration checkpoint is malformed: {}", err);
+            EnclaveError::FailedToDeserialize
+        }),
+        None => Ok(None),
+    }
+}
+
+fn check_migration_resume(
+    checkpoint: Option<MigrationCheckpoint>,
+    new_code_hash: &[u8],
+) -> MigrationResume {
+    let checkpoint = match checkpoint {
+        Some(checkpoint) => checkpoint,
+        None => return MigrationResume::None,
+    };
+
+    if checkpoint.code_hash.0 != new_code_hash {
+        return MigrationResume::Refused(format!(
+            "the migration of the contract to code {} that was checkpointed at block {} has to complete first",
+            hex::encode(&checkpoint.code_hash.0),
+            checkpoint.height
+        ));
+    }
+
+    MigrationResume::Resume(checkpoint.cursor.0)
+}
+
+/// Refuses executing a contract while its migration is pending
+pub fn check_no_pending_migration(engine: &mut Engine) -> Result<(), EnclaveError> {
+    if let Some(checkpoint) = read_migration_checkpoint(engine)? {
+        warn!(
+            "contract has a pending migration to code {} since block {}",
+            hex::encode(&checkpoint.code_hash.0),
+            checkpoint.height
+        );
+        return Err(EnclaveError::MigrationPending);
+    }
+
+    Ok(())
+}
+
+/// Checks whether migrating the contract to `new_code` resumes a pending migration, or is
+/// refused because of one
+pub fn check_migration_checkpoint(
+    engine: &mut Engine,
+    new_code: &ContractCode,
+) -> Result<MigrationResume, EnclaveError> {
+    let checkpoint = read_migration_checkpoint(engine)?;
+    Ok(check_migration_resume(checkpoint, &new_code.hash()))
+}
+
+/// Stores the cursor the contract checkpointed while it was migrated to the code with
+/// `code_hash`. If it didn't checkpoint, the migration is complete, and the checkpoint of a
+/// migration it resumed is removed.
+pub fn store_migration_checkpoint(
+    engine: &mut Engine,
+    cursor: Option<Vec<u8>>,
+    code_hash: [u8; HASH_SIZE],
+    block_height: u64,
+    resumed: bool,
+) -> Result<(), EnclaveError> {
+    let cursor = match cursor {
+        Some(cursor) => cursor,
+        None => {
+            if resumed {
+                engine.remove_reserved_key(reserved_keys::MIGRATION_CHECKPOINT)?;
+            }
+            return Ok(());
+        }
+    };
+
+    let stored = serde_json::to_vec(&MigrationCheckpoint {
+        code_hash: Binary(code_hash.to_vec()),
+        cursor: Binary(cursor),
+        height: block_height,
+    })
+    .map_err(|err| {
+        warn!("failed to serialize migration checkpoint: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+    engine.write_reserved_key(reserved_keys::MIGRATION_CHECKPOINT, &stored);
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_parse_migration_cursor() {
+        assert_eq!(
+            parse_migration_cursor(b"key-1000"),
+            Ok(b"key-1000".to_vec())
+        );
+        assert!(parse_migration_cursor(&[0u8; MAX_MIGRATION_CURSOR_SIZE]).is_ok());
+
+        assert!(parse_migration_cursor(b"").is_err());
+        assert!(parse_migration_cursor(&[0u8; MAX_MIGRATION_CURSOR_SIZE + 1]).is_err());
+    }
+
+    pub fn test_check_migration_resume() {
+        let checkpoint = || {
+            Some(MigrationCheckpoint {
+                code_hash: Binary(vec![1u8; HASH_SIZE]),
+                cursor: Binary(b"key-1000".to_vec()),
+                height: 100,
+            })
+        };
+
+        assert_eq!(
+            check_migration_resume(None, &[1u8; HASH_SIZE]),
+            MigrationResume::None
+        );
+        assert_eq!(
+            check_migration_resume(checkpoint(), &[1u8; HASH_SIZE]),
+            MigrationResume::Resume(b"key-1000".to_vec())
+        );
+        match check_migration_resume(checkpoint(), &[2u8; HASH_SIZE]) {
+            MigrationResume::Refused(reason) => {
+                assert!(reason.contains(&hex::encode([1u8; HASH_SIZE])));
+                assert!(reason.contains("block 100"));
+            }
+            other => panic!("migration to other code wasn't refused: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Oblivious storage, a path ORAM that contracts can keep their most sensitive entries in.
+//!
+//! The entries are kept in a binary tree of fixed size buckets, stored under reserved keys of the
+//! contract's state, and every entry is assigned a random leaf of the tree. An access reads every
+//! bucket on the path to the entry's leaf, moves the entry to a new random leaf, and writes back
+//! the whole path with as many entries as fit, so the host only sees a random path being read
+//! and rewritten, whichever entry was accessed and whether it was read, written or removed.
+//! Entries that don't fit back into the path wait in a stash, which is kept in the metadata
+//! along with the leaves of the entries.
+//!
+//! The leaves are drawn from a generator seeded with a secret of the contract and the number of
+//! accesses so far, so every node draws the same leaves and the host can't predict them.
+//!
+//! Queries can't write, so they read the path of an entry without moving it. Repeated queries of
+//! the same entry on one node read the same path.
+
+use std::collections::BTreeMap;
+
+use log::*;
+use rand_chacha::ChaChaRng;
+use rand_core::{RngCore, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use enclave_crypto::{sha_256, HASH_SIZE};
+
+use crate::cosmwasm_config::oblivious_storage::{
+    BLOCK_VALUE_SIZE, BUCKET_SIZE, MAX_ENTRIES, MAX_STASH, TREE_HEIGHT,
+};
+use crate::cosmwasm_config::reserved_keys::OBLIVIOUS_STORAGE_PREFIX;
+use crate::errors::WasmEngineError;
+
+const LEAF_COUNT: u32 = 1 << TREE_HEIGHT;
+
+/// The size of a serialized block: the id, the leaf and the length prefixed value
+const BLOCK_BYTES: usize = HASH_SIZE + 4 + 8 + BLOCK_VALUE_SIZE;
+/// The size every bucket is padded to: the access counter and the length prefixed blocks
+const BUCKET_BYTES: usize = 8 + 8 + BUCKET_SIZE * BLOCK_BYTES;
+/// The size the metadata is padded to: the access counter, the leaves of the entries and the
+/// stash
+const METADATA_BYTES: usize = 8 + 8 + MAX_ENTRIES * (HASH_SIZE + 4) + 8 + MAX_STASH * BLOCK_BYTES;
+
+/// Where the buckets and the metadata are read from and written to
+pub trait ObliviousBackend {
+    fn read(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, WasmEngineError>;
+    fn write(&mut self, key: &[u8], value: &[u8]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access<'v> {
+    Read,
+    Write(&'v [u8]),
+    Remove,
+}
+
+#[derive(Debug)]
+pub enum ObliviousStorageError {
+    /// The value is longer than `BLOCK_VALUE_SIZE`
+    ValueTooLarge,
+    /// The storage already has `MAX_ENTRIES` entries
+    Full,
+    /// More than `MAX_STASH` entries didn't fit back into the tree, which is very unlikely
+    StashOverflow,
+    Backend(WasmEngineError),
+}
+
+impl From<WasmEngineError> for ObliviousStorageError {
+    fn from(err: WasmEngineError) -> Self {
+        ObliviousStorageError::Backend(err)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Block {
+    /// The hash of the key of the entry
+    id: [u8; HASH_SIZE],
+    leaf: u32,
+    value: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Bucket {
+    /// The access that wrote the bucket, so that the bucket is encrypted differently every time
+    /// it's written, even if its entries didn't change
+    written_at: u64,
+    blocks: Vec<Block>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Metadata {
+    accesses: u64,
+    leaves: BTreeMap<[u8; HASH_SIZE], u32>,
+    stash: Vec<Block>,
+}
+
+/// Reads, writes or removes the entry of `key`, and returns its value before the access.
+/// `secret` seeds the leaves the entries are moved to, and must only be known to the enclave.
+pub fn access<B: ObliviousBackend>(
+    backend: &mut B,
+    secret: &[u8],
+    key: &[u8],
+    operation: Access,
+    read_only: bool,
+) -> Result<Option<Vec<u8>>, ObliviousStorageError> {
+    if let Access::Write(value) = operation {
+        if value.len() > BLOCK_VALUE_SIZE {
+            return Err(ObliviousStorageError::ValueTooLarge);
+        }
+    }
+
+    let mut metadata: Metadata = read_padded(backend, &metadata_key())?.unwrap_or_default();
+    let id = sha_256(key);
+
+    metadata.accesses += 1;
+    let mut rng = leaf_rng(secret, metadata.accesses);
+    // Entries that don't exist are looked up on a random path, like entries that do
+    let random_leaf = rng.next_u32() % LEAF_COUNT;
+    let new_leaf = rng.next_u32() % LEAF_COUNT;
+    let leaf = metadata.leaves.get(&id).copied().unwrap_or(random_leaf);
+
+    for level in 0..=TREE_HEIGHT {
+        let bucket: Bucket =
+            read_padded(backend, &bucket_key(bucket_index(leaf, level)))?.unwrap_or_default();
+        metadata.stash.extend(bucket.blocks);
+    }
+
+    let position = metadata.stash.iter().position(|block| block.id == id);
+    let value = position.map(|i| metadata.stash[i].value.clone());
+
+    if read_only {
+        return Ok(value);
+    }
+
+    match (operation, position) {
+        (Access::Read, Some(i)) => {
+            metadata.stash[i].leaf = new_leaf;
+            metadata.leaves.insert(id, new_leaf);
+        }
+        (Access::Read, None) | (Access::Remove, None) => {}
+        (Access::Write(new_value), Some(i)) => {
+            metadata.stash[i].value = new_value.to_vec();
+            metadata.stash[i].leaf = new_leaf;
+            metadata.leaves.insert(id, new_leaf);
+        }
+        (Access::Write(new_value), None) => {
+            if metadata.leaves.len() >= MAX_ENTRIES {
+                return Err(ObliviousStorageError::Full);
+            }
+            metadata.stash.push(Block {
+                id,
+                leaf: new_leaf,
+                value: new_value.to_vec(),
+            });
+            metadata.leaves.insert(id, new_leaf);
+        }
+        (Access::Remove, Some(i)) => {
+            metadata.stash.swap_remove(i);
+            metadata.leaves.remove(&id);
+        }
+    }
+
+    // Write the path back from the leaf up, so entries sink as deep as their leaves allow
+    for level in (0..=TREE_HEIGHT).rev() {
+        let index = bucket_index(leaf, level);
+        let mut bucket = Bucket {
+            written_at: metadata.accesses,
+            blocks: vec![],
+        };
+        let mut i = 0;
+        while i < metadata.stash.len() && bucket.blocks.len() < BUCKET_SIZE {
+            if bucket_index(metadata.stash[i].leaf, level) == index {
+                bucket.blocks.push(metadata.stash.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        write_padded(backend, &bucket_key(index), &bucket, BUCKET_BYTES)?;
+    }
+
+    if metadata.stash.len() > MAX_STASH {
+        warn!(
+            "oblivious storage stash has {} entries, more than {}",
+            metadata.stash.len(),
+            MAX_STASH
+        );
+        return Err(ObliviousStorageError::StashOverflow);
+    }
+    write_padded(backend, &metadata_key(), &metadata, METADATA_BYTES)?;
+
+    Ok(value)
+}
+
+fn leaf_rng(secret: &[u8], accesses: u64) -> ChaChaRng {
+    let mut seed_data = secret.to_vec();
+    seed_data.extend_from_slice(&accesses.to_be_bytes());
+    ChaChaRng::from_seed(sha_256(&seed_data))
+}
+
+/// The index of the bucket at `level` on the path from the root to `leaf`, with the buckets of
+/// the tree numbered level by level from the root, which is 0
+fn bucket_index(leaf: u32, level: u32) -> u32 {
+    (1 << level) - 1 + (leaf >> (TREE_HEIGHT - level))
+}
+
+fn bucket_key(index: u32) -> Vec<u8> {
+    let mut key = OBLIVIOUS_STORAGE_PREFIX.to_vec();
+    key.extend_from_slice(b"bucket/");
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+fn metadata_key() -> Vec<u8> {
+    let mut key = OBLIVIOUS_STORAGE_PREFIX.to_vec();
+    key.extend_from_slice(b"metadata");
+    key
+}
+
+/// Everything is padded to a fixed size, so that the sizes of the buckets and of the metadata
+/// don't reveal how many entries they hold
+fn write_padded<B: ObliviousBackend, T: Serialize>(
+    backend: &mut B,
+    key: &[u8],
+    value: &T,
+    size: usize,
+) -> Result<(), ObliviousStorageError> {
+    let serialized = bincode2::serialize(value).map_err(|err| {
+        warn!("failed to serialize oblivious storage: {:?}", err);
+        WasmEngineError::SerializationError
+    })?;
+    if serialized.len() > size {
+        warn!(
+            "serialized oblivious storage is {} bytes, more than {}",
+            serialized.len(),
+            size
+        );
+        return Err(WasmEngineError::SerializationError.into());
+    }
+
+    let mut padded = (serialized.len() as u32).to_be_bytes().to_vec();
+    padded.extend_from_slice(&serialized);
+    padded.resize(4 + size, 0);
+    backend.write(key, &padded);
+
+    Ok(())
+}
+
+fn read_padded<B: ObliviousBackend, T: DeserializeOwned>(
+    backend: &mut B,
+    key: &[u8],
+) -> Result<Option<T>, ObliviousStorageError> {
+    let padded = match backend.read(key)? {
+        Some(padded) => padded,
+        None => return Ok(None),
+    };
+
+    let malformed = || {
+        warn!("oblivious storage entry is malformed");
+        ObliviousStorageError::Backend(WasmEngineError::DeserializationError)
+    };
+    if padded.len() < 4 {
+        return Err(malformed());
+    }
+    let mut len = [0u8; 4];
+    len.copy_from_slice(&padded[..4]);
+    let serialized = padded
+        .get(4..4 + u32::from_be_bytes(len) as usize)
+        .ok_or_else(malformed)?;
+
+    bincode2::deserialize(serialized)
+        .map(Some)
+        .map_err(|_| malformed())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryBackend {
+        entries: HashMap<Vec<u8>, Vec<u8>>,
+        writes: usize,
+    }
+
+    impl ObliviousBackend for MemoryBackend {
+        fn read(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, WasmEngineError> {
+            Ok(self.entries.get(key).cloned())
+        }
+
+        fn write(&mut self, key: &[u8], value: &[u8]) {
+            self.writes += 1;
+            self.entries.insert(key.to_vec(), value.to_vec());
+        }
+    }
+
+    const SECRET: &[u8] = b"secret";
+
+    fn write(backend: &mut MemoryBackend, key: &[u8], value: &[u8]) {
+        access(backend, SECRET, key, Access::Write(value), false).unwrap();
+    }
+
+    fn read(backend: &mut MemoryBackend, key: &[u8]) -> Option<Vec<u8>> {
+        access(backend, SECRET, key, Access::Read, false).unwrap()
+    }
+
+    pub fn test_bucket_index() {
+        assert_eq!(bucket_index(0, 0), 0);
+        assert_eq!(bucket_index(LEAF_COUNT - 1, 0), 0);
+        assert_eq!(bucket_index(0, 1), 1);
+        assert_eq!(bucket_index(LEAF_COUNT - 1, 1), 2);
+        assert_eq!(bucket_index(0, TREE_HEIGHT), LEAF_COUNT - 1);
+        assert_eq!(
+            bucket_index(LEAF_COUNT - 1, TREE_HEIGHT),
+            2 * LEAF_COUNT - 2
+        );
+    }
+
+    pub fn test_oblivious_access() {
+        let mut backend = MemoryBackend::default();
+
+        assert_eq!(read(&mut backend, b"missing"), None);
+        for i in 0..100u32 {
+            write(&mut backend, &i.to_be_bytes(), &i.to_le_bytes());
+        }
+        for i in 0..100u32 {
+            assert_eq!(
+                read(&mut backend, &i.to_be_bytes()),
+                Some(i.to_le_bytes().to_vec())
+            );
+        }
+
+        write(&mut backend, b"key", b"first");
+        write(&mut backend, b"key", b"second");
+        assert_eq!(read(&mut backend, b"key"), Some(b"second".to_vec()));
+
+        let removed = access(&mut backend, SECRET, b"key", Access::Remove, false).unwrap();
+        assert_eq!(removed, Some(b"second".to_vec()));
+        assert_eq!(read(&mut backend, b"key"), None);
+
+        // Every stored entry has the same size
+        let sizes: Vec<usize> = backend.entries.values().map(Vec::len).collect();
+        assert!(sizes
+            .iter()
+            .all(|size| *size == BUCKET_BYTES + 4 || *size == METADATA_BYTES + 4));
+
+        // Queries don't write
+        let writes = backend.writes;
+        let value = access(
+            &mut backend,
+            SECRET,
+            &7u32.to_be_bytes(),
+            Access::Read,
+            true,
+        )
+        .unwrap();
+        assert_eq!(value, Some(7u32.to_le_bytes().to_vec()));
+        assert_eq!(backend.writes, writes);
+
+        assert!(matches!(
+            access(
+                &mut backend,
+                SECRET,
+                b"big",
+                Access::Write(&[0u8; BLOCK_VALUE_SIZE + 1]),
+                false
+            ),
+            Err(ObliviousStorageError::ValueTooLarge)
+        ));
+    }
+}
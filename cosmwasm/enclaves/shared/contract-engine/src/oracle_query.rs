@@ -0,0 +1,226 @@
+//! Queries of the exchange rates of a native oracle module, sent by contracts as
+//! `QueryRequest::Custom({"oracle": ...})`.
+//!
+//! The host isn't asked for the rate. The transaction that executes the contract carries an
+//! `OracleProof` of it in a non-critical extension option: a `StateProof` of the rate, and the
+//! header that commits to the proven state with the commit that signs it. The enclave verifies
+//! them against the validator set of the current block, so every node answers the query the same
+//! way whichever blocks it verified itself. Executions without a proof of the rate, like queries
+//! and submessages, get an error.
+
+use log::*;
+use protobuf::Message;
+use serde::{Deserialize, Serialize};
+
+use cosmos_proto::base::coin::DecProto;
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::math::Decimal;
+use cw_types_v010::std_error::StdResult;
+use cw_types_v010::system_error::{SystemError, SystemResult};
+
+use enclave_ffi_types::EnclaveError;
+
+use crate::state_proofs::{proven_app_hash, StateProof};
+
+/// The store of the oracle module, and the prefix of the exchange rates in it
+const ORACLE_STORE: &[u8] = b"oracle";
+const EXCHANGE_RATE_PREFIX: u8 = 0x01;
+const MAX_DENOM_LENGTH: usize = 128;
+
+/// Charged for an oracle query, which verifies the signatures of the commit of a header
+pub const ORACLE_QUERY_GAS: u64 = 100_000;
+
+/// Oracle rates are stored as `sdk.Dec`s, which have 18 fractional digits like `Decimal`
+const DEC_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum CustomQuery {
+    Oracle(OracleQuery),
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQuery {
+    /// Returns an `ExchangeRateResponse`
+    ExchangeRate { denom: String },
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ExchangeRateResponse {
+    pub denom: String,
+    pub rate: Decimal,
+    /// The height the rate was proven at, so contracts can reject stale rates
+    pub height: u64,
+}
+
+impl OracleQuery {
+    fn store_key(&self) -> Vec<u8> {
+        match self {
+            OracleQuery::ExchangeRate { denom } => {
+                let mut key = vec![EXCHANGE_RATE_PREFIX];
+                key.extend_from_slice(denom.as_bytes());
+                key
+            }
+        }
+    }
+}
+
+/// Returns the oracle query in a custom query, if it is one
+pub fn parse_oracle_query(custom: &serde_json::Value) -> Option<OracleQuery> {
+    match serde_json::from_value(custom.clone()) {
+        Ok(CustomQuery::Oracle(query)) => Some(query),
+        Err(_) => None,
+    }
+}
+
+/// The proof of a value of the oracle store that a transaction carries
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OracleProof {
+    /// The protobuf encoded header at the height after the one of the proof, whose app hash
+    /// commits to the proven state
+    pub header: Binary,
+    /// The protobuf encoded commit that signs the header
+    pub commit: Binary,
+    pub proof: StateProof,
+}
+
+impl OracleProof {
+    /// Verifies the proof and its header, and returns the proven value of the key
+    fn verified_value(&self, key: &[u8]) -> Result<&[u8], EnclaveError> {
+        let app_hash = proven_app_hash(
+            self.proof.height,
+            self.header.as_slice(),
+            self.commit.as_slice(),
+        )?;
+        if !self.proof.verify(ORACLE_STORE, key, &app_hash) {
+            warn!("oracle proof at height {} is invalid", self.proof.height);
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        Ok(self.proof.store_proof.value.as_slice())
+    }
+}
+
+fn invalid_request(query: &OracleQuery, error: &str) -> SystemResult<StdResult<Binary>> {
+    warn!("cannot answer an oracle query: {}", error);
+    let OracleQuery::ExchangeRate { denom } = query;
+    Err(SystemError::InvalidRequest {
+        error: error.to_string(),
+        request: Binary(denom.as_bytes().to_vec()),
+    })
+}
+
+fn parse_rate(value: &[u8]) -> Option<Decimal> {
+    let dec = DecProto::parse_from_bytes(value).ok()?;
+    let atomics: u128 = dec.dec.parse().ok()?;
+    format!(
+        "{}.{:018}",
+        atomics / DEC_FRACTIONAL,
+        atomics % DEC_FRACTIONAL
+    )
+    .parse()
+    .ok()
+}
+
+/// Answers an oracle query from the first proof of the rate that the transaction carries.
+/// Proofs that can't be parsed are ignored.
+pub fn answer_oracle_query(
+    query: &OracleQuery,
+    oracle_proofs: &[Binary],
+) -> SystemResult<StdResult<Binary>> {
+    let OracleQuery::ExchangeRate { denom } = query;
+    if denom.is_empty() || denom.len() > MAX_DENOM_LENGTH {
+        return invalid_request(query, "invalid denom");
+    }
+
+    let key = query.store_key();
+    let proof = oracle_proofs
+        .iter()
+        .filter_map(|proof| serde_json::from_slice::<OracleProof>(proof.as_slice()).ok())
+        .find(|proof| proof.proof.store_proof.key.as_slice() == key.as_slice());
+    let proof = match proof {
+        Some(proof) => proof,
+        None => return invalid_request(query, "the transaction carries no proof of the rate"),
+    };
+
+    let value = match proof.verified_value(&key) {
+        Ok(value) => value,
+        Err(_) => return invalid_request(query, "the proof of the rate is invalid"),
+    };
+    let rate = match parse_rate(value) {
+        Some(rate) => rate,
+        None => return invalid_request(query, "the proven rate is malformed"),
+    };
+
+    let answer = ExchangeRateResponse {
+        denom: denom.clone(),
+        rate,
+        height: proof.proof.height,
+    };
+    match serde_json::to_vec(&answer) {
+        Ok(answer) => Ok(Ok(Binary(answer))),
+        Err(_) => invalid_request(query, "failed to serialize the rate"),
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::state_proofs::tests::prove;
+
+    pub fn test_parse_oracle_query() {
+        let custom = serde_json::json!({"oracle": {"exchange_rate": {"denom": "uscrt"}}});
+        let query = parse_oracle_query(&custom).unwrap();
+        assert_eq!(
+            query,
+            OracleQuery::ExchangeRate {
+                denom: "uscrt".to_string()
+            }
+        );
+        assert_eq!(query.store_key(), b"\x01uscrt".to_vec());
+
+        // Other custom queries are passed to the host as they are
+        let custom = serde_json::json!({"swap": {"offer": "uscrt"}});
+        assert_eq!(parse_oracle_query(&custom), None);
+
+        let mut dec = DecProto::new();
+        dec.dec = "1500000000000000000".to_string();
+        let rate = parse_rate(&dec.write_to_bytes().unwrap()).unwrap();
+        assert_eq!(serde_json::to_string(&rate).unwrap(), r#""1.5""#);
+        dec.dec = "1.5".to_string();
+        assert_eq!(parse_rate(&dec.write_to_bytes().unwrap()), None);
+
+        // Without a proof of the rate in the transaction, or with one of another rate
+        assert!(matches!(
+            answer_oracle_query(&query, &[]),
+            Err(SystemError::InvalidRequest { .. })
+        ));
+        let (state_proof, _) = prove(ORACLE_STORE, b"\x01uatom", &dec.write_to_bytes().unwrap());
+        let other_rate = OracleProof {
+            header: Binary(vec![]),
+            commit: Binary(vec![]),
+            proof: state_proof,
+        };
+        let other_rate = Binary(serde_json::to_vec(&other_rate).unwrap());
+        let unparsable = Binary(br#"{"denom":"uscrt","rate":"1000"}"#.to_vec());
+        assert!(matches!(
+            answer_oracle_query(&query, &[unparsable.clone(), other_rate]),
+            Err(SystemError::InvalidRequest { .. })
+        ));
+
+        // A proof of the rate whose header isn't signed by the validators
+        let (state_proof, _) = prove(ORACLE_STORE, b"\x01uscrt", &dec.write_to_bytes().unwrap());
+        let unsigned = OracleProof {
+            header: Binary(vec![1, 2, 3]),
+            commit: Binary(vec![4, 5, 6]),
+            proof: state_proof,
+        };
+        let unsigned = Binary(serde_json::to_vec(&unsigned).unwrap());
+        assert!(matches!(
+            answer_oracle_query(&query, &[unparsable, unsigned]),
+            Err(SystemError::InvalidRequest { .. })
+        ));
+    }
+}
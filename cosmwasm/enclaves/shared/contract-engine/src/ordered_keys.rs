@@ -0,0 +1,287 @@
+//! Order-preserving keys, which let contracts that declare the `ordered_keys` feature range over
+//! the entries of a namespace in order, e.g. over the indexes of a cw-storage-plus `IndexedMap`.
+//!
+//! Regular keys are encrypted with AES-SIV, so the host stores them in a random order and a range
+//! has to scan and decrypt every key of the contract. A namespace becomes ordered when the
+//! contract first writes to it with `db_write_ordered`, and from then on all of its keys are
+//! stored as:
+//!
+//! `ORDERED_KEY_MAGIC || u16 length || AES-SIV(namespace) || encoded suffix`
+//!
+//! where the namespace is the length prefixed first component of the key, as cw-storage-plus
+//! lays it out, and every byte of the rest of the key is encoded as two bytes with a secret,
+//! strictly increasing table of its position. The host stores the keys of a namespace next to
+//! each other and in the order of their plaintext, so a range reads only the keys it returns.
+//!
+//! Privacy: within an ordered namespace the host learns the order of the keys, their lengths,
+//! which keys share a prefix, and which keys have the same byte at the same position, which may
+//! be enough to guess the keys of small or predictable key spaces (e.g. amounts or timestamps).
+//! It doesn't learn the name of the namespace, and values are encrypted as usual. Namespaces
+//! that were never written with `db_write_ordered` keep the regular encryption, so a contract
+//! only gives this up for the namespaces it ranges over.
+
+use std::collections::BTreeSet;
+
+use log::*;
+
+use enclave_crypto::{sha_256, AESKey, Kdf, SIVEncryptable};
+
+use crate::db::KeyScheme;
+use crate::errors::WasmEngineError;
+
+/// Starts every ordered key. Regular keys start with the length of their serialized header, and
+/// legacy keys are digests.
+pub const ORDERED_KEY_MAGIC: &[u8] = b"\xfeordered";
+
+/// Info used to derive the tables that the bytes of the keys of a namespace are encoded with
+const TABLES_DERIVATION_INFO: &[u8] = b"ordered_key_tables";
+
+/// The first component of a cw-storage-plus key: a big endian u16 length and that many bytes
+pub fn namespace_of(key: &[u8]) -> Option<&[u8]> {
+    if key.len() < 2 {
+        return None;
+    }
+    let length = u16::from_be_bytes([key[0], key[1]]) as usize;
+    if length == 0 || key.len() < 2 + length {
+        return None;
+    }
+    Some(&key[..2 + length])
+}
+
+/// The namespaces of a contract that are ordered, kept under a reserved key
+#[derive(Default, Debug, PartialEq)]
+pub struct OrderedNamespaces(BTreeSet<Vec<u8>>);
+
+impl OrderedNamespaces {
+    pub fn parse(stored: &[u8]) -> Result<Self, WasmEngineError> {
+        let namespaces = serde_json::from_slice(stored).map_err(|err| {
+            warn!("failed to parse the ordered namespaces: {:?}", err);
+            WasmEngineError::DeserializationError
+        })?;
+        Ok(OrderedNamespaces(namespaces))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.0).unwrap()
+    }
+
+    /// Whether the key is in an ordered namespace
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        namespace_of(key).map_or(false, |namespace| self.0.contains(namespace))
+    }
+
+    pub fn scheme_of(&self, key: &[u8]) -> KeyScheme {
+        if self.contains_key(key) {
+            KeyScheme::Ordered
+        } else {
+            KeyScheme::Siv
+        }
+    }
+
+    /// Makes the namespace of the key ordered, and returns whether it wasn't ordered yet
+    pub fn insert_namespace_of(&mut self, key: &[u8]) -> Result<bool, WasmEngineError> {
+        match namespace_of(key) {
+            Some(namespace) => Ok(self.0.insert(namespace.to_vec())),
+            None => {
+                debug!("can't order a key without a namespace");
+                Err(WasmEngineError::UnauthorizedWrite)
+            }
+        }
+    }
+}
+
+/// The secret, strictly increasing tables that the bytes of the keys of a namespace are encoded
+/// with, one per position
+struct ByteTables {
+    seed: [u8; 32],
+    tables: Vec<[u16; 256]>,
+}
+
+impl ByteTables {
+    fn new(state_key: &AESKey, namespace: &[u8]) -> Self {
+        let mut info = TABLES_DERIVATION_INFO.to_vec();
+        info.extend_from_slice(namespace);
+        ByteTables {
+            seed: *state_key.derive_key_from_this(&info).get(),
+            tables: vec![],
+        }
+    }
+
+    /// Every value is at least one more than the previous one, and the last is at most 65279
+    fn table(&mut self, position: usize) -> &[u16; 256] {
+        while self.tables.len() <= position {
+            let index = self.tables.len() as u32;
+            let mut table = [0u16; 256];
+            let mut next = 0u16;
+            for block in 0..8u8 {
+                let mut input = self.seed.to_vec();
+                input.extend_from_slice(&index.to_be_bytes());
+                input.push(block);
+                for (offset, gap) in sha_256(&input).iter().enumerate() {
+                    let byte = block as usize * 32 + offset;
+                    next += (*gap % 255) as u16;
+                    table[byte] = next;
+                    next += 1;
+                }
+            }
+            self.tables.push(table);
+        }
+        &self.tables[position]
+    }
+
+    fn encode(&mut self, suffix: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(suffix.len() * 2);
+        for (position, byte) in suffix.iter().enumerate() {
+            encoded.extend_from_slice(&self.table(position)[*byte as usize].to_be_bytes());
+        }
+        encoded
+    }
+
+    fn decode(&mut self, encoded: &[u8]) -> Option<Vec<u8>> {
+        if encoded.len() % 2 != 0 {
+            return None;
+        }
+        encoded
+            .chunks_exact(2)
+            .enumerate()
+            .map(|(position, code)| {
+                let code = u16::from_be_bytes([code[0], code[1]]);
+                self.table(position)
+                    .binary_search(&code)
+                    .ok()
+                    .map(|byte| byte as u8)
+            })
+            .collect()
+    }
+}
+
+/// The part of the ordered keys of the namespace that comes before the encoded suffix. The keys
+/// of the namespace are the raw keys that start with it.
+pub fn ordered_namespace_prefix(
+    state_key: &AESKey,
+    namespace: &[u8],
+) -> Result<Vec<u8>, WasmEngineError> {
+    let encrypted_namespace = state_key
+        .encrypt_siv(namespace, Some(&[ORDERED_KEY_MAGIC]))
+        .map_err(|err| {
+            warn!(
+                "failed to encrypt the namespace of an ordered key: {:?}",
+                err
+            );
+            WasmEngineError::EncryptionError
+        })?;
+
+    let mut prefix = ORDERED_KEY_MAGIC.to_vec();
+    prefix.extend_from_slice(&(encrypted_namespace.len() as u16).to_be_bytes());
+    prefix.extend_from_slice(&encrypted_namespace);
+    Ok(prefix)
+}
+
+/// Encodes a key of an ordered namespace into the key the chain stores
+pub fn encode_ordered_key(state_key: &AESKey, key: &[u8]) -> Result<Vec<u8>, WasmEngineError> {
+    let namespace = namespace_of(key).ok_or_else(|| {
+        warn!("can't encode a key without a namespace as an ordered key");
+        WasmEngineError::EncryptionError
+    })?;
+
+    let mut ordered_key = ordered_namespace_prefix(state_key, namespace)?;
+    ordered_key.extend(ByteTables::new(state_key, namespace).encode(&key[namespace.len()..]));
+    Ok(ordered_key)
+}
+
+pub fn is_ordered_key(raw_key: &[u8]) -> bool {
+    raw_key.starts_with(ORDERED_KEY_MAGIC)
+}
+
+/// Decodes the plaintext key of an ordered key. Returns `None` if the key isn't an ordered key
+/// that was encoded with this state key.
+pub fn decode_ordered_key(state_key: &AESKey, raw_key: &[u8]) -> Option<Vec<u8>> {
+    let rest = raw_key.strip_prefix(ORDERED_KEY_MAGIC)?;
+    if rest.len() < 2 {
+        return None;
+    }
+    let length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+    let rest = &rest[2..];
+    if rest.len() < length {
+        return None;
+    }
+    let (encrypted_namespace, encoded_suffix) = rest.split_at(length);
+
+    let mut key = state_key
+        .decrypt_siv(encrypted_namespace, Some(&[ORDERED_KEY_MAGIC]))
+        .ok()?;
+    let suffix = ByteTables::new(state_key, &key).decode(encoded_suffix)?;
+    key.extend(suffix);
+    Some(key)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn key(namespace: &[u8], suffix: &[u8]) -> Vec<u8> {
+        let mut key = (namespace.len() as u16).to_be_bytes().to_vec();
+        key.extend_from_slice(namespace);
+        key.extend_from_slice(suffix);
+        key
+    }
+
+    pub fn test_ordered_keys() {
+        let state_key = AESKey::new_from_slice(&[7u8; 32]);
+
+        let mut keys: Vec<Vec<u8>> = vec![
+            key(b"tokens", b""),
+            key(b"tokens", b"\x00"),
+            key(b"tokens", b"\x00\xff"),
+            key(b"tokens", b"\x01"),
+            key(b"tokens", b"abc"),
+            key(b"tokens", b"abd"),
+            key(b"tokens", b"b"),
+            key(b"tokens", &[0xff; 40]),
+        ];
+        keys.sort();
+        let encoded: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|key| encode_ordered_key(&state_key, key).unwrap())
+            .collect();
+
+        // The order of the plaintext keys is kept, and the keys can be decoded
+        for (key, ordered_key) in keys.iter().zip(&encoded) {
+            assert!(is_ordered_key(ordered_key));
+            assert_eq!(
+                decode_ordered_key(&state_key, ordered_key).as_ref(),
+                Some(key)
+            );
+        }
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(sorted, encoded);
+
+        let prefix = ordered_namespace_prefix(&state_key, &key(b"tokens", b"")).unwrap();
+        assert!(encoded.iter().all(|key| key.starts_with(&prefix)));
+        // Other namespaces and state keys encode differently
+        assert!(!encode_ordered_key(&state_key, &key(b"owners", b"abc"))
+            .unwrap()
+            .starts_with(&prefix));
+        let other_state_key = AESKey::new_from_slice(&[8u8; 32]);
+        assert_eq!(decode_ordered_key(&other_state_key, &encoded[4]), None);
+        assert!(encode_ordered_key(&state_key, b"\x00").is_err());
+    }
+
+    pub fn test_ordered_namespaces() {
+        assert_eq!(namespace_of(&key(b"ns", b"k")), Some(&key(b"ns", b"")[..]));
+        assert_eq!(namespace_of(b"\x00\x05ns"), None);
+        assert_eq!(namespace_of(b"\x00\x00k"), None);
+
+        let mut namespaces = OrderedNamespaces::default();
+        assert!(!namespaces.contains_key(&key(b"ns", b"k")));
+        assert!(namespaces.insert_namespace_of(&key(b"ns", b"k")).unwrap());
+        assert!(!namespaces.insert_namespace_of(&key(b"ns", b"j")).unwrap());
+        assert!(namespaces.insert_namespace_of(b"k").is_err());
+        assert!(namespaces.contains_key(&key(b"ns", b"other")));
+        assert!(!namespaces.contains_key(&key(b"nt", b"k")));
+
+        let parsed = OrderedNamespaces::parse(&namespaces.serialize()).unwrap();
+        assert_eq!(parsed, namespaces);
+    }
+}
@@ -0,0 +1,246 @@
+use std::convert::TryInto;
+
+use log::*;
+
+use cw_types_v010::types::HumanAddr;
+use cw_types_v1::results::{CosmosMsg, IbcMsg};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_cosmos_types::types::{
+    DirectSdkMsg, FungibleTokenPacketData, IbcHooksOutgoingTransferMemo, Packet,
+    TYPE_URL_MSG_TRANSFER,
+};
+use enclave_crypto::sha_256;
+use enclave_ffi_types::EnclaveError;
+
+use crate::compute_params::ComputeParams;
+use crate::cosmwasm_config::reserved_keys;
+use crate::io::RawWasmOutput;
+use crate::wasm3::Engine;
+
+/// Identifies an outgoing transfer whose ack or timeout should be routed back to the contract
+/// that sent it. The denom is left out, because the packet carries the denom trace while the
+/// contract sends the `ibc/...` denom of the voucher.
+fn binding_key(channel_id: &str, receiver: &str, amount: &str, memo: &str) -> Vec<u8> {
+    let binding = serde_json::json!([channel_id, receiver, amount, memo]).to_string();
+
+    let mut key = reserved_keys::OUTGOING_TRANSFERS_PREFIX.to_vec();
+    key.extend_from_slice(&sha_256(binding.as_bytes()));
+    key
+}
+
+/// Whether the memo asks ibc-hooks to call `contract_address` back on ack or timeout
+fn is_callback_memo(memo: &str, contract_address: &HumanAddr) -> bool {
+    match serde_json::from_str::<IbcHooksOutgoingTransferMemo>(memo) {
+        Ok(memo) => memo.ibc_callback == *contract_address,
+        Err(_) => false,
+    }
+}
+
+/// Returns the binding keys of the transfers in the raw output of a contract that ask for a
+/// callback to the contract itself, whether they are sent as IBC messages or as Stargate
+/// messages that encode an ICS-20 `MsgTransfer`
+fn find_callback_transfers(output: &[u8], contract_address: &HumanAddr) -> Vec<Vec<u8>> {
+    let messages = match serde_json::from_slice::<RawWasmOutput>(output) {
+        Ok(RawWasmOutput::OkV1 { ok, .. }) => ok.messages,
+        // v0.10 contracts can't set a memo, and malformed outputs are reported later
+        _ => return vec![],
+    };
+
+    messages
+        .iter()
+        .filter_map(|sub_msg| match &sub_msg.msg {
+            CosmosMsg::Ibc(IbcMsg::Transfer {
+                channel_id,
+                to_address,
+                amount,
+                memo: Some(memo),
+                ..
+            }) if is_callback_memo(memo, contract_address) => Some(binding_key(
+                channel_id,
+                to_address,
+                &amount.amount.to_string(),
+                memo,
+            )),
+            CosmosMsg::Stargate { type_url, value } if type_url == TYPE_URL_MSG_TRANSFER => {
+                match DirectSdkMsg::from_bytes(type_url, value.as_slice()) {
+                    Ok(DirectSdkMsg::MsgTransfer {
+                        source_channel,
+                        receiver,
+                        token,
+                        memo,
+                        ..
+                    }) if is_callback_memo(&memo, contract_address) => Some(binding_key(
+                        &source_channel,
+                        &receiver,
+                        &token.amount.to_string(),
+                        &memo,
+                    )),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn read_count(engine: &mut Engine, key: &[u8]) -> Result<u64, EnclaveError> {
+    match engine.read_reserved_key(key)? {
+        Some(count) => {
+            let count: [u8; 8] = count.as_slice().try_into().map_err(|_| {
+                warn!("stored outgoing transfer count is malformed");
+                EnclaveError::FailedToDeserialize
+            })?;
+            Ok(u64::from_be_bytes(count))
+        }
+        None => Ok(0),
+    }
+}
+
+/// Records the transfers a contract sent with an ibc-hooks callback to itself, so that the
+/// callbacks can later be matched to a transfer the contract really initiated
+pub fn record_outgoing_transfers(
+    engine: &mut Engine,
+    output: &[u8],
+    contract_address: &HumanAddr,
+) -> Result<(), EnclaveError> {
+    if !is_active(Feature::BoundTransferCallbacks) {
+        return Ok(());
+    }
+
+    for key in find_callback_transfers(output, contract_address) {
+        let count = read_count(engine, &key)?;
+        engine.write_reserved_key(&key, &(count + 1).to_be_bytes());
+    }
+
+    Ok(())
+}
+
+/// Verifies that the packet of an ibc-hooks ack or timeout callback belongs to a transfer the
+/// contract initiated, and consumes it so that the callback can't be replayed.
+/// Without this, anyone could send a transfer with a memo that names the contract as its callback.
+/// Transfers sent before the upgrade that introduced the bindings have none, so their callbacks
+/// are delivered as they were before.
+pub fn consume_outgoing_transfer(
+    engine: &mut Engine,
+    packet: &Packet,
+    contract_address: &HumanAddr,
+    compute_params: &ComputeParams,
+) -> Result<(), EnclaveError> {
+    if compute_params.is_unbound_callback(&packet.source_channel, packet.sequence) {
+        debug!(
+            "delivering the callback of packet {} of {}, which was sent before transfers were bound",
+            packet.sequence, packet.source_channel
+        );
+        return Ok(());
+    }
+
+    let packet_data: FungibleTokenPacketData =
+        serde_json::from_slice(&packet.data).map_err(|err| {
+            warn!("failed to parse outgoing transfer packet data: {}", err);
+            EnclaveError::FailedToDeserialize
+        })?;
+    let memo = packet_data.memo.unwrap_or_default();
+
+    let key = binding_key(
+        &packet.source_channel,
+        &packet_data.receiver.0,
+        &packet_data.amount.to_string(),
+        &memo,
+    );
+
+    match read_count(engine, &key)? {
+        0 => {
+            warn!(
+                "got an ibc-hooks callback for a transfer that wasn't sent by {}",
+                contract_address
+            );
+            Err(EnclaveError::ValidationFailure)
+        }
+        1 => engine.remove_reserved_key(&key),
+        count => {
+            engine.write_reserved_key(&key, &(count - 1).to_be_bytes());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cosmos_proto::ibc::transfer::tx::MsgTransfer;
+    use protobuf::Message;
+
+    pub fn test_find_callback_transfers() {
+        let contract = HumanAddr("secret1contract".to_string());
+        let callback_memo = r#"{\"ibc_callback\":\"secret1contract\"}"#;
+        let other_memo = r#"{\"ibc_callback\":\"secret1other\"}"#;
+
+        let transfer = |memo: &str| {
+            format!(
+                r#"{{"id":0,"msg":{{"ibc":{{"transfer":{{"channel_id":"channel-0","to_address":"cosmos1receiver","amount":{{"denom":"uscrt","amount":"100"}},"timeout":{{"block":null,"timestamp":"1"}},"memo":"{}"}}}}}},"gas_limit":null,"reply_on":"never"}}"#,
+                memo
+            )
+        };
+        let output = format!(
+            r#"{{"Ok":{{"messages":[{},{}],"attributes":[],"events":[],"data":null}}}}"#,
+            transfer(callback_memo),
+            transfer(other_memo)
+        );
+
+        assert_eq!(
+            find_callback_transfers(output.as_bytes(), &contract),
+            vec![binding_key(
+                "channel-0",
+                "cosmos1receiver",
+                "100",
+                r#"{"ibc_callback":"secret1contract"}"#
+            )]
+        );
+        assert!(find_callback_transfers(b"not json", &contract).is_empty());
+    }
+
+    pub fn test_find_stargate_callback_transfers() {
+        let contract = HumanAddr("secret1contract".to_string());
+        let callback_memo = r#"{"ibc_callback":"secret1contract"}"#;
+
+        let transfer = |memo: &str| {
+            let mut raw_msg = MsgTransfer::new();
+            raw_msg.set_source_port("transfer".to_string());
+            raw_msg.set_source_channel("channel-0".to_string());
+            raw_msg.mut_token().set_denom("uscrt".to_string());
+            raw_msg.mut_token().set_amount("100".to_string());
+            raw_msg.set_sender("secret1k0jntykt7e4g3y88ltc60czgjuqdy4c9e8fzek".to_string());
+            raw_msg.set_receiver("cosmos1receiver".to_string());
+            raw_msg.set_timeout_timestamp(1);
+            raw_msg.set_memo(memo.to_string());
+            format!(
+                r#"{{"id":0,"msg":{{"stargate":{{"type_url":"{}","value":"{}"}}}},"gas_limit":null,"reply_on":"never"}}"#,
+                TYPE_URL_MSG_TRANSFER,
+                base64::encode(raw_msg.write_to_bytes().unwrap())
+            )
+        };
+        let undecodable = format!(
+            r#"{{"id":0,"msg":{{"stargate":{{"type_url":"{}","value":"{}"}}}},"gas_limit":null,"reply_on":"never"}}"#,
+            TYPE_URL_MSG_TRANSFER,
+            base64::encode(b"not a transfer")
+        );
+        let output = format!(
+            r#"{{"Ok":{{"messages":[{},{},{}],"attributes":[],"events":[],"data":null}}}}"#,
+            transfer(callback_memo),
+            transfer(r#"{"ibc_callback":"secret1other"}"#),
+            undecodable
+        );
+
+        // The key must match the one of the packet that the transfer sends
+        assert_eq!(
+            find_callback_transfers(output.as_bytes(), &contract),
+            vec![binding_key(
+                "channel-0",
+                "cosmos1receiver",
+                "100",
+                callback_memo
+            )]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! Keeps a panic in one contract execution from affecting the executions after it.
+//!
+//! Everything an execution writes lives in the `Context` of its engine until the execution
+//! succeeds and it's committed: the storage cache, transient writes, the upgrade policy, etc.
+//! When the execution panics the engine is dropped, and with it everything the execution wrote,
+//! so a panic is isolated by catching it:
+//! - in each host function, since a panic can't unwind through the frames of wasm3, and
+//! - around the call into the contract, for the engine code that runs outside host functions.
+//!
+//! Both convert the panic into `EnclaveError::ContractPanicked`, instead of aborting the ecall.
+//! The locks that executions take on enclave-global state are taken with `lock_or_recover`, or
+//! `read_or_recover` and `write_or_recover` for read-write locks, so a panic while one is held
+//! doesn't make every later execution panic on the poisoned lock and stall the node.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{SgxMutex, SgxMutexGuard, SgxRwLock, SgxRwLockReadGuard, SgxRwLockWriteGuard};
+
+use log::*;
+
+use enclave_ffi_types::EnclaveError;
+use enclave_utils::oom_handler;
+
+use crate::errors::WasmEngineError;
+use crate::execution_trace::trace_span;
+
+pub trait LockOrRecover<T> {
+    /// Locks the mutex, even if an execution panicked while it held it. The state behind the
+    /// locks that executions take is valid after every write, so it's safe to keep using it.
+    fn lock_or_recover(&self) -> SgxMutexGuard<'_, T>;
+}
+
+impl<T> LockOrRecover<T> for SgxMutex<T> {
+    fn lock_or_recover(&self) -> SgxMutexGuard<'_, T> {
+        self.lock().unwrap_or_else(|poisoned| {
+            debug!("taking a lock that was held by an execution that panicked");
+            poisoned.into_inner()
+        })
+    }
+}
+
+pub trait RwLockOrRecover<T> {
+    /// Like `lock_or_recover`, for the read lock
+    fn read_or_recover(&self) -> SgxRwLockReadGuard<'_, T>;
+    /// Like `lock_or_recover`, for the write lock
+    fn write_or_recover(&self) -> SgxRwLockWriteGuard<'_, T>;
+}
+
+impl<T> RwLockOrRecover<T> for SgxRwLock<T> {
+    fn read_or_recover(&self) -> SgxRwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(|poisoned| {
+            debug!("taking a read lock that was held by an execution that panicked");
+            poisoned.into_inner()
+        })
+    }
+
+    fn write_or_recover(&self) -> SgxRwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(|poisoned| {
+            debug!("taking a write lock that was held by an execution that panicked");
+            poisoned.into_inner()
+        })
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Calls the host function `name`, converting a panic into `WasmEngineError::Panic`, which
+/// aborts the execution with `EnclaveError::ContractPanicked`
+pub fn catch_host_panic<R>(
+    name: &str,
+    host_function: impl FnOnce() -> Result<R, WasmEngineError>,
+) -> Result<R, WasmEngineError> {
+    panic::catch_unwind(AssertUnwindSafe(host_function)).unwrap_or_else(|payload| {
+        error!(
+            "host function {} panicked: {}",
+            name,
+            panic_message(payload.as_ref())
+        );
+        Err(WasmEngineError::Panic)
+    })
+}
+
+/// Runs a contract execution, converting a panic into `EnclaveError::ContractPanicked`. Running
+/// out of memory isn't the contract's fault, and is still reported as
+/// `EnclaveError::OutOfMemory` by the ecall.
+pub fn isolate_execution<T>(
+    execution: impl FnOnce() -> Result<T, EnclaveError>,
+) -> Result<T, EnclaveError> {
+    let _span = trace_span("execution");
+    let result = match panic::catch_unwind(AssertUnwindSafe(execution)) {
+        Ok(result) => result,
+        Err(payload) => {
+            if oom_handler::oom_happened() {
+                panic::resume_unwind(payload);
+            }
+            error!(
+                "contract execution panicked: {}",
+                panic_message(payload.as_ref())
+            );
+            Err(EnclaveError::ContractPanicked)
+        }
+    };
+
+    // A host function that ran out of memory aborted the execution like any panic
+    match result {
+        Err(EnclaveError::ContractPanicked) if oom_handler::get_then_clear_oom_happened() => {
+            Err(EnclaveError::OutOfMemory)
+        }
+        result => result,
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_isolate_execution() {
+        assert!(matches!(isolate_execution(|| Ok(1)), Ok(1)));
+        assert!(matches!(
+            isolate_execution::<()>(|| Err(EnclaveError::OutOfGas)),
+            Err(EnclaveError::OutOfGas)
+        ));
+        assert!(matches!(
+            isolate_execution::<()>(|| panic!("contract execution failed")),
+            Err(EnclaveError::ContractPanicked)
+        ));
+
+        // A host function that panicked aborts the execution, which is isolated the same way
+        let host_result = catch_host_panic::<i32>("db_read", || panic!("host function failed"));
+        assert!(matches!(host_result, Err(WasmEngineError::Panic)));
+        assert!(matches!(
+            isolate_execution::<()>(|| Err(EnclaveError::from(WasmEngineError::Panic))),
+            Err(EnclaveError::ContractPanicked)
+        ));
+        assert!(matches!(catch_host_panic("db_read", || Ok(1)), Ok(1)));
+    }
+
+    pub fn test_lock_or_recover() {
+        let mutex = SgxMutex::new(vec![1]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut guard = mutex.lock().unwrap();
+            guard.push(2);
+            panic!("execution failed while holding the lock");
+        }));
+        assert!(result.is_err());
+        assert!(mutex.lock().is_err());
+
+        // The executions after it keep using the state
+        mutex.lock_or_recover().push(3);
+        assert_eq!(*mutex.lock_or_recover(), vec![1, 2, 3]);
+
+        let rw_lock = SgxRwLock::new(vec![1]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut guard = rw_lock.write().unwrap();
+            guard.push(2);
+            panic!("execution failed while holding the write lock");
+        }));
+        assert!(result.is_err());
+        assert!(rw_lock.read().is_err());
+
+        rw_lock.write_or_recover().push(3);
+        assert_eq!(*rw_lock.read_or_recover(), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,202 @@
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_generic::BaseEnv;
+use cw_types_v1::results::{DecryptedReply, ReplyOn};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::reserved_keys;
+use crate::io::RawWasmOutput;
+use crate::wasm3::Engine;
+
+/// Identifies the transaction that replies belong to, so frames that were left behind by
+/// earlier transactions (e.g. because a submessage only asked for a reply on error) are ignored
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TxPosition {
+    pub height: u64,
+    pub tx_index: Option<u32>,
+}
+
+impl TxPosition {
+    pub fn from_env(env: &BaseEnv) -> Self {
+        TxPosition {
+            height: env.0.block.height,
+            tx_index: env.0.transaction.as_ref().map(|tx| tx.index),
+        }
+    }
+}
+
+/// The ids of the submessages that still have to reply to the contract. Each execution of the
+/// contract that emitted submessages with replies pushes a frame with their ids, in the order
+/// they are dispatched, and each reply consumes its id from the innermost frame that has it.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct PendingReplies {
+    tx: TxPosition,
+    frames: Vec<Vec<u64>>,
+    /// Whether there are pending replies in the state, which have to be removed once there are
+    /// no frames left
+    #[serde(skip)]
+    stored: bool,
+}
+
+impl PendingReplies {
+    fn load(engine: &mut Engine, tx: TxPosition) -> Result<Self, EnclaveError> {
+        let stored: Option<PendingReplies> =
+            match engine.read_reserved_key(reserved_keys::PENDING_REPLIES)? {
+                Some(stored) => Some(serde_json::from_slice(&stored).map_err(|err| {
+                    warn!("stored pending replies are malformed: {}", err);
+                    EnclaveError::FailedToDeserialize
+                })?),
+                None => None,
+            };
+
+        Ok(match stored {
+            Some(stored) if stored.tx == tx => PendingReplies {
+                stored: true,
+                ..stored
+            },
+            stored => PendingReplies {
+                tx,
+                frames: vec![],
+                stored: stored.is_some(),
+            },
+        })
+    }
+
+    fn store(&self, engine: &mut Engine) -> Result<(), EnclaveError> {
+        if self.frames.is_empty() {
+            if self.stored {
+                engine.remove_reserved_key(reserved_keys::PENDING_REPLIES)?;
+            }
+            return Ok(());
+        }
+
+        let stored = serde_json::to_vec(self).map_err(|err| {
+            warn!("failed to serialize pending replies: {}", err);
+            EnclaveError::FailedToSerialize
+        })?;
+        engine.write_reserved_key(reserved_keys::PENDING_REPLIES, &stored);
+
+        Ok(())
+    }
+
+    /// Consumes `reply_id` and returns the ids of the sibling submessages that still have to
+    /// reply after it
+    fn take_siblings(&mut self, reply_id: u64) -> Vec<u64> {
+        let frame_index = match self
+            .frames
+            .iter()
+            .rposition(|frame| frame.contains(&reply_id))
+        {
+            Some(frame_index) => frame_index,
+            None => return vec![],
+        };
+
+        // Frames above belong to executions that happened while the submessage was executed,
+        // and which already got all their replies
+        self.frames.truncate(frame_index + 1);
+
+        let frame = &mut self.frames[frame_index];
+        let position = frame.iter().position(|id| *id == reply_id).unwrap_or(0);
+        let siblings = frame.split_off(position + 1);
+        *frame = siblings.clone();
+        if frame.is_empty() {
+            self.frames.pop();
+        }
+
+        siblings
+    }
+}
+
+/// Returns the ids of the submessages in the raw output of a contract that ask for a reply
+fn reply_ids_in_output(output: &[u8]) -> Vec<u64> {
+    let messages = match serde_json::from_slice::<RawWasmOutput>(output) {
+        Ok(RawWasmOutput::OkV1 { ok, .. }) => ok.messages,
+        Ok(RawWasmOutput::OkIBCPacketReceive { ok }) => ok.messages,
+        // v0.10 contracts don't have submessages, and malformed outputs are reported later
+        _ => return vec![],
+    };
+
+    messages
+        .iter()
+        .filter(|sub_msg| sub_msg.reply_on != ReplyOn::Never)
+        .map(|sub_msg| sub_msg.id)
+        .collect()
+}
+
+/// Consumes the reply the contract is about to get, and returns the ids of the sibling
+/// submessages that still have to reply after it, which the contract reads with
+/// `pending_replies`. `reply` is the validated reply message.
+pub fn take_pending_siblings(
+    engine: &mut Engine,
+    tx: TxPosition,
+    reply: &[u8],
+) -> Result<Vec<u64>, EnclaveError> {
+    let reply: DecryptedReply = serde_json::from_slice(reply).map_err(|err| {
+        warn!("failed to parse reply to find its siblings: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let mut pending = PendingReplies::load(engine, tx)?;
+    let siblings = pending.take_siblings(reply.id);
+    pending.store(engine)?;
+
+    Ok(siblings)
+}
+
+/// Records the submessages of the output of the contract that ask for a reply
+pub fn record_pending_replies(
+    engine: &mut Engine,
+    tx: TxPosition,
+    output: &[u8],
+) -> Result<(), EnclaveError> {
+    let reply_ids = reply_ids_in_output(output);
+    if reply_ids.is_empty() {
+        return Ok(());
+    }
+
+    let mut pending = PendingReplies::load(engine, tx)?;
+    pending.frames.push(reply_ids);
+    pending.store(engine)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_take_siblings() {
+        let tx = TxPosition {
+            height: 1,
+            tx_index: Some(0),
+        };
+        let mut pending = PendingReplies {
+            tx,
+            frames: vec![vec![1, 2, 3]],
+            stored: false,
+        };
+
+        assert_eq!(pending.take_siblings(1), vec![2, 3]);
+        // The reply to 2 emitted submessages, and one of them replied
+        pending.frames.push(vec![7, 8]);
+        assert_eq!(pending.take_siblings(7), vec![8]);
+        // 8 only asked for a reply on error and succeeded, so its frame is dropped
+        assert_eq!(pending.take_siblings(2), vec![3]);
+        assert_eq!(pending.frames, vec![vec![3]]);
+        assert_eq!(pending.take_siblings(3), Vec::<u64>::new());
+        assert!(pending.frames.is_empty());
+
+        assert_eq!(pending.take_siblings(5), Vec::<u64>::new());
+    }
+
+    pub fn test_reply_ids_in_output() {
+        let output = br#"{"Ok":{"messages":[
+            {"id":1,"msg":{"bank":{"send":{"to_address":"a","amount":[]}}},"gas_limit":null,"reply_on":"always"},
+            {"id":2,"msg":{"bank":{"send":{"to_address":"a","amount":[]}}},"gas_limit":null,"reply_on":"never"},
+            {"id":3,"msg":{"bank":{"send":{"to_address":"a","amount":[]}}},"gas_limit":null,"reply_on":"error"}
+        ],"attributes":[],"events":[],"data":null}}"#;
+        assert_eq!(reply_ids_in_output(output), vec![1, 3]);
+
+        assert!(reply_ids_in_output(br#"{"Err":{"generic_err":{"msg":"x"}}}"#).is_empty());
+        assert!(reply_ids_in_output(b"not json").is_empty());
+    }
+}
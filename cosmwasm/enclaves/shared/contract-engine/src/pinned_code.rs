@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, SgxRwLock};
+
+use lazy_static::lazy_static;
+use log::*;
+
+use enclave_cosmos_types::types::{calc_contract_hash, ContractCode};
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::{EnclaveError, PINNED_CODE_REFERENCE_PREFIX, PINNED_CODE_REFERENCE_SIZE};
+
+use crate::panic_isolation::RwLockOrRecover;
+
+#[derive(Default)]
+struct PinnedCodes {
+    codes: HashMap<[u8; HASH_SIZE], Arc<Vec<u8>>>,
+    total_bytes: usize,
+}
+
+lazy_static! {
+    /// Pinned code is never evicted, so once a code was pinned successfully the host can keep
+    /// referencing it by its hash until the enclave is restarted.
+    static ref PINNED_CODES: SgxRwLock<PinnedCodes> = SgxRwLock::new(PinnedCodes::default());
+}
+
+/// How many bytes of code the node lets the enclave pin, see `configure_pinned_code`
+static MAX_PINNED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets how many bytes of code can be pinned, as configured by the node. Pinning only saves
+/// copying the code into the enclave, so nodes can size it to the memory of their enclave.
+/// 0 disables pinning.
+pub fn configure_pinned_code(max_pinned_bytes: usize) {
+    MAX_PINNED_BYTES.store(max_pinned_bytes, Ordering::SeqCst);
+}
+
+/// The code of a contract as passed to an execution ecall: either the whole binary or a
+/// reference to code that was pinned earlier
+pub enum ContractBytes<'a> {
+    Passed(&'a [u8]),
+    Pinned([u8; HASH_SIZE], Arc<Vec<u8>>),
+}
+
+impl<'a> ContractBytes<'a> {
+    pub fn contract_code(&self) -> ContractCode<'_> {
+        match self {
+            ContractBytes::Passed(code) => ContractCode::new(code),
+            // The hash was calculated when the code was pinned
+            ContractBytes::Pinned(hash, code) => ContractCode::with_hash(code, *hash),
+        }
+    }
+}
+
+impl<'a> Deref for ContractBytes<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ContractBytes::Passed(code) => code,
+            ContractBytes::Pinned(_, code) => code,
+        }
+    }
+}
+
+/// Returns the code hash in `contract` if it's a reference to pinned code
+fn parse_reference(contract: &[u8]) -> Option<[u8; HASH_SIZE]> {
+    if contract.len() != PINNED_CODE_REFERENCE_SIZE
+        || !contract.starts_with(PINNED_CODE_REFERENCE_PREFIX)
+    {
+        return None;
+    }
+
+    let mut hash = [0u8; HASH_SIZE];
+    hash.copy_from_slice(&contract[PINNED_CODE_REFERENCE_PREFIX.len()..]);
+    Some(hash)
+}
+
+/// Pins the code in the enclave so that later executions can reference it by its hash, and
+/// returns the hash. Pinning the same code again is allowed.
+pub fn pin_contract_code(code: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    if parse_reference(code).is_some() {
+        warn!("tried to pin a reference to pinned code");
+        return Err(EnclaveError::InvalidWasm);
+    }
+
+    let hash = calc_contract_hash(code);
+
+    let mut pinned = PINNED_CODES.write_or_recover();
+    if pinned.codes.contains_key(&hash) {
+        return Ok(hash.to_vec());
+    }
+
+    let max_pinned_bytes = MAX_PINNED_BYTES.load(Ordering::SeqCst);
+    if pinned.total_bytes + code.len() > max_pinned_bytes {
+        warn!(
+            "can't pin code {:x?} of {} bytes, {} of {} bytes are already pinned",
+            hash,
+            code.len(),
+            pinned.total_bytes,
+            max_pinned_bytes
+        );
+        return Err(EnclaveError::MemoryAllocationError);
+    }
+
+    debug!("pinning code {:x?} of {} bytes", hash, code.len());
+    pinned.total_bytes += code.len();
+    pinned.codes.insert(hash, Arc::new(code.to_vec()));
+
+    Ok(hash.to_vec())
+}
+
+/// Resolves the code passed to an execution ecall, which may be a reference to pinned code
+pub fn resolve_contract_code(contract: &[u8]) -> Result<ContractBytes, EnclaveError> {
+    let hash = match parse_reference(contract) {
+        Some(hash) => hash,
+        None => return Ok(ContractBytes::Passed(contract)),
+    };
+
+    match PINNED_CODES.read_or_recover().codes.get(&hash) {
+        Some(code) => Ok(ContractBytes::Pinned(hash, code.clone())),
+        None => {
+            warn!("referenced code {:x?} is not pinned", hash);
+            Err(EnclaveError::CodePinNotFound)
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_resolve_pinned_code() {
+        let code = b"\0asm\x01\x00\x00\x00pinned code test".to_vec();
+
+        let passed = resolve_contract_code(&code).unwrap();
+        assert!(matches!(passed, ContractBytes::Passed(_)));
+
+        configure_pinned_code(0);
+        assert!(pin_contract_code(&code).is_err());
+        configure_pinned_code(code.len());
+
+        let hash = pin_contract_code(&code).unwrap();
+        assert_eq!(pin_contract_code(&code).unwrap(), hash);
+
+        let mut reference = PINNED_CODE_REFERENCE_PREFIX.to_vec();
+        reference.extend_from_slice(&hash);
+        let pinned = resolve_contract_code(&reference).unwrap();
+        assert_eq!(&*pinned, code.as_slice());
+        assert_eq!(pinned.contract_code().hash().to_vec(), hash);
+        assert!(pin_contract_code(&reference).is_err());
+        // The cap was reached
+        assert!(pin_contract_code(b"\0asm\x01\x00\x00\x00other code").is_err());
+
+        let mut unknown = PINNED_CODE_REFERENCE_PREFIX.to_vec();
+        unknown.extend_from_slice(&[0u8; HASH_SIZE]);
+        assert!(resolve_contract_code(&unknown).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! The contract key from before the last migration.
+//!
+//! Transactions that were prepared before a contract was migrated may still carry its previous
+//! contract key for a few blocks. The migration records the key it replaced, after verifying it
+//! against the code hash the contract is migrated from, together with the height of its verified
+//! env. The record is stored in the contract's state under a reserved key, so the host can't
+//! forge it, and the previous key is accepted for `CONTRACT_KEY_GRACE_BLOCKS` blocks after that
+//! height.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_generic::BaseEnv;
+use cw_types_v010::encoding::Binary;
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::{Ctx, EnclaveError};
+use enclave_utils::kv_cache::KvCache;
+
+use crate::contract_validation::ContractKey;
+use crate::cosmwasm_config::reserved_keys;
+use crate::db::{read_from_state, KeyScheme, StateKeys};
+use crate::wasm3::Engine;
+
+/// Amount of blocks after a migration during which the previous contract key is still accepted,
+/// so that transactions that were in flight during the migration don't fail
+pub const CONTRACT_KEY_GRACE_BLOCKS: u64 = 10;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct PreviousContractKey {
+    contract_key: Binary,
+    /// The hash of the code the contract was migrated from, which the key was verified against
+    code_hash: Binary,
+    migration_height: u64,
+}
+
+impl PreviousContractKey {
+    fn accepts(&self, sent_contract_key: &ContractKey, block_height: u64) -> bool {
+        let in_grace_window = block_height >= self.migration_height
+            && block_height - self.migration_height <= CONTRACT_KEY_GRACE_BLOCKS;
+
+        in_grace_window && self.contract_key.as_slice() == sent_contract_key.as_slice()
+    }
+}
+
+/// Records the contract key that a migration replaces. The key must already be verified against
+/// the code hash, and the height is the one of the migration's verified env.
+pub fn record_previous_contract_key(
+    engine: &mut Engine,
+    previous_contract_key: &ContractKey,
+    old_code_hash: &[u8; HASH_SIZE],
+    migration_height: u64,
+) -> Result<(), EnclaveError> {
+    let previous = PreviousContractKey {
+        contract_key: Binary(previous_contract_key.to_vec()),
+        code_hash: Binary(old_code_hash.to_vec()),
+        migration_height,
+    };
+    let serialized = serde_json::to_vec(&previous).map_err(|err| {
+        warn!("failed to serialize the previous contract key: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+    engine.write_reserved_key(reserved_keys::PREVIOUS_CONTRACT_KEY, &serialized);
+
+    Ok(())
+}
+
+fn read_previous_contract_key(
+    context: &Ctx,
+    state_keys: &StateKeys,
+) -> Result<Option<PreviousContractKey>, EnclaveError> {
+    let (stored, _) = read_from_state(
+        reserved_keys::PREVIOUS_CONTRACT_KEY,
+        context,
+        state_keys,
+        false,
+        &mut KvCache::new(),
+        &[],
+        KeyScheme::Siv,
+    )
+    .map_err(EnclaveError::from)?;
+
+    match stored {
+        Some(stored) => serde_json::from_slice(&stored).map(Some).map_err(|err| {
+            warn!("stored previous contract key is malformed: {}", err);
+            EnclaveError::FailedToDeserialize
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Whether the contract key in env is the one that the last migration replaced, and the
+/// migration was recent enough for it to still be accepted
+pub fn is_accepted_previous_contract_key(
+    context: &Ctx,
+    base_env: &BaseEnv,
+    og_contract_key: &ContractKey,
+) -> Result<bool, EnclaveError> {
+    let sent_contract_key = if base_env.was_migrated() {
+        base_env.get_current_contract_key()?
+    } else {
+        *og_contract_key
+    };
+
+    let state_keys = StateKeys::at_height(context, og_contract_key, base_env.0.block.height)?;
+    Ok(match read_previous_contract_key(context, &state_keys)? {
+        Some(previous) => previous.accepts(&sent_contract_key, base_env.0.block.height),
+        None => false,
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_previous_contract_key_grace_window() {
+        let previous_contract_key = [1u8; 64];
+        let previous = PreviousContractKey {
+            contract_key: Binary(previous_contract_key.to_vec()),
+            code_hash: Binary(vec![2u8; HASH_SIZE]),
+            migration_height: 100,
+        };
+
+        // Accepted from the migration until the end of the grace window
+        assert!(previous.accepts(&previous_contract_key, 100));
+        assert!(previous.accepts(&previous_contract_key, 100 + CONTRACT_KEY_GRACE_BLOCKS));
+
+        // Refused before the migration and after the grace window
+        assert!(!previous.accepts(&previous_contract_key, 99));
+        assert!(!previous.accepts(&previous_contract_key, 101 + CONTRACT_KEY_GRACE_BLOCKS));
+
+        // Refused for any other key
+        assert!(!previous.accepts(&[3u8; 64], 100));
+
+        // The record round trips through the state
+        let serialized = serde_json::to_vec(&previous).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<PreviousContractKey>(&serialized).unwrap(),
+            previous
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::Deserialize;
+use sgx_rand::{Rng, SgxRng};
+
+use cw_types_v010::query::{QueryRequest, WasmQuery};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_crypto::{sha_256, HASH_SIZE};
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::query_cache::{MAX_CACHED_BYTES_PER_EXECUTION, SCOPE_PREFIX};
+use crate::panic_isolation::LockOrRecover;
+
+pub type QueryCacheId = [u8; 32];
+
+/// Where a query comes from, as the host flags it in env
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryOrigin {
+    /// A query that the node serves to a client, outside of transactions
+    Node,
+    /// A query that a contract made while it executed
+    Contract,
+}
+
+#[derive(Default)]
+struct QueryCache {
+    answers: HashMap<[u8; HASH_SIZE], Vec<u8>>,
+    cached_bytes: usize,
+}
+
+lazy_static! {
+    /// The caches of executions that are still running, by the ID of their scope
+    static ref QUERY_CACHES: SgxMutex<HashMap<QueryCacheId, QueryCache>> =
+        SgxMutex::new(HashMap::new());
+}
+
+/// The query cache that an execution shares with the queries nested in it, like a router that
+/// queries several pairs which all query the same token.
+///
+/// The chain's state can't change while an execution runs, so the answers stay valid for as long
+/// as the execution that created the scope. Scoping the cache to one execution, instead of e.g.
+/// a block, also means that whether a query hits the cache only depends on that execution, and
+/// not on simulations and queries that the node served, so the gas it saves is deterministic.
+pub struct QueryCacheScope {
+    id: QueryCacheId,
+    block_height: u64,
+    owns_cache: bool,
+}
+
+impl QueryCacheScope {
+    /// Creates the scope of an execution that isn't nested in another one. Before
+    /// `Feature::ExecutionQueryCache` activates the scope has no cache, so nothing is cached in it.
+    pub fn new(block_height: u64) -> Result<Self, EnclaveError> {
+        let mut id = [0u8; 32];
+        let mut rng = SgxRng::new().map_err(|err| {
+            warn!("failed to create a query cache scope: {:?}", err);
+            EnclaveError::InternalError
+        })?;
+        rng.fill_bytes(&mut id);
+
+        if is_active(Feature::ExecutionQueryCache) {
+            QUERY_CACHES
+                .lock()
+                .unwrap()
+                .insert(id, QueryCache::default());
+        }
+
+        Ok(Self {
+            id,
+            block_height,
+            owns_cache: true,
+        })
+    }
+
+    /// Joins the scope of the execution that made a query. If that execution already ended, or
+    /// the scope doesn't exist, the cache is simply empty. Only queries that the host flagged as
+    /// made by a contract may join a scope, see `QueryOrigin`.
+    pub fn join(id: QueryCacheId, block_height: u64) -> Self {
+        Self {
+            id,
+            block_height,
+            owns_cache: false,
+        }
+    }
+
+    /// Returns the cached answer to a query made at `query_depth`
+    pub fn get(&self, query: &[u8], query_depth: u32) -> Option<Vec<u8>> {
+        let key = cache_key(self.block_height, query, query_depth)?;
+        let caches = QUERY_CACHES.lock_or_recover();
+        let answer = caches.get(&self.id)?.answers.get(&key)?;
+
+        trace!("query cache hit for {:?}", String::from_utf8_lossy(query));
+        Some(answer.clone())
+    }
+
+    /// Caches the answer to a query made at `query_depth`, unless the cache is full or the query
+    /// isn't a smart query of a contract
+    pub fn insert(&self, query: &[u8], query_depth: u32, answer: &[u8]) {
+        let key = match cache_key(self.block_height, query, query_depth) {
+            Some(key) => key,
+            None => return,
+        };
+        let mut caches = QUERY_CACHES.lock_or_recover();
+        let cache = match caches.get_mut(&self.id) {
+            Some(cache) => cache,
+            None => return,
+        };
+
+        let cached_bytes = cache.cached_bytes.saturating_add(answer.len());
+        if cached_bytes > MAX_CACHED_BYTES_PER_EXECUTION {
+            trace!("query cache is full, not caching the answer");
+            return;
+        }
+
+        if let Some(replaced) = cache.answers.insert(key, answer.to_vec()) {
+            cache.cached_bytes = cached_bytes - replaced.len();
+        } else {
+            cache.cached_bytes = cached_bytes;
+        }
+    }
+
+    /// Prepends the scope to the message of a nested query, before it's encrypted
+    pub fn prepend_to_query(&self, msg: &mut Vec<u8>) {
+        if !is_active(Feature::ExecutionQueryCache) {
+            return;
+        }
+
+        let mut prefixed = Vec::with_capacity(SCOPE_PREFIX.len() + self.id.len() + msg.len());
+        prefixed.extend_from_slice(SCOPE_PREFIX);
+        prefixed.extend_from_slice(&self.id);
+        prefixed.append(msg);
+        *msg = prefixed;
+    }
+}
+
+impl Drop for QueryCacheScope {
+    fn drop(&mut self) {
+        if self.owns_cache {
+            QUERY_CACHES.lock_or_recover().remove(&self.id);
+        }
+    }
+}
+
+/// Only the smart queries of contracts are cached, by the height, the queried contract, its code
+/// hash and the full plaintext of the query, within the scope of the execution. Queries are also
+/// cached with the depth they were made at, because the same query could hit the recursion limit
+/// if it was made deeper. The parts are length prefixed, so they can't be shifted into each other.
+fn cache_key(block_height: u64, query: &[u8], query_depth: u32) -> Option<[u8; HASH_SIZE]> {
+    let (contract_addr, code_hash, msg) = match serde_json::from_slice(query).ok()? {
+        QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr,
+            callback_code_hash,
+            msg,
+        }) => (contract_addr, callback_code_hash, msg),
+        _ => return None,
+    };
+
+    let mut data = block_height.to_be_bytes().to_vec();
+    data.extend_from_slice(&query_depth.to_be_bytes());
+    for part in &[
+        contract_addr.0.as_bytes(),
+        code_hash.as_bytes(),
+        msg.as_slice(),
+    ] {
+        data.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        data.extend_from_slice(part);
+    }
+    Some(sha_256(&data))
+}
+
+/// Splits the scope that the querying execution prepended to a decrypted query message, if any,
+/// from the rest of the message
+pub fn split_query_cache_scope(msg: &[u8]) -> (Option<QueryCacheId>, &[u8]) {
+    let id_end = SCOPE_PREFIX.len() + std::mem::size_of::<QueryCacheId>();
+    if !msg.starts_with(SCOPE_PREFIX) || msg.len() < id_end {
+        return (None, msg);
+    }
+
+    let id = QueryCacheId::try_from(&msg[SCOPE_PREFIX.len()..id_end]).ok();
+    (id, &msg[id_end..])
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn smart_query(contract: &str, code_hash: &str, msg: &str) -> Vec<u8> {
+        format!(
+            r#"{{"wasm":{{"smart":{{"contract_addr":"{}","callback_code_hash":"{}","msg":"{}"}}}}}}"#,
+            contract,
+            code_hash,
+            base64::encode(msg)
+        )
+        .into_bytes()
+    }
+
+    pub fn test_query_cache_scope() {
+        let query = smart_query("secret1token", "hash", r#"{"balance":{}}"#);
+
+        let scope = QueryCacheScope::new(10).unwrap();
+        let nested = QueryCacheScope::join(scope.id, 10);
+
+        assert_eq!(scope.get(&query, 1), None);
+        nested.insert(&query, 1, b"answer");
+        assert_eq!(scope.get(&query, 1), Some(b"answer".to_vec()));
+        assert_eq!(scope.get(&query, 2), None);
+
+        // Replacing an answer doesn't count it twice
+        nested.insert(&query, 1, b"answer");
+        assert_eq!(
+            QUERY_CACHES.lock().unwrap()[&scope.id].cached_bytes,
+            b"answer".len()
+        );
+
+        // Every part of the query is in the key
+        for other_query in &[
+            smart_query("secret1other", "hash", r#"{"balance":{}}"#),
+            smart_query("secret1token", "other", r#"{"balance":{}}"#),
+            smart_query("secret1token", "hash", r#"{"balance":{"address":"a"}}"#),
+        ] {
+            assert_eq!(scope.get(other_query, 1), None);
+        }
+
+        // And so is the height
+        assert_eq!(QueryCacheScope::join(scope.id, 11).get(&query, 1), None);
+
+        // Only smart queries of contracts are cached
+        let bank_query = br#"{"bank":{"balance":{"address":"a","denom":"uscrt"}}}"#;
+        nested.insert(bank_query, 1, b"answer");
+        assert_eq!(scope.get(bank_query, 1), None);
+        nested.insert(b"not a query", 1, b"answer");
+        assert_eq!(scope.get(b"not a query", 1), None);
+
+        // Other executions don't see the answers
+        let other = QueryCacheScope::new(10).unwrap();
+        assert_eq!(other.get(&query, 1), None);
+
+        let id = scope.id;
+        drop(nested);
+        assert!(QUERY_CACHES.lock().unwrap().contains_key(&id));
+        drop(scope);
+        assert!(!QUERY_CACHES.lock().unwrap().contains_key(&id));
+
+        // Joining a scope that ended doesn't recreate it
+        let late = QueryCacheScope::join(id, 10);
+        late.insert(&query, 1, b"answer");
+        assert_eq!(late.get(&query, 1), None);
+    }
+
+    pub fn test_split_query_cache_scope() {
+        let scope = QueryCacheScope::new(10).unwrap();
+        let mut msg = b"code hash and msg".to_vec();
+        scope.prepend_to_query(&mut msg);
+
+        assert_eq!(
+            split_query_cache_scope(&msg),
+            (Some(scope.id), &b"code hash and msg"[..])
+        );
+        assert_eq!(
+            split_query_cache_scope(b"code hash and msg"),
+            (None, &b"code hash and msg"[..])
+        );
+        assert_eq!(split_query_cache_scope(SCOPE_PREFIX), (None, SCOPE_PREFIX));
+    }
+}
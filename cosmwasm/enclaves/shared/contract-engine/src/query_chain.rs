@@ -4,12 +4,18 @@ use sgx_types::sgx_status_t;
 
 use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError};
 
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
 use enclave_crypto::Ed25519PublicKey;
 use enclave_utils::recursion_depth;
 
 use super::errors::WasmEngineError;
+use crate::custom_query_auth::{authenticate_custom_response, CustomQueryAuth, CustomQueryKey};
 use crate::external::{ecalls, ocalls};
-use crate::types::{IoNonce, SecretMessage};
+use crate::gas::WasmCosts;
+use crate::io::strip_output_padding;
+use crate::oracle_query::{answer_oracle_query, parse_oracle_query, ORACLE_QUERY_GAS};
+use crate::query_cache::QueryCacheScope;
+use crate::types::{EnvelopeVersion, IoNonce, SecretMessage};
 
 use cw_types_v010::{
     encoding::Binary,
@@ -24,6 +30,9 @@ pub fn encrypt_and_query_chain(
     context: &Ctx,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
+    query_cache: &QueryCacheScope,
+    oracle_proofs: &[Binary],
+    custom_query_auth: &CustomQueryAuth,
     gas_used: &mut u64,
     gas_limit: u64,
 ) -> Result<Vec<u8>, WasmEngineError> {
@@ -40,7 +49,58 @@ pub fn encrypt_and_query_chain(
         }
     };
 
-    let is_encrypted = encrypt_query_request(&mut query_struct, nonce, user_public_key)?;
+    if let QueryRequest::Custom(custom) = &query_struct {
+        if let Some(oracle_query) = parse_oracle_query(custom) {
+            // Answered from the proofs in the transaction, without asking the host
+            *gas_used = ORACLE_QUERY_GAS;
+            let answer = answer_oracle_query(&oracle_query, oracle_proofs);
+
+            let answer_as_vec = serde_json::to_vec(&answer).map_err(|err| {
+                debug!("encrypt_and_query_chain() got an error while trying to serialize the oracle answer to bytes: {:?}", err);
+                WasmEngineError::SerializationError
+            })?;
+            return Ok(answer_as_vec);
+        }
+
+        match &custom_query_auth.key {
+            CustomQueryKey::Unsigned => {}
+            CustomQueryKey::Unproven => {
+                return system_error_invalid_request(
+                    query,
+                    "the responder key of custom queries is not proven",
+                );
+            }
+            CustomQueryKey::Registered(key) => {
+                // The responder signs the query as serialized here, which is how the host parses it
+                let signed_request = serde_json::to_vec(custom).map_err(|err| {
+                    debug!("encrypt_and_query_chain() got an error while trying to serialize the custom query {:?}: {:?}", custom, err);
+                    WasmEngineError::SerializationError
+                })?;
+                let custom_query = serde_json::to_vec(&query_struct).map_err(|err| {
+                    debug!("encrypt_and_query_chain() got an error while trying to serialize the query {:?} to pass to x/compute: {:?}", query_struct, err);
+                    WasmEngineError::SerializationError
+                })?;
+
+                let (result, query_used_gas) =
+                    query_chain(context, &custom_query, new_query_depth, gas_limit);
+                *gas_used = query_used_gas;
+                let answer = authenticate_custom_response(
+                    key,
+                    custom_query_auth.block_height,
+                    &signed_request,
+                    &result?,
+                );
+
+                return serde_json::to_vec(&answer).map_err(|err| {
+                    debug!("encrypt_and_query_chain() got an error while trying to serialize the custom answer to bytes: {:?}", err);
+                    WasmEngineError::SerializationError
+                });
+            }
+        }
+    }
+
+    let is_encrypted =
+        encrypt_query_request(&mut query_struct, nonce, user_public_key, query_cache)?;
 
     let encrypted_query = serde_json::to_vec(&query_struct).map_err(|err| {
         // this should never happen
@@ -141,6 +201,11 @@ pub fn encrypt_and_query_chain(
         WasmEngineError::SerializationError
     })?;
 
+    // Errors aren't cached, as they may be caused by the gas limit of this query
+    if let Ok(Ok(_)) = answer {
+        query_cache.insert(query, query_depth, &answer_as_vec);
+    }
+
     Ok(answer_as_vec)
 }
 
@@ -197,6 +262,26 @@ fn query_chain(
     (Ok(value), gas_used)
 }
 
+/// The max gas of a query at `query_depth`, where queries made by a contract that executes in a
+/// transaction are at depth 1. Each level of nesting gets `query_gas_decay_percent` of the level
+/// above it, so a chain of queries can't use much more gas than its first query.
+pub fn query_gas_budget(costs: &WasmCosts, query_depth: u32) -> u64 {
+    let decay_percent = costs.query_gas_decay_percent.min(100) as u64;
+
+    (1..query_depth).fold(costs.query_gas_limit as u64, |budget, _| {
+        budget * decay_percent / 100
+    })
+}
+
+/// The gas limit of a query at `query_depth`, made by a contract that has `remaining_gas` left
+pub fn query_gas_limit(costs: &WasmCosts, query_depth: u32, remaining_gas: u64) -> u64 {
+    if !is_active(Feature::QueryGasBudget) {
+        return remaining_gas;
+    }
+
+    remaining_gas.min(query_gas_budget(costs, query_depth))
+}
+
 /// Check whether the query is allowed to run.
 ///
 /// We make sure that a recursion limit is in place in order to
@@ -260,6 +345,7 @@ fn encrypt_query_request(
     query_struct: &mut QueryRequest,
     nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
+    query_cache: &QueryCacheScope,
 ) -> Result<bool, WasmEngineError> {
     let mut is_encrypted = false;
 
@@ -274,11 +360,13 @@ fn encrypt_query_request(
 
         let mut hash_appended_msg = callback_code_hash.clone().into_bytes();
         hash_appended_msg.extend_from_slice(&msg.0);
+        query_cache.prepend_to_query(&mut hash_appended_msg);
 
         let mut encrypted_msg = SecretMessage {
             msg: hash_appended_msg,
             user_public_key,
             nonce,
+            version: EnvelopeVersion::Unversioned,
         };
         encrypted_msg.encrypt_in_place().map_err(|err| {
             debug!(
@@ -308,6 +396,7 @@ fn decrypt_query_response(
         nonce,
         user_public_key,
         msg: response,
+        version: EnvelopeVersion::Unversioned,
     };
 
     let b64_decrypted = as_secret_msg.decrypt().map_err(|err| {
@@ -319,7 +408,7 @@ fn decrypt_query_response(
         WasmEngineError::DecryptionError
     })?;
 
-    base64::decode(&b64_decrypted).map_err(|err| {
+    base64::decode(strip_output_padding(&b64_decrypted)).map_err(|err| {
         debug!(
             "encrypt_and_query_chain() got an answer, managed to decrypt it, then tried to decode the output from base64 to bytes and failed: {:?}",
             err
@@ -338,6 +427,7 @@ fn decrypt_query_response_error(
         nonce,
         user_public_key,
         msg: error,
+        version: EnvelopeVersion::Unversioned,
     };
 
     error_msg.decrypt().map_err(|err| {
@@ -349,3 +439,34 @@ fn decrypt_query_response_error(
         WasmEngineError::DecryptionError
     })
 }
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_query_gas_budget() {
+        enclave_cosmos_types::feature_activation::set_active_block("secretdev-1", 1);
+
+        let costs = WasmCosts {
+            query_gas_limit: 1_000_000,
+            query_gas_decay_percent: 50,
+            ..WasmCosts::default()
+        };
+
+        assert_eq!(query_gas_budget(&costs, 1), 1_000_000);
+        assert_eq!(query_gas_budget(&costs, 2), 500_000);
+        assert_eq!(query_gas_budget(&costs, 3), 250_000);
+        assert_eq!(query_gas_budget(&costs, 10), 1_953);
+
+        // The remaining gas of the contract still bounds the query
+        assert_eq!(query_gas_limit(&costs, 2, 100), 100);
+        assert_eq!(query_gas_limit(&costs, 2, u64::MAX), 500_000);
+
+        // Nested queries can't get more than their parent
+        let costs = WasmCosts {
+            query_gas_decay_percent: 150,
+            ..costs
+        };
+        assert_eq!(query_gas_budget(&costs, 5), 1_000_000);
+    }
+}
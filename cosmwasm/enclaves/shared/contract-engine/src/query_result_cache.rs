@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use enclave_crypto::{sha_256, HASH_SIZE};
+
+use crate::cosmwasm_config::query_cache::{
+    MAX_CACHED_QUERY_RESULT_BYTES, MAX_CACHED_QUERY_RESULT_SIZE,
+};
+use crate::panic_isolation::LockOrRecover;
+use crate::query_cache::QueryOrigin;
+
+/// An encrypted query result, and the gas the query used when it was executed
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedQueryResult {
+    pub output: Vec<u8>,
+    pub used_gas: u64,
+}
+
+/// The results of the queries that the node served since the last block began.
+///
+/// RPC nodes get the same queries over and over, e.g. from front pages and bots that poll a
+/// contract. A query's result only depends on the contract's code, the state at the height it's
+/// made at and its message, and the result is encrypted for the key and nonce in the message, so
+/// a query with the same ciphertext gets the same encrypted result. It's only cached for queries
+/// that the node serves, not for the queries contracts make, so executions never depend on
+/// which queries the node served.
+#[derive(Default)]
+struct QueryResultCache {
+    results: HashMap<[u8; HASH_SIZE], CachedQueryResult>,
+    cached_bytes: usize,
+}
+
+impl QueryResultCache {
+    fn get(&self, key: &[u8; HASH_SIZE]) -> Option<CachedQueryResult> {
+        self.results.get(key).cloned()
+    }
+
+    /// Caches a result, unless it's too large or the cache is full. The cache isn't evicted
+    /// from, it's cleared when the next block begins.
+    fn insert(&mut self, key: [u8; HASH_SIZE], result: CachedQueryResult) {
+        let size = result.output.len();
+        if size > MAX_CACHED_QUERY_RESULT_SIZE {
+            trace!("query result of {} bytes is too large to cache", size);
+            return;
+        }
+
+        let cached_bytes = self.cached_bytes.saturating_add(size);
+        if cached_bytes > MAX_CACHED_QUERY_RESULT_BYTES {
+            trace!("query result cache is full, not caching the result");
+            return;
+        }
+
+        if let Some(replaced) = self.results.insert(key, result) {
+            self.cached_bytes = cached_bytes - replaced.output.len();
+        } else {
+            self.cached_bytes = cached_bytes;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.results.clear();
+        self.cached_bytes = 0;
+    }
+}
+
+lazy_static! {
+    static ref QUERY_RESULTS: SgxMutex<QueryResultCache> =
+        SgxMutex::new(QueryResultCache::default());
+}
+
+/// Whether a query is answered from, and its result added to, the cache. Only the top-level
+/// queries that the node serves are, never the queries of contracts or any query made while a
+/// transaction is delivered, so that the results of executions can't depend on the cache.
+pub fn is_cacheable_query(query_origin: Option<QueryOrigin>, deliver_tx: bool) -> bool {
+    query_origin == Some(QueryOrigin::Node) && !deliver_tx
+}
+
+/// The key of a query to `contract_address`, running `code_hash`, with the encrypted message
+/// `msg` at `block_height`. The parts are length prefixed, so they can't be shifted into each
+/// other.
+pub fn query_result_key(
+    contract_address: &[u8],
+    code_hash: &[u8],
+    msg: &[u8],
+    block_height: u64,
+) -> [u8; HASH_SIZE] {
+    let mut data = Vec::with_capacity(contract_address.len() + code_hash.len() + msg.len() + 32);
+    for part in &[contract_address, code_hash, msg] {
+        data.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        data.extend_from_slice(part);
+    }
+    data.extend_from_slice(&block_height.to_be_bytes());
+    sha_256(&data)
+}
+
+pub fn get_cached_query_result(key: &[u8; HASH_SIZE]) -> Option<CachedQueryResult> {
+    let result = QUERY_RESULTS.lock_or_recover().get(key);
+    if result.is_some() {
+        trace!("query result cache hit");
+    }
+    result
+}
+
+pub fn cache_query_result(key: [u8; HASH_SIZE], result: CachedQueryResult) {
+    QUERY_RESULTS.lock_or_recover().insert(key, result);
+}
+
+/// Clears the cache when a block begins. The results are keyed by height, so they wouldn't be
+/// served for the queries of the next block anyway, but the cache only has to hold one block.
+pub fn clear_query_result_cache() {
+    QUERY_RESULTS.lock_or_recover().clear();
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn result(output: &[u8]) -> CachedQueryResult {
+        CachedQueryResult {
+            output: output.to_vec(),
+            used_gas: 100,
+        }
+    }
+
+    pub fn test_query_result_key() {
+        let key = query_result_key(b"contract", b"hash", b"msg", 1);
+        assert_eq!(key, query_result_key(b"contract", b"hash", b"msg", 1));
+
+        assert_ne!(key, query_result_key(b"other", b"hash", b"msg", 1));
+        assert_ne!(key, query_result_key(b"contract", b"other", b"msg", 1));
+        assert_ne!(key, query_result_key(b"contract", b"hash", b"other", 1));
+        assert_ne!(key, query_result_key(b"contract", b"hash", b"msg", 2));
+        // Moving bytes from one part to the next changes the key
+        assert_ne!(key, query_result_key(b"contrac", b"thash", b"msg", 1));
+    }
+
+    pub fn test_is_cacheable_query() {
+        assert!(is_cacheable_query(Some(QueryOrigin::Node), false));
+
+        assert!(!is_cacheable_query(Some(QueryOrigin::Node), true));
+        assert!(!is_cacheable_query(Some(QueryOrigin::Contract), false));
+        assert!(!is_cacheable_query(None, false));
+    }
+
+    pub fn test_query_result_cache() {
+        let mut cache = QueryResultCache::default();
+        let key = query_result_key(b"contract", b"hash", b"msg", 1);
+        assert_eq!(cache.get(&key), None);
+
+        cache.insert(key, result(b"output"));
+        assert_eq!(cache.get(&key), Some(result(b"output")));
+
+        // Replacing a result doesn't count it twice
+        cache.insert(key, result(b"output"));
+        assert_eq!(cache.cached_bytes, b"output".len());
+
+        // Results that are too large aren't cached, and neither are results past the limit
+        let large_key = query_result_key(b"contract", b"hash", b"large", 1);
+        cache.insert(
+            large_key,
+            result(&vec![0; MAX_CACHED_QUERY_RESULT_SIZE + 1]),
+        );
+        assert_eq!(cache.get(&large_key), None);
+
+        cache.cached_bytes = MAX_CACHED_QUERY_RESULT_BYTES;
+        let late_key = query_result_key(b"contract", b"hash", b"late", 1);
+        cache.insert(late_key, result(b"output"));
+        assert_eq!(cache.get(&late_key), None);
+
+        cache.clear();
+        assert_eq!(cache.get(&key), None);
+        assert_eq!(cache.cached_bytes, 0);
+    }
+}
@@ -9,6 +9,8 @@ use log::trace;
 
 use std::sync::SgxMutex;
 
+use crate::panic_isolation::LockOrRecover;
+
 #[derive(Default, Clone, Copy, Debug)]
 pub struct MsgCounter {
     pub height: u64,
@@ -21,7 +23,7 @@ lazy_static! {
 
 #[cfg(feature = "random")]
 pub fn derive_random(seed: &Binary, contract_key: &ContractKey, height: u64) -> Binary {
-    let mut counter = MSG_COUNTER.lock().unwrap();
+    let mut counter = MSG_COUNTER.lock_or_recover();
 
     if counter.height != height {
         counter.height = height;
@@ -50,7 +52,7 @@ pub fn derive_random(seed: &Binary, contract_key: &ContractKey, height: u64) ->
 }
 
 pub fn update_msg_counter(height: u64) {
-    let mut counter = MSG_COUNTER.lock().unwrap();
+    let mut counter = MSG_COUNTER.lock_or_recover();
 
     if counter.height != height {
         counter.height = height;
@@ -0,0 +1,180 @@
+use log::*;
+use protobuf::well_known_types::Any;
+use protobuf::wire_format::WireType;
+use protobuf::{CodedInputStream, Message};
+
+use cw_types_v010::encoding::Binary;
+use cw_types_v1::results::{
+    NormalizedReplyData, ReplyDataEncoding, SubMsgResult, NORMALIZED_REPLY_DATA_VERSION,
+};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+
+use crate::sdk_responses::{decode_sdk_response, SdkResponse};
+
+/// `TxMsgData.data` holds the deprecated `MsgData`s and `TxMsgData.msg_responses` holds `Any`s.
+/// `MsgData` has the same fields as `Any`, so both are parsed as `Any`s.
+const TX_MSG_DATA_FIELDS: [u32; 2] = [1, 2];
+
+/// Only responses with a type URL are unwrapped, so raw data that happens to parse as
+/// protobuf is left as is
+fn parse_typed_any(bytes: &[u8]) -> Option<Any> {
+    let any = Any::parse_from_bytes(bytes).ok()?;
+    if any.type_url.starts_with('/') {
+        Some(any)
+    } else {
+        None
+    }
+}
+
+/// Parses a `TxMsgData` and returns its response, if it has exactly one
+fn parse_tx_msg_data(bytes: &[u8]) -> Option<Any> {
+    let mut input = CodedInputStream::from_bytes(bytes);
+    let mut responses = vec![];
+
+    while !input.eof().ok()? {
+        let (field, wire_type) = input.read_tag_unpack().ok()?;
+        if !TX_MSG_DATA_FIELDS.contains(&field) || wire_type != WireType::WireTypeLengthDelimited {
+            return None;
+        }
+        responses.push(parse_typed_any(&input.read_bytes().ok()?)?);
+    }
+
+    // Since SDK v0.46 the response is in both fields
+    responses.dedup_by(|a, b| a.type_url == b.type_url && a.value == b.value);
+    if responses.len() == 1 {
+        responses.pop()
+    } else {
+        None
+    }
+}
+
+fn normalize_response(encoding: ReplyDataEncoding, response: Any) -> NormalizedReplyData {
+    let (data, contract_address) = match decode_sdk_response(&response.type_url, &response.value) {
+        Ok(SdkResponse::InstantiateContract { address, data }) => (Some(data), Some(address)),
+        Ok(SdkResponse::ExecuteContract { data }) | Ok(SdkResponse::MigrateContract { data }) => {
+            (Some(data), None)
+        }
+        _ => (Some(Binary(response.value)), None),
+    };
+
+    NormalizedReplyData {
+        version: NORMALIZED_REPLY_DATA_VERSION,
+        encoding,
+        msg_type: Some(response.type_url),
+        data,
+        contract_address,
+    }
+}
+
+/// Decodes the data of a reply into a structure that doesn't depend on how the SDK encoded it
+pub fn normalize_reply_data(data: Option<&Binary>) -> NormalizedReplyData {
+    let bytes = match data {
+        Some(data) if !data.is_empty() => data.as_slice(),
+        _ => {
+            return NormalizedReplyData {
+                version: NORMALIZED_REPLY_DATA_VERSION,
+                encoding: ReplyDataEncoding::Raw,
+                msg_type: None,
+                data: data.cloned(),
+                contract_address: None,
+            }
+        }
+    };
+
+    if let Some(response) = parse_typed_any(bytes) {
+        return normalize_response(ReplyDataEncoding::Any, response);
+    }
+    if let Some(response) = parse_tx_msg_data(bytes) {
+        return normalize_response(ReplyDataEncoding::TxMsgData, response);
+    }
+
+    trace!("reply data is not an SDK response, passing it as is");
+    NormalizedReplyData {
+        version: NORMALIZED_REPLY_DATA_VERSION,
+        encoding: ReplyDataEncoding::Raw,
+        msg_type: None,
+        data: Some(Binary(bytes.to_vec())),
+        contract_address: None,
+    }
+}
+
+/// Sets the normalized data of a successful reply, before it's passed to the contract
+pub fn set_normalized_reply_data(result: &mut SubMsgResult) {
+    if !is_active(Feature::NormalizedReplyData) {
+        return;
+    }
+
+    if let SubMsgResult::Ok(response) = result {
+        response.normalized_data = Some(normalize_reply_data(response.data.as_ref()));
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cosmos_proto::cosmwasm::msg::MsgExecuteContractResponse;
+
+    fn any(type_url: &str, value: Vec<u8>) -> Any {
+        let mut any = Any::new();
+        any.type_url = type_url.to_string();
+        any.value = value;
+        any
+    }
+
+    pub fn test_normalize_reply_data() {
+        let mut response = MsgExecuteContractResponse::new();
+        response.data = b"contract data".to_vec();
+        let execute_response = any(
+            "/secret.compute.v1beta1.MsgExecuteContractResponse",
+            response.write_to_bytes().unwrap(),
+        );
+
+        let expected = NormalizedReplyData {
+            version: NORMALIZED_REPLY_DATA_VERSION,
+            encoding: ReplyDataEncoding::Any,
+            msg_type: Some("/secret.compute.v1beta1.MsgExecuteContractResponse".to_string()),
+            data: Some(Binary(b"contract data".to_vec())),
+            contract_address: None,
+        };
+
+        let wrapped = Binary(execute_response.write_to_bytes().unwrap());
+        assert_eq!(normalize_reply_data(Some(&wrapped)), expected);
+
+        // A TxMsgData with the response in both of its fields, like since SDK v0.46
+        let mut tx_msg_data = vec![];
+        for field in TX_MSG_DATA_FIELDS.iter() {
+            tx_msg_data.push(((field << 3) | 2) as u8);
+            tx_msg_data.push(wrapped.len() as u8);
+            tx_msg_data.extend_from_slice(wrapped.as_slice());
+        }
+        assert_eq!(
+            normalize_reply_data(Some(&Binary(tx_msg_data))),
+            NormalizedReplyData {
+                encoding: ReplyDataEncoding::TxMsgData,
+                ..expected
+            }
+        );
+
+        // Responses the enclave doesn't decode keep their protobuf encoding
+        let send_response = any("/cosmos.bank.v1beta1.MsgSendResponse", vec![]);
+        let normalized =
+            normalize_reply_data(Some(&Binary(send_response.write_to_bytes().unwrap())));
+        assert_eq!(normalized.encoding, ReplyDataEncoding::Any);
+        assert_eq!(normalized.data, Some(Binary(vec![])));
+
+        // The data of contracts
+        let raw = Binary(br#"{"count":1}"#.to_vec());
+        assert_eq!(
+            normalize_reply_data(Some(&raw)),
+            NormalizedReplyData {
+                version: NORMALIZED_REPLY_DATA_VERSION,
+                encoding: ReplyDataEncoding::Raw,
+                msg_type: None,
+                data: Some(raw.clone()),
+                contract_address: None,
+            }
+        );
+        assert_eq!(normalize_reply_data(None).data, None);
+    }
+}
@@ -1,8 +1,12 @@
+use crate::contract_validation::{reply_payload_section_len, take_reply_payload};
+use crate::io::{parse_typed_error, strip_output_padding};
+use crate::reply_data::set_normalized_reply_data;
 use crate::types::{ParsedMessage, SecretMessage};
 use cw_types_v010::encoding::Binary;
 use cw_types_v1::results::{
     DecryptedReply, Event, Reply, SubMsgResponse, SubMsgResult, REPLY_ENCRYPTION_MAGIC_BYTES,
 };
+use enclave_crypto::SIVEncryptable;
 use enclave_ffi_types::EnclaveError;
 use log::{trace, warn};
 
@@ -39,6 +43,7 @@ fn redact_custom_events(reply: &mut Reply) {
             SubMsgResult::Ok(SubMsgResponse {
                 events,
                 data: r.data.clone(),
+                normalized_data: r.normalized_data.clone(),
             })
         }
         SubMsgResult::Err(_) => reply.result.clone(),
@@ -55,9 +60,12 @@ fn get_data_from_reply(
                 nonce: input_msg.nonce,
                 user_public_key: input_msg.user_public_key,
                 msg: data.as_slice().to_vec(),
+                version: input_msg.version,
             };
 
-            let base64_data = tmp_secret_msg_data.decrypt()?[HEX_ENCODED_HASH_SIZE..].to_vec();
+            let decrypted_data = tmp_secret_msg_data.decrypt()?;
+            let base64_data =
+                strip_output_padding(&decrypted_data[HEX_ENCODED_HASH_SIZE..]).to_vec();
 
             Ok(Some(Binary::from_base64(
                 String::from_utf8(base64_data.clone())
@@ -80,29 +88,30 @@ fn get_data_from_reply(
 fn parse_message_id_of_encrypted_reply(
     input_msg: &SecretMessage,
     parsed_reply: &Reply,
-) -> Result<(u64, Vec<u8>), EnclaveError> {
+) -> Result<(u64, Vec<u8>, Vec<u8>), EnclaveError> {
     let tmp_secret_msg_id = SecretMessage {
         nonce: input_msg.nonce,
         user_public_key: input_msg.user_public_key,
         msg: parsed_reply.id.as_slice().to_vec(),
+        version: input_msg.version,
     };
 
     let mut tmp_decrypted_msg_id = tmp_secret_msg_id.decrypt()?;
 
     let mut data_for_validation: Vec<u8> = tmp_decrypted_msg_id[..HEX_ENCODED_HASH_SIZE].to_vec();
     tmp_decrypted_msg_id = tmp_decrypted_msg_id[HEX_ENCODED_HASH_SIZE..].to_vec();
+    // The payload of the submessage is only for this reply, it isn't part of the caller chain
+    let payload = take_reply_payload(&mut tmp_decrypted_msg_id)?;
     while tmp_decrypted_msg_id.len() >= REPLY_ENCRYPTION_MAGIC_BYTES.len()
         && tmp_decrypted_msg_id[0..(REPLY_ENCRYPTION_MAGIC_BYTES.len())]
             == *REPLY_ENCRYPTION_MAGIC_BYTES
     {
-        data_for_validation.extend_from_slice(
-            &tmp_decrypted_msg_id
-                [0..(REPLY_ENCRYPTION_MAGIC_BYTES.len() + SIZE_OF_U64 + HEX_ENCODED_HASH_SIZE)],
-        );
+        let header_len = REPLY_ENCRYPTION_MAGIC_BYTES.len() + SIZE_OF_U64 + HEX_ENCODED_HASH_SIZE;
+        let header_len =
+            header_len + reply_payload_section_len(&tmp_decrypted_msg_id[header_len..])?;
+        data_for_validation.extend_from_slice(&tmp_decrypted_msg_id[0..header_len]);
 
-        tmp_decrypted_msg_id = tmp_decrypted_msg_id
-            [(REPLY_ENCRYPTION_MAGIC_BYTES.len() + SIZE_OF_U64 + HEX_ENCODED_HASH_SIZE)..]
-            .to_vec();
+        tmp_decrypted_msg_id = tmp_decrypted_msg_id[header_len..].to_vec();
     }
 
     let msg_id = String::from_utf8(tmp_decrypted_msg_id.clone()).map_err(|err| {
@@ -121,17 +130,23 @@ fn parse_message_id_of_encrypted_reply(
         }
     };
 
-    Ok((msg_id_as_num, data_for_validation))
+    Ok((msg_id_as_num, data_for_validation, payload))
 }
 
 fn wrap_results_as_parsed_message(
     input_msg: &SecretMessage,
     id: u64,
+    payload: Vec<u8>,
     result: SubMsgResult,
     reply: &Reply,
     data_for_validation: Vec<u8>,
 ) -> Result<ParsedMessage, EnclaveError> {
-    let decrypted_reply = DecryptedReply { id, result };
+    let decrypted_reply = DecryptedReply {
+        id,
+        typed_error: parse_typed_error(&result),
+        result,
+        payload: Binary(payload),
+    };
 
     let decrypted_reply_as_vec = serde_json::to_vec(&decrypted_reply).map_err(|err| {
         warn!(
@@ -153,11 +168,10 @@ fn wrap_results_as_parsed_message(
         nonce: input_msg.nonce,
         user_public_key: input_msg.user_public_key,
         msg: serialized_encrypted_reply,
+        version: input_msg.version,
     };
 
     Ok(ParsedMessage {
-        should_verify_sig_info: true,
-        should_verify_input: true,
         was_msg_encrypted: true,
         should_encrypt_output: true,
         secret_msg: reply_secret_msg,
@@ -174,15 +188,25 @@ fn parse_encrypted_ok_reply(
     let decrypted_msg_data = get_data_from_reply(input_msg, response.clone())?;
 
     // Now we need to create synthetic SecretMessage to fit the API in "handle"
-    let result = SubMsgResult::Ok(SubMsgResponse {
+    let mut result = SubMsgResult::Ok(SubMsgResponse {
         events: response.events,
         data: decrypted_msg_data,
+        normalized_data: None,
     });
+    set_normalized_reply_data(&mut result);
 
-    let (id, data_for_validation) = parse_message_id_of_encrypted_reply(input_msg, parsed_reply)?;
+    let (id, data_for_validation, payload) =
+        parse_message_id_of_encrypted_reply(input_msg, parsed_reply)?;
     redact_custom_events(parsed_reply);
 
-    wrap_results_as_parsed_message(input_msg, id, result, parsed_reply, data_for_validation)
+    wrap_results_as_parsed_message(
+        input_msg,
+        id,
+        payload,
+        result,
+        parsed_reply,
+        data_for_validation,
+    )
 }
 
 fn parse_encrypted_error_reply(
@@ -190,7 +214,8 @@ fn parse_encrypted_error_reply(
     parsed_reply: &mut Reply,
     response: String,
 ) -> Result<ParsedMessage, EnclaveError> {
-    let (id, data_for_validation) = parse_message_id_of_encrypted_reply(input_msg, parsed_reply)?;
+    let (id, data_for_validation, payload) =
+        parse_message_id_of_encrypted_reply(input_msg, parsed_reply)?;
 
     let secret_msg = SecretMessage {
         nonce: input_msg.nonce,
@@ -202,23 +227,29 @@ fn parse_encrypted_error_reply(
             );
             EnclaveError::FailedToSerialize
         })?,
+        version: input_msg.version,
     };
 
     let decrypted_error = secret_msg.decrypt()?;
+    let decrypted_error = strip_output_padding(&decrypted_error[HEX_ENCODED_HASH_SIZE..]);
 
     // Now we need to create synthetic SecretMessage to fit the API in "handle"
-    let result = SubMsgResult::Err(
-        String::from_utf8(decrypted_error[HEX_ENCODED_HASH_SIZE..].to_vec()).map_err(|err| {
-            warn!(
-                "Failed to parse error as string {:?}: {}",
-                decrypted_error[HEX_ENCODED_HASH_SIZE..].to_vec(),
-                err
-            );
-            EnclaveError::FailedToDeserialize
-        })?,
-    );
-
-    wrap_results_as_parsed_message(input_msg, id, result, parsed_reply, data_for_validation)
+    let result = SubMsgResult::Err(String::from_utf8(decrypted_error.to_vec()).map_err(|err| {
+        warn!(
+            "Failed to parse error as string {:?}: {}",
+            decrypted_error, err
+        );
+        EnclaveError::FailedToDeserialize
+    })?);
+
+    wrap_results_as_parsed_message(
+        input_msg,
+        id,
+        payload,
+        result,
+        parsed_reply,
+        data_for_validation,
+    )
 }
 
 fn parse_encrypted_reply_message(
@@ -233,6 +264,31 @@ fn parse_encrypted_reply_message(
     }
 }
 
+/// Payloads of plaintext replies to messages that were encrypted were encrypted with the ID of
+/// their submessage, see `encrypt_v1_non_result_fields`
+fn decrypt_plaintext_reply_payload(
+    input_msg: &SecretMessage,
+    parsed_reply: &Reply,
+    msg_id: u64,
+) -> Result<Binary, EnclaveError> {
+    if !parsed_reply.was_orig_msg_encrypted || parsed_reply.payload.is_empty() {
+        return Ok(parsed_reply.payload.clone());
+    }
+
+    let payload = input_msg
+        .encryption_key()
+        .decrypt_siv(
+            parsed_reply.payload.as_slice(),
+            Some(&[&msg_id.to_be_bytes()]),
+        )
+        .map_err(|err| {
+            warn!("Failed to decrypt the payload of a reply: {:?}", err);
+            EnclaveError::DecryptionError
+        })?;
+
+    Ok(Binary(payload))
+}
+
 fn parse_plaintext_reply_message(
     input_msg: &SecretMessage,
     parsed_reply: &mut Reply,
@@ -254,10 +310,13 @@ fn parse_plaintext_reply_message(
         }
     };
 
-    let decrypted_reply = DecryptedReply {
+    let mut decrypted_reply = DecryptedReply {
         id: msg_id_as_num,
         result: parsed_reply.result.clone(),
+        payload: decrypt_plaintext_reply_payload(input_msg, parsed_reply, msg_id_as_num)?,
+        typed_error: parse_typed_error(&parsed_reply.result),
     };
+    set_normalized_reply_data(&mut decrypted_reply.result);
 
     redact_custom_events(parsed_reply);
     let serialized_reply: Vec<u8> = serde_json::to_vec(parsed_reply).map_err(|err| {
@@ -272,6 +331,7 @@ fn parse_plaintext_reply_message(
         nonce: input_msg.nonce,
         user_public_key: input_msg.user_public_key,
         msg: serialized_reply,
+        version: input_msg.version,
     };
 
     let serialized_reply: Vec<u8> = serde_json::to_vec(&decrypted_reply).map_err(|err| {
@@ -283,11 +343,6 @@ fn parse_plaintext_reply_message(
     })?;
 
     Ok(ParsedMessage {
-        // There's no msg.sender in reply, therefore we don't need to validate the sender.
-        // It's also not possible to validate with our current design, since contracts
-        // don't know if their output is a reply to another contract, thus can't sign it as such
-        should_verify_sig_info: false,
-        should_verify_input: false,
         was_msg_encrypted: false,
         should_encrypt_output: parsed_reply.was_orig_msg_encrypted,
         secret_msg: reply_secret_msg,
@@ -0,0 +1,435 @@
+//! The encoding of the results that executions return to the node.
+//!
+//! Results used to always be the JSON of a `WasmOutput`, which the node has to parse as a whole to
+//! find out what kind of result it got. From `FFI_VERSION_PROTOBUF_RESULTS` on, results are a
+//! protobuf `WasmOutputEnvelope` (see `secret/compute/v1beta1/wasm_output.proto`) instead: the
+//! kind of the result and the reply info are fields of their own, and only the response of the
+//! contract and the error stay JSON. New fields can be added to the envelope without breaking
+//! nodes that don't know them.
+//!
+//! The node announces the version of the FFI it speaks when it configures the runtime, and
+//! results are encoded for the lower of that version and `FFI_VERSION`.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use log::*;
+use protobuf::wire_format::WireType;
+use protobuf::{CodedInputStream, CodedOutputStream, ProtobufResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cw_types_v010::encoding::Binary;
+use enclave_ffi_types::{EnclaveError, FFI_VERSION, FFI_VERSION_PROTOBUF_RESULTS};
+
+use crate::io::{
+    IBCOpenChannelOutput, IBCOutput, IBCReceiveOutput, QueryOutput, V010WasmOutput, V1WasmOutput,
+    WasmOutput,
+};
+
+/// Nodes that don't announce a version speak the first one, which only knows JSON results
+static NEGOTIATED_FFI_VERSION: AtomicU32 = AtomicU32::new(1);
+
+mod field {
+    pub const KIND: u32 = 1;
+    pub const OK: u32 = 2;
+    pub const ERR: u32 = 3;
+    pub const DATA: u32 = 4;
+    pub const INTERNAL_REPLY_ENCLAVE_SIG: u32 = 5;
+    pub const INTERNAL_MSG_ID: u32 = 6;
+}
+
+/// The values of `WasmOutputEnvelope.Kind`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OutputKind {
+    Unspecified = 0,
+    V010 = 1,
+    V1 = 2,
+    IbcBasic = 3,
+    IbcPacketReceive = 4,
+    IbcOpenChannel = 5,
+    Query = 6,
+}
+
+impl OutputKind {
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(OutputKind::Unspecified),
+            1 => Some(OutputKind::V010),
+            2 => Some(OutputKind::V1),
+            3 => Some(OutputKind::IbcBasic),
+            4 => Some(OutputKind::IbcPacketReceive),
+            5 => Some(OutputKind::IbcOpenChannel),
+            6 => Some(OutputKind::Query),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResultEncoding {
+    Json,
+    Protobuf,
+}
+
+pub fn configure_result_encoding(host_ffi_version: u32) {
+    let version = host_ffi_version.min(FFI_VERSION);
+    debug!(
+        "configuring result encoding: node speaks FFI version {}, using {}",
+        host_ffi_version, version
+    );
+    NEGOTIATED_FFI_VERSION.store(version, Ordering::SeqCst);
+}
+
+pub fn result_encoding() -> ResultEncoding {
+    if NEGOTIATED_FFI_VERSION.load(Ordering::SeqCst) >= FFI_VERSION_PROTOBUF_RESULTS {
+        ResultEncoding::Protobuf
+    } else {
+        ResultEncoding::Json
+    }
+}
+
+/// Serializes a result in the encoding that was negotiated with the node
+pub fn encode_wasm_output(wasm_output: &WasmOutput) -> Result<Vec<u8>, EnclaveError> {
+    match result_encoding() {
+        ResultEncoding::Json => serde_json::to_vec(wasm_output).map_err(|err| {
+            debug!(
+                "got an error while trying to serialize wasm_output into json bytes {:?}: {}",
+                wasm_output, err
+            );
+            EnclaveError::FailedToSerialize
+        }),
+        ResultEncoding::Protobuf => wasm_output_to_envelope(wasm_output),
+    }
+}
+
+/// The parts of a `WasmOutput` that go in the envelope, with the response and the error as JSON
+struct Envelope {
+    kind: OutputKind,
+    ok: Option<Vec<u8>>,
+    err: Option<Vec<u8>>,
+    data: Option<Binary>,
+    internal_reply_enclave_sig: Option<Binary>,
+    internal_msg_id: Option<Binary>,
+}
+
+fn to_json<T: Serialize>(value: &Option<T>) -> Result<Option<Vec<u8>>, EnclaveError> {
+    value
+        .as_ref()
+        .map(|value| {
+            serde_json::to_vec(value).map_err(|err| {
+                warn!("failed to serialize a field of the result: {}", err);
+                EnclaveError::FailedToSerialize
+            })
+        })
+        .transpose()
+}
+
+fn from_json<T: DeserializeOwned>(bytes: &Option<Vec<u8>>) -> Result<Option<T>, EnclaveError> {
+    bytes
+        .as_ref()
+        .map(|bytes| {
+            serde_json::from_slice(bytes).map_err(|err| {
+                warn!(
+                    "failed to deserialize a field of the result envelope: {}",
+                    err
+                );
+                EnclaveError::FailedToDeserialize
+            })
+        })
+        .transpose()
+}
+
+impl Envelope {
+    fn from_output(wasm_output: &WasmOutput) -> Result<Self, EnclaveError> {
+        let mut envelope = Envelope {
+            kind: OutputKind::Unspecified,
+            ok: None,
+            err: None,
+            data: None,
+            internal_reply_enclave_sig: wasm_output.internal_reply_enclave_sig.clone(),
+            internal_msg_id: wasm_output.internal_msg_id.clone(),
+        };
+
+        // `finalize_raw_output` only sets one kind of result
+        if let Some(output) = &wasm_output.v010 {
+            envelope.kind = OutputKind::V010;
+            envelope.ok = to_json(&output.ok)?;
+            envelope.err = to_json(&output.err)?;
+        } else if let Some(output) = &wasm_output.v1 {
+            envelope.kind = OutputKind::V1;
+            envelope.ok = to_json(&output.ok)?;
+            envelope.err = to_json(&output.err)?;
+        } else if let Some(output) = &wasm_output.ibc_basic {
+            envelope.kind = OutputKind::IbcBasic;
+            envelope.ok = to_json(&output.ok)?;
+            envelope.err = to_json(&output.err)?;
+            envelope.data = output.data.clone();
+        } else if let Some(output) = &wasm_output.ibc_packet_receive {
+            envelope.kind = OutputKind::IbcPacketReceive;
+            envelope.ok = to_json(&output.ok)?;
+            envelope.err = to_json(&output.err)?;
+        } else if let Some(output) = &wasm_output.ibc_open_channel {
+            envelope.kind = OutputKind::IbcOpenChannel;
+            envelope.ok = to_json(&output.ok)?;
+            envelope.err = to_json(&output.err)?;
+        } else if let Some(output) = &wasm_output.query {
+            envelope.kind = OutputKind::Query;
+            envelope.ok = to_json(&output.ok)?;
+            envelope.err = to_json(&output.err)?;
+        }
+
+        Ok(envelope)
+    }
+
+    fn into_output(self) -> Result<WasmOutput, EnclaveError> {
+        let mut wasm_output = WasmOutput {
+            internal_reply_enclave_sig: self.internal_reply_enclave_sig,
+            internal_msg_id: self.internal_msg_id,
+            ..WasmOutput::default()
+        };
+
+        match self.kind {
+            OutputKind::Unspecified => {}
+            OutputKind::V010 => {
+                wasm_output.v010 = Some(V010WasmOutput {
+                    ok: from_json(&self.ok)?,
+                    err: from_json(&self.err)?,
+                })
+            }
+            OutputKind::V1 => {
+                wasm_output.v1 = Some(V1WasmOutput {
+                    ok: from_json(&self.ok)?,
+                    err: from_json(&self.err)?,
+                })
+            }
+            OutputKind::IbcBasic => {
+                wasm_output.ibc_basic = Some(IBCOutput {
+                    ok: from_json(&self.ok)?,
+                    err: from_json(&self.err)?,
+                    data: self.data,
+                })
+            }
+            OutputKind::IbcPacketReceive => {
+                wasm_output.ibc_packet_receive = Some(IBCReceiveOutput {
+                    ok: from_json(&self.ok)?,
+                    err: from_json(&self.err)?,
+                })
+            }
+            OutputKind::IbcOpenChannel => {
+                wasm_output.ibc_open_channel = Some(IBCOpenChannelOutput {
+                    ok: from_json(&self.ok)?,
+                    err: from_json(&self.err)?,
+                })
+            }
+            OutputKind::Query => {
+                wasm_output.query = Some(QueryOutput {
+                    ok: from_json(&self.ok)?,
+                    err: from_json(&self.err)?,
+                })
+            }
+        }
+
+        Ok(wasm_output)
+    }
+
+    fn encode(&self) -> ProtobufResult<Vec<u8>> {
+        let mut bytes = vec![];
+        {
+            let mut os = CodedOutputStream::vec(&mut bytes);
+            os.write_uint32(field::KIND, self.kind as u32)?;
+            let fields = [
+                (field::OK, self.ok.as_deref()),
+                (field::ERR, self.err.as_deref()),
+                (field::DATA, self.data.as_ref().map(Binary::as_slice)),
+                (
+                    field::INTERNAL_REPLY_ENCLAVE_SIG,
+                    self.internal_reply_enclave_sig
+                        .as_ref()
+                        .map(Binary::as_slice),
+                ),
+                (
+                    field::INTERNAL_MSG_ID,
+                    self.internal_msg_id.as_ref().map(Binary::as_slice),
+                ),
+            ];
+            for (number, value) in fields.iter() {
+                if let Some(value) = value {
+                    os.write_bytes(*number, value)?;
+                }
+            }
+            os.flush()?;
+        }
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> ProtobufResult<Option<Self>> {
+        let mut envelope = Envelope {
+            kind: OutputKind::Unspecified,
+            ok: None,
+            err: None,
+            data: None,
+            internal_reply_enclave_sig: None,
+            internal_msg_id: None,
+        };
+
+        let mut is = CodedInputStream::from_bytes(bytes);
+        while !is.eof()? {
+            let (number, wire_type) = is.read_tag_unpack()?;
+            match (number, wire_type) {
+                (field::KIND, WireType::WireTypeVarint) => {
+                    envelope.kind = match OutputKind::from_u32(is.read_uint32()?) {
+                        Some(kind) => kind,
+                        None => return Ok(None),
+                    }
+                }
+                (field::OK, WireType::WireTypeLengthDelimited) => {
+                    envelope.ok = Some(is.read_bytes()?)
+                }
+                (field::ERR, WireType::WireTypeLengthDelimited) => {
+                    envelope.err = Some(is.read_bytes()?)
+                }
+                (field::DATA, WireType::WireTypeLengthDelimited) => {
+                    envelope.data = Some(Binary(is.read_bytes()?))
+                }
+                (field::INTERNAL_REPLY_ENCLAVE_SIG, WireType::WireTypeLengthDelimited) => {
+                    envelope.internal_reply_enclave_sig = Some(Binary(is.read_bytes()?))
+                }
+                (field::INTERNAL_MSG_ID, WireType::WireTypeLengthDelimited) => {
+                    envelope.internal_msg_id = Some(Binary(is.read_bytes()?))
+                }
+                // Fields of later versions
+                (_, wire_type) => is.skip_field(wire_type)?,
+            }
+        }
+
+        Ok(Some(envelope))
+    }
+}
+
+/// Converts a result to the protobuf `WasmOutputEnvelope`
+pub fn wasm_output_to_envelope(wasm_output: &WasmOutput) -> Result<Vec<u8>, EnclaveError> {
+    Envelope::from_output(wasm_output)?.encode().map_err(|err| {
+        warn!("failed to encode the result envelope: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Converts a protobuf `WasmOutputEnvelope` back to a result
+pub fn wasm_output_from_envelope(envelope: &[u8]) -> Result<WasmOutput, EnclaveError> {
+    match Envelope::decode(envelope) {
+        Ok(Some(envelope)) => envelope.into_output(),
+        Ok(None) => {
+            warn!("the result envelope has an unknown kind");
+            Err(EnclaveError::FailedToDeserialize)
+        }
+        Err(err) => {
+            warn!("failed to decode the result envelope: {}", err);
+            Err(EnclaveError::FailedToDeserialize)
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn round_trip(wasm_output: WasmOutput) {
+        let envelope = wasm_output_to_envelope(&wasm_output).unwrap();
+        assert_eq!(wasm_output_from_envelope(&envelope).unwrap(), wasm_output);
+    }
+
+    pub fn test_result_envelope_round_trip() {
+        round_trip(WasmOutput {
+            v1: Some(V1WasmOutput {
+                ok: Some(
+                    serde_json::from_str(
+                        r#"{"messages":[],"attributes":[{"key":"a","value":"1"}],"events":[],"data":"ZGF0YQ=="}"#,
+                    )
+                    .unwrap(),
+                ),
+                err: None,
+            }),
+            internal_reply_enclave_sig: Some(Binary(vec![1u8; 32])),
+            internal_msg_id: Some(Binary(vec![2u8; 8])),
+            ..WasmOutput::default()
+        });
+        round_trip(WasmOutput {
+            v010: Some(V010WasmOutput {
+                ok: None,
+                err: Some(json!({"generic_err":{"msg":"failed"}})),
+            }),
+            ..WasmOutput::default()
+        });
+        round_trip(WasmOutput {
+            ibc_basic: Some(IBCOutput {
+                ok: None,
+                err: None,
+                data: Some(Binary(b"data".to_vec())),
+            }),
+            ..WasmOutput::default()
+        });
+        round_trip(WasmOutput {
+            ibc_open_channel: Some(IBCOpenChannelOutput {
+                ok: Some("ics20-1".to_string()),
+                err: None,
+            }),
+            ..WasmOutput::default()
+        });
+        round_trip(WasmOutput {
+            query: Some(QueryOutput {
+                ok: Some("eyJiYWxhbmNlIjoiMTAifQ==".to_string()),
+                err: None,
+            }),
+            ..WasmOutput::default()
+        });
+        round_trip(WasmOutput::default());
+
+        // Fields that a later version adds are skipped
+        let mut envelope = wasm_output_to_envelope(&WasmOutput {
+            query: Some(QueryOutput {
+                ok: None,
+                err: Some(json!("failed")),
+            }),
+            ..WasmOutput::default()
+        })
+        .unwrap();
+        envelope.extend_from_slice(&[(15 << 3) | 2, 1, 0xff]);
+        assert_eq!(
+            wasm_output_from_envelope(&envelope)
+                .unwrap()
+                .query
+                .unwrap()
+                .err,
+            Some(json!("failed"))
+        );
+
+        assert!(wasm_output_from_envelope(&[(field::KIND << 3) as u8, 100]).is_err());
+    }
+
+    pub fn test_negotiate_result_encoding() {
+        configure_result_encoding(1);
+        assert_eq!(result_encoding(), ResultEncoding::Json);
+        // A node of a later version gets the latest encoding this enclave knows
+        configure_result_encoding(FFI_VERSION + 1);
+        assert_eq!(result_encoding(), ResultEncoding::Protobuf);
+
+        let wasm_output = WasmOutput {
+            query: Some(QueryOutput {
+                ok: Some("b2s=".to_string()),
+                err: None,
+            }),
+            ..WasmOutput::default()
+        };
+        let encoded = encode_wasm_output(&wasm_output).unwrap();
+        assert_eq!(wasm_output_from_envelope(&encoded).unwrap(), wasm_output);
+
+        configure_result_encoding(1);
+        let encoded = encode_wasm_output(&wasm_output).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<WasmOutput>(&encoded).unwrap(),
+            wasm_output
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use log::*;
+use protobuf::well_known_types::Any;
+use protobuf::Message;
+use serde::Serialize;
+
+use cosmos_proto::cosmwasm::msg::{
+    MsgClearAdminResponse, MsgExecuteContractResponse, MsgInstantiateContractResponse,
+    MsgMigrateContractResponse, MsgStoreCodeResponse, MsgUpdateAdminResponse,
+};
+use cw_types_v010::encoding::Binary;
+
+/// The errors `decode_sdk_response` and `decode_any` return in the high half of their results
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u32)]
+pub enum SdkResponseError {
+    InvalidAny = 1,
+    /// The type isn't one of the responses the enclave decodes
+    UnsupportedType = 2,
+    InvalidResponse = 3,
+}
+
+/// The responses of SDK messages that contracts can decode from the data of their replies,
+/// serialized to JSON for the contract
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SdkResponse {
+    StoreCode { code_id: u64 },
+    InstantiateContract { address: String, data: Binary },
+    ExecuteContract { data: Binary },
+    MigrateContract { data: Binary },
+    UpdateAdmin {},
+    ClearAdmin {},
+}
+
+fn parse<M: Message>(value: &[u8]) -> Result<M, SdkResponseError> {
+    M::parse_from_bytes(value).map_err(|err| {
+        debug!("failed to parse an SDK response: {:?}", err);
+        SdkResponseError::InvalidResponse
+    })
+}
+
+/// Decodes the response of an SDK message, e.g. the data of the reply to a
+/// `MsgInstantiateContract` submessage
+pub fn decode_sdk_response(type_url: &str, value: &[u8]) -> Result<SdkResponse, SdkResponseError> {
+    let response = match type_url.trim_start_matches('/') {
+        "secret.compute.v1beta1.MsgStoreCodeResponse" => {
+            let response: MsgStoreCodeResponse = parse(value)?;
+            SdkResponse::StoreCode {
+                code_id: response.code_id,
+            }
+        }
+        "secret.compute.v1beta1.MsgInstantiateContractResponse" => {
+            let response: MsgInstantiateContractResponse = parse(value)?;
+            SdkResponse::InstantiateContract {
+                address: response.address,
+                data: Binary(response.data),
+            }
+        }
+        "secret.compute.v1beta1.MsgExecuteContractResponse" => {
+            let response: MsgExecuteContractResponse = parse(value)?;
+            SdkResponse::ExecuteContract {
+                data: Binary(response.data),
+            }
+        }
+        "secret.compute.v1beta1.MsgMigrateContractResponse" => {
+            let response: MsgMigrateContractResponse = parse(value)?;
+            SdkResponse::MigrateContract {
+                data: Binary(response.data),
+            }
+        }
+        "secret.compute.v1beta1.MsgUpdateAdminResponse" => {
+            parse::<MsgUpdateAdminResponse>(value)?;
+            SdkResponse::UpdateAdmin {}
+        }
+        "secret.compute.v1beta1.MsgClearAdminResponse" => {
+            parse::<MsgClearAdminResponse>(value)?;
+            SdkResponse::ClearAdmin {}
+        }
+        _ => {
+            debug!("can't decode SDK responses of type {}", type_url);
+            return Err(SdkResponseError::UnsupportedType);
+        }
+    };
+
+    Ok(response)
+}
+
+/// Decodes an SDK response that is wrapped in an `Any`, like the responses of the messages of a
+/// transaction are
+pub fn decode_any(any: &[u8]) -> Result<SdkResponse, SdkResponseError> {
+    let any = Any::parse_from_bytes(any).map_err(|err| {
+        debug!("failed to parse an Any: {:?}", err);
+        SdkResponseError::InvalidAny
+    })?;
+
+    decode_sdk_response(&any.type_url, &any.value)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_decode_sdk_responses() {
+        let mut response = MsgInstantiateContractResponse::new();
+        response.address = "secret1contract".to_string();
+        response.data = b"data".to_vec();
+        let value = response.write_to_bytes().unwrap();
+
+        let expected = SdkResponse::InstantiateContract {
+            address: "secret1contract".to_string(),
+            data: Binary(b"data".to_vec()),
+        };
+        assert_eq!(
+            decode_sdk_response(
+                "/secret.compute.v1beta1.MsgInstantiateContractResponse",
+                &value
+            ),
+            Ok(expected)
+        );
+        assert_eq!(
+            serde_json::to_string(
+                &decode_sdk_response(
+                    "secret.compute.v1beta1.MsgInstantiateContractResponse",
+                    &value
+                )
+                .unwrap()
+            )
+            .unwrap(),
+            r#"{"instantiate_contract":{"address":"secret1contract","data":"ZGF0YQ=="}}"#
+        );
+
+        let mut any = Any::new();
+        any.type_url = "/secret.compute.v1beta1.MsgInstantiateContractResponse".to_string();
+        any.value = value;
+        assert!(decode_any(&any.write_to_bytes().unwrap()).is_ok());
+
+        assert_eq!(
+            decode_sdk_response("/cosmos.bank.v1beta1.MsgSendResponse", &[]),
+            Err(SdkResponseError::UnsupportedType)
+        );
+        assert_eq!(
+            decode_sdk_response(
+                "/secret.compute.v1beta1.MsgExecuteContractResponse",
+                &[0x0a, 0x05]
+            ),
+            Err(SdkResponseError::InvalidResponse)
+        );
+        assert_eq!(decode_any(&[0xff]), Err(SdkResponseError::InvalidAny));
+    }
+}
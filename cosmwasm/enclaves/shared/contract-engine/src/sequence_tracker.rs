@@ -0,0 +1,153 @@
+//! Heuristic detection of replays of old signed transactions.
+//!
+//! The sequence of an account increases with every transaction it signs, so a transaction that
+//! the enclave sees with a lower sequence than a transaction of the same sender that it already
+//! saw may have been replayed by the host. The enclave remembers the last sequence it saw of each
+//! sender for as long as it keeps their block among the verified headers, and flags regressions
+//! in the logs and metrics.
+//!
+//! This is only a signal for operators, executions are never refused because of it. Simulations
+//! run through the enclave too, so a simulation of a newer transaction can make a pending one
+//! look like a regression.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use block_verifier::VERIFIED_HEADERS;
+use cw_types_v010::types::CanonicalAddr;
+
+use crate::metrics::record_sequence_regression;
+use crate::panic_isolation::LockOrRecover;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SequenceObservation {
+    /// The sender wasn't seen in the window
+    First,
+    /// The sequence is the same as the last one of the sender, like in the messages of one
+    /// transaction, or higher
+    InOrder,
+    /// The sequence is lower than the last one of the sender
+    Regressed { last_seen: u64 },
+}
+
+#[derive(Default, Debug)]
+pub struct AccountSequences {
+    /// The last sequence of each sender, and the block it was seen in
+    last_seen: HashMap<Vec<u8>, (u64, u64)>,
+    by_height: BTreeMap<u64, Vec<Vec<u8>>>,
+}
+
+impl AccountSequences {
+    pub fn observe(&mut self, account: &[u8], sequence: u64, height: u64) -> SequenceObservation {
+        let observation = match self.last_seen.get(account) {
+            None => SequenceObservation::First,
+            Some((last_seen, _)) if sequence < *last_seen => {
+                return SequenceObservation::Regressed {
+                    last_seen: *last_seen,
+                }
+            }
+            Some(_) => SequenceObservation::InOrder,
+        };
+
+        self.last_seen.insert(account.to_vec(), (sequence, height));
+        self.by_height
+            .entry(height)
+            .or_default()
+            .push(account.to_vec());
+        observation
+    }
+
+    /// Forgets the senders that weren't seen since `oldest_height`
+    pub fn prune(&mut self, oldest_height: u64) {
+        let kept = self.by_height.split_off(&oldest_height);
+        for (height, accounts) in std::mem::replace(&mut self.by_height, kept) {
+            for account in accounts {
+                // Senders that were seen again later are still in the window
+                if matches!(self.last_seen.get(&account), Some((_, seen_at)) if *seen_at == height)
+                {
+                    self.last_seen.remove(&account);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.last_seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_seen.is_empty()
+    }
+}
+
+lazy_static! {
+    static ref ACCOUNT_SEQUENCES: SgxMutex<AccountSequences> =
+        SgxMutex::new(AccountSequences::default());
+}
+
+/// Records the sequence that the sender of a verified transaction signed, and flags it if it's
+/// lower than a sequence of the sender that was already seen in the window
+pub fn observe_signed_sequence(sender: &CanonicalAddr, sequence: u64, block_height: u64) {
+    let oldest_height = VERIFIED_HEADERS.lock_or_recover().oldest_height();
+
+    let mut sequences = ACCOUNT_SEQUENCES.lock_or_recover();
+    if let Some(oldest_height) = oldest_height {
+        sequences.prune(oldest_height);
+    }
+
+    if let SequenceObservation::Regressed { last_seen } =
+        sequences.observe(sender.as_slice(), sequence, block_height)
+    {
+        warn!(
+            "sender {:?} signed sequence {} at height {} after sequence {}, the transaction may have been replayed",
+            sender, sequence, block_height, last_seen
+        );
+        record_sequence_regression();
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_account_sequences() {
+        let mut sequences = AccountSequences::default();
+
+        assert_eq!(
+            sequences.observe(b"alice", 5, 10),
+            SequenceObservation::First
+        );
+        // The messages of one transaction share its sequence
+        assert_eq!(
+            sequences.observe(b"alice", 5, 10),
+            SequenceObservation::InOrder
+        );
+        assert_eq!(
+            sequences.observe(b"alice", 7, 11),
+            SequenceObservation::InOrder
+        );
+        assert_eq!(
+            sequences.observe(b"alice", 6, 12),
+            SequenceObservation::Regressed { last_seen: 7 }
+        );
+        assert_eq!(sequences.observe(b"bob", 1, 12), SequenceObservation::First);
+        assert_eq!(sequences.len(), 2);
+
+        // alice was seen again at height 11, so it stays in the window
+        sequences.prune(11);
+        assert_eq!(
+            sequences.observe(b"alice", 6, 13),
+            SequenceObservation::Regressed { last_seen: 7 }
+        );
+
+        sequences.prune(13);
+        assert!(sequences.is_empty());
+        assert_eq!(
+            sequences.observe(b"alice", 1, 13),
+            SequenceObservation::First
+        );
+    }
+}
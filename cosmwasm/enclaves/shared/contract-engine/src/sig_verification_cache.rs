@@ -0,0 +1,163 @@
+//! Caches the signatures of transactions that were verified in the current block.
+//!
+//! Every message of a transaction that executes a contract is verified on its own, with the same
+//! `SigInfo`, so without the cache a transaction with many messages verifies the same signatures
+//! and parses the same `tx_bytes` once per message.
+//!
+//! Entries are keyed by the hash of the transaction and the signer, and hold a digest of the rest
+//! of the `SigInfo`, so that a host can't reuse a verified transaction with other sign bytes or
+//! signatures. The cache only holds the entries of one block.
+
+use std::collections::HashMap;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+
+use cw_types_v010::types::CanonicalAddr;
+use enclave_cosmos_types::types::SigInfo;
+use enclave_crypto::{sha_256, HASH_SIZE};
+
+use crate::panic_isolation::LockOrRecover;
+
+/// Bounds the memory of the cache. Signatures that don't fit are verified every time.
+const MAX_CACHED_SIGNATURES: usize = 1024;
+
+/// The hash of the transaction and the signer
+type CacheKey = ([u8; HASH_SIZE], Vec<u8>);
+
+#[derive(Default, Debug)]
+pub struct SigVerificationCache {
+    height: u64,
+    verified: HashMap<CacheKey, [u8; HASH_SIZE]>,
+}
+
+impl SigVerificationCache {
+    pub fn contains(&self, height: u64, key: &CacheKey, digest: &[u8; HASH_SIZE]) -> bool {
+        height == self.height && self.verified.get(key) == Some(digest)
+    }
+
+    /// Entries of earlier blocks are dropped when the first entry of a new block is inserted
+    pub fn insert(&mut self, height: u64, key: CacheKey, digest: [u8; HASH_SIZE]) {
+        if height != self.height {
+            self.height = height;
+            self.verified.clear();
+        }
+
+        if self.verified.len() >= MAX_CACHED_SIGNATURES && !self.verified.contains_key(&key) {
+            trace!("signature verification cache is full");
+            return;
+        }
+        self.verified.insert(key, digest);
+    }
+
+    pub fn len(&self) -> usize {
+        self.verified.len()
+    }
+}
+
+lazy_static! {
+    static ref SIG_VERIFICATION_CACHE: SgxMutex<SigVerificationCache> =
+        SgxMutex::new(SigVerificationCache::default());
+}
+
+fn cache_key(sig_info: &SigInfo, sender: &CanonicalAddr) -> CacheKey {
+    (
+        sha_256(sig_info.tx_bytes.as_slice()),
+        sender.as_slice().to_vec(),
+    )
+}
+
+/// A digest of everything that the signatures were verified with, other than the transaction
+fn sig_info_digest(sig_info: &SigInfo) -> [u8; HASH_SIZE] {
+    let mut data = vec![];
+    let mut push = |field: &[u8]| {
+        data.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        data.extend_from_slice(field);
+    };
+
+    push(sig_info.sign_bytes.as_slice());
+    push(&(sig_info.sign_mode as i32).to_be_bytes());
+    push(sig_info.mode_info.as_slice());
+    push(sig_info.public_key.as_slice());
+    push(sig_info.signature.as_slice());
+    for signer in &sig_info.other_signers {
+        push(signer.sign_bytes.as_slice());
+        push(&(signer.sign_mode as i32).to_be_bytes());
+        push(signer.public_key.as_slice());
+        push(signer.signature.as_slice());
+    }
+
+    sha_256(&data)
+}
+
+/// Whether the signatures of this `SigInfo` were already verified for the sender in this block
+pub fn is_signature_verified(
+    sig_info: &SigInfo,
+    sender: &CanonicalAddr,
+    block_height: u64,
+) -> bool {
+    SIG_VERIFICATION_CACHE.lock_or_recover().contains(
+        block_height,
+        &cache_key(sig_info, sender),
+        &sig_info_digest(sig_info),
+    )
+}
+
+/// Records that the signatures of this `SigInfo` were verified for the sender
+pub fn record_verified_signature(sig_info: &SigInfo, sender: &CanonicalAddr, block_height: u64) {
+    SIG_VERIFICATION_CACHE.lock_or_recover().insert(
+        block_height,
+        cache_key(sig_info, sender),
+        sig_info_digest(sig_info),
+    );
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cosmos_proto::tx::signing::SignMode;
+    use cw_types_v010::encoding::Binary;
+
+    fn sig_info(signature: &[u8]) -> SigInfo {
+        SigInfo {
+            tx_bytes: Binary(b"tx".to_vec()),
+            sign_bytes: Binary(b"sign bytes".to_vec()),
+            sign_mode: SignMode::SIGN_MODE_DIRECT,
+            mode_info: Binary(vec![]),
+            public_key: Binary(b"public key".to_vec()),
+            signature: Binary(signature.to_vec()),
+            callback_sig: None,
+            other_signers: vec![],
+        }
+    }
+
+    pub fn test_sig_verification_cache() {
+        let mut cache = SigVerificationCache::default();
+        let sender = CanonicalAddr(Binary(vec![1u8; 20]));
+        let other_sender = CanonicalAddr(Binary(vec![2u8; 20]));
+        let verified = sig_info(b"signature");
+
+        let key = cache_key(&verified, &sender);
+        let digest = sig_info_digest(&verified);
+        assert!(!cache.contains(10, &key, &digest));
+        cache.insert(10, key.clone(), digest);
+        assert!(cache.contains(10, &key, &digest));
+
+        // The same transaction with another signature or sender wasn't verified
+        assert!(!cache.contains(10, &key, &sig_info_digest(&sig_info(b"forged"))));
+        assert!(!cache.contains(10, &cache_key(&verified, &other_sender), &digest));
+
+        // Entries only last for their block
+        assert!(!cache.contains(11, &key, &digest));
+        cache.insert(11, cache_key(&verified, &other_sender), digest);
+        assert!(!cache.contains(10, &key, &digest));
+        assert_eq!(cache.len(), 1);
+
+        for i in 0..MAX_CACHED_SIGNATURES * 2 {
+            cache.insert(11, ([0u8; HASH_SIZE], i.to_be_bytes().to_vec()), digest);
+        }
+        assert_eq!(cache.len(), MAX_CACHED_SIGNATURES);
+    }
+}
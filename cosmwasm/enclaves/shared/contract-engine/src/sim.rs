@@ -0,0 +1,427 @@
+//! A simulated host, for running the engine on machines without SGX.
+//!
+//! With the `sim` feature, this module defines the ocalls of the enclave itself, instead of the
+//! host, and answers them from an in-memory store that is reached through the `Ctx` of the call.
+//! Doctests and examples can then drive the storage and query layers under `cargo test`:
+//!
+//! ```
+//! use enclave_contract_engine::sim::{ocall_read_db, ocall_write_db, SimHost};
+//! use enclave_ffi_types::{EnclaveBuffer, OcallReturn, UntrustedVmError};
+//! use sgx_types::sgx_status_t;
+//!
+//! let host = SimHost::default();
+//! let mut ocall_return = OcallReturn::Panic;
+//! let mut gas_used = 0;
+//! let mut replaced_size = 0;
+//!
+//! let (key, value) = (b"key", b"value");
+//! let status = unsafe {
+//!     ocall_write_db(
+//!         &mut ocall_return,
+//!         host.ctx(),
+//!         &mut UntrustedVmError::default(),
+//!         &mut gas_used,
+//!         &mut replaced_size,
+//!         key.as_ptr(),
+//!         key.len(),
+//!         value.as_ptr(),
+//!         value.len(),
+//!     )
+//! };
+//! assert_eq!(status, sgx_status_t::SGX_SUCCESS);
+//! assert_eq!(host.get(key), Some(value.to_vec()));
+//! // There was no entry to replace
+//! assert_eq!(replaced_size, 0);
+//!
+//! let mut buffer = EnclaveBuffer::default();
+//! unsafe {
+//!     ocall_read_db(
+//!         &mut ocall_return,
+//!         host.ctx(),
+//!         &mut UntrustedVmError::default(),
+//!         &mut gas_used,
+//!         &mut buffer,
+//!         key.as_ptr(),
+//!         key.len(),
+//!     )
+//! };
+//! assert!(!buffer.ptr.is_null());
+//! ```
+//!
+//! ## Executing contracts
+//!
+//! `init`, `handle` and `query` run against the same host. They also need the consensus keys,
+//! which the enclave unseals from the node's data directory, so the examples below only run on a
+//! machine whose sealed seeds the SGX SDK can read in simulation mode. The messages are
+//! encrypted for the network by a client, like the ones of transactions.
+//!
+//! ```no_run
+//! use enclave_contract_engine::sim::SimHost;
+//! use enclave_contract_engine::{handle, init, query};
+//!
+//! let host = SimHost::with_responder(|_query| None);
+//! let contract = std::fs::read("contract.wasm").unwrap();
+//! let code_hash = hex::encode(enclave_crypto::sha_256(&contract));
+//!
+//! let env = |contract_key: Option<String>| {
+//!     serde_json::json!({
+//!         "block": { "height": 1, "time": 1_000_000_000u64, "chain_id": "sim-1" },
+//!         "message": { "sender": "secret1sender", "sent_funds": [] },
+//!         "contract": { "address": "secret1contract" },
+//!         "contract_key": contract_key.map(|key| serde_json::json!({ "og_contract_key": key })),
+//!         "contract_code_hash": code_hash,
+//!     })
+//!     .to_string()
+//! };
+//! // Messages sent by contracts, e.g. in tests of submessages, are verified with the callback
+//! // signature instead of the signatures of a transaction
+//! let sig_info = |callback_sig: &[u8]| {
+//!     serde_json::json!({
+//!         "tx_bytes": "", "sign_bytes": "", "sign_mode": "SIGN_MODE_UNSPECIFIED",
+//!         "mode_info": "", "public_key": "", "signature": "",
+//!         "callback_sig": base64::encode(callback_sig),
+//!     })
+//!     .to_string()
+//! };
+//!
+//! // Instantiate the contract, whose key the host then passes in the env of every execution
+//! let mut used_gas = 0;
+//! let init_msg = std::fs::read("init_msg.bin").unwrap();
+//! let init_result = init(
+//!     host.ctx(),
+//!     10_000_000,
+//!     &mut used_gas,
+//!     &contract,
+//!     env(None).as_bytes(),
+//!     &init_msg,
+//!     sig_info(&std::fs::read("init_msg.sig").unwrap()).as_bytes(),
+//!     &[],
+//! )
+//! .unwrap();
+//! let contract_key = Some(base64::encode(init_result.contract_key));
+//! println!("instantiated with {} entries stored", host.len());
+//!
+//! // Execute it, which reads and writes the state in the host
+//! let execute_msg = std::fs::read("execute_msg.bin").unwrap();
+//! let handle_result = handle(
+//!     host.ctx(),
+//!     10_000_000,
+//!     &mut used_gas,
+//!     &contract,
+//!     env(contract_key.clone()).as_bytes(),
+//!     &execute_msg,
+//!     sig_info(&std::fs::read("execute_msg.sig").unwrap()).as_bytes(),
+//!     0, // HandleType::HANDLE_TYPE_EXECUTE
+//! )
+//! .unwrap();
+//! println!("{}", String::from_utf8_lossy(&handle_result.output));
+//!
+//! // And query the state it left
+//! let query_msg = std::fs::read("query_msg.bin").unwrap();
+//! let query_result = query(
+//!     host.ctx(),
+//!     10_000_000,
+//!     &mut used_gas,
+//!     &contract,
+//!     env(contract_key).as_bytes(),
+//!     &query_msg,
+//! )
+//! .unwrap();
+//! println!("{}", String::from_utf8_lossy(&query_result.output));
+//! println!("the contract logged {:?}", host.logs());
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+
+use log::*;
+
+use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError, UserSpaceBuffer};
+use sgx_types::sgx_status_t;
+
+use crate::external::ecalls::ecall_allocate;
+
+type QueryResponder = Box<dyn Fn(&[u8]) -> Option<Vec<u8>>>;
+
+/// The state and the chain that the simulated ocalls answer from
+#[derive(Default)]
+pub struct SimHost {
+    store: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+    responder: Option<QueryResponder>,
+    /// The lines that the engine streamed with `ocall_stream_logs`
+    logs: RefCell<Vec<String>>,
+}
+
+impl SimHost {
+    /// Answers the queries of contracts with `responder`. Queries it returns `None` for fail,
+    /// like queries of a host that has no responder.
+    pub fn with_responder(responder: impl Fn(&[u8]) -> Option<Vec<u8>> + 'static) -> Self {
+        Self {
+            responder: Some(Box::new(responder)),
+            ..Default::default()
+        }
+    }
+
+    /// The context to pass to the engine. It points to this host, so the host must outlive the
+    /// calls it is passed to.
+    pub fn ctx(&self) -> Ctx {
+        Ctx {
+            data: self as *const SimHost as *mut c_void,
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.store.borrow().get(key).cloned()
+    }
+
+    pub fn set(&self, key: &[u8], value: &[u8]) {
+        self.store.borrow_mut().insert(key.to_vec(), value.to_vec());
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.borrow().is_empty()
+    }
+
+    pub fn logs(&self) -> Vec<String> {
+        self.logs.borrow().clone()
+    }
+}
+
+/// The size of the entry that a write or remove replaced, as the host reports it
+fn entry_size(key: &[u8], value: Option<&[u8]>) -> u64 {
+    value.map_or(0, |value| (key.len() + value.len()) as u64)
+}
+
+/// Takes back a buffer that the engine passed to the host with `ocall_allocate`
+///
+/// # Safety
+/// The buffer must have been returned by `ocall_allocate`, and not been taken yet
+pub unsafe fn take_user_buffer(buffer: UserSpaceBuffer) -> Vec<u8> {
+    if buffer.ptr.is_null() {
+        return vec![];
+    }
+    *Box::from_raw(buffer.ptr as *mut Vec<u8>)
+}
+
+unsafe fn host<'a>(context: &Ctx) -> &'a SimHost {
+    &*(context.data as *const SimHost)
+}
+
+unsafe fn to_enclave_buffer(bytes: &[u8]) -> EnclaveBuffer {
+    ecall_allocate(bytes.as_ptr(), bytes.len())
+}
+
+/// # Safety
+/// The pointers must be valid
+#[no_mangle]
+pub unsafe extern "C" fn ocall_allocate(
+    retval: *mut UserSpaceBuffer,
+    buffer: *const u8,
+    length: usize,
+) -> sgx_status_t {
+    let bytes = std::slice::from_raw_parts(buffer, length).to_vec();
+    *retval = UserSpaceBuffer {
+        ptr: Box::into_raw(Box::new(bytes)) as *mut c_void,
+    };
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_read_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    value: *mut EnclaveBuffer,
+    key: *const u8,
+    key_len: usize,
+) -> sgx_status_t {
+    let key = std::slice::from_raw_parts(key, key_len);
+    *value = match host(&context).get(key) {
+        Some(found) => to_enclave_buffer(&found),
+        None => EnclaveBuffer::default(),
+    };
+    *gas_used = 0;
+    *retval = OcallReturn::Success;
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_query_chain(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    _gas_limit: u64,
+    value: *mut EnclaveBuffer,
+    query: *const u8,
+    query_len: usize,
+    _query_depth: u32,
+) -> sgx_status_t {
+    let query = std::slice::from_raw_parts(query, query_len);
+    *gas_used = 0;
+    *retval = match host(&context)
+        .responder
+        .as_ref()
+        .and_then(|responder| responder(query))
+    {
+        Some(response) => {
+            *value = to_enclave_buffer(&response);
+            OcallReturn::Success
+        }
+        None => {
+            debug!("the simulated host has no answer to the query");
+            OcallReturn::Failure
+        }
+    };
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_read_db_keys(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    keys: *mut EnclaveBuffer,
+    start: *const u8,
+    start_len: usize,
+    limit: u32,
+) -> sgx_status_t {
+    let start = std::slice::from_raw_parts(start, start_len).to_vec();
+    let page: Vec<Vec<u8>> = host(&context)
+        .store
+        .borrow()
+        .range(start..)
+        .take(limit as usize)
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    *gas_used = 0;
+    *retval = match serde_json::to_vec(&page) {
+        Ok(page) => {
+            *keys = to_enclave_buffer(&page);
+            OcallReturn::Success
+        }
+        Err(_) => OcallReturn::Failure,
+    };
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_remove_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    removed_size: *mut u64,
+    key: *const u8,
+    key_len: usize,
+) -> sgx_status_t {
+    let key = std::slice::from_raw_parts(key, key_len);
+    let removed = host(&context).store.borrow_mut().remove(key);
+    *removed_size = entry_size(key, removed.as_deref());
+    *gas_used = 0;
+    *retval = OcallReturn::Success;
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_write_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    replaced_size: *mut u64,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> sgx_status_t {
+    let key = std::slice::from_raw_parts(key, key_len);
+    let value = std::slice::from_raw_parts(value, value_len);
+    *replaced_size = entry_size(key, host(&context).get(key).as_deref());
+    host(&context).set(key, value);
+    *gas_used = 0;
+    *retval = OcallReturn::Success;
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_multiple_write_db(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    replaced_size: *mut u64,
+    keys: *const u8,
+    keys_len: usize,
+) -> sgx_status_t {
+    let keys = std::slice::from_raw_parts(keys, keys_len);
+    *gas_used = 0;
+    *replaced_size = 0;
+    *retval = match serde_json::from_slice::<Vec<(Vec<u8>, Vec<u8>)>>(keys) {
+        Ok(writes) => {
+            let mut store = host(&context).store.borrow_mut();
+            for (key, value) in writes {
+                let replaced = store.insert(key.clone(), value);
+                *replaced_size += entry_size(&key, replaced.as_deref());
+            }
+            OcallReturn::Success
+        }
+        Err(_) => OcallReturn::Failure,
+    };
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// # Safety
+/// The pointers must be valid, and `context` must come from `SimHost::ctx`
+#[no_mangle]
+pub unsafe extern "C" fn ocall_stream_logs(
+    retval: *mut OcallReturn,
+    context: Ctx,
+    logs: *const u8,
+    logs_len: usize,
+) -> sgx_status_t {
+    let logs = std::slice::from_raw_parts(logs, logs_len);
+    *retval = match serde_json::from_slice::<Vec<String>>(logs) {
+        Ok(lines) => {
+            host(&context).logs.borrow_mut().extend(lines);
+            OcallReturn::Success
+        }
+        Err(_) => OcallReturn::Failure,
+    };
+    sgx_status_t::SGX_SUCCESS
+}
+
+/// Traces are only exported when the node enables execution tracing, which simulations don't
+///
+/// # Safety
+/// The pointers must be valid
+#[no_mangle]
+pub unsafe extern "C" fn ocall_export_trace(
+    retval: *mut OcallReturn,
+    _context: Ctx,
+    _trace: *const u8,
+    _trace_len: usize,
+) -> sgx_status_t {
+    *retval = OcallReturn::Success;
+    sgx_status_t::SGX_SUCCESS
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::sync::SgxMutex;
+use std::{env, path};
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_generic::BaseAddr;
+use enclave_crypto::consts::{DEFAULT_SGX_SECRET_PATH, SCRT_SGX_STORAGE_ENV_VAR};
+use enclave_crypto::sha_256;
+use enclave_ffi_types::EnclaveError;
+use enclave_utils::storage::{seal, unseal};
+
+use crate::panic_isolation::LockOrRecover;
+
+const STATE_AUDIT_LOG_FILE_NAME: &str = "state_audit_log.sealed";
+
+/// Once the log has this many flushes, the oldest ones are dropped
+const MAX_STATE_AUDIT_RECORDS: usize = 1_000;
+
+lazy_static! {
+    static ref STATE_AUDIT_LOG_SEALING_PATH: String = path::Path::new(
+        &env::var(SCRT_SGX_STORAGE_ENV_VAR).unwrap_or_else(|_| DEFAULT_SGX_SECRET_PATH.to_string()),
+    )
+    .join(STATE_AUDIT_LOG_FILE_NAME)
+    .to_str()
+    .unwrap_or(DEFAULT_SGX_SECRET_PATH)
+    .to_string();
+    static ref STATE_AUDIT: SgxMutex<StateAudit> = SgxMutex::new(StateAudit::default());
+}
+
+/// A key the contract wrote. Only what is written to the chain state is described, so the log
+/// reveals nothing the host can't already see.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StateWrite {
+    /// Hex of the SHA-256 of the encrypted key, which is the same for every write of a key in
+    /// the same state key epoch
+    pub key_hash: String,
+    /// Size of the encrypted value
+    pub value_size: u64,
+}
+
+/// The writes of one call to `Engine::flush_cache`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FlushRecord {
+    pub block_height: u64,
+    pub contract: String,
+    pub total_size: u64,
+    /// Sorted by key hash, so the order doesn't depend on the plaintext keys
+    pub writes: Vec<StateWrite>,
+}
+
+impl FlushRecord {
+    /// `written` are the encrypted keys and values, as they are written to the chain state
+    pub fn new(
+        contract_address: &BaseAddr,
+        block_height: u64,
+        written: &[(Vec<u8>, Vec<u8>)],
+    ) -> Self {
+        let mut writes: Vec<StateWrite> = written
+            .iter()
+            .map(|(enc_key, enc_value)| StateWrite {
+                key_hash: hex::encode(sha_256(enc_key)),
+                value_size: enc_value.len() as u64,
+            })
+            .collect();
+        writes.sort_by(|a, b| a.key_hash.cmp(&b.key_hash));
+
+        FlushRecord {
+            block_height,
+            contract: contract_address.as_str().to_string(),
+            total_size: writes.iter().map(|write| write.value_size).sum(),
+            writes,
+        }
+    }
+}
+
+#[derive(Default)]
+struct StateAudit {
+    enabled: bool,
+    records: VecDeque<FlushRecord>,
+}
+
+fn unseal_log() -> VecDeque<FlushRecord> {
+    let sealed = match unseal(&STATE_AUDIT_LOG_SEALING_PATH) {
+        Ok(sealed) => sealed,
+        Err(_) => return VecDeque::new(),
+    };
+
+    serde_json::from_slice(&sealed).unwrap_or_else(|err| {
+        error!("failed to parse the sealed state audit log: {:?}", err);
+        VecDeque::new()
+    })
+}
+
+fn seal_log(records: &VecDeque<FlushRecord>) -> Result<(), EnclaveError> {
+    let serialized = serde_json::to_vec(records).map_err(|err| {
+        warn!("failed to serialize the state audit log: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+    seal(&serialized, &STATE_AUDIT_LOG_SEALING_PATH).map_err(|err| {
+        error!("failed to seal the state audit log: {:?}", err);
+        EnclaveError::FailedSeal
+    })
+}
+
+/// Enables or disables the audit mode, in which every cache flush is recorded in a sealed log
+/// that the operator can export with `ecall_export_state_audit_log`. The log is resealed after
+/// every flush, so the mode is meant for debugging the state growth of contracts.
+pub fn configure_state_audit(enabled: bool) {
+    let mut audit = STATE_AUDIT.lock_or_recover();
+    audit.enabled = enabled;
+    if enabled {
+        audit.records = unseal_log();
+        info!(
+            "state audit mode is enabled, the log has {} flushes",
+            audit.records.len()
+        );
+    }
+}
+
+pub fn is_state_audit_enabled() -> bool {
+    STATE_AUDIT.lock_or_recover().enabled
+}
+
+/// Appends a flush to the log. Failing to seal the log never fails the execution.
+pub fn record_flush(record: FlushRecord) {
+    let mut audit = STATE_AUDIT.lock_or_recover();
+    if !audit.enabled {
+        return;
+    }
+
+    trace!(
+        "recording a flush of {} keys of {}",
+        record.writes.len(),
+        record.contract
+    );
+    audit.records.push_back(record);
+    while audit.records.len() > MAX_STATE_AUDIT_RECORDS {
+        audit.records.pop_front();
+    }
+
+    let _ = seal_log(&audit.records);
+}
+
+/// Returns the flushes in the log as JSON, oldest first
+pub fn export_state_audit_log() -> Result<Vec<u8>, EnclaveError> {
+    let audit = STATE_AUDIT.lock_or_recover();
+    if !audit.enabled {
+        warn!("tried to export the state audit log while the audit mode is disabled");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    serde_json::to_vec(&audit.records).map_err(|err| {
+        warn!("failed to serialize the state audit log: {:?}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_flush_record() {
+        let written = vec![
+            (b"encrypted key 1".to_vec(), vec![0u8; 100]),
+            (b"encrypted key 2".to_vec(), vec![0u8; 28]),
+        ];
+        let record = FlushRecord::new(&BaseAddr::from("secret1contract"), 7, &written);
+
+        assert_eq!(record.block_height, 7);
+        assert_eq!(record.contract, "secret1contract");
+        assert_eq!(record.total_size, 128);
+        assert_eq!(record.writes.len(), 2);
+        assert!(record.writes[0].key_hash < record.writes[1].key_hash);
+        for (enc_key, enc_value) in &written {
+            assert!(record.writes.contains(&StateWrite {
+                key_hash: hex::encode(sha_256(enc_key)),
+                value_size: enc_value.len() as u64,
+            }));
+        }
+
+        // The keys themselves are never in the log
+        let serialized = serde_json::to_string(&record).unwrap();
+        assert!(!serialized.contains("encrypted key"));
+
+        // Empty flushes are recorded too, so every execution shows up in the log
+        let record = FlushRecord::new(&BaseAddr::from("secret1contract"), 8, &[]);
+        assert_eq!(record.total_size, 0);
+        assert!(record.writes.is_empty());
+    }
+}
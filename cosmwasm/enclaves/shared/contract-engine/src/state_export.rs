@@ -0,0 +1,425 @@
+use std::convert::TryFrom;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cosmos_proto::tx::signing::SignMode;
+use cw_types_generic::BaseEnv;
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::{CanonicalAddr, HumanAddr};
+use enclave_cosmos_types::traits::CosmosAminoPubkey;
+use enclave_crypto::secp256k1::Secp256k1PubKey;
+use enclave_crypto::traits::VerifyingKey;
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::{Ctx, EnclaveError};
+use enclave_utils::kv_cache::KvCache;
+
+use crate::contract_validation::{generate_admin_proof, validate_old_code_hash, ContractKey};
+use crate::cosmwasm_config::reserved_keys;
+use crate::cosmwasm_config::state_export::{MAX_EXPORTED_KEYS, PERMIT_MSG_TYPE};
+use crate::db::{read_from_state, read_ordered_namespaces, StateKeys};
+use crate::types::{EnvelopeVersion, SecretMessage};
+
+/// A request to export the state entries of a user. It must be signed by both the user and
+/// the admin of the contract, and the result is encrypted for the user.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct StateExportRequest {
+    /// The plaintext keys of the entries to export
+    keys: Vec<Binary>,
+    /// Used with `user_public_key` to encrypt the export, like the output of a transaction
+    nonce: Binary,
+    user_public_key: Binary,
+    user_signature: PermitSignature,
+    admin_signature: PermitSignature,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PermitSignature {
+    /// A compressed or uncompressed secp256k1 public key
+    pub_key: Binary,
+    signature: Binary,
+}
+
+/// The document the user and the admin sign, in the Amino JSON format of wallets. Fields are
+/// declared in alphabetical order so that it serializes canonically.
+#[derive(Serialize)]
+struct PermitSignDoc<'a> {
+    account_number: &'a str,
+    chain_id: &'a str,
+    fee: PermitFee,
+    memo: &'a str,
+    msgs: [PermitMsg<'a>; 1],
+    sequence: &'a str,
+}
+
+#[derive(Serialize)]
+struct PermitFee {
+    amount: &'static [&'static str],
+    gas: &'static str,
+}
+
+#[derive(Serialize)]
+struct PermitMsg<'a> {
+    #[serde(rename = "type")]
+    msg_type: &'a str,
+    value: PermitValue<'a>,
+}
+
+#[derive(Serialize)]
+struct PermitValue<'a> {
+    contract: &'a HumanAddr,
+    keys: &'a [Binary],
+    nonce: &'a Binary,
+    user: &'a HumanAddr,
+    user_public_key: &'a Binary,
+}
+
+#[derive(Serialize, Debug)]
+struct ExportedEntry {
+    key: Binary,
+    value: Option<Binary>,
+}
+
+#[derive(Serialize, Debug)]
+struct StateExport {
+    entries: Vec<ExportedEntry>,
+    /// Requested keys that don't belong to the user, and which were left out of the export
+    redacted: Vec<Binary>,
+}
+
+fn permit_sign_bytes(
+    chain_id: &str,
+    contract: &HumanAddr,
+    user: &HumanAddr,
+    request: &StateExportRequest,
+) -> Result<Vec<u8>, EnclaveError> {
+    let sign_doc = PermitSignDoc {
+        account_number: "0",
+        chain_id,
+        fee: PermitFee {
+            amount: &[],
+            gas: "1",
+        },
+        memo: "",
+        msgs: [PermitMsg {
+            msg_type: PERMIT_MSG_TYPE,
+            value: PermitValue {
+                contract,
+                keys: &request.keys,
+                nonce: &request.nonce,
+                user,
+                user_public_key: &request.user_public_key,
+            },
+        }],
+        sequence: "0",
+    };
+
+    serde_json::to_vec(&sign_doc).map_err(|err| {
+        warn!("failed to serialize state export permit: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+/// Verifies the signature on the permit and returns the address of the signer
+fn verify_permit_signature(
+    sign_bytes: &[u8],
+    signature: &PermitSignature,
+) -> Result<CanonicalAddr, EnclaveError> {
+    let pub_key = Secp256k1PubKey::new(signature.pub_key.0.clone());
+    pub_key
+        .verify_bytes(
+            sign_bytes,
+            &signature.signature.0,
+            SignMode::SIGN_MODE_LEGACY_AMINO_JSON,
+        )
+        .map_err(|err| {
+            warn!("state export permit signature is invalid: {:?}", err);
+            EnclaveError::FailedTxVerification
+        })?;
+
+    Ok(pub_key.get_address())
+}
+
+/// Entries belong to a user if a segment of their key is exactly the user's address, either
+/// canonical or bech32 encoded. Keys are split the way the storage libraries of contracts build
+/// them: namespaces prefixed by their 2 bytes big endian length, followed by the rest of the key.
+/// Addresses that merely appear inside a segment, e.g. as a substring of a longer address, don't
+/// count.
+fn belongs_to_user(key: &[u8], user: &CanonicalAddr, user_human: &HumanAddr) -> bool {
+    if reserved_keys::is_reserved(key) {
+        return false;
+    }
+
+    let is_user = |segment: &[u8]| {
+        !segment.is_empty() && (segment == user.as_slice() || segment == user_human.0.as_bytes())
+    };
+
+    let mut rest = key;
+    loop {
+        if is_user(rest) {
+            return true;
+        }
+        if rest.len() < 2 {
+            return false;
+        }
+
+        let namespace_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let namespace_end = 2 + namespace_len;
+        if namespace_len == 0 || namespace_end > rest.len() {
+            return false;
+        }
+        if is_user(&rest[2..namespace_end]) {
+            return true;
+        }
+        rest = &rest[namespace_end..];
+    }
+}
+
+/// Decrypts and exports the entries of a user from the state of a contract, e.g. to answer a
+/// data access request. The export is only possible if both the user and the admin of the
+/// contract signed the request, so neither can export the data alone. Entries whose key doesn't
+/// have the address of the user as one of its segments are redacted, and the export is encrypted for the user.
+pub fn export_user_state(
+    context: Ctx,
+    env: &[u8],
+    admin: &[u8],
+    admin_proof: &[u8],
+    request: &[u8],
+) -> Result<Vec<u8>, EnclaveError> {
+    let base_env: BaseEnv = serde_json::from_slice(env).map_err(|err| {
+        warn!("failed to deserialize env for state export: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let contract_address = &base_env.0.contract.address;
+    let canonical_contract_address =
+        CanonicalAddr::from_human(contract_address).map_err(|err| {
+            warn!(
+                "failed to canonicalize the address of the exported contract: {}",
+                err
+            );
+            EnclaveError::from(err)
+        })?;
+
+    let code_hash = hex::decode(&base_env.0.contract_code_hash)
+        .ok()
+        .and_then(|code_hash| <[u8; HASH_SIZE]>::try_from(code_hash.as_slice()).ok())
+        .ok_or_else(|| {
+            warn!("got an invalid code hash for state export");
+            EnclaveError::FailedToDeserialize
+        })?;
+    validate_old_code_hash(&base_env, &canonical_contract_address, &code_hash)?;
+
+    let og_contract_key: ContractKey = base_env.get_og_contract_key()?;
+    if admin.is_empty() || admin_proof != generate_admin_proof(admin, &og_contract_key) {
+        warn!("failed to validate the admin of the contract for state export");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let request: StateExportRequest = serde_json::from_slice(request).map_err(|err| {
+        warn!("failed to deserialize state export request: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if request.keys.len() > MAX_EXPORTED_KEYS {
+        warn!(
+            "got {} keys to export, the limit is {}",
+            request.keys.len(),
+            MAX_EXPORTED_KEYS
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    let nonce = <[u8; 32]>::try_from(request.nonce.as_slice());
+    let user_public_key = <[u8; 32]>::try_from(request.user_public_key.as_slice());
+    let (nonce, user_public_key) = match (nonce, user_public_key) {
+        (Ok(nonce), Ok(user_public_key)) => (nonce, user_public_key),
+        _ => {
+            warn!("got invalid encryption parameters for state export");
+            return Err(EnclaveError::FailedToDeserialize);
+        }
+    };
+
+    // The user is whoever signed the user's part of the permit
+    let user = Secp256k1PubKey::new(request.user_signature.pub_key.0.clone()).get_address();
+    let user_human = HumanAddr::from_canonical(&user).map_err(|err| {
+        warn!(
+            "failed to humanize the address of the exporting user: {}",
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let sign_bytes = permit_sign_bytes(
+        &base_env.0.block.chain_id,
+        contract_address,
+        &user_human,
+        &request,
+    )?;
+    verify_permit_signature(&sign_bytes, &request.user_signature)?;
+    let admin_signer = verify_permit_signature(&sign_bytes, &request.admin_signature)?;
+    if admin_signer.as_slice() != admin {
+        warn!("the state export permit wasn't signed by the admin of the contract");
+        return Err(EnclaveError::FailedTxVerification);
+    }
+
+    let state_keys = StateKeys::at_height(&context, &og_contract_key, base_env.0.block.height)?;
+    let mut kv_cache = KvCache::new();
+    let (ordered_namespaces, _) =
+        read_ordered_namespaces(&context, &state_keys, false, &mut kv_cache, &[])
+            .map_err(EnclaveError::from)?;
+    let mut export = StateExport {
+        entries: vec![],
+        redacted: vec![],
+    };
+
+    for key in request.keys {
+        if !belongs_to_user(key.as_slice(), &user, &user_human) {
+            export.redacted.push(key);
+            continue;
+        }
+
+        let (value, _) = read_from_state(
+            key.as_slice(),
+            &context,
+            &state_keys,
+            false,
+            &mut kv_cache,
+            &[],
+            ordered_namespaces.scheme_of(key.as_slice()),
+        )
+        .map_err(EnclaveError::from)?;
+
+        export.entries.push(ExportedEntry {
+            key,
+            value: value.map(Binary),
+        });
+    }
+
+    info!(
+        "exporting {} state entries of a user of {} ({} redacted)",
+        export.entries.len(),
+        contract_address,
+        export.redacted.len()
+    );
+
+    let serialized_export = serde_json::to_vec(&export).map_err(|err| {
+        warn!("failed to serialize state export: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+
+    let mut secret_export = SecretMessage {
+        nonce,
+        user_public_key,
+        msg: serialized_export,
+        version: EnvelopeVersion::Unversioned,
+    };
+    secret_export.encrypt_in_place()?;
+
+    Ok(secret_export.msg)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_belongs_to_user() {
+        let user = CanonicalAddr(Binary(vec![7u8; 20]));
+        let user_human = HumanAddr("secret1user".to_string());
+
+        let namespaced = |namespaces: &[&[u8]], rest: &[u8]| {
+            let mut key = vec![];
+            for namespace in namespaces {
+                key.extend_from_slice(&(namespace.len() as u16).to_be_bytes());
+                key.extend_from_slice(namespace);
+            }
+            key.extend_from_slice(rest);
+            key
+        };
+
+        // The address is the key, the rest of a namespaced key, or one of its namespaces
+        assert!(belongs_to_user(user.as_slice(), &user, &user_human));
+        assert!(belongs_to_user(
+            &namespaced(&[b"balance"], user.as_slice()),
+            &user,
+            &user_human
+        ));
+        assert!(belongs_to_user(
+            &namespaced(&[b"viewing_key"], b"secret1user"),
+            &user,
+            &user_human
+        ));
+        assert!(belongs_to_user(
+            &namespaced(&[b"allowance", b"secret1user"], b"secret1spender"),
+            &user,
+            &user_human
+        ));
+
+        assert!(!belongs_to_user(b"config", &user, &user_human));
+        assert!(!belongs_to_user(
+            &namespaced(&[b"balance"], b"secret1other"),
+            &user,
+            &user_human
+        ));
+
+        // The address only appears inside a segment
+        let mut balance_key = b"balance".to_vec();
+        balance_key.extend_from_slice(user.as_slice());
+        assert!(!belongs_to_user(&balance_key, &user, &user_human));
+        assert!(!belongs_to_user(
+            b"viewing_key/secret1user",
+            &user,
+            &user_human
+        ));
+        assert!(!belongs_to_user(
+            &namespaced(&[b"balance"], b"secret1user2"),
+            &user,
+            &user_human
+        ));
+        assert!(!belongs_to_user(
+            &namespaced(&[b"balance"], b"xsecret1user"),
+            &user,
+            &user_human
+        ));
+
+        let mut reserved_key = reserved_keys::PREFIX.to_vec();
+        reserved_key.extend_from_slice(user.as_slice());
+        assert!(!belongs_to_user(&reserved_key, &user, &user_human));
+    }
+
+    pub fn test_permit_sign_bytes() {
+        let request = StateExportRequest {
+            keys: vec![Binary(b"balance".to_vec())],
+            nonce: Binary(vec![1u8; 32]),
+            user_public_key: Binary(vec![2u8; 32]),
+            user_signature: PermitSignature {
+                pub_key: Binary(vec![]),
+                signature: Binary(vec![]),
+            },
+            admin_signature: PermitSignature {
+                pub_key: Binary(vec![]),
+                signature: Binary(vec![]),
+            },
+        };
+
+        let sign_bytes = permit_sign_bytes(
+            "secret-4",
+            &HumanAddr("secret1contract".to_string()),
+            &HumanAddr("secret1user".to_string()),
+            &request,
+        )
+        .unwrap();
+
+        let nonce = base64::encode(vec![1u8; 32]);
+        let user_public_key = base64::encode(vec![2u8; 32]);
+        assert_eq!(
+            String::from_utf8(sign_bytes).unwrap(),
+            format!(
+                r#"{{"account_number":"0","chain_id":"secret-4","fee":{{"amount":[],"gas":"1"}},"memo":"","msgs":[{{"type":"export_user_state","value":{{"contract":"secret1contract","keys":["YmFsYW5jZQ=="],"nonce":"{}","user":"secret1user","user_public_key":"{}"}}}}],"sequence":"0"}}"#,
+                nonce, user_public_key
+            )
+        );
+    }
+}
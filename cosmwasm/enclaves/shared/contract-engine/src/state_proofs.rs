@@ -0,0 +1,579 @@
+//! Proofs that a key has a value in the state committed to by the app hash of a header that the
+//! light client verified. They let the enclave use values of the chain state without trusting
+//! the host that supplies them.
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_v010::encoding::Binary;
+use enclave_crypto::{sha_256, HASH_SIZE};
+use enclave_ffi_types::EnclaveError;
+
+#[cfg(feature = "light-client-validation")]
+use block_verifier::VERIFIED_HEADERS;
+
+#[cfg(feature = "light-client-validation")]
+use crate::panic_isolation::LockOrRecover;
+
+/// Prefixes of the leaves and inner nodes of IAVL trees and of the simple Merkle tree of
+/// the multistore
+const LEAF_PREFIX: u8 = 0;
+const INNER_PREFIX: u8 = 1;
+/// Bounds of the prefixes of IAVL inner nodes: the height, size and version varints, and the
+/// length prefixed hash of the left child when the proven node is on the right
+const IAVL_MIN_INNER_PREFIX_LENGTH: usize = 4;
+const IAVL_MAX_INNER_PREFIX_LENGTH: usize = 12 + 1 + HASH_SIZE;
+const IAVL_CHILD_LENGTH: usize = 1 + HASH_SIZE;
+
+/// An inner node on the path from a leaf to the root: the hash of the node is
+/// `sha256(prefix || child || suffix)`, where the sibling is in either the prefix or the suffix
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InnerOp {
+    pub prefix: Binary,
+    pub suffix: Binary,
+}
+
+/// An ICS-23 existence proof of a key and value
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ExistenceProof {
+    pub key: Binary,
+    pub value: Binary,
+    /// For IAVL leaves, the height, size and version of the leaf. Empty for the multistore.
+    pub leaf_prefix: Binary,
+    pub path: Vec<InnerOp>,
+}
+
+fn encode_varint(mut value: usize, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+impl ExistenceProof {
+    fn leaf_hash(&self, leaf_prefix: &[u8]) -> [u8; HASH_SIZE] {
+        let mut data = leaf_prefix.to_vec();
+        encode_varint(self.key.len(), &mut data);
+        data.extend_from_slice(self.key.as_slice());
+        encode_varint(HASH_SIZE, &mut data);
+        data.extend_from_slice(&sha_256(self.value.as_slice()));
+        sha_256(&data)
+    }
+
+    fn root(&self, leaf_prefix: &[u8], is_valid_inner: fn(&InnerOp) -> bool) -> Option<Vec<u8>> {
+        let mut hash = self.leaf_hash(leaf_prefix);
+        for inner in &self.path {
+            if !is_valid_inner(inner) {
+                return None;
+            }
+
+            let mut data = inner.prefix.as_slice().to_vec();
+            data.extend_from_slice(&hash);
+            data.extend_from_slice(inner.suffix.as_slice());
+            hash = sha_256(&data);
+        }
+
+        Some(hash.to_vec())
+    }
+
+    /// The root of the IAVL tree of a store. Like the IAVL spec of ICS-23, the prefixes of
+    /// leaves and inner nodes are checked so a leaf can't be passed off as an inner node.
+    fn iavl_root(&self) -> Option<Vec<u8>> {
+        if self.leaf_prefix.as_slice().first() != Some(&LEAF_PREFIX) {
+            return None;
+        }
+
+        self.root(self.leaf_prefix.as_slice(), |inner| {
+            let prefix = inner.prefix.as_slice();
+            let suffix_len = inner.suffix.len();
+            prefix.first() != Some(&LEAF_PREFIX)
+                && prefix.len() >= IAVL_MIN_INNER_PREFIX_LENGTH
+                && prefix.len() <= IAVL_MAX_INNER_PREFIX_LENGTH
+                && (suffix_len == 0 || suffix_len == IAVL_CHILD_LENGTH)
+        })
+    }
+
+    /// The root of the simple Merkle tree of the multistore, i.e. the app hash
+    fn multistore_root(&self) -> Option<Vec<u8>> {
+        if !self.leaf_prefix.is_empty() {
+            return None;
+        }
+
+        // The sibling is either the whole suffix or the rest of the prefix
+        self.root(&[LEAF_PREFIX], |inner| {
+            let prefix = inner.prefix.as_slice();
+            let suffix_len = inner.suffix.len();
+            prefix.first() == Some(&INNER_PREFIX)
+                && ((prefix.len() == 1 && suffix_len == HASH_SIZE)
+                    || (prefix.len() == 1 + HASH_SIZE && suffix_len == 0))
+        })
+    }
+}
+
+/// Whether the proven node is the left child of an IAVL inner node, whose right child is then
+/// in the suffix
+fn is_left_child(inner: &InnerOp) -> bool {
+    !inner.suffix.is_empty()
+}
+
+/// Whether two leaves of an IAVL tree are next to each other: below the node where their paths
+/// split, the left leaf is the rightmost leaf of its subtree and the right leaf the leftmost one.
+/// Both paths must already be verified against the same root, so that the ops where they split
+/// are the same node.
+fn are_adjacent(left: &[InnerOp], right: &[InnerOp]) -> bool {
+    // The paths go from the leaves up, so the part they share is at their ends
+    let shared = left
+        .iter()
+        .rev()
+        .zip(right.iter().rev())
+        .take_while(|(left, right)| left == right)
+        .count();
+    let left = &left[..left.len() - shared];
+    let right = &right[..right.len() - shared];
+
+    match (left.split_last(), right.split_last()) {
+        (Some((left_split, left_below)), Some((right_split, right_below))) => {
+            is_left_child(left_split)
+                && !is_left_child(right_split)
+                && left_below.iter().all(|inner| !is_left_child(inner))
+                && right_below.iter().all(is_left_child)
+        }
+        _ => false,
+    }
+}
+
+/// An ICS-23 proof that a key has no value in an IAVL tree: the existence proofs of the keys
+/// next to it. A key that is smaller or larger than every key of the tree has one neighbor.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NonExistenceProof {
+    pub key: Binary,
+    pub left: Option<ExistenceProof>,
+    pub right: Option<ExistenceProof>,
+}
+
+impl NonExistenceProof {
+    fn verify(&self, key: &[u8], store_root: &[u8]) -> bool {
+        if self.key.as_slice() != key {
+            return false;
+        }
+        let neighbors = self.left.iter().chain(self.right.iter());
+        if !neighbors.all(|neighbor| neighbor.iavl_root().as_deref() == Some(store_root)) {
+            return false;
+        }
+
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => {
+                left.key.as_slice() < key
+                    && key < right.key.as_slice()
+                    && are_adjacent(&left.path, &right.path)
+            }
+            (Some(left), None) => {
+                left.key.as_slice() < key && left.path.iter().all(|inner| !is_left_child(inner))
+            }
+            (None, Some(right)) => {
+                key < right.key.as_slice() && right.path.iter().all(is_left_child)
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+/// Proves the value of a key in the IAVL tree of a store, or that it has none
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyProof {
+    Exist(ExistenceProof),
+    Nonexist(NonExistenceProof),
+}
+
+/// Proves a read of a key of one of the stores of the multistore, whether the key has a value or
+/// not, in the state that an app hash commits to
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReadProof {
+    pub store_proof: KeyProof,
+    /// Proves the root of the store in the multistore
+    pub multistore_proof: ExistenceProof,
+}
+
+impl ReadProof {
+    /// Verifies the proof against the app hash, and returns the proven value of the key
+    pub fn verify(
+        &self,
+        store: &[u8],
+        key: &[u8],
+        app_hash: &[u8],
+    ) -> Result<Option<&[u8]>, EnclaveError> {
+        if self.multistore_proof.key.as_slice() != store
+            || self.multistore_proof.multistore_root().as_deref() != Some(app_hash)
+        {
+            warn!("read proof doesn't prove the root of the store");
+            return Err(EnclaveError::ValidationFailure);
+        }
+        let store_root = self.multistore_proof.value.as_slice();
+
+        match &self.store_proof {
+            KeyProof::Exist(proof)
+                if proof.key.as_slice() == key
+                    && proof.iavl_root().as_deref() == Some(store_root) =>
+            {
+                Ok(Some(proof.value.as_slice()))
+            }
+            KeyProof::Nonexist(proof) if proof.verify(key, store_root) => Ok(None),
+            _ => {
+                warn!("read proof doesn't prove the key in the store");
+                Err(EnclaveError::ValidationFailure)
+            }
+        }
+    }
+}
+
+/// Proves the value of a key in one of the stores of the multistore
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StateProof {
+    /// The height of the header whose app hash the proofs are verified against
+    pub height: u64,
+    /// Proves the value of the key in the IAVL tree of the store
+    pub store_proof: ExistenceProof,
+    /// Proves the root of the store in the multistore
+    pub multistore_proof: ExistenceProof,
+}
+
+impl StateProof {
+    pub fn verify(&self, store: &[u8], key: &[u8], app_hash: &[u8]) -> bool {
+        if self.store_proof.key.as_slice() != key || self.multistore_proof.key.as_slice() != store {
+            warn!("state proof is not for the expected store and key");
+            return false;
+        }
+
+        let store_root = match self.store_proof.iavl_root() {
+            Some(store_root) => store_root,
+            None => return false,
+        };
+        if self.multistore_proof.value.as_slice() != store_root.as_slice() {
+            return false;
+        }
+
+        self.multistore_proof.multistore_root().as_deref() == Some(app_hash)
+    }
+
+    /// Verifies the proof against the app hash of the verified header at its height, and
+    /// returns the proven value
+    pub fn verified_value(&self, store: &[u8], key: &[u8]) -> Result<&[u8], EnclaveError> {
+        let app_hash = verified_app_hash(self.height).ok_or_else(|| {
+            warn!("header at height {} was not verified", self.height);
+            EnclaveError::ValidationFailure
+        })?;
+        if !self.verify(store, key, &app_hash) {
+            warn!("state proof at height {} is invalid", self.height);
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        Ok(self.store_proof.value.as_slice())
+    }
+}
+
+#[cfg(feature = "light-client-validation")]
+pub fn verified_app_hash(height: u64) -> Option<Vec<u8>> {
+    let verified_headers = VERIFIED_HEADERS.lock_or_recover();
+    Some(verified_headers.get(height)?.app_hash.clone())
+}
+
+/// Without the light client the enclave can't verify the app hash, so no proof is accepted
+#[cfg(not(feature = "light-client-validation"))]
+pub fn verified_app_hash(height: u64) -> Option<Vec<u8>> {
+    warn!(
+        "cannot verify state proofs at height {} without light client validation",
+        height
+    );
+    None
+}
+
+/// Verifies a header and the commit that signs it, as carried by a transaction to prove the state
+/// at a height, and returns the app hash that commits to that state. A header commits to the
+/// state after the block before it, so it must be the header at the next height. Unlike
+/// `verified_app_hash`, this doesn't depend on which blocks this node verified.
+#[cfg(feature = "light-client-validation")]
+pub fn proven_app_hash(height: u64, header: &[u8], commit: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    let proven = block_verifier::verify_header_proof(header, commit).map_err(|err| {
+        warn!(
+            "header proof of the state at height {} is invalid: {:?}",
+            height, err
+        );
+        EnclaveError::ValidationFailure
+    })?;
+    if Some(proven.height) != height.checked_add(1) {
+        warn!(
+            "header at height {} doesn't commit to the state at height {}",
+            proven.height, height
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(proven.app_hash)
+}
+
+#[cfg(not(feature = "light-client-validation"))]
+pub fn proven_app_hash(
+    height: u64,
+    _header: &[u8],
+    _commit: &[u8],
+) -> Result<Vec<u8>, EnclaveError> {
+    warn!(
+        "cannot verify header proofs at height {} without light client validation",
+        height
+    );
+    Err(EnclaveError::ValidationFailure)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn inner_node(left: &[u8], right: &[u8]) -> Vec<u8> {
+        let mut data = vec![INNER_PREFIX];
+        data.extend_from_slice(left);
+        data.extend_from_slice(right);
+        sha_256(&data).to_vec()
+    }
+
+    /// A proof of the value in a store with a sibling leaf on its left, in a multistore with
+    /// another store on its right. Returns the proof and the app hash it's valid for.
+    pub fn prove(store: &[u8], key: &[u8], value: &[u8]) -> (StateProof, Vec<u8>) {
+        let mut store_proof = ExistenceProof {
+            key: Binary(key.to_vec()),
+            value: Binary(value.to_vec()),
+            leaf_prefix: Binary(vec![0, 2, 10]),
+            path: vec![],
+        };
+        let sibling = [3u8; HASH_SIZE];
+        let mut prefix = vec![2, 4, 10, HASH_SIZE as u8];
+        prefix.extend_from_slice(&sibling);
+        prefix.push(HASH_SIZE as u8);
+        store_proof.path.push(InnerOp {
+            prefix: Binary(prefix),
+            suffix: Binary(vec![]),
+        });
+        let store_root = store_proof.iavl_root().unwrap();
+
+        let other_store = [5u8; HASH_SIZE];
+        let multistore_proof = ExistenceProof {
+            key: Binary(store.to_vec()),
+            value: Binary(store_root),
+            leaf_prefix: Binary(vec![]),
+            path: vec![InnerOp {
+                prefix: Binary(vec![INNER_PREFIX]),
+                suffix: Binary(other_store.to_vec()),
+            }],
+        };
+        let store_leaf = multistore_proof.leaf_hash(&[LEAF_PREFIX]);
+        let app_hash = inner_node(&store_leaf, &other_store);
+
+        let proof = StateProof {
+            height: 10,
+            store_proof,
+            multistore_proof,
+        };
+        (proof, app_hash)
+    }
+
+    pub fn test_state_proof() {
+        let (proof, app_hash) = prove(b"params", b"compute/GasCosts", b"value");
+        assert!(proof.verify(b"params", b"compute/GasCosts", &app_hash));
+        assert!(!proof.verify(b"params", b"compute/GasCosts", &[0u8; HASH_SIZE]));
+
+        // Another value under the key
+        let mut forged = proof.clone();
+        forged.store_proof.value = Binary(b"forged".to_vec());
+        assert!(!forged.verify(b"params", b"compute/GasCosts", &app_hash));
+
+        // A leaf of the store passed off as an inner node
+        let mut forged = proof.clone();
+        forged.store_proof.path[0].prefix = Binary(vec![LEAF_PREFIX, 2, 4, 10]);
+        assert!(!forged.verify(b"params", b"compute/GasCosts", &app_hash));
+
+        // Another key or store
+        assert!(!proof.verify(b"params", b"compute/Other", &app_hash));
+        assert!(!proof.verify(b"bank", b"compute/GasCosts", &app_hash));
+    }
+
+    fn iavl_leaf(key: &[u8], value: &[u8]) -> ExistenceProof {
+        ExistenceProof {
+            key: Binary(key.to_vec()),
+            value: Binary(value.to_vec()),
+            leaf_prefix: Binary(vec![0, 2, 10]),
+            path: vec![],
+        }
+    }
+
+    /// The op of an IAVL inner node, for a child on the left or on the right of the sibling
+    fn iavl_inner(sibling: &[u8], child_on_left: bool) -> InnerOp {
+        let mut prefix = vec![2, 4, 10, HASH_SIZE as u8];
+        let mut suffix = vec![HASH_SIZE as u8];
+        if child_on_left {
+            suffix.extend_from_slice(sibling);
+        } else {
+            prefix.extend_from_slice(sibling);
+            prefix.push(HASH_SIZE as u8);
+            suffix.clear();
+        }
+        InnerOp {
+            prefix: Binary(prefix),
+            suffix: Binary(suffix),
+        }
+    }
+
+    fn node_hash(child: &[u8], inner: &InnerOp) -> Vec<u8> {
+        let mut data = inner.prefix.as_slice().to_vec();
+        data.extend_from_slice(child);
+        data.extend_from_slice(inner.suffix.as_slice());
+        sha_256(&data).to_vec()
+    }
+
+    /// A proof that a key has no value in a store whose only key is smaller than it. Returns the
+    /// proof and the app hash it's valid for.
+    pub fn prove_absent(store: &[u8], key: &[u8], smaller_key: &[u8]) -> (ReadProof, Vec<u8>) {
+        let left = iavl_leaf(smaller_key, b"value");
+        let multistore_proof = ExistenceProof {
+            key: Binary(store.to_vec()),
+            value: Binary(left.iavl_root().unwrap()),
+            leaf_prefix: Binary(vec![]),
+            path: vec![InnerOp {
+                prefix: Binary(vec![INNER_PREFIX]),
+                suffix: Binary([5u8; HASH_SIZE].to_vec()),
+            }],
+        };
+        let app_hash = multistore_proof.multistore_root().unwrap();
+
+        let proof = ReadProof {
+            store_proof: KeyProof::Nonexist(NonExistenceProof {
+                key: Binary(key.to_vec()),
+                left: Some(left),
+                right: None,
+            }),
+            multistore_proof,
+        };
+        (proof, app_hash)
+    }
+
+    /// Proofs of the values of two keys of a store whose tree has only them. Returns the proofs
+    /// and the app hash they're both valid for.
+    pub fn prove_pair(
+        store: &[u8],
+        (left_key, left_value): (&[u8], &[u8]),
+        (right_key, right_value): (&[u8], &[u8]),
+    ) -> (ReadProof, ReadProof, Vec<u8>) {
+        let mut left = iavl_leaf(left_key, left_value);
+        let mut right = iavl_leaf(right_key, right_value);
+        let left_hash = left.leaf_hash(left.leaf_prefix.as_slice());
+        let right_hash = right.leaf_hash(right.leaf_prefix.as_slice());
+        left.path.push(iavl_inner(&right_hash, true));
+        right.path.push(iavl_inner(&left_hash, false));
+
+        let multistore_proof = ExistenceProof {
+            key: Binary(store.to_vec()),
+            value: Binary(left.iavl_root().unwrap()),
+            leaf_prefix: Binary(vec![]),
+            path: vec![InnerOp {
+                prefix: Binary(vec![INNER_PREFIX]),
+                suffix: Binary([5u8; HASH_SIZE].to_vec()),
+            }],
+        };
+        let app_hash = multistore_proof.multistore_root().unwrap();
+
+        let left = ReadProof {
+            store_proof: KeyProof::Exist(left),
+            multistore_proof: multistore_proof.clone(),
+        };
+        let right = ReadProof {
+            store_proof: KeyProof::Exist(right),
+            multistore_proof,
+        };
+        (left, right, app_hash)
+    }
+
+    pub fn test_read_proof() {
+        // The compute store is the tree ((a, c), e), next to another store in the multistore
+        let (mut a, mut c, mut e) = (
+            iavl_leaf(b"a", b"1"),
+            iavl_leaf(b"c", b"3"),
+            iavl_leaf(b"e", b"5"),
+        );
+        let a_hash = a.leaf_hash(a.leaf_prefix.as_slice());
+        let c_hash = c.leaf_hash(c.leaf_prefix.as_slice());
+        let e_hash = e.leaf_hash(e.leaf_prefix.as_slice());
+        let ac_hash = node_hash(&a_hash, &iavl_inner(&c_hash, true));
+        a.path = vec![iavl_inner(&c_hash, true), iavl_inner(&e_hash, true)];
+        c.path = vec![iavl_inner(&a_hash, false), iavl_inner(&e_hash, true)];
+        e.path = vec![iavl_inner(&ac_hash, false)];
+        let store_root = e.iavl_root().unwrap();
+        assert_eq!(a.iavl_root().unwrap(), store_root);
+        assert_eq!(c.iavl_root().unwrap(), store_root);
+
+        let other_store = [5u8; HASH_SIZE];
+        let multistore_proof = ExistenceProof {
+            key: Binary(b"compute".to_vec()),
+            value: Binary(store_root),
+            leaf_prefix: Binary(vec![]),
+            path: vec![InnerOp {
+                prefix: Binary(vec![INNER_PREFIX]),
+                suffix: Binary(other_store.to_vec()),
+            }],
+        };
+        let app_hash = multistore_proof.multistore_root().unwrap();
+        let read = |store_proof| ReadProof {
+            store_proof,
+            multistore_proof: multistore_proof.clone(),
+        };
+        let absent = |key: &[u8], left: Option<&ExistenceProof>, right: Option<&ExistenceProof>| {
+            KeyProof::Nonexist(NonExistenceProof {
+                key: Binary(key.to_vec()),
+                left: left.cloned(),
+                right: right.cloned(),
+            })
+        };
+
+        // Keys with values
+        let proof = read(KeyProof::Exist(c.clone()));
+        assert_eq!(
+            proof.verify(b"compute", b"c", &app_hash).unwrap(),
+            Some(&b"3"[..])
+        );
+        assert!(proof.verify(b"compute", b"a", &app_hash).is_err());
+        assert!(proof.verify(b"bank", b"c", &app_hash).is_err());
+        assert!(proof.verify(b"compute", b"c", &[0u8; HASH_SIZE]).is_err());
+        let mut forged = c.clone();
+        forged.value = Binary(b"4".to_vec());
+        assert!(read(KeyProof::Exist(forged))
+            .verify(b"compute", b"c", &app_hash)
+            .is_err());
+
+        // Keys without values, between two keys or past the ends of the tree
+        for (key, left, right) in [
+            (&b"b"[..], Some(&a), Some(&c)),
+            (&b"d"[..], Some(&c), Some(&e)),
+            (&b"0"[..], None, Some(&a)),
+            (&b"f"[..], Some(&e), None),
+        ]
+        .iter()
+        {
+            let proof = read(absent(key, *left, *right));
+            assert_eq!(proof.verify(b"compute", key, &app_hash).unwrap(), None);
+        }
+
+        // Neighbors that aren't next to each other, or not around the key, hide the keys between
+        for (key, left, right) in [
+            (&b"d"[..], Some(&a), Some(&e)),
+            (&b"0"[..], None, Some(&c)),
+            (&b"f"[..], Some(&c), None),
+            (&b"c"[..], Some(&a), Some(&e)),
+            (&b"b"[..], Some(&c), Some(&e)),
+            (&b"b"[..], None, None),
+        ]
+        .iter()
+        {
+            let proof = read(absent(key, *left, *right));
+            assert!(proof.verify(b"compute", key, &app_hash).is_err());
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! Rotation of the key the state of a contract is encrypted with.
+//!
+//! The chain starts a rotation with the first chunk of a re-encryption, which records it in the
+//! state of the contract under a reserved key. The record is encrypted with the og contract key,
+//! so the host can't forge it, and the new epoch only starts at the block after the one it was
+//! recorded in. The epoch of every execution is derived from the record and its verified block
+//! height, rather than taken from the host.
+
+use std::convert::TryFrom;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cw_types_generic::BaseEnv;
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::CanonicalAddr;
+use enclave_crypto::{AESKey, Kdf, SIVEncryptable, HASH_SIZE, KEY_MANAGER};
+use enclave_ffi_types::{Ctx, EnclaveError};
+use enclave_utils::kv_cache::KvCache;
+
+#[cfg(feature = "light-client-validation")]
+use crate::contract_validation::verify_block_info;
+use crate::contract_validation::{validate_old_code_hash, ContractKey};
+use crate::cosmwasm_config::reserved_keys;
+use crate::cosmwasm_config::state_reencryption::MAX_ENTRIES_PER_CHUNK;
+use crate::db::{
+    derive_state_key, read_from_state, reencrypt_raw_entries, remove_from_state,
+    write_to_encrypted_state, KeyScheme, StateKeys,
+};
+use crate::storage_quota::StorageUsage;
+
+/// Info used to derive the key that seals the progress of re-encryptions
+const PROGRESS_SEALING_INFO: &[u8] = b"secret_state_reencryption_progress";
+
+/// The rotation of the state key of a contract to `epoch`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StateKeyRotation {
+    pub epoch: u32,
+    /// The first height at which the state is encrypted with the key of `epoch`
+    pub activation_height: u64,
+    /// Set by the last chunk of the re-encryption, after which entries are no longer read with
+    /// the key of the previous epoch
+    pub done: bool,
+}
+
+impl StateKeyRotation {
+    /// The epoch of the state at a height
+    pub fn epoch_at(&self, block_height: u64) -> u32 {
+        if block_height >= self.activation_height {
+            self.epoch
+        } else {
+            self.epoch.saturating_sub(1)
+        }
+    }
+
+    /// Whether entries may still be encrypted with the key of the previous epoch at a height
+    pub fn is_pending_at(&self, block_height: u64) -> bool {
+        self.epoch > 0 && !self.done && block_height >= self.activation_height
+    }
+}
+
+pub fn read_state_key_rotation(
+    context: &Ctx,
+    og_contract_key: &ContractKey,
+) -> Result<Option<StateKeyRotation>, EnclaveError> {
+    let (stored, _) = read_from_state(
+        reserved_keys::STATE_KEY_ROTATION,
+        context,
+        &StateKeys::first_epoch(og_contract_key),
+        false,
+        &mut KvCache::new(),
+        &[],
+        KeyScheme::Siv,
+    )
+    .map_err(EnclaveError::from)?;
+
+    match stored {
+        Some(stored) => serde_json::from_slice(&stored).map(Some).map_err(|err| {
+            warn!("stored state key rotation is malformed: {}", err);
+            EnclaveError::FailedToDeserialize
+        }),
+        None => Ok(None),
+    }
+}
+
+fn write_state_key_rotation(
+    context: &Ctx,
+    og_contract_key: &ContractKey,
+    rotation: &StateKeyRotation,
+    block_height: u64,
+) -> Result<(), EnclaveError> {
+    let serialized = serde_json::to_vec(rotation).map_err(|err| {
+        warn!("failed to serialize the state key rotation: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+
+    write_to_encrypted_state(
+        reserved_keys::STATE_KEY_ROTATION,
+        &serialized,
+        context,
+        og_contract_key,
+        &block_height.to_be_bytes(),
+        KeyScheme::Siv,
+    )
+    .map_err(EnclaveError::from)?;
+
+    Ok(())
+}
+
+/// Starts a rotation to the epoch after the current one, from the block after `block_height`.
+/// A rotation can only start once the previous one is done, since entries are only ever read
+/// with the keys of the current and the previous epochs.
+fn start_rotation(
+    current: Option<&StateKeyRotation>,
+    block_height: u64,
+) -> Result<StateKeyRotation, EnclaveError> {
+    let epoch = match current {
+        None => 1,
+        Some(current) if current.done => current.epoch.checked_add(1).ok_or_else(|| {
+            warn!("the state key of the contract can't be rotated anymore");
+            EnclaveError::ValidationFailure
+        })?,
+        Some(current) => {
+            warn!(
+                "tried to start a state key rotation while the rotation to epoch {} isn't done",
+                current.epoch
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    };
+
+    Ok(StateKeyRotation {
+        epoch,
+        activation_height: block_height + 1,
+        done: false,
+    })
+}
+
+/// The count of the storage usage of a contract follows its raw keys, which the re-encryption
+/// changes, so a count that didn't finish starts over once the rotation is done, see
+/// `storage_quota`
+fn restart_storage_count(
+    context: &Ctx,
+    og_contract_key: &ContractKey,
+    rotation: Option<&StateKeyRotation>,
+    block_height: u64,
+) -> Result<(), EnclaveError> {
+    let state_keys = StateKeys::for_rotation(og_contract_key, rotation, block_height)
+        .map_err(EnclaveError::from)?;
+    let (stored, _) = read_from_state(
+        reserved_keys::STORAGE_USAGE,
+        context,
+        &state_keys,
+        false,
+        &mut KvCache::new(),
+        &[],
+        KeyScheme::Siv,
+    )
+    .map_err(EnclaveError::from)?;
+
+    match StorageUsage::parse(stored.as_deref()).map_err(EnclaveError::from)? {
+        Some(usage) if !usage.is_counted() => {
+            remove_from_state(
+                reserved_keys::STORAGE_USAGE,
+                context,
+                &state_keys,
+                KeyScheme::Siv,
+            )
+            .map_err(EnclaveError::from)?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Entries must be strictly ascending, and continue where the previous chunk stopped
+fn check_chunk_order(
+    last_raw_key: Option<&Vec<u8>>,
+    entries: &[(Vec<u8>, Vec<u8>)],
+) -> Result<(), EnclaveError> {
+    let mut last_raw_key = last_raw_key;
+    for (raw_key, _) in entries {
+        if last_raw_key.map_or(false, |last_raw_key| raw_key <= last_raw_key) {
+            warn!("state entries for re-encryption are out of order");
+            return Err(EnclaveError::ValidationFailure);
+        }
+        last_raw_key = Some(raw_key);
+    }
+
+    Ok(())
+}
+
+/// The progress of re-encrypting the state of a contract. It is sealed by the enclave and
+/// stored by the chain between chunks, so the chain can't tamper with it.
+#[derive(Serialize, Deserialize, Debug)]
+struct ReencryptionProgress {
+    contract_address: Vec<u8>,
+    epoch: u32,
+    entries_reencrypted: u64,
+    /// The last raw key of the previous chunk. Chunks must continue after it.
+    last_raw_key: Option<Vec<u8>>,
+    done: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct ReencryptionResult {
+    /// The sealed progress, to be passed with the next chunk
+    progress: Binary,
+    entries_reencrypted: u64,
+    /// Set once an empty chunk was processed, after which the chain may stop using the
+    /// key of the previous epoch
+    done: bool,
+}
+
+/// Re-encrypts a chunk of the state of a contract from the state key of the previous epoch to
+/// the key of the current one. The first chunk (without a sealed progress) starts the rotation
+/// and carries no entries. From the next block on, the chain iterates over the raw state of the
+/// contract in ascending order and passes up to `MAX_ENTRIES_PER_CHUNK` entries each time, along
+/// with the sealed progress from the previous chunk.
+pub fn reencrypt_state_chunk(
+    context: Ctx,
+    env: &[u8],
+    sealed_progress: &[u8],
+    entries: &[u8],
+) -> Result<Vec<u8>, EnclaveError> {
+    let base_env: BaseEnv = serde_json::from_slice(env).map_err(|err| {
+        warn!("failed to deserialize env for state re-encryption: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let canonical_contract_address = CanonicalAddr::from_human(&base_env.0.contract.address)
+        .map_err(|err| {
+            warn!(
+                "failed to canonicalize the address of the re-encrypted contract: {}",
+                err
+            );
+            EnclaveError::from(err)
+        })?;
+
+    let code_hash = hex::decode(&base_env.0.contract_code_hash)
+        .ok()
+        .and_then(|code_hash| <[u8; HASH_SIZE]>::try_from(code_hash.as_slice()).ok())
+        .ok_or_else(|| {
+            warn!("got an invalid code hash for state re-encryption");
+            EnclaveError::FailedToDeserialize
+        })?;
+    validate_old_code_hash(&base_env, &canonical_contract_address, &code_hash)?;
+
+    // The rotation is recorded at, and its epoch derived from, the verified height
+    #[cfg(feature = "light-client-validation")]
+    verify_block_info(&base_env)?;
+    let block_height = base_env.0.block.height;
+
+    let og_contract_key: ContractKey = base_env.get_og_contract_key()?;
+    let rotation = read_state_key_rotation(&context, &og_contract_key)?;
+
+    let entries: Vec<(Vec<u8>, Vec<u8>)> = serde_json::from_slice(entries).map_err(|err| {
+        warn!(
+            "failed to deserialize state entries for re-encryption: {}",
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if entries.len() > MAX_ENTRIES_PER_CHUNK {
+        warn!(
+            "got {} state entries to re-encrypt, the limit is {}",
+            entries.len(),
+            MAX_ENTRIES_PER_CHUNK
+        );
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    if sealed_progress.is_empty() {
+        if !entries.is_empty() {
+            warn!("the chunk that starts a state key rotation can't carry entries");
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        let started = start_rotation(rotation.as_ref(), block_height)?;
+        restart_storage_count(&context, &og_contract_key, rotation.as_ref(), block_height)?;
+        write_state_key_rotation(&context, &og_contract_key, &started, block_height)?;
+        info!(
+            "started rotating the state key of {} to epoch {} from height {}",
+            base_env.0.contract.address, started.epoch, started.activation_height
+        );
+
+        let progress = ReencryptionProgress {
+            contract_address: canonical_contract_address.as_slice().to_vec(),
+            epoch: started.epoch,
+            entries_reencrypted: 0,
+            last_raw_key: None,
+            done: false,
+        };
+        return serialize_result(&progress);
+    }
+
+    let mut progress = unseal_progress(sealed_progress, &canonical_contract_address)?;
+    let mut rotation = match rotation {
+        Some(rotation)
+            if rotation.epoch == progress.epoch
+                && rotation.is_pending_at(block_height)
+                && !progress.done =>
+        {
+            rotation
+        }
+        _ => {
+            warn!(
+                "state re-encryption progress doesn't match the rotation of the contract: {:?} {:?}",
+                progress, rotation
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+    };
+
+    check_chunk_order(progress.last_raw_key.as_ref(), &entries)?;
+
+    if entries.is_empty() {
+        info!(
+            "finished re-encrypting the state of {} to epoch {} ({} entries)",
+            base_env.0.contract.address, rotation.epoch, progress.entries_reencrypted
+        );
+        progress.done = true;
+        rotation.done = true;
+        write_state_key_rotation(&context, &og_contract_key, &rotation, block_height)?;
+    } else {
+        let from_key = derive_state_key(&og_contract_key, rotation.epoch - 1)?;
+        let to_key = derive_state_key(&og_contract_key, rotation.epoch)?;
+
+        progress.entries_reencrypted +=
+            reencrypt_raw_entries(&context, &entries, &from_key, &to_key)?;
+        progress.last_raw_key = entries.last().map(|(raw_key, _)| raw_key.clone());
+    }
+
+    serialize_result(&progress)
+}
+
+fn serialize_result(progress: &ReencryptionProgress) -> Result<Vec<u8>, EnclaveError> {
+    let result = ReencryptionResult {
+        progress: Binary(seal_progress(progress)?),
+        entries_reencrypted: progress.entries_reencrypted,
+        done: progress.done,
+    };
+
+    serde_json::to_vec(&result).map_err(|err| {
+        warn!("failed to serialize state re-encryption result: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+fn progress_sealing_key() -> Result<AESKey, EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|err| {
+        error!("failed to get the consensus state ikm: {:?}", err);
+        EnclaveError::FailedUnseal
+    })?;
+
+    Ok(consensus_state_ikm
+        .current
+        .derive_key_from_this(PROGRESS_SEALING_INFO))
+}
+
+fn seal_progress(progress: &ReencryptionProgress) -> Result<Vec<u8>, EnclaveError> {
+    let serialized = serde_json::to_vec(progress).map_err(|err| {
+        warn!("failed to serialize state re-encryption progress: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+
+    // The encryption is deterministic, so all nodes produce the same sealed progress
+    progress_sealing_key()?
+        .encrypt_siv(&serialized, Some(&[progress.contract_address.as_slice()]))
+        .map_err(|err| {
+            warn!("failed to seal state re-encryption progress: {:?}", err);
+            EnclaveError::EncryptionError
+        })
+}
+
+fn unseal_progress(
+    sealed_progress: &[u8],
+    contract_address: &CanonicalAddr,
+) -> Result<ReencryptionProgress, EnclaveError> {
+    let serialized = progress_sealing_key()?
+        .decrypt_siv(sealed_progress, Some(&[contract_address.as_slice()]))
+        .map_err(|err| {
+            warn!("failed to unseal state re-encryption progress: {:?}", err);
+            EnclaveError::DecryptionError
+        })?;
+
+    serde_json::from_slice(&serialized).map_err(|err| {
+        warn!(
+            "failed to deserialize state re-encryption progress: {}",
+            err
+        );
+        EnclaveError::FailedToDeserialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::db::StateKeys;
+
+    pub fn test_state_key_rotation_epochs() {
+        let rotation = StateKeyRotation {
+            epoch: 1,
+            activation_height: 100,
+            done: false,
+        };
+
+        // The rotation only applies from the block after the one it was started in
+        assert_eq!(rotation.epoch_at(99), 0);
+        assert!(!rotation.is_pending_at(99));
+        assert_eq!(rotation.epoch_at(100), 1);
+        assert!(rotation.is_pending_at(100));
+        assert!(!StateKeyRotation {
+            done: true,
+            ..rotation
+        }
+        .is_pending_at(100));
+
+        // Before the activation the state keys are the ones of epoch 0
+        let og_contract_key = [7u8; 64];
+        let state_keys = StateKeys::for_rotation(&og_contract_key, Some(&rotation), 99).unwrap();
+        assert_eq!(state_keys.current, og_contract_key);
+        assert!(state_keys.previous.is_none());
+        let state_keys = StateKeys::for_rotation(&og_contract_key, None, 1000).unwrap();
+        assert_eq!(state_keys.current, og_contract_key);
+        assert!(state_keys.previous.is_none());
+
+        // A rotation starts at the next block, and only once the previous one is done
+        assert_eq!(start_rotation(None, 99).unwrap(), rotation);
+        assert!(start_rotation(Some(&rotation), 150).is_err());
+        let done = StateKeyRotation {
+            done: true,
+            ..rotation
+        };
+        assert_eq!(
+            start_rotation(Some(&done), 150).unwrap(),
+            StateKeyRotation {
+                epoch: 2,
+                activation_height: 151,
+                done: false,
+            }
+        );
+        assert!(start_rotation(
+            Some(&StateKeyRotation {
+                epoch: u32::MAX,
+                ..done
+            }),
+            150
+        )
+        .is_err());
+    }
+
+    pub fn test_check_chunk_order() {
+        let entry = |key: &[u8]| (key.to_vec(), vec![]);
+
+        assert!(check_chunk_order(None, &[]).is_ok());
+        assert!(check_chunk_order(None, &[entry(b"a"), entry(b"b")]).is_ok());
+        assert!(check_chunk_order(Some(&b"a".to_vec()), &[entry(b"b")]).is_ok());
+
+        // Chunks can't go back, or repeat entries
+        assert!(check_chunk_order(None, &[entry(b"b"), entry(b"a")]).is_err());
+        assert!(check_chunk_order(None, &[entry(b"a"), entry(b"a")]).is_err());
+        assert!(check_chunk_order(Some(&b"b".to_vec()), &[entry(b"a")]).is_err());
+        assert!(check_chunk_order(Some(&b"b".to_vec()), &[entry(b"b")]).is_err());
+    }
+
+    pub fn test_malformed_chunk_is_an_error() {
+        let og_contract_key = [7u8; 64];
+        let context = Ctx {
+            data: std::ptr::null_mut(),
+        };
+
+        // Entries that aren't state entries fail the chunk rather than the enclave
+        let entries = vec![(b"not a state key".to_vec(), b"not a value".to_vec())];
+        assert!(reencrypt_raw_entries(&context, &entries, &og_contract_key, &[8u8; 64]).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Dummy storage accesses of contracts that declare the `padded_storage_access` feature.
+//!
+//! The host sees the length of every encrypted key and value, so the dummies mirror the shapes
+//! of the contract's own accesses: the `n`th dummy has the key size, and for writes the value
+//! size, of the `n`th access of the execution, wrapping around. Their keys can't be reserved
+//! keys, which all share a prefix longer than many keys of contracts, so they are encrypted with
+//! a padding key that is derived from the contract key instead. They can't collide with the
+//! contract's entries, and the host can't tell them apart from them.
+
+use log::*;
+
+use enclave_crypto::{AESKey, Kdf, HASH_SIZE, KEY_MANAGER};
+
+use crate::contract_validation::ContractKey;
+use crate::cosmwasm_config::storage_access_padding::{DEFAULT_KEY_SIZE, DEFAULT_VALUE_SIZE};
+use crate::db::StateKeys;
+use crate::errors::WasmEngineError;
+
+/// Separates the padding keys of contracts from the other keys derived from the consensus state
+/// ikm
+const STORAGE_PADDING_DERIVATION_INFO: &[u8] = b"secret_storage_padding";
+
+/// Pads the number of storage accesses an execution made to `maximum`, or to the next multiple
+/// of it if the execution made more.
+pub fn padded_access_count(count: u64, maximum: u64) -> u64 {
+    if maximum == 0 {
+        return count;
+    }
+
+    match count % maximum {
+        0 if count != 0 => count,
+        remainder => count + (maximum - remainder),
+    }
+}
+
+/// The key and value sizes of the `slot`th dummy access, mirrored from the accesses of the
+/// execution. Executions that made no accesses of that kind get the default sizes.
+pub fn mirrored_sizes(sizes: &[(usize, usize)], slot: u64) -> (usize, usize) {
+    if sizes.is_empty() {
+        return (DEFAULT_KEY_SIZE, DEFAULT_VALUE_SIZE);
+    }
+
+    sizes[(slot % sizes.len() as u64) as usize]
+}
+
+/// The key of the `slot`th dummy storage access, `key_size` bytes long. Dummy reads and writes
+/// use the same slots, so a dummy read looks up a key that a previous execution may have
+/// written, like a real read does.
+pub fn padding_slot_key(slot: u64, key_size: usize) -> Vec<u8> {
+    let slot = slot.to_be_bytes();
+    let mut key = vec![0u8; key_size.saturating_sub(slot.len())];
+    key.extend_from_slice(&slot[slot.len().saturating_sub(key_size)..]);
+    key
+}
+
+fn derive_padding_key(consensus_state_ikm: &AESKey, og_contract_key: &ContractKey) -> ContractKey {
+    let mut derivation_data = STORAGE_PADDING_DERIVATION_INFO.to_vec();
+    derivation_data.extend_from_slice(og_contract_key);
+    let derived_key = consensus_state_ikm.derive_key_from_this(&derivation_data);
+
+    // Keep the sender id part of the contract key, and replace the authenticated part
+    let mut padding_key = *og_contract_key;
+    padding_key[HASH_SIZE..].copy_from_slice(derived_key.get());
+    padding_key
+}
+
+/// The keys the dummy storage accesses of a contract are encrypted with. They are derived from
+/// the genesis consensus state ikm, like the subkeys of contracts, so they don't change.
+pub fn padding_state_keys(og_contract_key: &ContractKey) -> Result<StateKeys, WasmEngineError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|err| {
+        error!("failed to get the consensus state ikm: {:?}", err);
+        WasmEngineError::EncryptionError
+    })?;
+
+    Ok(StateKeys::first_epoch(&derive_padding_key(
+        &consensus_state_ikm.genesis,
+        og_contract_key,
+    )))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::contract_validation::CONTRACT_KEY_LENGTH;
+
+    pub fn test_padded_access_count() {
+        assert_eq!(padded_access_count(0, 16), 16);
+        assert_eq!(padded_access_count(1, 16), 16);
+        assert_eq!(padded_access_count(16, 16), 16);
+        assert_eq!(padded_access_count(17, 16), 32);
+        assert_eq!(padded_access_count(33, 16), 48);
+        assert_eq!(padded_access_count(5, 0), 5);
+    }
+
+    pub fn test_padding_slot_key() {
+        // The key has the requested size, however short or long
+        for key_size in &[0, 1, 6, 8, 40] {
+            assert_eq!(padding_slot_key(3, *key_size).len(), *key_size);
+        }
+        assert_ne!(padding_slot_key(0, 6), padding_slot_key(1, 6));
+        assert_eq!(padding_slot_key(3, 6), padding_slot_key(3, 6));
+        assert_eq!(padding_slot_key(258, 2), vec![1, 2]);
+        assert_eq!(padding_slot_key(258, 1), vec![2]);
+    }
+
+    pub fn test_mirrored_sizes() {
+        let sizes = [(6, 100), (40, 8)];
+        assert_eq!(mirrored_sizes(&sizes, 0), (6, 100));
+        assert_eq!(mirrored_sizes(&sizes, 1), (40, 8));
+        assert_eq!(mirrored_sizes(&sizes, 2), (6, 100));
+        assert_eq!(
+            mirrored_sizes(&[], 5),
+            (DEFAULT_KEY_SIZE, DEFAULT_VALUE_SIZE)
+        );
+    }
+
+    pub fn test_derive_padding_key() {
+        let ikm = AESKey::new_from_slice(&[1u8; 32]);
+        let contract_key = [2u8; CONTRACT_KEY_LENGTH];
+        let padding_key = derive_padding_key(&ikm, &contract_key);
+
+        assert_eq!(padding_key[..HASH_SIZE], contract_key[..HASH_SIZE]);
+        assert_ne!(padding_key, contract_key);
+        assert_eq!(derive_padding_key(&ikm, &contract_key), padding_key);
+        assert_ne!(
+            derive_padding_key(&ikm, &[3u8; CONTRACT_KEY_LENGTH])[HASH_SIZE..],
+            padding_key[HASH_SIZE..]
+        );
+    }
+}
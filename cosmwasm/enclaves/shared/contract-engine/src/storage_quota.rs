@@ -0,0 +1,407 @@
+//! The quota of the storage a contract can use.
+//!
+//! The engine accounts the bytes of the raw entries, encrypted keys and values, that the host
+//! stores for each contract, in a key that is reserved for the enclave. The host reports the
+//! size of the entry that each write and remove replaces, so the accounting reads nothing of
+//! its own. What the writes and removes of an execution stored is applied to the usage when its
+//! cache is flushed. A flush that takes the contract over `storage_quota` of the `WasmCosts`
+//! fails the execution, and the host drops its writes. The host could misreport the sizes, like
+//! the gas it used, which only changes the verdict of its own node.
+//!
+//! Contracts that stored entries before the quota was activated have no usage yet. Counting all
+//! their raw entries could take more gas than an execution has, so the chain counts them at the
+//! end of blocks instead, a page of raw entries at a time, in the order of their raw keys. While
+//! a contract is counted, its executions only measure the entries that the count already passed,
+//! and the count measures the others as they are when it gets to them. The quota is enforced
+//! once the count is done, and executions of contracts whose count didn't start don't account
+//! anything. Contracts that are instantiated after the quota was activated start from nothing.
+//! The usage itself isn't part of it.
+//!
+//! Gas pays for writes, but not for keeping what was written. So that a contract approaching
+//! its quota pays for the space it still takes, the bytes it grows by past
+//! `storage_surcharge_start_percent` of the quota are surcharged, by up to
+//! `storage_surcharge_per_byte` per byte as the usage reaches the quota.
+
+use std::convert::{TryFrom, TryInto};
+
+use log::*;
+use serde::Serialize;
+
+use cw_types_generic::BaseEnv;
+use cw_types_v010::types::CanonicalAddr;
+use enclave_cosmos_types::feature_activation::{is_active, set_active_block, Feature};
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::{Ctx, EnclaveError};
+use enclave_utils::kv_cache::KvCache;
+
+#[cfg(feature = "light-client-validation")]
+use crate::contract_validation::verify_block_info;
+use crate::contract_validation::{validate_old_code_hash, ContractKey};
+use crate::cosmwasm_config::reserved_keys;
+use crate::db::{
+    count_raw_entries_page, raw_state_key, read_from_state, write_to_encrypted_state, KeyScheme,
+    StateKeys, StoredBytes,
+};
+use crate::errors::{WasmEngineError, WasmEngineResult};
+use crate::gas::WasmCosts;
+use crate::state_reencryption::read_state_key_rotation;
+
+/// The bytes of the raw entries of a contract
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StorageUsage {
+    bytes: u64,
+    /// While the usage is counted, the last raw key that was counted, or an empty key if none was
+    counted_until: Option<Vec<u8>>,
+}
+
+/// Follows the bytes of a usage that is being counted
+const COUNTING: u8 = 1;
+
+impl StorageUsage {
+    pub fn new(bytes: u64) -> Self {
+        Self {
+            bytes,
+            counted_until: None,
+        }
+    }
+
+    /// A usage whose count starts, from the first raw entry
+    pub fn counting() -> Self {
+        Self {
+            bytes: 0,
+            counted_until: Some(vec![]),
+        }
+    }
+
+    /// The usage that is stored for a contract, or `None` if its count didn't start yet
+    pub fn parse(stored: Option<&[u8]>) -> WasmEngineResult<Option<Self>> {
+        let stored = match stored {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        let wrong_size = || {
+            warn!("stored storage usage has a wrong size: {}", stored.len());
+            WasmEngineError::DeserializationError
+        };
+        if stored.len() < 8 {
+            return Err(wrong_size());
+        }
+
+        let (bytes, counted_until) = stored.split_at(8);
+        let bytes = u64::from_be_bytes(bytes.try_into().map_err(|_| wrong_size())?);
+        let counted_until = match counted_until.split_first() {
+            None => None,
+            Some((&COUNTING, counted_until)) => Some(counted_until.to_vec()),
+            Some((tag, _)) => {
+                warn!("stored storage usage has an unknown tag: {}", tag);
+                return Err(WasmEngineError::DeserializationError);
+            }
+        };
+
+        Ok(Some(Self {
+            bytes,
+            counted_until,
+        }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut serialized = self.bytes.to_be_bytes().to_vec();
+        if let Some(counted_until) = &self.counted_until {
+            serialized.push(COUNTING);
+            serialized.extend_from_slice(counted_until);
+        }
+        serialized
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn is_counted(&self) -> bool {
+        self.counted_until.is_none()
+    }
+
+    /// While the usage is counted, the last raw key that was counted, see `measure_stored_bytes`
+    pub fn counted_until(&self) -> Option<&[u8]> {
+        self.counted_until.as_deref()
+    }
+
+    /// Adds the bytes of a page of the count, whose last raw key is `last_raw_key`, or `None` if
+    /// it was the last page, which completes the count
+    pub fn count_page(&mut self, bytes: u64, last_raw_key: Option<Vec<u8>>) {
+        self.bytes = self.bytes.saturating_add(bytes);
+        self.counted_until = last_raw_key;
+    }
+
+    /// Applies the bytes that the host reported storing and removing. Returns the gas surcharge
+    /// of the growth, or fails if it takes the usage over the quota. Neither applies before the
+    /// usage is counted.
+    pub fn apply(&mut self, stored: StoredBytes, costs: &WasmCosts) -> WasmEngineResult<u64> {
+        let usage = self
+            .bytes
+            .saturating_sub(stored.removed)
+            .saturating_add(stored.added);
+        if !self.is_counted() {
+            self.bytes = usage;
+            return Ok(0);
+        }
+
+        if usage > self.bytes && usage > costs.storage_quota {
+            debug!(
+                "storage usage of {} bytes would exceed the quota of {} bytes",
+                usage, costs.storage_quota
+            );
+            return Err(WasmEngineError::StorageQuotaExceeded);
+        }
+
+        let surcharge = surcharge(self.bytes, usage, costs);
+        self.bytes = usage;
+        Ok(surcharge)
+    }
+}
+
+/// The surcharge of growing from `from` to `to` bytes. The surcharge per byte grows linearly
+/// from 0 at the start of the surcharge to `storage_surcharge_per_byte` at the quota, so the
+/// surcharge of a growth is the area under that line.
+pub fn surcharge(from: u64, to: u64, costs: &WasmCosts) -> u64 {
+    let quota = costs.storage_quota as u128;
+    let start = quota * costs.storage_surcharge_start_percent.min(100) as u128 / 100;
+    let span = quota - start;
+    if to <= from || span == 0 {
+        return 0;
+    }
+
+    let over_before = (from as u128).saturating_sub(start);
+    let over_after = (to as u128).saturating_sub(start);
+    let area = over_after * over_after - over_before * over_before;
+    let surcharge = area * costs.storage_surcharge_per_byte as u128 / (2 * span);
+
+    surcharge.min(u64::MAX as u128) as u64
+}
+
+#[derive(Serialize, Debug)]
+struct CountResult {
+    /// Whether the usage of the contract is counted, so the chain can move on to the next one
+    done: bool,
+    /// Whether the count can't run yet, e.g. while the state of the contract is re-encrypted
+    waiting: bool,
+}
+
+impl CountResult {
+    const WAITING: Self = Self {
+        done: false,
+        waiting: true,
+    };
+
+    fn counted(done: bool) -> Self {
+        Self {
+            done,
+            waiting: false,
+        }
+    }
+}
+
+/// Counts a page of the raw entries of a contract whose usage isn't counted yet. The chain calls
+/// it at the end of blocks, for one contract after the other, until the count of each is done.
+/// The count waits while the state of the contract is re-encrypted, since that changes its raw
+/// keys.
+pub fn count_storage_usage(context: Ctx, env: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    let base_env: BaseEnv = serde_json::from_slice(env).map_err(|err| {
+        warn!("failed to deserialize env for the storage count: {}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let canonical_contract_address = CanonicalAddr::from_human(&base_env.0.contract.address)
+        .map_err(|err| {
+            warn!(
+                "failed to canonicalize the address of the counted contract: {}",
+                err
+            );
+            EnclaveError::from(err)
+        })?;
+
+    let code_hash = hex::decode(&base_env.0.contract_code_hash)
+        .ok()
+        .and_then(|code_hash| <[u8; HASH_SIZE]>::try_from(code_hash.as_slice()).ok())
+        .ok_or_else(|| {
+            warn!("got an invalid code hash for the storage count");
+            EnclaveError::FailedToDeserialize
+        })?;
+    validate_old_code_hash(&base_env, &canonical_contract_address, &code_hash)?;
+
+    #[cfg(feature = "light-client-validation")]
+    verify_block_info(&base_env)?;
+    let block_height = base_env.0.block.height;
+
+    set_active_block(&base_env.0.block.chain_id, block_height);
+    if !is_active(Feature::StorageQuota) {
+        return serialize_result(&CountResult::WAITING);
+    }
+
+    let og_contract_key: ContractKey = base_env.get_og_contract_key()?;
+    let rotation = read_state_key_rotation(&context, &og_contract_key)?;
+    if rotation.map_or(false, |rotation| !rotation.done) {
+        return serialize_result(&CountResult::WAITING);
+    }
+    let state_keys = StateKeys::for_rotation(&og_contract_key, rotation.as_ref(), block_height)
+        .map_err(EnclaveError::from)?;
+
+    let (stored, _) = read_from_state(
+        reserved_keys::STORAGE_USAGE,
+        &context,
+        &state_keys,
+        false,
+        &mut KvCache::new(),
+        &[],
+        KeyScheme::Siv,
+    )
+    .map_err(EnclaveError::from)?;
+    let mut usage = match StorageUsage::parse(stored.as_deref()).map_err(EnclaveError::from)? {
+        Some(usage) if usage.is_counted() => return serialize_result(&CountResult::counted(true)),
+        Some(usage) => usage,
+        None => StorageUsage::counting(),
+    };
+
+    let usage_raw_key = raw_state_key(reserved_keys::STORAGE_USAGE, &state_keys.current)
+        .map_err(EnclaveError::from)?;
+    let counted_until = usage.counted_until().unwrap_or_default().to_vec();
+    let (bytes, last_raw_key, _) = count_raw_entries_page(&context, &counted_until, &usage_raw_key)
+        .map_err(EnclaveError::from)?;
+    usage.count_page(bytes, last_raw_key);
+
+    write_to_encrypted_state(
+        reserved_keys::STORAGE_USAGE,
+        &usage.serialize(),
+        &context,
+        &state_keys.current,
+        &block_height.to_be_bytes(),
+        KeyScheme::Siv,
+    )
+    .map_err(EnclaveError::from)?;
+
+    if usage.is_counted() {
+        info!(
+            "counted the storage usage of {}: {} bytes",
+            base_env.0.contract.address,
+            usage.bytes()
+        );
+    }
+    serialize_result(&CountResult::counted(usage.is_counted()))
+}
+
+fn serialize_result(result: &CountResult) -> Result<Vec<u8>, EnclaveError> {
+    serde_json::to_vec(result).map_err(|err| {
+        warn!(
+            "failed to serialize the result of the storage count: {}",
+            err
+        );
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn costs() -> WasmCosts {
+        WasmCosts {
+            storage_quota: 1000,
+            storage_surcharge_start_percent: 80,
+            storage_surcharge_per_byte: 100,
+            ..WasmCosts::default()
+        }
+    }
+
+    fn stored(added: u64, removed: u64) -> StoredBytes {
+        StoredBytes { added, removed }
+    }
+
+    pub fn test_storage_usage() {
+        let costs = costs();
+        let mut usage = StorageUsage::new(0);
+
+        // New entries grow the usage, and rewrites and removes replace their size
+        assert_eq!(usage.apply(stored(300, 0), &costs).unwrap(), 0);
+        assert_eq!(usage.apply(stored(500, 300), &costs).unwrap(), 0);
+        assert_eq!(usage.bytes(), 500);
+        assert_eq!(usage.apply(stored(0, 500), &costs).unwrap(), 0);
+        assert_eq!(usage.bytes(), 0);
+
+        // Growth past the quota fails and leaves the usage as it was
+        usage.apply(stored(900, 0), &costs).unwrap();
+        assert!(matches!(
+            usage.apply(stored(101, 0), &costs),
+            Err(WasmEngineError::StorageQuotaExceeded)
+        ));
+        assert_eq!(usage.bytes(), 900);
+        assert!(usage.apply(stored(150, 50), &costs).is_ok());
+        // Shrinking is allowed even over the quota, e.g. after governance lowered it
+        let lowered = WasmCosts {
+            storage_quota: 500,
+            ..costs.clone()
+        };
+        assert!(usage.apply(stored(50, 100), &lowered).is_ok());
+        assert_eq!(usage.bytes(), 950);
+
+        // A usage that was never stored isn't 0, it's counted from the entries instead
+        assert_eq!(StorageUsage::parse(None).unwrap(), None);
+        let parsed = StorageUsage::parse(Some(&usage.serialize())).unwrap();
+        assert_eq!(parsed, Some(usage.clone()));
+        assert_eq!(usage.serialize(), 950_u64.to_be_bytes().to_vec());
+        assert!(StorageUsage::parse(Some(b"short")).is_err());
+        assert!(StorageUsage::parse(Some(&[0, 0, 0, 0, 0, 0, 0, 0, 2])).is_err());
+    }
+
+    pub fn test_storage_usage_count() {
+        let costs = costs();
+        let mut usage = StorageUsage::counting();
+        assert!(!usage.is_counted());
+        assert_eq!(usage.counted_until(), Some(&[][..]));
+
+        // Pages of the count add up, and so does what is stored meanwhile, without a quota or a
+        // surcharge
+        usage.count_page(600, Some(b"raw key".to_vec()));
+        assert_eq!(usage.apply(stored(600, 100), &costs).unwrap(), 0);
+        assert_eq!(usage.bytes(), 1100);
+        assert_eq!(usage.counted_until(), Some(&b"raw key"[..]));
+        let parsed = StorageUsage::parse(Some(&usage.serialize())).unwrap();
+        assert_eq!(parsed, Some(usage.clone()));
+
+        // The last page completes the count, and the quota applies from then on
+        usage.count_page(100, None);
+        assert!(usage.is_counted());
+        assert_eq!(usage.counted_until(), None);
+        assert_eq!(usage.bytes(), 1200);
+        assert!(matches!(
+            usage.apply(stored(1, 0), &costs),
+            Err(WasmEngineError::StorageQuotaExceeded)
+        ));
+        assert_eq!(
+            StorageUsage::parse(Some(&usage.serialize())).unwrap(),
+            Some(StorageUsage::new(1200))
+        );
+    }
+
+    pub fn test_storage_surcharge() {
+        let costs = costs();
+
+        // Nothing until 80% of the quota
+        assert_eq!(surcharge(0, 800, &costs), 0);
+        assert_eq!(surcharge(800, 700, &costs), 0);
+        // Then up to 100 per byte at the quota: 200 bytes * 100 / 2
+        assert_eq!(surcharge(0, 1000, &costs), 10_000);
+        assert_eq!(surcharge(800, 1000, &costs), 10_000);
+        // Bytes closer to the quota cost more
+        assert!(surcharge(900, 1000, &costs) > surcharge(800, 900, &costs));
+        assert_eq!(
+            surcharge(800, 900, &costs) + surcharge(900, 1000, &costs),
+            10_000
+        );
+
+        let no_surcharge = WasmCosts {
+            storage_surcharge_start_percent: 100,
+            ..costs
+        };
+        assert_eq!(surcharge(0, 1000, &no_surcharge), 0);
+    }
+}
@@ -0,0 +1,144 @@
+//! The storage keys that an execution read and wrote, for the host to schedule transactions that
+//! don't touch the same keys in parallel, and to index the state they touch.
+//!
+//! The keys are hashed with a salt of the block, so that the host can match the keys of the
+//! transactions of a block with each other without learning the plaintext keys, and can't link
+//! the keys of a contract across blocks.
+
+use std::collections::BTreeSet;
+
+use log::*;
+use serde::Serialize;
+
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::CanonicalAddr;
+use enclave_crypto::{AESKey, Hmac, Kdf, HMAC_SIGNATURE_SIZE, KEY_MANAGER};
+use enclave_ffi_types::EnclaveError;
+
+const SALT_LABEL: &[u8] = b"touched_keys";
+
+#[derive(Default, Debug)]
+pub struct TouchedKeys {
+    reads: BTreeSet<Vec<u8>>,
+    writes: BTreeSet<Vec<u8>>,
+}
+
+impl TouchedKeys {
+    pub fn read(&mut self, key: &[u8]) {
+        if !self.reads.contains(key) {
+            self.reads.insert(key.to_vec());
+        }
+    }
+
+    /// Removed keys are written
+    pub fn write(&mut self, key: &[u8]) {
+        if !self.writes.contains(key) {
+            self.writes.insert(key.to_vec());
+        }
+    }
+
+    /// Returns the JSON of the hashed keys, see `HashedTouchedKeys`
+    pub fn to_hashed(
+        &self,
+        block_height: u64,
+        contract_address: &CanonicalAddr,
+    ) -> Result<Vec<u8>, EnclaveError> {
+        let salt = block_salt(block_height)?;
+        let hashed = HashedTouchedKeys {
+            reads: hash_keys(&salt, contract_address, &self.reads),
+            writes: hash_keys(&salt, contract_address, &self.writes),
+        };
+
+        serde_json::to_vec(&hashed).map_err(|err| {
+            warn!("failed to serialize the touched keys: {:?}", err);
+            EnclaveError::FailedToSerialize
+        })
+    }
+}
+
+/// The hashes are sorted, so their order doesn't reveal the order of the keys
+#[derive(Serialize)]
+struct HashedTouchedKeys {
+    reads: Vec<Binary>,
+    writes: Vec<Binary>,
+}
+
+fn block_salt(block_height: u64) -> Result<AESKey, EnclaveError> {
+    let consensus_state_ikm = KEY_MANAGER.get_consensus_state_ikm().map_err(|err| {
+        error!("failed to get the consensus state ikm: {:?}", err);
+        EnclaveError::FailedUnseal
+    })?;
+
+    Ok(derive_block_salt(
+        &consensus_state_ikm.current,
+        block_height,
+    ))
+}
+
+fn derive_block_salt(ikm: &AESKey, block_height: u64) -> AESKey {
+    let mut data = SALT_LABEL.to_vec();
+    data.extend_from_slice(&block_height.to_be_bytes());
+    ikm.derive_key_from_this(&data)
+}
+
+/// Keys are hashed with the address of their contract, because the host sees which contracts a
+/// transaction executes but the same key of two contracts is not a conflict
+fn hash_key(
+    salt: &AESKey,
+    contract_address: &CanonicalAddr,
+    key: &[u8],
+) -> [u8; HMAC_SIGNATURE_SIZE] {
+    let address = contract_address.as_slice();
+    let mut data = Vec::with_capacity(4 + address.len() + key.len());
+    data.extend_from_slice(&(address.len() as u32).to_be_bytes());
+    data.extend_from_slice(address);
+    data.extend_from_slice(key);
+    salt.sign_sha_256(&data)
+}
+
+fn hash_keys(
+    salt: &AESKey,
+    contract_address: &CanonicalAddr,
+    keys: &BTreeSet<Vec<u8>>,
+) -> Vec<Binary> {
+    let hashes: BTreeSet<[u8; HMAC_SIGNATURE_SIZE]> = keys
+        .iter()
+        .map(|key| hash_key(salt, contract_address, key))
+        .collect();
+
+    hashes
+        .into_iter()
+        .map(|hash| Binary(hash.to_vec()))
+        .collect()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_hash_touched_keys() {
+        let ikm = AESKey::new_from_slice(&[1u8; 32]);
+        let salt = derive_block_salt(&ikm, 10);
+        let contract = CanonicalAddr(Binary(vec![2u8; 20]));
+        let other_contract = CanonicalAddr(Binary(vec![3u8; 20]));
+
+        let hash = hash_key(&salt, &contract, b"balance");
+        assert_eq!(hash_key(&salt, &contract, b"balance"), hash);
+        assert_ne!(hash_key(&salt, &contract, b"balances"), hash);
+        assert_ne!(hash_key(&salt, &other_contract, b"balance"), hash);
+        // The same key hashes differently in another block
+        assert_ne!(
+            hash_key(&derive_block_salt(&ikm, 11), &contract, b"balance"),
+            hash
+        );
+
+        let mut keys = BTreeSet::new();
+        keys.insert(b"a".to_vec());
+        keys.insert(b"b".to_vec());
+        keys.insert(b"c".to_vec());
+        let hashes = hash_keys(&salt, &contract, &keys);
+        assert_eq!(hashes.len(), 3);
+        assert!(hashes.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(hashes.contains(&Binary(hash_key(&salt, &contract, b"b").to_vec())));
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::Deserialize;
+
+use cw_types_generic::BaseEnv;
+
+use crate::cosmwasm_config::transient_storage::MAX_TRANSIENT_BYTES_PER_TX;
+use crate::io::RawWasmOutput;
+use crate::panic_isolation::LockOrRecover;
+use crate::pending_replies::TxPosition;
+use crate::wasm3::Engine;
+
+/// Where an execution is in its transaction, as the host flagged it in env
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct TransientInfo {
+    /// Whether the transaction is delivered in a block, rather than checked or simulated
+    #[serde(default)]
+    pub deliver_tx: bool,
+    /// The position of the execution among the executions of the transaction
+    #[serde(default)]
+    pub execution: u32,
+    /// Set in the reply to a submessage that failed, to the position of the first execution of
+    /// the submessage
+    #[serde(default)]
+    pub reverted_execution: Option<u32>,
+}
+
+/// The transaction and the contract that transient entries belong to
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransientScope {
+    tx: TxPosition,
+    tx_hash: String,
+    contract: String,
+    execution: u32,
+    /// The entries of executions that aren't part of a delivered transaction, in CheckTx or in
+    /// a simulation, are only kept for the execution itself
+    deliver_tx: bool,
+}
+
+impl TransientScope {
+    pub fn from_env(env: &BaseEnv, info: &TransientInfo) -> Self {
+        TransientScope {
+            tx: TxPosition::from_env(env),
+            tx_hash: env
+                .0
+                .transaction
+                .as_ref()
+                .map(|tx| tx.hash.clone())
+                .unwrap_or_default(),
+            contract: env.0.contract.address.0.clone(),
+            execution: info.execution,
+            deliver_tx: info.deliver_tx && env.0.transaction.is_some(),
+        }
+    }
+}
+
+/// A write that was kept, with the value it replaced so it can be reverted
+struct JournalEntry {
+    execution: u32,
+    contract: String,
+    key: Vec<u8>,
+    previous: Option<Vec<u8>>,
+}
+
+/// Entries that contracts keep for the rest of the transaction with `transient_write`. They
+/// live in the memory of the enclave, and are discarded once a contract is executed in another
+/// delivered transaction.
+#[derive(Default)]
+struct TransientStorage {
+    tx: Option<(TxPosition, String)>,
+    contracts: HashMap<String, HashMap<Vec<u8>, Vec<u8>>>,
+    journal: Vec<JournalEntry>,
+    size: usize,
+}
+
+impl TransientStorage {
+    fn is_of(&self, scope: &TransientScope) -> bool {
+        match &self.tx {
+            Some((tx, tx_hash)) => scope.deliver_tx && *tx == scope.tx && *tx_hash == scope.tx_hash,
+            None => false,
+        }
+    }
+
+    fn entries(&self, scope: &TransientScope) -> Option<&HashMap<Vec<u8>, Vec<u8>>> {
+        if !self.is_of(scope) {
+            return None;
+        }
+        self.contracts.get(&scope.contract)
+    }
+
+    fn read(&self, scope: &TransientScope, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries(scope)?.get(key).cloned()
+    }
+
+    fn size(&self, scope: &TransientScope) -> usize {
+        if self.is_of(scope) {
+            self.size
+        } else {
+            0
+        }
+    }
+
+    fn commit(&mut self, scope: &TransientScope, writes: HashMap<Vec<u8>, Vec<u8>>) {
+        if !scope.deliver_tx {
+            return;
+        }
+        if !self.is_of(scope) {
+            *self = TransientStorage {
+                tx: Some((scope.tx, scope.tx_hash.clone())),
+                ..Default::default()
+            };
+        }
+
+        let entries = self.contracts.entry(scope.contract.clone()).or_default();
+        for (key, value) in writes {
+            self.size += key.len() + value.len();
+            let previous = entries.insert(key.clone(), value);
+            if let Some(previous) = &previous {
+                self.size -= key.len() + previous.len();
+            }
+            self.journal.push(JournalEntry {
+                execution: scope.execution,
+                contract: scope.contract.clone(),
+                key,
+                previous,
+            });
+        }
+    }
+
+    /// Discards the entries that were kept by the executions from `execution` on
+    fn revert(&mut self, scope: &TransientScope, execution: u32) {
+        if !self.is_of(scope) {
+            return;
+        }
+
+        while self
+            .journal
+            .last()
+            .map_or(false, |entry| entry.execution >= execution)
+        {
+            let entry = self.journal.pop().unwrap();
+            let entries = self.contracts.entry(entry.contract).or_default();
+            if let Some(value) = entries.remove(&entry.key) {
+                self.size -= entry.key.len() + value.len();
+            }
+            if let Some(previous) = entry.previous {
+                self.size += entry.key.len() + previous.len();
+                entries.insert(entry.key, previous);
+            }
+        }
+    }
+}
+
+lazy_static! {
+    static ref TRANSIENT_STORAGE: SgxMutex<TransientStorage> =
+        SgxMutex::new(TransientStorage::default());
+}
+
+/// Reads an entry that the contract wrote earlier in the transaction
+pub fn read_transient(scope: &TransientScope, key: &[u8]) -> Option<Vec<u8>> {
+    TRANSIENT_STORAGE.lock_or_recover().read(scope, key)
+}
+
+/// Whether `extra_bytes` more bytes fit in the transient storage of the transaction
+pub fn fits_in_transient_storage(scope: &TransientScope, extra_bytes: usize) -> bool {
+    let size = TRANSIENT_STORAGE.lock_or_recover().size(scope);
+    size.saturating_add(extra_bytes) <= MAX_TRANSIENT_BYTES_PER_TX
+}
+
+/// Discards the entries that a submessage kept, when the reply to it is executed after it
+/// failed. Like the state that the host reverts, this covers the entries of every execution
+/// from the first one of the submessage on.
+pub fn revert_transient_writes(scope: &TransientScope, reverted_execution: u32) {
+    trace!(
+        "reverting the transient writes of executions from {}",
+        reverted_execution
+    );
+    TRANSIENT_STORAGE
+        .lock_or_recover()
+        .revert(scope, reverted_execution);
+}
+
+/// Keeps the entries the contract wrote with `transient_write` for the rest of the transaction,
+/// if the contract succeeded and the transaction is delivered. Like its state, the entries of a
+/// contract that failed are discarded, and so are the ones of a submessage that failed, once
+/// the reply to it is executed.
+pub fn commit_transient_writes(engine: &mut Engine, output: &[u8]) {
+    let (scope, writes) = engine.take_transient_writes();
+    let scope = match scope {
+        Some(scope) if !writes.is_empty() => scope,
+        _ => return,
+    };
+
+    if let Ok(RawWasmOutput::Err { .. }) = serde_json::from_slice::<RawWasmOutput>(output) {
+        trace!(
+            "discarding {} transient writes of a failed contract",
+            writes.len()
+        );
+        return;
+    }
+
+    TRANSIENT_STORAGE.lock_or_recover().commit(&scope, writes);
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn scope(tx_index: u32, contract: &str) -> TransientScope {
+        TransientScope {
+            tx: TxPosition {
+                height: 1,
+                tx_index: Some(tx_index),
+            },
+            tx_hash: format!("hash{}", tx_index),
+            contract: contract.to_string(),
+            execution: 0,
+            deliver_tx: true,
+        }
+    }
+
+    fn write(storage: &mut TransientStorage, scope: &TransientScope, key: &[u8], value: &[u8]) {
+        let mut writes = HashMap::new();
+        writes.insert(key.to_vec(), value.to_vec());
+        storage.commit(scope, writes);
+    }
+
+    pub fn test_transient_storage() {
+        let mut storage = TransientStorage::default();
+        let a = scope(0, "secret1a");
+        let b = scope(0, "secret1b");
+
+        write(&mut storage, &a, b"key", b"value");
+        assert_eq!(storage.read(&a, b"key"), Some(b"value".to_vec()));
+        assert_eq!(storage.size(&a), 8);
+
+        // Entries are scoped to the contract
+        assert_eq!(storage.read(&b, b"key"), None);
+
+        write(&mut storage, &a, b"key", b"v");
+        assert_eq!(storage.read(&a, b"key"), Some(b"v".to_vec()));
+        assert_eq!(storage.size(&a), 4);
+
+        // to delivered transactions, so CheckTx and simulations don't share them
+        let checked = TransientScope {
+            deliver_tx: false,
+            ..a.clone()
+        };
+        assert_eq!(storage.read(&checked, b"key"), None);
+        assert_eq!(storage.size(&checked), 0);
+        write(&mut storage, &checked, b"key", b"checked");
+        assert_eq!(storage.read(&a, b"key"), Some(b"v".to_vec()));
+
+        // to the hash of the transaction
+        let other_hash = TransientScope {
+            tx_hash: "other".to_string(),
+            ..a.clone()
+        };
+        assert_eq!(storage.read(&other_hash, b"key"), None);
+
+        // and to the transaction
+        let next_tx = scope(1, "secret1a");
+        assert_eq!(storage.read(&next_tx, b"key"), None);
+        assert_eq!(storage.size(&next_tx), 0);
+
+        write(&mut storage, &next_tx, b"other", b"value");
+        assert_eq!(storage.read(&a, b"key"), None);
+        assert_eq!(storage.read(&next_tx, b"key"), None);
+        assert_eq!(storage.size(&next_tx), 10);
+    }
+
+    pub fn test_transient_writes_of_failed_submessages_are_reverted() {
+        let mut storage = TransientStorage::default();
+        let parent = scope(0, "secret1a");
+        write(&mut storage, &parent, b"key", b"parent");
+
+        // A submessage that executes another contract, which writes to its own entries and to
+        // the ones of the parent when it calls it back
+        let submessage = TransientScope {
+            execution: 1,
+            ..scope(0, "secret1b")
+        };
+        write(&mut storage, &submessage, b"key", b"submessage");
+        let callback = TransientScope {
+            execution: 2,
+            ..parent.clone()
+        };
+        write(&mut storage, &callback, b"key", b"callback");
+        write(&mut storage, &callback, b"new", b"callback");
+        assert_eq!(storage.read(&parent, b"key"), Some(b"callback".to_vec()));
+
+        // The submessage failed, and the reply to it reverts its executions
+        storage.revert(&parent, 1);
+        assert_eq!(storage.read(&parent, b"key"), Some(b"parent".to_vec()));
+        assert_eq!(storage.read(&parent, b"new"), None);
+        assert_eq!(storage.read(&submessage, b"key"), None);
+        assert_eq!(storage.size(&parent), 9);
+
+        // Reverting in another transaction has no effect
+        let other = TransientScope {
+            tx_hash: "other".to_string(),
+            ..parent.clone()
+        };
+        storage.revert(&other, 0);
+        assert_eq!(storage.read(&parent, b"key"), Some(b"parent".to_vec()));
+    }
+}
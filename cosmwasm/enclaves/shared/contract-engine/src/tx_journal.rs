@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::SgxMutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::Serialize;
+
+use cw_types_generic::BaseEnv;
+use enclave_cosmos_types::types::SigInfo;
+use enclave_crypto::{sha_256, Ed25519PublicKey};
+use enclave_ffi_types::EnclaveError;
+
+use crate::panic_isolation::LockOrRecover;
+use crate::types::{EnvelopeVersion, IoNonce, SecretMessage};
+
+/// Amount of blocks for which failure reports are kept inside the enclave
+const TX_JOURNAL_BLOCKS: usize = 100;
+/// Upper bound on the amount of failure reports kept per block
+const MAX_REPORTS_PER_BLOCK: usize = 10_000;
+
+pub type TxHash = [u8; 32];
+
+/// The stage of processing in which a transaction failed
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureStage {
+    /// Parsing the inputs of the transaction failed
+    Decode,
+    /// The signature or the parameters of the transaction could not be verified
+    Verification,
+    /// The message could not be decrypted
+    Decryption,
+    /// The contract failed while executing
+    Execution,
+    /// The output of the contract could not be processed
+    OutputProcessing,
+    /// The enclave failed for reasons that are unrelated to the transaction
+    Internal,
+}
+
+/// A failure report as it is returned to the sender of the transaction.
+/// Only the name of the error and its generic description are included, so the
+/// report never contains data that originated from the contract or its state.
+#[derive(Serialize, Clone, Debug)]
+pub struct FailureReport {
+    pub stage: FailureStage,
+    pub error_code: String,
+    pub message: String,
+}
+
+struct JournalEntry {
+    nonce: IoNonce,
+    user_public_key: Ed25519PublicKey,
+    report: FailureReport,
+}
+
+lazy_static! {
+    /// block height -> tx hash -> failure report
+    static ref TX_JOURNAL: SgxMutex<BTreeMap<u64, HashMap<TxHash, JournalEntry>>> =
+        SgxMutex::new(BTreeMap::new());
+}
+
+impl FailureReport {
+    fn from_error(err: &EnclaveError) -> Self {
+        let stage = match err {
+            EnclaveError::FailedToDeserialize | EnclaveError::InvalidWasm => FailureStage::Decode,
+            EnclaveError::ValidationFailure
+            | EnclaveError::FailedTxVerification
+            | EnclaveError::DuplicateIbcCallback
+            | EnclaveError::DuplicateExecution
+            | EnclaveError::MigrationPending
+            | EnclaveError::InvalidAddressPrefix
+            | EnclaveError::InvalidAddressChecksum
+            | EnclaveError::InvalidAddressLength
+            | EnclaveError::FailedContractAuthentication => FailureStage::Verification,
+            EnclaveError::DecryptionError => FailureStage::Decryption,
+            EnclaveError::FailedToSerialize | EnclaveError::EncryptionError => {
+                FailureStage::OutputProcessing
+            }
+            EnclaveError::OutOfGas
+            | EnclaveError::ExecutionTimeout
+            | EnclaveError::BlockGasExceeded
+            | EnclaveError::FailedFunctionCall
+            | EnclaveError::UnauthorizedWrite
+            | EnclaveError::StorageQuotaExceeded
+            | EnclaveError::ExceededRecursionLimit
+            | EnclaveError::ContractPanicUnreachable
+            | EnclaveError::ContractPanicMemoryAccessOutOfBounds
+            | EnclaveError::ContractPanicTableAccessOutOfBounds
+            | EnclaveError::ContractPanicElemUninitialized
+            | EnclaveError::ContractPanicDivisionByZero
+            | EnclaveError::ContractPanicInvalidConversionToInt
+            | EnclaveError::ContractPanicStackOverflow
+            | EnclaveError::CallStackExceeded { .. }
+            | EnclaveError::ContractPanicIntegerOverflow
+            | EnclaveError::ContractPanicUnexpectedSignature
+            | EnclaveError::ContractPanicked => FailureStage::Execution,
+            _ => FailureStage::Internal,
+        };
+
+        // The Debug representation of variants with fields contains raw pointers,
+        // so we only keep the name of the variant
+        let debug_repr = format!("{:?}", err);
+        let error_code = debug_repr
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        Self {
+            stage,
+            error_code,
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Records the failure of a transaction in the journal of the block it was executed in.
+/// Transactions whose message was not encrypted can not be introspected, so they are ignored.
+pub fn record_failure(env: &[u8], msg: &[u8], sig_info: &[u8], err: &EnclaveError) {
+    let block_height = match serde_json::from_slice::<BaseEnv>(env) {
+        Ok(base_env) => base_env.0.block.height,
+        Err(_) => return,
+    };
+    let tx_bytes = match serde_json::from_slice::<SigInfo>(sig_info) {
+        Ok(sig_info) if !sig_info.tx_bytes.is_empty() => sig_info.tx_bytes,
+        _ => return,
+    };
+    let secret_msg = match SecretMessage::from_slice(msg) {
+        Ok(secret_msg) => secret_msg,
+        Err(_) => return,
+    };
+
+    let tx_hash = sha_256(tx_bytes.as_slice());
+    let entry = JournalEntry {
+        nonce: secret_msg.nonce,
+        user_public_key: secret_msg.user_public_key,
+        report: FailureReport::from_error(err),
+    };
+
+    let mut journal = TX_JOURNAL.lock_or_recover();
+
+    let block_journal = journal.entry(block_height).or_insert_with(HashMap::new);
+    if block_journal.len() >= MAX_REPORTS_PER_BLOCK {
+        debug!(
+            "tx journal for block {} is full, dropping report",
+            block_height
+        );
+        return;
+    }
+    // Only the first failure of a tx is kept, later failures are a consequence of it
+    block_journal.entry(tx_hash).or_insert(entry);
+
+    while journal.len() > TX_JOURNAL_BLOCKS {
+        let oldest = *journal.keys().next().unwrap();
+        journal.remove(&oldest);
+    }
+}
+
+/// Returns the failure report of a transaction, encrypted with the encryption key of the
+/// original message. The caller has to present the nonce and public key that were used in the
+/// transaction, and only the holder of the matching private key can decrypt the report.
+pub fn get_failure_report(
+    tx_hash: &TxHash,
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+) -> Result<Vec<u8>, EnclaveError> {
+    let report = find_failure_report(tx_hash, nonce, user_public_key)?;
+
+    let serialized_report = serde_json::to_vec(&report).map_err(|err| {
+        warn!("failed to serialize failure report: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+
+    let mut secret_report = SecretMessage {
+        nonce: *nonce,
+        user_public_key: *user_public_key,
+        msg: serialized_report,
+        version: EnvelopeVersion::Unversioned,
+    };
+    secret_report.encrypt_in_place()?;
+
+    Ok(secret_report.msg)
+}
+
+/// Looks up the failure report of a transaction, if it was sent with the given nonce and key
+fn find_failure_report(
+    tx_hash: &TxHash,
+    nonce: &IoNonce,
+    user_public_key: &Ed25519PublicKey,
+) -> Result<FailureReport, EnclaveError> {
+    let journal = TX_JOURNAL.lock_or_recover();
+
+    let entry = journal
+        .values()
+        .rev()
+        .find_map(|block_journal| block_journal.get(tx_hash))
+        .ok_or_else(|| {
+            debug!("no failure report for tx {}", hex::encode(tx_hash));
+            EnclaveError::ValidationFailure
+        })?;
+
+    if &entry.nonce != nonce || &entry.user_public_key != user_public_key {
+        warn!("tried to get a failure report with mismatching encryption parameters");
+        return Err(EnclaveError::ValidationFailure);
+    }
+
+    Ok(entry.report.clone())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    // Far above the heights used by other tests, as the journal is shared
+    const TEST_HEIGHT: u64 = 1_000_000;
+
+    fn env_at(height: u64) -> Vec<u8> {
+        format!(
+            r#"{{
+                "block": {{"height": {}, "time": 1, "chain_id": "secretdev-1"}},
+                "message": {{"sender": "sender", "sent_funds": []}},
+                "contract": {{"address": "contract"}},
+                "contract_key": null
+            }}"#,
+            height
+        )
+        .into_bytes()
+    }
+
+    fn sig_info_with_tx(tx_bytes: &[u8]) -> Vec<u8> {
+        format!(
+            r#"{{
+                "tx_bytes": "{}",
+                "sign_bytes": "",
+                "sign_mode": "SIGN_MODE_DIRECT",
+                "mode_info": "",
+                "public_key": "",
+                "signature": "",
+                "callback_sig": null
+            }}"#,
+            base64::encode(tx_bytes)
+        )
+        .into_bytes()
+    }
+
+    fn secret_msg(nonce: IoNonce, user_public_key: Ed25519PublicKey) -> Vec<u8> {
+        SecretMessage {
+            nonce,
+            user_public_key,
+            msg: vec![0u8; 32],
+            version: EnvelopeVersion::Unversioned,
+        }
+        .to_vec()
+    }
+
+    pub fn test_record_failure() {
+        let tx_bytes = b"record failure tx";
+        let tx_hash = sha_256(tx_bytes);
+        let (nonce, key) = ([1u8; 32], [2u8; 32]);
+
+        record_failure(
+            &env_at(TEST_HEIGHT),
+            &secret_msg(nonce, key),
+            &sig_info_with_tx(tx_bytes),
+            &EnclaveError::OutOfGas,
+        );
+        // Only the first failure of a tx is kept
+        record_failure(
+            &env_at(TEST_HEIGHT),
+            &secret_msg(nonce, key),
+            &sig_info_with_tx(tx_bytes),
+            &EnclaveError::FailedToSerialize,
+        );
+
+        let report = find_failure_report(&tx_hash, &nonce, &key).unwrap();
+        assert_eq!(report.stage, FailureStage::Execution);
+        assert_eq!(report.error_code, "OutOfGas");
+
+        // Messages that aren't encrypted, and calls without a tx, are not recorded
+        let plaintext_tx = b"plaintext tx";
+        record_failure(
+            &env_at(TEST_HEIGHT),
+            b"{}",
+            &sig_info_with_tx(plaintext_tx),
+            &EnclaveError::OutOfGas,
+        );
+        assert!(find_failure_report(&sha_256(plaintext_tx), &nonce, &key).is_err());
+        record_failure(
+            &env_at(TEST_HEIGHT),
+            &secret_msg(nonce, key),
+            &sig_info_with_tx(b""),
+            &EnclaveError::OutOfGas,
+        );
+        assert!(find_failure_report(&sha_256(b""), &nonce, &key).is_err());
+
+        // Errors with fields are reported by the name of the variant only
+        let report = FailureReport::from_error(&EnclaveError::CallStackExceeded { depth: 11 });
+        assert_eq!(report.error_code, "CallStackExceeded");
+    }
+
+    pub fn test_get_failure_report() {
+        let tx_bytes = b"get failure report tx";
+        let tx_hash = sha_256(tx_bytes);
+        let (nonce, key) = ([3u8; 32], [4u8; 32]);
+
+        record_failure(
+            &env_at(TEST_HEIGHT + 1),
+            &secret_msg(nonce, key),
+            &sig_info_with_tx(tx_bytes),
+            &EnclaveError::DecryptionError,
+        );
+        let report = find_failure_report(&tx_hash, &nonce, &key).unwrap();
+        assert_eq!(report.stage, FailureStage::Decryption);
+
+        // Only the sender of the tx may read its report
+        assert!(find_failure_report(&tx_hash, &[9u8; 32], &key).is_err());
+        assert!(find_failure_report(&tx_hash, &nonce, &[9u8; 32]).is_err());
+        assert!(get_failure_report(&tx_hash, &nonce, &[9u8; 32]).is_err());
+        assert!(find_failure_report(&[0u8; 32], &nonce, &key).is_err());
+
+        // Reports of old blocks are evicted
+        for height in 0..TX_JOURNAL_BLOCKS as u64 {
+            record_failure(
+                &env_at(TEST_HEIGHT + 2 + height),
+                &secret_msg(nonce, key),
+                &sig_info_with_tx(&height.to_be_bytes()),
+                &EnclaveError::OutOfGas,
+            );
+        }
+        assert!(find_failure_report(&tx_hash, &nonce, &key).is_err());
+    }
+}
@@ -1,9 +1,16 @@
 use log::*;
 use serde::{Deserialize, Serialize};
 
-use enclave_crypto::{AESKey, Ed25519PublicKey, SIVEncryptable};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_crypto::{
+    AESKey, CryptoError, Ed25519PublicKey, Kdf, SIVEncryptable, XChaCha20Poly1305Encryptable,
+};
 use enclave_ffi_types::EnclaveError;
 
+use super::cosmwasm_config::io_envelope::{
+    VERSIONED_MAGIC, VERSION_AES_SIV, VERSION_XCHACHA20_POLY1305, XCHACHA20_POLY1305_KEY_INFO,
+};
+use super::execution_trace::trace_span;
 use super::io::calc_encryption_key;
 
 pub type IoNonce = [u8; 32];
@@ -12,11 +19,96 @@ pub struct SecretMessage {
     pub nonce: IoNonce,
     pub user_public_key: Ed25519PublicKey,
     pub msg: Vec<u8>,
+    /// The outputs of the message are encrypted in the same cipher suite as the message
+    #[serde(default)]
+    pub version: EnvelopeVersion,
+}
+
+/// The version of the envelope of a message, which the sender picks. The messages that the
+/// enclave encrypts for other contracts, and the replies to them, keep the version of the message
+/// that the contract executes, so the whole execution is encrypted in the suite of the sender.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnvelopeVersion {
+    /// The original envelope, without a version, which is encrypted with AES-SIV
+    Unversioned,
+    AesSiv,
+    XChaCha20Poly1305,
+}
+
+impl Default for EnvelopeVersion {
+    fn default() -> Self {
+        EnvelopeVersion::Unversioned
+    }
+}
+
+impl EnvelopeVersion {
+    /// Splits the version from the start of an envelope
+    fn split(envelope: &[u8]) -> Result<(Self, &[u8]), EnclaveError> {
+        if !is_active(Feature::VersionedEnvelopes) || !envelope.starts_with(VERSIONED_MAGIC) {
+            return Ok((EnvelopeVersion::Unversioned, envelope));
+        }
+
+        let rest = &envelope[VERSIONED_MAGIC.len()..];
+        let version = match rest.first() {
+            Some(&VERSION_AES_SIV) => EnvelopeVersion::AesSiv,
+            Some(&VERSION_XCHACHA20_POLY1305) => EnvelopeVersion::XChaCha20Poly1305,
+            other => {
+                error!("Envelope version {:?} is not supported", other);
+                return Err(EnclaveError::DecryptionError);
+            }
+        };
+        Ok((version, &rest[1..]))
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let version = match self {
+            EnvelopeVersion::Unversioned => return vec![],
+            EnvelopeVersion::AesSiv => VERSION_AES_SIV,
+            EnvelopeVersion::XChaCha20Poly1305 => VERSION_XCHACHA20_POLY1305,
+        };
+        let mut header = VERSIONED_MAGIC.to_vec();
+        header.push(version);
+        header
+    }
+}
+
+/// The key of an envelope, which encrypts in the cipher suite of its version
+pub struct IoKey {
+    key: AESKey,
+    version: EnvelopeVersion,
+}
+
+impl IoKey {
+    pub fn new(key: AESKey, version: EnvelopeVersion) -> Self {
+        let key = match version {
+            EnvelopeVersion::XChaCha20Poly1305 => {
+                key.derive_key_from_this(XCHACHA20_POLY1305_KEY_INFO)
+            }
+            EnvelopeVersion::Unversioned | EnvelopeVersion::AesSiv => key,
+        };
+        Self { key, version }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], ad: Option<&[&[u8]]>) -> Result<Vec<u8>, CryptoError> {
+        match self.version {
+            EnvelopeVersion::XChaCha20Poly1305 => self.key.encrypt_xchacha(plaintext, ad),
+            EnvelopeVersion::Unversioned | EnvelopeVersion::AesSiv => {
+                self.key.encrypt_siv(plaintext, ad)
+            }
+        }
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8], ad: Option<&[&[u8]]>) -> Result<Vec<u8>, CryptoError> {
+        match self.version {
+            EnvelopeVersion::XChaCha20Poly1305 => self.key.decrypt_xchacha(ciphertext, ad),
+            EnvelopeVersion::Unversioned | EnvelopeVersion::AesSiv => {
+                self.key.decrypt_siv(ciphertext, ad)
+            }
+        }
+    }
 }
 
 pub struct ParsedMessage {
-    pub should_verify_sig_info: bool,
-    pub should_verify_input: bool,
     pub was_msg_encrypted: bool,
     pub should_encrypt_output: bool,
     pub secret_msg: SecretMessage,
@@ -33,8 +125,8 @@ pub struct DecryptedSecretMessage {
 impl SecretMessage {
     pub fn encrypt_in_place(&mut self) -> Result<(), EnclaveError> {
         self.msg = self
-            .encryption_key()
-            .encrypt_siv(self.msg.as_slice(), None)
+            .io_key()
+            .encrypt(self.msg.as_slice(), None)
             .map_err(|err| {
                 error!("got an error while trying to encrypt the msg: {:?}", err);
                 EnclaveError::EncryptionError
@@ -45,9 +137,9 @@ impl SecretMessage {
 
     pub fn try_decrypt(&self) -> Option<Vec<u8>> {
         trace!("input before decryption: {:?}", base64::encode(&self.msg));
-        let key = self.encryption_key();
+        let key = self.io_key();
 
-        if let Ok(msg) = key.decrypt_siv(self.msg.as_slice(), None) {
+        if let Ok(msg) = key.decrypt(self.msg.as_slice(), None) {
             trace!(
                 "input after decryption: {:?}",
                 String::from_utf8_lossy(&msg)
@@ -60,6 +152,7 @@ impl SecretMessage {
     }
 
     pub fn decrypt(&self) -> Result<Vec<u8>, EnclaveError> {
+        let _span = trace_span("decrypt");
         match self.try_decrypt() {
             Some(msg) => Ok(msg),
             None => {
@@ -69,10 +162,34 @@ impl SecretMessage {
         }
     }
 
+    /// Encrypts `plaintext` in an envelope addressed to `public_key`, whose holder decrypts it
+    /// like any other message, with the consensus IO exchange key
+    pub fn encrypt_for(
+        nonce: IoNonce,
+        public_key: Ed25519PublicKey,
+        plaintext: &[u8],
+    ) -> Result<Self, EnclaveError> {
+        let mut envelope = SecretMessage {
+            nonce,
+            user_public_key: public_key,
+            msg: plaintext.to_vec(),
+            version: EnvelopeVersion::Unversioned,
+        };
+        envelope.encrypt_in_place()?;
+        Ok(envelope)
+    }
+
+    /// The AES-SIV key of the envelope, whatever its version, which the enclave encrypts what
+    /// only it decrypts with, like the payloads of submessages
     pub fn encryption_key(&self) -> AESKey {
         calc_encryption_key(&self.nonce, &self.user_public_key)
     }
 
+    /// The key of the envelope in its cipher suite, which decrypts it and encrypts its outputs
+    pub fn io_key(&self) -> IoKey {
+        IoKey::new(self.encryption_key(), self.version)
+    }
+
     pub fn from_base64(
         msg_b64: String,
         nonce: IoNonce,
@@ -90,13 +207,16 @@ impl SecretMessage {
             msg,
             nonce,
             user_public_key,
+            version: EnvelopeVersion::Unversioned,
         })
     }
 
     pub fn from_slice(msg: &[u8]) -> Result<Self, EnclaveError> {
+        // An optional version, see `EnvelopeVersion`
         // 32 bytes of nonce
         // 32 bytes of 25519 compressed public key
         // 16+ bytes of encrypted data
+        let (version, msg) = EnvelopeVersion::split(msg)?;
 
         if msg.len() < 82 {
             error!(
@@ -122,11 +242,13 @@ impl SecretMessage {
             nonce,
             user_public_key: user_pubkey,
             msg: msg[64..].to_vec(),
+            version,
         })
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut packed_msg: Vec<u8> = self.nonce.to_vec();
+        let mut packed_msg: Vec<u8> = self.version.header();
+        packed_msg.extend_from_slice(&self.nonce);
         packed_msg.extend_from_slice(&self.user_public_key);
         packed_msg.extend_from_slice(self.msg.as_slice());
         packed_msg
@@ -152,10 +274,47 @@ pub mod tests {
             nonce,
             user_public_key,
             msg: msg.as_bytes().to_vec(),
+            version: EnvelopeVersion::Unversioned,
         };
 
         let msg_from_slice = SecretMessage::from_slice(&slice).unwrap();
 
         assert_eq!(secret_msg, msg_from_slice);
     }
+
+    pub fn test_versioned_from_slice() {
+        let secret_msg = SecretMessage {
+            nonce: [1u8; 32],
+            user_public_key: [2u8; 32],
+            msg: vec![3u8; 40],
+            version: EnvelopeVersion::XChaCha20Poly1305,
+        };
+        let slice = secret_msg.to_vec();
+
+        if !is_active(Feature::VersionedEnvelopes) {
+            // The version is read as the start of the nonce
+            let parsed = SecretMessage::from_slice(&slice).unwrap();
+            assert_eq!(parsed.version, EnvelopeVersion::Unversioned);
+            assert_ne!(parsed.nonce, secret_msg.nonce);
+            return;
+        }
+
+        assert!(slice.starts_with(VERSIONED_MAGIC));
+        assert_eq!(SecretMessage::from_slice(&slice).unwrap(), secret_msg);
+
+        // Envelopes are serialized as they were received, so their signatures still verify
+        let aes_siv = SecretMessage {
+            version: EnvelopeVersion::AesSiv,
+            ..secret_msg
+        };
+        let aes_siv_slice = aes_siv.to_vec();
+        assert_eq!(SecretMessage::from_slice(&aes_siv_slice).unwrap(), aes_siv);
+        let unversioned = SecretMessage::from_slice(&aes_siv_slice[VERSIONED_MAGIC.len() + 1..]);
+        assert_eq!(unversioned.unwrap().version, EnvelopeVersion::Unversioned);
+
+        let mut unknown_version = VERSIONED_MAGIC.to_vec();
+        unknown_version.push(0xff);
+        unknown_version.extend_from_slice(&slice[VERSIONED_MAGIC.len() + 1..]);
+        assert!(SecretMessage::from_slice(&unknown_version).is_err());
+    }
 }
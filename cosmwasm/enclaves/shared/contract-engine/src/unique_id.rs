@@ -0,0 +1,75 @@
+use enclave_crypto::sha_256;
+
+use crate::random::MsgCounter;
+
+/// Separates the hashes ids are derived from from other hashes of the same inputs
+const UNIQUE_ID_DOMAIN: &[u8] = b"secret_unique_id";
+
+/// Derives the `index`th id that an execution returns from `unique_id`, as a version 8 UUID
+/// string. The position of the execution in the block makes ids unique across executions, and
+/// the hash of the transaction makes them unique across chains that share heights.
+///
+/// Queries don't advance the counter of executions, so ids minted in queries may repeat.
+pub fn derive_unique_id(tx_hash: Option<&str>, msg_counter: MsgCounter, index: u64) -> String {
+    let tx_hash = tx_hash.unwrap_or_default().as_bytes();
+
+    let mut data = UNIQUE_ID_DOMAIN.to_vec();
+    data.extend_from_slice(&msg_counter.height.to_be_bytes());
+    data.extend_from_slice(&msg_counter.counter.to_be_bytes());
+    data.extend_from_slice(&(tx_hash.len() as u64).to_be_bytes());
+    data.extend_from_slice(tx_hash);
+    data.extend_from_slice(&index.to_be_bytes());
+
+    let mut uuid = [0u8; 16];
+    uuid.copy_from_slice(&sha_256(&data)[..16]);
+    // Version 8 (custom) and the RFC 4122 variant
+    uuid[6] = (uuid[6] & 0x0f) | 0x80;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+
+    let uuid = hex::encode(uuid);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &uuid[..8],
+        &uuid[8..12],
+        &uuid[12..16],
+        &uuid[16..20],
+        &uuid[20..]
+    )
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_derive_unique_id() {
+        let counter = MsgCounter {
+            height: 100,
+            counter: 2,
+        };
+        let id = derive_unique_id(Some("AB12"), counter, 0);
+
+        assert_eq!(id.len(), 36);
+        assert_eq!(
+            id.split('-').map(str::len).collect::<Vec<_>>(),
+            vec![8, 4, 4, 4, 12]
+        );
+        assert_eq!(&id[14..15], "8");
+        assert!(["8", "9", "a", "b"].contains(&&id[19..20]));
+
+        assert_eq!(derive_unique_id(Some("AB12"), counter, 0), id);
+        assert_ne!(derive_unique_id(Some("AB12"), counter, 1), id);
+        assert_ne!(derive_unique_id(Some("AB13"), counter, 0), id);
+        assert_ne!(derive_unique_id(None, counter, 0), id);
+        assert_ne!(
+            derive_unique_id(
+                Some("AB12"),
+                MsgCounter {
+                    height: 100,
+                    counter: 3,
+                },
+                0
+            ),
+            id
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use cosmos_proto::tx::signing::SignMode;
+use cw_types_v010::encoding::Binary;
+use enclave_cosmos_types::contract_metadata::{split_code_signature, CODE_SIGNATURE_SECTION_NAME};
+use enclave_cosmos_types::types::ContractCode;
+use enclave_crypto::secp256k1::Secp256k1PubKey;
+use enclave_crypto::traits::VerifyingKey;
+use enclave_crypto::HASH_SIZE;
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::reserved_keys;
+use crate::wasm3::Engine;
+
+/// Restricts how a contract can be migrated, on top of the migration being sent by its admin.
+/// Contracts set it with `set_upgrade_policy` while they are instantiated or migrated, which
+/// gives users guarantees about how the contract can evolve that they can check in its code.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct UpgradePolicy {
+    /// If set, the contract can only be migrated to code that was signed by this secp256k1
+    /// public key, see `CODE_SIGNATURE_SECTION_NAME`
+    #[serde(default)]
+    pub code_signer: Option<Binary>,
+    /// The amount of blocks that have to pass after the policy was set or the contract was last
+    /// migrated, before it can be migrated again
+    #[serde(default)]
+    pub timelock_blocks: u64,
+    /// If set, the contract can only be migrated to code whose hash it announced with
+    /// `announce_migration` at least this many blocks earlier, so users have time to exit
+    #[serde(default)]
+    pub announcement_delay_blocks: u64,
+    /// The hex encoded hashes of the code that the contract can migrate itself to, by sending a
+    /// migrate message to its own address. The contract doesn't need to be its own admin, or to
+    /// have an admin at all. Empty means the contract can't migrate itself.
+    #[serde(default)]
+    pub self_migration_code_hashes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StoredUpgradePolicy {
+    policy: UpgradePolicy,
+    /// The height at which the policy was set or the contract was last migrated
+    since_height: u64,
+}
+
+/// A migration the contract announced with `announce_migration`. It's stored in the
+/// contract's state at the height of the block it was announced in, which is verified with the
+/// rest of the env when light client validation is enabled.
+#[derive(Serialize, Deserialize, Debug)]
+struct MigrationAnnouncement {
+    code_hash: Binary,
+    height: u64,
+}
+
+/// Parses the policy passed to `set_upgrade_policy`. Errors are returned to the contract as
+/// messages.
+pub fn parse_upgrade_policy(policy: &[u8]) -> Result<UpgradePolicy, String> {
+    let policy: UpgradePolicy =
+        serde_json::from_slice(policy).map_err(|err| format!("Invalid upgrade policy: {}", err))?;
+
+    if let Some(code_signer) = &policy.code_signer {
+        if code_signer.0.len() != 33 && code_signer.0.len() != 65 {
+            return Err(format!(
+                "Invalid code signer: public key length is {}, should be 33 or 65",
+                code_signer.0.len()
+            ));
+        }
+    }
+
+    for code_hash in &policy.self_migration_code_hashes {
+        parse_announced_code_hash(code_hash.as_bytes())?;
+    }
+
+    Ok(policy)
+}
+
+/// Parses the hex encoded code hash passed to `announce_migration`. Errors are returned to the
+/// contract as messages.
+pub fn parse_announced_code_hash(code_hash: &[u8]) -> Result<[u8; HASH_SIZE], String> {
+    let code_hash = hex::decode(code_hash).map_err(|err| format!("Invalid code hash: {}", err))?;
+    if code_hash.len() != HASH_SIZE {
+        return Err(format!(
+            "Invalid code hash: length is {}, should be {}",
+            code_hash.len(),
+            HASH_SIZE
+        ));
+    }
+
+    let mut hash = [0u8; HASH_SIZE];
+    hash.copy_from_slice(&code_hash);
+    Ok(hash)
+}
+
+fn read_stored_policy(engine: &mut Engine) -> Result<Option<StoredUpgradePolicy>, EnclaveError> {
+    match engine.read_reserved_key(reserved_keys::UPGRADE_POLICY)? {
+        Some(stored) => serde_json::from_slice(&stored).map(Some).map_err(|err| {
+            warn!("stored upgrade policy is malformed: {}", err);
+            EnclaveError::FailedToDeserialize
+        }),
+        None => Ok(None),
+    }
+}
+
+fn read_migration_announcement(
+    engine: &mut Engine,
+) -> Result<Option<MigrationAnnouncement>, EnclaveError> {
+    match engine.read_reserved_key(reserved_keys::MIGRATION_ANNOUNCEMENT)? {
+        Some(stored) => serde_json::from_slice(&stored).map(Some).map_err(|err| {
+            warn!("stored migration announcement is malformed: {}", err);
+            EnclaveError::FailedToDeserialize
+        }),
+        None => Ok(None),
+    }
+}
+
+fn check_migration_announcement(
+    announcement: Option<MigrationAnnouncement>,
+    new_code_hash: &[u8],
+    block_height: u64,
+    delay_blocks: u64,
+) -> Result<(), String> {
+    let announcement = announcement.ok_or_else(|| {
+        "the upgrade policy requires the migration to be announced first".to_string()
+    })?;
+
+    if announcement.code_hash.0 != new_code_hash {
+        return Err(format!(
+            "the announced migration is to code {}",
+            hex::encode(&announcement.code_hash.0)
+        ));
+    }
+
+    let unlocked_at = announcement.height.saturating_add(delay_blocks);
+    if block_height < unlocked_at {
+        return Err(format!(
+            "the announced migration can't take effect before block {}",
+            unlocked_at
+        ));
+    }
+
+    Ok(())
+}
+
+fn verify_code_signature(code: &[u8], code_signer: &Binary) -> Result<(), String> {
+    let (signed_code, signature) = split_code_signature(code).ok_or_else(|| {
+        format!(
+            "the upgrade policy requires signed code, but the code has no {} section",
+            CODE_SIGNATURE_SECTION_NAME
+        )
+    })?;
+
+    Secp256k1PubKey::new(code_signer.0.clone())
+        .verify_bytes(signed_code, signature, SignMode::SIGN_MODE_DIRECT)
+        .map_err(|_| "the code isn't signed by the signer of the upgrade policy".to_string())
+}
+
+/// Checks that the upgrade policy of the contract allows migrating it to `new_code` at
+/// `block_height`. Returns the reason if it doesn't.
+pub fn check_upgrade_policy(
+    engine: &mut Engine,
+    new_code: &ContractCode,
+    block_height: u64,
+) -> Result<Option<String>, EnclaveError> {
+    let stored = match read_stored_policy(engine)? {
+        Some(stored) => stored,
+        None => return Ok(None),
+    };
+
+    let unlocked_at = stored
+        .since_height
+        .saturating_add(stored.policy.timelock_blocks);
+    if block_height < unlocked_at {
+        return Ok(Some(format!(
+            "the upgrade policy doesn't allow migrating the contract before block {}",
+            unlocked_at
+        )));
+    }
+
+    if stored.policy.announcement_delay_blocks > 0 {
+        let announcement = read_migration_announcement(engine)?;
+        if let Err(reason) = check_migration_announcement(
+            announcement,
+            &new_code.hash(),
+            block_height,
+            stored.policy.announcement_delay_blocks,
+        ) {
+            return Ok(Some(reason));
+        }
+    }
+
+    if let Some(code_signer) = &stored.policy.code_signer {
+        if let Err(reason) = verify_code_signature(new_code.code(), code_signer) {
+            return Ok(Some(reason));
+        }
+    }
+
+    Ok(None)
+}
+
+fn check_self_migration_code(
+    policy: Option<&UpgradePolicy>,
+    new_code_hash: &[u8],
+) -> Result<(), String> {
+    let allowed = policy.map_or(&[][..], |policy| &policy.self_migration_code_hashes[..]);
+    if allowed.is_empty() {
+        return Err("the upgrade policy doesn't allow the contract to migrate itself".to_string());
+    }
+
+    let new_code_hash = hex::encode(new_code_hash);
+    if !allowed
+        .iter()
+        .any(|code_hash| code_hash.eq_ignore_ascii_case(&new_code_hash))
+    {
+        return Err(format!(
+            "the upgrade policy doesn't allow the contract to migrate itself to code {}",
+            new_code_hash
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that the upgrade policy of the contract allows it to migrate itself to `new_code`,
+/// which replaces the check that the sender is its admin. Returns the reason if it doesn't. The
+/// rest of the policy is checked by `check_upgrade_policy` as for any migration.
+pub fn check_self_migration(
+    engine: &mut Engine,
+    new_code: &ContractCode,
+) -> Result<Option<String>, EnclaveError> {
+    let stored = read_stored_policy(engine)?;
+    Ok(check_self_migration_code(
+        stored.as_ref().map(|stored| &stored.policy),
+        &new_code.hash(),
+    )
+    .err())
+}
+
+/// Stores the migration the contract announced while it was executed. A new announcement
+/// replaces the previous one and restarts its delay.
+pub fn store_migration_announcement(
+    engine: &mut Engine,
+    code_hash: [u8; HASH_SIZE],
+    block_height: u64,
+) -> Result<(), EnclaveError> {
+    let stored = serde_json::to_vec(&MigrationAnnouncement {
+        code_hash: Binary(code_hash.to_vec()),
+        height: block_height,
+    })
+    .map_err(|err| {
+        warn!("failed to serialize migration announcement: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+    engine.write_reserved_key(reserved_keys::MIGRATION_ANNOUNCEMENT, &stored);
+
+    Ok(())
+}
+
+/// Removes the announcement of a migration once the contract was migrated
+pub fn clear_migration_announcement(engine: &mut Engine) -> Result<(), EnclaveError> {
+    if read_migration_announcement(engine)?.is_some() {
+        engine.remove_reserved_key(reserved_keys::MIGRATION_ANNOUNCEMENT)?;
+    }
+
+    Ok(())
+}
+
+/// Stores the policy the contract set while it was instantiated or migrated. If it didn't set
+/// one, a migration restarts the timelock of the current policy.
+pub fn store_upgrade_policy(
+    engine: &mut Engine,
+    new_policy: Option<UpgradePolicy>,
+    block_height: u64,
+) -> Result<(), EnclaveError> {
+    let policy = match new_policy {
+        Some(policy) => policy,
+        None => match read_stored_policy(engine)? {
+            Some(stored) => stored.policy,
+            None => return Ok(()),
+        },
+    };
+
+    let stored = serde_json::to_vec(&StoredUpgradePolicy {
+        policy,
+        since_height: block_height,
+    })
+    .map_err(|err| {
+        warn!("failed to serialize upgrade policy: {}", err);
+        EnclaveError::FailedToSerialize
+    })?;
+    engine.write_reserved_key(reserved_keys::UPGRADE_POLICY, &stored);
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_parse_upgrade_policy() {
+        let policy = parse_upgrade_policy(br#"{"timelock_blocks":100}"#).unwrap();
+        assert_eq!(
+            policy,
+            UpgradePolicy {
+                code_signer: None,
+                timelock_blocks: 100,
+                announcement_delay_blocks: 0,
+                self_migration_code_hashes: vec![],
+            }
+        );
+
+        let signer = base64::encode(vec![2u8; 33]);
+        let policy =
+            parse_upgrade_policy(format!(r#"{{"code_signer":"{}"}}"#, signer).as_bytes()).unwrap();
+        assert_eq!(policy.code_signer, Some(Binary(vec![2u8; 33])));
+        assert_eq!(policy.timelock_blocks, 0);
+
+        let policy = parse_upgrade_policy(br#"{"announcement_delay_blocks":50}"#).unwrap();
+        assert_eq!(policy.announcement_delay_blocks, 50);
+
+        let short_signer = base64::encode(vec![2u8; 20]);
+        assert!(parse_upgrade_policy(
+            format!(r#"{{"code_signer":"{}"}}"#, short_signer).as_bytes()
+        )
+        .is_err());
+        assert!(parse_upgrade_policy(br#"{"allowed_code_hashes":[]}"#).is_err());
+        assert!(parse_upgrade_policy(br#"{"self_migration_code_hashes":["abcd"]}"#).is_err());
+        assert!(parse_upgrade_policy(b"not json").is_err());
+    }
+
+    pub fn test_check_migration_announcement() {
+        let code_hash =
+            parse_announced_code_hash(hex::encode([7u8; HASH_SIZE]).as_bytes()).unwrap();
+        assert!(parse_announced_code_hash(b"not hex").is_err());
+        assert!(parse_announced_code_hash(hex::encode([7u8; 20]).as_bytes()).is_err());
+
+        let announcement = || {
+            Some(MigrationAnnouncement {
+                code_hash: Binary(code_hash.to_vec()),
+                height: 1000,
+            })
+        };
+
+        assert!(check_migration_announcement(None, &code_hash, 2000, 100).is_err());
+        assert!(
+            check_migration_announcement(announcement(), &[8u8; HASH_SIZE], 2000, 100).is_err()
+        );
+        assert!(check_migration_announcement(announcement(), &code_hash, 1099, 100).is_err());
+        assert!(check_migration_announcement(announcement(), &code_hash, 1100, 100).is_ok());
+    }
+
+    pub fn test_check_self_migration_code() {
+        let code_hash = [7u8; HASH_SIZE];
+        let policy = parse_upgrade_policy(
+            format!(
+                r#"{{"self_migration_code_hashes":["{}"]}}"#,
+                hex::encode_upper(code_hash)
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        assert!(check_self_migration_code(Some(&policy), &code_hash).is_ok());
+        assert!(check_self_migration_code(Some(&policy), &[8u8; HASH_SIZE]).is_err());
+        // Contracts without a policy, or whose policy has no allowlist, can't migrate themselves
+        assert!(check_self_migration_code(None, &code_hash).is_err());
+        let policy = parse_upgrade_policy(br#"{"timelock_blocks":100}"#).unwrap();
+        assert!(check_self_migration_code(Some(&policy), &code_hash).is_err());
+    }
+
+    pub fn test_verify_code_signature() {
+        let code = b"\0asm\x01\0\0\0".to_vec();
+        assert!(verify_code_signature(&code, &Binary(vec![2u8; 33])).is_err());
+
+        let name = CODE_SIGNATURE_SECTION_NAME.as_bytes();
+        let mut section = vec![name.len() as u8];
+        section.extend_from_slice(name);
+        section.extend_from_slice(&[0u8; 64]);
+        let mut signed = code;
+        signed.extend_from_slice(&[0, section.len() as u8]);
+        signed.extend(section);
+
+        // A signature that doesn't verify
+        assert!(verify_code_signature(&signed, &Binary(vec![2u8; 33])).is_err());
+    }
+}
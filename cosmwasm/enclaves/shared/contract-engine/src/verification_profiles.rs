@@ -0,0 +1,158 @@
+use log::*;
+
+use enclave_cosmos_types::types::VerifyParamsType;
+use enclave_ffi_types::EnclaveError;
+
+use crate::handle_routes::handle_route;
+
+/// What `verify_params` verifies for a kind of message
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VerificationProfile {
+    /// The sender signed the transaction, or it's a callback signed by the calling contract.
+    /// The message is verified against the signed messages of the transaction.
+    Signed,
+    /// Like `Signed` for encrypted messages. Plaintext messages aren't verified: there's no
+    /// msg.sender in a plaintext reply, and contracts don't know if their output is a reply to
+    /// another contract, thus can't sign it as such.
+    SignedIfEncrypted,
+    /// The message isn't signed by the sender, but it must match a message of the transaction,
+    /// e.g. a packet that was relayed over IBC
+    Relayed,
+    /// There's nothing to verify the message against, e.g. IBC channel handshakes
+    Unverified,
+}
+
+impl VerificationProfile {
+    pub fn should_verify_sig_info(self, was_msg_encrypted: bool) -> bool {
+        match self {
+            VerificationProfile::Signed => true,
+            VerificationProfile::SignedIfEncrypted => was_msg_encrypted,
+            VerificationProfile::Relayed | VerificationProfile::Unverified => false,
+        }
+    }
+
+    pub fn should_verify_input(self, was_msg_encrypted: bool) -> bool {
+        match self {
+            VerificationProfile::Signed | VerificationProfile::Relayed => true,
+            VerificationProfile::SignedIfEncrypted => was_msg_encrypted,
+            VerificationProfile::Unverified => false,
+        }
+    }
+}
+
+/// A profile that applies to a kind of message from a height on
+struct ProfileActivation {
+    params_type: VerifyParamsType,
+    since_height: u64,
+    profile: VerificationProfile,
+}
+
+const fn activation(
+    params_type: VerifyParamsType,
+    since_height: u64,
+    profile: VerificationProfile,
+) -> ProfileActivation {
+    ProfileActivation {
+        params_type,
+        since_height,
+        profile,
+    }
+}
+
+/// The verification profiles of the kinds of messages. Handle types are verified with the profile
+/// of their route until a profile is scheduled for them here. To change how a kind of message is
+/// verified with a chain upgrade, add a profile for it that applies from the upgrade height: the
+/// profile with the highest height at or below the current height applies.
+static VERIFICATION_PROFILES: &[ProfileActivation] = &[
+    activation(VerifyParamsType::Init, 0, VerificationProfile::Signed),
+    activation(VerifyParamsType::Migrate, 0, VerificationProfile::Signed),
+    activation(
+        VerifyParamsType::UpdateAdmin,
+        0,
+        VerificationProfile::Signed,
+    ),
+];
+
+fn find_profile(
+    profiles: &[ProfileActivation],
+    params_type: VerifyParamsType,
+    block_height: u64,
+) -> Option<VerificationProfile> {
+    profiles
+        .iter()
+        .filter(|activation| {
+            activation.params_type == params_type && activation.since_height <= block_height
+        })
+        .max_by_key(|activation| activation.since_height)
+        .map(|activation| activation.profile)
+}
+
+/// Returns the profile that `verify_params` verifies a kind of message with at a height
+pub fn verification_profile(
+    params_type: VerifyParamsType,
+    block_height: u64,
+) -> Result<VerificationProfile, EnclaveError> {
+    let scheduled = find_profile(VERIFICATION_PROFILES, params_type, block_height);
+    let routed = match params_type {
+        VerifyParamsType::HandleType(handle_type) => Some(handle_route(handle_type).verification),
+        _ => None,
+    };
+
+    scheduled.or(routed).ok_or_else(|| {
+        warn!(
+            "no verification profile for {:?} at height {}",
+            params_type, block_height
+        );
+        EnclaveError::ValidationFailure
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use enclave_cosmos_types::types::HandleType;
+
+    pub fn test_verification_profile() {
+        let reply = VerifyParamsType::HandleType(HandleType::HANDLE_TYPE_REPLY);
+        let profile = verification_profile(reply, 1).unwrap();
+        assert!(profile.should_verify_sig_info(true));
+        assert!(profile.should_verify_input(true));
+        assert!(!profile.should_verify_sig_info(false));
+        assert!(!profile.should_verify_input(false));
+
+        let recv_packet = VerifyParamsType::HandleType(HandleType::HANDLE_TYPE_IBC_PACKET_RECEIVE);
+        let profile = verification_profile(recv_packet, 1).unwrap();
+        assert!(!profile.should_verify_sig_info(true));
+        assert!(profile.should_verify_input(false));
+
+        // Every handle type has a profile
+        for handle_type in 0..=10 {
+            let handle_type = HandleType::try_from(handle_type).unwrap();
+            assert!(verification_profile(VerifyParamsType::HandleType(handle_type), 0).is_ok());
+        }
+    }
+
+    pub fn test_height_gated_profiles() {
+        let execute = VerifyParamsType::HandleType(HandleType::HANDLE_TYPE_EXECUTE);
+        let profiles = [
+            activation(execute, 10, VerificationProfile::Relayed),
+            activation(execute, 0, VerificationProfile::Signed),
+            activation(VerifyParamsType::Init, 5, VerificationProfile::Signed),
+        ];
+
+        assert_eq!(
+            find_profile(&profiles, execute, 9),
+            Some(VerificationProfile::Signed)
+        );
+        assert_eq!(
+            find_profile(&profiles, execute, 10),
+            Some(VerificationProfile::Relayed)
+        );
+        assert_eq!(find_profile(&profiles, VerifyParamsType::Init, 4), None);
+        assert_eq!(
+            find_profile(&profiles, VerifyParamsType::Migrate, 100),
+            None
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use std::convert::TryFrom;
+
+use enclave_crypto::Ed25519PublicKey;
+use enclave_ffi_types::EnclaveError;
+
+use crate::cosmwasm_config::output_padding;
+use crate::io::PaddingBuckets;
+use crate::types::{IoNonce, SecretMessage};
+
+/// Parses the x25519 public key passed to `designate_viewer`. Errors are returned to the contract
+/// as messages.
+pub fn parse_viewer_key(key: &[u8]) -> Result<Ed25519PublicKey, String> {
+    Ed25519PublicKey::try_from(key)
+        .map_err(|_| format!("Invalid viewer key: length is {}, should be 32", key.len()))
+}
+
+/// Encrypts the plaintext output of the contract for the viewer it designated, with the same
+/// nonce as the output that is encrypted for the user. The result is the base64 of a
+/// `SecretMessage` envelope (nonce, viewer key and ciphertext), which the viewer decrypts with
+/// the consensus IO exchange key like the user decrypts their output.
+/// The plaintext is padded like other outputs, and the padding is added to `padded_bytes`.
+pub fn encrypt_for_viewer(
+    output: &[u8],
+    nonce: &IoNonce,
+    viewer_key: &Ed25519PublicKey,
+    buckets: &PaddingBuckets,
+    padded_bytes: &mut u64,
+) -> Result<String, EnclaveError> {
+    let padding = buckets.padded_len(output.len()) - output.len();
+    *padded_bytes = padded_bytes.saturating_add(padding as u64);
+
+    let mut plaintext = output.to_vec();
+    plaintext.resize(output.len() + padding, output_padding::PADDING_BYTE);
+
+    let envelope = SecretMessage::encrypt_for(*nonce, *viewer_key, &plaintext)?;
+    Ok(base64::encode(envelope.to_vec()))
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_parse_viewer_key() {
+        assert_eq!(parse_viewer_key(&[7u8; 32]), Ok([7u8; 32]));
+        assert!(parse_viewer_key(&[7u8; 31]).is_err());
+        assert!(parse_viewer_key(&[7u8; 33]).is_err());
+        assert!(parse_viewer_key(b"").is_err());
+    }
+}
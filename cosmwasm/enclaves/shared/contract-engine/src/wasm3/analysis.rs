@@ -0,0 +1,166 @@
+use log::*;
+use serde::Serialize;
+
+use cw_types_generic::{ContractFeature, CosmWasmApiVersion};
+use enclave_cosmos_types::types::ContractCode;
+use enclave_ffi_types::EnclaveError;
+
+use super::module_cache::{contract_features, cosmwasm_api_version};
+use super::validation;
+use crate::cosmwasm_config::ContractOperation;
+use crate::enclave_manifest::{is_host_function, missing_capabilities};
+use crate::memory_limits::DEFAULT_MAX_MEMORY_PAGES;
+
+/// The module contracts import host functions from
+const HOST_MODULE: &str = "env";
+
+/// The report returned by `analyze_contract`
+#[derive(Serialize, Debug, Default)]
+struct ContractAnalysis {
+    /// Whether the contract can be instantiated by this enclave
+    valid: bool,
+    code_hash: String,
+    /// `v0.10` or `v1`, if the contract has one of the markers of the API versions
+    api_version: Option<&'static str>,
+    features: Vec<ContractFeature>,
+    /// The exports the enclave needs to instantiate the contract, which it doesn't have
+    missing_exports: Vec<String>,
+    /// Imports that aren't host functions of this enclave. Contracts with such imports can be
+    /// stored and instantiated, but fail when they call them.
+    unknown_imports: Vec<String>,
+    /// Capabilities the contract requires that this enclave doesn't support
+    missing_capabilities: Vec<String>,
+    /// Contracts with floats are accepted, and the NaNs their float operations return are
+    /// canonicalized so they run deterministically
+    has_floats: bool,
+    /// The reasons the contract can't be instantiated
+    errors: Vec<String>,
+}
+
+fn required_exports(api_version: CosmWasmApiVersion) -> &'static [&'static str] {
+    match api_version {
+        CosmWasmApiVersion::V010 => &["allocate", "deallocate", "init"],
+        _ => &["allocate", "deallocate", "instantiate"],
+    }
+}
+
+fn analyze(contract_code: &ContractCode) -> ContractAnalysis {
+    let mut analysis = ContractAnalysis {
+        code_hash: hex::encode(contract_code.hash()),
+        ..Default::default()
+    };
+
+    let mut module = match walrus::ModuleConfig::new()
+        .generate_producers_section(false)
+        .parse(contract_code.code())
+    {
+        Ok(module) => module,
+        Err(err) => {
+            analysis
+                .errors
+                .push(format!("failed to parse wasm: {}", err));
+            return analysis;
+        }
+    };
+
+    analysis.unknown_imports = module
+        .imports
+        .iter()
+        .filter(|import| import.module != HOST_MODULE || !is_host_function(&import.name))
+        .map(|import| format!("{}.{}", import.module, import.name))
+        .collect();
+
+    match cosmwasm_api_version(&module) {
+        Some(api_version) => {
+            analysis.api_version = Some(match api_version {
+                CosmWasmApiVersion::V010 => "v0.10",
+                _ => "v1",
+            });
+            analysis.missing_exports = required_exports(api_version)
+                .iter()
+                .filter(|name| !module.exports.iter().any(|exp| exp.name == **name))
+                .map(|name| name.to_string())
+                .collect();
+            if !analysis.missing_exports.is_empty() {
+                analysis.errors.push(format!(
+                    "missing exports: {}",
+                    analysis.missing_exports.join(", ")
+                ));
+            }
+
+            match contract_features(contract_code, &module, api_version, ContractOperation::Init) {
+                Ok(features) => analysis.features = features,
+                Err(_) => analysis
+                    .errors
+                    .push("the contract declares unsupported features".to_string()),
+            }
+        }
+        None => analysis
+            .errors
+            .push("the contract doesn't export a supported CosmWasm API version".to_string()),
+    }
+
+    match contract_code.required_capabilities() {
+        Ok(required) => {
+            analysis.missing_capabilities = missing_capabilities(&required);
+            if !analysis.missing_capabilities.is_empty() {
+                analysis.errors.push(format!(
+                    "the contract requires unsupported capabilities: {}",
+                    analysis.missing_capabilities.join(", ")
+                ));
+            }
+        }
+        Err(err) => analysis
+            .errors
+            .push(format!("malformed requirements section: {}", err)),
+    }
+
+    // The memory limits of the compute params are in the env of executions, so contracts are
+    // analyzed with the default limit
+    if let Err(err) = validation::validate_memory(&mut module, DEFAULT_MAX_MEMORY_PAGES) {
+        analysis.errors.push(format!("invalid memory: {}", err));
+    }
+
+    analysis.has_floats = module.has_floats();
+
+    analysis.valid = analysis.errors.is_empty();
+    analysis
+}
+
+/// Runs the checks the enclave runs before instantiating a contract on `code`, without storing
+/// or running it, and returns a JSON report. This lets tools validate a contract against the
+/// rules of the enclave before storing it.
+pub fn analyze_contract(code: &[u8]) -> Result<Vec<u8>, EnclaveError> {
+    let analysis = analyze(&ContractCode::new(code));
+    debug!(
+        "analyzed contract {}: valid: {}",
+        analysis.code_hash, analysis.valid
+    );
+
+    serde_json::to_vec(&analysis).map_err(|err| {
+        warn!("failed to serialize contract analysis: {}", err);
+        EnclaveError::FailedToSerialize
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_analyze_contract() {
+        let analysis = analyze(&ContractCode::new(b"not wasm"));
+        assert!(!analysis.valid);
+        assert_eq!(analysis.errors.len(), 1);
+
+        // An empty module, without the marker of an API version or any exports
+        let analysis = analyze(&ContractCode::new(b"\0asm\x01\0\0\0"));
+        assert!(!analysis.valid);
+        assert_eq!(analysis.api_version, None);
+        assert!(analysis.unknown_imports.is_empty());
+        assert!(!analysis.has_floats);
+        assert_eq!(
+            analysis.errors,
+            vec!["the contract doesn't export a supported CosmWasm API version".to_string()]
+        );
+    }
+}
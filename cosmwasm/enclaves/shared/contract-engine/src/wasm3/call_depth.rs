@@ -0,0 +1,255 @@
+//! Call depth limit instrumentation.
+//!
+//! The interpreter only fails deep recursion once its stack runs out, which depends on the sizes
+//! of the frames and on the stack size of the runtime. Instead, calls between the functions of
+//! the contract count their depth in a global, and fail once it's over `max_call_depth` of the
+//! `WasmCosts`, so that recursive contracts fail at the same depth everywhere.
+//!
+//! The stack of the runtime is sized from the limit, so that calls within it fit. Contracts whose
+//! frames are larger than `STACK_SIZE_PER_CALL` can still run out of stack before the limit,
+//! which fails them with the depth they reached as well.
+
+use std::collections::HashSet;
+
+use walrus::{ir::*, FunctionBuilder, FunctionId, GlobalId, InitExpr, Module, ValType};
+
+/// Name of the exported global that holds the depth of the calls.
+pub const EXPORT_CALL_DEPTH: &str = "call_depth";
+/// Name of the exported global that holds the depth that was over the limit.
+pub const EXPORT_CALL_DEPTH_EXCEEDED: &str = "call_depth_exceeded";
+
+/// The stack of runtimes of modules without a call depth limit
+pub const DEFAULT_STACK_SIZE: u32 = 1024 * 60;
+/// The stack that each call within the limit is given
+pub const STACK_SIZE_PER_CALL: u32 = 1024;
+/// The stack of runtimes doesn't grow past this, whatever the limit
+pub const MAX_STACK_SIZE: u32 = 8 * 1024 * 1024;
+
+/// Returns the call depth that was over the limit, or 0 if the limit wasn't exceeded.
+pub fn get_exceeded_call_depth<C>(instance: &wasm3::Instance<C>) -> u64 {
+    instance
+        .get_global(EXPORT_CALL_DEPTH_EXCEEDED)
+        .unwrap_or_default()
+}
+
+/// Returns the depth of the calls, or 0 if the module has no call depth limit.
+pub fn get_call_depth<C>(instance: &wasm3::Instance<C>) -> u64 {
+    instance.get_global(EXPORT_CALL_DEPTH).unwrap_or_default()
+}
+
+/// The stack size of the runtime of a module with the call depth limit, if any
+pub fn runtime_stack_size(max_call_depth: Option<u32>) -> u32 {
+    match max_call_depth {
+        Some(max_call_depth) => max_call_depth
+            .saturating_mul(STACK_SIZE_PER_CALL)
+            .clamp(DEFAULT_STACK_SIZE, MAX_STACK_SIZE),
+        None => DEFAULT_STACK_SIZE,
+    }
+}
+
+/// Inject the call depth limit into the module.
+pub fn add_call_depth_limit(module: &mut Module, max_call_depth: u32) {
+    let call_depth_global =
+        module
+            .globals
+            .add_local(ValType::I64, true, InitExpr::Value(Value::I64(0)));
+    let call_depth_exceeded_global =
+        module
+            .globals
+            .add_local(ValType::I64, true, InitExpr::Value(Value::I64(0)));
+    module.exports.add(EXPORT_CALL_DEPTH, call_depth_global);
+    module
+        .exports
+        .add(EXPORT_CALL_DEPTH_EXCEEDED, call_depth_exceeded_global);
+
+    // Calls to imports return before they can call the contract again, so they aren't counted
+    let local_funcs: HashSet<FunctionId> = module
+        .funcs
+        .iter_local()
+        .map(|(func_id, _)| func_id)
+        .collect();
+
+    let enter_call = create_enter_call(
+        module,
+        max_call_depth,
+        call_depth_global,
+        call_depth_exceeded_global,
+    );
+    let exit_call = create_exit_call(module, call_depth_global);
+
+    for (func_id, func) in module.funcs.iter_local_mut() {
+        if !local_funcs.contains(&func_id) {
+            continue;
+        }
+
+        let block_ids: Vec<_> = func.blocks().map(|(block_id, _block)| block_id).collect();
+        for block_id in block_ids {
+            let block_instrs = &mut func.block_mut(block_id).instrs;
+            let call_locations: Vec<usize> = block_instrs
+                .iter()
+                .enumerate()
+                .filter(|(_, (instr, _))| match instr {
+                    Instr::Call(Call { func }) => local_funcs.contains(func),
+                    Instr::CallIndirect(_) => true,
+                    _ => false,
+                })
+                .map(|(loc, _)| loc)
+                .collect();
+
+            // In reverse, so that the insertions don't move the locations that are left
+            for loc in call_locations.into_iter().rev() {
+                let call_exit = Instr::from(Call { func: exit_call });
+                block_instrs.insert(loc + 1, (call_exit, Default::default()));
+                let call_enter = Instr::from(Call { func: enter_call });
+                block_instrs.insert(loc, (call_enter, Default::default()));
+            }
+        }
+    }
+}
+
+fn create_enter_call(
+    module: &mut Module,
+    max_call_depth: u32,
+    call_depth_global: GlobalId,
+    call_depth_exceeded_global: GlobalId,
+) -> FunctionId {
+    let mut func = FunctionBuilder::new(&mut module.types, &[], &[]);
+
+    func.func_body()
+        // globals[call_depth] += 1;
+        .global_get(call_depth_global)
+        .i64_const(1)
+        .binop(BinaryOp::I64Add)
+        .global_set(call_depth_global)
+        // if unsigned(globals[call_depth]) > max_call_depth { throw(); }
+        .global_get(call_depth_global)
+        .i64_const(max_call_depth as i64)
+        .binop(BinaryOp::I64GtU)
+        .if_else(
+            None,
+            |then| {
+                then.global_get(call_depth_global)
+                    .global_set(call_depth_exceeded_global)
+                    .unreachable();
+            },
+            |_else| {},
+        );
+
+    func.finish(vec![], &mut module.funcs)
+}
+
+fn create_exit_call(module: &mut Module, call_depth_global: GlobalId) -> FunctionId {
+    let mut func = FunctionBuilder::new(&mut module.types, &[], &[]);
+
+    func.func_body()
+        // globals[call_depth] -= 1;
+        .global_get(call_depth_global)
+        .i64_const(1)
+        .binop(BinaryOp::I64Sub)
+        .global_set(call_depth_global);
+
+    func.finish(vec![], &mut module.funcs)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use walrus::FunctionKind;
+
+    pub fn test_add_call_depth_limit() {
+        let mut module = Module::default();
+        let import_type = module.types.add(&[], &[]);
+        let (import, _) = module.add_import_func("env", "debug", import_type);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+        builder.func_body().i32_const(1);
+        let callee = builder.finish(vec![], &mut module.funcs);
+
+        let mut builder = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+        builder.func_body().call(import).call(callee);
+        let caller = builder.finish(vec![], &mut module.funcs);
+
+        add_call_depth_limit(&mut module, 100);
+
+        let func = match &module.funcs.get(caller).kind {
+            FunctionKind::Local(func) => func,
+            _ => panic!("the function is not local"),
+        };
+        let instrs = &func.block(func.entry_block()).instrs;
+        // Only the call to the contract's own function is counted
+        assert_eq!(instrs.len(), 4);
+        assert!(matches!(instrs[0].0, Instr::Call(Call { func }) if func == import));
+        assert!(matches!(instrs[2].0, Instr::Call(Call { func }) if func == callee));
+        let (enter_call, exit_call) = match (&instrs[1].0, &instrs[3].0) {
+            (Instr::Call(enter), Instr::Call(exit)) => (enter.func, exit.func),
+            other => panic!("calls weren't counted: {:?}", other),
+        };
+        assert_ne!(enter_call, exit_call);
+
+        assert!(module.exports.iter().any(|e| e.name == EXPORT_CALL_DEPTH));
+        assert!(module
+            .exports
+            .iter()
+            .any(|e| e.name == EXPORT_CALL_DEPTH_EXCEEDED));
+
+        // The instrumented module is still valid wasm
+        assert!(Module::from_buffer(&module.emit_wasm()).is_ok());
+    }
+
+    pub fn test_runtime_stack_size() {
+        assert_eq!(runtime_stack_size(None), DEFAULT_STACK_SIZE);
+        assert_eq!(runtime_stack_size(Some(1)), DEFAULT_STACK_SIZE);
+        assert_eq!(runtime_stack_size(Some(1024)), 1024 * STACK_SIZE_PER_CALL);
+        assert_eq!(runtime_stack_size(Some(u32::MAX)), MAX_STACK_SIZE);
+    }
+
+    /// `(func (export "recurse") (param i32) (result i32)`, which calls itself until its param
+    /// is 0, and returns the depth it reached
+    #[rustfmt::skip]
+    const RECURSIVE_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic and version
+        0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // type (i32) -> i32
+        0x03, 0x02, 0x01, 0x00, // function 0 has type 0
+        // export function 0 as "recurse"
+        0x07, 0x0b, 0x01, 0x07, b'r', b'e', b'c', b'u', b'r', b's', b'e', 0x00, 0x00,
+        0x0a, 0x17, 0x01, 0x15, 0x00, // code of function 0, without locals
+        0x20, 0x00, 0x45, 0x04, 0x7f, // if param == 0
+        0x41, 0x00, // then 0
+        // else recurse(param - 1) + 1
+        0x05, 0x20, 0x00, 0x41, 0x01, 0x6b, 0x10, 0x00, 0x41, 0x01, 0x6a,
+        0x0b, 0x0b, // end
+    ];
+
+    fn recurse(max_call_depth: u32, depth: u32) -> (Result<u32, wasm3::Error>, u64) {
+        let mut module = Module::from_buffer(RECURSIVE_MODULE).unwrap();
+        add_call_depth_limit(&mut module, max_call_depth);
+
+        let environment = wasm3::Environment::new().unwrap();
+        let runtime = environment
+            .new_runtime::<()>(runtime_stack_size(Some(max_call_depth)), None)
+            .unwrap();
+        let module = environment.parse_module(&module.emit_wasm()).unwrap();
+        let instance = runtime.load_module(module).unwrap();
+        let result = instance
+            .find_function::<u32, u32>("recurse")
+            .unwrap()
+            .call_with_context(&mut (), depth);
+
+        (result, get_exceeded_call_depth(&instance))
+    }
+
+    pub fn test_recursion_depth() {
+        let max_call_depth = 1024;
+
+        // Recursion up to the limit fits in the stack of the runtime
+        let (result, exceeded) = recurse(max_call_depth, max_call_depth);
+        assert_eq!(result.unwrap(), max_call_depth);
+        assert_eq!(exceeded, 0);
+
+        // and fails at the limit, rather than when the stack runs out
+        let (result, exceeded) = recurse(max_call_depth, max_call_depth + 1);
+        assert!(matches!(result, Err(wasm3::Error::UnreachableExecuted)));
+        assert_eq!(exceeded, max_call_depth as u64 + 1);
+    }
+}
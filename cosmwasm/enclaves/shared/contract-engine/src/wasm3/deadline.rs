@@ -0,0 +1,92 @@
+//! Wall-clock deadline for the queries that the node serves.
+//!
+//! Gas bounds the work done by the contract's own code, but not the time the host spends inside
+//! host functions. When the node configures a deadline, every host function call of a query that
+//! the node serves to a client checks it, and the query is aborted with
+//! `EnclaveError::ExecutionTimeout` once it has passed, regardless of the remaining gas.
+//!
+//! The clock is the untrusted host's, and a query can time out on one node but not on another.
+//! Executions that are part of consensus, and the queries that contracts make while they execute,
+//! are only limited by their gas, so that every node reaches the same result.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::untrusted::time::InstantEx;
+
+use log::*;
+
+use crate::errors::{WasmEngineError, WasmEngineResult};
+
+/// How long an execution may run for, in milliseconds. 0 means there is no deadline.
+static EXECUTION_DEADLINE_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+pub fn configure_execution_deadline(millis: u64) {
+    debug!("configuring execution deadline: {}ms", millis);
+    EXECUTION_DEADLINE_MILLIS.store(millis, Ordering::SeqCst);
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExecutionDeadline {
+    deadline: Option<Instant>,
+}
+
+impl ExecutionDeadline {
+    /// No deadline, for the executions that are part of consensus
+    pub fn none() -> Self {
+        Self { deadline: None }
+    }
+
+    /// Starts the configured deadline from now
+    pub fn start() -> Self {
+        match EXECUTION_DEADLINE_MILLIS.load(Ordering::SeqCst) {
+            0 => Self { deadline: None },
+            millis => Self::starting_at(Instant::now(), Duration::from_millis(millis)),
+        }
+    }
+
+    fn starting_at(start: Instant, timeout: Duration) -> Self {
+        Self {
+            deadline: start.checked_add(timeout),
+        }
+    }
+
+    /// Doesn't read the clock if there is no deadline
+    pub fn check(&self) -> WasmEngineResult<()> {
+        match self.deadline {
+            Some(deadline) => Self::check_at(deadline, Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    fn check_at(deadline: Instant, now: Instant) -> WasmEngineResult<()> {
+        if now > deadline {
+            debug!(
+                "execution passed its deadline by {:?}",
+                now.duration_since(deadline)
+            );
+            return Err(WasmEngineError::ExecutionTimeout);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_execution_deadline() {
+        let start = Instant::now();
+        let deadline = ExecutionDeadline::starting_at(start, Duration::from_millis(100))
+            .deadline
+            .unwrap();
+
+        assert!(ExecutionDeadline::check_at(deadline, start).is_ok());
+        assert!(ExecutionDeadline::check_at(deadline, deadline).is_ok());
+        assert!(matches!(
+            ExecutionDeadline::check_at(deadline, deadline + Duration::from_millis(1)),
+            Err(WasmEngineError::ExecutionTimeout)
+        ));
+
+        assert!(ExecutionDeadline::none().check().is_ok());
+    }
+}
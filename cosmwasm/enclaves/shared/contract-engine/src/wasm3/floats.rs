@@ -0,0 +1,204 @@
+//! NaN canonicalization of floating point operations.
+//!
+//! Wasm float operations are deterministic, except for the bits of the NaNs they return, which
+//! are left to the hardware. Replacing every NaN a float operation returns with the canonical NaN
+//! makes floats deterministic, so contracts that include float instructions can run in consensus.
+
+use log::*;
+
+use walrus::{ir::*, LocalFunction, LocalId, Module, ModuleLocals, ValType};
+
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+/// Number of instructions injected after each float operation.
+const CANONICALIZATION_INSTRUCTION_COUNT: usize = 7;
+
+/// The type of the result of float operations that can return a NaN with arbitrary bits.
+/// Sign operations (`abs`, `neg`, `copysign`), loads, constants and reinterpretations only
+/// move bits around, so their results are already deterministic.
+fn nan_producing_type(instr: &Instr) -> Option<ValType> {
+    match instr {
+        Instr::Binop(Binop { op }) => match op {
+            BinaryOp::F32Add
+            | BinaryOp::F32Sub
+            | BinaryOp::F32Mul
+            | BinaryOp::F32Div
+            | BinaryOp::F32Min
+            | BinaryOp::F32Max => Some(ValType::F32),
+            BinaryOp::F64Add
+            | BinaryOp::F64Sub
+            | BinaryOp::F64Mul
+            | BinaryOp::F64Div
+            | BinaryOp::F64Min
+            | BinaryOp::F64Max => Some(ValType::F64),
+            _ => None,
+        },
+        Instr::Unop(Unop { op }) => match op {
+            UnaryOp::F32Ceil
+            | UnaryOp::F32Floor
+            | UnaryOp::F32Trunc
+            | UnaryOp::F32Nearest
+            | UnaryOp::F32Sqrt
+            | UnaryOp::F32DemoteF64 => Some(ValType::F32),
+            UnaryOp::F64Ceil
+            | UnaryOp::F64Floor
+            | UnaryOp::F64Trunc
+            | UnaryOp::F64Nearest
+            | UnaryOp::F64Sqrt
+            | UnaryOp::F64PromoteF32 => Some(ValType::F64),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The scratch locals of a function, added the first time the function needs them
+#[derive(Default)]
+struct ScratchLocals {
+    f32: Option<LocalId>,
+    f64: Option<LocalId>,
+}
+
+impl ScratchLocals {
+    fn get(&mut self, locals: &mut ModuleLocals, ty: ValType) -> LocalId {
+        let local = match ty {
+            ValType::F32 => &mut self.f32,
+            _ => &mut self.f64,
+        };
+        *local.get_or_insert_with(|| locals.add(ty))
+    }
+}
+
+/// `select(canonical_nan, result, result != result)`, which replaces the result on the stack
+/// with the canonical NaN if it's a NaN
+fn canonicalization(ty: ValType, scratch: LocalId) -> Vec<Instr> {
+    let (canonical_nan, ne) = match ty {
+        ValType::F32 => (
+            Value::F32(f32::from_bits(CANONICAL_NAN_F32)),
+            BinaryOp::F32Ne,
+        ),
+        _ => (
+            Value::F64(f64::from_bits(CANONICAL_NAN_F64)),
+            BinaryOp::F64Ne,
+        ),
+    };
+
+    vec![
+        LocalSet { local: scratch }.into(),
+        Const {
+            value: canonical_nan,
+        }
+        .into(),
+        LocalGet { local: scratch }.into(),
+        LocalGet { local: scratch }.into(),
+        LocalGet { local: scratch }.into(),
+        Binop { op: ne }.into(),
+        Select { ty: None }.into(),
+    ]
+}
+
+fn transform_function(func: &mut LocalFunction, locals: &mut ModuleLocals) -> usize {
+    let mut scratch = ScratchLocals::default();
+    let mut canonicalized = 0;
+
+    let block_ids: Vec<_> = func.blocks().map(|(block_id, _block)| block_id).collect();
+    for block_id in block_ids {
+        let block = func.block_mut(block_id);
+        let float_ops: Vec<_> = block
+            .instrs
+            .iter()
+            .filter_map(|(instr, _)| nan_producing_type(instr))
+            .collect();
+        if float_ops.is_empty() {
+            continue;
+        }
+
+        let mut new_instrs = Vec::with_capacity(
+            block.instrs.len() + float_ops.len() * CANONICALIZATION_INSTRUCTION_COUNT,
+        );
+        for (instr, loc) in block.instrs.drain(..) {
+            let ty = nan_producing_type(&instr);
+            new_instrs.push((instr, loc));
+            if let Some(ty) = ty {
+                let local = scratch.get(locals, ty);
+                // using Default is fine - it's the same as what `InstrSeqBuilder::instr_at` does.
+                new_instrs.extend(
+                    canonicalization(ty, local)
+                        .into_iter()
+                        .map(|instr| (instr, Default::default())),
+                );
+            }
+        }
+        block.instrs = new_instrs;
+        canonicalized += float_ops.len();
+    }
+
+    canonicalized
+}
+
+/// Injects the canonicalization of NaNs after every float operation of the module
+pub fn canonicalize_nans(module: &mut Module) {
+    let mut canonicalized = 0;
+    for (_, func) in module.funcs.iter_local_mut() {
+        canonicalized += transform_function(func, &mut module.locals);
+    }
+    debug!(
+        "canonicalized the NaNs of {} float operations",
+        canonicalized
+    );
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use walrus::{FunctionBuilder, FunctionKind};
+
+    pub fn test_canonicalize_nans() {
+        let mut module = Module::default();
+        let a = module.locals.add(ValType::F64);
+        let b = module.locals.add(ValType::F64);
+        let mut builder = FunctionBuilder::new(
+            &mut module.types,
+            &[ValType::F64, ValType::F64],
+            &[ValType::F32],
+        );
+        builder
+            .func_body()
+            .local_get(a)
+            .local_get(b)
+            .binop(BinaryOp::F64Div)
+            .unop(UnaryOp::F32DemoteF64)
+            .unop(UnaryOp::F32Neg);
+        let func_id = builder.finish(vec![a, b], &mut module.funcs);
+        assert!(module.has_floats());
+
+        canonicalize_nans(&mut module);
+
+        let func = match &module.funcs.get(func_id).kind {
+            FunctionKind::Local(func) => func,
+            _ => panic!("the function is not local"),
+        };
+        let instrs = &func.block(func.entry_block()).instrs;
+        // Both the division and the demotion are canonicalized, the negation isn't
+        assert_eq!(instrs.len(), 5 + 2 * CANONICALIZATION_INSTRUCTION_COUNT);
+        assert!(matches!(instrs[9].0, Instr::Select(_)));
+        assert!(matches!(
+            instrs[4].0,
+            Instr::Const(Const {
+                value: Value::F64(_)
+            })
+        ));
+        assert!(matches!(
+            instrs[12].0,
+            Instr::Const(Const {
+                value: Value::F32(_)
+            })
+        ));
+        assert!(matches!(instrs.last().unwrap().0, Instr::Unop(_)));
+
+        // The instrumented module is still valid wasm
+        assert!(Module::from_buffer(&module.emit_wasm()).is_ok());
+    }
+}
@@ -0,0 +1,156 @@
+//! Which host functions a contract can call in which operation.
+//!
+//! The rules are kept in a single matrix, which `link_host_functions` consults when it links the
+//! host functions for an execution. A function that isn't available in the operation is linked to
+//! a stub that aborts the execution with `WasmEngineError::ForbiddenHostFunction`, naming the
+//! function, so the host functions themselves don't check the operation.
+//!
+//! The functions that aren't listed are available in every operation.
+
+use crate::cosmwasm_config::ContractOperation;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Availability {
+    init: bool,
+    handle: bool,
+    query: bool,
+    migrate: bool,
+}
+
+impl Availability {
+    pub fn allows(&self, operation: ContractOperation) -> bool {
+        match operation {
+            ContractOperation::Init => self.init,
+            ContractOperation::Handle => self.handle,
+            ContractOperation::Query => self.query,
+            ContractOperation::Migrate => self.migrate,
+        }
+    }
+}
+
+const EVERYWHERE: Availability = Availability {
+    init: true,
+    handle: true,
+    query: true,
+    migrate: true,
+};
+
+/// Functions that change the state, or what the transaction does, can't be called in queries
+const NOT_IN_QUERY: Availability = Availability {
+    query: false,
+    ..EVERYWHERE
+};
+
+/// Only instantiations and migrations, which the admin controls, can change the upgrade policy
+const INIT_OR_MIGRATE: Availability = Availability {
+    init: true,
+    handle: false,
+    query: false,
+    migrate: true,
+};
+
+const INIT_ONLY: Availability = Availability {
+    init: true,
+    handle: false,
+    query: false,
+    migrate: false,
+};
+
+const HANDLE_ONLY: Availability = Availability {
+    init: false,
+    handle: true,
+    query: false,
+    migrate: false,
+};
+
+const MIGRATE_ONLY: Availability = Availability {
+    init: false,
+    handle: false,
+    query: false,
+    migrate: true,
+};
+
+const RESTRICTED_HOST_FUNCTIONS: &[(&str, Availability)] = &[
+    ("db_write", NOT_IN_QUERY),
+    ("db_remove", NOT_IN_QUERY),
+    ("db_remove_range", NOT_IN_QUERY),
+    ("db_write_ordered", NOT_IN_QUERY),
+    ("execute_contracts_atomic", NOT_IN_QUERY),
+    ("set_upgrade_policy", INIT_OR_MIGRATE),
+    ("announce_migration", HANDLE_ONLY),
+    ("migration_checkpoint", MIGRATE_ONLY),
+    ("set_auditor_key", INIT_ONLY),
+    ("transient_write", NOT_IN_QUERY),
+    ("designate_viewer", NOT_IN_QUERY),
+    ("oblivious_write", NOT_IN_QUERY),
+    ("oblivious_remove", NOT_IN_QUERY),
+    ("random_commit", NOT_IN_QUERY),
+    ("random_reveal", NOT_IN_QUERY),
+];
+
+pub fn availability(name: &str) -> Availability {
+    RESTRICTED_HOST_FUNCTIONS
+        .iter()
+        .find(|(restricted, _)| *restricted == name)
+        .map(|(_, availability)| *availability)
+        .unwrap_or(EVERYWHERE)
+}
+
+pub fn is_available(name: &str, operation: ContractOperation) -> bool {
+    availability(name).allows(operation)
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use crate::enclave_manifest::is_host_function;
+
+    pub fn test_host_function_availability() {
+        use ContractOperation::*;
+
+        // Everything that isn't restricted is available everywhere
+        for operation in &[Init, Handle, Query, Migrate] {
+            assert!(is_available("db_read", *operation));
+            assert!(is_available("query_chain", *operation));
+            assert!(is_available("migration_cursor", *operation));
+        }
+
+        for name in &[
+            "db_write",
+            "db_remove",
+            "oblivious_write",
+            "designate_viewer",
+            "random_reveal",
+        ] {
+            assert!(is_available(name, Init));
+            assert!(is_available(name, Handle));
+            assert!(is_available(name, Migrate));
+            assert!(!is_available(name, Query));
+        }
+
+        assert!(is_available("set_upgrade_policy", Init));
+        assert!(!is_available("set_upgrade_policy", Handle));
+        assert!(is_available("set_upgrade_policy", Migrate));
+
+        assert!(is_available("announce_migration", Handle));
+        assert!(!is_available("announce_migration", Init));
+        assert!(!is_available("announce_migration", Migrate));
+
+        assert!(is_available("migration_checkpoint", Migrate));
+        assert!(!is_available("migration_checkpoint", Handle));
+
+        assert!(is_available("set_auditor_key", Init));
+        assert!(!is_available("set_auditor_key", Migrate));
+
+        // Nothing that restricts state changes is callable in a query
+        for (name, availability) in RESTRICTED_HOST_FUNCTIONS {
+            assert!(is_host_function(name), "{} isn't a host function", name);
+            assert!(
+                !availability.allows(Query),
+                "{} is available in queries",
+                name
+            );
+        }
+    }
+}
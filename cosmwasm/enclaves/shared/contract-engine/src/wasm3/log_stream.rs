@@ -0,0 +1,193 @@
+//! Streaming of the logs of a contract to the host while it runs.
+//!
+//! In builds with the `debug-print` feature, the messages a contract prints with `debug_print`
+//! are sent to the host with `ocall_stream_logs` in batches, which the host logs as they arrive.
+//! This lets developers follow long executions, e.g. migrations over large state on a testnet,
+//! instead of reading the logs after the execution completes.
+//!
+//! A batch is sent when it's full, when it's been waiting for longer than
+//! `STREAM_FLUSH_INTERVAL` at a host function call, and when the execution ends. The channel is
+//! bounded: lines are truncated to `MAX_STREAMED_LINE_BYTES`, and once an execution has streamed
+//! `MAX_STREAMED_BYTES` the remaining lines are dropped, so a contract that prints in a loop
+//! can't flood the host. Dropped lines are counted, and the count is sent with the next batch.
+
+// Without the feature nothing is pushed, and flushing does nothing
+#![cfg_attr(not(feature = "debug-print"), allow(dead_code))]
+
+use std::time::{Duration, Instant};
+use std::untrusted::time::InstantEx;
+
+use log::*;
+
+use enclave_ffi_types::{Ctx, OcallReturn};
+use sgx_types::sgx_status_t;
+
+use crate::external::ocalls;
+
+/// The most lines that are sent in one batch
+pub const MAX_BATCH_LINES: usize = 64;
+/// The most bytes of lines that are sent in one batch
+pub const MAX_BATCH_BYTES: usize = 16 * 1024;
+/// Longer lines are truncated
+pub const MAX_STREAMED_LINE_BYTES: usize = 2048;
+/// The most bytes of lines that one execution streams
+pub const MAX_STREAMED_BYTES: usize = 1024 * 1024;
+/// How long a line may wait for its batch to fill up
+pub const STREAM_FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default)]
+pub struct LogStream {
+    lines: Vec<String>,
+    batch_bytes: usize,
+    streamed_bytes: usize,
+    /// Lines that were dropped since the last batch that was sent
+    dropped: u64,
+    /// When the first line of the batch was pushed
+    batch_started: Option<Instant>,
+}
+
+impl LogStream {
+    /// Adds a line to the batch, and sends the batch if it's full
+    pub fn push(&mut self, context: &Ctx, line: String) {
+        if self.push_at(line, Instant::now()) {
+            self.flush(context);
+        }
+    }
+
+    /// Sends the batch if it's been waiting for longer than `STREAM_FLUSH_INTERVAL`. Doesn't
+    /// read the clock if the batch is empty.
+    pub fn flush_if_due(&mut self, context: &Ctx) {
+        if self.batch_started.is_some() && self.is_due_at(Instant::now()) {
+            self.flush(context);
+        }
+    }
+
+    /// Sends the batch, and the number of lines that were dropped, if there are any
+    pub fn flush(&mut self, context: &Ctx) {
+        let lines = self.lines.len() as u64;
+        let batch = match self.take_batch() {
+            Some(batch) => batch,
+            None => return,
+        };
+
+        let mut ocall_return = OcallReturn::Success;
+        let status = unsafe {
+            ocalls::ocall_stream_logs(
+                &mut ocall_return,
+                context.unsafe_clone(),
+                batch.as_ptr(),
+                batch.len(),
+            )
+        };
+        if status != sgx_status_t::SGX_SUCCESS || !matches!(ocall_return, OcallReturn::Success) {
+            debug!(
+                "failed to stream {} log lines to the host: {:?} {:?}",
+                lines, status, ocall_return
+            );
+            // The count is sent with the next batch
+            self.dropped += lines;
+        }
+    }
+
+    /// Returns whether the batch is full
+    fn push_at(&mut self, mut line: String, now: Instant) -> bool {
+        if line.len() > MAX_STREAMED_LINE_BYTES {
+            let mut end = MAX_STREAMED_LINE_BYTES;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+            line.push_str("...");
+        }
+
+        if self.streamed_bytes + line.len() > MAX_STREAMED_BYTES {
+            self.dropped += 1;
+            return false;
+        }
+
+        self.streamed_bytes += line.len();
+        self.batch_bytes += line.len();
+        self.lines.push(line);
+        self.batch_started.get_or_insert(now);
+
+        self.lines.len() >= MAX_BATCH_LINES || self.batch_bytes >= MAX_BATCH_BYTES
+    }
+
+    fn is_due_at(&self, now: Instant) -> bool {
+        self.batch_started.map_or(false, |started| {
+            now.saturating_duration_since(started) >= STREAM_FLUSH_INTERVAL
+        })
+    }
+
+    /// The JSON array of the lines of the batch, which starts with a note of the lines that were
+    /// dropped
+    fn take_batch(&mut self) -> Option<Vec<u8>> {
+        if self.lines.is_empty() && self.dropped == 0 {
+            return None;
+        }
+
+        let mut lines = Vec::with_capacity(self.lines.len() + 1);
+        if self.dropped != 0 {
+            lines.push(format!("({} log lines were dropped)", self.dropped));
+            self.dropped = 0;
+        }
+        lines.append(&mut self.lines);
+        self.batch_bytes = 0;
+        self.batch_started = None;
+
+        Some(serde_json::to_vec(&lines).unwrap())
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn batch_lines(stream: &mut LogStream) -> Vec<String> {
+        stream
+            .take_batch()
+            .map(|batch| serde_json::from_slice(&batch).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn test_log_stream() {
+        let start = Instant::now();
+        let mut stream = LogStream::default();
+        assert_eq!(stream.take_batch(), None);
+        assert!(!stream.is_due_at(start));
+
+        // A batch waits until it's full or due
+        assert!(!stream.push_at("first".to_string(), start));
+        assert!(!stream.is_due_at(start + STREAM_FLUSH_INTERVAL / 2));
+        assert!(stream.is_due_at(start + STREAM_FLUSH_INTERVAL));
+        for index in 1..MAX_BATCH_LINES - 1 {
+            assert!(!stream.push_at(index.to_string(), start));
+        }
+        assert!(stream.push_at("last".to_string(), start));
+        let lines = batch_lines(&mut stream);
+        assert_eq!(lines.len(), MAX_BATCH_LINES);
+        assert_eq!(lines[0], "first");
+        assert!(!stream.is_due_at(start + STREAM_FLUSH_INTERVAL));
+
+        // Long lines are truncated, on a character boundary
+        assert!(!stream.push_at("é".repeat(MAX_STREAMED_LINE_BYTES), start));
+        let lines = batch_lines(&mut stream);
+        assert_eq!(lines[0].len(), MAX_STREAMED_LINE_BYTES + "...".len());
+
+        // Past the limit of the execution lines are dropped, and the count is sent
+        let line = "x".repeat(MAX_STREAMED_LINE_BYTES);
+        while stream.streamed_bytes + line.len() <= MAX_STREAMED_BYTES {
+            if stream.push_at(line.clone(), start) {
+                stream.take_batch();
+            }
+        }
+        stream.take_batch();
+        assert!(!stream.push_at(line.clone(), start));
+        assert!(!stream.push_at(line, start));
+        assert_eq!(
+            batch_lines(&mut stream),
+            vec!["(2 log lines were dropped)".to_string()]
+        );
+        assert_eq!(stream.take_batch(), None);
+    }
+}
@@ -1,10 +1,12 @@
 use core::cmp::max;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
 
 use log::*;
 
 use bech32::{FromBase32, ToBase32};
-use cw_types_generic::{ContractFeature, CosmWasmApiVersion, CwEnv};
+use cw_types_generic::{BaseAddr, ContractFeature, CosmWasmApiVersion, CwEnv, VerifiedFee};
 use rand_chacha::ChaChaRng;
 use rand_core::SeedableRng;
 use sgx_rand::Rng;
@@ -13,24 +15,68 @@ use wasm3::{Instance, Memory, Trap};
 
 use cw_types_v010::consts::BECH32_PREFIX_ACC_ADDR;
 use cw_types_v010::encoding::Binary;
-use enclave_cosmos_types::types::{ContractCode, HandleType};
-use enclave_crypto::{sha_256, Ed25519PublicKey, WasmApiCryptoError};
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
+use enclave_cosmos_types::types::ContractCode;
+use enclave_crypto::{sha_256, Ed25519PublicKey, WasmApiCryptoError, HASH_SIZE};
 use enclave_ffi_types::{Ctx, EnclaveError};
 
-use crate::contract_validation::ContractKey;
-use crate::cosmwasm_config::ContractOperation;
-use crate::db::read_from_encrypted_state;
-use crate::db::{remove_from_encrypted_state, write_multiple_keys};
+use crate::atomic_calls::{parse_atomic_calls, AtomicCall};
+use crate::auditor::parse_auditor_key;
+use crate::bech32_codec::{self, Bech32Error, Variant};
+use crate::canonical_json::canonicalize_json;
+use crate::capability_summary::{CapabilitySummary, HostCalls};
+use crate::codecs::Codec;
+use crate::commit_reveal::{
+    commitment_key, derive_committed_random, record_commitment, reveal_commitment,
+    CommitRevealError, COMMITMENT_SIZE,
+};
+use crate::contract_subkeys::derive_contract_subkey;
+use crate::cosmwasm_config::{reserved_keys, storage_access_padding, ContractOperation};
+use crate::custom_query_auth::CustomQueryAuth;
+use crate::db::{
+    measure_stored_bytes, read_from_state, read_ordered_keys, read_ordered_namespaces,
+    remove_from_state, remove_prefix_from_state, with_cached_keys_with_prefix, write_multiple_keys,
+    write_to_encrypted_state, KeyScheme, StateKeys, StoredBytes,
+};
 use crate::errors::{ToEnclaveError, ToEnclaveResult, WasmEngineError, WasmEngineResult};
+use crate::fee_params::encode_fee_params;
 use crate::gas::{WasmCosts, READ_BASE_GAS, WRITE_BASE_GAS};
-use crate::query_chain::encrypt_and_query_chain;
+use crate::handle_routes::HandleRoute;
+use crate::migration_checkpoint::parse_migration_cursor;
+use crate::oblivious_storage::{self, Access, ObliviousBackend, ObliviousStorageError};
+use crate::ordered_keys::{namespace_of, OrderedNamespaces};
+use crate::panic_isolation::{catch_host_panic, LockOrRecover};
+use crate::query_cache::QueryCacheScope;
+use crate::query_chain::{encrypt_and_query_chain, query_gas_limit};
 use crate::random::MSG_COUNTER;
+use crate::sdk_responses::{decode_any, decode_sdk_response, SdkResponse, SdkResponseError};
+use crate::state_audit::{is_state_audit_enabled, record_flush, FlushRecord};
+use crate::storage_padding::{
+    mirrored_sizes, padded_access_count, padding_slot_key, padding_state_keys,
+};
+use crate::storage_quota::StorageUsage;
+use crate::touched_keys::TouchedKeys;
+use crate::transient_storage::{fits_in_transient_storage, read_transient, TransientScope};
 use crate::types::IoNonce;
+use crate::unique_id::derive_unique_id;
+use crate::upgrade_policy::{parse_announced_code_hash, parse_upgrade_policy, UpgradePolicy};
+use crate::viewer::parse_viewer_key;
+use crate::write_intents::{cancel_write_intent, record_write_intent};
 
+use call_depth::{get_call_depth, get_exceeded_call_depth, runtime_stack_size};
+use deadline::ExecutionDeadline;
 use gas::{get_exhausted_amount, get_remaining_gas, use_gas};
-use module_cache::create_module_instance;
-
+use host_availability::is_available;
+use log_stream::LogStream;
+use module_cache::{create_module_instance, VersionedCode};
+
+pub mod analysis;
+mod call_depth;
+pub mod deadline;
+mod floats;
 mod gas;
+mod host_availability;
+mod log_stream;
 pub mod module_cache;
 mod validation;
 // use std::time::Instant;
@@ -114,12 +160,74 @@ pub struct Context {
     gas_costs: WasmCosts,
     query_depth: u32,
     operation: ContractOperation,
-    og_contract_key: ContractKey,
+    state_keys: StateKeys,
     user_nonce: IoNonce,
     user_public_key: Ed25519PublicKey,
     kv_cache: KvCache,
     last_error: Option<WasmEngineError>,
     timestamp: u64,
+    atomic_calls: Vec<AtomicCall>,
+    upgrade_policy: Option<UpgradePolicy>,
+    migration_announcement: Option<[u8; HASH_SIZE]>,
+    /// The cursor of the migration that this migration resumes, read with `migration_cursor`
+    migration_cursor: Option<Vec<u8>>,
+    /// Set with `migration_checkpoint`, the migration is resumed from it later
+    migration_checkpoint: Option<Vec<u8>>,
+    auditor_key: Option<Ed25519PublicKey>,
+    /// Set with `designate_viewer`, can also decrypt the output of this execution
+    designated_viewer: Option<Ed25519PublicKey>,
+    query_cache: QueryCacheScope,
+    fee: Option<VerifiedFee>,
+    /// The proofs that the transaction carries, see `oracle_query`
+    oracle_proofs: Vec<Binary>,
+    /// Whether the answers to custom queries must be signed, see `custom_query_auth`
+    custom_query_auth: CustomQueryAuth,
+    pending_replies: Vec<u64>,
+    tx_hash: Option<String>,
+    /// The number of ids the contract got from `unique_id`
+    unique_ids: u64,
+    /// The calls the contract made to each host function, see `capability_summary`
+    host_calls: HostCalls,
+    /// Not set in queries, which can't use transient storage
+    transient_scope: Option<TransientScope>,
+    /// Written with `transient_write`, and kept for the rest of the transaction if the contract
+    /// succeeds
+    transient_writes: HashMap<Vec<u8>, Vec<u8>>,
+    /// Checked by every host function, but only set for the queries that the node serves, see
+    /// `deadline`
+    deadline: ExecutionDeadline,
+    /// The contract declared the `padded_storage_access` feature
+    pads_storage_access: bool,
+    /// The key sizes of the reads that missed the cache and were made to the chain state
+    read_key_sizes: Vec<usize>,
+    /// The dummy writes of `pad_storage_access`, which are encrypted with the padding key when
+    /// the cache is flushed
+    padding_writes: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The keys the contract read, wrote and removed
+    touched_keys: TouchedKeys,
+    /// The contract declared the `ordered_keys` feature
+    orders_keys: bool,
+    /// Read on first use, see `ordered_namespaces`
+    ordered_namespaces: Option<OrderedNamespaces>,
+    /// The keys that are left in the iterators opened with `db_scan`, by their id minus one
+    iterators: Vec<VecDeque<Vec<u8>>>,
+    /// Sends what the contract prints to the host while it runs, see `log_stream`
+    log_stream: LogStream,
+    /// The intent of the writes flushed to the host, see `write_intents`
+    write_intent: Option<Vec<u8>>,
+    /// Whether the execution is part of a transaction that is delivered in a block, whose writes
+    /// the host commits
+    deliver_tx: bool,
+    /// Writes and removes of the contract are accounted against its storage quota
+    enforces_storage_quota: bool,
+    /// The bytes that the host reported storing and removing for the contract in this
+    /// execution, see `storage_quota`
+    stored_bytes: StoredBytes,
+    /// The storage usage of the contract, once it was read
+    storage_usage: Option<Option<StorageUsage>>,
+    /// The height of the block and its seed, which `random_commit` and `random_reveal` use. Not
+    /// set if the contract didn't declare the `random` feature.
+    block_random: Option<(u64, Binary)>,
 }
 
 impl Context {
@@ -138,11 +246,107 @@ impl Context {
     pub fn set_last_error(&mut self, error: WasmEngineError) {
         self.last_error = Some(error);
     }
+
+    /// The namespaces of the contract that are ordered, which are read from the state once per
+    /// execution
+    fn ordered_namespaces(&mut self) -> WasmEngineResult<&mut OrderedNamespaces> {
+        if self.ordered_namespaces.is_none() {
+            let (namespaces, used_gas) = read_ordered_namespaces(
+                &self.context,
+                &self.state_keys,
+                !self.operation.is_query(),
+                &mut self.kv_cache,
+                &get_encryption_salt(self.timestamp),
+            )?;
+            self.use_gas_externally(used_gas);
+            self.ordered_namespaces = Some(namespaces);
+        }
+        Ok(self.ordered_namespaces.get_or_insert_with(Default::default))
+    }
+
+    /// The storage usage of the contract, or `None` if its count didn't start yet, see
+    /// `storage_quota`
+    fn storage_usage(&mut self) -> WasmEngineResult<Option<StorageUsage>> {
+        if let Some(usage) = &self.storage_usage {
+            return Ok(usage.clone());
+        }
+
+        let (stored, used_gas) = read_from_state(
+            reserved_keys::STORAGE_USAGE,
+            &self.context,
+            &self.state_keys,
+            !self.operation.is_query(),
+            &mut self.kv_cache,
+            &get_encryption_salt(self.timestamp),
+            KeyScheme::Siv,
+        )?;
+        self.use_gas_externally(used_gas);
+        let usage = StorageUsage::parse(stored.as_deref())?;
+        self.storage_usage = Some(usage.clone());
+        Ok(usage)
+    }
+
+    /// While the usage of the contract is counted, the last raw key that the count passed, up to
+    /// which the writes and removes are measured, see `measure_stored_bytes`
+    fn counted_until(&mut self) -> WasmEngineResult<Option<Vec<u8>>> {
+        if !self.enforces_storage_quota {
+            return Ok(None);
+        }
+        Ok(self
+            .storage_usage()?
+            .and_then(|usage| usage.counted_until().map(|raw_key| raw_key.to_vec())))
+    }
+
+    /// Reads a key that is reserved for the enclave, from the cache if it was written in this
+    /// execution
+    fn read_reserved(&mut self, key: &[u8]) -> WasmEngineResult<Option<Vec<u8>>> {
+        if let Some(value) = self.kv_cache.read(key) {
+            return Ok(Some(value));
+        }
+
+        let (value, used_gas) = read_from_state(
+            key,
+            &self.context,
+            &self.state_keys,
+            !self.operation.is_query(),
+            &mut self.kv_cache,
+            &get_encryption_salt(self.timestamp),
+            KeyScheme::Siv,
+        )?;
+        self.use_gas_externally(used_gas);
+
+        Ok(value)
+    }
+
+    /// How the key is stored. Only contracts that declared the `ordered_keys` feature can have
+    /// ordered namespaces, so other contracts don't pay for looking them up.
+    fn key_scheme(&mut self, key: &[u8]) -> WasmEngineResult<KeyScheme> {
+        if !self.orders_keys {
+            return Ok(KeyScheme::Siv);
+        }
+        Ok(self.ordered_namespaces()?.scheme_of(key))
+    }
+}
+
+/// Oblivious storage is kept in the contract's state like reserved keys, and its writes are
+/// flushed with the rest of the contract's writes
+impl ObliviousBackend for Context {
+    fn read(&mut self, key: &[u8]) -> WasmEngineResult<Option<Vec<u8>>> {
+        self.read_reserved(key)
+    }
+
+    fn write(&mut self, key: &[u8], value: &[u8]) {
+        self.kv_cache.write(key, value);
+    }
 }
 
 /// Wrap the hook function such that we expect the context to be passed in,
 /// and we save the WasmEngineError in the Context.
+/// The execution deadline is checked before and after the hook runs, and logs that waited long
+/// enough are streamed to the host after it. A panic in the hook aborts the execution, see
+/// `panic_isolation`.
 fn expect_context<F, A, R>(
+    name: &str,
     mut func: F,
 ) -> impl FnMut(wasm3::CallContext<Context>, A) -> Result<R, Trap> + 'static
 where
@@ -150,20 +354,31 @@ where
     A: wasm3::Arg,
     R: wasm3::Arg,
 {
+    let name = name.to_string();
     move |call_context, input| {
         let err_msg = "module functions must be called with a context";
         let context = call_context.context.expect(err_msg);
         let instance = call_context.instance;
-        func(context, instance, input).map_err(|err| {
-            context.set_last_error(err);
-            wasm3::Trap::Abort
-        })
+        context
+            .deadline
+            .check()
+            .and_then(|_| catch_host_panic(&name, || func(context, instance, input)))
+            .and_then(|output| context.deadline.check().map(|_| output))
+            .map_err(|err| {
+                context.set_last_error(err);
+                wasm3::Trap::Abort
+            })
+            .map(|output| {
+                context.log_stream.flush_if_due(&context.context);
+                output
+            })
     }
 }
 
 fn link_fn_no_args<F, R>(
     instance: &mut Instance<Context>,
-    name: &str,
+    operation: ContractOperation,
+    name: &'static str,
     mut func: F,
 ) -> Wasm3RsResult<()>
 where
@@ -173,21 +388,41 @@ where
     let wrapped_func =
         move |ctx: &mut Context, instance: &wasm3::Instance<Context>, _: ()| func(ctx, instance);
 
-    let wrapped_func = expect_context(wrapped_func);
-    instance
-        .link_function("env", name, wrapped_func)
-        .allow_missing_import()
+    link_fn(instance, operation, name, wrapped_func)
 }
 
-fn link_fn<F, A, R>(instance: &mut Instance<Context>, name: &str, func: F) -> Wasm3RsResult<()>
+/// Links the host function, or if it isn't available in the operation, a stub with the same
+/// signature that aborts the execution, see `host_availability`
+fn link_fn<F, A, R>(
+    instance: &mut Instance<Context>,
+    operation: ContractOperation,
+    name: &'static str,
+    mut func: F,
+) -> Wasm3RsResult<()>
 where
     F: FnMut(&mut Context, &wasm3::Instance<Context>, A) -> Result<R, WasmEngineError> + 'static,
     A: wasm3::Arg + 'static,
     R: wasm3::Arg + 'static,
 {
-    let func = expect_context(func);
+    if is_active(Feature::HostFunctionMatrix) && !is_available(name, operation) {
+        let forbidden = move |_: &mut Context, _: &wasm3::Instance<Context>, _: A| {
+            debug!("{} was called during {:?}", name, operation);
+            Err::<R, _>(WasmEngineError::ForbiddenHostFunction { name, operation })
+        };
+        let forbidden = expect_context(name, forbidden);
+        return instance
+            .link_function("env", name, forbidden)
+            .allow_missing_import();
+    }
+
+    // Counted for `capability_summary`
+    let counted = move |ctx: &mut Context, instance: &wasm3::Instance<Context>, args: A| {
+        *ctx.host_calls.entry(name).or_insert(0) += 1;
+        func(ctx, instance, args)
+    };
+    let counted = expect_context(name, counted);
     instance
-        .link_function("env", name, func)
+        .link_function("env", name, counted)
         .allow_missing_import()
 }
 
@@ -207,6 +442,22 @@ fn check_execution_result<T>(
             );
             EnclaveError::OutOfGas
         }
+        // Likewise if "call_depth_exceeded" isn't 0, the contract called too deep.
+        wasm3::Error::UnreachableExecuted if get_exceeded_call_depth(instance) != 0 => {
+            let depth = get_exceeded_call_depth(instance);
+            debug!("Detected call stack exceeded! Depth: {}", depth);
+            EnclaveError::CallStackExceeded {
+                depth: depth as u32,
+            }
+        }
+        // The frames of the contract can fill the stack before the limit, see `call_depth`
+        wasm3::Error::StackOverflow if get_call_depth(instance) != 0 => {
+            let depth = get_call_depth(instance);
+            debug!("Detected stack overflow! Depth: {}", depth);
+            EnclaveError::CallStackExceeded {
+                depth: depth as u32,
+            }
+        }
         // Otherwise, check if a hook set an error, in which case we propagate it.
         err => match context.take_last_error() {
             Some(err) => err.into(),
@@ -220,10 +471,7 @@ pub struct Engine {
     gas_limit: u64,
     used_gas: u64,
     environment: wasm3::Environment,
-    code: Vec<u8>,
-    api_version: CosmWasmApiVersion,
-    #[allow(dead_code)]
-    features: Vec<ContractFeature>,
+    module: Arc<VersionedCode>,
 }
 
 impl Engine {
@@ -233,14 +481,17 @@ impl Engine {
         gas_limit: u64,
         gas_costs: WasmCosts,
         contract_code: &ContractCode,
-        og_contract_key: ContractKey,
+        state_keys: StateKeys,
         operation: ContractOperation,
         user_nonce: IoNonce,
         user_public_key: Ed25519PublicKey,
         query_depth: u32,
         timestamp: u64,
+        query_cache: QueryCacheScope,
+        max_memory_pages: u32,
     ) -> Result<Engine, EnclaveError> {
-        let versioned_code = create_module_instance(contract_code, &gas_costs, operation)?;
+        let versioned_code =
+            create_module_instance(contract_code, &gas_costs, operation, max_memory_pages)?;
         let kv_cache = KvCache::new();
         let context = Context {
             context,
@@ -249,12 +500,48 @@ impl Engine {
             gas_used_externally: 0,
             gas_costs,
             operation,
-            og_contract_key,
+            state_keys,
             user_nonce,
             user_public_key,
             kv_cache,
             last_error: None,
             timestamp,
+            atomic_calls: vec![],
+            upgrade_policy: None,
+            migration_announcement: None,
+            migration_cursor: None,
+            migration_checkpoint: None,
+            auditor_key: None,
+            designated_viewer: None,
+            query_cache,
+            fee: None,
+            oracle_proofs: vec![],
+            custom_query_auth: CustomQueryAuth::unproven(),
+            pending_replies: vec![],
+            tx_hash: None,
+            unique_ids: 0,
+            host_calls: HostCalls::new(),
+            transient_scope: None,
+            transient_writes: HashMap::new(),
+            deadline: ExecutionDeadline::none(),
+            pads_storage_access: versioned_code
+                .features
+                .contains(&ContractFeature::PaddedStorageAccess),
+            read_key_sizes: vec![],
+            padding_writes: vec![],
+            touched_keys: TouchedKeys::default(),
+            orders_keys: versioned_code
+                .features
+                .contains(&ContractFeature::OrderedKeys),
+            ordered_namespaces: None,
+            iterators: vec![],
+            log_stream: LogStream::default(),
+            write_intent: None,
+            deliver_tx: false,
+            enforces_storage_quota: is_active(Feature::StorageQuota) && !operation.is_query(),
+            stored_bytes: StoredBytes::default(),
+            storage_usage: None,
+            block_random: None,
         };
 
         debug!("setting up runtime");
@@ -270,9 +557,7 @@ impl Engine {
             gas_limit,
             used_gas: 0,
             environment,
-            code: versioned_code.code,
-            api_version: versioned_code.version,
-            features: versioned_code.features,
+            module: versioned_code,
         })
     }
 
@@ -283,7 +568,10 @@ impl Engine {
         // let start = Instant::now();
         let runtime = self
             .environment
-            .new_runtime::<Context>(1024 * 60, Some(192 /* 12 MiB */))
+            .new_runtime::<Context>(
+                runtime_stack_size(self.module.max_call_depth),
+                Some(self.module.max_memory_pages),
+            )
             .to_enclave_result()?;
         // let duration = start.elapsed();
         // trace!("Time elapsed in environment.new_runtime is: {:?}", duration);
@@ -292,7 +580,7 @@ impl Engine {
         // let start = Instant::now();
         let module = self
             .environment
-            .parse_module(&self.code)
+            .parse_module(&self.module.code)
             .to_enclave_result()?;
         // let duration = start.elapsed();
         // trace!(
@@ -314,7 +602,7 @@ impl Engine {
         trace!("set gas limit");
 
         // let start = Instant::now();
-        Self::link_host_functions(&mut instance).to_enclave_result()?;
+        Self::link_host_functions(&mut instance, self.context.operation).to_enclave_result()?;
         // let duration = start.elapsed();
         // trace!("Time elapsed in link_host_functions is: {:?}", duration);
         trace!("linked functions");
@@ -324,6 +612,16 @@ impl Engine {
         // let duration = start.elapsed();
         // trace!("Instance: elapsed time for running func is: {:?}", duration);
         trace!("function returned {:?}", result);
+        self.context.log_stream.flush(&self.context.context);
+
+        let result = match result {
+            Ok(output) if self.context.pads_storage_access => {
+                pad_storage_access(&instance, &mut self.context)
+                    .map(|_| output)
+                    .map_err(EnclaveError::from)
+            }
+            result => result,
+        };
 
         self.used_gas = self
             .gas_limit
@@ -334,30 +632,80 @@ impl Engine {
         result
     }
 
-    fn link_host_functions(instance: &mut wasm3::Instance<Context>) -> Wasm3RsResult<()> {
-        link_fn(instance, "db_read", host_read_db)?;
-        link_fn(instance, "db_write", host_write_db)?;
-        link_fn(instance, "db_remove", host_remove_db)?;
-        link_fn(instance, "canonicalize_address", host_canonicalize_address)?;
-        link_fn(instance, "humanize_address", host_humanize_address)?;
-        link_fn(instance, "query_chain", host_query_chain)?;
+    fn link_host_functions(
+        instance: &mut wasm3::Instance<Context>,
+        operation: ContractOperation,
+    ) -> Wasm3RsResult<()> {
+        link_fn(instance, operation, "db_read", host_read_db)?;
+        link_fn(instance, operation, "db_write", host_write_db)?;
+        link_fn(instance, operation, "db_remove", host_remove_db)?;
+        link_fn(instance, operation, "db_remove_range", host_remove_range_db)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "canonicalize_address", host_canonicalize_address)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "humanize_address", host_humanize_address)?;
+        link_fn(instance, operation, "query_chain", host_query_chain)?;
 
-        link_fn(instance, "addr_canonicalize", host_addr_canonicalize)?;
-        link_fn(instance, "addr_humanize", host_humanize_address)?;
-        link_fn(instance, "addr_validate", host_addr_validate)?;
-        link_fn(instance, "debug_print", host_debug_print)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "addr_canonicalize", host_addr_canonicalize)?;
+        link_fn(instance, operation, "addr_humanize", host_humanize_address)?;
+        link_fn(instance, operation, "addr_validate", host_addr_validate)?;
+        link_fn(instance, operation, "debug_print", host_debug_print)?;
 
-        link_fn(instance, "debug", host_debug_print)?;
+        link_fn(instance, operation, "debug", host_debug_print)?;
 
-        link_fn(instance, "secp256k1_verify", host_secp256k1_verify)?;
         #[rustfmt::skip]
-        link_fn(instance, "secp256k1_recover_pubkey", host_secp256k1_recover_pubkey)?;
-        link_fn(instance, "ed25519_verify", host_ed25519_verify)?;
-        link_fn(instance, "ed25519_batch_verify", host_ed25519_batch_verify)?;
-        link_fn(instance, "secp256k1_sign", host_secp256k1_sign)?;
-        link_fn(instance, "ed25519_sign", host_ed25519_sign)?;
-        link_fn_no_args(instance, "check_gas", host_check_gas_used)?;
-        link_fn(instance, "gas_evaporate", host_gas_evaporate)?;
+        link_fn(instance, operation, "secp256k1_verify", host_secp256k1_verify)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "secp256k1_recover_pubkey", host_secp256k1_recover_pubkey)?;
+        link_fn(instance, operation, "ed25519_verify", host_ed25519_verify)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "ed25519_batch_verify", host_ed25519_batch_verify)?;
+        link_fn(instance, operation, "secp256k1_sign", host_secp256k1_sign)?;
+        link_fn(instance, operation, "ed25519_sign", host_ed25519_sign)?;
+        link_fn_no_args(instance, operation, "check_gas", host_check_gas_used)?;
+        link_fn(instance, operation, "gas_evaporate", host_gas_evaporate)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "execute_contracts_atomic", host_execute_contracts_atomic)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "set_upgrade_policy", host_set_upgrade_policy)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "announce_migration", host_announce_migration)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "migration_checkpoint", host_migration_checkpoint)?;
+        #[rustfmt::skip]
+        link_fn_no_args(instance, operation, "migration_cursor", host_migration_cursor)?;
+        link_fn(instance, operation, "set_auditor_key", host_set_auditor_key)?;
+        link_fn_no_args(instance, operation, "fee_params", host_fee_params)?;
+        link_fn_no_args(instance, operation, "pending_replies", host_pending_replies)?;
+        link_fn_no_args(instance, operation, "unique_id", host_unique_id)?;
+        link_fn(instance, operation, "canonical_json", host_canonical_json)?;
+        link_fn(instance, operation, "transient_read", host_transient_read)?;
+        link_fn(instance, operation, "transient_write", host_transient_write)?;
+        link_fn(instance, operation, "bech32_encode", host_bech32_encode)?;
+        link_fn(instance, operation, "bech32_decode", host_bech32_decode)?;
+        link_fn(instance, operation, "bech32_validate", host_bech32_validate)?;
+        link_fn(instance, operation, "base64_encode", host_base64_encode)?;
+        link_fn(instance, operation, "base64_decode", host_base64_decode)?;
+        link_fn(instance, operation, "hex_encode", host_hex_encode)?;
+        link_fn(instance, operation, "hex_decode", host_hex_decode)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "decode_sdk_response", host_decode_sdk_response)?;
+        link_fn(instance, operation, "decode_any", host_decode_any)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "derive_contract_subkey", host_derive_contract_subkey)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "designate_viewer", host_designate_viewer)?;
+        link_fn(instance, operation, "oblivious_read", host_oblivious_read)?;
+        link_fn(instance, operation, "oblivious_write", host_oblivious_write)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "oblivious_remove", host_oblivious_remove)?;
+        #[rustfmt::skip]
+        link_fn(instance, operation, "db_write_ordered", host_write_ordered_db)?;
+        link_fn(instance, operation, "db_scan", host_scan_db)?;
+        link_fn(instance, operation, "db_next", host_next_db)?;
+        link_fn(instance, operation, "random_commit", host_random_commit)?;
+        link_fn(instance, operation, "random_reveal", host_random_reveal)?;
 
         //    DbReadIndex = 0,
         //     DbWriteIndex = 1,
@@ -388,16 +736,135 @@ impl Engine {
     }
 
     pub fn get_api_version(&self) -> CosmWasmApiVersion {
-        self.api_version
+        self.module.version
     }
 
-    #[allow(dead_code)]
     pub fn supported_features(&self) -> &Vec<ContractFeature> {
-        &self.features
+        &self.module.features
+    }
+
+    /// Takes the calls the contract made with `execute_contracts_atomic`
+    pub fn take_atomic_calls(&mut self) -> Vec<AtomicCall> {
+        std::mem::take(&mut self.context.atomic_calls)
     }
 
-    pub fn migrate(&mut self, env: &CwEnv, msg: Vec<u8>) -> Result<Vec<u8>, EnclaveError> {
+    /// Takes the policy the contract set with `set_upgrade_policy`
+    pub fn take_upgrade_policy(&mut self) -> Option<UpgradePolicy> {
+        self.context.upgrade_policy.take()
+    }
+
+    /// Takes the code hash the contract announced with `announce_migration`
+    pub fn take_migration_announcement(&mut self) -> Option<[u8; HASH_SIZE]> {
+        self.context.migration_announcement.take()
+    }
+
+    /// Sets the cursor of the migration that this migration resumes
+    pub fn set_migration_cursor(&mut self, cursor: Vec<u8>) {
+        self.context.migration_cursor = Some(cursor);
+    }
+
+    /// Takes the cursor the contract checkpointed with `migration_checkpoint`
+    pub fn take_migration_checkpoint(&mut self) -> Option<Vec<u8>> {
+        self.context.migration_checkpoint.take()
+    }
+
+    /// Takes the key the contract set with `set_auditor_key`
+    pub fn take_auditor_key(&mut self) -> Option<Ed25519PublicKey> {
+        self.context.auditor_key.take()
+    }
+
+    /// Takes the key the contract designated with `designate_viewer`
+    pub fn take_designated_viewer(&mut self) -> Option<Ed25519PublicKey> {
+        self.context.designated_viewer.take()
+    }
+
+    /// Takes the intent of the writes that `flush_cache` flushed, which is empty if there were
+    /// none or the execution isn't part of a delivered transaction. The host acknowledges it once
+    /// it committed the block.
+    pub fn take_write_intent(&mut self) -> Vec<u8> {
+        self.context.write_intent.take().unwrap_or_default()
+    }
+
+    /// The summary of the capabilities the contract exercised, if the client requested it
+    pub fn capability_summary(&self, requested: bool) -> Option<CapabilitySummary> {
+        if !requested {
+            return None;
+        }
+        Some(CapabilitySummary::new(self.context.host_calls.clone()))
+    }
+
+    /// The keys the contract read, wrote and removed
+    pub fn touched_keys(&self) -> &TouchedKeys {
+        &self.context.touched_keys
+    }
+
+    /// Sets the fee of the transaction, which the contract reads with `fee_params`
+    pub fn set_verified_fee(&mut self, fee: Option<VerifiedFee>) {
+        self.context.fee = fee;
+    }
+
+    /// Starts the deadline the node configured for the queries it serves, see `deadline`
+    pub fn start_deadline(&mut self) {
+        self.context.deadline = ExecutionDeadline::start();
+    }
+
+    /// Sets the proofs of chain state that the transaction carries, which the oracle queries of
+    /// the contract are answered from
+    pub fn set_oracle_proofs(&mut self, oracle_proofs: Vec<Binary>) {
+        self.context.oracle_proofs = oracle_proofs;
+    }
+
+    /// Sets the responder key that the answers to the custom queries of the contract are
+    /// verified with
+    pub fn set_custom_query_auth(&mut self, custom_query_auth: CustomQueryAuth) {
+        self.context.custom_query_auth = custom_query_auth;
+    }
+
+    /// Sets the ids of the sibling submessages that still have to reply after the reply the
+    /// contract is handling, which the contract reads with `pending_replies`
+    pub fn set_pending_replies(&mut self, pending_replies: Vec<u64>) {
+        self.context.pending_replies = pending_replies;
+    }
+
+    /// Sets the hash of the transaction, which the ids returned by `unique_id` are derived from
+    pub fn set_tx_hash(&mut self, tx_hash: Option<String>) {
+        self.context.tx_hash = tx_hash;
+    }
+
+    /// Sets the height of the block and its seed, see `commit_reveal`
+    pub fn set_block_random(&mut self, block_height: u64, block_seed: Option<Binary>) {
+        self.context.block_random = block_seed.map(|block_seed| (block_height, block_seed));
+    }
+
+    /// Sets whether the execution is part of a delivered transaction. Only those record the intent
+    /// of their writes, since the writes of checked and simulated ones are never committed.
+    pub fn set_deliver_tx(&mut self, deliver_tx: bool) {
+        self.context.deliver_tx = deliver_tx;
+    }
+
+    /// Sets the transaction and the contract that `transient_read` and `transient_write` use
+    pub fn set_transient_scope(&mut self, scope: TransientScope) {
+        self.context.transient_scope = Some(scope);
+    }
+
+    /// Takes the entries the contract wrote with `transient_write`
+    pub fn take_transient_writes(&mut self) -> (Option<TransientScope>, HashMap<Vec<u8>, Vec<u8>>) {
+        (
+            self.context.transient_scope.take(),
+            std::mem::take(&mut self.context.transient_writes),
+        )
+    }
+
+    /// `migrate_info` is the serialized `MigrateInfo`, which is only passed to contracts that
+    /// accept it
+    pub fn migrate(
+        &mut self,
+        env: &CwEnv,
+        msg: Vec<u8>,
+        migrate_info: &[u8],
+    ) -> Result<Vec<u8>, EnclaveError> {
         let api_version = self.get_api_version();
+        let accepts_migrate_info = self.module.features.contains(&ContractFeature::MigrateInfo);
 
         self.with_instance(|instance, context| {
             debug!("starting migrate, api version: {:?}", api_version);
@@ -427,6 +894,16 @@ impl Engine {
                     );
                     migrate.call_with_context(context, args)
                 }
+                CosmWasmApiVersion::V1 if accepts_migrate_info => {
+                    let migrate_info_ptr = write_to_memory(instance, migrate_info)?;
+                    let (migrate, args) = (
+                        instance
+                            .find_function::<(u32, u32, u32), u32>("migrate")
+                            .to_enclave_result()?,
+                        (env_ptr, msg_ptr, migrate_info_ptr),
+                    );
+                    migrate.call_with_context(context, args)
+                }
                 CosmWasmApiVersion::V1 => {
                     let (migrate, args) = (
                         instance
@@ -528,10 +1005,15 @@ impl Engine {
         &mut self,
         env: &CwEnv,
         msg: Vec<u8>,
-        handle_type: &HandleType,
+        route: &HandleRoute,
     ) -> Result<Vec<u8>, EnclaveError> {
         let api_version = self.get_api_version();
 
+        if route.ibc_entrypoint && !self.module.features.contains(&ContractFeature::Ibc) {
+            warn!("contract received an IBC message but doesn't support IBC");
+            return Err(EnclaveError::FailedFunctionCall);
+        }
+
         self.with_instance(|instance, context| {
             trace!("starting handle");
             let (env_bytes, msg_info_bytes) = env.get_wasm_ptrs()?;
@@ -552,7 +1034,7 @@ impl Engine {
                     handle.call_with_context(context, args)
                 }
                 CosmWasmApiVersion::V1 => {
-                    let export_name = HandleType::get_export_name(handle_type);
+                    let export_name = route.export;
 
                     if export_name == "execute" {
                         let msg_info_ptr = write_to_memory(instance, &msg_info_bytes)?;
@@ -635,36 +1117,171 @@ impl Engine {
         })
     }
 
-    pub fn flush_cache(&mut self, random: Option<Binary>) -> Result<u64, EnclaveError> {
+    /// Reads a key that is reserved for the enclave from the contract's state
+    pub fn read_reserved_key(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, EnclaveError> {
+        if let Some(value) = self.context.kv_cache.read(key) {
+            return Ok(Some(value));
+        }
+
+        let (value, _) = read_from_state(
+            key,
+            &self.context.context,
+            &self.context.state_keys,
+            !self.context.operation.is_query(),
+            &mut self.context.kv_cache,
+            &get_encryption_salt(self.context.timestamp),
+            KeyScheme::Siv,
+        )
+        .map_err(EnclaveError::from)?;
+
+        Ok(value)
+    }
+
+    /// Writes a key that is reserved for the enclave to the cache, to be flushed with the
+    /// rest of the contract's writes
+    pub fn write_reserved_key(&mut self, key: &[u8], value: &[u8]) {
+        self.context.kv_cache.write(key, value);
+    }
+
+    /// Removes a key that is reserved for the enclave from the contract's state
+    pub fn remove_reserved_key(&mut self, key: &[u8]) -> Result<(), EnclaveError> {
+        self.context.kv_cache.remove(key);
+
+        let used_gas = remove_from_state(
+            key,
+            &self.context.context,
+            &self.context.state_keys,
+            KeyScheme::Siv,
+        )
+        .map_err(EnclaveError::from)?;
+        self.context.use_gas_externally(used_gas);
+
+        Ok(())
+    }
+
+    /// The contract address and block height are only used to record the flush in the state
+    /// audit log, if the audit mode is enabled
+    pub fn flush_cache(
+        &mut self,
+        random: Option<Binary>,
+        contract_address: &BaseAddr,
+        block_height: u64,
+    ) -> Result<u64, EnclaveError> {
         use crate::db::create_encrypted_key_value;
 
         // here we refund all the pseudo gas charged for writes to cache
         // todo: optimize to only charge for writes that change chain state
         let total_gas_to_refund = self.context.kv_cache.drain_gas_tracker();
 
-        let mut keys: Vec<(Vec<u8>, Vec<u8>)> = self
-            .context
-            .kv_cache
-            .flush()
-            .into_iter()
-            .map(|(k, v)| {
+        // The cache may hold changes of the ordered namespaces, so they're read before it's
+        // flushed
+        if self.context.orders_keys {
+            self.context
+                .ordered_namespaces()
+                .map_err(EnclaveError::from)?;
+        }
+
+        let flushed = self.context.kv_cache.flush();
+        let schemes = flushed
+            .iter()
+            .map(|(k, _)| self.context.key_scheme(k))
+            .collect::<WasmEngineResult<Vec<KeyScheme>>>()
+            .map_err(EnclaveError::from)?;
+
+        let counted_until = self.context.counted_until().map_err(EnclaveError::from)?;
+
+        // Encrypting a key also removes its entry in the legacy format, if there's one
+        let (mut keys, stored_bytes) = measure_stored_bytes(counted_until.as_deref(), || {
+            flushed
+                .iter()
+                .zip(&schemes)
+                .map(|((k, v), scheme)| {
+                    let (enc_key, _, enc_v) = create_encrypted_key_value(
+                        k,
+                        v,
+                        &self.context.context,
+                        &self.context.state_keys.current,
+                        &get_encryption_salt(self.context.timestamp),
+                        *scheme,
+                    )
+                    .unwrap();
+
+                    (enc_key.to_vec(), enc_v)
+                })
+                .collect::<Vec<(Vec<u8>, Vec<u8>)>>()
+        });
+        self.context.stored_bytes.add(stored_bytes);
+
+        if !self.context.padding_writes.is_empty() {
+            let padding_keys =
+                padding_state_keys(&self.context.state_keys.og).map_err(EnclaveError::from)?;
+            for (k, v) in self.context.padding_writes.drain(..) {
                 let (enc_key, _, enc_v) = create_encrypted_key_value(
                     &k,
                     &v,
                     &self.context.context,
-                    &self.context.og_contract_key,
+                    &padding_keys.current,
                     &get_encryption_salt(self.context.timestamp),
+                    KeyScheme::Siv,
                 )
-                .unwrap();
+                .map_err(EnclaveError::from)?;
+                keys.push((enc_key, enc_v));
+            }
 
-                (enc_key.to_vec(), enc_v)
-            })
-            .collect();
+            // Ordered by their encrypted keys, the dummies are mixed with the contract's writes
+            keys.sort();
+        }
 
         if let Some(random_unwraped) = random {
             shuffle_cache(&mut keys, random_unwraped);
         }
 
+        // The intent is recorded before anything is written to the host
+        let write_intent = if keys.is_empty() || !self.context.deliver_tx {
+            None
+        } else {
+            Some(record_write_intent(&keys))
+        };
+        let (written, stored_bytes) = measure_stored_bytes(counted_until.as_deref(), || {
+            self.write_flushed(&flushed, &schemes, keys, contract_address, block_height)
+        });
+        self.context.stored_bytes.add(stored_bytes);
+        if let Err(err) = written {
+            if let Some(write_intent) = write_intent {
+                cancel_write_intent(&write_intent);
+            }
+            return Err(err);
+        }
+        self.context.write_intent = write_intent;
+
+        Ok(total_gas_to_refund)
+    }
+
+    fn write_flushed(
+        &self,
+        flushed: &[(Vec<u8>, Vec<u8>)],
+        schemes: &[KeyScheme],
+        keys: Vec<(Vec<u8>, Vec<u8>)>,
+        contract_address: &BaseAddr,
+        block_height: u64,
+    ) -> Result<(), EnclaveError> {
+        use crate::db::remove_from_encrypted_state;
+
+        // While the state is being re-encrypted, entries of the previous epoch must not
+        // outlive the values we write now
+        if let Some(previous_key) = &self.context.state_keys.previous {
+            for ((k, _), scheme) in flushed.iter().zip(schemes) {
+                remove_from_encrypted_state(k, &self.context.context, previous_key, *scheme)
+                    .map_err(EnclaveError::from)?;
+            }
+        }
+
+        let audit_record = if is_state_audit_enabled() {
+            Some(FlushRecord::new(contract_address, block_height, &keys))
+        } else {
+            None
+        };
+
         write_multiple_keys(&self.context.context, keys).map_err(|err| {
             debug!(
                 "write_db() error while trying to write the value to state: {:?}",
@@ -674,7 +1291,60 @@ impl Engine {
             EnclaveError::from(err)
         })?;
 
-        Ok(total_gas_to_refund)
+        if let Some(audit_record) = audit_record {
+            record_flush(audit_record);
+        }
+
+        Ok(())
+    }
+
+    /// Applies what the execution stored to the storage usage of the contract once its cache
+    /// was flushed, see `storage_quota`. Returns the surcharge of the growth, which is added to
+    /// the gas used.
+    pub fn account_storage(&mut self) -> Result<u64, EnclaveError> {
+        if !self.context.enforces_storage_quota {
+            return Ok(0);
+        }
+
+        let stored_bytes = std::mem::take(&mut self.context.stored_bytes);
+        let mut usage = match self.context.storage_usage()? {
+            Some(_) if stored_bytes == StoredBytes::default() => return Ok(0),
+            Some(usage) => usage,
+            // A new contract had no entries before the ones it stored. Its usage is stored even
+            // if it stored nothing, so that it isn't counted at the end of a block.
+            None if self.context.operation.is_init() => StorageUsage::default(),
+            // The count at the end of a block includes what the contract stored until then
+            None => return Ok(0),
+        };
+        let surcharge = usage.apply(stored_bytes, &self.context.gas_costs)?;
+
+        self.used_gas = self.used_gas.saturating_add(surcharge);
+        if self.used_gas > self.gas_limit {
+            return Err(EnclaveError::OutOfGas);
+        }
+
+        let used_gas = write_to_encrypted_state(
+            reserved_keys::STORAGE_USAGE,
+            &usage.serialize(),
+            &self.context.context,
+            &self.context.state_keys.current,
+            &get_encryption_salt(self.context.timestamp),
+            KeyScheme::Siv,
+        )?;
+        self.context.use_gas_externally(used_gas);
+        self.context.storage_usage = Some(Some(usage));
+
+        Ok(surcharge)
+    }
+}
+
+impl Drop for Engine {
+    /// The host drops the writes of executions that fail after they flushed their cache, so their
+    /// intents are never acknowledged
+    fn drop(&mut self) {
+        if let Some(write_intent) = self.context.write_intent.take() {
+            cancel_write_intent(&write_intent);
+        }
     }
 }
 
@@ -910,6 +1580,7 @@ fn host_read_db(
     )?;
 
     debug!("db_read reading key {}", show_bytes(&state_key_name));
+    context.touched_keys.read(&state_key_name);
 
     let value = context.kv_cache.read(&state_key_name);
 
@@ -927,10 +1598,12 @@ fn host_read_db(
     }
 
     debug!("Missed value in cache");
-    let (value, used_gas) = read_from_encrypted_state(
+    context.read_key_sizes.push(state_key_name.len());
+    let scheme = context.key_scheme(&state_key_name)?;
+    let (value, used_gas) = read_from_state(
         &state_key_name,
         &context.context,
-        &context.og_contract_key,
+        &context.state_keys,
         match context.operation {
             ContractOperation::Init => true,
             ContractOperation::Handle => true,
@@ -939,6 +1612,7 @@ fn host_read_db(
         },
         &mut context.kv_cache,
         &get_encryption_salt(context.timestamp),
+        scheme,
     )
     .map_err(debug_err!("db_read failed to read key from storage"))?;
     context.use_gas_externally(used_gas);
@@ -959,42 +1633,148 @@ fn host_read_db(
     Ok(region_ptr as i32)
 }
 
-fn host_remove_db(
-    context: &mut Context,
+/// Makes the dummy storage accesses of contracts that declared the `padded_storage_access`
+/// feature, after the contract returned. The dummy writes are flushed with the contract's writes.
+fn pad_storage_access(
     instance: &wasm3::Instance<Context>,
-    state_key_region_ptr: i32,
+    context: &mut Context,
 ) -> WasmEngineResult<()> {
+    let padding_keys = padding_state_keys(&context.state_keys.og)?;
+
+    let read_sizes: Vec<(usize, usize)> = context
+        .read_key_sizes
+        .iter()
+        .map(|key_size| (*key_size, 0))
+        .collect();
+    let reads = read_sizes.len() as u64;
+    let dummy_reads = padded_access_count(reads, storage_access_padding::READS) - reads;
+    for slot in 0..dummy_reads {
+        use_gas(instance, READ_BASE_GAS)?;
+        let (key_size, _) = mirrored_sizes(&read_sizes, slot);
+        // The dummies aren't cached, since their keys may be the keys of the contract's entries
+        let (_, used_gas) = read_from_state(
+            &padding_slot_key(slot, key_size),
+            &context.context,
+            &padding_keys,
+            false,
+            &mut KvCache::new(),
+            &get_encryption_salt(context.timestamp),
+            KeyScheme::Siv,
+        )
+        .map_err(debug_err!("failed to make a dummy storage read"))?;
+        context.use_gas_externally(used_gas);
+    }
+
     if context.operation.is_query() {
-        debug!("db_remove was called while in query mode");
-        return Err(WasmEngineError::UnauthorizedWrite);
+        return Ok(());
+    }
+
+    let write_sizes = context.kv_cache.pending_write_sizes();
+    let writes = write_sizes.len() as u64;
+    let dummy_writes = padded_access_count(writes, storage_access_padding::WRITES) - writes;
+    for slot in 0..dummy_writes {
+        use_gas(instance, WRITE_BASE_GAS)?;
+        let (key_size, value_size) = mirrored_sizes(&write_sizes, slot);
+        context
+            .padding_writes
+            .push((padding_slot_key(slot, key_size), vec![0u8; value_size]));
     }
+    debug!(
+        "padded storage access with {} dummy reads and {} dummy writes",
+        dummy_reads, dummy_writes
+    );
+
+    Ok(())
+}
 
+fn host_remove_db(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    state_key_region_ptr: i32,
+) -> WasmEngineResult<()> {
     let state_key_name = read_from_memory(instance, state_key_region_ptr as u32).map_err(
         debug_err!(err => "db_remove failed to extract vector from state_key_region_ptr: {err}"),
     )?;
 
+    if reserved_keys::is_reserved(&state_key_name) {
+        debug!("db_remove was called with a reserved key");
+        return Err(WasmEngineError::UnauthorizedWrite);
+    }
+
     debug!("db_remove removing key {}", show_bytes(&state_key_name));
 
+    context.touched_keys.write(&state_key_name);
+
     // Also remove the key from the cache to avoid rewriting it
     context.kv_cache.remove(&state_key_name);
 
-    let used_gas =
-        remove_from_encrypted_state(&state_key_name, &context.context, &context.og_contract_key)?;
-    context.use_gas_externally(used_gas);
+    let scheme = context.key_scheme(&state_key_name)?;
+    let counted_until = context.counted_until()?;
+    let (used_gas, stored_bytes) = measure_stored_bytes(counted_until.as_deref(), || {
+        remove_from_state(
+            &state_key_name,
+            &context.context,
+            &context.state_keys,
+            scheme,
+        )
+    });
+    context.stored_bytes.add(stored_bytes);
+    context.use_gas_externally(used_gas?);
 
     Ok(())
 }
 
-fn host_write_db(
+/// Removes every key that starts with the prefix, and returns how many keys were removed. Besides
+/// the removes, the gas is proportional to the number of keys of the contract, which are all
+/// scanned, see `remove_prefix_from_state`.
+fn host_remove_range_db(
     context: &mut Context,
     instance: &wasm3::Instance<Context>,
-    (state_key_region_ptr, value_region_ptr): (i32, i32),
-) -> WasmEngineResult<()> {
-    if context.operation.is_query() {
-        debug!("db_write was called while in query mode");
+    prefix_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    use_gas(instance, context.gas_costs.external_db_remove_range as u64)?;
+
+    let prefix = read_from_memory(instance, prefix_region_ptr as u32).map_err(
+        debug_err!(err => "db_remove_range failed to extract vector from prefix_region_ptr: {err}"),
+    )?;
+
+    if reserved_keys::is_reserved(&prefix) {
+        debug!("db_remove_range was called with a reserved prefix");
         return Err(WasmEngineError::UnauthorizedWrite);
     }
 
+    debug!("db_remove_range removing prefix {}", show_bytes(&prefix));
+
+    let gas_per_key = context.gas_costs.external_db_remove_range_per_key as u64;
+    let counted_until = context.counted_until()?;
+    let (removed, stored_bytes) = measure_stored_bytes(counted_until.as_deref(), || {
+        remove_prefix_from_state(
+            &prefix,
+            &context.context,
+            &context.state_keys,
+            |scanned_keys| use_gas(instance, scanned_keys.saturating_mul(gas_per_key)),
+        )
+    });
+    context.stored_bytes.add(stored_bytes);
+    let (removed_keys, used_gas) = removed?;
+    context.use_gas_externally(used_gas);
+
+    // Also remove the keys from the cache, including writes that weren't flushed yet
+    let removed_keys = with_cached_keys_with_prefix(removed_keys, &context.kv_cache, &prefix);
+    for key in &removed_keys {
+        context.kv_cache.remove(key);
+        context.touched_keys.write(key);
+    }
+
+    debug!("db_remove_range removed {} keys", removed_keys.len());
+    Ok(removed_keys.len().min(i32::MAX as usize) as i32)
+}
+
+fn host_write_db(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (state_key_region_ptr, value_region_ptr): (i32, i32),
+) -> WasmEngineResult<()> {
     use_gas(instance, WRITE_BASE_GAS)?;
 
     let state_key_name = read_from_memory(instance, state_key_region_ptr as u32).map_err(
@@ -1004,50 +1784,242 @@ fn host_write_db(
         debug_err!(err => "db_write failed to extract vector from value_region_ptr: {err}"),
     )?;
 
+    if reserved_keys::is_reserved(&state_key_name) {
+        debug!("db_write was called with a reserved key");
+        return Err(WasmEngineError::UnauthorizedWrite);
+    }
+
     debug!(
         "db_write writing key: {}, value: {}",
         show_bytes(&state_key_name),
         show_bytes(&value)
     );
 
+    context.touched_keys.write(&state_key_name);
     let (_, pseudo_cost_for_write) = context.kv_cache.write(&state_key_name, &value);
     use_gas(instance, pseudo_cost_for_write)?; // Use gas now, refund later
 
     Ok(())
 }
 
-fn host_canonicalize_address(
+/// Writes like `db_write`, and orders the namespace of the key if it isn't ordered yet, see
+/// `ordered_keys`
+fn host_write_ordered_db(
     context: &mut Context,
     instance: &wasm3::Instance<Context>,
-    (human_region_ptr, canonical_region_ptr): (i32, i32),
-) -> WasmEngineResult<i32> {
-    let used_gas = context.gas_costs.external_canonicalize_address as u64;
-    use_gas(instance, used_gas)?;
+    (state_key_region_ptr, value_region_ptr): (i32, i32),
+) -> WasmEngineResult<()> {
+    if !context.orders_keys {
+        debug!("db_write_ordered was called by a contract that didn't declare ordered_keys");
+        return Err(WasmEngineError::NonExistentImportFunction);
+    }
 
-    let human = read_from_memory(instance, human_region_ptr as u32)
-        .map_err(debug_err!(err => "canonicalize_address failed to extract vector from human_region_ptr: {err}"))?;
+    host_write_db(context, instance, (state_key_region_ptr, value_region_ptr))?;
 
-    let mut human_addr_str = match std::str::from_utf8(&human) {
-        Ok(addr) => addr,
-        Err(_err) => {
-            debug!(
-                "canonicalize_address input was not valid UTF-8: {}",
-                show_bytes(&human)
-            );
-            return write_to_memory(instance, b"input is not valid UTF-8")
-                .map(|n| n as i32)
-                .map_err(debug_err!("failed to write error message to contract"));
-        }
-    };
-    human_addr_str = human_addr_str.trim();
-    if human_addr_str.is_empty() {
-        debug!("canonicalize_address input was empty");
-        return write_to_memory(instance, b"input is empty")
-            .map(|n| n as i32)
-            .map_err(debug_err!("failed to write error message to contract"));
-    }
+    let state_key_name = read_from_memory(instance, state_key_region_ptr as u32).map_err(
+        debug_err!(err => "db_write_ordered failed to extract vector from state_key_region_ptr: {err}"),
+    )?;
 
-    //debug!("canonicalize_address was called with {:?}", human_addr_str);
+    let namespaces = context.ordered_namespaces()?;
+    if namespaces.insert_namespace_of(&state_key_name)? {
+        let serialized = namespaces.serialize();
+        context
+            .kv_cache
+            .write(reserved_keys::ORDERED_NAMESPACES, &serialized);
+        debug!(
+            "db_write_ordered ordered the namespace of key {}",
+            show_bytes(&state_key_name)
+        );
+    }
+
+    Ok(())
+}
+
+/// Opens an iterator over the keys of an ordered namespace that are at least `start` and less
+/// than `end`, and returns its id. The namespace is the one of `start`, and namespaces that
+/// aren't ordered are empty. `order` is 1 for ascending and 2 for descending, like cosmwasm-std's
+/// `Order`.
+fn host_scan_db(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (start_region_ptr, end_region_ptr, order): (i32, i32, i32),
+) -> WasmEngineResult<i32> {
+    if !context.orders_keys {
+        debug!("db_scan was called by a contract that didn't declare ordered_keys");
+        return Err(WasmEngineError::NonExistentImportFunction);
+    }
+
+    use_gas(instance, context.gas_costs.external_db_scan as u64)?;
+
+    if start_region_ptr == 0 {
+        debug!("db_scan was called without a start, which has the namespace to range over");
+        return Err(WasmEngineError::InvalidIterator);
+    }
+    let start = read_from_memory(instance, start_region_ptr as u32).map_err(
+        debug_err!(err => "db_scan failed to extract vector from start_region_ptr: {err}"),
+    )?;
+    let end = match end_region_ptr {
+        0 => None,
+        ptr => Some(read_from_memory(instance, ptr as u32).map_err(
+            debug_err!(err => "db_scan failed to extract vector from end_region_ptr: {err}"),
+        )?),
+    };
+    let ascending = match order {
+        1 => true,
+        2 => false,
+        _ => {
+            debug!("db_scan was called with an unknown order {}", order);
+            return Err(WasmEngineError::InvalidIterator);
+        }
+    };
+
+    let namespace = match namespace_of(&start) {
+        Some(namespace) => namespace.to_vec(),
+        None => {
+            debug!("db_scan was called with a start that has no namespace");
+            return Err(WasmEngineError::InvalidIterator);
+        }
+    };
+
+    let mut keys = BTreeSet::new();
+    if context.ordered_namespaces()?.contains_key(&start) {
+        let gas_per_key = context.gas_costs.external_db_scan_per_key as u64;
+        let (stored_keys, used_gas) = read_ordered_keys(
+            &start,
+            end.as_deref(),
+            &context.context,
+            &context.state_keys,
+            |read_keys| use_gas(instance, read_keys.saturating_mul(gas_per_key)),
+        )?;
+        context.use_gas_externally(used_gas);
+        keys = stored_keys;
+
+        // Also the writes that weren't flushed yet
+        let in_range = |key: &Vec<u8>| *key >= start && end.as_ref().map_or(true, |end| key < end);
+        keys.extend(
+            context
+                .kv_cache
+                .keys_with_prefix(&namespace)
+                .into_iter()
+                .filter(in_range),
+        );
+    }
+
+    debug!(
+        "db_scan opened an iterator over {} keys from {}",
+        keys.len(),
+        show_bytes(&start)
+    );
+
+    let mut keys: VecDeque<Vec<u8>> = keys.into_iter().collect();
+    if !ascending {
+        keys = keys.into_iter().rev().collect();
+    }
+    context.iterators.push(keys);
+
+    Ok(context.iterators.len().min(i32::MAX as usize) as i32)
+}
+
+/// Returns the next entry of an iterator opened with `db_scan`, encoded like cosmwasm-std's
+/// `encode_sections`: the key, its length as a big endian u32, the value and its length. An
+/// empty key and value mark the end of the iterator.
+fn host_next_db(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    iterator_id: i32,
+) -> WasmEngineResult<i32> {
+    if !context.orders_keys {
+        debug!("db_next was called by a contract that didn't declare ordered_keys");
+        return Err(WasmEngineError::NonExistentImportFunction);
+    }
+
+    use_gas(instance, context.gas_costs.external_db_next as u64)?;
+
+    loop {
+        let iterator = (iterator_id as usize)
+            .checked_sub(1)
+            .and_then(|index| context.iterators.get_mut(index))
+            .ok_or_else(|| {
+                debug!(
+                    "db_next was called with an unknown iterator {}",
+                    iterator_id
+                );
+                WasmEngineError::InvalidIterator
+            })?;
+
+        let key = match iterator.pop_front() {
+            Some(key) => key,
+            None => return write_to_memory(instance, &encode_kv_pair(&[], &[])).map(|n| n as i32),
+        };
+
+        let value = match context.kv_cache.read(&key) {
+            Some(value) => Some(value),
+            None => {
+                context.read_key_sizes.push(key.len());
+                let (value, used_gas) = read_from_state(
+                    &key,
+                    &context.context,
+                    &context.state_keys,
+                    !context.operation.is_query(),
+                    &mut context.kv_cache,
+                    &get_encryption_salt(context.timestamp),
+                    KeyScheme::Ordered,
+                )
+                .map_err(debug_err!("db_next failed to read key from storage"))?;
+                context.use_gas_externally(used_gas);
+                value
+            }
+        };
+
+        // The key may have been removed since the iterator was opened
+        if let Some(value) = value {
+            context.touched_keys.read(&key);
+            return write_to_memory(instance, &encode_kv_pair(&key, &value)).map(|n| n as i32);
+        }
+    }
+}
+
+fn encode_kv_pair(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(key.len() + value.len() + 8);
+    encoded.extend_from_slice(key);
+    encoded.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    encoded.extend_from_slice(value);
+    encoded.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    encoded
+}
+
+fn host_canonicalize_address(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (human_region_ptr, canonical_region_ptr): (i32, i32),
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_canonicalize_address as u64;
+    use_gas(instance, used_gas)?;
+
+    let human = read_from_memory(instance, human_region_ptr as u32)
+        .map_err(debug_err!(err => "canonicalize_address failed to extract vector from human_region_ptr: {err}"))?;
+
+    let mut human_addr_str = match std::str::from_utf8(&human) {
+        Ok(addr) => addr,
+        Err(_err) => {
+            debug!(
+                "canonicalize_address input was not valid UTF-8: {}",
+                show_bytes(&human)
+            );
+            return write_to_memory(instance, b"input is not valid UTF-8")
+                .map(|n| n as i32)
+                .map_err(debug_err!("failed to write error message to contract"));
+        }
+    };
+    human_addr_str = human_addr_str.trim();
+    if human_addr_str.is_empty() {
+        debug!("canonicalize_address input was empty");
+        return write_to_memory(instance, b"input is empty")
+            .map(|n| n as i32)
+            .map_err(debug_err!("failed to write error message to contract"));
+    }
+
+    //debug!("canonicalize_address was called with {:?}", human_addr_str);
 
     let (decoded_prefix, data) = match bech32::decode(human_addr_str) {
         Ok(ret) => ret,
@@ -1271,6 +2243,17 @@ fn host_query_chain(
         debug_err!(err => "query_chain failed to extract vector from query_region_ptr: {err}"),
     )?;
 
+    if let Some(answer) = context.query_cache.get(&query_buffer, context.query_depth) {
+        use_gas(instance, context.gas_costs.external_query_cache_hit as u64)?;
+        return write_to_memory(instance, &answer).map(|region_ptr| region_ptr as i32);
+    }
+
+    let gas_limit = query_gas_limit(
+        &context.gas_costs,
+        context.query_depth + 1,
+        get_remaining_gas(instance),
+    );
+
     let mut used_gas: u64 = 0;
     let answer = encrypt_and_query_chain(
         &query_buffer,
@@ -1278,18 +2261,31 @@ fn host_query_chain(
         &context.context,
         context.user_nonce,
         context.user_public_key,
+        &context.query_cache,
+        &context.oracle_proofs,
+        &context.custom_query_auth,
         &mut used_gas,
-        get_remaining_gas(instance),
+        gas_limit,
     )?;
 
     context.use_gas_externally(used_gas);
 
+    if used_gas > gas_limit {
+        warn!(
+            "query at depth {} used {} gas, more than its limit of {}",
+            context.query_depth + 1,
+            used_gas,
+            gas_limit
+        );
+        return Err(WasmEngineError::OutOfGas);
+    }
+
     write_to_memory(instance, &answer).map(|region_ptr| region_ptr as i32)
 }
 
 #[cfg(feature = "debug-print")]
 fn host_debug_print(
-    _context: &mut Context,
+    context: &mut Context,
     instance: &wasm3::Instance<Context>,
     message_region_ptr: i32,
 ) -> WasmEngineResult<()> {
@@ -1297,7 +2293,8 @@ fn host_debug_print(
     let message =
         String::from_utf8(message_buffer).unwrap_or_else(|err| hex::encode(err.into_bytes()));
 
-    info!("debug_print: {:?}", message);
+    trace!("debug_print: {:?}", message);
+    context.log_stream.push(&context.context, message);
 
     Ok(())
 }
@@ -1868,7 +2865,7 @@ fn get_encryption_salt(timestamp: u64) -> Vec<u8> {
 
     encryption_salt.extend(timestamp.to_be_bytes());
 
-    let msg_counter = MSG_COUNTER.lock().unwrap();
+    let msg_counter = MSG_COUNTER.lock_or_recover();
 
     encryption_salt.extend(msg_counter.height.to_be_bytes());
     encryption_salt.extend(msg_counter.counter.to_be_bytes());
@@ -1918,8 +2915,842 @@ fn host_check_gas_used(
     Ok(gas_used as i64)
 }
 
+/// Queues calls to other contracts that are executed after the calling contract, such that all
+/// of their state changes are committed or rolled back together.
+/// Returns 0 on success, or a pointer to an error message.
+fn host_execute_contracts_atomic(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    calls_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_execute_contracts_atomic as u64;
+    use_gas(instance, used_gas)?;
+
+    let calls = read_from_memory(instance, calls_region_ptr as u32).map_err(
+        debug_err!(err => "execute_contracts_atomic failed to extract vector from calls_region_ptr: {err}"),
+    )?;
+
+    match parse_atomic_calls(&calls, context.atomic_calls.len()) {
+        Ok(calls) => {
+            trace!("execute_contracts_atomic() queued {} calls", calls.len());
+            context.atomic_calls.extend(calls);
+            Ok(0)
+        }
+        Err(err) => {
+            debug!("execute_contracts_atomic() got invalid calls: {}", err);
+            write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+        }
+    }
+}
+
+fn host_set_upgrade_policy(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    policy_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_set_upgrade_policy as u64;
+    use_gas(instance, used_gas)?;
+
+    let policy = read_from_memory(instance, policy_region_ptr as u32).map_err(
+        debug_err!(err => "set_upgrade_policy failed to extract vector from policy_region_ptr: {err}"),
+    )?;
+
+    match parse_upgrade_policy(&policy) {
+        Ok(policy) => {
+            trace!("set_upgrade_policy() set {:?}", policy);
+            context.upgrade_policy = Some(policy);
+            Ok(0)
+        }
+        Err(err) => {
+            debug!("set_upgrade_policy() got an invalid policy: {}", err);
+            write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+        }
+    }
+}
+
+/// Announces a migration of the contract to the code with the given hex encoded hash. If the
+/// upgrade policy of the contract asks for it, the contract can only be migrated to announced
+/// code after a delay.
+/// Returns 0 on success, or a pointer to an error message.
+fn host_announce_migration(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    code_hash_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_announce_migration as u64;
+    use_gas(instance, used_gas)?;
+
+    let code_hash = read_from_memory(instance, code_hash_region_ptr as u32).map_err(
+        debug_err!(err => "announce_migration failed to extract vector from code_hash_region_ptr: {err}"),
+    )?;
+
+    match parse_announced_code_hash(&code_hash) {
+        Ok(code_hash) => {
+            trace!("announce_migration() announced {:x?}", code_hash);
+            context.migration_announcement = Some(code_hash);
+            Ok(0)
+        }
+        Err(err) => {
+            debug!("announce_migration() got an invalid code hash: {}", err);
+            write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+        }
+    }
+}
+
+/// Checkpoints a migration that can't complete in one transaction with a cursor of its
+/// progress. The migration is resumed from the cursor when the contract is migrated again, to the
+/// same code, and until then the contract can't be migrated to other code. Calling it again
+/// replaces the cursor.
+/// Returns 0 on success, or a pointer to an error message.
+fn host_migration_checkpoint(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    cursor_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_migration_checkpoint as u64;
+    use_gas(instance, used_gas)?;
+
+    let cursor = read_from_memory(instance, cursor_region_ptr as u32).map_err(
+        debug_err!(err => "migration_checkpoint failed to extract vector from cursor_region_ptr: {err}"),
+    )?;
+
+    match parse_migration_cursor(&cursor) {
+        Ok(cursor) => {
+            trace!("migration_checkpoint() checkpointed {:?}", cursor);
+            context.migration_checkpoint = Some(cursor);
+            Ok(0)
+        }
+        Err(err) => {
+            debug!("migration_checkpoint() got an invalid cursor: {}", err);
+            write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+        }
+    }
+}
+
+/// Returns a pointer to the cursor of the migration that this migration resumes, or 0 if it
+/// doesn't resume one
+fn host_migration_cursor(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_migration_checkpoint as u64;
+    use_gas(instance, used_gas)?;
+
+    match &context.migration_cursor {
+        Some(cursor) => {
+            trace!("migration_cursor() returned {:?}", cursor);
+            write_to_memory(instance, cursor).map(|n| n as i32)
+        }
+        None => Ok(0),
+    }
+}
+
+/// Sets the x25519 public key of an auditor, which outputs of the contract are also encrypted
+/// for. This can only be done while the contract is instantiated.
+/// Returns 0 on success, or a pointer to an error message.
+fn host_set_auditor_key(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    key_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_set_auditor_key as u64;
+    use_gas(instance, used_gas)?;
+
+    let key = read_from_memory(instance, key_region_ptr as u32).map_err(
+        debug_err!(err => "set_auditor_key failed to extract vector from key_region_ptr: {err}"),
+    )?;
+
+    match parse_auditor_key(&key) {
+        Ok(key) => {
+            trace!("set_auditor_key() set {:x?}", key);
+            context.auditor_key = Some(key);
+            Ok(0)
+        }
+        Err(err) => {
+            debug!("set_auditor_key() got an invalid key: {}", err);
+            write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+        }
+    }
+}
+
+fn host_fee_params(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_fee_params as u64;
+    use_gas(instance, used_gas)?;
+
+    let fee_params = encode_fee_params(context.fee.as_ref())?;
+    trace!(
+        "fee_params() returned {:?}",
+        String::from_utf8_lossy(&fee_params)
+    );
+
+    write_to_memory(instance, &fee_params).map(|n| n as i32)
+}
+
+/// Returns the ids of the sibling submessages that still have to reply after the reply that is
+/// being handled, as a JSON array. Outside of `reply` the array is empty.
+fn host_pending_replies(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_pending_replies as u64;
+    use_gas(instance, used_gas)?;
+
+    let pending_replies = serde_json::to_vec(&context.pending_replies).map_err(|err| {
+        debug!(
+            "pending_replies failed to serialize the pending replies: {}",
+            err
+        );
+        WasmEngineError::SerializationError
+    })?;
+    trace!("pending_replies() returned {:?}", context.pending_replies);
+
+    write_to_memory(instance, &pending_replies).map(|n| n as i32)
+}
+
+/// Returns a new id, which is unique on the chain and deterministic, so contracts can use it for
+/// orders or tokens without storing a counter
+fn host_unique_id(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_unique_id as u64;
+    use_gas(instance, used_gas)?;
+
+    let msg_counter = *MSG_COUNTER.lock_or_recover();
+    let unique_id = derive_unique_id(context.tx_hash.as_deref(), msg_counter, context.unique_ids);
+    context.unique_ids += 1;
+    trace!("unique_id() returned {}", unique_id);
+
+    write_to_memory(instance, unique_id.as_bytes()).map(|n| n as i32)
+}
+
+/// Canonicalizes JSON like the Amino JSON sign docs the enclave verifies, so contracts can hash
+/// or sign JSON that off-chain verifiers reproduce exactly. Returns a pointer to the canonical
+/// JSON in the low half, or a `CanonicalJsonError` in the high half.
+fn host_canonical_json(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    json_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let json = read_from_memory(instance, json_ptr as u32)
+        .map_err(debug_err!(err => "canonical_json failed to read the json from memory: {err}"))?;
+
+    let used_gas = context.gas_costs.external_canonical_json as u64
+        + context.gas_costs.external_canonical_json_per_byte as u64 * json.len() as u64;
+    use_gas(instance, used_gas)?;
+
+    let canonical = match canonicalize_json(&json) {
+        Ok(canonical) => canonical,
+        Err(err) => {
+            debug!("canonical_json failed to canonicalize: {:?}", err);
+            return Ok(to_high_half(err as u32) as i64);
+        }
+    };
+
+    let ptr = write_to_memory(instance, &canonical)?;
+    Ok(to_low_half(ptr) as i64)
+}
+
+/// Reads an entry that the contract wrote with `transient_write` earlier in the transaction.
+/// Returns 0 if there's no such entry.
+fn host_transient_read(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    key_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_transient_read as u64;
+    use_gas(instance, used_gas)?;
+
+    let key = read_from_memory(instance, key_ptr as u32)
+        .map_err(debug_err!(err => "transient_read failed to read the key from memory: {err}"))?;
+
+    let scope = match &context.transient_scope {
+        Some(scope) => scope,
+        None => return Ok(0),
+    };
+    let value = match context.transient_writes.get(&key) {
+        Some(value) => Some(value.clone()),
+        None => read_transient(scope, &key),
+    };
+    debug!(
+        "transient_read read key {}: {:?}",
+        show_bytes(&key),
+        value.as_ref().map(|value| show_bytes(value))
+    );
+
+    match value {
+        Some(value) => write_to_memory(instance, &value).map(|n| n as i32),
+        None => Ok(0),
+    }
+}
+
+/// Writes an entry that the contract can read with `transient_read` for the rest of the
+/// transaction, e.g. in the reply to a submessage. Unlike the state of the contract, the entry
+/// isn't stored, so it's much cheaper.
+fn host_transient_write(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (key_ptr, value_ptr): (i32, i32),
+) -> WasmEngineResult<()> {
+    let key = read_from_memory(instance, key_ptr as u32)
+        .map_err(debug_err!(err => "transient_write failed to read the key from memory: {err}"))?;
+    let value = read_from_memory(instance, value_ptr as u32).map_err(
+        debug_err!(err => "transient_write failed to read the value from memory: {err}"),
+    )?;
+
+    let written_bytes = key.len() + value.len();
+    let used_gas = context.gas_costs.external_transient_write as u64
+        + context.gas_costs.external_transient_write_per_byte as u64 * written_bytes as u64;
+    use_gas(instance, used_gas)?;
+
+    let scope = context.transient_scope.as_ref().ok_or_else(|| {
+        debug!("transient_write was called without a transaction");
+        WasmEngineError::UnauthorizedWrite
+    })?;
+    let pending_bytes: usize = context
+        .transient_writes
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum();
+    if !fits_in_transient_storage(scope, pending_bytes + written_bytes) {
+        debug!("transient_write exceeded the transient storage of the transaction");
+        return Err(WasmEngineError::MemoryAllocationError);
+    }
+
+    debug!(
+        "transient_write writing key: {}, value: {}",
+        show_bytes(&key),
+        show_bytes(&value)
+    );
+    context.transient_writes.insert(key, value);
+
+    Ok(())
+}
+
+fn read_utf8_from_memory(
+    instance: &wasm3::Instance<Context>,
+    ptr: i32,
+) -> WasmEngineResult<Result<String, Bech32Error>> {
+    let bytes = read_from_memory(instance, ptr as u32)?;
+    Ok(String::from_utf8(bytes).map_err(|_| Bech32Error::InvalidChar))
+}
+
+/// Encodes data as bech32 (variant 0) or bech32m (variant 1) with any human readable part, e.g.
+/// for addresses on other chains. Returns a pointer to the string in the low half, or a
+/// `Bech32Error` in the high half.
+fn host_bech32_encode(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (hrp_ptr, data_ptr, variant): (i32, i32, i32),
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_bech32_encode as u64;
+    use_gas(instance, used_gas)?;
+
+    let hrp = read_utf8_from_memory(instance, hrp_ptr)
+        .map_err(debug_err!(err => "bech32_encode failed to read the prefix from memory: {err}"))?;
+    let data = read_from_memory(instance, data_ptr as u32)
+        .map_err(debug_err!(err => "bech32_encode failed to read the data from memory: {err}"))?;
+
+    let encoded = hrp.and_then(|hrp| {
+        let variant = Variant::from_u32(variant as u32).ok_or(Bech32Error::InvalidVariant)?;
+        bech32_codec::encode(&hrp, &data, variant)
+    });
+    match encoded {
+        Ok(encoded) => {
+            let ptr = write_to_memory(instance, encoded.as_bytes())?;
+            Ok(to_low_half(ptr) as i64)
+        }
+        Err(err) => {
+            debug!("bech32_encode failed to encode: {:?}", err);
+            Ok(to_high_half(err as u32) as i64)
+        }
+    }
+}
+
+/// Decodes a bech32 or bech32m string with any human readable part. Returns a pointer to
+/// `{"hrp":...,"data":<base64>,"variant":"bech32"|"bech32m"}` in the low half, or a
+/// `Bech32Error` in the high half.
+fn host_bech32_decode(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    input_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_bech32_decode as u64;
+    use_gas(instance, used_gas)?;
+
+    let input = read_utf8_from_memory(instance, input_ptr)
+        .map_err(debug_err!(err => "bech32_decode failed to read the input from memory: {err}"))?;
+
+    match input.and_then(|input| bech32_codec::decode(&input)) {
+        Ok(decoded) => {
+            let decoded = serde_json::to_vec(&decoded).map_err(|err| {
+                debug!(
+                    "bech32_decode failed to serialize the decoded input: {:?}",
+                    err
+                );
+                WasmEngineError::SerializationError
+            })?;
+            let ptr = write_to_memory(instance, &decoded)?;
+            Ok(to_low_half(ptr) as i64)
+        }
+        Err(err) => {
+            debug!("bech32_decode failed to decode: {:?}", err);
+            Ok(to_high_half(err as u32) as i64)
+        }
+    }
+}
+
+/// Validates that a string is a normalized (lowercase) bech32 or bech32m string with the given
+/// human readable part, like `addr_validate` does for addresses of this chain. Returns 0 if it
+/// is, or a pointer to an error message.
+fn host_bech32_validate(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (input_ptr, hrp_ptr): (i32, i32),
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_bech32_validate as u64;
+    use_gas(instance, used_gas)?;
+
+    let input = read_utf8_from_memory(instance, input_ptr).map_err(
+        debug_err!(err => "bech32_validate failed to read the input from memory: {err}"),
+    )?;
+    let hrp = read_utf8_from_memory(instance, hrp_ptr).map_err(
+        debug_err!(err => "bech32_validate failed to read the prefix from memory: {err}"),
+    )?;
+
+    let (input, hrp) = match (input, hrp) {
+        (Ok(input), Ok(hrp)) => (input, hrp),
+        _ => return write_to_memory(instance, b"Input is not valid UTF-8").map(|n| n as i32),
+    };
+
+    let decoded = match bech32_codec::decode(&input) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            debug!("bech32_validate failed to decode {:?}: {:?}", input, err);
+            return write_to_memory(instance, err.message().as_bytes()).map(|n| n as i32);
+        }
+    };
+
+    if decoded.hrp != hrp {
+        return write_to_memory(instance, b"Wrong human readable part").map(|n| n as i32);
+    }
+    if input != input.to_ascii_lowercase() {
+        return write_to_memory(instance, b"Input is not normalized").map(|n| n as i32);
+    }
+
+    Ok(0)
+}
+
+fn encode_with_codec(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    data_ptr: i32,
+    codec: Codec,
+) -> WasmEngineResult<i32> {
+    let data = read_from_memory(instance, data_ptr as u32)
+        .map_err(debug_err!(err => "failed to read the data to encode from memory: {err}"))?;
+
+    let used_gas = context.gas_costs.external_codec as u64
+        + context.gas_costs.external_codec_per_byte as u64 * data.len() as u64;
+    use_gas(instance, used_gas)?;
+
+    write_to_memory(instance, codec.encode(&data).as_bytes()).map(|n| n as i32)
+}
+
+fn decode_with_codec(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    encoded_ptr: i32,
+    codec: Codec,
+) -> WasmEngineResult<i64> {
+    let encoded = read_from_memory(instance, encoded_ptr as u32)
+        .map_err(debug_err!(err => "failed to read the data to decode from memory: {err}"))?;
+
+    let used_gas = context.gas_costs.external_codec as u64
+        + context.gas_costs.external_codec_per_byte as u64 * encoded.len() as u64;
+    use_gas(instance, used_gas)?;
+
+    match codec.decode(&encoded) {
+        Ok(decoded) => {
+            let ptr = write_to_memory(instance, &decoded)?;
+            Ok(to_low_half(ptr) as i64)
+        }
+        Err(err) => Ok(to_high_half(err as u32) as i64),
+    }
+}
+
+/// Encodes data as padded standard base64
+fn host_base64_encode(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    data_ptr: i32,
+) -> WasmEngineResult<i32> {
+    encode_with_codec(context, instance, data_ptr, Codec::Base64)
+}
+
+/// Decodes standard base64. Returns a pointer to the data in the low half, or a `DecodeError`
+/// in the high half.
+fn host_base64_decode(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    encoded_ptr: i32,
+) -> WasmEngineResult<i64> {
+    decode_with_codec(context, instance, encoded_ptr, Codec::Base64)
+}
+
+/// Encodes data as lowercase hex
+fn host_hex_encode(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    data_ptr: i32,
+) -> WasmEngineResult<i32> {
+    encode_with_codec(context, instance, data_ptr, Codec::Hex)
+}
+
+/// Decodes hex of either case. Returns a pointer to the data in the low half, or a
+/// `DecodeError` in the high half.
+fn host_hex_decode(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    encoded_ptr: i32,
+) -> WasmEngineResult<i64> {
+    decode_with_codec(context, instance, encoded_ptr, Codec::Hex)
+}
+
+fn write_sdk_response(
+    instance: &wasm3::Instance<Context>,
+    response: Result<SdkResponse, SdkResponseError>,
+) -> WasmEngineResult<i64> {
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => return Ok(to_high_half(err as u32) as i64),
+    };
+
+    let response = serde_json::to_vec(&response).map_err(|err| {
+        debug!("failed to serialize an SDK response: {:?}", err);
+        WasmEngineError::SerializationError
+    })?;
+    let ptr = write_to_memory(instance, &response)?;
+    Ok(to_low_half(ptr) as i64)
+}
+
+/// Decodes the response of an SDK message of the given type, e.g. the data of the reply to a
+/// `MsgInstantiateContract` submessage. Returns a pointer to the response as JSON in the low
+/// half, or an `SdkResponseError` in the high half.
+fn host_decode_sdk_response(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (type_url_ptr, value_ptr): (i32, i32),
+) -> WasmEngineResult<i64> {
+    let type_url = read_from_memory(instance, type_url_ptr as u32).map_err(
+        debug_err!(err => "decode_sdk_response failed to read the type url from memory: {err}"),
+    )?;
+    let value = read_from_memory(instance, value_ptr as u32).map_err(
+        debug_err!(err => "decode_sdk_response failed to read the response from memory: {err}"),
+    )?;
+
+    let used_gas = context.gas_costs.external_decode_sdk_response as u64
+        + context.gas_costs.external_decode_sdk_response_per_byte as u64 * value.len() as u64;
+    use_gas(instance, used_gas)?;
+
+    let response = match std::str::from_utf8(&type_url) {
+        Ok(type_url) => decode_sdk_response(type_url, &value),
+        Err(_) => Err(SdkResponseError::UnsupportedType),
+    };
+    write_sdk_response(instance, response)
+}
+
+/// Like `decode_sdk_response`, for a response wrapped in an `Any`
+fn host_decode_any(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    any_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let any = read_from_memory(instance, any_ptr as u32)
+        .map_err(debug_err!(err => "decode_any failed to read the Any from memory: {err}"))?;
+
+    let used_gas = context.gas_costs.external_decode_sdk_response as u64
+        + context.gas_costs.external_decode_sdk_response_per_byte as u64 * any.len() as u64;
+    use_gas(instance, used_gas)?;
+
+    write_sdk_response(instance, decode_any(&any))
+}
+
+/// Derives a secret of the contract for the given label, which is the same in every call of the
+/// contract with the label. Returns a pointer to the 32 byte subkey in the low half, or a
+/// `ContractSubkeyError` in the high half.
+fn host_derive_contract_subkey(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    label_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_derive_contract_subkey as u64;
+    use_gas(instance, used_gas)?;
+
+    let label = read_from_memory(instance, label_ptr as u32).map_err(
+        debug_err!(err => "derive_contract_subkey failed to read the label from memory: {err}"),
+    )?;
+
+    let subkey = match derive_contract_subkey(&context.state_keys.og, &label) {
+        Ok(subkey) => subkey,
+        Err(err) => {
+            debug!("derive_contract_subkey failed: {:?}", err);
+            return Ok(to_high_half(err as u32) as i64);
+        }
+    };
+
+    let ptr = write_to_memory(instance, &subkey)?;
+    Ok(to_low_half(ptr) as i64)
+}
+
+/// Commits to randomness that is revealed in a later block with `random_reveal`, see
+/// `commit_reveal`. The commitment is 32 bytes, e.g. the hash of the tickets, and is stored with
+/// the randomness, which is fixed by the first commitment of the block. Returns 0, or a
+/// `CommitRevealError` in the high half.
+fn host_random_commit(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    commitment_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_random_commit as u64;
+    use_gas(instance, used_gas)?;
+
+    let commitment = read_from_memory(instance, commitment_ptr as u32).map_err(
+        debug_err!(err => "random_commit failed to read the commitment from memory: {err}"),
+    )?;
+
+    let (height, block_seed) = match &context.block_random {
+        Some((height, block_seed)) => (*height, block_seed.clone()),
+        None => {
+            debug!("random_commit was called without randomness");
+            return Ok(to_high_half(CommitRevealError::NoRandomness as u32) as i64);
+        }
+    };
+    if commitment.len() != COMMITMENT_SIZE {
+        debug!(
+            "random_commit was called with a commitment of {} bytes",
+            commitment.len()
+        );
+        return Ok(to_high_half(CommitRevealError::InvalidCommitment as u32) as i64);
+    }
+
+    let key = commitment_key(height);
+    let record = record_commitment(context.read_reserved(&key)?, &commitment, || {
+        derive_committed_random(&block_seed.0, &context.state_keys.og, height)
+    });
+    context.kv_cache.write(&key, &record);
+    trace!("random_commit() committed at height {}", height);
+
+    Ok(0)
+}
+
+/// Reveals the randomness of the commitment, which must have been made at the height, before the
+/// current block. Every reveal of the height returns the same randomness. Returns a pointer to
+/// its 32 bytes in the low half, or a `CommitRevealError` in the high half.
+fn host_random_reveal(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    commit_height: i64,
+    commitment_ptr: i32,
+) -> WasmEngineResult<i64> {
+    let used_gas = context.gas_costs.external_random_reveal as u64;
+    use_gas(instance, used_gas)?;
+
+    let commitment = read_from_memory(instance, commitment_ptr as u32).map_err(
+        debug_err!(err => "random_reveal failed to read the commitment from memory: {err}"),
+    )?;
+
+    let height = match &context.block_random {
+        Some((height, _)) => *height,
+        None => {
+            debug!("random_reveal was called without randomness");
+            return Ok(to_high_half(CommitRevealError::NoRandomness as u32) as i64);
+        }
+    };
+    let commit_height = commit_height as u64;
+    if commit_height >= height {
+        debug!(
+            "random_reveal was called at height {} for a commitment at height {}",
+            height, commit_height
+        );
+        return Ok(to_high_half(CommitRevealError::TooEarly as u32) as i64);
+    }
+
+    if commitment.len() != COMMITMENT_SIZE {
+        debug!(
+            "random_reveal was called with a commitment of {} bytes",
+            commitment.len()
+        );
+        return Ok(to_high_half(CommitRevealError::InvalidCommitment as u32) as i64);
+    }
+
+    let record = match context.read_reserved(&commitment_key(commit_height))? {
+        Some(record) => record,
+        None => {
+            debug!(
+                "random_reveal found no commitment at height {}",
+                commit_height
+            );
+            return Ok(to_high_half(CommitRevealError::NotCommitted as u32) as i64);
+        }
+    };
+    let revealed = match reveal_commitment(&record, &commitment) {
+        Ok(revealed) => revealed,
+        Err(err) => {
+            debug!(
+                "random_reveal can't reveal the commitment at height {}: {:?}",
+                commit_height, err
+            );
+            return Ok(to_high_half(err as u32) as i64);
+        }
+    };
+
+    trace!(
+        "random_reveal() revealed the commitment at height {}",
+        commit_height
+    );
+
+    let ptr = write_to_memory(instance, revealed)?;
+    Ok(to_low_half(ptr) as i64)
+}
+
+/// Lets the holder of the key decrypt the output of this execution, in addition to the sender.
+/// Designating another viewer replaces the previous one. Returns 0, or a pointer to an error
+/// message.
+fn host_designate_viewer(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    key_region_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let used_gas = context.gas_costs.external_designate_viewer as u64;
+    use_gas(instance, used_gas)?;
+
+    let key = read_from_memory(instance, key_region_ptr as u32).map_err(
+        debug_err!(err => "designate_viewer failed to extract vector from key_region_ptr: {err}"),
+    )?;
+
+    match parse_viewer_key(&key) {
+        Ok(key) => {
+            trace!("designate_viewer() designated {:x?}", key);
+            context.designated_viewer = Some(key);
+            Ok(0)
+        }
+        Err(err) => {
+            debug!("designate_viewer() got an invalid key: {}", err);
+            write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+        }
+    }
+}
+
+/// Charges an access to oblivious storage and makes it. Queries read without moving the entry.
+/// Errors that the contract caused are returned in the inner result.
+fn access_oblivious_storage(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    key: &[u8],
+    operation: Access,
+) -> WasmEngineResult<Result<Option<Vec<u8>>, ObliviousStorageError>> {
+    let path_gas = (crate::cosmwasm_config::oblivious_storage::TREE_HEIGHT as u64 + 2)
+        * (READ_BASE_GAS + WRITE_BASE_GAS);
+    let used_gas = context.gas_costs.external_oblivious_access as u64 + path_gas;
+    use_gas(instance, used_gas)?;
+
+    let secret = derive_contract_subkey(
+        &context.state_keys.og,
+        crate::cosmwasm_config::oblivious_storage::SUBKEY_LABEL,
+    )
+    .map_err(|err| {
+        debug!("failed to derive the oblivious storage secret: {:?}", err);
+        WasmEngineError::EncryptionError
+    })?;
+
+    let read_only = context.operation.is_query();
+    match oblivious_storage::access(context, &secret, key, operation, read_only) {
+        Err(ObliviousStorageError::Backend(err)) => Err(err),
+        Err(ObliviousStorageError::StashOverflow) => Err(WasmEngineError::MemoryAllocationError),
+        result => Ok(result),
+    }
+}
+
+/// Returns a pointer to the value, or 0 if the key isn't in oblivious storage
+fn host_oblivious_read(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    key_ptr: i32,
+) -> WasmEngineResult<i32> {
+    let key = read_from_memory(instance, key_ptr as u32)
+        .map_err(debug_err!(err => "oblivious_read failed to read the key from memory: {err}"))?;
+
+    match access_oblivious_storage(context, instance, &key, Access::Read)? {
+        Ok(Some(value)) => write_to_memory(instance, &value).map(|n| n as i32),
+        Ok(None) => Ok(0),
+        Err(err) => {
+            debug!("oblivious_read failed: {:?}", err);
+            Ok(0)
+        }
+    }
+}
+
+/// Returns 0, or a pointer to an error message if the value is too large or the storage is full
+fn host_oblivious_write(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    (key_ptr, value_ptr): (i32, i32),
+) -> WasmEngineResult<i32> {
+    let key = read_from_memory(instance, key_ptr as u32)
+        .map_err(debug_err!(err => "oblivious_write failed to read the key from memory: {err}"))?;
+    let value = read_from_memory(instance, value_ptr as u32).map_err(
+        debug_err!(err => "oblivious_write failed to read the value from memory: {err}"),
+    )?;
+
+    let err = match access_oblivious_storage(context, instance, &key, Access::Write(&value))? {
+        Ok(_) => return Ok(0),
+        Err(ObliviousStorageError::ValueTooLarge) => format!(
+            "Value is too large for oblivious storage: length is {}, max is {}",
+            value.len(),
+            crate::cosmwasm_config::oblivious_storage::BLOCK_VALUE_SIZE
+        ),
+        Err(ObliviousStorageError::Full) => format!(
+            "Oblivious storage is full, it can hold {} entries",
+            crate::cosmwasm_config::oblivious_storage::MAX_ENTRIES
+        ),
+        Err(err) => {
+            debug!("oblivious_write failed: {:?}", err);
+            return Err(WasmEngineError::MemoryAllocationError);
+        }
+    };
+    debug!("oblivious_write failed: {}", err);
+    write_to_memory(instance, err.as_bytes()).map(|n| n as i32)
+}
+
+fn host_oblivious_remove(
+    context: &mut Context,
+    instance: &wasm3::Instance<Context>,
+    key_ptr: i32,
+) -> WasmEngineResult<()> {
+    let key = read_from_memory(instance, key_ptr as u32)
+        .map_err(debug_err!(err => "oblivious_remove failed to read the key from memory: {err}"))?;
+
+    match access_oblivious_storage(context, instance, &key, Access::Remove)? {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            debug!("oblivious_remove failed: {:?}", err);
+            Err(WasmEngineError::MemoryAllocationError)
+        }
+    }
+}
+
 #[cfg(feature = "test")]
 pub mod tests {
+    use super::analysis;
+    use super::call_depth;
+    use super::deadline;
+    use super::floats;
+    use super::host_availability;
+    use super::log_stream;
     use super::shuffle_cache;
     use crate::count_failures;
     use crate::wasm3::Binary;
@@ -1930,6 +3761,14 @@ pub mod tests {
 
         count_failures!(failures, {
             cache_shuffle_works();
+            analysis::tests::test_analyze_contract();
+            call_depth::tests::test_add_call_depth_limit();
+            call_depth::tests::test_runtime_stack_size();
+            call_depth::tests::test_recursion_depth();
+            deadline::tests::test_execution_deadline();
+            floats::tests::test_canonicalize_nans();
+            log_stream::tests::test_log_stream();
+            host_availability::tests::test_host_function_availability();
         });
 
         // The test doesn't work for some reason
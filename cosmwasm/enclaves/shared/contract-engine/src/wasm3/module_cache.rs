@@ -1,116 +1,222 @@
-use std::sync::SgxRwLock;
+use std::collections::HashMap;
+use std::sync::{Arc, SgxRwLock};
 
 use lazy_static::lazy_static;
 use log::*;
 use lru::LruCache;
 
-use cw_types_generic::{ContractFeature, CosmWasmApiVersion};
+use cw_types_generic::{ContractFeature, CosmWasmApiVersion, ShapedEntrypoint};
 
 use enclave_ffi_types::EnclaveError;
 
+use enclave_cosmos_types::feature_activation::{is_active, Feature};
 use enclave_cosmos_types::types::ContractCode;
 use enclave_crypto::HASH_SIZE;
 
-use super::{gas, validation};
+use super::{call_depth, floats, gas, validation};
+use crate::admin_registry::is_system_code;
+use crate::cosmwasm_config::system_modules::MAX_SYSTEM_MODULES;
 use crate::cosmwasm_config::ContractOperation;
 use crate::cosmwasm_config::{api_marker, features};
 use crate::gas::WasmCosts;
+use crate::metrics::record_module_cache_lookup;
+use crate::panic_isolation::RwLockOrRecover;
 
 pub struct VersionedCode {
     pub code: Vec<u8>,
     pub version: CosmWasmApiVersion,
     pub features: Vec<ContractFeature>,
+    /// The pages the memory of instances can grow to, which the code was validated against
+    pub max_memory_pages: u32,
+    /// The call depth limit that was injected into the code, if any
+    pub max_call_depth: Option<u32>,
+    /// Whether the NaNs of the code's float operations were canonicalized
+    pub canonicalizes_nans: bool,
 }
 
 impl VersionedCode {
-    pub fn new(code: Vec<u8>, version: CosmWasmApiVersion, features: Vec<ContractFeature>) -> Self {
+    pub fn new(
+        code: Vec<u8>,
+        version: CosmWasmApiVersion,
+        features: Vec<ContractFeature>,
+        max_memory_pages: u32,
+        max_call_depth: Option<u32>,
+        canonicalizes_nans: bool,
+    ) -> Self {
         Self {
             code,
             version,
             features,
+            max_memory_pages,
+            max_call_depth,
+            canonicalizes_nans,
         }
     }
+
+    /// Whether the module was analyzed with the same limits and instrumentation as it would be
+    /// now. Modules that were analyzed before governance changed a limit, or before a feature
+    /// that changes the instrumentation activated, are analyzed again.
+    fn is_current(&self, gas_costs: &WasmCosts, max_memory_pages: u32) -> bool {
+        self.max_memory_pages == max_memory_pages
+            && self.max_call_depth == injected_call_depth(gas_costs)
+            && self.canonicalizes_nans == is_active(Feature::CanonicalNans)
+    }
+}
+
+/// The call depth limit that is injected into modules
+fn injected_call_depth(gas_costs: &WasmCosts) -> Option<u32> {
+    Some(gas_costs.max_call_depth).filter(|_| is_active(Feature::CallDepthLimit))
 }
 
 lazy_static! {
-    static ref MODULE_CACHE: SgxRwLock<LruCache<[u8; HASH_SIZE], VersionedCode>> =
+    /// Modules are shared between engines, so that concurrent executions of the same contract
+    /// (mostly queries on RPC nodes) don't need to copy the code or rebuild the module. The
+    /// engines themselves aren't pooled: wasm3 environments can't move between threads, and each
+    /// call needs a fresh runtime anyway. Modules are looked up with the read lock, so concurrent
+    /// queries don't wait on each other, and the write lock is never held while a module is
+    /// analyzed or executed.
+    static ref MODULE_CACHE: SgxRwLock<LruCache<[u8; HASH_SIZE], Arc<VersionedCode>>> =
         SgxRwLock::new(LruCache::new(0));
+    /// Modules of system contracts, which are kept out of the LRU cache so that they are never
+    /// evicted, however many other contracts are executed. Codes that are no longer system codes
+    /// are dropped whenever a module is added.
+    static ref SYSTEM_MODULES: SgxRwLock<HashMap<[u8; HASH_SIZE], Arc<VersionedCode>>> =
+        SgxRwLock::new(HashMap::new());
 }
 
 pub fn configure_module_cache(cap: usize) {
     debug!("configuring module cache: {}", cap);
-    MODULE_CACHE.write().unwrap().resize(cap)
+    MODULE_CACHE.write_or_recover().resize(cap)
 }
 
 pub fn create_module_instance(
     contract_code: &ContractCode,
     gas_costs: &WasmCosts,
     operation: ContractOperation,
-) -> Result<VersionedCode, EnclaveError> {
+    max_memory_pages: u32,
+) -> Result<Arc<VersionedCode>, EnclaveError> {
+    let code_hash = contract_code.hash();
+    if is_system_code(&code_hash) {
+        if let Some(module) =
+            create_system_module_instance(contract_code, gas_costs, operation, max_memory_pages)
+        {
+            return module;
+        }
+    }
+
     trace!("fetching module from cache");
-    let cache = MODULE_CACHE.read().unwrap();
+    let cache = MODULE_CACHE.read_or_recover();
 
     // If the cache is disabled, don't try to use it and just compile the module.
     if cache.cap() == 0 {
+        drop(cache);
         trace!("cache is disabled, building module");
-        return analyze_module(contract_code, gas_costs, operation);
+        return analyze_module(contract_code, gas_costs, operation, max_memory_pages).map(Arc::new);
     }
     trace!("cache is enabled");
 
     // Try to fetch a cached instance
-    let mut code = None;
-    let mut api_version = CosmWasmApiVersion::Invalid;
-    let mut features = vec![];
     trace!("peeking in cache");
-    let peek_result = cache.peek(&contract_code.hash());
-    if let Some(VersionedCode {
-        code: cached_code,
-        version: cached_ver,
-        features: cached_features,
-    }) = peek_result
-    {
-        trace!("found instance in cache!");
-        code = Some(cached_code.clone());
-        api_version = *cached_ver;
-        features = cached_features.clone();
-    }
+    let cached_module = cache
+        .peek(&contract_code.hash())
+        .filter(|module| module.is_current(gas_costs, max_memory_pages))
+        .cloned();
 
     drop(cache); // Release read lock
 
-    // if we couldn't find the code in the cache, analyze it now
-    if code.is_none() {
-        trace!("code not found in cache! analyzing now");
-        let versioned_code = analyze_module(contract_code, gas_costs, operation)?;
-        code = Some(versioned_code.code);
-        api_version = versioned_code.version;
-        features = versioned_code.features;
-    }
-
-    // If we analyzed the code in the previous step, insert it to the LRU cache
-    trace!("updating cache");
-    let mut cache = MODULE_CACHE.write().unwrap();
-    if let Some(code) = code.clone() {
-        trace!("storing code in cache");
-        cache.put(
-            contract_code.hash(),
-            VersionedCode::new(code, api_version, features.clone()),
-        );
-    } else {
-        // Touch the cache to update the LRU value
+    record_module_cache_lookup(cached_module.is_some());
+
+    if let Some(module) = cached_module {
+        trace!("found instance in cache!");
+
+        // Touch the cache to update the LRU value. Queries only touch it if no other execution
+        // holds the lock, so that they never wait on each other, but the modules of contracts
+        // that are only queried still aren't evicted however often they're used.
         trace!("updating LRU without storing anything");
-        cache.get(&contract_code.hash());
+        if operation.is_query() {
+            if let Ok(mut cache) = MODULE_CACHE.try_write() {
+                cache.get(&contract_code.hash());
+            }
+        } else {
+            MODULE_CACHE.write_or_recover().get(&contract_code.hash());
+        }
+
+        trace!("returning built instance");
+        return Ok(module);
     }
 
-    let code = code.unwrap();
+    // if we couldn't find the code in the cache, analyze it now
+    trace!("code not found in cache! analyzing now");
+    let module = Arc::new(analyze_module(
+        contract_code,
+        gas_costs,
+        operation,
+        max_memory_pages,
+    )?);
+
+    // Insert the module we analyzed to the LRU cache
+    trace!("storing code in cache");
+    MODULE_CACHE
+        .write_or_recover()
+        .put(contract_code.hash(), module.clone());
 
     trace!("returning built instance");
-    Ok(VersionedCode::new(code, api_version, features))
+    Ok(module)
+}
+
+/// Like `create_module_instance` for the code of a system contract, which is cached even if the
+/// module cache is disabled. Returns `None` if there's no room for another system module, in
+/// which case the module goes to the LRU cache.
+fn create_system_module_instance(
+    contract_code: &ContractCode,
+    gas_costs: &WasmCosts,
+    operation: ContractOperation,
+    max_memory_pages: u32,
+) -> Option<Result<Arc<VersionedCode>, EnclaveError>> {
+    let code_hash = contract_code.hash();
+    let cached_module = SYSTEM_MODULES
+        .read_or_recover()
+        .get(&code_hash)
+        .filter(|module| module.is_current(gas_costs, max_memory_pages))
+        .cloned();
+
+    if let Some(module) = cached_module {
+        record_module_cache_lookup(true);
+        trace!("found system module in cache");
+        return Some(Ok(module));
+    }
+
+    {
+        // The registry may have dropped system codes since their modules were cached
+        let mut system_modules = SYSTEM_MODULES.write_or_recover();
+        system_modules.retain(|hash, _| is_system_code(hash));
+        if !system_modules.contains_key(&code_hash) && system_modules.len() >= MAX_SYSTEM_MODULES {
+            warn!(
+                "no room for the module of system contract {:x?}, caching it with the others",
+                code_hash
+            );
+            return None;
+        }
+    }
+    record_module_cache_lookup(false);
+
+    debug!("analyzing the code of system contract {:x?}", code_hash);
+    let module = match analyze_module(contract_code, gas_costs, operation, max_memory_pages) {
+        Ok(module) => Arc::new(module),
+        Err(err) => return Some(Err(err)),
+    };
+    SYSTEM_MODULES
+        .write_or_recover()
+        .insert(code_hash, module.clone());
+
+    Some(Ok(module))
 }
 
 pub fn analyze_module(
     contract_code: &ContractCode,
     gas_costs: &WasmCosts,
     operation: ContractOperation,
+    max_memory_pages: u32,
 ) -> Result<VersionedCode, EnclaveError> {
     let mut module = walrus::ModuleConfig::new()
         .generate_producers_section(false)
@@ -124,45 +230,158 @@ pub fn analyze_module(
         trace!("export {:?}", export)
     }
 
-    use walrus::Export;
-    let mut exports = module.exports.iter();
-    let marker_export =
-        exports.find(|&exp| exp.name == api_marker::V0_10 || exp.name == api_marker::V1);
-    let cosmwasm_api_version = match marker_export {
-        Some(Export { name, .. }) if name == api_marker::V0_10 => CosmWasmApiVersion::V010,
-        Some(Export { name, .. }) if name == api_marker::V1 => CosmWasmApiVersion::V1,
-        _ => {
-            error!("Invalid cosmwasm api version2");
-            return Err(EnclaveError::InvalidWasm);
-        }
-    };
+    let cosmwasm_api_version = cosmwasm_api_version(&module).ok_or_else(|| {
+        error!("Invalid cosmwasm api version2");
+        EnclaveError::InvalidWasm
+    })?;
 
-    // features
-    let random_enabled = module
-        .exports
-        .iter()
-        .any(|exp| exp.name == features::RANDOM);
+    let features = contract_features(contract_code, &module, cosmwasm_api_version, operation)?;
+    debug!("Found supported features: {:?}", features);
 
-    let features = if random_enabled {
-        debug!("Found supported features: random");
-        vec![ContractFeature::Random]
-    } else {
-        vec![]
-    };
-    drop(exports);
+    validation::validate_memory(&mut module, max_memory_pages)?;
 
-    validation::validate_memory(&mut module)?;
-
-    if let ContractOperation::Init = operation {
-        if module.has_floats() {
-            debug!("contract was found to contain floating point operations");
+    // Canonicalized before metering, so the injected instructions are metered too
+    let canonicalizes_nans = is_active(Feature::CanonicalNans);
+    if module.has_floats() {
+        debug!("contract was found to contain floating point operations");
+        if canonicalizes_nans {
+            floats::canonicalize_nans(&mut module);
+        } else if let ContractOperation::Init = operation {
             return Err(EnclaveError::WasmModuleWithFP);
         }
     }
 
+    // The calls that count the depth are metered like the calls of the contract
+    let max_call_depth = injected_call_depth(gas_costs);
+    if let Some(max_call_depth) = max_call_depth {
+        call_depth::add_call_depth_limit(&mut module, max_call_depth);
+    }
     gas::add_metering(&mut module, gas_costs);
 
     let code = module.emit_wasm();
 
-    Ok(VersionedCode::new(code, cosmwasm_api_version, features))
+    Ok(VersionedCode::new(
+        code,
+        cosmwasm_api_version,
+        features,
+        max_memory_pages,
+        max_call_depth,
+        canonicalizes_nans,
+    ))
+}
+
+/// Finds the version of the CosmWasm API the contract uses from its marker export
+pub(super) fn cosmwasm_api_version(module: &walrus::Module) -> Option<CosmWasmApiVersion> {
+    module.exports.iter().find_map(|exp| {
+        if exp.name == api_marker::V0_10 {
+            Some(CosmWasmApiVersion::V010)
+        } else if exp.name == api_marker::V1 {
+            Some(CosmWasmApiVersion::V1)
+        } else {
+            None
+        }
+    })
+}
+
+pub(super) fn contract_features(
+    contract_code: &ContractCode,
+    module: &walrus::Module,
+    cosmwasm_api_version: CosmWasmApiVersion,
+    operation: ContractOperation,
+) -> Result<Vec<ContractFeature>, EnclaveError> {
+    let mut features = match contract_code.declared_features() {
+        Some(declared_features) => parse_declared_features(declared_features, operation)?,
+        None => detect_features(module),
+    };
+    if cosmwasm_api_version == CosmWasmApiVersion::V1 && accepts_migrate_info(module) {
+        features.push(ContractFeature::MigrateInfo);
+    }
+
+    Ok(features)
+}
+
+/// Maps the features a contract declared in its features section to the features the enclave
+/// supports. Unsupported features fail the instantiation of the contract, and are ignored
+/// afterwards so contracts keep working if support for a feature is removed.
+fn parse_declared_features(
+    declared_features: &[String],
+    operation: ContractOperation,
+) -> Result<Vec<ContractFeature>, EnclaveError> {
+    let mut contract_features = vec![];
+
+    for feature in declared_features {
+        let contract_feature = match feature.as_str() {
+            features::declared::RANDOM => ContractFeature::Random,
+            features::declared::IBC => ContractFeature::Ibc,
+            features::declared::COARSE_PADDING => ContractFeature::CoarsePadding,
+            features::declared::CONSTANT_SHAPE_EXECUTE => {
+                ContractFeature::ConstantShape(ShapedEntrypoint::Execute)
+            }
+            features::declared::CONSTANT_SHAPE_REPLY => {
+                ContractFeature::ConstantShape(ShapedEntrypoint::Reply)
+            }
+            features::declared::CONSTANT_SHAPE_QUERY => {
+                ContractFeature::ConstantShape(ShapedEntrypoint::Query)
+            }
+            features::declared::PADDED_STORAGE_ACCESS => ContractFeature::PaddedStorageAccess,
+            features::declared::ORDERED_KEYS => ContractFeature::OrderedKeys,
+            features::declared::NORMALIZED_OUTPUT_V1 => ContractFeature::NormalizedOutput(1),
+            _ => {
+                if operation.is_init() {
+                    warn!("contract declared an unsupported feature: {:?}", feature);
+                    return Err(EnclaveError::InvalidWasm);
+                }
+                debug!(
+                    "ignoring unsupported feature declared by contract: {:?}",
+                    feature
+                );
+                continue;
+            }
+        };
+
+        if !contract_features.contains(&contract_feature) {
+            contract_features.push(contract_feature);
+        }
+    }
+
+    Ok(contract_features)
+}
+
+/// Detects the features of contracts that don't declare them from their exports
+fn detect_features(module: &walrus::Module) -> Vec<ContractFeature> {
+    let mut contract_features = vec![];
+
+    if module
+        .exports
+        .iter()
+        .any(|exp| exp.name == features::RANDOM)
+    {
+        contract_features.push(ContractFeature::Random);
+    }
+    if module
+        .exports
+        .iter()
+        .any(|exp| exp.name.starts_with(features::IBC_EXPORT_PREFIX))
+    {
+        contract_features.push(ContractFeature::Ibc);
+    }
+
+    contract_features
+}
+
+/// Contracts that accept a `MigrateInfo` export `migrate(env, msg, migrate_info)`, instead of the
+/// usual `migrate(env, msg)`
+fn accepts_migrate_info(module: &walrus::Module) -> bool {
+    let migrate_func = module.exports.iter().find_map(|exp| match exp.item {
+        walrus::ExportItem::Function(func) if exp.name == "migrate" => Some(func),
+        _ => None,
+    });
+
+    match migrate_func {
+        Some(func) => {
+            let ty = module.types.get(module.funcs.get(func).ty());
+            ty.params().len() == 3
+        }
+        None => false,
+    }
 }
@@ -4,7 +4,11 @@ use walrus::Module;
 
 use enclave_ffi_types::EnclaveError;
 
-pub fn validate_memory(module: &mut Module) -> Result<(), EnclaveError> {
+/// Caps the memory of the module at `maximum_allowed_pages` pages of 64 KiB
+pub fn validate_memory(
+    module: &mut Module,
+    maximum_allowed_pages: u32,
+) -> Result<(), EnclaveError> {
     // Verify that there is no start function defined.
     if module.start.is_some() {
         return Err(EnclaveError::WasmModuleWithStart);
@@ -17,7 +21,6 @@ pub fn validate_memory(module: &mut Module) -> Result<(), EnclaveError> {
 
     for memory in module.memories.iter_mut() {
         let requested_initial_pages: u32 = memory.initial;
-        let maximum_allowed_pages: u32 = 192; // 12 MiB
 
         if requested_initial_pages > maximum_allowed_pages {
             error!(
@@ -0,0 +1,318 @@
+//! Write-ahead intents of the writes that executions flush to the host.
+//!
+//! An execution writes its state through ocalls when it flushes its cache, but the host only
+//! commits those writes after the enclave returned the output of the execution. If the node dies
+//! in between, it can restart with a state that doesn't match an output it already returned.
+//!
+//! Before the writes of a delivered transaction are flushed, the enclave records an intent: a
+//! sequence number and the digest of the encrypted entries it's about to write, MAC'd with a key
+//! of this run of the enclave. The intent is returned with the result of the execution. The
+//! pending intents are sealed once per block, when it ends and before the host commits it. Once
+//! the host committed the block, it acknowledges the intents of its executions together with
+//! `ecall_acknowledge_write_intents`. Intents that are still pending when the enclave starts
+//! again belong to writes whose commit the host never acknowledged, and are reported as such.
+//!
+//! Simulations and queries never have their writes committed, so they record no intents. The
+//! digest only covers ciphertexts that the host sees anyway.
+
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::sync::SgxMutex;
+use std::{env, path};
+
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+use sgx_rand::{Rng, SgxRng};
+
+use enclave_crypto::consts::{DEFAULT_SGX_SECRET_PATH, SCRT_SGX_STORAGE_ENV_VAR};
+use enclave_crypto::{sha_256, AESKey, Hmac, HASH_SIZE, HMAC_SIGNATURE_SIZE};
+use enclave_ffi_types::EnclaveError;
+use enclave_utils::storage::{seal, unseal};
+
+use crate::cosmwasm_config::write_intents::MAX_PENDING_WRITE_INTENTS;
+use crate::metrics::record_unacknowledged_write_intents;
+use crate::panic_isolation::LockOrRecover;
+
+const WRITE_INTENTS_FILE_NAME: &str = "write_intents.sealed";
+
+const SEQUENCE_SIZE: usize = 8;
+/// An intent is its sequence number, the digest of its writes, and their MAC
+pub const WRITE_INTENT_SIZE: usize = SEQUENCE_SIZE + HASH_SIZE + HMAC_SIGNATURE_SIZE;
+/// The host acknowledges at most the intents that can be pending at once
+pub const MAX_ACKNOWLEDGED_INTENTS_LENGTH: usize = MAX_PENDING_WRITE_INTENTS * WRITE_INTENT_SIZE;
+
+lazy_static! {
+    static ref WRITE_INTENTS_SEALING_PATH: String = path::Path::new(
+        &env::var(SCRT_SGX_STORAGE_ENV_VAR).unwrap_or_else(|_| DEFAULT_SGX_SECRET_PATH.to_string()),
+    )
+    .join(WRITE_INTENTS_FILE_NAME)
+    .to_str()
+    .unwrap_or(DEFAULT_SGX_SECRET_PATH)
+    .to_string();
+    static ref WRITE_INTENTS: SgxMutex<WriteIntents> = SgxMutex::new(WriteIntents::load());
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingIntent {
+    sequence: u64,
+    digest: [u8; HASH_SIZE],
+}
+
+pub struct WriteIntents {
+    key: AESKey,
+    next_sequence: u64,
+    /// The intents the host didn't acknowledge yet, oldest first
+    pending: VecDeque<PendingIntent>,
+}
+
+/// The digest of the encrypted entries of a flush, in the order they're written
+pub fn digest_writes(writes: &[(Vec<u8>, Vec<u8>)]) -> [u8; HASH_SIZE] {
+    let mut data = vec![];
+    for (key, value) in writes {
+        data.extend_from_slice(&(key.len() as u64).to_be_bytes());
+        data.extend_from_slice(key);
+        data.extend_from_slice(&(value.len() as u64).to_be_bytes());
+        data.extend_from_slice(value);
+    }
+    sha_256(&data)
+}
+
+impl WriteIntents {
+    pub fn new(key: AESKey) -> Self {
+        Self {
+            key,
+            next_sequence: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Starts with a fresh MAC key, and reports the intents the previous run left pending
+    fn load() -> Self {
+        let mut key = [0u8; 32];
+        match SgxRng::new() {
+            Ok(mut rng) => rng.fill_bytes(&mut key),
+            Err(err) => error!("failed to create the key of the write intents: {:?}", err),
+        }
+        let intents = Self::new(AESKey::new_from_slice(&key));
+
+        let left_pending: Vec<PendingIntent> = match unseal(&WRITE_INTENTS_SEALING_PATH) {
+            Ok(sealed) => serde_json::from_slice(&sealed).unwrap_or_else(|err| {
+                error!("failed to parse the sealed write intents: {:?}", err);
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+        if !left_pending.is_empty() {
+            for intent in &left_pending {
+                error!(
+                    "the host never acknowledged committing the writes of intent {} (digest {})",
+                    intent.sequence,
+                    hex::encode(intent.digest)
+                );
+            }
+            record_unacknowledged_write_intents(left_pending.len() as u64);
+            intents.persist();
+        }
+
+        intents
+    }
+
+    /// Seals the pending intents. A node that fails to seal them only loses the detection of
+    /// unacknowledged commits across restarts, so it never fails the execution.
+    fn persist(&self) {
+        let pending: Vec<PendingIntent> = self.pending.iter().copied().collect();
+        let result = serde_json::to_vec(&pending)
+            .map_err(|err| format!("{:?}", err))
+            .and_then(|sealed| {
+                seal(&sealed, &WRITE_INTENTS_SEALING_PATH).map_err(|err| format!("{:?}", err))
+            });
+        if let Err(err) = result {
+            error!("failed to seal the pending write intents: {}", err);
+        }
+    }
+
+    fn encode(&self, intent: &PendingIntent) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(WRITE_INTENT_SIZE);
+        encoded.extend_from_slice(&intent.sequence.to_be_bytes());
+        encoded.extend_from_slice(&intent.digest);
+        let mac = self.key.sign_sha_256(&encoded);
+        encoded.extend_from_slice(&mac);
+        encoded
+    }
+
+    /// Adds a pending intent for the writes, and returns the encoded intent
+    pub fn record(&mut self, writes: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+        let intent = PendingIntent {
+            sequence: self.next_sequence,
+            digest: digest_writes(writes),
+        };
+        self.next_sequence += 1;
+
+        if self.pending.len() >= MAX_PENDING_WRITE_INTENTS {
+            if let Some(dropped) = self.pending.pop_front() {
+                warn!(
+                    "dropping write intent {}, which the host never acknowledged",
+                    dropped.sequence
+                );
+                record_unacknowledged_write_intents(1);
+            }
+        }
+        self.pending.push_back(intent);
+
+        self.encode(&intent)
+    }
+
+    /// Verifies an encoded intent that this run of the enclave issued, and returns what it holds
+    fn decode(&self, encoded: &[u8]) -> Result<PendingIntent, EnclaveError> {
+        if encoded.len() != WRITE_INTENT_SIZE {
+            warn!("write intent has a wrong size: {}", encoded.len());
+            return Err(EnclaveError::ValidationFailure);
+        }
+        let (signed, mac) = encoded.split_at(SEQUENCE_SIZE + HASH_SIZE);
+        if self.key.sign_sha_256(signed)[..] != mac[..] {
+            warn!("write intent has an invalid MAC");
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        let (sequence, digest) = signed.split_at(SEQUENCE_SIZE);
+        Ok(PendingIntent {
+            sequence: u64::from_be_bytes(sequence.try_into().unwrap()),
+            digest: digest.try_into().unwrap(),
+        })
+    }
+
+    /// Removes the pending intent, which fails if it wasn't issued by this run of the enclave or
+    /// isn't pending anymore
+    pub fn remove(&mut self, encoded: &[u8]) -> Result<(), EnclaveError> {
+        let intent = self.decode(encoded)?;
+        match self.pending.iter().position(|pending| *pending == intent) {
+            Some(index) => {
+                self.pending.remove(index);
+                Ok(())
+            }
+            None => {
+                warn!("write intent {} isn't pending", intent.sequence);
+                Err(EnclaveError::ValidationFailure)
+            }
+        }
+    }
+
+    /// Removes the pending intents that are concatenated in `encoded`. The valid ones are all
+    /// removed even if others fail, which fails the whole acknowledgement.
+    pub fn remove_all(&mut self, encoded: &[u8]) -> Result<(), EnclaveError> {
+        if encoded.len() % WRITE_INTENT_SIZE != 0 {
+            warn!(
+                "acknowledged write intents have a wrong size: {}",
+                encoded.len()
+            );
+            return Err(EnclaveError::ValidationFailure);
+        }
+
+        let mut result = Ok(());
+        for intent in encoded.chunks(WRITE_INTENT_SIZE) {
+            if let Err(err) = self.remove(intent) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Records the intent of writing the encrypted entries, before they're flushed to the host. It's
+/// sealed with the other intents of the block when the block ends.
+pub fn record_write_intent(writes: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    WRITE_INTENTS.lock_or_recover().record(writes)
+}
+
+/// Forgets the intent of an execution that failed after it flushed its writes, since the host
+/// drops the writes of failed executions
+pub fn cancel_write_intent(intent: &[u8]) {
+    let _ = WRITE_INTENTS.lock_or_recover().remove(intent);
+}
+
+/// Seals the intents of the block that ends, before the host commits it
+pub fn seal_write_intents() {
+    WRITE_INTENTS.lock_or_recover().persist();
+}
+
+/// Called by the host once it committed a block, with the concatenated intents it got with the
+/// results of the block's delivered transactions
+pub fn acknowledge_write_intents(intents: &[u8]) -> Result<(), EnclaveError> {
+    let mut pending = WRITE_INTENTS.lock_or_recover();
+    let result = pending.remove_all(intents);
+    pending.persist();
+    result
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn writes(value: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![(b"key".to_vec(), value.to_vec())]
+    }
+
+    pub fn test_write_intents() {
+        let mut intents = WriteIntents::new(AESKey::new_from_slice(&[1u8; 32]));
+
+        let first = intents.record(&writes(b"a"));
+        let second = intents.record(&writes(b"b"));
+        assert_eq!(first.len(), WRITE_INTENT_SIZE);
+        assert_ne!(first[SEQUENCE_SIZE..], second[SEQUENCE_SIZE..]);
+        assert_eq!(intents.pending(), 2);
+
+        // The digest binds the boundaries between keys and values
+        assert_ne!(
+            digest_writes(&[(b"ab".to_vec(), b"c".to_vec())]),
+            digest_writes(&[(b"a".to_vec(), b"bc".to_vec())])
+        );
+
+        // Intents that were tampered with, or issued with another key, are refused
+        let mut tampered = second.clone();
+        tampered[SEQUENCE_SIZE] ^= 1;
+        assert!(matches!(
+            intents.remove(&tampered),
+            Err(EnclaveError::ValidationFailure)
+        ));
+        let other = WriteIntents::new(AESKey::new_from_slice(&[2u8; 32])).encode(&PendingIntent {
+            sequence: 1,
+            digest: digest_writes(&writes(b"b")),
+        });
+        assert!(intents.remove(&other).is_err());
+        assert!(intents.remove(&second[1..]).is_err());
+
+        assert!(intents.remove(&second).is_ok());
+        assert_eq!(intents.pending(), 1);
+        // An intent can only be acknowledged once
+        assert!(intents.remove(&second).is_err());
+        assert!(intents.remove(&first).is_ok());
+        assert_eq!(intents.pending(), 0);
+
+        // The oldest intents are dropped past the limit
+        let oldest = intents.record(&writes(b"c"));
+        for _ in 0..MAX_PENDING_WRITE_INTENTS {
+            intents.record(&writes(b"d"));
+        }
+        assert_eq!(intents.pending(), MAX_PENDING_WRITE_INTENTS);
+        assert!(intents.remove(&oldest).is_err());
+
+        // The intents of a block are acknowledged together
+        let mut intents = WriteIntents::new(AESKey::new_from_slice(&[1u8; 32]));
+        let first = intents.record(&writes(b"a"));
+        let second = intents.record(&writes(b"b"));
+        let third = intents.record(&writes(b"c"));
+        assert!(intents.remove_all(&[first, second].concat()).is_ok());
+        assert_eq!(intents.pending(), 1);
+        assert!(intents.remove_all(&[]).is_ok());
+        assert!(intents.remove_all(&third[1..]).is_err());
+
+        // An invalid intent fails the acknowledgement, but the valid ones are still removed
+        assert!(intents.remove_all(&[tampered, third].concat()).is_err());
+        assert_eq!(intents.pending(), 0);
+    }
+}
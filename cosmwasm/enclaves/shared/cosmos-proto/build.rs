@@ -113,6 +113,10 @@ mod protobuf {
                 ],
             ),
             ("src/ibc", &[from_cosmos("upgrade/v1beta1/upgrade.proto")]),
+            (
+                "src/ibc/transfer",
+                &[from_ibc("applications/transfer/v1/tx.proto")],
+            ),
         ];
 
         for (out_dir, inputs) in directories {
@@ -0,0 +1,685 @@
+// This file is generated by rust-protobuf 2.25.2. Do not edit
+// @generated
+
+// https://github.com/rust-lang/rust-clippy/issues/702
+#![allow(unknown_lints)]
+#![allow(clippy::all)]
+
+#![allow(unused_attributes)]
+#![cfg_attr(rustfmt, rustfmt::skip)]
+
+#![allow(box_pointers)]
+#![allow(dead_code)]
+#![allow(missing_docs)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+#![allow(trivial_casts)]
+#![allow(unused_imports)]
+#![allow(unused_results)]
+//! Generated file from `ibc/applications/transfer/v1/tx.proto`
+
+/// Generated files are compatible only with the same version
+/// of protobuf runtime.
+// const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_2_25_2;
+
+#[derive(PartialEq,Clone,Default)]
+pub struct MsgTransfer {
+    // message fields
+    pub source_port: ::std::string::String,
+    pub source_channel: ::std::string::String,
+    pub token: ::protobuf::SingularPtrField<super::coin::Coin>,
+    pub sender: ::std::string::String,
+    pub receiver: ::std::string::String,
+    pub timeout_height: ::protobuf::SingularPtrField<super::client::Height>,
+    pub timeout_timestamp: u64,
+    pub memo: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a MsgTransfer {
+    fn default() -> &'a MsgTransfer {
+        <MsgTransfer as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl MsgTransfer {
+    pub fn new() -> MsgTransfer {
+        ::std::default::Default::default()
+    }
+
+    // string source_port = 1;
+
+
+    pub fn get_source_port(&self) -> &str {
+        &self.source_port
+    }
+    pub fn clear_source_port(&mut self) {
+        self.source_port.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_source_port(&mut self, v: ::std::string::String) {
+        self.source_port = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_source_port(&mut self) -> &mut ::std::string::String {
+        &mut self.source_port
+    }
+
+    // Take field
+    pub fn take_source_port(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.source_port, ::std::string::String::new())
+    }
+
+    // string source_channel = 2;
+
+
+    pub fn get_source_channel(&self) -> &str {
+        &self.source_channel
+    }
+    pub fn clear_source_channel(&mut self) {
+        self.source_channel.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_source_channel(&mut self, v: ::std::string::String) {
+        self.source_channel = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_source_channel(&mut self) -> &mut ::std::string::String {
+        &mut self.source_channel
+    }
+
+    // Take field
+    pub fn take_source_channel(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.source_channel, ::std::string::String::new())
+    }
+
+    // .cosmos.base.v1beta1.Coin token = 3;
+
+
+    pub fn get_token(&self) -> &super::coin::Coin {
+        self.token.as_ref().unwrap_or_else(|| <super::coin::Coin as ::protobuf::Message>::default_instance())
+    }
+    pub fn clear_token(&mut self) {
+        self.token.clear();
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_token(&mut self, v: super::coin::Coin) {
+        self.token = ::protobuf::SingularPtrField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_token(&mut self) -> &mut super::coin::Coin {
+        if self.token.is_none() {
+            self.token.set_default();
+        }
+        self.token.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_token(&mut self) -> super::coin::Coin {
+        self.token.take().unwrap_or_else(|| super::coin::Coin::new())
+    }
+
+    // string sender = 4;
+
+
+    pub fn get_sender(&self) -> &str {
+        &self.sender
+    }
+    pub fn clear_sender(&mut self) {
+        self.sender.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_sender(&mut self, v: ::std::string::String) {
+        self.sender = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_sender(&mut self) -> &mut ::std::string::String {
+        &mut self.sender
+    }
+
+    // Take field
+    pub fn take_sender(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.sender, ::std::string::String::new())
+    }
+
+    // string receiver = 5;
+
+
+    pub fn get_receiver(&self) -> &str {
+        &self.receiver
+    }
+    pub fn clear_receiver(&mut self) {
+        self.receiver.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_receiver(&mut self, v: ::std::string::String) {
+        self.receiver = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_receiver(&mut self) -> &mut ::std::string::String {
+        &mut self.receiver
+    }
+
+    // Take field
+    pub fn take_receiver(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.receiver, ::std::string::String::new())
+    }
+
+    // .ibc.core.client.v1.Height timeout_height = 6;
+
+
+    pub fn get_timeout_height(&self) -> &super::client::Height {
+        self.timeout_height.as_ref().unwrap_or_else(|| <super::client::Height as ::protobuf::Message>::default_instance())
+    }
+    pub fn clear_timeout_height(&mut self) {
+        self.timeout_height.clear();
+    }
+
+    pub fn has_timeout_height(&self) -> bool {
+        self.timeout_height.is_some()
+    }
+
+    // Param is passed by value, moved
+    pub fn set_timeout_height(&mut self, v: super::client::Height) {
+        self.timeout_height = ::protobuf::SingularPtrField::some(v);
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_timeout_height(&mut self) -> &mut super::client::Height {
+        if self.timeout_height.is_none() {
+            self.timeout_height.set_default();
+        }
+        self.timeout_height.as_mut().unwrap()
+    }
+
+    // Take field
+    pub fn take_timeout_height(&mut self) -> super::client::Height {
+        self.timeout_height.take().unwrap_or_else(|| super::client::Height::new())
+    }
+
+    // uint64 timeout_timestamp = 7;
+
+
+    pub fn get_timeout_timestamp(&self) -> u64 {
+        self.timeout_timestamp
+    }
+    pub fn clear_timeout_timestamp(&mut self) {
+        self.timeout_timestamp = 0;
+    }
+
+    // Param is passed by value, moved
+    pub fn set_timeout_timestamp(&mut self, v: u64) {
+        self.timeout_timestamp = v;
+    }
+
+    // string memo = 8;
+
+
+    pub fn get_memo(&self) -> &str {
+        &self.memo
+    }
+    pub fn clear_memo(&mut self) {
+        self.memo.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_memo(&mut self, v: ::std::string::String) {
+        self.memo = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_memo(&mut self) -> &mut ::std::string::String {
+        &mut self.memo
+    }
+
+    // Take field
+    pub fn take_memo(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.memo, ::std::string::String::new())
+    }
+}
+
+impl ::protobuf::Message for MsgTransfer {
+    fn is_initialized(&self) -> bool {
+        for v in &self.token {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
+        for v in &self.timeout_height {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.source_port)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.source_channel)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.token)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.sender)?;
+                },
+                5 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.receiver)?;
+                },
+                6 => {
+                    ::protobuf::rt::read_singular_message_into(wire_type, is, &mut self.timeout_height)?;
+                },
+                7 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.timeout_timestamp = tmp;
+                },
+                8 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.memo)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.source_port.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.source_port);
+        }
+        if !self.source_channel.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.source_channel);
+        }
+        if let Some(ref v) = self.token.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
+        if !self.sender.is_empty() {
+            my_size += ::protobuf::rt::string_size(4, &self.sender);
+        }
+        if !self.receiver.is_empty() {
+            my_size += ::protobuf::rt::string_size(5, &self.receiver);
+        }
+        if let Some(ref v) = self.timeout_height.as_ref() {
+            let len = v.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        }
+        if self.timeout_timestamp != 0 {
+            my_size += ::protobuf::rt::value_size(7, self.timeout_timestamp, ::protobuf::wire_format::WireTypeVarint);
+        }
+        if !self.memo.is_empty() {
+            my_size += ::protobuf::rt::string_size(8, &self.memo);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.source_port.is_empty() {
+            os.write_string(1, &self.source_port)?;
+        }
+        if !self.source_channel.is_empty() {
+            os.write_string(2, &self.source_channel)?;
+        }
+        if let Some(ref v) = self.token.as_ref() {
+            os.write_tag(3, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
+        if !self.sender.is_empty() {
+            os.write_string(4, &self.sender)?;
+        }
+        if !self.receiver.is_empty() {
+            os.write_string(5, &self.receiver)?;
+        }
+        if let Some(ref v) = self.timeout_height.as_ref() {
+            os.write_tag(6, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        }
+        if self.timeout_timestamp != 0 {
+            os.write_uint64(7, self.timeout_timestamp)?;
+        }
+        if !self.memo.is_empty() {
+            os.write_string(8, &self.memo)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> MsgTransfer {
+        MsgTransfer::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "source_port",
+                |m: &MsgTransfer| { &m.source_port },
+                |m: &mut MsgTransfer| { &mut m.source_port },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "source_channel",
+                |m: &MsgTransfer| { &m.source_channel },
+                |m: &mut MsgTransfer| { &mut m.source_channel },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_singular_ptr_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<super::coin::Coin>>(
+                "token",
+                |m: &MsgTransfer| { &m.token },
+                |m: &mut MsgTransfer| { &mut m.token },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "sender",
+                |m: &MsgTransfer| { &m.sender },
+                |m: &mut MsgTransfer| { &mut m.sender },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "receiver",
+                |m: &MsgTransfer| { &m.receiver },
+                |m: &mut MsgTransfer| { &mut m.receiver },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_singular_ptr_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<super::client::Height>>(
+                "timeout_height",
+                |m: &MsgTransfer| { &m.timeout_height },
+                |m: &mut MsgTransfer| { &mut m.timeout_height },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "timeout_timestamp",
+                |m: &MsgTransfer| { &m.timeout_timestamp },
+                |m: &mut MsgTransfer| { &mut m.timeout_timestamp },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "memo",
+                |m: &MsgTransfer| { &m.memo },
+                |m: &mut MsgTransfer| { &mut m.memo },
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<MsgTransfer>(
+                "MsgTransfer",
+                fields,
+                file_descriptor_proto()
+            )
+        })
+    }
+
+    fn default_instance() -> &'static MsgTransfer {
+        static instance: ::protobuf::rt::LazyV2<MsgTransfer> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(MsgTransfer::new)
+    }
+}
+
+impl ::protobuf::Clear for MsgTransfer {
+    fn clear(&mut self) {
+        self.source_port.clear();
+        self.source_channel.clear();
+        self.token.clear();
+        self.sender.clear();
+        self.receiver.clear();
+        self.timeout_height.clear();
+        self.timeout_timestamp = 0;
+        self.memo.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for MsgTransfer {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for MsgTransfer {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default)]
+pub struct MsgTransferResponse {
+    // message fields
+    pub sequence: u64,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a MsgTransferResponse {
+    fn default() -> &'a MsgTransferResponse {
+        <MsgTransferResponse as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl MsgTransferResponse {
+    pub fn new() -> MsgTransferResponse {
+        ::std::default::Default::default()
+    }
+
+    // uint64 sequence = 1;
+
+
+    pub fn get_sequence(&self) -> u64 {
+        self.sequence
+    }
+    pub fn clear_sequence(&mut self) {
+        self.sequence = 0;
+    }
+
+    // Param is passed by value, moved
+    pub fn set_sequence(&mut self, v: u64) {
+        self.sequence = v;
+    }
+}
+
+impl ::protobuf::Message for MsgTransferResponse {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
+                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
+                    }
+                    let tmp = is.read_uint64()?;
+                    self.sequence = tmp;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if self.sequence != 0 {
+            my_size += ::protobuf::rt::value_size(1, self.sequence, ::protobuf::wire_format::WireTypeVarint);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if self.sequence != 0 {
+            os.write_uint64(1, self.sequence)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> MsgTransferResponse {
+        MsgTransferResponse::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeUint64>(
+                "sequence",
+                |m: &MsgTransferResponse| { &m.sequence },
+                |m: &mut MsgTransferResponse| { &mut m.sequence },
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<MsgTransferResponse>(
+                "MsgTransferResponse",
+                fields,
+                file_descriptor_proto()
+            )
+        })
+    }
+
+    fn default_instance() -> &'static MsgTransferResponse {
+        static instance: ::protobuf::rt::LazyV2<MsgTransferResponse> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(MsgTransferResponse::new)
+    }
+}
+
+impl ::protobuf::Clear for MsgTransferResponse {
+    fn clear(&mut self) {
+        self.sequence = 0;
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for MsgTransferResponse {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for MsgTransferResponse {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+static file_descriptor_proto_data: &'static [u8] = b"\
+    \n%ibc/applications/transfer/v1/tx.proto\x12\x1cibc.applications.transfe\
+    r.v1\x1a\x14gogoproto/gogo.proto\x1a\x1ecosmos/base/v1beta1/coin.proto\
+    \x1a\x1fibc/core/client/v1/client.proto\"\xbe\x03\n\x0bMsgTransfer\x127\
+    \n\x0bsource_port\x18\x01\x20\x01(\tR\nsourcePortB\x16\xf2\xde\x1f\x12ya\
+    ml:\"source_port\"\x12@\n\x0esource_channel\x18\x02\x20\x01(\tR\rsourceC\
+    hannelB\x19\xf2\xde\x1f\x15yaml:\"source_channel\"\x125\n\x05token\x18\
+    \x03\x20\x01(\x0b2\x19.cosmos.base.v1beta1.CoinR\x05tokenB\x04\xc8\xde\
+    \x1f\0\x12\x16\n\x06sender\x18\x04\x20\x01(\tR\x06sender\x12\x1a\n\x08re\
+    ceiver\x18\x05\x20\x01(\tR\x08receiver\x12`\n\x0etimeout_height\x18\x06\
+    \x20\x01(\x0b2\x1a.ibc.core.client.v1.HeightR\rtimeoutHeightB\x1d\xf2\
+    \xde\x1f\x15yaml:\"timeout_height\"\xc8\xde\x1f\0\x12I\n\x11timeout_time\
+    stamp\x18\x07\x20\x01(\x04R\x10timeoutTimestampB\x1c\xf2\xde\x1f\x18yaml\
+    :\"timeout_timestamp\"\x12\x12\n\x04memo\x18\x08\x20\x01(\tR\x04memo:\
+    \x08\x88\xa0\x1f\0\xe8\xa0\x1f\0\"1\n\x13MsgTransferResponse\x12\x1a\n\
+    \x08sequence\x18\x01\x20\x01(\x04R\x08sequence2o\n\x03Msg\x12h\n\x08Tran\
+    sfer\x12).ibc.applications.transfer.v1.MsgTransfer\x1a1.ibc.applications\
+    .transfer.v1.MsgTransferResponseB9Z7github.com/cosmos/ibc-go/v4/modules/\
+    apps/transfer/typesb\x06proto3\
+";
+
+static file_descriptor_proto_lazy: ::protobuf::rt::LazyV2<::protobuf::descriptor::FileDescriptorProto> = ::protobuf::rt::LazyV2::INIT;
+
+fn parse_descriptor_proto() -> ::protobuf::descriptor::FileDescriptorProto {
+    ::protobuf::Message::parse_from_bytes(file_descriptor_proto_data).unwrap()
+}
+
+pub fn file_descriptor_proto() -> &'static ::protobuf::descriptor::FileDescriptorProto {
+    file_descriptor_proto_lazy.get(|| {
+        parse_descriptor_proto()
+    })
+}
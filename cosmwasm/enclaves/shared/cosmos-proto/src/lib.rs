@@ -47,6 +47,13 @@ pub mod ibc {
     pub mod client;
     pub mod tx;
     pub mod upgrade;
+
+    pub mod transfer {
+        pub mod tx;
+
+        use super::client;
+        use crate::base::coin;
+    }
 }
 
 pub mod registration {
@@ -0,0 +1,124 @@
+//! Bindings of `enclave_cosmos_types` for client SDKs, like secret.js, that compile this crate to
+//! wasm32 with `cargo build --target wasm32-unknown-unknown` and `wasm-bindgen`.
+//!
+//! Clients that build and check transactions with the same parsing and verification as the
+//! enclave can't drift from it: a message that a client parses from a sign doc differently than
+//! the enclave fails here before it's ever sent. Messages are returned as the JSON of the
+//! `DirectSdkMsg`s the enclave verifies the inputs of contracts against.
+//!
+//! Parsing depends on the features active at the height of the chain the transaction is for, see
+//! `feature_activation`. Clients set them with `setActiveBlock`, and until they do none are active.
+//!
+//! The crate is a workspace of its own, outside of the enclave's: the enclave crates use the SGX
+//! forks of serde, serde_json, prost and base64, which don't build for wasm32, and this workspace
+//! patches them with the upstream crates of the same versions.
+//!
+//! CI checks the build with `make check-cosmos-types-wasm`, and runs the smoke tests of
+//! tests/bindings.rs with `make test-cosmos-types-wasm`.
+
+use wasm_bindgen::prelude::*;
+
+use cosmos_proto::tx::signing::SignMode;
+use cw_types_v010::encoding::Binary;
+use cw_types_v010::types::{CanonicalAddr, Coin};
+use enclave_cosmos_types::callback_sig::callback_signature;
+use enclave_cosmos_types::feature_activation;
+use enclave_cosmos_types::types::{AminoSdkMsg, DirectSdkMsg, SigInfo, SignDoc, StdSignDoc};
+use enclave_crypto::traits::VerifyingKey;
+
+fn to_js_error(err: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn messages_to_json(messages: &[DirectSdkMsg]) -> Result<String, JsValue> {
+    serde_json::to_string(messages).map_err(to_js_error)
+}
+
+fn direct_messages(sign_doc: &[u8]) -> Result<Vec<DirectSdkMsg>, JsValue> {
+    let sign_doc = SignDoc::from_bytes(sign_doc).map_err(to_js_error)?;
+    Ok(sign_doc.body.messages)
+}
+
+fn amino_messages(sign_doc: &[u8]) -> Result<Vec<DirectSdkMsg>, JsValue> {
+    let sign_doc: StdSignDoc = serde_json::from_slice(sign_doc).map_err(to_js_error)?;
+    sign_doc
+        .msgs
+        .into_iter()
+        .map(AminoSdkMsg::into_direct_msg)
+        .collect::<Result<Vec<DirectSdkMsg>, _>>()
+        .map_err(to_js_error)
+}
+
+/// Sets the chain and the height of the block that transactions are parsed and verified for
+#[wasm_bindgen(js_name = setActiveBlock)]
+pub fn set_active_block(chain_id: &str, height: u64) {
+    feature_activation::set_active_block(chain_id, height);
+}
+
+/// The messages of a `SIGN_MODE_DIRECT` sign doc
+#[wasm_bindgen(js_name = signDocMessages)]
+pub fn sign_doc_messages(sign_doc: &[u8]) -> Result<String, JsValue> {
+    messages_to_json(&direct_messages(sign_doc)?)
+}
+
+/// The messages of a `SIGN_MODE_LEGACY_AMINO_JSON` sign doc
+#[wasm_bindgen(js_name = aminoSignDocMessages)]
+pub fn amino_sign_doc_messages(sign_doc: &[u8]) -> Result<String, JsValue> {
+    messages_to_json(&amino_messages(sign_doc)?)
+}
+
+/// The messages signed in the JSON of a `SigInfo`, which fails if the enclave couldn't parse it
+#[wasm_bindgen(js_name = sigInfoMessages)]
+pub fn sig_info_messages(sig_info: &[u8]) -> Result<String, JsValue> {
+    let sig_info: SigInfo = serde_json::from_slice(sig_info).map_err(to_js_error)?;
+    let messages = match sig_info.sign_mode {
+        SignMode::SIGN_MODE_DIRECT => direct_messages(sig_info.sign_bytes.as_slice())?,
+        SignMode::SIGN_MODE_LEGACY_AMINO_JSON => amino_messages(sig_info.sign_bytes.as_slice())?,
+        other => {
+            return Err(JsValue::from_str(&format!(
+                "sign mode {:?} isn't supported here",
+                other
+            )))
+        }
+    };
+    messages_to_json(&messages)
+}
+
+/// Verifies the signature of a `SIGN_MODE_DIRECT` sign doc by the signer with the canonical
+/// address, with the public key the sign doc has for the signer, like the enclave verifies the
+/// signature of the sender of a message
+#[wasm_bindgen(js_name = verifyDirectSignature)]
+pub fn verify_direct_signature(
+    sign_doc: &[u8],
+    signature: &[u8],
+    signer: &[u8],
+) -> Result<(), JsValue> {
+    let parsed = SignDoc::from_bytes(sign_doc).map_err(to_js_error)?;
+    let signer = CanonicalAddr(Binary(signer.to_vec()));
+    let public_key = parsed
+        .auth_info
+        .sender_public_key(&signer)
+        .ok_or_else(|| JsValue::from_str("the signer isn't among the signers of the sign doc"))?;
+
+    public_key
+        .verify_bytes(sign_doc, signature, SignMode::SIGN_MODE_DIRECT)
+        .map_err(to_js_error)
+}
+
+/// The callback signature of a message, see `callback_sig`. Only useful to tools and local
+/// networks that know the callback secret.
+#[wasm_bindgen(js_name = callbackSignature)]
+pub fn wasm_callback_signature(
+    callback_secret: &[u8],
+    chain_id: Option<String>,
+    msg: &[u8],
+    sent_funds: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    let sent_funds: Vec<Coin> = serde_json::from_slice(sent_funds).map_err(to_js_error)?;
+    Ok(callback_signature(
+        callback_secret,
+        chain_id.as_deref(),
+        msg,
+        &sent_funds,
+    ))
+}
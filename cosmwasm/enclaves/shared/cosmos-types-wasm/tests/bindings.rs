@@ -0,0 +1,81 @@
+//! Smoke tests of the bindings for client SDKs, which run the wasm32 build of the crate with
+//! `wasm-bindgen-test-runner`, see `make test-cosmos-types-wasm`.
+
+#![cfg(target_arch = "wasm32")]
+
+use k256::ecdsa::signature::{Signature as _, Signer};
+use k256::ecdsa::{Signature, SigningKey};
+use protobuf::well_known_types::Any;
+use protobuf::Message;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+use cosmos_proto as proto;
+use enclave_cosmos_types::traits::CosmosAminoPubkey;
+use enclave_cosmos_types::types::TYPE_URL_SECP256K1_PUBKEY;
+use enclave_cosmos_types_wasm::{
+    amino_sign_doc_messages, sign_doc_messages, verify_direct_signature,
+};
+use enclave_crypto::secp256k1::Secp256k1PubKey;
+
+const SENDER: &str = "secret1k0jntykt7e4g3y88ltc60czgjuqdy4c9e8fzek";
+
+/// A sign doc without messages, signed by the only signer of its auth info
+fn direct_sign_doc(signing_key: &SigningKey) -> Vec<u8> {
+    let mut public_key = proto::crypto::secp256k1::PubKey::new();
+    public_key.key = signing_key.verifying_key().to_bytes().to_vec();
+
+    let mut any_public_key = Any::new();
+    any_public_key.type_url = TYPE_URL_SECP256K1_PUBKEY.to_string();
+    any_public_key.value = public_key.write_to_bytes().unwrap();
+
+    let mut signer_info = proto::tx::tx::SignerInfo::new();
+    signer_info.set_public_key(any_public_key);
+    let mut auth_info = proto::tx::tx::AuthInfo::new();
+    auth_info.signer_infos.push(signer_info);
+
+    let mut sign_doc = proto::tx::tx::SignDoc::new();
+    sign_doc.body_bytes = proto::tx::tx::TxBody::new().write_to_bytes().unwrap();
+    sign_doc.auth_info_bytes = auth_info.write_to_bytes().unwrap();
+    sign_doc.chain_id = "secret-4".to_string();
+    sign_doc.write_to_bytes().unwrap()
+}
+
+#[wasm_bindgen_test]
+fn verifies_direct_signatures() {
+    let signing_key = SigningKey::from_bytes(&[7u8; 32]).unwrap();
+    let signer =
+        Secp256k1PubKey::new(signing_key.verifying_key().to_bytes().to_vec()).get_address();
+
+    let sign_doc = direct_sign_doc(&signing_key);
+    let signature: Signature = signing_key.sign(&sign_doc);
+    let signature = signature.as_bytes();
+
+    assert_eq!(sign_doc_messages(&sign_doc).unwrap(), "[]");
+    assert!(verify_direct_signature(&sign_doc, signature, signer.as_slice()).is_ok());
+
+    // Refused for a tampered signature, and for a signer that didn't sign
+    let mut tampered = signature.to_vec();
+    tampered[0] ^= 1;
+    assert!(verify_direct_signature(&sign_doc, &tampered, signer.as_slice()).is_err());
+    assert!(verify_direct_signature(&sign_doc, signature, &[1u8; 20]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn parses_amino_sign_docs() {
+    let sign_doc = format!(
+        r#"{{"account_number":"1","chain_id":"secret-4","memo":"","sequence":"0","msgs":[{{"type":"wasm/MsgExecuteContract","value":{{"sender":"{0}","contract":"{0}","msg":"eyJhIjoxfQ=="}}}}]}}"#,
+        SENDER
+    );
+
+    let messages: Vec<serde_json::Value> =
+        serde_json::from_str(&amino_sign_doc_messages(sign_doc.as_bytes()).unwrap()).unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        messages[0]["MsgExecuteContract"]["contract"],
+        serde_json::json!(SENDER)
+    );
+
+    // Fails like the enclave for a sender that isn't an address
+    let malformed = sign_doc.replacen(SENDER, "secret1", 1);
+    assert!(amino_sign_doc_messages(malformed.as_bytes()).is_err());
+}
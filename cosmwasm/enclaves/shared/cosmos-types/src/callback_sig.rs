@@ -0,0 +1,64 @@
+//! The format of the callback signatures, which the enclave puts on the messages that contracts
+//! send to other contracts, and verifies when the messages are executed.
+//!
+//! ```text
+//! sha256(callback_secret | msg | json(sent_funds)), or, once the chain binds its signatures,
+//! sha256(callback_secret | len(chain_id) | chain_id | msg | json(sent_funds))
+//! ```
+
+use sha2::{Digest, Sha256};
+
+use cw_types_v010::types::Coin;
+
+/// The callback signature of the message and funds, with the consensus callback secret.
+/// `chain_id` is only set once the chain activated `ChainBoundCallbackSig`.
+pub fn callback_signature(
+    callback_secret: &[u8],
+    chain_id: Option<&str>,
+    msg: &[u8],
+    sent_funds: &[Coin],
+) -> Vec<u8> {
+    let mut callback_sig_bytes = callback_secret.to_vec();
+
+    if let Some(chain_id) = chain_id {
+        callback_sig_bytes.extend((chain_id.len() as u32).to_be_bytes());
+        callback_sig_bytes.extend(chain_id.as_bytes());
+    }
+
+    callback_sig_bytes.extend(msg);
+    callback_sig_bytes.extend(serde_json::to_vec(sent_funds).unwrap());
+
+    Sha256::digest(callback_sig_bytes.as_slice()).to_vec()
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use cw_types_v010::math::Uint256;
+
+    pub fn test_callback_signature() {
+        let funds = vec![Coin {
+            denom: "uscrt".to_string(),
+            amount: Uint256::from(100u128),
+        }];
+        let sig = callback_signature(b"secret", None, b"{}", &funds);
+
+        assert_eq!(sig.len(), 32);
+        assert_eq!(
+            sig,
+            Sha256::digest(br#"secret{}[{"denom":"uscrt","amount":"100"}]"#).to_vec()
+        );
+        assert_eq!(callback_signature(b"secret", None, b"{}", &funds), sig);
+        assert_ne!(callback_signature(b"other", None, b"{}", &funds), sig);
+        assert_ne!(callback_signature(b"secret", None, b"{}", &[]), sig);
+
+        // Signatures of other chains don't match
+        let bound = callback_signature(b"secret", Some("secret-4"), b"{}", &funds);
+        assert_ne!(bound, sig);
+        assert_ne!(
+            callback_signature(b"secret", Some("pulsar-3"), b"{}", &funds),
+            bound
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! The channel upgrade handshake of ibc-go v8, which IBC-enabled contracts take part in with the
+//! `ibc_channel_upgrade_*` entry points.
+//!
+//! The protobuf definitions of the handshake are newer than the ibc-go protos that `cosmos-proto`
+//! is generated from, so the messages are decoded here, and only the fields that the enclave
+//! verifies are kept:
+//!
+//! ```text
+//! MsgChannelUpgradeInit    { port_id = 1, channel_id = 2, fields = 3, signer = 4 }
+//! MsgChannelUpgradeTry     { port_id = 1, channel_id = 2, counterparty_upgrade_fields = 4, signer = 9 }
+//! MsgChannelUpgradeAck     { port_id = 1, channel_id = 2, counterparty_upgrade = 3, signer = 7 }
+//! MsgChannelUpgradeConfirm { port_id = 1, channel_id = 2, counterparty_upgrade = 4, signer = 8 }
+//! Upgrade                  { fields = 1 }
+//! UpgradeFields            { version = 3 }
+//! ```
+
+use protobuf::wire_format::WireType;
+use protobuf::{CodedInputStream, ProtobufResult};
+use serde::{Deserialize, Serialize};
+
+use enclave_ffi_types::EnclaveError;
+use log::*;
+
+use crate::types::{DirectSdkMsg, HandleType};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelUpgradeStep {
+    Init,
+    Try,
+    Ack,
+    Confirm,
+}
+
+/// Where the version of the upgrade is in a message: the field of the `UpgradeFields`, or of the
+/// `Upgrade` that holds them
+enum VersionField {
+    Fields(u32),
+    Upgrade(u32),
+}
+
+impl ChannelUpgradeStep {
+    pub fn from_type_url(type_url: &str) -> Option<Self> {
+        match type_url {
+            "/ibc.core.channel.v1.MsgChannelUpgradeInit" => Some(ChannelUpgradeStep::Init),
+            "/ibc.core.channel.v1.MsgChannelUpgradeTry" => Some(ChannelUpgradeStep::Try),
+            "/ibc.core.channel.v1.MsgChannelUpgradeAck" => Some(ChannelUpgradeStep::Ack),
+            "/ibc.core.channel.v1.MsgChannelUpgradeConfirm" => Some(ChannelUpgradeStep::Confirm),
+            _ => None,
+        }
+    }
+
+    /// The handle type that the contract is called with in this step
+    pub fn handle_type(self) -> HandleType {
+        match self {
+            ChannelUpgradeStep::Init => HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_INIT,
+            ChannelUpgradeStep::Try => HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_TRY,
+            ChannelUpgradeStep::Ack => HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_ACK,
+            ChannelUpgradeStep::Confirm => HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM,
+        }
+    }
+
+    fn version_field(self) -> VersionField {
+        match self {
+            ChannelUpgradeStep::Init => VersionField::Fields(3),
+            ChannelUpgradeStep::Try => VersionField::Fields(4),
+            ChannelUpgradeStep::Ack => VersionField::Upgrade(3),
+            ChannelUpgradeStep::Confirm => VersionField::Upgrade(4),
+        }
+    }
+
+    fn signer_field(self) -> u32 {
+        match self {
+            ChannelUpgradeStep::Init => 4,
+            ChannelUpgradeStep::Try => 9,
+            ChannelUpgradeStep::Ack => 7,
+            ChannelUpgradeStep::Confirm => 8,
+        }
+    }
+}
+
+/// Calls `on_field` with the number of every length delimited field of the message and a stream
+/// that is positioned at its value, which it must read. Other fields are skipped.
+fn for_each_field(
+    bytes: &[u8],
+    mut on_field: impl FnMut(u32, &mut CodedInputStream) -> ProtobufResult<()>,
+) -> ProtobufResult<()> {
+    let mut is = CodedInputStream::from_bytes(bytes);
+    while !is.eof()? {
+        let (number, wire_type) = is.read_tag_unpack()?;
+        if wire_type == WireType::WireTypeLengthDelimited {
+            on_field(number, &mut is)?;
+        } else {
+            is.skip_field(wire_type)?;
+        }
+    }
+    Ok(())
+}
+
+/// The version of `UpgradeFields`
+fn parse_upgrade_fields_version(bytes: &[u8]) -> ProtobufResult<String> {
+    let mut version = String::new();
+    for_each_field(bytes, |number, is| {
+        match number {
+            3 => version = is.read_string()?,
+            _ => {
+                is.read_bytes()?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(version)
+}
+
+/// The version of the `UpgradeFields` of an `Upgrade`
+fn parse_upgrade_version(bytes: &[u8]) -> ProtobufResult<String> {
+    let mut version = String::new();
+    for_each_field(bytes, |number, is| {
+        match number {
+            1 => version = parse_upgrade_fields_version(&is.read_bytes()?)?,
+            _ => {
+                is.read_bytes()?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(version)
+}
+
+pub fn parse_channel_upgrade(
+    step: ChannelUpgradeStep,
+    bytes: &[u8],
+) -> Result<DirectSdkMsg, EnclaveError> {
+    let mut port_id = String::new();
+    let mut channel_id = String::new();
+    let mut version = String::new();
+    let mut signer = String::new();
+
+    let version_field = step.version_field();
+    let signer_field = step.signer_field();
+    for_each_field(bytes, |number, is| {
+        match (number, &version_field) {
+            (1, _) => port_id = is.read_string()?,
+            (2, _) => channel_id = is.read_string()?,
+            (number, VersionField::Fields(field)) if number == *field => {
+                version = parse_upgrade_fields_version(&is.read_bytes()?)?
+            }
+            (number, VersionField::Upgrade(field)) if number == *field => {
+                version = parse_upgrade_version(&is.read_bytes()?)?
+            }
+            (number, _) if number == signer_field => signer = is.read_string()?,
+            _ => {
+                is.read_bytes()?;
+            }
+        }
+        Ok(())
+    })
+    .map_err(|err| {
+        warn!("failed to parse channel upgrade {:?}: {:?}", step, err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    if port_id.is_empty() || channel_id.is_empty() {
+        warn!("channel upgrade {:?} has no port or channel", step);
+        return Err(EnclaveError::FailedToDeserialize);
+    }
+
+    Ok(DirectSdkMsg::MsgChannelUpgrade {
+        step,
+        port_id,
+        channel_id,
+        version,
+        signer,
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use protobuf::CodedOutputStream;
+
+    fn encode(write: impl FnOnce(&mut CodedOutputStream) -> ProtobufResult<()>) -> Vec<u8> {
+        let mut bytes = vec![];
+        {
+            let mut os = CodedOutputStream::vec(&mut bytes);
+            write(&mut os).unwrap();
+            os.flush().unwrap();
+        }
+        bytes
+    }
+
+    fn upgrade_fields(version: &str) -> Vec<u8> {
+        encode(|os| {
+            os.write_enum(1, 2)?;
+            os.write_string(2, "connection-0")?;
+            os.write_string(3, version)
+        })
+    }
+
+    pub fn test_parse_channel_upgrade() {
+        let init = encode(|os| {
+            os.write_string(1, "wasm.secret1contract")?;
+            os.write_string(2, "channel-0")?;
+            os.write_bytes(3, &upgrade_fields("ics20-2"))?;
+            os.write_string(4, "secret1authority")
+        });
+        let msg =
+            DirectSdkMsg::from_bytes("/ibc.core.channel.v1.MsgChannelUpgradeInit", &init).unwrap();
+        assert_eq!(
+            msg,
+            DirectSdkMsg::MsgChannelUpgrade {
+                step: ChannelUpgradeStep::Init,
+                port_id: "wasm.secret1contract".to_string(),
+                channel_id: "channel-0".to_string(),
+                version: "ics20-2".to_string(),
+                signer: "secret1authority".to_string(),
+            }
+        );
+        assert!(msg.sender().is_none());
+        assert!(!msg.has_amino_encoding());
+
+        // The version of Ack and Confirm is in the counterparty's upgrade, after fields that
+        // aren't kept
+        let upgrade = encode(|os| {
+            os.write_bytes(1, &upgrade_fields("ics20-2"))?;
+            os.write_uint64(3, 7)
+        });
+        let confirm = encode(|os| {
+            os.write_string(1, "wasm.secret1contract")?;
+            os.write_string(2, "channel-0")?;
+            os.write_enum(3, 5)?;
+            os.write_bytes(4, &upgrade)?;
+            os.write_bytes(5, b"proof")?;
+            os.write_string(8, "secret1relayer")
+        });
+        match parse_channel_upgrade(ChannelUpgradeStep::Confirm, &confirm).unwrap() {
+            DirectSdkMsg::MsgChannelUpgrade {
+                step,
+                version,
+                signer,
+                ..
+            } => {
+                assert_eq!(step, ChannelUpgradeStep::Confirm);
+                assert_eq!(version, "ics20-2");
+                assert_eq!(signer, "secret1relayer");
+            }
+            other => panic!("parsed {:?}", other),
+        }
+        assert_eq!(
+            ChannelUpgradeStep::Confirm.handle_type(),
+            HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM
+        );
+
+        // A message without a channel, and a truncated message
+        let no_channel = encode(|os| os.write_string(1, "wasm.secret1contract"));
+        assert!(parse_channel_upgrade(ChannelUpgradeStep::Ack, &no_channel).is_err());
+        assert!(parse_channel_upgrade(ChannelUpgradeStep::Init, &init[..init.len() - 1]).is_err());
+    }
+}
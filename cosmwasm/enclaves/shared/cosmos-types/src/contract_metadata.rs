@@ -0,0 +1,192 @@
+use log::*;
+
+/// Name of the custom wasm section in which contracts declare the features they use.
+/// The content of the section is a JSON array of feature names, e.g. `["random","ibc"]`.
+pub const FEATURES_SECTION_NAME: &str = "secret_features";
+
+/// Name of the custom wasm section in which contracts declare the enclave capabilities they
+/// require, as a JSON array of capability names, e.g. `["random","env_v1"]`.
+/// See `EnclaveManifest.capabilities` for the capabilities an enclave supports.
+pub const REQUIREMENTS_SECTION_NAME: &str = "secret_requirements";
+
+/// Name of the custom wasm section that carries a signature over the code, which upgrade
+/// policies can require. It must be the last section of the code, and its content is a compact
+/// secp256k1 signature over all of the code before it.
+pub const CODE_SIGNATURE_SECTION_NAME: &str = "secret_code_signature";
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+const WASM_HEADER_LEN: usize = 8;
+const CUSTOM_SECTION_ID: u8 = 0;
+
+/// Reads a LEB128 encoded u32 from the start of `bytes`.
+/// Returns the value and the amount of bytes it was encoded in.
+fn read_var_u32(bytes: &[u8]) -> Option<(usize, usize)> {
+    let value = prost::decode_length_delimiter(bytes).ok()?;
+    Some((value, prost::length_delimiter_len(value)))
+}
+
+/// Returns the content of the first custom section with the given name
+fn find_custom_section<'code>(code: &'code [u8], name: &str) -> Option<&'code [u8]> {
+    if !code.starts_with(WASM_MAGIC) {
+        return None;
+    }
+
+    let mut rest = code.get(WASM_HEADER_LEN..)?;
+    while let Some((&section_id, after_id)) = rest.split_first() {
+        let (section_len, len_size) = read_var_u32(after_id)?;
+        let section = after_id.get(len_size..len_size.checked_add(section_len)?)?;
+        rest = &after_id[len_size + section_len..];
+
+        if section_id != CUSTOM_SECTION_ID {
+            continue;
+        }
+
+        let (name_len, name_len_size) = read_var_u32(section)?;
+        let section_name = section.get(name_len_size..name_len_size.checked_add(name_len)?)?;
+        if section_name == name.as_bytes() {
+            return Some(&section[name_len_size + name_len..]);
+        }
+    }
+
+    None
+}
+
+/// Returns the code before its signature section and the content of the signature section,
+/// if the last section of the code is a signature section
+pub fn split_code_signature(code: &[u8]) -> Option<(&[u8], &[u8])> {
+    if !code.starts_with(WASM_MAGIC) {
+        return None;
+    }
+
+    let mut offset = WASM_HEADER_LEN;
+    let mut last_section = None;
+    while offset < code.len() {
+        let after_id = &code[offset + 1..];
+        let (section_len, len_size) = read_var_u32(after_id)?;
+        let section = after_id.get(len_size..len_size.checked_add(section_len)?)?;
+        last_section = Some((offset, section));
+        offset += 1 + len_size + section_len;
+    }
+
+    let (section_start, section) = last_section?;
+    if code[section_start] != CUSTOM_SECTION_ID {
+        return None;
+    }
+
+    let (name_len, name_len_size) = read_var_u32(section)?;
+    let section_name = section.get(name_len_size..name_len_size.checked_add(name_len)?)?;
+    if section_name != CODE_SIGNATURE_SECTION_NAME.as_bytes() {
+        return None;
+    }
+
+    Some((&code[..section_start], &section[name_len_size + name_len..]))
+}
+
+/// Parses the features a contract declared in its custom features section.
+/// Returns `None` if the contract doesn't declare its features, or if the section is malformed,
+/// in which case the features are detected from the exports of the contract.
+pub fn parse_declared_features(code: &[u8]) -> Option<Vec<String>> {
+    let section = find_custom_section(code, FEATURES_SECTION_NAME)?;
+
+    match serde_json::from_slice(section) {
+        Ok(features) => Some(features),
+        Err(err) => {
+            warn!("contract has a malformed features section: {}", err);
+            None
+        }
+    }
+}
+
+/// Parses the capabilities a contract requires in its custom requirements section.
+/// Unlike the features section, a malformed requirements section is an error, since ignoring it
+/// would let the contract run on enclaves that don't meet its requirements.
+pub fn parse_required_capabilities(code: &[u8]) -> Result<Vec<String>, serde_json::Error> {
+    match find_custom_section(code, REQUIREMENTS_SECTION_NAME) {
+        Some(section) => serde_json::from_slice(section),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    fn custom_section(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut section = vec![name.len() as u8];
+        section.extend_from_slice(name.as_bytes());
+        section.extend_from_slice(content);
+
+        let mut encoded = vec![CUSTOM_SECTION_ID, section.len() as u8];
+        encoded.extend(section);
+        encoded
+    }
+
+    pub fn test_parse_declared_features() {
+        let mut code = b"\0asm\x01\0\0\0".to_vec();
+        // An empty type section
+        code.extend_from_slice(&[1, 1, 0]);
+        code.extend(custom_section("name", b"\0"));
+        code.extend(custom_section(
+            FEATURES_SECTION_NAME,
+            br#"["random","ibc"]"#,
+        ));
+
+        assert_eq!(
+            parse_declared_features(&code),
+            Some(vec!["random".to_string(), "ibc".to_string()])
+        );
+    }
+
+    pub fn test_parse_declared_features_missing_or_malformed() {
+        let code = b"\0asm\x01\0\0\0".to_vec();
+        assert_eq!(parse_declared_features(&code), None);
+
+        let mut malformed = code.clone();
+        malformed.extend(custom_section(FEATURES_SECTION_NAME, b"random"));
+        assert_eq!(parse_declared_features(&malformed), None);
+
+        let mut truncated = code;
+        truncated.extend_from_slice(&[CUSTOM_SECTION_ID, 100, 1]);
+        assert_eq!(parse_declared_features(&truncated), None);
+    }
+
+    pub fn test_parse_required_capabilities() {
+        let code = b"\0asm\x01\0\0\0".to_vec();
+        assert_eq!(
+            parse_required_capabilities(&code).unwrap(),
+            Vec::<String>::new()
+        );
+
+        let mut requiring = code.clone();
+        requiring.extend(custom_section(
+            REQUIREMENTS_SECTION_NAME,
+            br#"["random","env_v1"]"#,
+        ));
+        assert_eq!(
+            parse_required_capabilities(&requiring).unwrap(),
+            vec!["random".to_string(), "env_v1".to_string()]
+        );
+
+        let mut malformed = code;
+        malformed.extend(custom_section(REQUIREMENTS_SECTION_NAME, b"random"));
+        assert!(parse_required_capabilities(&malformed).is_err());
+    }
+
+    pub fn test_split_code_signature() {
+        let mut code = b"\0asm\x01\0\0\0".to_vec();
+        code.extend_from_slice(&[1, 1, 0]);
+        assert_eq!(split_code_signature(&code), None);
+
+        let mut signed = code.clone();
+        signed.extend(custom_section(CODE_SIGNATURE_SECTION_NAME, b"signature"));
+        assert_eq!(
+            split_code_signature(&signed),
+            Some((code.as_slice(), b"signature".as_ref()))
+        );
+
+        // The signature section must be the last one
+        let mut not_last = signed;
+        not_last.extend(custom_section("name", b"\0"));
+        assert_eq!(split_code_signature(&not_last), None);
+    }
+}
@@ -0,0 +1,411 @@
+//! Activation heights of the consensus-affecting behaviors of the enclave.
+//!
+//! A behavior that changes the results of executions must start at the same height on every
+//! node of a chain, so instead of comparing block heights where the behavior is implemented,
+//! each behavior is a `Feature` that is scheduled here per chain-id.
+//!
+//! Executions set the block they run in with `set_active_block`, and the code that implements a
+//! feature asks `is_active`. Without a block, no feature is active. Chains that aren't listed in `CHAIN_SCHEDULES`, like local networks,
+//! activate every feature at genesis. Listed chains activate the features of each upgrade at the
+//! height of that upgrade, and every feature must be part of an upgrade of every listed chain.
+
+use std::cell::RefCell;
+
+use log::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// `HANDLE_TYPE_IBC_WASM_HOOKS_*` transfer handle types
+    IbcHooksHandleTypes,
+    /// `HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER`
+    Ics721HandleType,
+    /// The gas table of the compute params replaces the default `WasmCosts`
+    GovernanceGasTable,
+    /// Callback signatures commit to the chain-id, so they can't be replayed on another chain
+    /// that shares the consensus secrets, like a testnet forked from mainnet
+    ChainBoundCallbackSig,
+    /// Executions are refused once the gas used in a block reaches the max gas of its verified
+    /// consensus params
+    BlockGasLimit,
+    /// Queries that contracts make get a gas budget that decays with their depth
+    QueryGasBudget,
+    /// The memory limits of the compute params replace the default limit of contract instances
+    GovernanceMemoryLimits,
+    /// `HANDLE_TYPE_IBC_CHANNEL_UPGRADE_*` handle types
+    IbcChannelUpgradeHandleTypes,
+    /// The storage that contracts use is accounted, capped by the quota of the `WasmCosts`, and
+    /// surcharged near the cap
+    StorageQuota,
+    /// Executions can carry an idempotency key, and duplicates of them aren't executed again
+    IdempotencyKeys,
+    /// Envelopes of encrypted messages can have a version, which picks their cipher suite
+    VersionedEnvelopes,
+    /// The padding buckets of the compute params replace the default `output_padding` buckets
+    GovernanceOutputPadding,
+    /// Encrypted outputs are padded to the buckets of `output_padding`
+    OutputPadding,
+    /// Plaintext outputs can't have attributes with the keys of `plaintext_attribute_denylist`
+    SensitiveAttributeKeys,
+    /// The env, msg and sig_info of executions are charged gas by their size
+    InputGas,
+    /// The contract key that a migration replaced is accepted for a few blocks after it
+    PreviousContractKeyGrace,
+    /// ibc-hooks ack and timeout callbacks are refused unless the contract sent the transfer
+    BoundTransferCallbacks,
+    /// The verified memo and fee payer of the transaction are passed to v1 contracts
+    TxInfoInEnv,
+    /// IBC timeouts are refused unless the timeout of the packet elapsed
+    IbcTimeoutElapsed,
+    /// Coin amounts in messages are parsed as 256 bit integers
+    WideCoinAmounts,
+    /// Amino signed transactions can mix in messages that have no Amino encoding
+    AminoIbcMessages,
+    /// The verified hash and proposer of the block are passed to v1 contracts
+    BlockHeaderInEnv,
+    /// The data of plaintext and IBC outputs reaches the reply of the caller
+    PlaintextReplyData,
+    /// Replies carry their data normalized, whatever the SDK encoding
+    NormalizedReplyData,
+    /// NaNs of float operations are canonicalized, instead of refusing contracts with floats
+    CanonicalNans,
+    /// Every signer of a transaction is verified, not just the sender
+    AllSignersVerified,
+    /// Typed contract errors are passed to the reply of the caller
+    TypedContractErrors,
+    /// ibc-hooks callbacks of packets that were already processed are refused
+    ProcessedCallbacks,
+    /// secp256r1 public keys are accepted in transactions
+    Secp256r1Keys,
+    /// Host functions are only available in the operations of their matrix
+    HostFunctionMatrix,
+    /// The calls of contracts are limited to `max_call_depth` of the `WasmCosts`
+    CallDepthLimit,
+    /// The answers to the queries that contracts make are cached within the execution
+    ExecutionQueryCache,
+    /// The denoms of sent funds must be valid, and their IBC and tokenfactory forms are parsed
+    ValidatedDenoms,
+    /// Clients can request a summary of the capabilities that executions exercised
+    CapabilitySummary,
+    /// Answers to custom queries must be signed by the responder key of the compute params, which
+    /// executions prove against a verified header
+    ProvenCustomQueryKey,
+    /// Group policies can execute contracts through the accepted proposals that executions prove
+    /// against a verified header
+    ProvenGroupProposals,
+}
+
+pub const ALL_FEATURES: &[Feature] = &[
+    Feature::IbcHooksHandleTypes,
+    Feature::Ics721HandleType,
+    Feature::GovernanceGasTable,
+    Feature::ChainBoundCallbackSig,
+    Feature::BlockGasLimit,
+    Feature::QueryGasBudget,
+    Feature::GovernanceMemoryLimits,
+    Feature::IbcChannelUpgradeHandleTypes,
+    Feature::StorageQuota,
+    Feature::IdempotencyKeys,
+    Feature::VersionedEnvelopes,
+    Feature::GovernanceOutputPadding,
+    Feature::OutputPadding,
+    Feature::SensitiveAttributeKeys,
+    Feature::InputGas,
+    Feature::PreviousContractKeyGrace,
+    Feature::BoundTransferCallbacks,
+    Feature::TxInfoInEnv,
+    Feature::IbcTimeoutElapsed,
+    Feature::WideCoinAmounts,
+    Feature::AminoIbcMessages,
+    Feature::BlockHeaderInEnv,
+    Feature::PlaintextReplyData,
+    Feature::NormalizedReplyData,
+    Feature::CanonicalNans,
+    Feature::AllSignersVerified,
+    Feature::TypedContractErrors,
+    Feature::ProcessedCallbacks,
+    Feature::Secp256r1Keys,
+    Feature::HostFunctionMatrix,
+    Feature::CallDepthLimit,
+    Feature::ExecutionQueryCache,
+    Feature::ValidatedDenoms,
+    Feature::CapabilitySummary,
+    Feature::ProvenCustomQueryKey,
+    Feature::ProvenGroupProposals,
+];
+
+/// The features that an upgrade of a chain activates, at the height of the upgrade
+#[derive(Clone, Copy, Debug)]
+pub struct Activation {
+    pub features: &'static [Feature],
+    pub height: u64,
+}
+
+const fn activation(features: &'static [Feature], height: u64) -> Activation {
+    Activation { features, height }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChainSchedule {
+    pub chain_id: &'static str,
+    pub activations: &'static [Activation],
+}
+
+/// The features that the listed chains already had at the height they were scheduled from
+const GENESIS_FEATURES: &[Feature] = &[Feature::IbcHooksHandleTypes, Feature::Ics721HandleType];
+
+/// The features of the next upgrade, which change the results of executions
+const NEXT_UPGRADE_FEATURES: &[Feature] = &[
+    Feature::GovernanceGasTable,
+    Feature::ChainBoundCallbackSig,
+    Feature::BlockGasLimit,
+    Feature::QueryGasBudget,
+    Feature::GovernanceMemoryLimits,
+    Feature::IbcChannelUpgradeHandleTypes,
+    Feature::StorageQuota,
+    Feature::IdempotencyKeys,
+    Feature::VersionedEnvelopes,
+    Feature::GovernanceOutputPadding,
+    Feature::OutputPadding,
+    Feature::SensitiveAttributeKeys,
+    Feature::InputGas,
+    Feature::PreviousContractKeyGrace,
+    Feature::BoundTransferCallbacks,
+    Feature::TxInfoInEnv,
+    Feature::IbcTimeoutElapsed,
+    Feature::WideCoinAmounts,
+    Feature::AminoIbcMessages,
+    Feature::BlockHeaderInEnv,
+    Feature::PlaintextReplyData,
+    Feature::NormalizedReplyData,
+    Feature::CanonicalNans,
+    Feature::AllSignersVerified,
+    Feature::TypedContractErrors,
+    Feature::ProcessedCallbacks,
+    Feature::Secp256r1Keys,
+    Feature::HostFunctionMatrix,
+    Feature::CallDepthLimit,
+    Feature::ExecutionQueryCache,
+    Feature::ValidatedDenoms,
+    Feature::CapabilitySummary,
+    Feature::ProvenCustomQueryKey,
+    Feature::ProvenGroupProposals,
+];
+
+/// The heights of the next upgrade, which its software upgrade proposal sets on each chain. Until
+/// the proposal passes the features of the upgrade stay inactive there.
+pub const SECRET_4_NEXT_UPGRADE_HEIGHT: u64 = u64::MAX;
+pub const PULSAR_3_NEXT_UPGRADE_HEIGHT: u64 = u64::MAX;
+
+pub const CHAIN_SCHEDULES: &[ChainSchedule] = &[
+    ChainSchedule {
+        chain_id: "secret-4",
+        activations: &[
+            activation(GENESIS_FEATURES, 0),
+            activation(NEXT_UPGRADE_FEATURES, SECRET_4_NEXT_UPGRADE_HEIGHT),
+        ],
+    },
+    ChainSchedule {
+        chain_id: "pulsar-3",
+        activations: &[
+            activation(GENESIS_FEATURES, 0),
+            activation(NEXT_UPGRADE_FEATURES, PULSAR_3_NEXT_UPGRADE_HEIGHT),
+        ],
+    },
+];
+
+/// The height a feature activates at on a chain, or `None` if it isn't scheduled there
+pub fn activation_height(
+    schedules: &[ChainSchedule],
+    feature: Feature,
+    chain_id: &str,
+) -> Option<u64> {
+    match schedules
+        .iter()
+        .find(|schedule| schedule.chain_id == chain_id)
+    {
+        Some(schedule) => schedule
+            .activations
+            .iter()
+            .find(|activation| activation.features.contains(&feature))
+            .map(|activation| activation.height),
+        None => Some(0),
+    }
+}
+
+pub fn is_active_at(
+    schedules: &[ChainSchedule],
+    feature: Feature,
+    chain_id: &str,
+    height: u64,
+) -> bool {
+    activation_height(schedules, feature, chain_id).map_or(false, |since| since <= height)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActiveBlock {
+    pub chain_id: String,
+    pub height: u64,
+}
+
+thread_local! {
+    /// The block of the execution that runs on this thread. Queries can run in parallel with
+    /// each other, at different heights, so this is per thread.
+    static ACTIVE_BLOCK: RefCell<Option<ActiveBlock>> = RefCell::new(None);
+}
+
+/// Sets the block that features are activated by, for the execution that runs on this thread
+pub fn set_active_block(chain_id: &str, height: u64) {
+    trace!("active block: {} at height {}", chain_id, height);
+    ACTIVE_BLOCK.with(|block| {
+        *block.borrow_mut() = Some(ActiveBlock {
+            chain_id: chain_id.to_string(),
+            height,
+        })
+    });
+}
+
+pub fn clear_active_block() {
+    ACTIVE_BLOCK.with(|block| *block.borrow_mut() = None);
+}
+
+/// Restores the block of the execution that was running on this thread when it's dropped
+pub struct ActiveBlockScope {
+    previous: Option<ActiveBlock>,
+}
+
+/// Clears the active block for the duration of an ecall, so that it never sees the block of an
+/// earlier one. The queries that contracts make run in ecalls nested in the execution that made
+/// them, which gets its own block back once they return.
+pub fn enter_active_block_scope() -> ActiveBlockScope {
+    let previous = ACTIVE_BLOCK.with(|block| block.borrow_mut().take());
+    ActiveBlockScope { previous }
+}
+
+impl Drop for ActiveBlockScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        ACTIVE_BLOCK.with(|block| *block.borrow_mut() = previous);
+    }
+}
+
+pub fn active_block() -> Option<ActiveBlock> {
+    ACTIVE_BLOCK.with(|block| block.borrow().clone())
+}
+
+/// Whether a feature is active in the block of the current execution. Outside of executions the
+/// height is unknown, so no feature is active.
+pub fn is_active(feature: Feature) -> bool {
+    match active_block() {
+        Some(block) => is_active_at(CHAIN_SCHEDULES, feature, &block.chain_id, block.height),
+        None => {
+            debug!("{:?} is inactive outside of executions", feature);
+            false
+        }
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    const SCHEDULES: &[ChainSchedule] = &[ChainSchedule {
+        chain_id: "secret-4",
+        activations: &[
+            activation(&[Feature::GovernanceGasTable], 0),
+            activation(&[Feature::ChainBoundCallbackSig], 100),
+        ],
+    }];
+
+    pub fn test_activation_heights() {
+        assert!(is_active_at(
+            SCHEDULES,
+            Feature::GovernanceGasTable,
+            "secret-4",
+            0
+        ));
+        assert!(!is_active_at(
+            SCHEDULES,
+            Feature::ChainBoundCallbackSig,
+            "secret-4",
+            99
+        ));
+        assert!(is_active_at(
+            SCHEDULES,
+            Feature::ChainBoundCallbackSig,
+            "secret-4",
+            100
+        ));
+        // Features that a listed chain doesn't schedule are never active on it
+        assert!(!is_active_at(
+            SCHEDULES,
+            Feature::IbcHooksHandleTypes,
+            "secret-4",
+            u64::MAX
+        ));
+        // Chains that aren't listed activate every feature at genesis
+        for feature in ALL_FEATURES {
+            assert_eq!(
+                activation_height(SCHEDULES, *feature, "secretdev-1"),
+                Some(0)
+            );
+        }
+    }
+
+    pub fn test_every_feature_is_scheduled() {
+        for schedule in CHAIN_SCHEDULES {
+            for feature in ALL_FEATURES {
+                assert!(
+                    activation_height(CHAIN_SCHEDULES, *feature, schedule.chain_id).is_some(),
+                    "{:?} isn't scheduled on {}",
+                    feature,
+                    schedule.chain_id
+                );
+            }
+        }
+    }
+
+    pub fn test_active_block() {
+        // Without a block the height is unknown, even features of the genesis of every chain
+        // aren't active
+        clear_active_block();
+        assert!(!is_active(Feature::ChainBoundCallbackSig));
+        assert!(!is_active(Feature::IbcHooksHandleTypes));
+
+        set_active_block("secretdev-1", 1);
+        assert!(is_active(Feature::ChainBoundCallbackSig));
+
+        set_active_block("secret-4", 1);
+        assert_eq!(
+            active_block(),
+            Some(ActiveBlock {
+                chain_id: "secret-4".to_string(),
+                height: 1
+            })
+        );
+        assert!(is_active(Feature::IbcHooksHandleTypes));
+        assert!(!is_active(Feature::GovernanceGasTable));
+        assert!(!is_active(Feature::ChainBoundCallbackSig));
+
+        // An ecall starts without a block, and the block of the ecall it's nested in is restored
+        // once it's done
+        {
+            let _scope = enter_active_block_scope();
+            assert_eq!(active_block(), None);
+            set_active_block("secret-4", 2);
+        }
+        assert_eq!(
+            active_block(),
+            Some(ActiveBlock {
+                chain_id: "secret-4".to_string(),
+                height: 1
+            })
+        );
+
+        clear_active_block();
+        assert_eq!(active_block(), None);
+        assert!(!is_active(Feature::ChainBoundCallbackSig));
+
+        // The other tests run in a local network
+        set_active_block("secretdev-1", 1);
+    }
+}
@@ -0,0 +1,281 @@
+//! Proposals of the group module, whose messages `MsgExec` executes with the group policy of the
+//! proposal as their sender, e.g. to migrate a contract whose admin is a group policy.
+//!
+//! `cosmos-proto` isn't generated from the group protos, so the messages and the state that the
+//! enclave verifies are decoded here, and only the fields it needs are kept:
+//!
+//! ```text
+//! MsgExec         { proposal_id = 1, executor = 2 }
+//! Proposal        { id = 1, group_policy_address = 2, group_policy_version = 7, status = 8,
+//!                   executor_result = 11, messages = 12 (repeated) }
+//! GroupPolicyInfo { address = 1, version = 5 }
+//! Any             { type_url = 1, value = 2 }
+//! ```
+
+use protobuf::wire_format::WireType;
+use protobuf::{CodedInputStream, ProtobufResult};
+
+use cw_types_v010::types::{CanonicalAddr, HumanAddr};
+use enclave_ffi_types::EnclaveError;
+use log::*;
+
+use crate::types::DirectSdkMsg;
+
+/// `cosmos.group.v1.ProposalStatus.PROPOSAL_STATUS_ACCEPTED`
+const PROPOSAL_STATUS_ACCEPTED: u64 = 2;
+/// `cosmos.group.v1.ProposalExecutorResult.PROPOSAL_EXECUTOR_RESULT_SUCCESS`
+const PROPOSAL_EXECUTOR_RESULT_SUCCESS: u64 = 2;
+
+/// A proposal as the group module stores it
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupProposal {
+    pub id: u64,
+    pub group_policy_address: CanonicalAddr,
+    pub group_policy_version: u64,
+    pub status: u64,
+    pub executor_result: u64,
+    /// The messages that the proposal executes, parsed like the messages of a transaction
+    pub messages: Vec<DirectSdkMsg>,
+}
+
+impl GroupProposal {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EnclaveError> {
+        let mut id = 0;
+        let mut group_policy_address = String::new();
+        let mut group_policy_version = 0;
+        let mut status = 0;
+        let mut executor_result = 0;
+        let mut messages = vec![];
+
+        for_each_field(bytes, |number, wire_type, is| {
+            match (number, wire_type) {
+                (1, WireType::WireTypeVarint) => id = is.read_uint64()?,
+                (2, WireType::WireTypeLengthDelimited) => {
+                    group_policy_address = is.read_string()?
+                }
+                (7, WireType::WireTypeVarint) => group_policy_version = is.read_uint64()?,
+                (8, WireType::WireTypeVarint) => status = is.read_uint64()?,
+                (11, WireType::WireTypeVarint) => executor_result = is.read_uint64()?,
+                (12, WireType::WireTypeLengthDelimited) => {
+                    messages.push(parse_any(&is.read_bytes()?)?)
+                }
+                _ => is.skip_field(wire_type)?,
+            }
+            Ok(())
+        })
+        .map_err(|err| {
+            warn!("failed to parse group proposal: {:?}", err);
+            EnclaveError::FailedToDeserialize
+        })?;
+
+        Ok(GroupProposal {
+            id,
+            group_policy_address: CanonicalAddr::from_human(&HumanAddr(group_policy_address))
+                .map_err(EnclaveError::from)?,
+            group_policy_version,
+            status,
+            executor_result,
+            messages: messages
+                .iter()
+                .map(|(type_url, value)| DirectSdkMsg::from_bytes(type_url, value))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Whether `MsgExec` executes the messages of the proposal: it was accepted, and they
+    /// haven't been executed yet
+    pub fn is_executable(&self) -> bool {
+        self.status == PROPOSAL_STATUS_ACCEPTED
+            && self.executor_result != PROPOSAL_EXECUTOR_RESULT_SUCCESS
+    }
+}
+
+/// Calls `on_field` with the number and wire type of every field of the message and a stream
+/// that is positioned at its value, which it must read or skip
+fn for_each_field(
+    bytes: &[u8],
+    mut on_field: impl FnMut(u32, WireType, &mut CodedInputStream) -> ProtobufResult<()>,
+) -> ProtobufResult<()> {
+    let mut is = CodedInputStream::from_bytes(bytes);
+    while !is.eof()? {
+        let (number, wire_type) = is.read_tag_unpack()?;
+        on_field(number, wire_type, &mut is)?;
+    }
+    Ok(())
+}
+
+/// The type URL and value of an `Any`
+fn parse_any(bytes: &[u8]) -> ProtobufResult<(String, Vec<u8>)> {
+    let mut type_url = String::new();
+    let mut value = vec![];
+    for_each_field(bytes, |number, wire_type, is| {
+        match (number, wire_type) {
+            (1, WireType::WireTypeLengthDelimited) => type_url = is.read_string()?,
+            (2, WireType::WireTypeLengthDelimited) => value = is.read_bytes()?,
+            _ => is.skip_field(wire_type)?,
+        }
+        Ok(())
+    })?;
+    Ok((type_url, value))
+}
+
+/// The address and version of a `GroupPolicyInfo`
+pub fn parse_group_policy(bytes: &[u8]) -> Result<(CanonicalAddr, u64), EnclaveError> {
+    let mut address = String::new();
+    let mut version = 0;
+
+    for_each_field(bytes, |number, wire_type, is| {
+        match (number, wire_type) {
+            (1, WireType::WireTypeLengthDelimited) => address = is.read_string()?,
+            (5, WireType::WireTypeVarint) => version = is.read_uint64()?,
+            _ => is.skip_field(wire_type)?,
+        }
+        Ok(())
+    })
+    .map_err(|err| {
+        warn!("failed to parse group policy: {:?}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    let address = CanonicalAddr::from_human(&HumanAddr(address)).map_err(EnclaveError::from)?;
+    Ok((address, version))
+}
+
+pub fn parse_group_exec(bytes: &[u8]) -> Result<DirectSdkMsg, EnclaveError> {
+    let mut proposal_id = 0;
+    let mut executor = String::new();
+
+    for_each_field(bytes, |number, wire_type, is| {
+        match (number, wire_type) {
+            (1, WireType::WireTypeVarint) => proposal_id = is.read_uint64()?,
+            (2, WireType::WireTypeLengthDelimited) => executor = is.read_string()?,
+            _ => is.skip_field(wire_type)?,
+        }
+        Ok(())
+    })
+    .map_err(|err| {
+        warn!("failed to parse MsgExec: {:?}", err);
+        EnclaveError::FailedToDeserialize
+    })?;
+
+    Ok(DirectSdkMsg::MsgGroupExec {
+        proposal_id,
+        executor: CanonicalAddr::from_human(&HumanAddr(executor)).map_err(EnclaveError::from)?,
+    })
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use protobuf::CodedOutputStream;
+
+    use cw_types_v010::encoding::Binary;
+
+    fn encode(write: impl FnOnce(&mut CodedOutputStream) -> ProtobufResult<()>) -> Vec<u8> {
+        let mut bytes = vec![];
+        {
+            let mut os = CodedOutputStream::vec(&mut bytes);
+            write(&mut os).unwrap();
+            os.flush().unwrap();
+        }
+        bytes
+    }
+
+    /// A `MsgExecuteContract` of the sender, as an `Any`
+    fn execute_any(sender: &CanonicalAddr) -> Vec<u8> {
+        let execute = encode(|os| {
+            os.write_bytes(1, sender.as_slice())?;
+            os.write_bytes(2, &[2u8; 20])?;
+            os.write_bytes(3, b"msg")
+        });
+        encode(|os| {
+            os.write_string(1, "/secret.compute.v1beta1.MsgExecuteContract")?;
+            os.write_bytes(2, &execute)
+        })
+    }
+
+    fn group_proposal(
+        id: u64,
+        group_policy: &CanonicalAddr,
+        status: u64,
+        executor_result: u64,
+        messages: &[Vec<u8>],
+    ) -> Vec<u8> {
+        encode(|os| {
+            os.write_uint64(1, id)?;
+            os.write_string(2, &HumanAddr::from_canonical(group_policy).unwrap().0)?;
+            os.write_string(3, "metadata")?;
+            os.write_uint64(7, 1)?;
+            os.write_uint64(8, status)?;
+            os.write_uint64(11, executor_result)?;
+            for message in messages {
+                os.write_bytes(12, message)?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn test_parse_group_proposals() {
+        let policy = CanonicalAddr(Binary(vec![3u8; 32]));
+        let executor = CanonicalAddr(Binary(vec![4u8; 20]));
+
+        let exec = encode(|os| {
+            os.write_uint64(1, 7)?;
+            os.write_string(2, &HumanAddr::from_canonical(&executor).unwrap().0)
+        });
+        let msg = DirectSdkMsg::from_bytes("/cosmos.group.v1.MsgExec", &exec).unwrap();
+        assert_eq!(
+            msg,
+            DirectSdkMsg::MsgGroupExec {
+                proposal_id: 7,
+                executor: executor.clone(),
+            }
+        );
+        // The executor signs `MsgExec`, but isn't the sender of the messages it executes
+        assert_eq!(msg.sender(), Some(&executor));
+        assert!(!msg.has_amino_encoding());
+
+        let proposal = group_proposal(
+            7,
+            &policy,
+            PROPOSAL_STATUS_ACCEPTED,
+            1,
+            &[execute_any(&policy)],
+        );
+        let proposal = GroupProposal::from_bytes(&proposal).unwrap();
+        assert_eq!(proposal.id, 7);
+        assert_eq!(proposal.group_policy_address, policy);
+        assert_eq!(proposal.group_policy_version, 1);
+        assert!(proposal.is_executable());
+        assert_eq!(proposal.messages.len(), 1);
+        assert_eq!(proposal.messages[0].sender(), Some(&policy));
+
+        // Proposals that weren't accepted, or whose messages were already executed
+        let submitted = group_proposal(7, &policy, 1, 1, &[]);
+        assert!(!GroupProposal::from_bytes(&submitted)
+            .unwrap()
+            .is_executable());
+        let executed = group_proposal(
+            7,
+            &policy,
+            PROPOSAL_STATUS_ACCEPTED,
+            PROPOSAL_EXECUTOR_RESULT_SUCCESS,
+            &[],
+        );
+        assert!(!GroupProposal::from_bytes(&executed)
+            .unwrap()
+            .is_executable());
+
+        let policy_info = encode(|os| {
+            os.write_string(1, &HumanAddr::from_canonical(&policy).unwrap().0)?;
+            os.write_uint64(2, 1)?;
+            os.write_uint64(5, 3)
+        });
+        assert_eq!(parse_group_policy(&policy_info).unwrap(), (policy, 3));
+
+        // A truncated proposal
+        let proposal = group_proposal(7, &policy, PROPOSAL_STATUS_ACCEPTED, 1, &[]);
+        assert!(GroupProposal::from_bytes(&proposal[..proposal.len() - 1]).is_err());
+    }
+}
@@ -1,7 +1,12 @@
 // Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
-#[cfg(not(target_env = "sgx"))]
+#[cfg(not(any(target_env = "sgx", target_arch = "wasm32")))]
 extern crate sgx_tstd as std;
 
+pub mod callback_sig;
+pub mod channel_upgrade;
+pub mod contract_metadata;
+pub mod feature_activation;
+pub mod group_proposals;
 pub mod multisig;
 pub mod single_address;
 pub mod traits;
@@ -9,7 +14,13 @@ pub mod types;
 
 #[cfg(feature = "test")]
 pub mod tests {
+    use crate::callback_sig;
+    use crate::channel_upgrade;
+    use crate::contract_metadata;
+    use crate::feature_activation;
+    use crate::group_proposals;
     use crate::multisig;
+    use crate::types;
 
     /// Catch failures like the standard test runner, and print similar information per test.
     /// Tests can only fail by panicking, not by returning a `Result` type.
@@ -33,6 +44,9 @@ pub mod tests {
         println!();
         let mut failures = 0;
 
+        // Local networks activate every feature
+        feature_activation::set_active_block("secretdev-1", 1);
+
         count_failures!(failures, {
             // kdf::tests::test_derive_key();
             // storage::tests::test_open();
@@ -43,6 +57,22 @@ pub mod tests {
             multisig::tests_decode_multisig_signature::test_decode_malformed_sig_only_prefix();
             multisig::tests_decode_multisig_signature::test_decode_sig_length_zero();
             multisig::tests_decode_multisig_signature::test_decode_malformed_sig_wrong_length();
+            contract_metadata::tests::test_parse_declared_features();
+            contract_metadata::tests::test_parse_declared_features_missing_or_malformed();
+            contract_metadata::tests::test_parse_required_capabilities();
+            contract_metadata::tests::test_split_code_signature();
+            feature_activation::tests::test_activation_heights();
+            feature_activation::tests::test_active_block();
+            feature_activation::tests::test_every_feature_is_scheduled();
+            callback_sig::tests::test_callback_signature();
+            types::tests_parse_funds::test_parse_funds_wide_amounts();
+            types::tests_amino_msgs::test_amino_msgs_with_omitted_fields();
+            types::tests_transfer_msgs::test_parse_transfer();
+            types::tests_transfer_msgs::test_parse_transfer_invalid_sender();
+            types::tests_public_keys::test_secp256r1_from_proto();
+            types::tests_tx_body::test_tx_body_oracle_proofs();
+            channel_upgrade::tests::test_parse_channel_upgrade();
+            group_proposals::tests::test_parse_group_proposals();
         });
 
         if failures != 0 {
@@ -1,4 +1,5 @@
 use enclave_crypto::secp256k1::{Secp256k1PubKey, SECP256K1_PREFIX};
+use enclave_crypto::secp256r1::Secp256r1PubKey;
 use log::warn;
 
 use super::traits::CosmosAminoPubkey;
@@ -45,3 +46,25 @@ impl CosmosAminoPubkey for Secp256k1PubKey {
         encoded
     }
 }
+
+/// The type of secp256r1 keys, which their addresses are derived with
+const SECP256R1_KEY_TYPE: &str = "secp256r1";
+
+impl CosmosAminoPubkey for Secp256r1PubKey {
+    fn get_address(&self) -> CanonicalAddr {
+        // Unlike secp256k1 keys, the sdk derives the addresses of these keys as in ADR-028:
+        // https://github.com/cosmos/cosmos-sdk/blob/main/docs/architecture/adr-028-public-key-addresses.md
+        let type_hash = sha_256(SECP256R1_KEY_TYPE.as_bytes());
+        let mut data = type_hash.to_vec();
+        data.extend_from_slice(&self.0);
+
+        CanonicalAddr::from_vec(sha_256(&data).to_vec())
+    }
+
+    fn amino_bytes(&self) -> Vec<u8> {
+        // secp256r1 keys are not registered with amino, so they can't be part of a legacy amino
+        // multisig, which is the only use of these bytes
+        warn!("secp256r1 public keys have no amino encoding");
+        vec![]
+    }
+}
@@ -5,10 +5,15 @@ use proto::tx::signing::SignMode;
 use protobuf::Message;
 use serde::{Deserialize, Serialize};
 
+use crate::channel_upgrade::{parse_channel_upgrade, ChannelUpgradeStep};
+use crate::contract_metadata::{parse_declared_features, parse_required_capabilities};
+use crate::feature_activation::{is_active, Feature};
+use crate::group_proposals::parse_group_exec;
 use crate::multisig::MultisigThresholdPubKey;
 
 use enclave_crypto::{
-    hash::sha::HASH_SIZE, secp256k1::Secp256k1PubKey, sha_256, traits::VerifyingKey, CryptoError,
+    hash::sha::HASH_SIZE, secp256k1::Secp256k1PubKey, secp256r1::Secp256r1PubKey, sha_256,
+    traits::VerifyingKey, CryptoError,
 };
 
 use cosmos_proto as proto;
@@ -16,7 +21,7 @@ use cosmos_proto as proto;
 use cw_types_v010::{
     coins::Coin,
     encoding::Binary,
-    math::Uint128,
+    math::{Uint128, Uint256},
     types::{CanonicalAddr, HumanAddr},
 };
 
@@ -29,12 +34,28 @@ pub fn calc_contract_hash(contract_bytes: &[u8]) -> [u8; HASH_SIZE] {
 pub struct ContractCode<'code> {
     code: &'code [u8],
     hash: [u8; HASH_SIZE],
+    declared_features: Option<Vec<String>>,
 }
 
 impl<'code> ContractCode<'code> {
     pub fn new(code: &'code [u8]) -> Self {
         let hash = calc_contract_hash(code);
-        Self { code, hash }
+        let declared_features = parse_declared_features(code);
+        Self {
+            code,
+            hash,
+            declared_features,
+        }
+    }
+
+    /// For code whose hash was already calculated, e.g. when it was pinned in the enclave
+    pub fn with_hash(code: &'code [u8], hash: [u8; HASH_SIZE]) -> Self {
+        let declared_features = parse_declared_features(code);
+        Self {
+            code,
+            hash,
+            declared_features,
+        }
     }
 
     pub fn code(&self) -> &[u8] {
@@ -44,23 +65,44 @@ impl<'code> ContractCode<'code> {
     pub fn hash(&self) -> [u8; HASH_SIZE] {
         self.hash
     }
+
+    /// The features the contract declared in its features section, if it has one
+    pub fn declared_features(&self) -> Option<&[String]> {
+        self.declared_features.as_deref()
+    }
+
+    /// The enclave capabilities the contract requires in its requirements section.
+    /// This is only needed when the code is instantiated or migrated to, so it's parsed on demand.
+    pub fn required_capabilities(&self) -> Result<Vec<String>, serde_json::Error> {
+        parse_required_capabilities(self.code)
+    }
 }
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum CosmosPubKey {
     Secp256k1(Secp256k1PubKey),
+    Secp256r1(Secp256r1PubKey),
     Multisig(MultisigThresholdPubKey),
 }
 
 /// `"/"` + `proto::crypto::multisig::LegacyAminoPubKey::descriptor_static().full_name()`
-const TYPE_URL_MULTISIG_LEGACY_AMINO_PUBKEY: &str = "/cosmos.crypto.multisig.LegacyAminoPubKey";
+pub const TYPE_URL_MULTISIG_LEGACY_AMINO_PUBKEY: &str = "/cosmos.crypto.multisig.LegacyAminoPubKey";
 /// `"/"` + `proto::crypto::secp256k1::PubKey::descriptor_static().full_name()`
-const TYPE_URL_SECP256K1_PUBKEY: &str = "/cosmos.crypto.secp256k1.PubKey";
+pub const TYPE_URL_SECP256K1_PUBKEY: &str = "/cosmos.crypto.secp256k1.PubKey";
+/// `"/"` + `proto::crypto::secp256r1::PubKey::descriptor_static().full_name()`
+pub const TYPE_URL_SECP256R1_PUBKEY: &str = "/cosmos.crypto.secp256r1.PubKey";
+/// The non-critical extension options of a transaction with this type carry the proofs of the
+/// chain state that contracts query, see `oracle_query.rs` in the contract engine
+pub const TYPE_URL_ORACLE_PROOF: &str = "/google.protobuf.BytesValue";
+pub const TYPE_URL_MSG_TRANSFER: &str = "/ibc.applications.transfer.v1.MsgTransfer";
 
 impl CosmosPubKey {
     pub fn from_proto(public_key: &protobuf::well_known_types::Any) -> Result<Self, CryptoError> {
         let public_key_parser = match public_key.type_url.as_str() {
             TYPE_URL_SECP256K1_PUBKEY => Self::secp256k1_from_proto,
+            TYPE_URL_SECP256R1_PUBKEY if is_active(Feature::Secp256r1Keys) => {
+                Self::secp256r1_from_proto
+            }
             TYPE_URL_MULTISIG_LEGACY_AMINO_PUBKEY => Self::multisig_legacy_amino_from_proto,
             _ => {
                 warn!("found public key of unsupported type: {:?}", public_key);
@@ -83,6 +125,18 @@ impl CosmosPubKey {
         Ok(CosmosPubKey::Secp256k1(Secp256k1PubKey::new(pub_key.key)))
     }
 
+    fn secp256r1_from_proto(public_key_bytes: &[u8]) -> Result<Self, CryptoError> {
+        use proto::crypto::secp256r1::PubKey;
+        let pub_key = PubKey::parse_from_bytes(public_key_bytes).map_err(|_err| {
+            warn!(
+                "Could not parse secp256r1 public key from these bytes: {}",
+                Binary(public_key_bytes.to_vec())
+            );
+            CryptoError::ParsingError
+        })?;
+        Ok(CosmosPubKey::Secp256r1(Secp256r1PubKey::new(pub_key.key)))
+    }
+
     fn multisig_legacy_amino_from_proto(public_key_bytes: &[u8]) -> Result<Self, CryptoError> {
         use proto::crypto::multisig::LegacyAminoPubKey;
         let multisig_key =
@@ -108,6 +162,7 @@ impl CosmosAminoPubkey for CosmosPubKey {
     fn get_address(&self) -> CanonicalAddr {
         match self {
             CosmosPubKey::Secp256k1(pubkey) => pubkey.get_address(),
+            CosmosPubKey::Secp256r1(pubkey) => pubkey.get_address(),
             CosmosPubKey::Multisig(pubkey) => pubkey.get_address(),
         }
     }
@@ -115,6 +170,7 @@ impl CosmosAminoPubkey for CosmosPubKey {
     fn amino_bytes(&self) -> Vec<u8> {
         match self {
             CosmosPubKey::Secp256k1(pubkey) => pubkey.amino_bytes(),
+            CosmosPubKey::Secp256r1(pubkey) => pubkey.amino_bytes(),
             CosmosPubKey::Multisig(pubkey) => pubkey.amino_bytes(),
         }
     }
@@ -129,6 +185,7 @@ impl VerifyingKey for CosmosPubKey {
     ) -> Result<(), CryptoError> {
         match self {
             CosmosPubKey::Secp256k1(pubkey) => pubkey.verify_bytes(bytes, sig, sign_mode),
+            CosmosPubKey::Secp256r1(pubkey) => pubkey.verify_bytes(bytes, sig, sign_mode),
             CosmosPubKey::Multisig(pubkey) => pubkey.verify_bytes(bytes, sig, sign_mode),
         }
     }
@@ -162,11 +219,16 @@ pub enum HandleType {
     HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER = 8,
     HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK = 9,
     HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT = 10,
+    HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER = 11,
+    HANDLE_TYPE_IBC_CHANNEL_UPGRADE_INIT = 12,
+    HANDLE_TYPE_IBC_CHANNEL_UPGRADE_TRY = 13,
+    HANDLE_TYPE_IBC_CHANNEL_UPGRADE_ACK = 14,
+    HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM = 15,
 }
 
 impl HandleType {
     pub fn try_from(value: u8) -> Result<Self, EnclaveError> {
-        match value {
+        let handle_type = match value {
             0 => Ok(HandleType::HANDLE_TYPE_EXECUTE),
             1 => Ok(HandleType::HANDLE_TYPE_REPLY),
             2 => Ok(HandleType::HANDLE_TYPE_IBC_CHANNEL_OPEN),
@@ -178,26 +240,45 @@ impl HandleType {
             8 => Ok(HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER),
             9 => Ok(HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK),
             10 => Ok(HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT),
+            11 => Ok(HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER),
+            12 => Ok(HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_INIT),
+            13 => Ok(HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_TRY),
+            14 => Ok(HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_ACK),
+            15 => Ok(HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM),
             _ => {
                 error!("unrecognized handle type: {}", value);
                 Err(EnclaveError::FailedToDeserialize)
             }
+        }?;
+
+        match handle_type.activation_feature() {
+            Some(feature) if !is_active(feature) => {
+                error!("handle type {:?} is not active yet", handle_type);
+                Err(EnclaveError::FailedToDeserialize)
+            }
+            _ => Ok(handle_type),
         }
     }
 
-    pub fn get_export_name(h: &HandleType) -> &'static str {
-        match h {
-            HandleType::HANDLE_TYPE_EXECUTE => "execute",
-            HandleType::HANDLE_TYPE_REPLY => "reply",
-            HandleType::HANDLE_TYPE_IBC_CHANNEL_OPEN => "ibc_channel_open",
-            HandleType::HANDLE_TYPE_IBC_CHANNEL_CONNECT => "ibc_channel_connect",
-            HandleType::HANDLE_TYPE_IBC_CHANNEL_CLOSE => "ibc_channel_close",
-            HandleType::HANDLE_TYPE_IBC_PACKET_RECEIVE => "ibc_packet_receive",
-            HandleType::HANDLE_TYPE_IBC_PACKET_ACK => "ibc_packet_ack",
-            HandleType::HANDLE_TYPE_IBC_PACKET_TIMEOUT => "ibc_packet_timeout",
-            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER => "execute",
-            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK => "sudo",
-            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT => "sudo",
+    /// The feature that introduced the handle type, for handle types that weren't there from
+    /// the start
+    pub fn activation_feature(&self) -> Option<Feature> {
+        match self {
+            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_TRANSFER
+            | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_ACK
+            | HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_OUTGOING_TRANSFER_TIMEOUT => {
+                Some(Feature::IbcHooksHandleTypes)
+            }
+            HandleType::HANDLE_TYPE_IBC_WASM_HOOKS_INCOMING_NFT_TRANSFER => {
+                Some(Feature::Ics721HandleType)
+            }
+            HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_INIT
+            | HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_TRY
+            | HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_ACK
+            | HandleType::HANDLE_TYPE_IBC_CHANNEL_UPGRADE_CONFIRM => {
+                Some(Feature::IbcChannelUpgradeHandleTypes)
+            }
+            _ => None,
         }
     }
 }
@@ -223,6 +304,20 @@ pub struct SigInfo {
     pub public_key: Binary,
     pub signature: Binary,
     pub callback_sig: Option<Binary>,
+    /// The signatures of the signers of the transaction other than the sender, in the order of
+    /// the signer infos of the transaction
+    #[serde(default)]
+    pub other_signers: Vec<SignerSigInfo>,
+}
+
+/// What's needed to verify the signature of one signer of a transaction
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct SignerSigInfo {
+    pub sign_bytes: Binary,
+    #[serde(with = "SignModeDef")]
+    pub sign_mode: proto::tx::signing::SignMode,
+    pub public_key: Binary,
+    pub signature: Binary,
 }
 
 // Should be in sync with https://github.com/cosmos/cosmos-sdk/blob/v0.38.3/x/auth/types/stdtx.go#L216
@@ -230,11 +325,26 @@ pub struct SigInfo {
 pub struct StdSignDoc {
     pub account_number: String,
     pub chain_id: String,
+    #[serde(default)]
+    pub fee: StdFee,
     pub memo: String,
     pub msgs: Vec<AminoSdkMsg>,
     pub sequence: String,
 }
 
+// Should be in sync with https://github.com/cosmos/cosmos-sdk/blob/v0.47.0/x/auth/migrations/legacytx/stdtx.go#L26
+#[derive(Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct StdFee {
+    #[serde(default)]
+    pub amount: Vec<Coin>,
+    #[serde(default)]
+    pub gas: String,
+    #[serde(default)]
+    pub payer: Option<HumanAddr>,
+    #[serde(default)]
+    pub granter: Option<HumanAddr>,
+}
+
 #[derive(Debug)]
 pub struct SignDoc {
     pub body: TxBody,
@@ -269,9 +379,10 @@ impl SignDoc {
 #[derive(Debug)]
 pub struct TxBody {
     pub messages: Vec<DirectSdkMsg>,
-    // Leaving this here for discoverability. We can use this, but don't verify it today.
-    #[allow(dead_code)]
-    memo: (),
+    pub memo: String,
+    /// The JSON encoded proofs of the non-critical extension options of type
+    /// `TYPE_URL_ORACLE_PROOF`
+    pub oracle_proofs: Vec<Binary>,
     #[allow(dead_code)]
     timeout_height: (),
 }
@@ -293,9 +404,20 @@ impl TxBody {
             .map(|any| DirectSdkMsg::from_bytes(&any.type_url, &any.value))
             .collect::<Result<Vec<_>, _>>()?;
 
+        let oracle_proofs = tx_body
+            .non_critical_extension_options
+            .iter()
+            .filter(|any| any.type_url == TYPE_URL_ORACLE_PROOF)
+            .filter_map(|any| {
+                protobuf::well_known_types::BytesValue::parse_from_bytes(&any.value).ok()
+            })
+            .map(|proof| Binary(proof.value))
+            .collect();
+
         Ok(TxBody {
             messages,
-            memo: (),
+            memo: tx_body.memo,
+            oracle_proofs,
             timeout_height: (),
         })
     }
@@ -310,6 +432,8 @@ pub enum AminoSdkMsg {
         contract: HumanAddr,
         /// msg is the json-encoded HandleMsg struct (as raw Binary)
         msg: String,
+        /// Amino JSON omits empty fields, which some wallets follow for the funds
+        #[serde(default)]
         sent_funds: Vec<Coin>,
     },
     #[serde(alias = "wasm/MsgInstantiateContract")]
@@ -317,6 +441,7 @@ pub enum AminoSdkMsg {
         sender: HumanAddr,
         code_id: String,
         init_msg: String,
+        #[serde(default)]
         init_funds: Vec<Coin>,
         label: String,
         #[serde(default)]
@@ -362,7 +487,7 @@ impl AminoSdkMsg {
             } => {
                 let sender = CanonicalAddr::from_human(&sender).map_err(|err| {
                     warn!("failed to turn human addr to canonical addr when parsing DirectSdkMsg: {:?}", err);
-                    EnclaveError::FailedToDeserialize
+                    EnclaveError::from(err)
                 })?;
                 let msg = Binary::from_base64(&msg).map_err(|err| {
                     warn!(
@@ -395,7 +520,7 @@ impl AminoSdkMsg {
             } => {
                 let sender = CanonicalAddr::from_human(&sender).map_err(|err| {
                     warn!("failed to turn human addr to canonical addr when parsing DirectSdkMsg: {:?}", err);
-                    EnclaveError::FailedToDeserialize
+                    EnclaveError::from(err)
                 })?;
                 let msg = Binary::from_base64(&msg).map_err(|err| {
                     warn!(
@@ -423,7 +548,7 @@ impl AminoSdkMsg {
             } => {
                 let sender = CanonicalAddr::from_human(&sender).map_err(|err| {
                     warn!("failed to turn human addr to canonical addr when parsing DirectSdkMsg: {:?}", err);
-                    EnclaveError::FailedToDeserialize
+                    EnclaveError::from(err)
                 })?;
                 let init_msg = Binary::from_base64(&init_msg).map_err(|err| {
                     warn!(
@@ -457,7 +582,7 @@ impl AminoSdkMsg {
             } => {
                 let sender = CanonicalAddr::from_human(&sender).map_err(|err| {
                     warn!("failed to turn human addr to canonical addr when parsing DirectSdkMsg: {:?}", err);
-                    EnclaveError::FailedToDeserialize
+                    EnclaveError::from(err)
                 })?;
 
                 Ok(DirectSdkMsg::MsgUpdateAdmin {
@@ -469,7 +594,7 @@ impl AminoSdkMsg {
             AminoSdkMsg::MsgClearAdmin { sender, contract } => {
                 let sender = CanonicalAddr::from_human(&sender).map_err(|err| {
                     warn!("failed to turn human addr to canonical addr when parsing DirectSdkMsg: {:?}", err);
-                    EnclaveError::FailedToDeserialize
+                    EnclaveError::from(err)
                 })?;
 
                 Ok(DirectSdkMsg::MsgClearAdmin { sender, contract })
@@ -483,12 +608,48 @@ impl AminoSdkMsg {
 
 pub struct FungibleTokenPacketData {
     pub denom: String,
-    pub amount: Uint128,
+    pub amount: Uint256,
+    pub sender: HumanAddr,
+    pub receiver: HumanAddr,
+    pub memo: Option<String>,
+}
+
+/// The packet data of ICS-721 NFT transfers (https://github.com/cosmos/ibc/tree/main/spec/app/ics-721-nft-transfer)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NonFungibleTokenPacketData {
+    pub class_id: String,
+    #[serde(default)]
+    pub class_uri: Option<String>,
+    #[serde(default)]
+    pub class_data: Option<String>,
+    pub token_ids: Vec<String>,
+    #[serde(default)]
+    pub token_uris: Option<Vec<String>>,
+    #[serde(default)]
+    pub token_data: Option<Vec<String>>,
     pub sender: HumanAddr,
     pub receiver: HumanAddr,
+    #[serde(default)]
     pub memo: Option<String>,
 }
 
+/// The message that contracts are executed with when they receive NFTs through ibc-hooks.
+/// Unlike received funds, which are passed in the env, the received NFTs are described in the
+/// message, next to the message in the memo of the packet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcHooksIncomingNftTransferMsg {
+    ReceiveIbcNfts {
+        /// The local class ID of the NFTs
+        class_id: String,
+        token_ids: Vec<String>,
+        /// The sender of the NFTs on the other chain
+        sender: HumanAddr,
+        msg: serde_json::Value,
+    },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct IbcHooksIncomingTransferMsg {
     pub wasm: IbcHooksIncomingTransferWasmMsg,
@@ -512,6 +673,14 @@ pub struct Height {
     pub revision_height: u64,
 }
 
+impl Height {
+    /// Heights are ordered by revision number first, like in ibc-go
+    pub fn is_at_least(&self, other: &Height) -> bool {
+        (self.revision_number, self.revision_height)
+            >= (other.revision_number, other.revision_height)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum IBCLifecycleComplete {
@@ -664,6 +833,16 @@ pub enum DirectSdkMsg {
         contract: HumanAddr,
     },
     // IBC:
+    /// A step of the channel upgrade handshake of ibc-go v8, see `channel_upgrade`
+    MsgChannelUpgrade {
+        step: ChannelUpgradeStep,
+        port_id: String,
+        channel_id: String,
+        /// The version that the channel is upgraded to: the proposed one in `Init`, and the
+        /// counterparty's in the other steps
+        version: String,
+        signer: String,
+    },
     // MsgChannelOpenInit {}, // TODO
     // MsgChannelOpenTry {}, // TODO
     // MsgChannelOpenAck {}, // TODO
@@ -679,6 +858,11 @@ pub enum DirectSdkMsg {
     },
     MsgTimeout {
         packet: Packet,
+        /// The height of the counterparty chain after which the packet times out, if any
+        timeout_height: Option<Height>,
+        /// The time of the counterparty chain after which the packet times out, in nanoseconds
+        /// since the UNIX epoch. Zero means there is no timeout by time.
+        timeout_timestamp: u64,
         proof_unreceived: Vec<u8>,
         proof_height: Option<Height>,
         next_sequence_recv: u64,
@@ -690,6 +874,25 @@ pub enum DirectSdkMsg {
         proof_height: Option<Height>,
         signer: String,
     },
+    // ICS-20:
+    MsgTransfer {
+        sender: CanonicalAddr,
+        source_port: String,
+        source_channel: String,
+        token: Coin,
+        receiver: String,
+        /// The height of the counterparty chain after which the transfer times out, if any
+        timeout_height: Option<Height>,
+        /// In nanoseconds since the UNIX epoch. Zero means there is no timeout by time.
+        timeout_timestamp: u64,
+        memo: String,
+    },
+    // Groups:
+    /// Executes the messages of an accepted group proposal, see `group_proposals`
+    MsgGroupExec {
+        proposal_id: u64,
+        executor: CanonicalAddr,
+    },
     // All else:
     Other,
 }
@@ -705,7 +908,17 @@ impl DirectSdkMsg {
             "/ibc.core.channel.v1.MsgRecvPacket" => Self::try_parse_ibc_recv_packet(bytes),
             "/ibc.core.channel.v1.MsgAcknowledgement" => Self::try_parse_ibc_ack(bytes),
             "/ibc.core.channel.v1.MsgTimeout" => Self::try_parse_ibc_timeout(bytes),
-            _ => Ok(DirectSdkMsg::Other),
+            // Transfers don't execute contracts, so one that can't be parsed is left to the SDK
+            // to reject, rather than failing the parsing of the whole transaction
+            TYPE_URL_MSG_TRANSFER => Ok(Self::try_parse_transfer(bytes).unwrap_or_else(|err| {
+                warn!("skipping a transfer that can't be parsed: {:?}", err);
+                DirectSdkMsg::Other
+            })),
+            "/cosmos.group.v1.MsgExec" => parse_group_exec(bytes),
+            _ => match ChannelUpgradeStep::from_type_url(type_url) {
+                Some(step) => parse_channel_upgrade(step, bytes),
+                None => Ok(DirectSdkMsg::Other),
+            },
         }
     }
 
@@ -778,6 +991,16 @@ impl DirectSdkMsg {
                     destination_channel: packet.destination_channel,
                     data: packet.data,
                 },
+                // A zero height means there is no timeout by height
+                timeout_height: packet
+                    .timeout_height
+                    .into_option()
+                    .filter(|height| height.revision_number != 0 || height.revision_height != 0)
+                    .map(|height| Height {
+                        revision_number: height.revision_number,
+                        revision_height: height.revision_height,
+                    }),
+                timeout_timestamp: packet.timeout_timestamp,
                 next_sequence_recv: raw_msg.next_sequence_recv,
                 proof_unreceived: raw_msg.proof_unreceived,
                 proof_height: raw_msg.proof_height.into_option().map(|height| Height {
@@ -816,6 +1039,47 @@ impl DirectSdkMsg {
         }
     }
 
+    fn try_parse_transfer(bytes: &[u8]) -> Result<Self, EnclaveError> {
+        use proto::ibc::transfer::tx::MsgTransfer;
+
+        let raw_msg =
+            MsgTransfer::parse_from_bytes(bytes).map_err(|_| EnclaveError::FailedToDeserialize)?;
+
+        trace!(
+            "try_parse_transfer sender: len={} val={:?}",
+            raw_msg.sender.len(),
+            raw_msg.sender
+        );
+
+        let sender =
+            CanonicalAddr::from_human(&HumanAddr(raw_msg.sender)).map_err(EnclaveError::from)?;
+
+        let token = raw_msg
+            .token
+            .into_option()
+            .ok_or(EnclaveError::FailedToDeserialize)?;
+        let token = Self::parse_funds(vec![token].into())?.remove(0);
+
+        Ok(DirectSdkMsg::MsgTransfer {
+            sender,
+            source_port: raw_msg.source_port,
+            source_channel: raw_msg.source_channel,
+            token,
+            receiver: raw_msg.receiver,
+            // A zero height means there is no timeout by height
+            timeout_height: raw_msg
+                .timeout_height
+                .into_option()
+                .filter(|height| height.revision_number != 0 || height.revision_height != 0)
+                .map(|height| Height {
+                    revision_number: height.revision_number,
+                    revision_height: height.revision_height,
+                }),
+            timeout_timestamp: raw_msg.timeout_timestamp,
+            memo: raw_msg.memo,
+        })
+    }
+
     fn try_parse_migrate(bytes: &[u8]) -> Result<Self, EnclaveError> {
         use proto::cosmwasm::msg::MsgMigrateContract;
 
@@ -828,8 +1092,8 @@ impl DirectSdkMsg {
             raw_msg.sender
         );
 
-        let sender = CanonicalAddr::from_human(&HumanAddr(raw_msg.sender))
-            .map_err(|_| EnclaveError::FailedToDeserialize)?;
+        let sender =
+            CanonicalAddr::from_human(&HumanAddr(raw_msg.sender)).map_err(EnclaveError::from)?;
 
         Ok(DirectSdkMsg::MsgMigrateContract {
             sender,
@@ -849,8 +1113,8 @@ impl DirectSdkMsg {
             raw_msg.sender
         );
 
-        let sender = CanonicalAddr::from_human(&HumanAddr(raw_msg.sender))
-            .map_err(|_| EnclaveError::FailedToDeserialize)?;
+        let sender =
+            CanonicalAddr::from_human(&HumanAddr(raw_msg.sender)).map_err(EnclaveError::from)?;
 
         let new_admin = HumanAddr(raw_msg.new_admin);
 
@@ -872,7 +1136,7 @@ impl DirectSdkMsg {
         );
 
         let sender = CanonicalAddr::from_human(&HumanAddr(raw_update_msg.sender))
-            .map_err(|_| EnclaveError::FailedToDeserialize)?;
+            .map_err(EnclaveError::from)?;
 
         Ok(DirectSdkMsg::MsgClearAdmin {
             sender,
@@ -942,20 +1206,29 @@ impl DirectSdkMsg {
         })
     }
 
+    /// Amounts are parsed as 256 bit integers like in the SDK, so that coins with many decimals
+    /// don't fail to parse
     fn parse_funds(
         raw_init_funds: protobuf::RepeatedField<proto::base::coin::Coin>,
     ) -> Result<Vec<Coin>, EnclaveError> {
         let mut init_funds = Vec::with_capacity(raw_init_funds.len());
         for raw_coin in raw_init_funds {
-            let amount: u128 = raw_coin.amount.parse().map_err(|_err| {
+            let amount: Uint256 = raw_coin.amount.parse().map_err(|_err| {
                 warn!(
                     "instantiate message funds were not a numeric string: {:?}",
                     raw_coin.amount,
                 );
                 EnclaveError::FailedToDeserialize
             })?;
+            if !is_active(Feature::WideCoinAmounts) && amount.to_u128().is_none() {
+                warn!(
+                    "funds amount doesn't fit in 128 bits: {:?}",
+                    raw_coin.amount
+                );
+                return Err(EnclaveError::FailedToDeserialize);
+            }
             let coin = Coin {
-                amount: Uint128(amount),
+                amount,
                 denom: raw_coin.denom,
             };
             init_funds.push(coin);
@@ -970,21 +1243,38 @@ impl DirectSdkMsg {
             | DirectSdkMsg::MsgInstantiateContract { sender, .. }
             | DirectSdkMsg::MsgMigrateContract { sender, .. }
             | DirectSdkMsg::MsgUpdateAdmin { sender, .. }
-            | DirectSdkMsg::MsgClearAdmin { sender, .. } => Some(sender),
+            | DirectSdkMsg::MsgClearAdmin { sender, .. }
+            | DirectSdkMsg::MsgTransfer { sender, .. } => Some(sender),
+            // The executor of a proposal, not the group policy that sends its messages
+            DirectSdkMsg::MsgGroupExec { executor, .. } => Some(executor),
             DirectSdkMsg::MsgRecvPacket { .. } => None,
             DirectSdkMsg::MsgAcknowledgement { .. } => None,
             DirectSdkMsg::MsgTimeout { .. } => None,
+            DirectSdkMsg::MsgChannelUpgrade { .. } => None,
             DirectSdkMsg::Other => None,
         }
     }
+
+    /// Whether the message is parsed from Amino JSON sign docs. Messages that aren't, like the
+    /// core IBC messages and ICS-20 transfers, are parsed as `Other` from Amino sign docs.
+    pub fn has_amino_encoding(&self) -> bool {
+        !matches!(
+            self,
+            DirectSdkMsg::MsgRecvPacket { .. }
+                | DirectSdkMsg::MsgAcknowledgement { .. }
+                | DirectSdkMsg::MsgTimeout { .. }
+                | DirectSdkMsg::MsgChannelUpgrade { .. }
+                | DirectSdkMsg::MsgTransfer { .. }
+                | DirectSdkMsg::MsgGroupExec { .. }
+                | DirectSdkMsg::Other
+        )
+    }
 }
 
 #[derive(Debug)]
 pub struct AuthInfo {
     pub signer_infos: Vec<SignerInfo>,
-    // Leaving this here for discoverability. We can use this, but don't verify it today.
-    #[allow(dead_code)]
-    fee: (),
+    pub fee: Fee,
 }
 
 impl AuthInfo {
@@ -1005,10 +1295,9 @@ impl AuthInfo {
             return Err(EnclaveError::FailedToDeserialize);
         }
 
-        Ok(Self {
-            signer_infos,
-            fee: (),
-        })
+        let fee = Fee::from_proto(raw_auth_info.fee.unwrap_or_default())?;
+
+        Ok(Self { signer_infos, fee })
     }
 
     pub fn sender_public_key(&self, sender: &CanonicalAddr) -> Option<&CosmosPubKey> {
@@ -1017,6 +1306,67 @@ impl AuthInfo {
             .find(|signer_info| &signer_info.public_key.get_address() == sender)
             .map(|si| &si.public_key)
     }
+
+    /// The account that pays the fee: the payer set in the fee, which must be one of the
+    /// signers, or the first signer otherwise
+    pub fn fee_payer(&self) -> Result<CanonicalAddr, EnclaveError> {
+        let first_signer = self.signer_infos[0].public_key.get_address();
+
+        match &self.fee.payer {
+            Some(payer) => {
+                if self.sender_public_key(payer).is_none() {
+                    warn!("The fee payer of the transaction is not one of its signers");
+                    return Err(EnclaveError::FailedTxVerification);
+                }
+                Ok(payer.clone())
+            }
+            None => Ok(first_signer),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Fee {
+    pub amount: Vec<Coin>,
+    pub gas_limit: u64,
+    /// The account that pays the fee, if it isn't the first signer
+    pub payer: Option<CanonicalAddr>,
+    /// The account whose fee allowance pays the fee, if any
+    pub granter: Option<CanonicalAddr>,
+}
+
+impl Fee {
+    pub fn from_proto(raw_fee: proto::tx::tx::Fee) -> Result<Self, EnclaveError> {
+        let amount = DirectSdkMsg::parse_funds(raw_fee.amount)?;
+        let payer = Self::parse_optional_address(&raw_fee.payer, "payer")?;
+        let granter = Self::parse_optional_address(&raw_fee.granter, "granter")?;
+
+        Ok(Self {
+            amount,
+            gas_limit: raw_fee.gas_limit,
+            payer,
+            granter,
+        })
+    }
+
+    fn parse_optional_address(
+        address: &str,
+        field: &str,
+    ) -> Result<Option<CanonicalAddr>, EnclaveError> {
+        if address.is_empty() {
+            return Ok(None);
+        }
+
+        CanonicalAddr::from_human(&HumanAddr(address.to_string()))
+            .map(Some)
+            .map_err(|err| {
+                warn!(
+                    "Fee {} {:?} is not a valid address: {:?}",
+                    field, address, err
+                );
+                EnclaveError::from(err)
+            })
+    }
 }
 
 #[derive(Debug)]
@@ -1045,3 +1395,247 @@ impl SignerInfo {
         Ok(signer_info)
     }
 }
+
+#[cfg(feature = "test")]
+pub mod tests_parse_funds {
+    use super::*;
+
+    fn raw_coins(amounts: &[&str]) -> protobuf::RepeatedField<proto::base::coin::Coin> {
+        amounts
+            .iter()
+            .map(|amount| proto::base::coin::Coin {
+                denom: "aevmos".to_string(),
+                amount: amount.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    pub fn test_parse_funds_wide_amounts() {
+        // 2^128 and 2^256 - 1, which tokens with 18 decimals can reach
+        let above_u128 = "340282366920938463463374607431768211456";
+        let max_u256 =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+        let funds = DirectSdkMsg::parse_funds(raw_coins(&["1", above_u128, max_u256])).unwrap();
+        assert_eq!(funds.len(), 3);
+        assert_eq!(funds[0].amount, Uint256::from(1u128));
+        assert_eq!(funds[1].amount.to_string(), above_u128);
+        assert_eq!(funds[1].amount.to_u128(), None);
+        assert_eq!(funds[2].amount.to_string(), max_u256);
+        assert!(funds[2].amount > funds[1].amount);
+
+        let serialized = serde_json::to_string(&funds[1]).unwrap();
+        assert_eq!(
+            serialized,
+            format!(r#"{{"denom":"aevmos","amount":"{}"}}"#, above_u128)
+        );
+        assert_eq!(serde_json::from_str::<Coin>(&serialized).unwrap(), funds[1]);
+
+        let above_u256 =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        assert!(DirectSdkMsg::parse_funds(raw_coins(&[above_u256])).is_err());
+        assert!(DirectSdkMsg::parse_funds(raw_coins(&["-1"])).is_err());
+        assert!(DirectSdkMsg::parse_funds(raw_coins(&["1.5"])).is_err());
+        assert!(DirectSdkMsg::parse_funds(raw_coins(&[""])).is_err());
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests_amino_msgs {
+    use super::*;
+
+    pub fn test_amino_msgs_with_omitted_fields() {
+        let sign_doc: StdSignDoc = serde_json::from_str(
+            r#"{"account_number":"1","chain_id":"secret-4","fee":{"amount":[],"gas":"200000"},"memo":"","msgs":[
+                {"type":"wasm/MsgExecuteContract","value":{"sender":"secret1sender","contract":"secret1contract","msg":"e30="}},
+                {"type":"wasm/MsgInstantiateContract","value":{"sender":"secret1sender","code_id":"1","init_msg":"e30=","label":"label"}},
+                {"type":"cosmos-sdk/MsgTransfer","value":{"source_port":"transfer","memo":"{\"wasm\":{}}"}}
+            ],"sequence":"0"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            sign_doc.msgs,
+            vec![
+                AminoSdkMsg::Execute {
+                    sender: HumanAddr("secret1sender".to_string()),
+                    contract: HumanAddr("secret1contract".to_string()),
+                    msg: "e30=".to_string(),
+                    sent_funds: vec![],
+                },
+                AminoSdkMsg::Instantiate {
+                    sender: HumanAddr("secret1sender".to_string()),
+                    code_id: "1".to_string(),
+                    init_msg: "e30=".to_string(),
+                    init_funds: vec![],
+                    label: "label".to_string(),
+                    admin: HumanAddr::default(),
+                },
+                AminoSdkMsg::Other,
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests_transfer_msgs {
+    use super::*;
+
+    pub fn test_parse_transfer() {
+        let sender = "secret1k0jntykt7e4g3y88ltc60czgjuqdy4c9e8fzek";
+
+        let mut raw_msg = proto::ibc::transfer::tx::MsgTransfer::new();
+        raw_msg.set_source_port("transfer".to_string());
+        raw_msg.set_source_channel("channel-0".to_string());
+        raw_msg.mut_token().set_denom("uscrt".to_string());
+        raw_msg.mut_token().set_amount("100".to_string());
+        raw_msg.set_sender(sender.to_string());
+        raw_msg.set_receiver("cosmos1receiver".to_string());
+        raw_msg.set_timeout_timestamp(1);
+        raw_msg.set_memo(r#"{"ibc_callback":"secret1contract"}"#.to_string());
+        let bytes = raw_msg.write_to_bytes().unwrap();
+
+        let msg = DirectSdkMsg::from_bytes(TYPE_URL_MSG_TRANSFER, &bytes).unwrap();
+        assert_eq!(
+            msg,
+            DirectSdkMsg::MsgTransfer {
+                sender: CanonicalAddr::from_human(&HumanAddr(sender.to_string())).unwrap(),
+                source_port: "transfer".to_string(),
+                source_channel: "channel-0".to_string(),
+                token: Coin {
+                    denom: "uscrt".to_string(),
+                    amount: Uint256::from(100u128),
+                },
+                receiver: "cosmos1receiver".to_string(),
+                timeout_height: None,
+                timeout_timestamp: 1,
+                memo: r#"{"ibc_callback":"secret1contract"}"#.to_string(),
+            }
+        );
+        assert!(msg.sender().is_some());
+        assert!(!msg.has_amino_encoding());
+
+        // A transfer that can't be parsed doesn't fail the parsing of the transaction
+        raw_msg.clear_token();
+        let bytes = raw_msg.write_to_bytes().unwrap();
+        assert!(DirectSdkMsg::try_parse_transfer(&bytes).is_err());
+        assert_eq!(
+            DirectSdkMsg::from_bytes(TYPE_URL_MSG_TRANSFER, &bytes).unwrap(),
+            DirectSdkMsg::Other
+        );
+        assert_eq!(
+            DirectSdkMsg::from_bytes(TYPE_URL_MSG_TRANSFER, b"not a transfer").unwrap(),
+            DirectSdkMsg::Other
+        );
+    }
+
+    pub fn test_parse_transfer_invalid_sender() {
+        let parse = |sender: &str| {
+            let mut raw_msg = proto::ibc::transfer::tx::MsgTransfer::new();
+            raw_msg.mut_token().set_denom("uscrt".to_string());
+            raw_msg.mut_token().set_amount("100".to_string());
+            raw_msg.set_sender(sender.to_string());
+            let bytes = raw_msg.write_to_bytes().unwrap();
+            DirectSdkMsg::try_parse_transfer(&bytes)
+        };
+
+        assert!(parse("secret1k0jntykt7e4g3y88ltc60czgjuqdy4c9e8fzek").is_ok());
+        assert!(matches!(
+            parse("cosmos1k0jntykt7e4g3y88ltc60czgjuqdy4c9mzaty2"),
+            Err(EnclaveError::InvalidAddressPrefix)
+        ));
+        // The last character has a typo
+        assert!(matches!(
+            parse("secret1k0jntykt7e4g3y88ltc60czgjuqdy4c9e8fzel"),
+            Err(EnclaveError::InvalidAddressChecksum)
+        ));
+        // An address with no bytes, and a string longer than bech32 allows
+        assert!(matches!(
+            parse("secret1zajwe8"),
+            Err(EnclaveError::InvalidAddressLength)
+        ));
+        assert!(matches!(
+            parse(&format!("secret1{}", "q".repeat(100))),
+            Err(EnclaveError::InvalidAddressLength)
+        ));
+        assert!(matches!(
+            parse("secretk0jntykt7e4g3y88ltc60czgjuqdy4c9e8fzek"),
+            Err(EnclaveError::FailedToDeserialize)
+        ));
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests_public_keys {
+    use super::*;
+
+    pub fn test_secp256r1_from_proto() {
+        let mut key = vec![2u8];
+        key.extend_from_slice(&[7u8; 32]);
+
+        let mut raw_key = proto::crypto::secp256r1::PubKey::new();
+        raw_key.set_key(key.clone());
+        let mut any = protobuf::well_known_types::Any::new();
+        any.set_type_url(TYPE_URL_SECP256R1_PUBKEY.to_string());
+        any.set_value(raw_key.write_to_bytes().unwrap());
+
+        let public_key = CosmosPubKey::from_proto(&any).unwrap();
+        assert_eq!(
+            public_key,
+            CosmosPubKey::Secp256r1(Secp256r1PubKey::new(key))
+        );
+        // sha256(sha256("secp256r1") || key), i.e. `address.Hash("secp256r1", key)` of the sdk
+        assert_eq!(
+            public_key.get_address().as_slice(),
+            &[
+                0x8b, 0xfb, 0x56, 0x60, 0x8e, 0x29, 0x91, 0xc6, 0xeb, 0x8c, 0xe2, 0x17, 0x01, 0x33,
+                0x73, 0x64, 0xb8, 0x8b, 0x85, 0x29, 0x93, 0x9d, 0xc2, 0x52, 0xfe, 0x2a, 0xa8, 0xfc,
+                0xaa, 0x28, 0x2f, 0x41
+            ]
+        );
+    }
+}
+
+#[cfg(feature = "test")]
+pub mod tests_tx_body {
+    use super::*;
+
+    fn extension_option(type_url: &str, value: Vec<u8>) -> protobuf::well_known_types::Any {
+        let mut any = protobuf::well_known_types::Any::new();
+        any.set_type_url(type_url.to_string());
+        any.set_value(value);
+        any
+    }
+
+    pub fn test_tx_body_oracle_proofs() {
+        let mut proof = protobuf::well_known_types::BytesValue::new();
+        proof.set_value(br#"{"header":""}"#.to_vec());
+
+        let mut raw_body = proto::tx::tx::TxBody::new();
+        raw_body.set_memo("memo".to_string());
+        raw_body
+            .mut_non_critical_extension_options()
+            .push(extension_option(
+                TYPE_URL_ORACLE_PROOF,
+                proof.write_to_bytes().unwrap(),
+            ));
+        // Options of other types, and values that aren't bytes, aren't proofs
+        raw_body
+            .mut_non_critical_extension_options()
+            .push(extension_option(
+                "/other.Option",
+                proof.write_to_bytes().unwrap(),
+            ));
+        raw_body
+            .mut_non_critical_extension_options()
+            .push(extension_option(TYPE_URL_ORACLE_PROOF, vec![0xff]));
+
+        let body = TxBody::from_bytes(&raw_body.write_to_bytes().unwrap()).unwrap();
+        assert_eq!(body.memo, "memo");
+        assert_eq!(
+            body.oracle_proofs,
+            vec![Binary(br#"{"header":""}"#.to_vec())]
+        );
+    }
+}
@@ -1,7 +1,6 @@
 use log::warn;
 use serde::{Deserialize, Serialize};
 
-#[cfg(feature = "random")]
 use cw_types_v010::encoding::Binary;
 
 use cw_types_v010::types as v010types;
@@ -25,10 +24,103 @@ pub enum CosmWasmApiVersion {
     Invalid,
 }
 
+/// The parts of the transaction that sent a message which the enclave verified against the
+/// signed bytes
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedTxInfo {
+    pub memo: String,
+    /// `None` if the fee payer couldn't be verified against the signers
+    pub fee_payer: Option<HumanAddr>,
+    /// `None` if the gas limit of the fee couldn't be parsed
+    pub fee: Option<VerifiedFee>,
+    /// The proofs of chain state that the transaction carries, which oracle queries are
+    /// answered from
+    pub oracle_proofs: Vec<Binary>,
+}
+
+/// The fee of the transaction that sent a message, as it was signed
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedFee {
+    pub amount: Vec<BaseCoin>,
+    pub gas_limit: u64,
+}
+
+/// The kinds of denoms that funds sent to contracts can have
+#[derive(Clone, Debug, PartialEq)]
+pub enum DenomKind {
+    /// A denom of the chain, like `uscrt`
+    Native,
+    /// `ibc/{hash}`, the hash of the trace of tokens received over IBC. The trace is only known
+    /// if the enclave verified it against the packet the tokens were received in.
+    Ibc { trace: Option<v1types::DenomTrace> },
+    /// `factory/{creator}/{subdenom}`, a denom of the token factory
+    Factory { creator: HumanAddr },
+}
+
+/// A coin that was sent to a contract, whose denom the enclave validated
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifiedCoin {
+    pub coin: BaseCoin,
+    pub kind: DenomKind,
+}
+
+/// The funds that were sent to a contract, as they were verified against the message that sent
+/// them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VerifiedFunds(pub Vec<VerifiedCoin>);
+
+impl VerifiedFunds {
+    pub fn coins(&self) -> Vec<BaseCoin> {
+        self.0
+            .iter()
+            .map(|verified| verified.coin.clone())
+            .collect()
+    }
+
+    /// The traces of the IBC denoms that were verified, or `None` if there are none
+    pub fn denom_traces(&self) -> Option<Vec<v1types::DenomTrace>> {
+        let traces: Vec<_> = self
+            .0
+            .iter()
+            .filter_map(|verified| match &verified.kind {
+                DenomKind::Ibc { trace } => trace.clone(),
+                _ => None,
+            })
+            .collect();
+
+        if traces.is_empty() {
+            None
+        } else {
+            Some(traces)
+        }
+    }
+}
+
 /// features that a contract requires
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum ContractFeature {
     Random,
+    Ibc,
+    /// The `migrate` entry point accepts a `MigrateInfo` as its third argument
+    MigrateInfo,
+    /// Encrypted outputs are padded to the coarse buckets of `output_padding`
+    CoarsePadding,
+    /// The outputs of the entry point have the constant shape of `constant_shape`
+    ConstantShape(ShapedEntrypoint),
+    /// Storage reads and writes are padded with dummies to the counts of `storage_access_padding`
+    PaddedStorageAccess,
+    /// Namespaces can be ordered with `db_write_ordered`, and ranged over with `db_scan`
+    OrderedKeys,
+    /// Outputs are brought to the canonical order of the given version of `output_normalization`
+    NormalizedOutput(u8),
+}
+
+/// The entry points that contracts can give a constant shape
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum ShapedEntrypoint {
+    Execute,
+    Reply,
+    Query,
 }
 
 pub type BaseAddr = HumanAddr;
@@ -187,6 +279,7 @@ impl BaseEnv {
                 contract_key: None,
                 contract_code_hash: self.0.contract_code_hash,
                 transaction: None,
+                compute_params: None,
             },
         }
     }
@@ -203,12 +296,23 @@ impl BaseEnv {
                     chain_id: self.0.block.chain_id,
                     #[cfg(feature = "random")]
                     random: self.0.block.random,
+                    // These are only taken from verified headers, see `set_verified_block_header`
+                    hash: None,
+                    proposer: None,
                 },
                 contract: v1types::ContractInfo {
                     address: v1types::Addr::unchecked(self.0.contract.address.0),
                     code_hash: self.0.contract_code_hash,
                 },
-                transaction: self.0.transaction,
+                // These are only taken from the verified sign bytes, see `set_verified_tx_info`
+                transaction: self
+                    .0
+                    .transaction
+                    .map(|transaction| v1types::TransactionInfo {
+                        memo: None,
+                        fee_payer: None,
+                        ..transaction
+                    }),
             },
             msg_info: v1types::MessageInfo {
                 sender: v1types::Addr::unchecked(self.0.message.sender.0),
@@ -219,6 +323,7 @@ impl BaseEnv {
                     .into_iter()
                     .map(|x| x.into())
                     .collect(),
+                denom_traces: None,
             },
         }
     }
@@ -309,6 +414,47 @@ impl CwEnv {
         }
     }
 
+    /// Sets the parts of the transaction that sent the message which the enclave verified.
+    /// v0.10 contracts don't get the transaction info, so it's only set for v1 contracts.
+    pub fn set_verified_tx_info(&mut self, tx_info: Option<VerifiedTxInfo>) {
+        if let CwEnv::V1Env { env, .. } = self {
+            if let Some(transaction) = env.transaction.as_mut() {
+                let (memo, fee_payer) = match tx_info {
+                    Some(tx_info) => (
+                        Some(tx_info.memo),
+                        tx_info.fee_payer.map(|fee_payer| fee_payer.0),
+                    ),
+                    None => (None, None),
+                };
+                transaction.memo = memo;
+                transaction.fee_payer = fee_payer;
+            }
+        }
+    }
+
+    /// Sets the funds that were sent to the contract, as the enclave verified them. The traces of
+    /// IBC denoms are only passed to v1 contracts.
+    pub fn set_verified_funds(&mut self, funds: VerifiedFunds) {
+        match self {
+            CwEnv::V010Env { env } => {
+                env.message.sent_funds = funds.coins();
+            }
+            CwEnv::V1Env { msg_info, .. } => {
+                msg_info.funds = funds.coins().into_iter().map(|x| x.into()).collect();
+                msg_info.denom_traces = funds.denom_traces();
+            }
+        }
+    }
+
+    /// Sets the hash and proposer of the block, from its header that the enclave verified.
+    /// v0.10 contracts don't get them, so they're only set for v1 contracts.
+    pub fn set_verified_block_header(&mut self, hash: Binary, proposer: Binary) {
+        if let CwEnv::V1Env { env, .. } = self {
+            env.block.hash = Some(hash);
+            env.block.proposer = Some(proposer);
+        }
+    }
+
     pub fn set_msg_sender(&mut self, msg_sender: &str) {
         match self {
             CwEnv::V010Env { env } => {
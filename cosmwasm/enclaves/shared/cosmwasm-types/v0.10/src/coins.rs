@@ -2,10 +2,13 @@
 
 use serde::{Deserialize, Serialize};
 
-use super::math::Uint128;
+use super::math::Uint256;
 
+/// Amounts can be up to 256 bits like in the SDK, so that coins with many decimals can be sent to
+/// contracts and verified against the messages that sent them. They are passed to contracts as
+/// decimal strings either way.
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, Eq)]
 pub struct Coin {
     pub denom: String,
-    pub amount: Uint128,
+    pub amount: Uint256,
 }
@@ -281,3 +281,222 @@ impl<'de> de::Visitor<'de> for Uint128Visitor {
         }
     }
 }
+
+//*** Uint256 and Uint512 ***/
+#[derive(Display)]
+pub struct WideUintParseErr(String);
+
+/// Defines an unsigned integer of `$limbs` 64 bit limbs, least significant first. These are
+/// only parsed, compared and printed, so unlike Uint128 they don't implement arithmetic.
+macro_rules! construct_wide_uint {
+    ($(#[$attr:meta])* $name:ident, $limbs:expr, $visitor:ident) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+        pub struct $name([u64; $limbs]);
+
+        impl $name {
+            pub const fn zero() -> Self {
+                $name([0; $limbs])
+            }
+
+            pub fn is_zero(&self) -> bool {
+                self.0.iter().all(|limb| *limb == 0)
+            }
+
+            /// Returns the value as a u128, if it fits in one
+            pub fn to_u128(&self) -> Option<u128> {
+                if self.0[2..].iter().any(|limb| *limb != 0) {
+                    return None;
+                }
+                Some(((self.0[1] as u128) << 64) | self.0[0] as u128)
+            }
+
+            /// Returns self * mul + add, or None on overflow
+            fn checked_mul_add_small(&self, mul: u64, add: u64) -> Option<Self> {
+                let mut result = [0u64; $limbs];
+                let mut carry = add as u128;
+                for (i, limb) in self.0.iter().enumerate() {
+                    let product = (*limb as u128) * (mul as u128) + carry;
+                    result[i] = product as u64;
+                    carry = product >> 64;
+                }
+
+                if carry != 0 {
+                    return None;
+                }
+                Some($name(result))
+            }
+
+            /// Returns self / div and self % div
+            fn div_rem_small(&self, div: u64) -> (Self, u64) {
+                let mut result = [0u64; $limbs];
+                let mut remainder = 0u128;
+                for i in (0..$limbs).rev() {
+                    let dividend = (remainder << 64) | self.0[i] as u128;
+                    result[i] = (dividend / div as u128) as u64;
+                    remainder = dividend % div as u128;
+                }
+                ($name(result), remainder as u64)
+            }
+        }
+
+        impl From<u128> for $name {
+            fn from(val: u128) -> Self {
+                let mut limbs = [0u64; $limbs];
+                limbs[0] = val as u64;
+                limbs[1] = (val >> 64) as u64;
+                $name(limbs)
+            }
+        }
+
+        impl From<Uint128> for $name {
+            fn from(val: Uint128) -> Self {
+                val.0.into()
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.iter().rev().cmp(other.0.iter().rev())
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = WideUintParseErr;
+
+            /// Parses a decimal string, like the amounts of coins in the SDK
+            fn from_str(input: &str) -> Result<Self, Self::Err> {
+                if input.is_empty() {
+                    return Err(WideUintParseErr(format!(
+                        "Parsing {}: empty string",
+                        stringify!($name)
+                    )));
+                }
+
+                let mut value = $name::zero();
+                for c in input.chars() {
+                    let digit = c.to_digit(10).ok_or_else(|| {
+                        WideUintParseErr(format!(
+                            "Parsing {}: invalid digit {:?}",
+                            stringify!($name),
+                            c
+                        ))
+                    })?;
+                    value = value.checked_mul_add_small(10, digit as u64).ok_or_else(|| {
+                        WideUintParseErr(format!("Parsing {}: number too large", stringify!($name)))
+                    })?;
+                }
+
+                Ok(value)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                // Print 19 digits at a time, the most that fit in a u64
+                const CHUNK: u64 = 10_000_000_000_000_000_000;
+
+                let mut chunks = vec![];
+                let mut rest = *self;
+                loop {
+                    let (quotient, remainder) = rest.div_rem_small(CHUNK);
+                    chunks.push(remainder);
+                    if quotient.is_zero() {
+                        break;
+                    }
+                    rest = quotient;
+                }
+
+                let mut chunks = chunks.iter().rev();
+                if let Some(first) = chunks.next() {
+                    write!(f, "{}", first)?;
+                }
+                for chunk in chunks {
+                    write!(f, "{:019}", chunk)?;
+                }
+                Ok(())
+            }
+        }
+
+        /// Serializes as a decimal string
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ser::Serializer,
+            {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        /// Deserializes from a decimal string
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<$name, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_str($visitor)
+            }
+        }
+
+        struct $visitor;
+
+        impl<'de> de::Visitor<'de> for $visitor {
+            type Value = $name;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("string-encoded integer")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                $name::from_str(v)
+                    .map_err(|e| E::custom(format!("invalid {} '{}' - {}", stringify!($name), v, e)))
+            }
+        }
+    };
+}
+
+construct_wide_uint!(
+    /// An unsigned 256 bit integer, which is the largest amount a coin can have in the SDK
+    Uint256,
+    4,
+    Uint256Visitor
+);
+
+construct_wide_uint!(
+    /// An unsigned 512 bit integer, wide enough for the atomics of any amount with 18 decimals
+    Uint512,
+    8,
+    Uint512Visitor
+);
+
+impl From<Uint256> for Uint512 {
+    fn from(val: Uint256) -> Self {
+        let mut limbs = [0u64; 8];
+        limbs[..4].copy_from_slice(&val.0);
+        Uint512(limbs)
+    }
+}
+
+impl TryFrom<Uint512> for Uint256 {
+    type Error = WideUintParseErr;
+
+    fn try_from(val: Uint512) -> Result<Self, Self::Error> {
+        if val.0[4..].iter().any(|limb| *limb != 0) {
+            return Err(WideUintParseErr(
+                "Uint512 doesn't fit in Uint256".to_string(),
+            ));
+        }
+        let mut limbs = [0u64; 4];
+        limbs.copy_from_slice(&val.0[..4]);
+        Ok(Uint256(limbs))
+    }
+}
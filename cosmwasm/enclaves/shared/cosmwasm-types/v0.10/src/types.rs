@@ -81,13 +81,26 @@ impl CanonicalAddr {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
-    pub fn from_human(human_addr: &HumanAddr) -> Result<Self, bech32::Error> {
+    pub fn from_human(human_addr: &HumanAddr) -> Result<Self, AddressError> {
         if human_addr.is_empty() {
             return Ok(CanonicalAddr(Binary(vec![])));
         }
 
-        let (decoded_prefix, data) = bech32::decode(human_addr.as_str())?;
+        let (decoded_prefix, data) = match bech32::decode(human_addr.as_str()) {
+            Ok(decoded) => decoded,
+            Err(bech32::Error::InvalidLength) => {
+                return Err(AddressError::InvalidLength(human_addr.len()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if decoded_prefix != BECH32_PREFIX_ACC_ADDR {
+            return Err(AddressError::InvalidPrefix(decoded_prefix));
+        }
+
         let canonical = Vec::<u8>::from_base32(&data)?;
+        if canonical.is_empty() || canonical.len() > MAX_ADDR_LEN {
+            return Err(AddressError::InvalidLength(canonical.len()));
+        }
 
         Ok(CanonicalAddr(Binary(canonical)))
     }
@@ -97,6 +110,57 @@ impl CanonicalAddr {
     }
 }
 
+/// Like `VerifyAddressFormat` of the sdk
+pub const MAX_ADDR_LEN: usize = 255;
+
+/// Why a human address couldn't be canonicalized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address is bech32, but of another chain
+    InvalidPrefix(String),
+    /// The checksum doesn't match, which usually means that the address has a typo
+    InvalidChecksum,
+    /// The bech32 string, or the address it encodes, is too short or too long
+    InvalidLength(usize),
+    /// Any other bech32 error, like a missing separator or an invalid character
+    Malformed(bech32::Error),
+}
+
+impl From<bech32::Error> for AddressError {
+    fn from(err: bech32::Error) -> Self {
+        match err {
+            bech32::Error::InvalidChecksum => AddressError::InvalidChecksum,
+            err => AddressError::Malformed(err),
+        }
+    }
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::InvalidPrefix(prefix) => write!(
+                f,
+                "wrong address prefix: expected {:?}, got {:?}",
+                BECH32_PREFIX_ACC_ADDR, prefix
+            ),
+            AddressError::InvalidChecksum => write!(f, "invalid address checksum"),
+            AddressError::InvalidLength(len) => write!(f, "invalid address length: {}", len),
+            AddressError::Malformed(err) => write!(f, "malformed address: {}", err),
+        }
+    }
+}
+
+impl From<AddressError> for EnclaveError {
+    fn from(err: AddressError) -> Self {
+        match err {
+            AddressError::InvalidPrefix(_) => EnclaveError::InvalidAddressPrefix,
+            AddressError::InvalidChecksum => EnclaveError::InvalidAddressChecksum,
+            AddressError::InvalidLength(_) => EnclaveError::InvalidAddressLength,
+            AddressError::Malformed(_) => EnclaveError::FailedToDeserialize,
+        }
+    }
+}
+
 impl fmt::Display for CanonicalAddr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt(f)
@@ -123,6 +187,10 @@ pub struct Env {
     pub contract_code_hash: String,
     #[serde(default)]
     pub transaction: Option<TransactionInfo>,
+    /// The parameters of the compute module that configure the enclave. Set by the host, and
+    /// never passed to contracts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compute_params: Option<serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -139,6 +207,14 @@ pub struct TransactionInfo {
     /// hash = sha256(tx_bytes)
     #[serde(default)]
     pub hash: String,
+    /// The memo of the transaction, if it was signed by the sender of the message.
+    /// This is set by the enclave, and only passed to v1 contracts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// The account that paid the fee of the transaction, if it was signed by the sender of the
+    /// message. This is set by the enclave, and only passed to v1 contracts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_payer: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
@@ -2,19 +2,19 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use cw_types_v010::coins::Coin as V010Coin;
+use cw_types_v010::math::Uint256;
 
-use super::math::Uint128;
-
+/// Amounts are 256 bits wide, see `cw_types_v010::coins::Coin`
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
 pub struct Coin {
     pub denom: String,
-    pub amount: Uint128,
+    pub amount: Uint256,
 }
 
 impl Coin {
     pub fn new(amount: u128, denom: impl Into<String>) -> Self {
         Coin {
-            amount: Uint128::new(amount),
+            amount: Uint256::from(amount),
             denom: denom.into(),
         }
     }
@@ -33,7 +33,7 @@ impl fmt::Display for Coin {
 impl From<V010Coin> for Coin {
     fn from(other: V010Coin) -> Self {
         Coin {
-            amount: other.amount.into(),
+            amount: other.amount,
             denom: other.denom,
         }
     }
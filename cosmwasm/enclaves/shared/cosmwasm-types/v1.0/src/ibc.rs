@@ -163,6 +163,17 @@ pub enum IbcChannelCloseMsg {
     CloseConfirm { channel: IbcChannel }, // pub channel: IbcChannel,
 }
 
+/// The message that is passed into the `ibc_channel_upgrade_*` entry points, in the steps of the
+/// channel upgrade handshake of ibc-go v8
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct IbcChannelUpgradeMsg {
+    /// The channel of the contract that is upgraded
+    pub endpoint: IbcEndpoint,
+    /// The version that the channel is upgraded to: the proposed one in `ibc_channel_upgrade_init`,
+    /// and the counterparty's in the other steps
+    pub version: String,
+}
+
 /// The message that is passed into `ibc_packet_receive`
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct IbcPacketReceiveMsg {
@@ -91,6 +91,9 @@ pub enum IbcMsg {
 }
 
 pub const REPLY_ENCRYPTION_MAGIC_BYTES: &[u8] = b"REPLY01";
+/// Follows a reply header when the submessage has a payload, then the length of the payload
+/// as a big endian u32 and the payload itself
+pub const REPLY_PAYLOAD_MAGIC_BYTES: &[u8] = b"PAYLD01";
 
 /// The message types of the staking module.
 ///
@@ -47,6 +47,10 @@ where
     // Plaintext replies will be encrypted only if the original message was.
     #[serde(default = "bool_false")]
     pub was_msg_encrypted: bool,
+    /// Opaque bytes that are passed back to the contract in the `reply`, like the `payload`
+    /// of CosmWasm 2.0
+    #[serde(default, skip_serializing_if = "Binary::is_empty")]
+    pub payload: Binary,
 }
 
 /// The information we get back from a successful sub message execution,
@@ -55,6 +59,39 @@ where
 pub struct SubMsgResponse {
     pub events: Vec<Event>,
     pub data: Option<Binary>,
+    /// `data` decoded into the same structure whatever the SDK version encoded it with.
+    /// Only set by the enclave on the replies it passes to contracts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalized_data: Option<NormalizedReplyData>,
+}
+
+/// The version of `NormalizedReplyData`, bumped if its fields change meaning
+pub const NORMALIZED_REPLY_DATA_VERSION: u32 = 1;
+
+/// How the SDK encoded the data of a reply
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplyDataEncoding {
+    /// The bytes the message returned, e.g. the data of a contract
+    Raw,
+    /// The response of the message wrapped in an `Any`, like the `msg_responses` of SDK v0.46+
+    Any,
+    /// A `TxMsgData` with the response of the message, like the SDK encodes the data of
+    /// transactions
+    TxMsgData,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NormalizedReplyData {
+    pub version: u32,
+    pub encoding: ReplyDataEncoding,
+    /// The type URL of the response, if the encoding has one
+    pub msg_type: Option<String>,
+    /// The data of the contract for the responses of contract messages, otherwise the
+    /// protobuf encoded response
+    pub data: Option<Binary>,
+    /// The address of the contract, for the responses of `MsgInstantiateContract`
+    pub contract_address: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -76,6 +113,9 @@ pub struct Reply {
     pub result: SubMsgResult,
     pub was_orig_msg_encrypted: bool,
     pub is_encrypted: bool,
+    /// The payload of plaintext replies. The payload of encrypted replies is in their `id`.
+    #[serde(default, skip_serializing_if = "Binary::is_empty")]
+    pub payload: Binary,
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct DecryptedReply {
@@ -83,6 +123,25 @@ pub struct DecryptedReply {
     /// Use this to identify which submessage triggered the `reply`.
     pub id: u64,
     pub result: SubMsgResult,
+    /// The payload that the contract set on the `SubMsg`
+    #[serde(default, skip_serializing_if = "Binary::is_empty")]
+    pub payload: Binary,
+    /// The structured error that the submessage failed with, if it returned one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typed_error: Option<TypedError>,
+}
+
+/// A structured error, such as a variant of a contract's `ContractError`. Contracts return it
+/// with an error message that is the JSON of `{"typed_error":{"kind":..,"data":..}}`, and
+/// the contracts that get the error in a reply can match on its kind.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TypedError {
+    /// The name of the error variant
+    pub kind: String,
+    /// The fields of the variant, in an encoding of the contract's choice
+    #[serde(default, skip_serializing_if = "Binary::is_empty")]
+    pub data: Binary,
 }
 
 /// The information we get back from a successful sub-call, with full sdk events
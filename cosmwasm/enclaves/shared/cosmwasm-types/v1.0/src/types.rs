@@ -66,6 +66,13 @@ pub struct BlockInfo {
     #[cfg(feature = "random")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub random: Option<Binary>,
+    /// Hash of the block header, from the header the enclave verified for the block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<Binary>,
+    /// Consensus address of the validator that proposed the block, from the header the enclave
+    /// verified for the block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposer: Option<Binary>,
 }
 
 /// Additional information from [MsgInstantiateContract] and [MsgExecuteContract], which is passed
@@ -91,6 +98,35 @@ pub struct MessageInfo {
     /// or `MsgExecuteContract`. The transfer is processed in bank before the contract
     /// is executed such that the new balance is visible during contract execution.
     pub funds: Vec<Coin>,
+    /// The traces of the IBC denoms in `funds`, for the funds that were received over IBC in the
+    /// packet that called the contract. Only set if the enclave verified them against the packet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denom_traces: Option<Vec<DenomTrace>>,
+}
+
+/// The trace of an IBC denom, e.g. `transfer/channel-0/uatom` for
+/// `ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DenomTrace {
+    pub denom: String,
+    /// The ports and channels the tokens were transferred through, e.g. `transfer/channel-0`
+    pub path: String,
+    /// The denom of the tokens in the chain they were minted in
+    pub base_denom: String,
+}
+
+/// Additional information passed to the `migrate` entry point of contracts that accept it.
+///
+/// See <https://github.com/CosmWasm/cosmwasm/blob/v2.2.0/packages/std/src/types.rs>
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MigrateInfo {
+    /// The sender of the `MsgMigrateContract`, which is the admin of the contract
+    pub sender: Addr,
+    /// The migrate version of the previous code, as declared in its `cw_migrate_version`
+    /// custom section. This is always `None`, as the enclave can't verify it.
+    pub old_migrate_version: Option<u64>,
+    /// The hex encoded hash of the code the contract is migrated from
+    pub old_code_hash: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
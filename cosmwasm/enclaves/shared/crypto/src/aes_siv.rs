@@ -14,6 +14,10 @@
 /// would expect it to be. 256/512 bit for Aes128/256 respectively.
 ///
 /// The result of encrypted data will be the size of the data + 16 bytes, same as in GCM mode
+///
+/// When the enclave is built with the `constant-time-aes` feature, AES always uses the bitsliced
+/// software implementation instead of AES-NI. Both implementations must produce the same outputs,
+/// which the known answer tests below check on either build.
 use crate::keys::{AESKey, SymmetricKey};
 use crate::traits::SIVEncryptable;
 use crate::CryptoError;
@@ -64,6 +68,61 @@ pub mod tests {
 
     use super::{aes_siv_decrypt, aes_siv_encrypt};
 
+    /// Deterministic authenticated encryption example from RFC 5297, appendix A.1
+    pub fn test_aes_siv_kat_deterministic() {
+        let key = hex::decode("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff")
+            .unwrap();
+        let ad = hex::decode("101112131415161718191a1b1c1d1e1f2021222324252627").unwrap();
+        let plaintext = hex::decode("112233445566778899aabbccddee").unwrap();
+        let ciphertext =
+            hex::decode("85632d07c6e8f37f950acd320a2ecc9340c02b9690c4dc04daef7f6afe5c").unwrap();
+
+        let mut symmetric_key = [0u8; 32];
+        symmetric_key.copy_from_slice(&key);
+        let ad: [&[u8]; 1] = [&ad];
+
+        let result = aes_siv_encrypt(&plaintext, Some(&ad[..]), &symmetric_key).unwrap();
+        assert_eq!(result, ciphertext);
+
+        let result = aes_siv_decrypt(&ciphertext, Some(&ad[..]), &symmetric_key).unwrap();
+        assert_eq!(result, plaintext);
+    }
+
+    /// Nonce-based authenticated encryption example from RFC 5297, appendix A.2. The nonce is
+    /// passed as the last associated data.
+    pub fn test_aes_siv_kat_nonce_based() {
+        let key = hex::decode("7f7e7d7c7b7a79787776757473727170404142434445464748494a4b4c4d4e4f")
+            .unwrap();
+        let ad1 = hex::decode(
+            "00112233445566778899aabbccddeeffdeaddadadeaddadaffeeddccbbaa99887766554433221100",
+        )
+        .unwrap();
+        let ad2 = hex::decode("102030405060708090a0").unwrap();
+        let nonce = hex::decode("09f911029d74e35bd84156c5635688c0").unwrap();
+        let plaintext = hex::decode(
+            "7468697320697320736f6d6520706c61696e7465787420746f20656e6372797074207573696e67205349562d414553",
+        )
+        .unwrap();
+        let ciphertext = hex::decode(
+            "7bdb6e3b432667eb06f4d14bff2fbd0fcb900f2fddbe404326601965c889bf17dba77ceb094fa663b7a3f748ba8af829ea64ad544a272e9c485b62a3fd5c0d",
+        )
+        .unwrap();
+
+        let mut symmetric_key = [0u8; 32];
+        symmetric_key.copy_from_slice(&key);
+        let ad: [&[u8]; 3] = [&ad1, &ad2, &nonce];
+
+        let result = aes_siv_encrypt(&plaintext, Some(&ad[..]), &symmetric_key).unwrap();
+        assert_eq!(result, ciphertext);
+
+        let result = aes_siv_decrypt(&ciphertext, Some(&ad[..]), &symmetric_key).unwrap();
+        assert_eq!(result, plaintext);
+
+        let mut tampered = ciphertext;
+        tampered[20] ^= 1;
+        assert!(aes_siv_decrypt(&tampered, Some(&ad[..]), &symmetric_key).is_err());
+    }
+
     // todo: fix test vectors to actually work
     pub fn _test_aes_encrypt() {
         let key = b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
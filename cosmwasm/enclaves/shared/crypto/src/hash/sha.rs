@@ -1,8 +1,11 @@
+#[cfg(not(target_arch = "wasm32"))]
 pub use ring::digest;
+#[cfg(not(target_arch = "wasm32"))]
 pub use ring::digest::SHA256;
 
 pub const HASH_SIZE: usize = 32;
 
+#[cfg(not(target_arch = "wasm32"))]
 pub fn sha_256(data: &[u8]) -> [u8; HASH_SIZE] {
     let hash = digest::digest(&digest::SHA256, data);
 
@@ -12,6 +15,17 @@ pub fn sha_256(data: &[u8]) -> [u8; HASH_SIZE] {
     result
 }
 
+/// ring-sgx doesn't build for wasm32, so sha2 computes the same digest there
+#[cfg(target_arch = "wasm32")]
+pub fn sha_256(data: &[u8]) -> [u8; HASH_SIZE] {
+    use sha2::{Digest, Sha256};
+
+    let mut result = [0u8; HASH_SIZE];
+    result.copy_from_slice(&Sha256::digest(data));
+
+    result
+}
+
 // #[cfg(feature = "test")]
 // pub mod tests {
 //     use super::{
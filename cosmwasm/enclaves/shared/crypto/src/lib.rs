@@ -1,42 +1,68 @@
-#![cfg_attr(not(target_env = "sgx"), no_std)]
+#![cfg_attr(not(any(target_env = "sgx", target_arch = "wasm32")), no_std)]
 #![cfg_attr(target_env = "sgx", feature(rustc_private))]
 
+//! When compiled to wasm32, e.g. as a dependency of `enclave_cosmos_types` in client SDKs, only
+//! the hashes and the verification of signatures are available. Everything that needs the SGX
+//! SDK, like the keys of the enclave and sealing, is left out.
+
+#[cfg(not(target_arch = "wasm32"))]
 extern crate sgx_trts;
+#[cfg(not(target_arch = "wasm32"))]
 extern crate sgx_types;
 
 // Trick to get the IDE to use sgx_tstd even when it doesn't know we're targeting SGX
 extern crate alloc;
-#[cfg(not(target_env = "sgx"))]
+#[cfg(not(any(target_env = "sgx", target_arch = "wasm32")))]
 extern crate sgx_tstd as std;
 
+#[cfg(not(target_arch = "wasm32"))]
 pub mod consts;
 mod errors;
+#[cfg(not(target_arch = "wasm32"))]
 pub(crate) mod kdf;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod key_manager;
+#[cfg(not(target_arch = "wasm32"))]
 mod keys;
+#[cfg(not(target_arch = "wasm32"))]
 mod storage;
 pub mod traits;
 
 // mod aes_gcm;
+#[cfg(not(target_arch = "wasm32"))]
 mod aes_siv;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod ed25519;
+#[cfg(not(target_arch = "wasm32"))]
 mod hmac;
 pub mod secp256k1;
+pub mod secp256r1;
+#[cfg(not(target_arch = "wasm32"))]
+mod xchacha20_poly1305;
 
+#[cfg(not(target_arch = "wasm32"))]
 mod rng;
 
 pub mod hash;
 
 pub use errors::{CryptoError, WasmApiCryptoError};
+#[cfg(not(target_arch = "wasm32"))]
 pub use key_manager::Keychain;
+#[cfg(not(target_arch = "wasm32"))]
 pub use key_manager::KEY_MANAGER;
+#[cfg(not(target_arch = "wasm32"))]
 pub use keys::{AESKey, Seed, SymmetricKey, SEED_KEY_SIZE};
 
+#[cfg(not(target_arch = "wasm32"))]
 pub use ed25519::{Ed25519PublicKey, KeyPair, PUBLIC_KEY_SIZE, SECRET_KEY_SIZE};
 
 pub use hash::sha::{sha_256, HASH_SIZE};
-pub use traits::{Encryptable, Hmac, Kdf, SIVEncryptable, SealedKey, HMAC_SIGNATURE_SIZE};
+pub use traits::{
+    Encryptable, Hmac, Kdf, SIVEncryptable, SealedKey, XChaCha20Poly1305Encryptable,
+    HMAC_SIGNATURE_SIZE,
+};
 
+#[cfg(not(target_arch = "wasm32"))]
 pub use kdf::hkdf_sha_256;
 
 #[cfg(feature = "test")]
@@ -60,10 +86,14 @@ pub mod tests {
     }
 
     pub fn run_tests() {
-        let failures = 0;
+        let mut failures = 0;
 
         count_failures!(failures, {
             // todo: add encryption and other tests here
+            crate::aes_siv::tests::test_aes_siv_kat_deterministic();
+            crate::aes_siv::tests::test_aes_siv_kat_nonce_based();
+            crate::xchacha20_poly1305::tests::test_xchacha20_poly1305_round_trip();
+            crate::secp256r1::tests::test_secp256r1_verify();
         });
 
         if failures != 0 {
@@ -3,15 +3,11 @@ use log::*;
 use crate::traits::VerifyingKey;
 use crate::CryptoError;
 use cosmos_proto::tx::signing::SignMode;
-// use k256::ecdsa::{
-//     signature::{DigestSigner, DigestVerifier},
-//     Signature, SigningKey,
-// };
+#[cfg(not(target_arch = "wasm32"))]
 use secp256k1::Secp256k1;
 
 use sha2::{Digest as Sha2Digest, Sha256};
 use sha3::Keccak256;
-// use std::time::Instant;
 
 pub const SECP256K1_PREFIX: [u8; 4] = [235, 90, 233, 135];
 
@@ -32,79 +28,115 @@ impl VerifyingKey for Secp256k1PubKey {
         sign_mode: SignMode,
     ) -> Result<(), CryptoError> {
         // Signing ref: https://docs.cosmos.network/master/spec/_ics/ics-030-signed-messages.html#preliminary
-
-        //let start = Instant::now();
         let sign_bytes_hash = if sign_mode == SignMode::SIGN_MODE_EIP_191 {
             Keccak256::digest(bytes)
         } else {
             Sha256::digest(bytes)
         };
-        // let duration = start.elapsed();
-        // trace!(
-        //     "verify_bytes: Time elapsed in Sha256::digest: {:?}",
-        //     duration
-        // );
 
-        //let start = Instant::now();
-        //let verifying_key = VerifyingKey
-        let msg = secp256k1::Message::from_slice(sign_bytes_hash.as_slice()).map_err(|err| {
-            warn!("Failed to create a secp256k1 message from tx: {:?}", err);
-            CryptoError::VerificationError
-        })?;
-        // let duration = start.elapsed();
-        // trace!(
-        //     "verify_bytes: Time elapsed in Message::from_slice: {:?}",
-        //     duration
-        // );
+        verify_digest(self.0.as_slice(), sign_bytes_hash.as_slice(), sig)?;
 
-        //let start = Instant::now();
+        trace!("successfully verified this signature: {:?}", sig);
+        Ok(())
+    }
+}
 
-        let verifier = Secp256k1::verification_only();
-        // let duration = start.elapsed();
-        // trace!(
-        //     "verify_bytes: Time elapsed in Secp256k1::verification_only: {:?}",
-        //     duration
-        // );
+#[cfg(not(target_arch = "wasm32"))]
+fn verify_digest(public_key: &[u8], digest: &[u8], sig: &[u8]) -> Result<(), CryptoError> {
+    let msg = secp256k1::Message::from_slice(digest).map_err(|err| {
+        warn!("Failed to create a secp256k1 message from tx: {:?}", err);
+        CryptoError::VerificationError
+    })?;
 
-        //let start = Instant::now();
-        // Create `secp256k1`'s types
-        let sec_signature = secp256k1::ecdsa::Signature::from_compact(sig).map_err(|err| {
-            warn!("Malformed signature: {:?}", err);
+    let verifier = Secp256k1::verification_only();
+
+    // Create `secp256k1`'s types
+    let sec_signature = secp256k1::ecdsa::Signature::from_compact(sig).map_err(|err| {
+        warn!("Malformed signature: {:?}", err);
+        CryptoError::VerificationError
+    })?;
+
+    let sec_public_key = secp256k1::PublicKey::from_slice(public_key).map_err(|err| {
+        warn!("Malformed public key: {:?}", err);
+        CryptoError::VerificationError
+    })?;
+
+    verifier
+        .verify_ecdsa(&msg, &sec_signature, &sec_public_key)
+        .map_err(|err| {
+            warn!(
+                "Failed to verify signatures for the given transaction: {:?}",
+                err
+            );
             CryptoError::VerificationError
-        })?;
-        // let duration = start.elapsed();
-        // trace!(
-        //     "verify_bytes: Time elapsed in Signature::from_compact: {:?}",
-        //     duration
-        // );
+        })
+}
 
-        //let start = Instant::now();
-        let sec_public_key =
-            secp256k1::PublicKey::from_slice(self.0.as_slice()).map_err(|err| {
-                warn!("Malformed public key: {:?}", err);
-                CryptoError::VerificationError
-            })?;
-        // let duration = start.elapsed();
-        // trace!(
-        //     "verify_bytes: Time elapsed in PublicKey from_slice: {:?}",
-        //     duration
-        // );
+/// libsecp256k1 is written in C, which doesn't build for wasm32 without a C toolchain for that
+/// target, so client SDKs verify with the arithmetic of the pure Rust k256 instead. The ecdsa crate
+/// of the same version can't be locked next to the `signature` that tendermint pins, so the
+/// verification is the one of k256's `VerifyPrimitive`. Like libsecp256k1, it only accepts
+/// signatures in lower-S form.
+#[cfg(target_arch = "wasm32")]
+fn verify_digest(public_key: &[u8], digest: &[u8], sig: &[u8]) -> Result<(), CryptoError> {
+    use k256::elliptic_curve::group::ff::PrimeField;
+    use k256::elliptic_curve::ops::{LinearCombination, Reduce};
+    use k256::elliptic_curve::{AffineXCoordinate, IsHigh};
+    use k256::{FieldBytes, ProjectivePoint, PublicKey, Scalar, U256};
 
-        //let start = Instant::now();
-        verifier
-            .verify_ecdsa(&msg, &sec_signature, &sec_public_key)
-            .map_err(|err| {
-                warn!(
-                    "Failed to verify signatures for the given transaction: {:?}",
-                    err
-                );
-                CryptoError::VerificationError
-            })?;
-        // let duration = start.elapsed();
-        // trace!("verify_bytes: Time elapsed in verify_ecdsa: {:?}", duration);
-        trace!("successfully verified this signature: {:?}", sig);
-        Ok(())
+    use crate::HASH_SIZE;
+
+    /// The scalar encoded by 32 big endian bytes, if it's in `[1, n)`
+    fn non_zero_scalar(bytes: &[u8]) -> Option<Scalar> {
+        let mut repr = FieldBytes::default();
+        repr.copy_from_slice(bytes);
+        Option::<Scalar>::from(Scalar::from_repr(repr))
+            .filter(|scalar| !bool::from(scalar.is_zero()))
+    }
+
+    if digest.len() != HASH_SIZE {
+        warn!("Digest has a wrong size: {}", digest.len());
+        return Err(CryptoError::VerificationError);
     }
+
+    let (r, s) = match sig.len() {
+        64 => (non_zero_scalar(&sig[..32]), non_zero_scalar(&sig[32..])),
+        _ => (None, None),
+    };
+    let (r, s) = match (r, s) {
+        (Some(r), Some(s)) if !bool::from(s.is_high()) => (r, s),
+        _ => {
+            warn!("Malformed signature or not in lower-S form: {:?}", sig);
+            return Err(CryptoError::VerificationError);
+        }
+    };
+
+    let public_key = PublicKey::from_sec1_bytes(public_key).map_err(|err| {
+        warn!("Malformed public key: {:?}", err);
+        CryptoError::VerificationError
+    })?;
+
+    let mut z = FieldBytes::default();
+    z.copy_from_slice(digest);
+    let z = <Scalar as Reduce<U256>>::from_be_bytes_reduced(z);
+
+    // `s` isn't zero, so it has an inverse
+    let s_inv = s.invert().unwrap();
+    let x = ProjectivePoint::lincomb(
+        &ProjectivePoint::GENERATOR,
+        &(z * s_inv),
+        &public_key.to_projective(),
+        &(r * s_inv),
+    )
+    .to_affine()
+    .x();
+
+    if <Scalar as Reduce<U256>>::from_be_bytes_reduced(x) != r {
+        warn!("Failed to verify signatures for the given transaction");
+        return Err(CryptoError::VerificationError);
+    }
+
+    Ok(())
 }
 
 // TODO: Can we get rid of this comment below?
@@ -0,0 +1,148 @@
+use log::*;
+
+use cosmos_proto::tx::signing::SignMode;
+use p256::elliptic_curve::group::ff::{Field, PrimeField};
+use p256::elliptic_curve::ops::{LinearCombination, Reduce};
+use p256::elliptic_curve::{AffineXCoordinate, IsHigh};
+use p256::{FieldBytes, ProjectivePoint, PublicKey, Scalar, U256};
+use sha2::{Digest, Sha256};
+
+use crate::traits::VerifyingKey;
+use crate::CryptoError;
+
+/// A P-256 public key, as used by passkey and WebAuthn wallets. Keys are SEC1 encoded, compressed
+/// in the keys of accounts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Secp256r1PubKey(pub Vec<u8>);
+
+impl Secp256r1PubKey {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl VerifyingKey for Secp256r1PubKey {
+    /// Like the sdk, signatures are the 64 bytes of `r` and `s` over the SHA-256 of the sign bytes,
+    /// and `s` must be in the lower half of the order so that signatures aren't malleable
+    fn verify_bytes(
+        &self,
+        bytes: &[u8],
+        sig: &[u8],
+        sign_mode: SignMode,
+    ) -> Result<(), CryptoError> {
+        if sign_mode == SignMode::SIGN_MODE_EIP_191 {
+            warn!("secp256r1 keys can't sign in EIP-191 mode");
+            return Err(CryptoError::VerificationError);
+        }
+
+        verify_digest(self.0.as_slice(), &Sha256::digest(bytes), sig)?;
+
+        trace!("successfully verified this signature: {:?}", sig);
+        Ok(())
+    }
+}
+
+/// The scalar encoded by 32 big endian bytes, if it's in `[1, n)`
+fn non_zero_scalar(bytes: &[u8]) -> Option<Scalar> {
+    let mut repr = FieldBytes::default();
+    repr.copy_from_slice(bytes);
+    Option::<Scalar>::from(Scalar::from_repr(repr)).filter(|scalar| !bool::from(scalar.is_zero()))
+}
+
+/// The ecdsa crate of the version that shares its dependencies with k256 can't be locked next to
+/// the `signature` that tendermint pins, so like the wasm32 verification of secp256k1 signatures,
+/// the verification is the one of p256's `VerifyPrimitive`
+fn verify_digest(public_key: &[u8], digest: &[u8], sig: &[u8]) -> Result<(), CryptoError> {
+    let (r, s) = match sig.len() {
+        64 => (non_zero_scalar(&sig[..32]), non_zero_scalar(&sig[32..])),
+        _ => (None, None),
+    };
+    let (r, s) = match (r, s) {
+        (Some(r), Some(s)) if !bool::from(s.is_high()) => (r, s),
+        _ => {
+            warn!(
+                "Malformed secp256r1 signature or not in lower-S form: {:?}",
+                sig
+            );
+            return Err(CryptoError::VerificationError);
+        }
+    };
+
+    let public_key = PublicKey::from_sec1_bytes(public_key).map_err(|err| {
+        warn!("Malformed public key: {:?}", err);
+        CryptoError::VerificationError
+    })?;
+
+    let mut z = FieldBytes::default();
+    z.copy_from_slice(digest);
+    let z = <Scalar as Reduce<U256>>::from_be_bytes_reduced(z);
+
+    // `s` isn't zero, so it has an inverse
+    let s_inv = s.invert().unwrap();
+    let x = ProjectivePoint::lincomb(
+        &ProjectivePoint::GENERATOR,
+        &(z * s_inv),
+        &public_key.to_projective(),
+        &(r * s_inv),
+    )
+    .to_affine()
+    .x();
+
+    if <Scalar as Reduce<U256>>::from_be_bytes_reduced(x) != r {
+        warn!(
+            "Failed to verify signatures for the given transaction: {:?}",
+            sig
+        );
+        return Err(CryptoError::VerificationError);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+    /// A key and a signature of `MESSAGE` in lower-S form, made with the secret key `[3u8; 32]`
+    const PUBLIC_KEY: &str = "02591ab771ebbcfd6d9cb9094d106528add1a69d44c2c1f627f089ec58b9c61adf";
+    const MESSAGE: &[u8] = b"sign bytes";
+    const SIGNATURE: &str = "e169c3c98e9208151c6fc599c1b6489878a297aa4dbbaa337c00bdb60476ee142168c4294c47fb337e0b1e3c002c7a6cfec08b0e9434c8c18399756d7b2c88e0";
+
+    pub fn test_secp256r1_verify() {
+        let compressed = hex::decode(PUBLIC_KEY).unwrap();
+        let uncompressed = PublicKey::from_sec1_bytes(&compressed)
+            .unwrap()
+            .to_encoded_point(false)
+            .as_bytes()
+            .to_vec();
+        assert_eq!(uncompressed.len(), 65);
+        let sig = hex::decode(SIGNATURE).unwrap();
+
+        for key in [compressed.clone(), uncompressed] {
+            let public_key = Secp256r1PubKey::new(key);
+            assert!(public_key
+                .verify_bytes(MESSAGE, &sig, SignMode::SIGN_MODE_DIRECT)
+                .is_ok());
+        }
+
+        let public_key = Secp256r1PubKey::new(compressed);
+        assert!(public_key
+            .verify_bytes(b"other bytes", &sig, SignMode::SIGN_MODE_DIRECT)
+            .is_err());
+        assert!(public_key
+            .verify_bytes(MESSAGE, &sig, SignMode::SIGN_MODE_EIP_191)
+            .is_err());
+        assert!(public_key
+            .verify_bytes(MESSAGE, &sig[..63], SignMode::SIGN_MODE_DIRECT)
+            .is_err());
+
+        // The same signature with the high `s` is refused
+        let s = non_zero_scalar(&sig[32..]).unwrap();
+        let high_s = [&sig[..32], &(-s).to_repr()[..]].concat();
+        assert!(public_key
+            .verify_bytes(MESSAGE, &high_s, SignMode::SIGN_MODE_DIRECT)
+            .is_err());
+    }
+}
@@ -16,6 +16,19 @@ pub trait SIVEncryptable {
     fn decrypt_siv(&self, plaintext: &[u8], ad: Option<&[&[u8]]>) -> Result<Vec<u8>, CryptoError>;
 }
 
+pub trait XChaCha20Poly1305Encryptable {
+    fn encrypt_xchacha(
+        &self,
+        plaintext: &[u8],
+        ad: Option<&[&[u8]]>,
+    ) -> Result<Vec<u8>, CryptoError>;
+    fn decrypt_xchacha(
+        &self,
+        ciphertext: &[u8],
+        ad: Option<&[&[u8]]>,
+    ) -> Result<Vec<u8>, CryptoError>;
+}
+
 pub trait SealedKey
 where
     Self: std::marker::Sized,
@@ -0,0 +1,139 @@
+/// XChaCha20-Poly1305 encryption, the second cipher suite of the envelopes of messages, next to
+/// AES-SIV (see `aes_siv`).
+///
+/// The enclave has to encrypt deterministically, so that every node produces the same outputs,
+/// and it encrypts many fields of an output with the same key. Instead of a random nonce, the
+/// nonce is synthetic like in SIV mode: it's the HMAC of the associated data and the plaintext,
+/// so it only repeats when the same plaintext is encrypted again with the same data. The cipher
+/// and the HMAC use separate keys, both derived from the key.
+///
+/// The result is the nonce, followed by the ciphertext and the tag, which is the size of the data
+/// + 40 bytes.
+use crate::keys::AESKey;
+use crate::traits::{Hmac, Kdf, XChaCha20Poly1305Encryptable};
+use crate::CryptoError;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use log::*;
+
+pub const XNONCE_SIZE: usize = 24;
+
+const CIPHER_KEY_INFO: &[u8] = b"xchacha20poly1305_cipher";
+const NONCE_KEY_INFO: &[u8] = b"xchacha20poly1305_nonce";
+
+impl XChaCha20Poly1305Encryptable for AESKey {
+    fn encrypt_xchacha(
+        &self,
+        plaintext: &[u8],
+        ad: Option<&[&[u8]]>,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let ad = encode_ad(ad);
+
+        let mut nonce_input = ad.clone();
+        nonce_input.extend_from_slice(plaintext);
+        let nonce_key: AESKey = self.derive_key_from_this(NONCE_KEY_INFO);
+        let nonce = nonce_key.sign_sha_256(&nonce_input);
+        let nonce = XNonce::from_slice(&nonce[..XNONCE_SIZE]);
+
+        let ciphertext = cipher(self)
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &ad,
+                },
+            )
+            .map_err(|e| {
+                warn!("xchacha20poly1305_encrypt error: {:?}", e);
+                CryptoError::EncryptionError
+            })?;
+
+        let mut result = nonce.to_vec();
+        result.extend_from_slice(&ciphertext);
+        Ok(result)
+    }
+
+    fn decrypt_xchacha(
+        &self,
+        ciphertext: &[u8],
+        ad: Option<&[&[u8]]>,
+    ) -> Result<Vec<u8>, CryptoError> {
+        if ciphertext.len() < XNONCE_SIZE {
+            warn!(
+                "xchacha20poly1305_decrypt error: ciphertext of {} bytes has no nonce",
+                ciphertext.len()
+            );
+            return Err(CryptoError::DecryptionError);
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(XNONCE_SIZE);
+
+        cipher(self)
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &encode_ad(ad),
+                },
+            )
+            .map_err(|e| {
+                warn!("xchacha20poly1305_decrypt error: {:?}", e);
+                CryptoError::DecryptionError
+            })
+    }
+}
+
+fn cipher(key: &AESKey) -> XChaCha20Poly1305 {
+    let cipher_key: AESKey = key.derive_key_from_this(CIPHER_KEY_INFO);
+    XChaCha20Poly1305::new(Key::from_slice(cipher_key.get()))
+}
+
+/// Prefixes each part of the associated data with its length, so that different parts can't
+/// encode to the same bytes
+fn encode_ad(ad: Option<&[&[u8]]>) -> Vec<u8> {
+    let mut encoded = vec![];
+    for part in ad.unwrap_or(&[]) {
+        encoded.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(part);
+    }
+    encoded
+}
+
+#[cfg(feature = "test")]
+pub mod tests {
+    use super::*;
+
+    pub fn test_xchacha20_poly1305_round_trip() {
+        let key = AESKey::new_from_slice(&[1u8; 32]);
+        let ad: &[&[u8]] = &[b"contract"];
+
+        let ciphertext = key.encrypt_xchacha(b"plaintext", Some(ad)).unwrap();
+        assert_eq!(ciphertext.len(), XNONCE_SIZE + b"plaintext".len() + 16);
+        assert_eq!(
+            key.decrypt_xchacha(&ciphertext, Some(ad)).unwrap(),
+            b"plaintext".to_vec()
+        );
+
+        // Deterministic, but the nonce changes with the plaintext and the data
+        assert_eq!(
+            key.encrypt_xchacha(b"plaintext", Some(ad)).unwrap(),
+            ciphertext
+        );
+        let other = key.encrypt_xchacha(b"other", Some(ad)).unwrap();
+        assert_ne!(other[..XNONCE_SIZE], ciphertext[..XNONCE_SIZE]);
+        let no_ad = key.encrypt_xchacha(b"plaintext", None).unwrap();
+        assert_ne!(no_ad[..XNONCE_SIZE], ciphertext[..XNONCE_SIZE]);
+
+        // Other data, keys, or tampered ciphertexts don't decrypt
+        assert!(key.decrypt_xchacha(&ciphertext, None).is_err());
+        assert!(key
+            .decrypt_xchacha(&ciphertext, Some(&[b"cont", b"ract"]))
+            .is_err());
+        assert!(AESKey::new_from_slice(&[2u8; 32])
+            .decrypt_xchacha(&ciphertext, Some(ad))
+            .is_err());
+        let mut tampered = ciphertext.clone();
+        tampered[XNONCE_SIZE] ^= 1;
+        assert!(key.decrypt_xchacha(&tampered, Some(ad)).is_err());
+        assert!(key.decrypt_xchacha(&ciphertext[..10], Some(ad)).is_err());
+    }
+}
@@ -47,6 +47,30 @@ impl KvCache {
         self.readable_cache.remove(key);
     }
 
+    /// The keys in the cache that start with `prefix`
+    pub fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        let with_prefix = |cache: &BTreeMap<Vec<u8>, Vec<u8>>| -> Vec<Vec<u8>> {
+            cache
+                .range(prefix.to_vec()..)
+                .take_while(|(k, _v)| k.starts_with(prefix))
+                .map(|(k, _v)| k.clone())
+                .collect()
+        };
+
+        let mut keys = with_prefix(&self.writeable_cache);
+        keys.extend(with_prefix(&self.readable_cache));
+        keys
+    }
+
+    /// The key and value sizes of the entries that will be written by the next flush, in the
+    /// order of their keys
+    pub fn pending_write_sizes(&self) -> Vec<(usize, usize)> {
+        self.writeable_cache
+            .iter()
+            .map(|(k, v)| (k.len(), v.len()))
+            .collect()
+    }
+
     pub fn drain_gas_tracker(&mut self) -> u64 {
         let gas_used = self.gas_tracker;
         self.gas_tracker = 0;
@@ -77,6 +77,7 @@ pub extern "C" fn ocall_write_db(
     _context: Ctx,
     _vm_error: *mut UntrustedVmError,
     _gas_used: *mut u64,
+    _replaced_size: *mut u64,
     _key: *const u8,
     _key_len: usize,
     _value: *const u8,
@@ -90,17 +91,50 @@ pub extern "C" fn ocall_multiple_write_db(
     _context: Ctx,
     _vm_error: *mut UntrustedVmError,
     _gas_used: *mut u64,
+    _replaced_size: *mut u64,
     _keys: *const u8,
     _keys_len: usize,
 ) -> OcallReturn {
     unimplemented!()
 }
 
+#[no_mangle]
+pub extern "C" fn ocall_stream_logs(
+    _context: Ctx,
+    _logs: *const u8,
+    _logs_len: usize,
+) -> OcallReturn {
+    unimplemented!()
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_export_trace(
+    _context: Ctx,
+    _trace: *const u8,
+    _trace_len: usize,
+) -> OcallReturn {
+    unimplemented!()
+}
+
+#[no_mangle]
+pub extern "C" fn ocall_read_db_keys(
+    _context: Ctx,
+    _vm_error: *mut UntrustedVmError,
+    _gas_used: *mut u64,
+    _keys: *mut EnclaveBuffer,
+    _start: *const u8,
+    _start_len: usize,
+    _limit: u32,
+) -> OcallReturn {
+    unimplemented!()
+}
+
 #[no_mangle]
 pub extern "C" fn ocall_remove_db(
     _context: Ctx,
     _vm_error: *mut UntrustedVmError,
     _gas_used: *mut u64,
+    _removed_size: *mut u64,
     _key: *const u8,
     _key_len: usize,
 ) -> OcallReturn {
@@ -19,6 +19,20 @@ extern "C" {
         api_key_len: u32,
         flags: u32,
     ) -> sgx_status_t;
+    pub fn ecall_get_attestation_validity(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        expires_at: *mut u64,
+        remaining_secs: *mut u64,
+    ) -> sgx_status_t;
+    pub fn ecall_renew_attestation_if_due(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        api_key: *const u8,
+        api_key_len: u32,
+        flags: u32,
+        renewed: *mut u8,
+    ) -> sgx_status_t;
     pub fn ecall_authenticate_new_node(
         eid: sgx_enclave_id_t,
         retval: *mut NodeAuthResult,
@@ -189,6 +203,69 @@ pub fn create_attestation_report_u(api_key: &[u8], flags: u32) -> SgxResult<()>
     Ok(())
 }
 
+/// Returns when the last attestation of the enclave expires and how many seconds it has left, as
+/// unix timestamps. Both are 0 if the enclave never created an attestation.
+pub fn untrusted_get_attestation_validity() -> SgxResult<(u64, u64)> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let eid = enclave.geteid();
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let mut expires_at = 0u64;
+    let mut remaining_secs = 0u64;
+    let status = unsafe {
+        ecall_get_attestation_validity(eid, &mut retval, &mut expires_at, &mut remaining_secs)
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
+    }
+
+    Ok((expires_at, remaining_secs))
+}
+
+/// Creates a new attestation report if the last one is about to expire. Returns whether it did.
+pub fn untrusted_renew_attestation_if_due(api_key: &[u8], flags: u32) -> SgxResult<bool> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let eid = enclave.geteid();
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let mut renewed = 0u8;
+    let status = unsafe {
+        ecall_renew_attestation_if_due(
+            eid,
+            &mut retval,
+            api_key.as_ptr(),
+            api_key.len() as u32,
+            flags,
+            &mut renewed,
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
+    }
+
+    Ok(renewed != 0)
+}
+
 pub fn untrusted_get_encrypted_seed(
     cert: &[u8],
 ) -> SgxResult<Result<[u8; OUTPUT_ENCRYPTED_SEED_SIZE as usize], NodeAuthResult>> {
@@ -0,0 +1,88 @@
+use sgx_types::*;
+
+use log::{debug, warn};
+
+use crate::enclave::ENCLAVE_DOORBELL;
+use crate::system_contracts::refresh_system_codes;
+
+extern "C" {
+    pub fn ecall_submit_block_gas_limit(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        block_height: u64,
+        block_max_bytes: i64,
+        block_max_gas: i64,
+    ) -> sgx_status_t;
+
+    pub fn ecall_end_block(
+        eid: sgx_enclave_id_t,
+        retval: *mut sgx_status_t,
+        block_height: u64,
+    ) -> sgx_status_t;
+}
+
+/// Submits the block params of the consensus params of a block to the enclave, which limits the
+/// gas of the executions in the block to their max gas
+pub fn untrusted_submit_block_gas_limit(
+    block_height: u64,
+    block_max_bytes: i64,
+    block_max_gas: i64,
+) -> SgxResult<()> {
+    debug!(
+        "submitting gas limit {} of block {}",
+        block_max_gas, block_height
+    );
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let status = unsafe {
+        ecall_submit_block_gas_limit(
+            enclave.geteid(),
+            &mut retval,
+            block_height,
+            block_max_bytes,
+            block_max_gas,
+        )
+    };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
+    }
+    Ok(())
+}
+
+/// Ends the gas accounting of a block in the enclave, and reloads the codes of the system
+/// contracts, which the block may have changed
+pub fn untrusted_end_block(block_height: u64) -> SgxResult<()> {
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or(sgx_status_t::SGX_ERROR_BUSY)?;
+    let enclave = (*enclave_access_token)?;
+
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let status = unsafe { ecall_end_block(enclave.geteid(), &mut retval, block_height) };
+
+    if status != sgx_status_t::SGX_SUCCESS {
+        return Err(status);
+    }
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
+    }
+    drop(enclave_access_token);
+
+    if let Err(err) = refresh_system_codes() {
+        warn!("Failed to reload the system contracts: {}", err);
+    }
+    Ok(())
+}
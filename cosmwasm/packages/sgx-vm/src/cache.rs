@@ -14,6 +14,8 @@ use crate::compatability::{check_wasm, check_wasm_exports, REQUIRED_IBC_EXPORTS}
 use crate::errors::{VmError, VmResult};
 use crate::features::required_features_from_module;
 use crate::instance::Instance;
+use crate::pinned_code::{pinned_code_reference, try_pin_code};
+use crate::system_contracts::lane_for;
 /*
 use crate::modules::FileSystemCache;
 */
@@ -170,10 +172,30 @@ where
         }
         */
 
+        let mut instance = self.get_instance_of_code(checksum, deps, gas_limit)?;
+        instance.set_lane(lane_for(checksum));
+        Ok(instance)
+    }
+
+    fn get_instance_of_code(
+        &mut self,
+        checksum: &Checksum,
+        deps: Extern<S, A, Q>,
+        gas_limit: u64,
+    ) -> VmResult<Instance<S, A, Q>> {
+        // Code that is pinned in the enclave is passed by reference, so it isn't loaded at all
+        if let Some(reference) = pinned_code_reference(checksum) {
+            return Instance::from_code(&reference, deps, gas_limit);
+        }
+
         // fall back to wasm cache (and re-compiling) - this is for backends that don't support serialization
         let wasm = self.load_wasm(checksum)?;
         self.inner.lock().unwrap().stats.misses += 1;
-        Instance::from_code(&wasm, deps, gas_limit)
+        let wasm_dir = self.inner.lock().unwrap().wasm_path.clone();
+        match try_pin_code(checksum, &wasm, &wasm_dir) {
+            Some(reference) => Instance::from_code(&reference, deps, gas_limit),
+            None => Instance::from_code(&wasm, deps, gas_limit),
+        }
     }
 }
 
@@ -200,7 +222,10 @@ fn save_wasm_to_disk<P: Into<PathBuf>>(dir: P, wasm: &[u8]) -> VmResult<Checksum
     Ok(checksum)
 }
 
-fn load_wasm_from_disk<P: Into<PathBuf>>(dir: P, checksum: &Checksum) -> VmResult<Vec<u8>> {
+pub(crate) fn load_wasm_from_disk<P: Into<PathBuf>>(
+    dir: P,
+    checksum: &Checksum,
+) -> VmResult<Vec<u8>> {
     // this requires the directory and file to exist
     let path = dir.into().join(checksum.to_hex());
     let mut file = File::open(path)
@@ -72,7 +72,8 @@ pub fn call_query<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
 }
 */
 
-/// Calls Wasm export "migrate" and returns raw data from the contract.
+/// Calls Wasm export "migrate" and returns raw data from the contract, along with the intent of
+/// the writes of the execution, which the host acknowledges once it committed them.
 /// The result is length limited to prevent abuse but otherwise unchecked.
 pub fn call_migrate_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
     instance: &mut Instance<S, A, Q>,
@@ -81,7 +82,7 @@ pub fn call_migrate_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'st
     sig_info: &[u8],
     admin: &[u8],
     admin_proof: &[u8],
-) -> VmResult<Vec<u8>> {
+) -> VmResult<(Vec<u8>, Vec<u8>)> {
     instance.set_storage_readonly(false);
     /*
     call_raw(instance, "init", &[env, msg], MAX_LENGTH_INIT)
@@ -106,7 +107,8 @@ pub fn call_update_admin_raw<S: Storage + 'static, A: Api + 'static, Q: Querier
     instance.call_update_admin(env, sig_info, current_admin, current_admin_proof, new_admin)
 }
 
-/// Calls Wasm export "init" and returns raw data from the contract.
+/// Calls Wasm export "init" and returns raw data from the contract, along with the intent of the
+/// writes of the execution, which the host acknowledges once it committed them.
 /// The result is length limited to prevent abuse but otherwise unchecked.
 pub fn call_init_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
     instance: &mut Instance<S, A, Q>,
@@ -114,7 +116,7 @@ pub fn call_init_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'stati
     msg: &[u8],
     sig_info: &[u8],
     admin: &[u8],
-) -> VmResult<Vec<u8>> {
+) -> VmResult<(Vec<u8>, Vec<u8>)> {
     instance.set_storage_readonly(false);
     /*
     call_raw(instance, "init", &[env, msg], MAX_LENGTH_INIT)
@@ -122,7 +124,10 @@ pub fn call_init_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'stati
     instance.call_init(env, msg, sig_info, admin)
 }
 
-/// Calls Wasm export "handle" and returns raw data from the contract.
+/// Calls Wasm export "handle" and returns raw data from the contract, along with the JSON of the
+/// salted hashes of the storage keys it read and wrote, the JSON of the signed receipt of the
+/// execution, which is empty unless the node enabled execution receipts, and the intent of its
+/// writes.
 /// The result is length limited to prevent abuse but otherwise unchecked.
 pub fn call_handle_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
     instance: &mut Instance<S, A, Q>,
@@ -130,7 +135,7 @@ pub fn call_handle_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'sta
     msg: &[u8],
     sig_info: &[u8],
     handle_type: u8,
-) -> VmResult<Vec<u8>> {
+) -> VmResult<(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>)> {
     instance.set_storage_readonly(false);
     /*
     call_raw(instance, "handle", &[env, msg], MAX_LENGTH_HANDLE)
@@ -152,6 +157,45 @@ pub fn call_query_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'stat
     instance.call_query(env, msg)
 }
 
+/// Re-encrypts a chunk of the raw state of a contract to its current state key.
+/// Returns the JSON encoded result, which contains the sealed progress for the next chunk.
+pub fn call_reencrypt_state_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    progress: &[u8],
+    entries: &[u8],
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(false);
+    instance.call_reencrypt_state(env, progress, entries)
+}
+
+/// Exports the state entries of a user, if both the user and the admin of the contract signed
+/// the request. Returns the export encrypted for the user.
+pub fn call_export_user_state_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+    admin: &[u8],
+    admin_proof: &[u8],
+    request: &[u8],
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(true);
+    instance.call_export_user_state(env, admin, admin_proof, request)
+}
+
+/// Counts a page of the storage usage of a contract whose usage isn't counted yet. Returns the
+/// JSON encoded result, which says whether the count of the contract is done.
+pub fn call_count_storage_usage_raw<
+    S: Storage + 'static,
+    A: Api + 'static,
+    Q: Querier + 'static,
+>(
+    instance: &mut Instance<S, A, Q>,
+    env: &[u8],
+) -> VmResult<Vec<u8>> {
+    instance.set_storage_readonly(false);
+    instance.call_count_storage_usage(env)
+}
+
 #[cfg(not(feature = "default-enclave"))]
 fn call_raw<S: Storage + 'static, A: Api + 'static, Q: Querier + 'static>(
     instance: &mut Instance<S, A, Q>,
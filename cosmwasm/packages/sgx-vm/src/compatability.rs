@@ -51,7 +51,8 @@ const SUPPORTED_IMPORTS_V1: &[&str] = &[
     #[cfg(feature = "iterator")]
     "env.db_next",
     "env.gas_evaporate",
-    "env.check_gas"
+    "env.check_gas",
+    "env.db_remove_range",
 ];
 
 /// Lists all entry points we expect to be present when calling a v0.10 contract.
@@ -11,6 +11,8 @@ use lazy_static::lazy_static;
 use log::*;
 use parking_lot::{Condvar, Mutex};
 
+use crate::system_contracts::has_system_codes;
+
 #[cfg(feature = "production")]
 const ENCLAVE_DEBUG: i32 = 0;
 
@@ -70,8 +72,20 @@ static ENCLAVE_FILE: &str = "librust_cosmwasm_enclave.signed.so";
 /// before giving up.
 const ENCLAVE_LOCK_TIMEOUT: u64 = 6 * 5;
 const TCS_NUM: u8 = 8;
+/// Enclave threads that are reserved for executions of system contracts, see `Lane`
+const SYSTEM_LANE_TCS: u8 = 1;
 lazy_static! {
-    pub static ref ENCLAVE_DOORBELL: EnclaveDoorbell = EnclaveDoorbell::new(ENCLAVE_FILE, TCS_NUM);
+    pub static ref ENCLAVE_DOORBELL: EnclaveDoorbell =
+        EnclaveDoorbell::new(ENCLAVE_FILE, TCS_NUM - SYSTEM_LANE_TCS, SYSTEM_LANE_TCS);
+}
+
+/// The threads of the enclave that a call can use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    Shared,
+    /// Executions of system contracts, which have threads of their own so that spam of other
+    /// contracts can't keep them waiting. They use the shared threads when their own are busy.
+    System,
 }
 
 /// This struct manages the access to the enclave.
@@ -87,15 +101,22 @@ pub struct EnclaveDoorbell {
     condvar: Condvar,
     /// Amount of tasks allowed to use the enclave at the same time.
     count: Mutex<u8>,
+    /// Amount of tasks in `Lane::System` allowed to use the enclave on top of `count`. While there
+    /// are no system contracts, other tasks use these threads too.
+    system_count: Mutex<u8>,
 }
 
 impl EnclaveDoorbell {
-    fn new(enclave_file: &str, count: u8) -> Self {
-        info!("Setting up enclave doorbell for up to {} threads", count);
+    fn new(enclave_file: &str, count: u8, system_count: u8) -> Self {
+        info!(
+            "Setting up enclave doorbell for up to {} threads and {} threads of system contracts",
+            count, system_count
+        );
         Self {
             enclave: init_enclave(enclave_file),
             condvar: Condvar::new(),
             count: Mutex::new(count),
+            system_count: Mutex::new(system_count),
         }
     }
 
@@ -103,21 +124,62 @@ impl EnclaveDoorbell {
         if query_depth == 1 {
             let mut count = self.count.lock();
             if *count == 0 {
+                if let Some(token) = self.try_unused_system_access() {
+                    return Some(token);
+                }
                 // try to wait for other tasks to complete
                 let wait = self.condvar.wait_for(&mut count, duration);
                 // double check that the count is nonzero, so there's an available slot in the enclave.
                 if wait.timed_out() || *count == 0 {
-                    return None;
+                    drop(count);
+                    return self.try_unused_system_access();
                 }
             }
             *count -= 1;
         }
-        Some(EnclaveAccessToken::new(self, query_depth))
+        Some(EnclaveAccessToken::new(self, query_depth, Lane::Shared))
+    }
+
+    /// Takes a thread of `Lane::System` if no code runs in that lane, so that the threads aren't
+    /// held back for nothing
+    fn try_unused_system_access(&'static self) -> Option<EnclaveAccessToken> {
+        if has_system_codes() {
+            return None;
+        }
+        self.try_system_access()
+    }
+
+    fn try_system_access(&'static self) -> Option<EnclaveAccessToken> {
+        let mut system_count = self.system_count.lock();
+        if *system_count == 0 {
+            return None;
+        }
+        *system_count -= 1;
+        Some(EnclaveAccessToken::new(self, 1, Lane::System))
     }
 
     pub fn get_access(&'static self, query_depth: u32) -> Option<EnclaveAccessToken> {
         self.wait_for(Duration::from_secs(ENCLAVE_LOCK_TIMEOUT), query_depth)
     }
+
+    /// Like `get_access`, but calls in `Lane::System` first try to use a thread of their own
+    pub fn get_access_in(
+        &'static self,
+        lane: Lane,
+        query_depth: u32,
+    ) -> Option<EnclaveAccessToken> {
+        if lane == Lane::System && query_depth == 1 {
+            if let Some(token) = self.try_system_access() {
+                return Some(token);
+            }
+        }
+        self.get_access(query_depth)
+    }
+
+    /// Like `get_access` for a non-recursive call, but gives up immediately if the enclave is busy
+    pub fn try_get_access(&'static self) -> Option<EnclaveAccessToken> {
+        self.wait_for(Duration::from_secs(0), 1)
+    }
 }
 
 // NEVER add Clone or Copy
@@ -125,15 +187,17 @@ pub struct EnclaveAccessToken {
     doorbell: &'static EnclaveDoorbell,
     enclave: SgxResult<&'static SgxEnclave>,
     query_depth: u32,
+    lane: Lane,
 }
 
 impl EnclaveAccessToken {
-    fn new(doorbell: &'static EnclaveDoorbell, query_depth: u32) -> Self {
+    fn new(doorbell: &'static EnclaveDoorbell, query_depth: u32, lane: Lane) -> Self {
         let enclave = doorbell.enclave.as_ref().map_err(|status| *status);
         Self {
             doorbell,
             enclave,
             query_depth,
+            lane,
         }
     }
 }
@@ -149,10 +213,17 @@ impl Deref for EnclaveAccessToken {
 impl Drop for EnclaveAccessToken {
     fn drop(&mut self) {
         if self.query_depth == 1 {
-            let mut count = self.doorbell.count.lock();
-            *count += 1;
-            drop(count);
-            self.doorbell.condvar.notify_one();
+            match self.lane {
+                Lane::Shared => {
+                    let mut count = self.doorbell.count.lock();
+                    *count += 1;
+                    drop(count);
+                    self.doorbell.condvar.notify_one();
+                }
+                // Nobody waits for the threads of system contracts, tasks of the shared lane
+                // only take them when they are free
+                Lane::System => *self.doorbell.system_count.lock() += 1,
+            }
         }
     }
 }
@@ -1,4 +1,5 @@
 use lazy_static::lazy_static;
+use log::*;
 
 use parking_lot::Mutex;
 use sgx_types::{sgx_enclave_id_t, sgx_status_t, SgxResult};
@@ -6,6 +7,7 @@ use sgx_types::{sgx_enclave_id_t, sgx_status_t, SgxResult};
 use enclave_ffi_types::RuntimeConfiguration;
 
 use crate::enclave::ENCLAVE_DOORBELL;
+use crate::system_contracts::refresh_system_codes;
 
 lazy_static! {
     /// This variable indicates if the enclave configuration has already been set
@@ -22,12 +24,24 @@ extern "C" {
 
 pub struct EnclaveRuntimeConfig {
     pub module_cache_size: u32,
+    pub state_audit_mode: bool,
+    pub execution_deadline_millis: u64,
+    pub execution_receipts: bool,
+    pub execution_tracing: bool,
+    pub pinned_code_bytes: u64,
+    pub ffi_version: u32,
 }
 
 impl EnclaveRuntimeConfig {
     fn to_ffi_type(&self) -> RuntimeConfiguration {
         RuntimeConfiguration {
             module_cache_size: self.module_cache_size,
+            state_audit_mode: self.state_audit_mode,
+            execution_deadline_millis: self.execution_deadline_millis,
+            execution_receipts: self.execution_receipts,
+            execution_tracing: self.execution_tracing,
+            pinned_code_bytes: self.pinned_code_bytes,
+            ffi_version: self.ffi_version,
         }
     }
 }
@@ -59,6 +73,14 @@ pub fn configure_enclave(config: EnclaveRuntimeConfig) -> SgxResult<()> {
     if retval != sgx_status_t::SGX_SUCCESS {
         return Err(retval);
     }
+    drop(enclave_access_token);
+
+    // The system contracts are in the admin registry of the compute params. The enclave only
+    // knows the registry it verified last, and the codes are reloaded at the end of every block.
+    match refresh_system_codes() {
+        Ok(count) => info!("Running {} system contract codes in their own lane", count),
+        Err(err) => warn!("Failed to load the system contracts: {}", err),
+    }
 
     Ok(())
 }
@@ -0,0 +1,40 @@
+use std::mem::MaybeUninit;
+
+use sgx_types::*;
+
+use enclave_ffi_types::QueryResult;
+
+use crate::enclave::ENCLAVE_DOORBELL;
+use crate::errors::{EnclaveError, VmError, VmResult};
+use crate::wasmi::results::query_result_to_vm_result;
+
+extern "C" {
+    pub fn ecall_get_enclave_manifest(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+    ) -> sgx_status_t;
+}
+
+/// Fetch the protobuf encoded `EnclaveManifest` describing the capabilities of the running enclave
+pub fn untrusted_get_enclave_manifest() -> VmResult<Vec<u8>> {
+    let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or_else(|| {
+            VmError::generic_err("The enclave is too busy and can not respond to this query")
+        })?;
+    let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+    let status = unsafe { ecall_get_enclave_manifest(enclave.geteid(), query_result.as_mut_ptr()) };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let query_result = unsafe { query_result.assume_init() };
+            query_result_to_vm_result(query_result).map(|success| success.into_output())
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
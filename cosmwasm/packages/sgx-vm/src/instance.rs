@@ -37,6 +37,7 @@ use crate::memory::{get_memory_info, read_region, write_region};
 */
 use crate::traits::{Api, Extern, Querier, Storage};
 
+use crate::enclave::Lane;
 use crate::wasmi::Module;
 
 /*
@@ -269,6 +270,11 @@ where
     /// Sets the readonly storage flag on this instance. Since one instance can be used
     /// for multiple calls in integration tests, this should be set to the desired value
     /// right before every call.
+    /// Sets the enclave threads that calls of this instance use
+    pub(crate) fn set_lane(&mut self, lane: Lane) {
+        self.inner.set_lane(lane);
+    }
+
     pub fn set_storage_readonly(&mut self, new_value: bool) {
         set_storage_readonly::<S, Q>(self.inner.context_mut(), new_value);
     }
@@ -326,6 +332,7 @@ where
         Ok(function)
     }
 
+    /// Returns the output and the intent of the writes of the execution
     pub fn call_migrate(
         &mut self,
         env: &[u8],
@@ -333,7 +340,7 @@ where
         sig_info: &[u8],
         admin: &[u8],
         admin_proof: &[u8],
-    ) -> VmResult<Vec<u8>> {
+    ) -> VmResult<(Vec<u8>, Vec<u8>)> {
         let result = self.inner.migrate(env, msg, sig_info, admin, admin_proof)?;
         Ok(result.into_output())
     }
@@ -356,24 +363,27 @@ where
         Ok(result.into_output())
     }
 
+    /// Returns the output and the intent of the writes of the execution
     pub fn call_init(
         &mut self,
         env: &[u8],
         msg: &[u8],
         sig_info: &[u8],
         admin: &[u8],
-    ) -> VmResult<Vec<u8>> {
+    ) -> VmResult<(Vec<u8>, Vec<u8>)> {
         let result = self.inner.init(env, msg, sig_info, admin)?;
         Ok(result.into_output())
     }
 
+    /// Returns the output, the salted hashes of the storage keys the contract touched, the
+    /// receipt of the execution and the intent of its writes
     pub fn call_handle(
         &mut self,
         env: &[u8],
         msg: &[u8],
         sig_info: &[u8],
         handle_type: u8,
-    ) -> VmResult<Vec<u8>> {
+    ) -> VmResult<(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>)> {
         let result = self.inner.handle(env, msg, sig_info, handle_type)?;
         Ok(result.into_output())
     }
@@ -382,6 +392,34 @@ where
         let result = self.inner.query(env, msg)?;
         Ok(result.into_output())
     }
+
+    pub fn call_reencrypt_state(
+        &mut self,
+        env: &[u8],
+        progress: &[u8],
+        entries: &[u8],
+    ) -> VmResult<Vec<u8>> {
+        let result = self.inner.reencrypt_state(env, progress, entries)?;
+        Ok(result.into_output())
+    }
+
+    pub fn call_export_user_state(
+        &mut self,
+        env: &[u8],
+        admin: &[u8],
+        admin_proof: &[u8],
+        request: &[u8],
+    ) -> VmResult<Vec<u8>> {
+        let result = self
+            .inner
+            .export_user_state(env, admin, admin_proof, request)?;
+        Ok(result.into_output())
+    }
+
+    pub fn call_count_storage_usage(&mut self, env: &[u8]) -> VmResult<Vec<u8>> {
+        let result = self.inner.count_storage_usage(env)?;
+        Ok(result.into_output())
+    }
 }
 
 #[cfg(test)]
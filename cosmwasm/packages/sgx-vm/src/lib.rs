@@ -20,16 +20,28 @@ mod traits;
 // Secret Network specific modules
 mod attestation;
 mod attestation_dcap;
+mod block_gas;
+mod contract_analysis;
+mod crypto_calibration;
 mod enclave;
 mod enclave_config;
+mod enclave_manifest;
+mod enclave_metrics;
+mod execution_receipts;
+mod pinned_code;
 mod seed;
+mod state_audit;
+mod system_contracts;
+mod tx_journal;
 mod wasmi;
+mod write_intents;
 
 mod random;
 
 pub use crate::cache::CosmCache;
 pub use crate::calls::{
-    call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw, call_update_admin_raw,
+    call_count_storage_usage_raw, call_export_user_state_raw, call_handle_raw, call_init_raw,
+    call_migrate_raw, call_query_raw, call_reencrypt_state_raw, call_update_admin_raw,
 };
 pub use crate::checksum::Checksum;
 pub use crate::errors::{
@@ -51,7 +63,9 @@ pub use crate::traits::StorageIterator;
 
 // Secret Network specific exports
 pub use crate::attestation::{
-    create_attestation_report_u, untrusted_get_encrypted_genesis_seed, untrusted_get_encrypted_seed,
+    create_attestation_report_u, untrusted_get_attestation_validity,
+    untrusted_get_encrypted_genesis_seed, untrusted_get_encrypted_seed,
+    untrusted_renew_attestation_if_due,
 };
 pub use crate::seed::{
     untrusted_health_check, untrusted_init_bootstrap, untrusted_init_node, untrusted_key_gen,
@@ -59,3 +73,12 @@ pub use crate::seed::{
 };
 
 pub use crate::random::untrusted_submit_block_signatures;
+pub use crate::block_gas::{untrusted_end_block, untrusted_submit_block_gas_limit};
+pub use crate::contract_analysis::untrusted_analyze_contract;
+pub use crate::crypto_calibration::untrusted_calibrate_crypto_gas;
+pub use crate::enclave_manifest::untrusted_get_enclave_manifest;
+pub use crate::enclave_metrics::untrusted_get_enclave_metrics;
+pub use crate::execution_receipts::untrusted_get_execution_receipt_key;
+pub use crate::state_audit::untrusted_export_state_audit_log;
+pub use crate::tx_journal::untrusted_get_tx_failure_report;
+pub use crate::write_intents::untrusted_acknowledge_write_intents;
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use log::*;
+use sgx_types::*;
+
+use enclave_ffi_types::{QueryResult, PINNED_CODE_REFERENCE_PREFIX, PINNED_CODE_REFERENCE_SIZE};
+
+use crate::cache::load_wasm_from_disk;
+use crate::checksum::Checksum;
+use crate::enclave::ENCLAVE_DOORBELL;
+use crate::errors::{EnclaveError, VmError, VmResult};
+use crate::wasmi::results::query_result_to_vm_result;
+
+/// Smaller contracts are cheap enough to copy into the enclave on every call
+const PIN_THRESHOLD: usize = 256 * 1024;
+
+extern "C" {
+    pub fn ecall_pin_contract_code(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+        contract: *const u8,
+        contract_len: usize,
+    ) -> sgx_status_t;
+}
+
+lazy_static! {
+    /// Codes that were pinned in the enclave, and the directories they were loaded from. The
+    /// enclave never evicts pinned code, so these stay valid as long as this process (and the
+    /// enclave with it) is alive, but see `unpin_and_load`.
+    static ref PINNED_CODES: Mutex<HashMap<Checksum, PathBuf>> = Mutex::new(HashMap::new());
+}
+
+fn code_reference(checksum: &Checksum) -> Vec<u8> {
+    let hash: Vec<u8> = (*checksum).into();
+    let mut reference = PINNED_CODE_REFERENCE_PREFIX.to_vec();
+    reference.extend_from_slice(&hash);
+    reference
+}
+
+/// Returns the reference to pass to the enclave instead of the code, if the code is pinned
+pub fn pinned_code_reference(checksum: &Checksum) -> Option<Vec<u8>> {
+    if PINNED_CODES.lock().unwrap().contains_key(checksum) {
+        Some(code_reference(checksum))
+    } else {
+        None
+    }
+}
+
+/// Pins large contracts in the enclave, so that later calls pass a reference to the code instead
+/// of copying it into the enclave again. Returns the reference if the code was pinned.
+///
+/// This is opportunistic: if the enclave is busy or has no room for the code, the code is simply
+/// passed in full as before.
+pub fn try_pin_code(checksum: &Checksum, wasm: &[u8], wasm_dir: &Path) -> Option<Vec<u8>> {
+    if wasm.len() < PIN_THRESHOLD {
+        return None;
+    }
+
+    let expected_hash: Vec<u8> = (*checksum).into();
+    match untrusted_pin_contract_code(wasm) {
+        Ok(hash) if hash == expected_hash => {
+            PINNED_CODES
+                .lock()
+                .unwrap()
+                .insert(*checksum, wasm_dir.to_path_buf());
+            Some(code_reference(checksum))
+        }
+        Ok(_) => {
+            warn!(
+                "The enclave pinned code {} under a different hash",
+                checksum.to_hex()
+            );
+            None
+        }
+        Err(err) => {
+            debug!("Not pinning code {}: {}", checksum.to_hex(), err);
+            None
+        }
+    }
+}
+
+/// Whether an execution failed because the enclave didn't have the code it was passed a
+/// reference to
+pub fn is_code_pin_not_found(err: &VmError) -> bool {
+    matches!(
+        err,
+        VmError::EnclaveErr {
+            source: EnclaveError::EnclaveErr {
+                error: enclave_ffi_types::EnclaveError::CodePinNotFound,
+                ..
+            },
+        }
+    )
+}
+
+/// Forgets that the code of a reference is pinned, and loads it from disk so that it can be
+/// passed in full instead. The enclave refuses a reference without using any gas, so the
+/// execution can simply be retried with the code.
+pub fn unpin_and_load(reference: &[u8]) -> Option<Vec<u8>> {
+    if reference.len() != PINNED_CODE_REFERENCE_SIZE
+        || !reference.starts_with(PINNED_CODE_REFERENCE_PREFIX)
+    {
+        return None;
+    }
+    let checksum = Checksum::try_from(&reference[PINNED_CODE_REFERENCE_PREFIX.len()..]).ok()?;
+    let wasm_dir = PINNED_CODES.lock().unwrap().remove(&checksum)?;
+
+    warn!(
+        "The enclave doesn't have code {} pinned, passing it in full",
+        checksum.to_hex()
+    );
+    match load_wasm_from_disk(&wasm_dir, &checksum) {
+        Ok(wasm) if Checksum::generate(&wasm) == checksum => Some(wasm),
+        Ok(_) => {
+            warn!("Code {} changed on disk", checksum.to_hex());
+            None
+        }
+        Err(err) => {
+            warn!("Failed to load code {}: {}", checksum.to_hex(), err);
+            None
+        }
+    }
+}
+
+fn untrusted_pin_contract_code(wasm: &[u8]) -> VmResult<Vec<u8>> {
+    let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+    // Code is loaded while other calls may be using the enclave, including the outer call of a
+    // nested query, so don't wait for access.
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .try_get_access()
+        .ok_or_else(|| VmError::generic_err("The enclave is too busy to pin code"))?;
+    let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+    let status = unsafe {
+        ecall_pin_contract_code(
+            enclave.geteid(),
+            query_result.as_mut_ptr(),
+            wasm.as_ptr(),
+            wasm.len(),
+        )
+    };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let query_result = unsafe { query_result.assume_init() };
+            query_result_to_vm_result(query_result).map(|success| success.into_output())
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
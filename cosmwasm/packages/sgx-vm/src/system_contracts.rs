@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::mem::MaybeUninit;
+
+use lazy_static::lazy_static;
+use log::*;
+use parking_lot::RwLock;
+use sgx_types::*;
+
+use enclave_ffi_types::QueryResult;
+
+use crate::checksum::Checksum;
+use crate::enclave::{Lane, ENCLAVE_DOORBELL};
+use crate::errors::{EnclaveError, VmError, VmResult};
+use crate::wasmi::results::query_result_to_vm_result;
+
+extern "C" {
+    pub fn ecall_get_system_code_hashes(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+    ) -> sgx_status_t;
+}
+
+lazy_static! {
+    /// The codes of the system contracts that governance designated in the admin registry of the
+    /// enclave. Their executions run in `Lane::System`.
+    static ref SYSTEM_CODES: RwLock<HashSet<Checksum>> = RwLock::new(HashSet::new());
+}
+
+/// The lane that executions of the code run in
+pub fn lane_for(checksum: &Checksum) -> Lane {
+    if SYSTEM_CODES.read().contains(checksum) {
+        Lane::System
+    } else {
+        Lane::Shared
+    }
+}
+
+/// Whether governance designated any system contracts
+pub fn has_system_codes() -> bool {
+    !SYSTEM_CODES.read().is_empty()
+}
+
+/// Reloads the codes of the system contracts from the enclave, and returns how many there are.
+/// The enclave takes them from the admin registry in the compute params, which governance can
+/// change in any block, so they are reloaded at the end of every block.
+pub fn refresh_system_codes() -> VmResult<usize> {
+    // Codes that may no longer be system codes don't keep their lane
+    let hashes = untrusted_get_system_code_hashes().map_err(|err| {
+        SYSTEM_CODES.write().clear();
+        err
+    })?;
+
+    let mut codes = HashSet::new();
+    for hash in hashes.chunks(32) {
+        let checksum = Checksum::try_from(hash).map_err(|_| {
+            SYSTEM_CODES.write().clear();
+            VmError::generic_err("The enclave returned a malformed code hash")
+        })?;
+        codes.insert(checksum);
+    }
+
+    let count = codes.len();
+    debug!("{} system contract codes", count);
+    *SYSTEM_CODES.write() = codes;
+    Ok(count)
+}
+
+fn untrusted_get_system_code_hashes() -> VmResult<Vec<u8>> {
+    let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or_else(|| {
+            VmError::generic_err("The enclave is too busy and can not respond to this query")
+        })?;
+    let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+    let status =
+        unsafe { ecall_get_system_code_hashes(enclave.geteid(), query_result.as_mut_ptr()) };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let query_result = unsafe { query_result.assume_init() };
+            query_result_to_vm_result(query_result).map(|success| success.into_output())
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
@@ -0,0 +1,63 @@
+use std::mem::MaybeUninit;
+
+use sgx_types::*;
+
+use enclave_ffi_types::QueryResult;
+
+use crate::enclave::ENCLAVE_DOORBELL;
+use crate::errors::{EnclaveError, VmError, VmResult};
+use crate::wasmi::results::query_result_to_vm_result;
+
+extern "C" {
+    pub fn ecall_get_tx_failure_report(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+        tx_hash: *const u8,
+        tx_hash_len: usize,
+        nonce: *const u8,
+        nonce_len: usize,
+        user_public_key: *const u8,
+        user_public_key_len: usize,
+    ) -> sgx_status_t;
+}
+
+/// Fetch the failure report of a transaction that failed in one of the recent blocks.
+/// The report is encrypted with the encryption key of the original transaction,
+/// so only its sender can read it.
+pub fn untrusted_get_tx_failure_report(
+    tx_hash: &[u8],
+    nonce: &[u8],
+    user_public_key: &[u8],
+) -> VmResult<Vec<u8>> {
+    let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or_else(|| {
+            VmError::generic_err("The enclave is too busy and can not respond to this query")
+        })?;
+    let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+    let status = unsafe {
+        ecall_get_tx_failure_report(
+            enclave.geteid(),
+            query_result.as_mut_ptr(),
+            tx_hash.as_ptr(),
+            tx_hash.len(),
+            nonce.as_ptr(),
+            nonce.len(),
+            user_public_key.as_ptr(),
+            user_public_key.len(),
+        )
+    };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let query_result = unsafe { query_result.assume_init() };
+            query_result_to_vm_result(query_result).map(|success| success.into_output())
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
@@ -1,9 +1,12 @@
 use std::ffi::c_void;
 
+use log::*;
 use sgx_types::SgxResult;
 
 use enclave_ffi_types::{Ctx, EnclaveBuffer, OcallReturn, UntrustedVmError, UserSpaceBuffer};
 
+#[cfg(feature = "iterator")]
+use cosmwasm_std::Order;
 use cosmwasm_std::{Binary, StdResult, SystemResult};
 
 use crate::context::{with_querier_from_context, with_storage_from_context};
@@ -171,12 +174,81 @@ fn ocall_query_chain_concrete(
         .unwrap_or(OcallReturn::Panic)
 }
 
+#[no_mangle]
+pub extern "C" fn ocall_read_db_keys(
+    context: Ctx,
+    vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    keys: *mut EnclaveBuffer,
+    start: *const u8,
+    start_len: usize,
+    limit: u32,
+) -> OcallReturn {
+    ocall_read_db_keys_concrete(
+        super::allocate_enclave_buffer,
+        context,
+        vm_error,
+        gas_used,
+        keys,
+        start,
+        start_len,
+        limit,
+    )
+}
+
+/// Read up to `limit` keys of the contracts key-value store, in order, starting from `start`.
+/// The enclave uses these to find the keys it removes by their plaintext prefix.
+#[allow(clippy::too_many_arguments)]
+fn ocall_read_db_keys_concrete(
+    alloc_impl: fn(&[u8]) -> SgxResult<EnclaveBuffer>,
+    context: Ctx,
+    vm_error: *mut UntrustedVmError,
+    gas_used: *mut u64,
+    keys: *mut EnclaveBuffer,
+    start: *const u8,
+    start_len: usize,
+    limit: u32,
+) -> OcallReturn {
+    let start = unsafe { std::slice::from_raw_parts(start, start_len) };
+
+    let implementation = unsafe { get_implementations_from_context(&context).read_db_keys };
+
+    std::panic::catch_unwind(|| implementation(context, start, limit))
+        // Get either an error(`OcallReturn`), or a response(`EnclaveBuffer`)
+        // which will be converted to a success status.
+        .map(|result| -> Result<EnclaveBuffer, OcallReturn> {
+            match result {
+                Ok((found_keys, gas_cost)) => {
+                    unsafe { *gas_used = gas_cost };
+                    serde_json::to_vec(&found_keys)
+                        .map_err(|_| OcallReturn::Failure)
+                        .and_then(|val| alloc_impl(&val).map_err(|_| OcallReturn::Failure))
+                }
+                Err(err) => {
+                    unsafe { store_vm_error(err, vm_error) };
+                    Err(OcallReturn::Failure)
+                }
+            }
+        })
+        // Return the result or report the error
+        .map(|result| match result {
+            Ok(enclave_buffer) => {
+                unsafe { *keys = enclave_buffer };
+                OcallReturn::Success
+            }
+            Err(err) => err,
+        })
+        // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+        .unwrap_or(OcallReturn::Panic)
+}
+
 /// Remove a key from the contracts key-value store.
 #[no_mangle]
 pub extern "C" fn ocall_remove_db(
     context: Ctx,
     vm_error: *mut UntrustedVmError,
     gas_used: *mut u64,
+    removed_size: *mut u64,
     key: *const u8,
     key_len: usize,
 ) -> OcallReturn {
@@ -188,8 +260,11 @@ pub extern "C" fn ocall_remove_db(
     // In the future, if we see that panics do occur here, we should add a way to report this to the enclave.
     // TODO add logging if we fail to write
     std::panic::catch_unwind(|| match implementation(context, key) {
-        Ok(gas_cost) => {
-            unsafe { *gas_used = gas_cost };
+        Ok((gas_cost, size)) => {
+            unsafe {
+                *gas_used = gas_cost;
+                *removed_size = size;
+            };
             OcallReturn::Success
         }
         Err(err) => {
@@ -207,6 +282,7 @@ pub extern "C" fn ocall_multiple_write_db(
     context: Ctx,
     vm_error: *mut UntrustedVmError,
     gas_used: *mut u64,
+    replaced_size: *mut u64,
     keys: *const u8,
     keys_len: usize, // keys_capacity: usize,
                      // values: *const u8,
@@ -223,8 +299,11 @@ pub extern "C" fn ocall_multiple_write_db(
     let implementation = unsafe { get_implementations_from_context(&context).write_multiple_db };
 
     std::panic::catch_unwind(|| match implementation(context, x) {
-        Ok(gas_cost) => {
-            unsafe { *gas_used = gas_cost };
+        Ok((gas_cost, size)) => {
+            unsafe {
+                *gas_used = gas_cost;
+                *replaced_size = size;
+            };
             OcallReturn::Success
         }
         Err(err) => {
@@ -275,6 +354,7 @@ pub extern "C" fn ocall_write_db(
     context: Ctx,
     vm_error: *mut UntrustedVmError,
     gas_used: *mut u64,
+    replaced_size: *mut u64,
     key: *const u8,
     key_len: usize,
     value: *const u8,
@@ -289,8 +369,11 @@ pub extern "C" fn ocall_write_db(
     // In the future, if we see that panics do occur here, we should add a way to report this to the enclave.
     // TODO add logging if we fail to write
     std::panic::catch_unwind(|| match implementation(context, key, value) {
-        Ok(gas_cost) => {
-            unsafe { *gas_used = gas_cost };
+        Ok((gas_cost, size)) => {
+            unsafe {
+                *gas_used = gas_cost;
+                *replaced_size = size;
+            };
             OcallReturn::Success
         }
         Err(err) => {
@@ -301,6 +384,57 @@ pub extern "C" fn ocall_write_db(
     // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
     .unwrap_or(OcallReturn::Panic)
 }
+/// Log the lines that a contract printed, which debug enclaves stream while the contract runs.
+/// `logs` is a JSON array of strings.
+#[no_mangle]
+pub extern "C" fn ocall_stream_logs(
+    _context: Ctx,
+    logs: *const u8,
+    logs_len: usize,
+) -> OcallReturn {
+    let logs = unsafe { std::slice::from_raw_parts(logs, logs_len) };
+
+    std::panic::catch_unwind(|| match serde_json::from_slice::<Vec<String>>(logs) {
+        Ok(lines) => {
+            for line in lines {
+                info!("contract: {}", line);
+            }
+            OcallReturn::Success
+        }
+        Err(err) => {
+            warn!("failed to parse the logs streamed by the enclave: {}", err);
+            OcallReturn::Failure
+        }
+    })
+    // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+    .unwrap_or(OcallReturn::Panic)
+}
+
+/// Log the timing of the stages of an execution, which the enclave exports when the node enables
+/// execution tracing. `trace` is a JSON object of the operation and its spans, which is logged as
+/// is under the `enclave_trace` target, so that operators can filter the traces out of the logs.
+#[no_mangle]
+pub extern "C" fn ocall_export_trace(
+    _context: Ctx,
+    trace: *const u8,
+    trace_len: usize,
+) -> OcallReturn {
+    let trace = unsafe { std::slice::from_raw_parts(trace, trace_len) };
+
+    std::panic::catch_unwind(|| match std::str::from_utf8(trace) {
+        Ok(trace) => {
+            info!(target: "enclave_trace", "{}", trace);
+            OcallReturn::Success
+        }
+        Err(err) => {
+            warn!("failed to parse the trace exported by the enclave: {}", err);
+            OcallReturn::Failure
+        }
+    })
+    // This will happen only when `catch_unwind` returns `Err`, which indicates a caught panic
+    .unwrap_or(OcallReturn::Panic)
+}
+
 /// Box the error and return a pointer to it.
 /// This box will be recovered on the side that called the enclave.
 ///
@@ -325,9 +459,10 @@ struct ExportImplementations {
         query_depth: u32,
         gas_limit: u64,
     ) -> VmResult<(SystemResult<StdResult<Binary>>, u64)>,
-    remove_db: fn(context: Ctx, key: &[u8]) -> VmResult<u64>,
-    write_db: fn(context: Ctx, key: &[u8], value: &[u8]) -> VmResult<u64>,
-    write_multiple_db: fn(context: Ctx, keys: Vec<(Vec<u8>, Vec<u8>)>) -> VmResult<u64>,
+    read_db_keys: fn(context: Ctx, start: &[u8], limit: u32) -> VmResult<(Vec<Vec<u8>>, u64)>,
+    remove_db: fn(context: Ctx, key: &[u8]) -> VmResult<(u64, u64)>,
+    write_db: fn(context: Ctx, key: &[u8], value: &[u8]) -> VmResult<(u64, u64)>,
+    write_multiple_db: fn(context: Ctx, keys: Vec<(Vec<u8>, Vec<u8>)>) -> VmResult<(u64, u64)>,
 }
 
 impl ExportImplementations {
@@ -339,6 +474,7 @@ impl ExportImplementations {
         Self {
             read_db: ocall_read_db_impl::<S, Q>,
             query_chain: ocall_query_chain_impl::<S, Q>,
+            read_db_keys: ocall_read_db_keys_impl::<S, Q>,
             remove_db: ocall_remove_db_impl::<S, Q>,
             write_db: ocall_write_db_impl::<S, Q>,
             write_multiple_db: ocall_write_multiple_db_impl::<S, Q>,
@@ -405,54 +541,164 @@ where
     })
 }
 
-fn ocall_remove_db_impl<S, Q>(mut context: Ctx, key: &[u8]) -> VmResult<u64>
+#[cfg(feature = "iterator")]
+fn ocall_read_db_keys_impl<S, Q>(
+    mut context: Ctx,
+    start: &[u8],
+    limit: u32,
+) -> VmResult<(Vec<Vec<u8>>, u64)>
 where
     S: Storage,
     Q: Querier,
 {
     with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
-        let (ffi_result, gas_info) = storage.remove(key);
-        ffi_result
-            .and(Ok(gas_info.externally_used))
-            .map_err(Into::into)
+        // An empty start is the beginning of the store, which some backends only accept as `None`
+        let start = if start.is_empty() { None } else { Some(start) };
+        let (ffi_result, gas_info) = storage.range(start, None, Order::Ascending);
+        let mut gas_used = gas_info.externally_used;
+        let mut iterator = ffi_result?;
+
+        let mut keys = vec![];
+        while keys.len() < limit as usize {
+            let (ffi_result, gas_info) = iterator.next();
+            gas_used += gas_info.externally_used;
+            match ffi_result? {
+                Some((key, _)) => keys.push(key),
+                None => break,
+            }
+        }
+
+        Ok((keys, gas_used))
+    })
+}
+
+#[cfg(not(feature = "iterator"))]
+fn ocall_read_db_keys_impl<S, Q>(
+    _context: Ctx,
+    _start: &[u8],
+    _limit: u32,
+) -> VmResult<(Vec<Vec<u8>>, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    Err(VmError::generic_err(
+        "reading the keys of a contract requires the iterator feature",
+    ))
+}
+
+fn ocall_remove_db_impl<S, Q>(mut context: Ctx, key: &[u8]) -> VmResult<(u64, u64)>
+where
+    S: Storage,
+    Q: Querier,
+{
+    with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
+        remove_entry(storage, key)
     })
 }
 
-fn ocall_write_db_impl<S, Q>(mut context: Ctx, key: &[u8], value: &[u8]) -> VmResult<u64>
+fn ocall_write_db_impl<S, Q>(mut context: Ctx, key: &[u8], value: &[u8]) -> VmResult<(u64, u64)>
 where
     S: Storage,
     Q: Querier,
 {
     with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
-        let (ffi_result, gas_info) = storage.set(key, value);
-        ffi_result
-            .and(Ok(gas_info.externally_used))
-            .map_err(Into::into)
+        write_entries(storage, vec![(key.to_vec(), value.to_vec())])
     })
 }
 
 fn ocall_write_multiple_db_impl<S, Q>(
     mut context: Ctx,
     keys: Vec<(Vec<u8>, Vec<u8>)>,
-) -> VmResult<u64>
+) -> VmResult<(u64, u64)>
 where
     S: Storage,
     Q: Querier,
 {
     with_storage_from_context::<S, Q, _, _>(&mut context, |storage: &mut S| {
-        let mut total_gas = 0;
+        write_entries(storage, keys)
+    })
+}
 
-        for (k, v) in keys.into_iter() {
-            let (ffi_result, gas_info) = storage.set(&k, &v);
-            total_gas += gas_info.externally_used;
+/// The size of the entry of a key, its key and value, or 0 if there's none. Writes and removes
+/// report the sizes of the entries they replace to the enclave, which accounts the storage of
+/// the contract with them. Returns the size and the gas used to look it up.
+fn stored_entry_size<S: Storage>(storage: &S, key: &[u8]) -> VmResult<(u64, u64)> {
+    let (ffi_result, gas_info) = storage.get(key);
+    let size = ffi_result?.map_or(0, |value| (key.len() + value.len()) as u64);
+    Ok((size, gas_info.externally_used))
+}
 
-            if ffi_result.is_err() {
-                return Err(VmError::FfiErr {
-                    source: ffi_result.unwrap_err(),
-                });
-            }
+/// Removes an entry. Returns the gas used and the size of the removed entry.
+fn remove_entry<S: Storage>(storage: &mut S, key: &[u8]) -> VmResult<(u64, u64)> {
+    let (removed_size, gas_used) = stored_entry_size(storage, key)?;
+    let (ffi_result, gas_info) = storage.remove(key);
+    ffi_result?;
+
+    Ok((gas_used + gas_info.externally_used, removed_size))
+}
+
+/// Writes entries. Returns the gas used and the total size of the entries they replaced.
+fn write_entries<S: Storage>(
+    storage: &mut S,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+) -> VmResult<(u64, u64)> {
+    let mut total_gas = 0;
+    let mut replaced_size = 0;
+
+    for (k, v) in entries.into_iter() {
+        let (size, gas_used) = stored_entry_size(storage, &k)?;
+        let (ffi_result, gas_info) = storage.set(&k, &v);
+        total_gas += gas_used + gas_info.externally_used;
+
+        if ffi_result.is_err() {
+            return Err(VmError::FfiErr {
+                source: ffi_result.unwrap_err(),
+            });
         }
+        replaced_size += size;
+    }
 
-        Ok(total_gas)
-    })
+    Ok((total_gas, replaced_size))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::MockStorage;
+
+    #[test]
+    fn writes_and_removes_report_the_entries_they_replace() {
+        let mut storage = MockStorage::new();
+
+        // New entries replace nothing
+        let (_, replaced_size) = write_entries(
+            &mut storage,
+            vec![
+                (b"foo".to_vec(), b"bar".to_vec()),
+                (b"hello".to_vec(), b"world".to_vec()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(replaced_size, 0);
+
+        // Rewrites replace both the key and the value of the entry
+        let (gas_used, replaced_size) = write_entries(
+            &mut storage,
+            vec![(b"foo".to_vec(), b"longer value".to_vec())],
+        )
+        .unwrap();
+        assert_eq!(replaced_size, 6);
+        // The lookup of the replaced entry is metered with the write
+        assert_eq!(gas_used, 3 + 3 + 12);
+
+        let (_, removed_size) = remove_entry(&mut storage, b"hello").unwrap();
+        assert_eq!(removed_size, 10);
+        let (_, removed_size) = remove_entry(&mut storage, b"hello").unwrap();
+        assert_eq!(removed_size, 0);
+        assert_eq!(
+            storage.get(b"foo").0.unwrap(),
+            Some(b"longer value".to_vec())
+        );
+    }
 }
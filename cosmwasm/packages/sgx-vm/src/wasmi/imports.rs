@@ -107,6 +107,43 @@ extern "C" {
         msg: *const u8,
         msg_len: usize,
     ) -> sgx_status_t;
+
+    /// Re-encrypt a chunk of the state of a contract to its current state key
+    pub fn ecall_reencrypt_contract_state(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+        context: Ctx,
+        env: *const u8,
+        env_len: usize,
+        progress: *const u8,
+        progress_len: usize,
+        entries: *const u8,
+        entries_len: usize,
+    ) -> sgx_status_t;
+
+    /// Export the state entries of a user, as authorized by the user and the contract admin
+    pub fn ecall_export_user_state(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+        context: Ctx,
+        env: *const u8,
+        env_len: usize,
+        admin: *const u8,
+        admin_len: usize,
+        admin_proof: *const u8,
+        admin_proof_len: usize,
+        request: *const u8,
+        request_len: usize,
+    ) -> sgx_status_t;
+
+    /// Count a page of the storage usage of a contract that wasn't counted yet
+    pub fn ecall_count_storage_usage(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+        context: Ctx,
+        env: *const u8,
+        env_len: usize,
+    ) -> sgx_status_t;
 }
 
 /// This is a safe wrapper for allocating buffers inside the enclave.
@@ -1,6 +1,6 @@
 mod exports;
 mod imports;
-mod results;
+pub(crate) mod results;
 mod utils;
 mod wrapper;
 
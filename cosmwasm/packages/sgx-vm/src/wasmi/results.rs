@@ -9,14 +9,18 @@ pub struct InitSuccess {
     /// The contract_key for this contract.
     contract_key: [u8; 64],
     admin_proof: [u8; 32],
+    /// The intent of the writes of the execution, empty if it didn't write anything
+    write_intent: Vec<u8>,
 }
 
 impl InitSuccess {
-    pub fn into_output(self) -> Vec<u8> {
+    /// Returns the output, prefixed with the contract key and the admin proof, and the intent of
+    /// the writes
+    pub fn into_output(self) -> (Vec<u8>, Vec<u8>) {
         let mut out_vec = self.contract_key.to_vec();
         out_vec.extend_from_slice(&self.admin_proof);
         out_vec.extend_from_slice(&self.output);
-        out_vec
+        (out_vec, self.write_intent)
     }
 }
 
@@ -26,10 +30,12 @@ pub fn init_result_to_vm_result(other: InitResult) -> VmResult<InitSuccess> {
             output,
             contract_key,
             admin_proof,
+            write_intent,
         } => Ok(InitSuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
             contract_key,
             admin_proof,
+            write_intent: unsafe { exports::recover_buffer(write_intent) }.unwrap_or_else(Vec::new),
         }),
         InitResult::Failure { err } => Err(err.into()),
     }
@@ -41,10 +47,12 @@ pub fn migrate_result_to_vm_result(other: MigrateResult) -> VmResult<MigrateSucc
             output,
             new_contract_key,
             new_contract_key_proof,
+            write_intent,
         } => Ok(MigrateSuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
             new_contract_key,
             new_contract_key_proof,
+            write_intent: unsafe { exports::recover_buffer(write_intent) }.unwrap_or_else(Vec::new),
         }),
         MigrateResult::Failure { err } => Err(err.into()),
     }
@@ -65,14 +73,18 @@ pub struct MigrateSuccess {
     output: Vec<u8>,
     new_contract_key: [u8; 64],
     new_contract_key_proof: [u8; 32],
+    /// The intent of the writes of the execution, empty if it didn't write anything
+    write_intent: Vec<u8>,
 }
 
 impl MigrateSuccess {
-    pub fn into_output(self) -> Vec<u8> {
+    /// Returns the output, prefixed with the new contract key and its proof, and the intent of
+    /// the writes
+    pub fn into_output(self) -> (Vec<u8>, Vec<u8>) {
         let mut out_vec = self.new_contract_key.to_vec();
         out_vec.extend_from_slice(&self.new_contract_key_proof);
         out_vec.extend_from_slice(&self.output);
-        out_vec
+        (out_vec, self.write_intent)
     }
 }
 
@@ -91,18 +103,38 @@ impl UpdateAdminSuccess {
 pub struct HandleSuccess {
     /// A pointer to the output of the execution
     output: Vec<u8>,
+    /// The JSON of the salted hashes of the storage keys the execution read and wrote
+    touched_keys: Vec<u8>,
+    /// The JSON of the signed receipt of the execution, empty if receipts are disabled
+    receipt: Vec<u8>,
+    /// The intent of the writes of the execution, empty if it didn't write anything
+    write_intent: Vec<u8>,
 }
 
 impl HandleSuccess {
-    pub fn into_output(self) -> Vec<u8> {
-        self.output
+    /// Returns the output, the touched keys, the receipt and the intent of the writes
+    pub fn into_output(self) -> (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) {
+        (
+            self.output,
+            self.touched_keys,
+            self.receipt,
+            self.write_intent,
+        )
     }
 }
 
 pub fn handle_result_to_vm_result(other: HandleResult) -> VmResult<HandleSuccess> {
     match other {
-        HandleResult::Success { output } => Ok(HandleSuccess {
+        HandleResult::Success {
+            output,
+            touched_keys,
+            receipt,
+            write_intent,
+        } => Ok(HandleSuccess {
             output: unsafe { exports::recover_buffer(output) }.unwrap_or_else(Vec::new),
+            touched_keys: unsafe { exports::recover_buffer(touched_keys) }.unwrap_or_else(Vec::new),
+            receipt: unsafe { exports::recover_buffer(receipt) }.unwrap_or_else(Vec::new),
+            write_intent: unsafe { exports::recover_buffer(write_intent) }.unwrap_or_else(Vec::new),
         }),
         HandleResult::Failure { err } => Err(err.into()),
     }
@@ -4,8 +4,9 @@ use std::ffi::c_void;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 
-use crate::enclave::ENCLAVE_DOORBELL;
+use crate::enclave::{Lane, ENCLAVE_DOORBELL};
 use crate::errors::{EnclaveError, VmResult};
+use crate::pinned_code::{is_code_pin_not_found, unpin_and_load};
 use crate::{Querier, Storage, VmError};
 
 use enclave_ffi_types::{
@@ -37,6 +38,7 @@ where
     used_gas: u64,
     ctx: Ctx,
     finalizer: fn(*mut c_void),
+    lane: Lane,
 
     // This does not store data but only fixes type information
     type_storage: PhantomData<S>,
@@ -64,6 +66,7 @@ where
             used_gas: 0,
             ctx,
             finalizer,
+            lane: Lane::Shared,
             type_storage: Default::default(),
             type_querier: Default::default(),
         }
@@ -78,6 +81,10 @@ where
         &mut self.ctx
     }
 
+    pub fn set_lane(&mut self, lane: Lane) {
+        self.lane = lane;
+    }
+
     pub fn gas_limit(&self) -> u64 {
         self.gas_limit
     }
@@ -95,6 +102,21 @@ where
         VmError::generic_err("The enclave is too busy and can not respond to this query")
     }
 
+    /// Code that the enclave doesn't have pinned is loaded from disk, so that the call can be
+    /// retried with the whole code. Returns whether it should be retried.
+    fn reload_unpinned_code<T>(&mut self, result: &VmResult<T>) -> bool {
+        match result {
+            Err(err) if is_code_pin_not_found(err) => match unpin_and_load(&self.bytecode) {
+                Some(code) => {
+                    self.bytecode = code;
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
     pub fn migrate(
         &mut self,
         env: &[u8],
@@ -102,6 +124,21 @@ where
         sig_info: &[u8],
         admin: &[u8],
         admin_proof: &[u8],
+    ) -> VmResult<MigrateSuccess> {
+        let result = self.migrate_once(env, msg, sig_info, admin, admin_proof);
+        if self.reload_unpinned_code(&result) {
+            return self.migrate_once(env, msg, sig_info, admin, admin_proof);
+        }
+        result
+    }
+
+    fn migrate_once(
+        &mut self,
+        env: &[u8],
+        msg: &[u8],
+        sig_info: &[u8],
+        admin: &[u8],
+        admin_proof: &[u8],
     ) -> VmResult<MigrateSuccess> {
         trace!(
             "migrate() called with env: {:?} msg: {:?} gas_left: {}",
@@ -116,7 +153,7 @@ where
         // Bind the token to a local variable to ensure its
         // destructor runs in the end of the function
         let enclave_access_token = ENCLAVE_DOORBELL
-            .get_access(1) // This can never be recursive
+            .get_access_in(self.lane, 1) // This can never be recursive
             .ok_or_else(Self::busy_enclave_err)?;
         let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
 
@@ -176,7 +213,7 @@ where
         // Bind the token to a local variable to ensure its
         // destructor runs in the end of the function
         let enclave_access_token = ENCLAVE_DOORBELL
-            .get_access(1) // This can never be recursive
+            .get_access_in(self.lane, 1) // This can never be recursive
             .ok_or_else(Self::busy_enclave_err)?;
         let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
 
@@ -214,6 +251,20 @@ where
         msg: &[u8],
         sig_info: &[u8],
         admin: &[u8],
+    ) -> VmResult<InitSuccess> {
+        let result = self.init_once(env, msg, sig_info, admin);
+        if self.reload_unpinned_code(&result) {
+            return self.init_once(env, msg, sig_info, admin);
+        }
+        result
+    }
+
+    fn init_once(
+        &mut self,
+        env: &[u8],
+        msg: &[u8],
+        sig_info: &[u8],
+        admin: &[u8],
     ) -> VmResult<InitSuccess> {
         trace!(
             "init() called with env: {:?} msg: {:?} gas_left: {}",
@@ -228,7 +279,7 @@ where
         // Bind the token to a local variable to ensure its
         // destructor runs in the end of the function
         let enclave_access_token = ENCLAVE_DOORBELL
-            .get_access(1) // This can never be recursive
+            .get_access_in(self.lane, 1) // This can never be recursive
             .ok_or_else(Self::busy_enclave_err)?;
         let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
 
@@ -274,6 +325,20 @@ where
         msg: &[u8],
         sig_info: &[u8],
         handle_type: u8,
+    ) -> VmResult<HandleSuccess> {
+        let result = self.handle_once(env, msg, sig_info, handle_type);
+        if self.reload_unpinned_code(&result) {
+            return self.handle_once(env, msg, sig_info, handle_type);
+        }
+        result
+    }
+
+    fn handle_once(
+        &mut self,
+        env: &[u8],
+        msg: &[u8],
+        sig_info: &[u8],
+        handle_type: u8,
     ) -> VmResult<HandleSuccess> {
         trace!(
             "handle() called with env: {:?} msg: {:?} gas_left: {}",
@@ -288,7 +353,7 @@ where
         // Bind the token to a local variable to ensure its
         // destructor runs in the end of the function
         let enclave_access_token = ENCLAVE_DOORBELL
-            .get_access(1) // This can never be recursive
+            .get_access_in(self.lane, 1) // This can never be recursive
             .ok_or_else(Self::busy_enclave_err)?;
         let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
 
@@ -328,6 +393,14 @@ where
     }
 
     pub fn query(&mut self, env: &[u8], msg: &[u8]) -> VmResult<QuerySuccess> {
+        let result = self.query_once(env, msg);
+        if self.reload_unpinned_code(&result) {
+            return self.query_once(env, msg);
+        }
+        result
+    }
+
+    fn query_once(&mut self, env: &[u8], msg: &[u8]) -> VmResult<QuerySuccess> {
         trace!(
             "query() called with env: {:?} msg: {:?}",
             String::from_utf8_lossy(env),
@@ -342,7 +415,7 @@ where
         // Bind the token to a local variable to ensure its
         // destructor runs in the end of the function
         let enclave_access_token = doorbell
-            .get_access(get_query_depth(env)?)
+            .get_access_in(self.lane, get_query_depth(env)?)
             .ok_or_else(Self::busy_enclave_err)?;
         let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
 
@@ -379,6 +452,130 @@ where
         }
     }
 
+    pub fn reencrypt_state(
+        &mut self,
+        env: &[u8],
+        progress: &[u8],
+        entries: &[u8],
+    ) -> VmResult<QuerySuccess> {
+        trace!(
+            "reencrypt_state() called with env: {:?} entries_len: {}",
+            String::from_utf8_lossy(env),
+            entries.len(),
+        );
+
+        let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+        // Bind the token to a local variable to ensure its
+        // destructor runs in the end of the function
+        let enclave_access_token = ENCLAVE_DOORBELL
+            .get_access_in(self.lane, 1) // This can never be recursive
+            .ok_or_else(Self::busy_enclave_err)?;
+        let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+        let status = unsafe {
+            imports::ecall_reencrypt_contract_state(
+                enclave.geteid(),
+                query_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                env.as_ptr(),
+                env.len(),
+                progress.as_ptr(),
+                progress.len(),
+                entries.as_ptr(),
+                entries.len(),
+            )
+        };
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let query_result = unsafe { query_result.assume_init() };
+                query_result_to_vm_result(query_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn export_user_state(
+        &mut self,
+        env: &[u8],
+        admin: &[u8],
+        admin_proof: &[u8],
+        request: &[u8],
+    ) -> VmResult<QuerySuccess> {
+        trace!(
+            "export_user_state() called with env: {:?}",
+            String::from_utf8_lossy(env),
+        );
+
+        let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+        // Bind the token to a local variable to ensure its
+        // destructor runs in the end of the function
+        let enclave_access_token = ENCLAVE_DOORBELL
+            .get_access_in(self.lane, 1) // This can never be recursive
+            .ok_or_else(Self::busy_enclave_err)?;
+        let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+        let status = unsafe {
+            imports::ecall_export_user_state(
+                enclave.geteid(),
+                query_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                env.as_ptr(),
+                env.len(),
+                admin.as_ptr(),
+                admin.len(),
+                admin_proof.as_ptr(),
+                admin_proof.len(),
+                request.as_ptr(),
+                request.len(),
+            )
+        };
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let query_result = unsafe { query_result.assume_init() };
+                query_result_to_vm_result(query_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
+    pub fn count_storage_usage(&mut self, env: &[u8]) -> VmResult<QuerySuccess> {
+        trace!(
+            "count_storage_usage() called with env: {:?}",
+            String::from_utf8_lossy(env),
+        );
+
+        let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+        // Bind the token to a local variable to ensure its
+        // destructor runs in the end of the function
+        let enclave_access_token = ENCLAVE_DOORBELL
+            .get_access_in(self.lane, 1) // This can never be recursive
+            .ok_or_else(Self::busy_enclave_err)?;
+        let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+        let status = unsafe {
+            imports::ecall_count_storage_usage(
+                enclave.geteid(),
+                query_result.as_mut_ptr(),
+                self.ctx.unsafe_clone(),
+                env.as_ptr(),
+                env.len(),
+            )
+        };
+
+        match status {
+            sgx_status_t::SGX_SUCCESS => {
+                let query_result = unsafe { query_result.assume_init() };
+                query_result_to_vm_result(query_result)
+            }
+            failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+        }
+    }
+
     fn consume_gas(&mut self, used_gas: u64) {
         self.used_gas = self.used_gas.saturating_add(used_gas);
     }
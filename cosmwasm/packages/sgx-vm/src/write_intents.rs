@@ -0,0 +1,51 @@
+use std::mem::MaybeUninit;
+
+use sgx_types::*;
+
+use enclave_ffi_types::QueryResult;
+
+use crate::enclave::ENCLAVE_DOORBELL;
+use crate::errors::{EnclaveError, VmError, VmResult};
+use crate::wasmi::results::query_result_to_vm_result;
+
+extern "C" {
+    pub fn ecall_acknowledge_write_intents(
+        eid: sgx_enclave_id_t,
+        retval: *mut QueryResult,
+        intents: *const u8,
+        intents_len: usize,
+    ) -> sgx_status_t;
+}
+
+/// Acknowledge that the writes of the executions of a block were committed, with the
+/// concatenated intents the enclave returned along with their results. Fails if any of them isn't
+/// a pending intent of the enclave.
+pub fn untrusted_acknowledge_write_intents(intents: &[u8]) -> VmResult<()> {
+    let mut query_result = MaybeUninit::<QueryResult>::uninit();
+
+    // Bind the token to a local variable to ensure its
+    // destructor runs in the end of the function
+    let enclave_access_token = ENCLAVE_DOORBELL
+        .get_access(1) // This can never be recursive
+        .ok_or_else(|| {
+            VmError::generic_err("The enclave is too busy and can not respond to this query")
+        })?;
+    let enclave = enclave_access_token.map_err(EnclaveError::sdk_err)?;
+
+    let status = unsafe {
+        ecall_acknowledge_write_intents(
+            enclave.geteid(),
+            query_result.as_mut_ptr(),
+            intents.as_ptr(),
+            intents.len(),
+        )
+    };
+
+    match status {
+        sgx_status_t::SGX_SUCCESS => {
+            let query_result = unsafe { query_result.assume_init() };
+            query_result_to_vm_result(query_result).map(|_| ())
+        }
+        failure_status => Err(EnclaveError::sdk_err(failure_status).into()),
+    }
+}
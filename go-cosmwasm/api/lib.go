@@ -46,6 +46,107 @@ func HealthCheck() ([]byte, error) {
 	return receiveVector(res), nil
 }
 
+// GetEnclaveManifest returns the protobuf encoded EnclaveManifest of the running enclave
+func GetEnclaveManifest() ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_enclave_manifest(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// GetExecutionReceiptKey returns the Ed25519 public key that the enclaves of the network sign
+// execution receipts with
+func GetExecutionReceiptKey() ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_execution_receipt_key(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// AnalyzeContract runs the checks the enclave runs before instantiating a contract on the
+// given wasm, and returns a JSON report of the result
+func AnalyzeContract(code []byte) ([]byte, error) {
+	errmsg := C.Buffer{}
+	codeSlice := sendSlice(code)
+	defer freeAfterSend(codeSlice)
+	res, err := C.analyze_contract(codeSlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// GetTxFailureReport returns the failure report the enclave kept for a transaction of one of the
+// recent blocks, encrypted with the nonce and user public key of the transaction
+func GetTxFailureReport(txHash []byte, nonce []byte, userPublicKey []byte) ([]byte, error) {
+	errmsg := C.Buffer{}
+	txHashSlice := sendSlice(txHash)
+	defer freeAfterSend(txHashSlice)
+	nonceSlice := sendSlice(nonce)
+	defer freeAfterSend(nonceSlice)
+	userPublicKeySlice := sendSlice(userPublicKey)
+	defer freeAfterSend(userPublicKeySlice)
+	res, err := C.get_tx_failure_report(txHashSlice, nonceSlice, userPublicKeySlice, &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// GetEnclaveMetrics returns the metrics of the running enclave in the Prometheus text format
+func GetEnclaveMetrics() ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.get_enclave_metrics(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// CalibrateCryptoGas times the crypto host functions in the enclave over the given number of
+// iterations, and returns a JSON report with the gas costs it suggests for them
+func CalibrateCryptoGas(iterations uint32) ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.calibrate_crypto_gas(C.uint32_t(iterations), &errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// ExportStateAuditLog returns the cache flushes the enclave recorded while the state audit mode
+// was enabled, as JSON
+func ExportStateAuditLog() ([]byte, error) {
+	errmsg := C.Buffer{}
+
+	res, err := C.export_state_audit_log(&errmsg)
+	if err != nil {
+		return nil, errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), nil
+}
+
+// AcknowledgeWriteIntents tells the enclave that the writes of the executions of a block were
+// committed, with the concatenated intents it returned along with their results
+func AcknowledgeWriteIntents(intents []byte) error {
+	errmsg := C.Buffer{}
+	intentsSlice := sendSlice(intents)
+	defer freeAfterSend(intentsSlice)
+	_, err := C.acknowledge_write_intents(intentsSlice, &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
 func SubmitBlockSignatures(header []byte, commit []byte, txs []byte, encRandom []byte /* valSet []byte, nextValSet []byte */) ([]byte, error) {
 	errmsg := C.Buffer{}
 	spidSlice := sendSlice(header)
@@ -64,6 +165,30 @@ func SubmitBlockSignatures(header []byte, commit []byte, txs []byte, encRandom [
 	return receiveVector(res), nil
 }
 
+// SubmitBlockGasLimit submits the block params of the consensus params of a block whose
+// signatures were submitted. The enclave refuses the executions of delivered transactions beyond
+// the max gas of the block, and all of them until the max gas is submitted.
+func SubmitBlockGasLimit(height uint64, maxBytes int64, maxGas int64) error {
+	errmsg := C.Buffer{}
+
+	_, err := C.submit_block_gas_limit(u64(height), i64(maxBytes), i64(maxGas), &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
+// EndBlock ends the gas accounting of a block in the enclave
+func EndBlock(height uint64) error {
+	errmsg := C.Buffer{}
+
+	_, err := C.end_block(u64(height), &errmsg)
+	if err != nil {
+		return errorWithMessage(err, errmsg)
+	}
+	return nil
+}
+
 func InitBootstrap(spid []byte, apiKey []byte) ([]byte, error) {
 	errmsg := C.Buffer{}
 	spidSlice := sendSlice(spid)
@@ -125,11 +250,21 @@ func ReleaseCache(cache Cache) {
 	C.release_cache(cache.ptr)
 }
 
-func InitEnclaveRuntime(moduleCacheSize uint16) error {
+// FfiVersion is the version of the interface with the enclave that this node speaks. From version
+// 2 on, the enclave returns results as protobuf envelopes.
+const FfiVersion = 2
+
+func InitEnclaveRuntime(moduleCacheSize uint16, stateAuditMode bool, executionDeadlineMillis uint64, executionReceipts bool, executionTracing bool, pinnedCodeBytes uint64) error {
 	errmsg := C.Buffer{}
 
 	config := C.EnclaveRuntimeConfig{
-		module_cache_size: u32(moduleCacheSize),
+		module_cache_size:         u32(moduleCacheSize),
+		state_audit_mode:          C.bool(stateAuditMode),
+		execution_deadline_millis: u64(executionDeadlineMillis),
+		execution_receipts:        C.bool(executionReceipts),
+		execution_tracing:         C.bool(executionTracing),
+		pinned_code_bytes:         u64(pinnedCodeBytes),
+		ffi_version:               u32(FfiVersion),
 	}
 	_, err := C.configure_enclave_runtime(config, &errmsg)
 	if err != nil {
@@ -174,7 +309,7 @@ func Migrate(
 	sigInfo []byte,
 	admin []byte,
 	adminProof []byte,
-) ([]byte, uint64, error) {
+) ([]byte, []byte, uint64, error) {
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
 	p := sendSlice(params)
@@ -207,12 +342,13 @@ func Migrate(
 	//runtime.LockOSThread()
 	//defer runtime.UnlockOSThread()
 
-	res, err := C.migrate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, adminBuffer, adminProofBuffer)
+	writeIntent := C.Buffer{}
+	res, err := C.migrate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, adminBuffer, adminProofBuffer, &writeIntent)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
 		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		return nil, nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
-	return receiveVector(res), uint64(gasUsed), nil
+	return receiveVector(res), receiveVector(writeIntent), uint64(gasUsed), nil
 }
 
 func UpdateAdmin(
@@ -280,7 +416,7 @@ func Instantiate(
 	gasLimit uint64,
 	sigInfo []byte,
 	admin []byte,
-) ([]byte, uint64, error) {
+) ([]byte, []byte, uint64, error) {
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
 	p := sendSlice(params)
@@ -310,12 +446,13 @@ func Instantiate(
 	//runtime.LockOSThread()
 	//defer runtime.UnlockOSThread()
 
-	res, err := C.instantiate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, adminBuffer)
+	writeIntent := C.Buffer{}
+	res, err := C.instantiate(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, adminBuffer, &writeIntent)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
 		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		return nil, nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
-	return receiveVector(res), uint64(gasUsed), nil
+	return receiveVector(res), receiveVector(writeIntent), uint64(gasUsed), nil
 }
 
 func Handle(
@@ -330,7 +467,7 @@ func Handle(
 	gasLimit uint64,
 	sigInfo []byte,
 	handleType types.HandleType,
-) ([]byte, uint64, error) {
+) ([]byte, []byte, []byte, []byte, uint64, error) {
 	id := sendSlice(code_id)
 	defer freeAfterSend(id)
 	p := sendSlice(params)
@@ -350,18 +487,21 @@ func Handle(
 	q := buildQuerier(querier)
 	var gasUsed u64
 	errmsg := C.Buffer{}
+	touchedKeys := C.Buffer{}
+	receipt := C.Buffer{}
+	writeIntent := C.Buffer{}
 
 	//// This is done in order to ensure that goroutines don't
 	//// swap threads between recursive calls to the enclave.
 	//runtime.LockOSThread()
 	//defer runtime.UnlockOSThread()
 
-	res, err := C.handle(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, u8(handleType))
+	res, err := C.handle(cache.ptr, id, p, m, db, a, q, u64(gasLimit), &gasUsed, &errmsg, s, u8(handleType), &touchedKeys, &receipt, &writeIntent)
 	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
 		// Depending on the nature of the error, `gasUsed` will either have a meaningful value, or just 0.
-		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+		return nil, nil, nil, nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	}
-	return receiveVector(res), uint64(gasUsed), nil
+	return receiveVector(res), receiveVector(touchedKeys), receiveVector(receipt), receiveVector(writeIntent), uint64(gasUsed), nil
 }
 
 func Query(
@@ -406,6 +546,85 @@ func Query(
 	return receiveVector(res), uint64(gasUsed), nil
 }
 
+// ExportUserState exports the state entries of a user of a contract, which the user and the
+// admin of the contract both signed the request for. The export is encrypted for the user.
+func ExportUserState(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	admin []byte,
+	adminProof []byte,
+	request []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+	ad := sendSlice(admin)
+	defer freeAfterSend(ad)
+	adp := sendSlice(adminProof)
+	defer freeAfterSend(adp)
+	r := sendSlice(request)
+	defer freeAfterSend(r)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.export_user_state(cache.ptr, id, p, ad, adp, r, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
+// CountStorageUsage counts a page of the storage usage of a contract that wasn't counted yet,
+// and returns whether its count is done.
+func CountStorageUsage(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	id := sendSlice(code_id)
+	defer freeAfterSend(id)
+	p := sendSlice(params)
+	defer freeAfterSend(p)
+
+	// set up a new stack frame to handle iterators
+	counter := startContract()
+	defer endContract(counter)
+
+	dbState := buildDBState(store, counter)
+	db := buildDB(&dbState, gasMeter)
+	a := buildAPI(api)
+	q := buildQuerier(querier)
+	var gasUsed u64
+	errmsg := C.Buffer{}
+
+	res, err := C.count_storage_usage(cache.ptr, id, p, db, a, q, u64(gasLimit), &gasUsed, &errmsg)
+	if err != nil && err.(syscall.Errno) != C.ErrnoValue_Success {
+		return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
+	}
+	return receiveVector(res), uint64(gasUsed), nil
+}
+
 func AnalyzeCode(
 	cache Cache,
 	codeHash []byte,
@@ -455,6 +674,42 @@ func CreateAttestationReport(apiKey []byte, no_epid bool, no_dcap bool) (bool, e
 	return true, nil
 }
 
+// GetAttestationValidity returns when the last attestation report of the enclave expires and how
+// many seconds it has left. Both are 0 if the enclave never created a report.
+func GetAttestationValidity() (uint64, uint64, error) {
+	errmsg := C.Buffer{}
+	var expiresAt u64
+	var remainingSecs u64
+
+	_, err := C.get_attestation_validity(&expiresAt, &remainingSecs, &errmsg)
+	if err != nil {
+		return 0, 0, errorWithMessage(err, errmsg)
+	}
+	return uint64(expiresAt), uint64(remainingSecs), nil
+}
+
+// RenewAttestationIfDue creates a new attestation report, like CreateAttestationReport, if the
+// last one is about to expire. Returns whether it did.
+func RenewAttestationIfDue(apiKey []byte, no_epid bool, no_dcap bool) (bool, error) {
+	errmsg := C.Buffer{}
+	apiKeySlice := sendSlice(apiKey)
+	defer freeAfterSend(apiKeySlice)
+
+	flags := u32(0)
+	if no_epid {
+		flags |= u32(1)
+	}
+	if no_dcap {
+		flags |= u32(2)
+	}
+
+	renewed, err := C.renew_attestation_if_due(apiKeySlice, flags, &errmsg)
+	if err != nil {
+		return false, errorWithMessage(err, errmsg)
+	}
+	return bool(renewed), nil
+}
+
 func GetEncryptedSeed(cert []byte) ([]byte, error) {
 	errmsg := C.Buffer{}
 	certSlice := sendSlice(cert)
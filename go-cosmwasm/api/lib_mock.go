@@ -35,6 +35,38 @@ func HealthCheck() ([]byte, error) {
 	return nil, nil
 }
 
+func GetEnclaveManifest() ([]byte, error) {
+	return nil, nil
+}
+
+func GetExecutionReceiptKey() ([]byte, error) {
+	return nil, nil
+}
+
+func AnalyzeContract(code []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func GetTxFailureReport(txHash []byte, nonce []byte, userPublicKey []byte) ([]byte, error) {
+	return nil, nil
+}
+
+func GetEnclaveMetrics() ([]byte, error) {
+	return nil, nil
+}
+
+func CalibrateCryptoGas(iterations uint32) ([]byte, error) {
+	return nil, nil
+}
+
+func ExportStateAuditLog() ([]byte, error) {
+	return nil, nil
+}
+
+func AcknowledgeWriteIntents(intents []byte) error {
+	return nil
+}
+
 func InitBootstrap(spid []byte, apiKey []byte) ([]byte, error) {
 	return nil, nil
 }
@@ -43,6 +75,14 @@ func SubmitBlockSignatures(header []byte, commit []byte, txs []byte, random []by
 	return nil, nil
 }
 
+func SubmitBlockGasLimit(height uint64, maxBytes int64, maxGas int64) error {
+	return nil
+}
+
+func EndBlock(height uint64) error {
+	return nil
+}
+
 func LoadSeedToEnclave(masterKey []byte, seed []byte, apiKey []byte) (bool, error) {
 	return true, nil
 }
@@ -71,7 +111,7 @@ func ReleaseCache(cache Cache) {
 	// C.release_cache(cache.ptr)
 }
 
-func InitEnclaveRuntime(ModuleCacheSize uint16) error {
+func InitEnclaveRuntime(ModuleCacheSize uint16, StateAuditMode bool, ExecutionDeadlineMillis uint64, ExecutionReceipts bool, ExecutionTracing bool, PinnedCodeBytes uint64) error {
 	return nil
 }
 
@@ -112,8 +152,8 @@ func Migrate(
 	sigInfo []byte,
 	admin []byte,
 	adminProof []byte,
-) ([]byte, uint64, error) {
-	return nil, 0, nil
+) ([]byte, []byte, uint64, error) {
+	return nil, nil, 0, nil
 }
 
 func UpdateAdmin(
@@ -145,7 +185,7 @@ func Instantiate(
 	gasLimit uint64,
 	sigInfo []byte,
 	admin []byte,
-) ([]byte, uint64, error) {
+) ([]byte, []byte, uint64, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
 	//p := sendSlice(params)
@@ -163,7 +203,7 @@ func Instantiate(
 	//	return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	//}
 	//return receiveVector(res), uint64(gasUsed), nil
-	return nil, 0, nil
+	return nil, nil, 0, nil
 }
 
 func Handle(
@@ -178,7 +218,7 @@ func Handle(
 	gasLimit uint64,
 	sigInfo []byte,
 	handleType types.HandleType,
-) ([]byte, uint64, error) {
+) ([]byte, []byte, []byte, []byte, uint64, error) {
 	//id := sendSlice(code_id)
 	//defer freeAfterSend(id)
 	//p := sendSlice(params)
@@ -196,7 +236,7 @@ func Handle(
 	//	return nil, uint64(gasUsed), errorWithMessage(err, errmsg)
 	//}
 	//return receiveVector(res), uint64(gasUsed), nil
-	return nil, 0, nil
+	return nil, nil, nil, nil, 0, nil
 }
 
 func Query(
@@ -228,6 +268,35 @@ func Query(
 	return nil, 0, nil
 }
 
+func ExportUserState(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	admin []byte,
+	adminProof []byte,
+	request []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
+func CountStorageUsage(
+	cache Cache,
+	code_id []byte,
+	params []byte,
+	gasMeter *GasMeter,
+	store KVStore,
+	api *GoAPI,
+	querier *Querier,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	return nil, 0, nil
+}
+
 func AnalyzeCode(
 	cache Cache,
 	codeHash []byte,
@@ -260,6 +329,14 @@ func KeyGen() ([]byte, error) {
 }
 
 // KeyGen Seng KeyGen request to enclave
+func GetAttestationValidity() (uint64, uint64, error) {
+	return 0, 0, nil
+}
+
+func RenewAttestationIfDue(apiKey []byte, no_epid bool, no_dcap bool) (bool, error) {
+	return false, nil
+}
+
 func CreateAttestationReport(apiKey []byte, no_epid bool, no_dcap bool) (bool, error) {
 	//errmsg := C.Buffer{}
 	//_, err := C.create_attestation_report(&errmsg)
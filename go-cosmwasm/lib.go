@@ -3,6 +3,7 @@ package cosmwasm
 import (
 	"encoding/json"
 	"fmt"
+	"sync"
 
 	"github.com/scrtlabs/SecretNetwork/go-cosmwasm/api"
 	types "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
@@ -32,7 +33,15 @@ type GasMeter = api.GasMeter
 // You should create an instance with it's own subdirectory to manage state inside,
 // and call it for all cosmwasm code related actions.
 type Wasmer struct {
-	cache api.Cache
+	cache        api.Cache
+	writeIntents *blockWriteIntents
+}
+
+// blockWriteIntents collects the write intents of the executions of a block, which are
+// acknowledged together once the block is committed
+type blockWriteIntents struct {
+	mtx     sync.Mutex
+	intents []byte
 }
 
 // NewWasmer creates a new binding, with the given dataDir where
@@ -40,17 +49,27 @@ type Wasmer struct {
 // cacheSize sets the size of an optional in-memory LRU cache for prepared VMs.
 // They allow popular contracts to be executed very rapidly (no loading overhead),
 // but require ~32-64MB each in memory usage.
-func NewWasmer(dataDir string, supportedFeatures string, cacheSize uint64, moduleCacheSize uint16) (*Wasmer, error) {
+// stateAuditMode makes the enclave record the encrypted writes of every execution in a sealed
+// log, see api.ExportStateAuditLog.
+// executionDeadlineMillis aborts the queries the node serves that run for longer than that many
+// milliseconds, regardless of their remaining gas. 0 disables the deadline.
+// executionReceipts makes the enclave sign a receipt of every successful execution of a delivered
+// transaction, which Execute returns, see api.GetExecutionReceiptKey.
+// executionTracing makes the enclave export the time every execution spends in each of its
+// stages, which is logged.
+// pinnedCodeBytes is how many bytes of large contract binaries the enclave keeps in its memory,
+// so they aren't copied into it on every call. 0 disables pinning.
+func NewWasmer(dataDir string, supportedFeatures string, cacheSize uint64, moduleCacheSize uint16, stateAuditMode bool, executionDeadlineMillis uint64, executionReceipts bool, executionTracing bool, pinnedCodeBytes uint64) (*Wasmer, error) {
 	cache, err := api.InitCache(dataDir, supportedFeatures, cacheSize)
 	if err != nil {
 		return nil, err
 	}
-	err = api.InitEnclaveRuntime(moduleCacheSize)
+	err = api.InitEnclaveRuntime(moduleCacheSize, stateAuditMode, executionDeadlineMillis, executionReceipts, executionTracing, pinnedCodeBytes)
 	if err != nil {
 		return nil, err
 	}
 
-	return &Wasmer{cache: cache}, nil
+	return &Wasmer{cache: cache, writeIntents: &blockWriteIntents{}}, nil
 }
 
 // Cleanup should be called when no longer using this to free resources on the rust-side
@@ -152,20 +171,20 @@ func (w *Wasmer) Instantiate(
 		return nil, nil, nil, 0, err
 	}
 
-	data, gasUsed, err := api.Instantiate(w.cache, codeId, paramBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, admin)
+	data, writeIntent, gasUsed, err := api.Instantiate(w.cache, codeId, paramBin, initMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, admin)
 	if err != nil {
 		return nil, nil, nil, gasUsed, err
 	}
+	w.collectWriteIntent(writeIntent)
 
 	key := data[0:64]
 	adminProof := data[64:96]
 	data = data[96:]
 
-	var respV010orV1 V010orV1ContractInitResponse
-	err = json.Unmarshal(data, &respV010orV1)
+	respV010orV1, err := parseInitResponse(data)
 	if err != nil {
 		// unidentified response 🤷
-		return nil, nil, nil, gasUsed, fmt.Errorf("instantiate: cannot parse response from json: %w", err)
+		return nil, nil, nil, gasUsed, fmt.Errorf("instantiate: cannot parse response: %w", err)
 	}
 
 	isOutputAddressedToReply := len(respV010orV1.InternaReplyEnclaveSig) > 0 && len(respV010orV1.InternalMsgId) > 0
@@ -217,6 +236,34 @@ func (w *Wasmer) Instantiate(
 	return nil, nil, nil, gasUsed, fmt.Errorf("instantiate: cannot detect response type (v0.10 or v1)")
 }
 
+// collectWriteIntent keeps the intent of an execution until its block is committed. Only the
+// executions of delivered transactions return one.
+func (w *Wasmer) collectWriteIntent(writeIntent []byte) {
+	if len(writeIntent) == 0 {
+		return
+	}
+	w.writeIntents.mtx.Lock()
+	defer w.writeIntents.mtx.Unlock()
+	w.writeIntents.intents = append(w.writeIntents.intents, writeIntent...)
+}
+
+// AcknowledgeWriteIntents tells the enclave that the block whose executions returned the collected
+// intents is committed, so their writes are in the store. The writes of transactions that were
+// reverted after an execution are dropped rather than committed, and their intents are
+// acknowledged with the block all the same. The enclave reports the intents that are never
+// acknowledged, so a failure here doesn't fail the commit.
+func (w *Wasmer) AcknowledgeWriteIntents() {
+	w.writeIntents.mtx.Lock()
+	intents := w.writeIntents.intents
+	w.writeIntents.intents = nil
+	w.writeIntents.mtx.Unlock()
+
+	if len(intents) == 0 {
+		return
+	}
+	_ = api.AcknowledgeWriteIntents(intents)
+}
+
 func AppendReplyInternalDataToData(data []byte, internaReplyEnclaveSig []byte, internalMsgId []byte) ([]byte, error) {
 	dataWithInternalReply := v1types.DataWithInternalReplyInfo{
 		InternaReplyEnclaveSig: internaReplyEnclaveSig,
@@ -233,6 +280,9 @@ func AppendReplyInternalDataToData(data []byte, internaReplyEnclaveSig []byte, i
 //
 // The caller is responsible for passing the correct `store` (which must have been initialized exactly once),
 // and setting the env with relevant info on this instance (address, balance, etc)
+//
+// The receipt is the JSON of the receipt the enclave signed for the execution, which is only
+// set when the node enables receipts and the execution is part of a delivered transaction.
 func (w *Wasmer) Execute(
 	code CodeHash,
 	env types.Env,
@@ -244,26 +294,33 @@ func (w *Wasmer) Execute(
 	gasLimit uint64,
 	sigInfo types.SigInfo,
 	handleType types.HandleType,
-) (interface{}, uint64, error) {
+) (response interface{}, receipt []byte, gasUsed uint64, err error) {
 	paramBin, err := json.Marshal(env)
 	if err != nil {
-		return nil, 0, err
+		return nil, nil, 0, err
 	}
 	sigInfoBin, err := json.Marshal(sigInfo)
 	if err != nil {
-		return nil, 0, err
+		return nil, nil, 0, err
 	}
 
-	data, gasUsed, err := api.Handle(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, handleType)
+	// The touched keys aren't used yet
+	data, _, receipt, writeIntent, gasUsed, err := api.Handle(w.cache, code, paramBin, executeMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, handleType)
 	if err != nil {
-		return nil, gasUsed, err
+		return nil, nil, gasUsed, err
 	}
+	w.collectWriteIntent(writeIntent)
 
-	var resp ContractExecResponse
-	err = json.Unmarshal(data, &resp)
+	response, gasUsed, err = parseHandleResponse(data, gasUsed)
+	return response, receipt, gasUsed, err
+}
+
+// parseHandleResponse parses the output of a handle into the response of its handle type
+func parseHandleResponse(data []byte, gasUsed uint64) (interface{}, uint64, error) {
+	resp, err := parseExecResponse(data)
 	if err != nil {
 		// unidentified response 🤷
-		return nil, gasUsed, fmt.Errorf("handle: cannot parse response from json: %w", err)
+		return nil, gasUsed, fmt.Errorf("handle: cannot parse response: %w", err)
 	}
 
 	isOutputAddressedToReply := len(resp.InternaReplyEnclaveSig) > 0 && len(resp.InternalMsgId) > 0
@@ -314,6 +371,7 @@ func (w *Wasmer) Execute(
 		if resp.IBCBasic.Err != nil { //nolint:gocritic
 			return nil, gasUsed, fmt.Errorf("%+v", resp.IBCBasic.Err)
 		} else if resp.IBCBasic.Ok != nil {
+			resp.IBCBasic.Ok.Data = resp.IBCBasic.Data
 			return resp.IBCBasic.Ok, gasUsed, nil
 		} else {
 			return nil, gasUsed, fmt.Errorf("cannot parse IBCBasic response: %+v", resp)
@@ -366,8 +424,7 @@ func (w *Wasmer) Query(
 		return nil, gasUsed, err
 	}
 
-	var resp types.ContractQueryResponse
-	err = json.Unmarshal(data, &resp)
+	resp, err := parseQueryResponse(data)
 	if err != nil {
 		return nil, gasUsed, err
 	}
@@ -387,6 +444,53 @@ func (w *Wasmer) Query(
 	return nil, gasUsed, fmt.Errorf("query: cannot detect response type")
 }
 
+// ExportUserState exports the state entries of a user of a contract. The request must be signed
+// by both the user and the admin of the contract, and the export is encrypted for the user.
+// The code isn't executed, so env must carry the hash of the code of the contract.
+func (w *Wasmer) ExportUserState(
+	code CodeHash,
+	env types.Env,
+	admin []byte,
+	adminProof []byte,
+	request []byte,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+	return api.ExportUserState(w.cache, code, paramBin, admin, adminProof, request, &gasMeter, store, &goapi, &querier, gasLimit)
+}
+
+// CountStorageUsage counts a page of the storage usage of a contract that stored entries before
+// its usage was accounted. The result says whether the count of the contract is done. The code
+// isn't executed, so env must carry the hash of the code of the contract.
+func (w *Wasmer) CountStorageUsage(
+	code CodeHash,
+	env types.Env,
+	store KVStore,
+	goapi GoAPI,
+	querier Querier,
+	gasMeter GasMeter,
+	gasLimit uint64,
+) ([]byte, uint64, error) {
+	paramBin, err := json.Marshal(env)
+	if err != nil {
+		return nil, 0, err
+	}
+	return api.CountStorageUsage(w.cache, code, paramBin, &gasMeter, store, &goapi, &querier, gasLimit)
+}
+
+// GetTxFailureReport returns the failure report of a transaction of one of the recent blocks,
+// encrypted for its sender. The reports are kept in the memory of the enclave of this node only.
+func (w *Wasmer) GetTxFailureReport(txHash []byte, nonce []byte, userPublicKey []byte) ([]byte, error) {
+	return api.GetTxFailureReport(txHash, nonce, userPublicKey)
+}
+
 // AnalyzeCode returns a report of static analysis of the wasm contract (uncompiled).
 // This contract must have been stored in the cache previously (via Create).
 // Only info currently returned is if it exposes all ibc entry points, but this may grow later
@@ -426,20 +530,20 @@ func (w *Wasmer) Migrate(
 		return nil, nil, nil, 0, err
 	}
 
-	data, gasUsed, err := api.Migrate(w.cache, newCodeId, paramBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, admin, adminProof)
+	data, writeIntent, gasUsed, err := api.Migrate(w.cache, newCodeId, paramBin, migrateMsg, &gasMeter, store, &goapi, &querier, gasLimit, sigInfoBin, admin, adminProof)
 	if err != nil {
 		return nil, nil, nil, gasUsed, err
 	}
+	w.collectWriteIntent(writeIntent)
 
 	newContractKey := data[0:64]
 	proof := data[64:96]
 	data = data[96:]
 
-	var respV010orV1 ContractExecResponse
-	err = json.Unmarshal(data, &respV010orV1)
+	respV010orV1, err := parseExecResponse(data)
 	if err != nil {
 		// unidentified response 🤷
-		return nil, nil, nil, gasUsed, fmt.Errorf("migrate: cannot parse response from json: %w", err)
+		return nil, nil, nil, gasUsed, fmt.Errorf("migrate: cannot parse response: %w", err)
 	}
 
 	isOutputAddressedToReply := len(respV010orV1.InternaReplyEnclaveSig) > 0 && len(respV010orV1.InternalMsgId) > 0
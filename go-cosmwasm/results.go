@@ -0,0 +1,112 @@
+package cosmwasm
+
+import (
+	"encoding/json"
+	"fmt"
+
+	types "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
+	v1types "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types/v1"
+)
+
+// The enclave returns results as a protobuf envelope from FFI version 2 on, and as JSON before.
+// These parse both into the same responses.
+
+func parseExecResponse(data []byte) (*ContractExecResponse, error) {
+	var resp ContractExecResponse
+	if !types.IsResultEnvelope(data) {
+		err := json.Unmarshal(data, &resp)
+		return &resp, err
+	}
+
+	envelope, err := types.DecodeResultEnvelope(data)
+	if err != nil {
+		return nil, err
+	}
+	resp.InternaReplyEnclaveSig = envelope.InternalReplyEnclaveSig
+	resp.InternalMsgId = envelope.InternalMsgId
+
+	switch envelope.Kind {
+	case types.ResultKindV010:
+		resp.V010 = &V010ContractExecResponse{}
+		err = envelope.UnmarshalResult(&resp.V010.Ok, &resp.V010.Err)
+	case types.ResultKindV1:
+		resp.V1 = &V1ContractExecResponse{}
+		err = envelope.UnmarshalResult(&resp.V1.Ok, &resp.V1.Err)
+	case types.ResultKindIBCBasic:
+		resp.IBCBasic = &v1types.IBCBasicResult{Data: envelope.Data}
+		err = envelope.UnmarshalResult(&resp.IBCBasic.Ok, &resp.IBCBasic.Err)
+	case types.ResultKindIBCPacketReceive:
+		resp.IBCPacketReceive = &v1types.IBCReceiveResult{}
+		err = envelope.UnmarshalResult(&resp.IBCPacketReceive.Ok, &resp.IBCPacketReceive.Err)
+	case types.ResultKindIBCOpenChannel:
+		resp.IBCChannelOpen = &v1types.IBCOpenChannelResult{}
+		err = envelope.UnmarshalResult(&resp.IBCChannelOpen.Ok, &resp.IBCChannelOpen.Err)
+	case types.ResultKindUnspecified:
+	default:
+		return nil, fmt.Errorf("unexpected result kind %d", envelope.Kind)
+	}
+	if err != nil {
+		return nil, err
+	}
+
+	return &resp, nil
+}
+
+func parseInitResponse(data []byte) (*V010orV1ContractInitResponse, error) {
+	var resp V010orV1ContractInitResponse
+	if !types.IsResultEnvelope(data) {
+		err := json.Unmarshal(data, &resp)
+		return &resp, err
+	}
+
+	envelope, err := types.DecodeResultEnvelope(data)
+	if err != nil {
+		return nil, err
+	}
+	resp.InternaReplyEnclaveSig = envelope.InternalReplyEnclaveSig
+	resp.InternalMsgId = envelope.InternalMsgId
+
+	switch envelope.Kind {
+	case types.ResultKindV010:
+		resp.V010 = &V010ContractInitResponse{}
+		err = envelope.UnmarshalResult(&resp.V010.Ok, &resp.V010.Err)
+	case types.ResultKindV1:
+		resp.V1 = &V1ContractInitResponse{}
+		err = envelope.UnmarshalResult(&resp.V1.Ok, &resp.V1.Err)
+	case types.ResultKindUnspecified:
+	default:
+		return nil, fmt.Errorf("unexpected result kind %d", envelope.Kind)
+	}
+	if err != nil {
+		return nil, err
+	}
+
+	return &resp, nil
+}
+
+func parseQueryResponse(data []byte) (*types.ContractQueryResponse, error) {
+	var resp types.ContractQueryResponse
+	if !types.IsResultEnvelope(data) {
+		err := json.Unmarshal(data, &resp)
+		return &resp, err
+	}
+
+	envelope, err := types.DecodeResultEnvelope(data)
+	if err != nil {
+		return nil, err
+	}
+
+	switch envelope.Kind {
+	case types.ResultKindQuery:
+		resp.Query = &types.QueryResponse{}
+		err = envelope.UnmarshalResult(&resp.Query.Ok, &resp.Query.Err)
+	case types.ResultKindUnspecified:
+	default:
+		return nil, fmt.Errorf("unexpected result kind %d", envelope.Kind)
+	}
+	if err != nil {
+		return nil, err
+	}
+
+	return &resp, nil
+}
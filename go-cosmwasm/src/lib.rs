@@ -21,13 +21,17 @@ use crate::error::{clear_error, handle_c_error, handle_c_error_default, set_erro
 
 use cosmwasm_sgx_vm::untrusted_init_bootstrap;
 use cosmwasm_sgx_vm::{
-    call_handle_raw, call_init_raw, call_migrate_raw, call_query_raw, call_update_admin_raw,
-    features_from_csv, Checksum, CosmCache, Extern,
+    call_count_storage_usage_raw, call_export_user_state_raw, call_handle_raw, call_init_raw,
+    call_migrate_raw, call_query_raw, call_update_admin_raw, features_from_csv, Checksum,
+    CosmCache, Extern,
 };
 use cosmwasm_sgx_vm::{
-    create_attestation_report_u, untrusted_get_encrypted_genesis_seed,
-    untrusted_get_encrypted_seed, untrusted_health_check, untrusted_init_node, untrusted_key_gen,
-    untrusted_migrate_sealing,
+    create_attestation_report_u, untrusted_acknowledge_write_intents, untrusted_analyze_contract,
+    untrusted_calibrate_crypto_gas, untrusted_export_state_audit_log,
+    untrusted_get_enclave_manifest, untrusted_get_enclave_metrics,
+    untrusted_get_encrypted_genesis_seed, untrusted_get_encrypted_seed,
+    untrusted_get_execution_receipt_key, untrusted_get_tx_failure_report, untrusted_health_check,
+    untrusted_init_node, untrusted_key_gen, untrusted_migrate_sealing,
 };
 
 use ctor::ctor;
@@ -65,6 +69,148 @@ pub extern "C" fn get_health_check(err: Option<&mut Buffer>) -> Buffer {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn analyze_contract(wasm: Buffer, err: Option<&mut Buffer>) -> Buffer {
+    let wasm = match unsafe { wasm.read() } {
+        None => {
+            set_error(Error::empty_arg(WASM_ARG), err);
+            return Buffer::default();
+        }
+        Some(r) => r,
+    };
+    match untrusted_analyze_contract(wasm) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(report) => {
+            clear_error();
+            Buffer::from_vec(report)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_tx_failure_report(
+    tx_hash: Buffer,
+    nonce: Buffer,
+    user_public_key: Buffer,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match (unsafe { tx_hash.read() }, unsafe { nonce.read() }, unsafe {
+        user_public_key.read()
+    }) {
+        (None, _, _) => Err(Error::empty_arg(TX_HASH_ARG)),
+        (_, None, _) => Err(Error::empty_arg(NONCE_ARG)),
+        (_, _, None) => Err(Error::empty_arg(USER_PUBLIC_KEY_ARG)),
+        (Some(tx_hash), Some(nonce), Some(user_public_key)) => {
+            untrusted_get_tx_failure_report(tx_hash, nonce, user_public_key)
+                .map_err(|e| Error::enclave_err(e.to_string()))
+        }
+    };
+    match r {
+        Err(e) => {
+            set_error(e, err);
+            Buffer::default()
+        }
+        Ok(report) => {
+            clear_error();
+            Buffer::from_vec(report)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_enclave_manifest(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_enclave_manifest() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(manifest) => {
+            clear_error();
+            Buffer::from_vec(manifest)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_execution_receipt_key(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_execution_receipt_key() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(key) => {
+            clear_error();
+            Buffer::from_vec(key)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn get_enclave_metrics(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_get_enclave_metrics() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(metrics) => {
+            clear_error();
+            Buffer::from_vec(metrics)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn calibrate_crypto_gas(iterations: u32, err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_calibrate_crypto_gas(iterations) {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(report) => {
+            clear_error();
+            Buffer::from_vec(report)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn export_state_audit_log(err: Option<&mut Buffer>) -> Buffer {
+    match untrusted_export_state_audit_log() {
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            Buffer::default()
+        }
+        Ok(log) => {
+            clear_error();
+            Buffer::from_vec(log)
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn acknowledge_write_intents(intents: Buffer, err: Option<&mut Buffer>) -> bool {
+    let intents = match unsafe { intents.read() } {
+        None => {
+            set_error(Error::empty_arg(INTENTS_ARG), err);
+            return false;
+        }
+        Some(r) => r,
+    };
+    match untrusted_acknowledge_write_intents(intents) {
+        Ok(()) => {
+            clear_error();
+            true
+        }
+        Err(e) => {
+            set_error(Error::enclave_err(e.to_string()), err);
+            false
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn get_encrypted_seed(cert: Buffer, err: Option<&mut Buffer>) -> Buffer {
     trace!("Called get_encrypted_seed");
@@ -217,6 +363,61 @@ pub extern "C" fn create_attestation_report(
     true
 }
 
+#[no_mangle]
+pub extern "C" fn get_attestation_validity(
+    expires_at: Option<&mut u64>,
+    remaining_secs: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let (expires_at, remaining_secs) = match (expires_at, remaining_secs) {
+        (Some(expires_at), Some(remaining_secs)) => (expires_at, remaining_secs),
+        _ => {
+            set_error(Error::empty_arg("expires_at"), err);
+            return false;
+        }
+    };
+
+    match cosmwasm_sgx_vm::untrusted_get_attestation_validity() {
+        Err(status) => {
+            set_error(Error::enclave_err(status.to_string()), err);
+            false
+        }
+        Ok((expires, remaining)) => {
+            *expires_at = expires;
+            *remaining_secs = remaining;
+            clear_error();
+            true
+        }
+    }
+}
+
+/// Returns whether a new attestation report was created
+#[no_mangle]
+pub extern "C" fn renew_attestation_if_due(
+    api_key: Buffer,
+    flags: u32,
+    err: Option<&mut Buffer>,
+) -> bool {
+    let api_key_slice = match unsafe { api_key.read() } {
+        None => {
+            set_error(Error::empty_arg("api_key"), err);
+            return false;
+        }
+        Some(r) => r,
+    };
+
+    match cosmwasm_sgx_vm::untrusted_renew_attestation_if_due(api_key_slice, flags) {
+        Err(status) => {
+            set_error(Error::enclave_err(status.to_string()), err);
+            false
+        }
+        Ok(renewed) => {
+            clear_error();
+            renewed
+        }
+    }
+}
+
 fn to_extern(storage: DB, api: GoApi, querier: GoQuerier) -> Extern<DB, GoApi, GoQuerier> {
     Extern {
         storage,
@@ -325,6 +526,35 @@ pub extern "C" fn submit_block_signatures(
     }
 }
 
+#[no_mangle]
+pub extern "C" fn submit_block_gas_limit(
+    block_height: u64,
+    block_max_bytes: i64,
+    block_max_gas: i64,
+    err: Option<&mut Buffer>,
+) -> bool {
+    if let Err(e) = cosmwasm_sgx_vm::untrusted_submit_block_gas_limit(
+        block_height,
+        block_max_bytes,
+        block_max_gas,
+    ) {
+        set_error(Error::enclave_err(e.to_string()), err);
+        return false;
+    }
+    clear_error();
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn end_block(block_height: u64, err: Option<&mut Buffer>) -> bool {
+    if let Err(e) = cosmwasm_sgx_vm::untrusted_end_block(block_height) {
+        set_error(Error::enclave_err(e.to_string()), err);
+        return false;
+    }
+    clear_error();
+    true
+}
+
 // store some common string for argument names
 static DATA_DIR_ARG: &str = "data_dir";
 static FEATURES_ARG: &str = "supported_features";
@@ -336,7 +566,12 @@ static PARAMS_ARG: &str = "params";
 static GAS_USED_ARG: &str = "gas_used";
 static SIG_INFO_ARG: &str = "sig_info";
 static CURRENT_ADMIN_ARG: &str = "current_admin";
+static REQUEST_ARG: &str = "request";
+static INTENTS_ARG: &str = "intents";
 static CURRENT_ADMIN_PROOF_ARG: &str = "current_admin_proof";
+static TX_HASH_ARG: &str = "tx_hash";
+static NONCE_ARG: &str = "nonce";
+static USER_PUBLIC_KEY_ARG: &str = "user_public_key";
 
 fn do_init_cache(
     data_dir: Buffer,
@@ -371,12 +606,24 @@ pub extern "C" fn release_cache(cache: *mut cache_t) {
 #[repr(C)]
 pub struct EnclaveRuntimeConfig {
     pub module_cache_size: u32,
+    pub state_audit_mode: bool,
+    pub execution_deadline_millis: u64,
+    pub execution_receipts: bool,
+    pub execution_tracing: bool,
+    pub pinned_code_bytes: u64,
+    pub ffi_version: u32,
 }
 
 impl EnclaveRuntimeConfig {
     fn to_sgx_vm(&self) -> cosmwasm_sgx_vm::EnclaveRuntimeConfig {
         cosmwasm_sgx_vm::EnclaveRuntimeConfig {
             module_cache_size: self.module_cache_size,
+            state_audit_mode: self.state_audit_mode,
+            execution_deadline_millis: self.execution_deadline_millis,
+            execution_receipts: self.execution_receipts,
+            execution_tracing: self.execution_tracing,
+            pinned_code_bytes: self.pinned_code_bytes,
+            ffi_version: self.ffi_version,
         }
     }
 }
@@ -446,6 +693,7 @@ pub extern "C" fn instantiate(
     err: Option<&mut Buffer>,
     sig_info: Buffer,
     admin: Buffer,
+    write_intent: Option<&mut Buffer>,
 ) -> Buffer {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
@@ -461,6 +709,7 @@ pub extern "C" fn instantiate(
                 gas_used,
                 sig_info,
                 admin,
+                write_intent,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
@@ -483,6 +732,7 @@ fn do_init(
     gas_used: Option<&mut u64>,
     sig_info: Buffer,
     admin: Buffer,
+    write_intent: Option<&mut Buffer>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
     let code_id: Checksum = unsafe { code_id.read() }
@@ -499,7 +749,11 @@ fn do_init(
     let res = call_init_raw(&mut instance, params, msg, sig_info, admin);
     *gas_used = instance.create_gas_report().used_internally;
     instance.recycle();
-    Ok(res?)
+    let (output, intent) = res?;
+    if let Some(write_intent) = write_intent {
+        *write_intent = Buffer::from_vec(intent);
+    }
+    Ok(output)
 }
 
 #[no_mangle]
@@ -517,6 +771,7 @@ pub extern "C" fn migrate(
     sig_info: Buffer,
     admin: Buffer,
     admin_proof: Buffer,
+    write_intent: Option<&mut Buffer>,
 ) -> Buffer {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
@@ -533,6 +788,7 @@ pub extern "C" fn migrate(
                 sig_info,
                 admin,
                 admin_proof,
+                write_intent,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
@@ -556,6 +812,7 @@ fn do_migrate(
     sig_info: Buffer,
     admin: Buffer,
     admin_proof: Buffer,
+    write_intent: Option<&mut Buffer>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
     let code_id: Checksum = unsafe { code_id.read() }
@@ -574,7 +831,11 @@ fn do_migrate(
     let res = call_migrate_raw(&mut instance, params, msg, sig_info, admin, admin_proof);
     *gas_used = instance.create_gas_report().used_internally;
     instance.recycle();
-    Ok(res?)
+    let (output, intent) = res?;
+    if let Some(write_intent) = write_intent {
+        *write_intent = Buffer::from_vec(intent);
+    }
+    Ok(output)
 }
 
 #[no_mangle]
@@ -669,6 +930,9 @@ pub extern "C" fn handle(
     err: Option<&mut Buffer>,
     sig_info: Buffer,
     handle_type: u8,
+    touched_keys: Option<&mut Buffer>,
+    receipt: Option<&mut Buffer>,
+    write_intent: Option<&mut Buffer>,
 ) -> Buffer {
     let r = match to_cache(cache) {
         Some(c) => catch_unwind(AssertUnwindSafe(move || {
@@ -684,6 +948,9 @@ pub extern "C" fn handle(
                 gas_used,
                 sig_info,
                 handle_type,
+                touched_keys,
+                receipt,
+                write_intent,
             )
         }))
         .unwrap_or_else(|_| Err(Error::panic())),
@@ -706,6 +973,9 @@ fn do_handle(
     gas_used: Option<&mut u64>,
     sig_info: Buffer,
     handle_type: u8,
+    touched_keys: Option<&mut Buffer>,
+    receipt: Option<&mut Buffer>,
+    write_intent: Option<&mut Buffer>,
 ) -> Result<Vec<u8>, Error> {
     let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
     let code_id: Checksum = unsafe { code_id.read() }
@@ -721,7 +991,17 @@ fn do_handle(
     let res = call_handle_raw(&mut instance, params, msg, sig_info, handle_type);
     *gas_used = instance.create_gas_report().used_internally;
     instance.recycle();
-    Ok(res?)
+    let (output, keys, signed_receipt, intent) = res?;
+    if let Some(touched_keys) = touched_keys {
+        *touched_keys = Buffer::from_vec(keys);
+    }
+    if let Some(receipt) = receipt {
+        *receipt = Buffer::from_vec(signed_receipt);
+    }
+    if let Some(write_intent) = write_intent {
+        *write_intent = Buffer::from_vec(intent);
+    }
+    Ok(output)
 }
 
 #[no_mangle]
@@ -777,6 +1057,125 @@ fn do_query(
     Ok(res?)
 }
 
+#[no_mangle]
+pub extern "C" fn export_user_state(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    admin: Buffer,
+    admin_proof: Buffer,
+    request: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_export_user_state(
+                c,
+                code_id,
+                params,
+                admin,
+                admin_proof,
+                request,
+                db,
+                api,
+                querier,
+                gas_limit,
+                gas_used,
+            )
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+fn do_export_user_state(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    params: Buffer,
+    admin: Buffer,
+    admin_proof: Buffer,
+    request: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+    let admin = unsafe { admin.read() }.ok_or_else(|| Error::empty_arg(CURRENT_ADMIN_ARG))?;
+    let admin_proof =
+        unsafe { admin_proof.read() }.ok_or_else(|| Error::empty_arg(CURRENT_ADMIN_PROOF_ARG))?;
+    let request = unsafe { request.read() }.ok_or_else(|| Error::empty_arg(REQUEST_ARG))?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_export_user_state_raw(&mut instance, params, admin, admin_proof, request);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?)
+}
+
+#[no_mangle]
+pub extern "C" fn count_storage_usage(
+    cache: *mut cache_t,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+    err: Option<&mut Buffer>,
+) -> Buffer {
+    let r = match to_cache(cache) {
+        Some(c) => catch_unwind(AssertUnwindSafe(move || {
+            do_count_storage_usage(c, code_id, params, db, api, querier, gas_limit, gas_used)
+        }))
+        .unwrap_or_else(|_| Err(Error::panic())),
+        None => Err(Error::empty_arg(CACHE_ARG)),
+    };
+    let data = handle_c_error(r, err);
+    Buffer::from_vec(data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn do_count_storage_usage(
+    cache: &mut CosmCache<DB, GoApi, GoQuerier>,
+    code_id: Buffer,
+    params: Buffer,
+    db: DB,
+    api: GoApi,
+    querier: GoQuerier,
+    gas_limit: u64,
+    gas_used: Option<&mut u64>,
+) -> Result<Vec<u8>, Error> {
+    let gas_used = gas_used.ok_or_else(|| Error::empty_arg(GAS_USED_ARG))?;
+    let code_id: Checksum = unsafe { code_id.read() }
+        .ok_or_else(|| Error::empty_arg(CODE_ID_ARG))?
+        .try_into()?;
+    let params = unsafe { params.read() }.ok_or_else(|| Error::empty_arg(PARAMS_ARG))?;
+
+    let deps = to_extern(db, api, querier);
+    let mut instance = cache.get_instance(&code_id, deps, gas_limit)?;
+    // We only check this result after reporting gas usage and returning the instance into the cache.
+    let res = call_count_storage_usage_raw(&mut instance, params);
+    *gas_used = instance.create_gas_report().used_internally;
+    instance.recycle();
+    Ok(res?)
+}
+
 /// The result type of the FFI function analyze_code.
 ///
 /// Please note that the unmanaged vector in `required_features`
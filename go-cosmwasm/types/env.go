@@ -1,5 +1,7 @@
 package types
 
+import "encoding/json"
+
 //---------- Env ---------
 
 // Env defines the state of the blockchain environment this contract is
@@ -8,12 +10,76 @@ package types
 //
 // Env are json encoded to a byte slice before passing to the wasm contract.
 type Env struct {
-	Block       BlockInfo        `json:"block"`
-	Message     MessageInfo      `json:"message"`
-	Contract    ContractInfo     `json:"contract"`
-	Key         ContractKey      `json:"contract_key"`
-	QueryDepth  uint32           `json:"query_depth"`
+	Block      BlockInfo    `json:"block"`
+	Message    MessageInfo  `json:"message"`
+	Contract   ContractInfo `json:"contract"`
+	Key        ContractKey  `json:"contract_key"`
+	QueryDepth uint32       `json:"query_depth"`
+	// QueryHeight is set when a query is executed against the state of a past block.
+	// The enclave only accepts it for heights whose header and next header it verified, and
+	// takes the values of the reads from their proofs against the app hash of the next header.
+	QueryHeight *uint64          `json:"query_height,omitempty"`
 	Transaction *TransactionInfo `json:"transaction,omitempty"`
+	// QueryOrigin is where a query comes from. Only queries made by contracts can share the
+	// query cache of the execution that made them.
+	QueryOrigin QueryOrigin `json:"query_origin,omitempty"`
+	// QueryConnection is the address of the client of a query that the node serves, without its
+	// port. The enclave limits the failures to decrypt the queries of each connection.
+	QueryConnection string `json:"query_connection,omitempty"`
+	// DeliverTx is set for executions in transactions that are delivered in a block, rather than
+	// checked or simulated. Only those keep the transient entries of contracts for the rest of the
+	// transaction.
+	DeliverTx bool `json:"deliver_tx,omitempty"`
+	// Execution is the position of the execution among the contract executions of the transaction.
+	Execution uint32 `json:"execution,omitempty"`
+	// RevertedExecution is set in the reply to a submessage that failed, to the position of the
+	// first execution of the submessage. The enclave discards the transient entries from there on.
+	RevertedExecution *uint32 `json:"reverted_execution,omitempty"`
+	// OldCodeHash is the hex encoded hash of the code a contract is migrated from.
+	// It is only set for migrations, and is verified by the enclave against the contract key.
+	OldCodeHash string `json:"old_code_hash,omitempty"`
+	// ContractCodeHash is the hex encoded hash of the code of the contract. It is only set for
+	// state exports, which don't run the code, and is verified by the enclave against the contract key.
+	ContractCodeHash string `json:"contract_code_hash,omitempty"`
+	// ComputeParams are the parameters of the compute module that configure the enclave.
+	// They are never passed to contracts.
+	ComputeParams *ComputeParams `json:"compute_params,omitempty"`
+	// CustomQueryKeyProof proves the CustomQueryResponderKey compute param in the state before the
+	// block, which the enclave verifies against the app hash of the header of the block. Without it
+	// the enclave refuses the custom queries of contracts.
+	CustomQueryKeyProof json.RawMessage `json:"custom_query_key_proof,omitempty"`
+	// GroupProposalProofs prove the accepted group proposals that the transaction executes for the
+	// sender, and their group policies, in the state before the block. Group policies don't sign,
+	// so the enclave only verifies the messages they send against these proposals.
+	GroupProposalProofs []GroupProposalProof `json:"group_proposal_proofs,omitempty"`
+}
+
+// GroupProposalProof proves a proposal of the group module and its group policy
+type GroupProposalProof struct {
+	ProposalID  uint64          `json:"proposal_id"`
+	Proposal    json.RawMessage `json:"proposal"`
+	GroupPolicy json.RawMessage `json:"group_policy"`
+}
+
+// QueryOrigin is where a query comes from, as the host flags it for the enclave
+type QueryOrigin string
+
+const (
+	// QueryOriginNode is a query that the node serves to a client, outside of transactions
+	QueryOriginNode QueryOrigin = "node"
+	// QueryOriginContract is a query that a contract made while it executed
+	QueryOriginContract QueryOrigin = "contract"
+)
+
+// ComputeParams are the parameters of the compute module as the enclave reads them. Each one is
+// the JSON of a table of the enclave, and a missing one keeps the defaults of the enclave.
+type ComputeParams struct {
+	GasCosts                   json.RawMessage `json:"gas_costs,omitempty"`
+	MemoryLimits               json.RawMessage `json:"memory_limits,omitempty"`
+	OutputPadding              json.RawMessage `json:"output_padding,omitempty"`
+	CallbackBindingSequences   json.RawMessage `json:"callback_binding_sequences,omitempty"`
+	AdminRegistry              json.RawMessage `json:"admin_registry,omitempty"`
+	PlaintextAttributeDenylist json.RawMessage `json:"plaintext_attribute_denylist,omitempty"`
 }
 
 type ContractKey struct {
@@ -0,0 +1,105 @@
+package types
+
+import (
+	"encoding/json"
+	"fmt"
+
+	"google.golang.org/protobuf/encoding/protowire"
+)
+
+// ResultKind tells which of the results of a contract a ResultEnvelope holds
+type ResultKind uint32
+
+const (
+	ResultKindUnspecified      ResultKind = 0
+	ResultKindV010             ResultKind = 1
+	ResultKindV1               ResultKind = 2
+	ResultKindIBCBasic         ResultKind = 3
+	ResultKindIBCPacketReceive ResultKind = 4
+	ResultKindIBCOpenChannel   ResultKind = 5
+	ResultKindQuery            ResultKind = 6
+)
+
+// ResultEnvelope is the WasmOutputEnvelope that the enclave returns results in from FFI version
+// 2 on, see secret/compute/v1beta1/wasm_output.proto. Ok and Err hold the JSON of the response of
+// the contract and of its error.
+type ResultEnvelope struct {
+	Kind                    ResultKind
+	Ok                      []byte
+	Err                     []byte
+	Data                    []byte
+	InternalReplyEnclaveSig []byte
+	InternalMsgId           []byte
+}
+
+// IsResultEnvelope tells a result envelope, which starts with its kind, from a JSON result
+func IsResultEnvelope(data []byte) bool {
+	return len(data) > 0 && data[0] == byte(protowire.EncodeTag(1, protowire.VarintType))
+}
+
+// DecodeResultEnvelope decodes a result envelope. Fields it doesn't know are skipped, so the
+// enclave can add fields to the envelope.
+func DecodeResultEnvelope(data []byte) (*ResultEnvelope, error) {
+	envelope := &ResultEnvelope{}
+
+	for len(data) > 0 {
+		num, typ, n := protowire.ConsumeTag(data)
+		if n < 0 {
+			return nil, fmt.Errorf("cannot decode result envelope: %w", protowire.ParseError(n))
+		}
+		data = data[n:]
+
+		var field *[]byte
+		switch {
+		case num == 1 && typ == protowire.VarintType:
+			kind, n := protowire.ConsumeVarint(data)
+			if n < 0 {
+				return nil, fmt.Errorf("cannot decode result kind: %w", protowire.ParseError(n))
+			}
+			envelope.Kind = ResultKind(kind)
+			data = data[n:]
+			continue
+		case num == 2 && typ == protowire.BytesType:
+			field = &envelope.Ok
+		case num == 3 && typ == protowire.BytesType:
+			field = &envelope.Err
+		case num == 4 && typ == protowire.BytesType:
+			field = &envelope.Data
+		case num == 5 && typ == protowire.BytesType:
+			field = &envelope.InternalReplyEnclaveSig
+		case num == 6 && typ == protowire.BytesType:
+			field = &envelope.InternalMsgId
+		default:
+			n = protowire.ConsumeFieldValue(num, typ, data)
+			if n < 0 {
+				return nil, fmt.Errorf("cannot skip field %d of result envelope: %w", num, protowire.ParseError(n))
+			}
+			data = data[n:]
+			continue
+		}
+
+		value, n := protowire.ConsumeBytes(data)
+		if n < 0 {
+			return nil, fmt.Errorf("cannot decode field %d of result envelope: %w", num, protowire.ParseError(n))
+		}
+		*field = value
+		data = data[n:]
+	}
+
+	return envelope, nil
+}
+
+// UnmarshalResult unmarshals the response and the error of the envelope, if it has them
+func (e *ResultEnvelope) UnmarshalResult(ok interface{}, err interface{}) error {
+	if e.Ok != nil {
+		if jsonErr := json.Unmarshal(e.Ok, ok); jsonErr != nil {
+			return fmt.Errorf("cannot parse the response in the result envelope: %w", jsonErr)
+		}
+	}
+	if e.Err != nil {
+		if jsonErr := json.Unmarshal(e.Err, err); jsonErr != nil {
+			return fmt.Errorf("cannot parse the error in the result envelope: %w", jsonErr)
+		}
+	}
+	return nil
+}
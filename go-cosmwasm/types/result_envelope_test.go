@@ -0,0 +1,43 @@
+package types
+
+import (
+	"testing"
+
+	"github.com/stretchr/testify/assert"
+	"github.com/stretchr/testify/require"
+	"google.golang.org/protobuf/encoding/protowire"
+)
+
+func TestDecodeResultEnvelope(t *testing.T) {
+	var bz []byte
+	bz = protowire.AppendTag(bz, 1, protowire.VarintType)
+	bz = protowire.AppendVarint(bz, uint64(ResultKindQuery))
+	bz = protowire.AppendTag(bz, 2, protowire.BytesType)
+	bz = protowire.AppendBytes(bz, []byte(`"b2s="`))
+	bz = protowire.AppendTag(bz, 6, protowire.BytesType)
+	bz = protowire.AppendBytes(bz, []byte{1, 2})
+	// A field of a later version
+	bz = protowire.AppendTag(bz, 15, protowire.BytesType)
+	bz = protowire.AppendBytes(bz, []byte{0xff})
+
+	require.True(t, IsResultEnvelope(bz))
+	envelope, err := DecodeResultEnvelope(bz)
+	require.NoError(t, err)
+	assert.Equal(t, ResultKindQuery, envelope.Kind)
+	assert.Equal(t, []byte{1, 2}, envelope.InternalMsgId)
+	assert.Nil(t, envelope.Err)
+
+	var ok []byte
+	var stdErr *StdError
+	require.NoError(t, envelope.UnmarshalResult(&ok, &stdErr))
+	assert.Equal(t, []byte("ok"), ok)
+	assert.Nil(t, stdErr)
+
+	_, err = DecodeResultEnvelope(bz[:len(bz)-1])
+	require.Error(t, err)
+}
+
+func TestIsResultEnvelope(t *testing.T) {
+	assert.False(t, IsResultEnvelope([]byte(`{"query":{"Ok":"b2s="}}`)))
+	assert.False(t, IsResultEnvelope(nil))
+}
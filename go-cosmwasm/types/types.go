@@ -66,6 +66,15 @@ type SigInfo struct {
 	PublicKey         []byte `json:"public_key"`
 	Signature         []byte `json:"signature"`
 	CallbackSignature []byte `json:"callback_sig"` // Optional
+	// The signatures of the other signers of the tx, in the order of its signer infos
+	OtherSigners []SignerSigInfo `json:"other_signers,omitempty"`
+}
+
+type SignerSigInfo struct {
+	SignBytes []byte `json:"sign_bytes"`
+	SignMode  string `json:"sign_mode"`
+	PublicKey []byte `json:"public_key"`
+	Signature []byte `json:"signature"`
 }
 
 type HandleType int
@@ -82,6 +91,12 @@ const (
 	HandleTypeIbcWasmHooksIncomingTransfer
 	HandleTypeIbcWasmHooksOutgoingTransferAck
 	HandleTypeIbcWasmHooksOutgoingTransferTimeout
+	HandleTypeIbcWasmHooksIncomingNftTransfer
+	// The steps of the channel upgrade handshake of ibc-go v8
+	HandleTypeIbcChannelUpgradeInit
+	HandleTypeIbcChannelUpgradeTry
+	HandleTypeIbcChannelUpgradeAck
+	HandleTypeIbcChannelUpgradeConfirm
 )
 
 type CosmosMsgVersion int
@@ -234,6 +234,8 @@ type IBC3ChannelOpenResponse struct {
 type IBCBasicResult struct {
 	Ok  *IBCBasicResponse `json:"ok,omitempty"`
 	Err *types.StdError   `json:"Err,omitempty"`
+	// Data is set by the enclave for plaintext outputs, which can return data to a reply
+	Data []byte `json:"data,omitempty"`
 }
 
 // IBCBasicResponse defines the return value on a successful processing.
@@ -249,6 +251,8 @@ type IBCBasicResponse struct {
 	// custom events (separate from the main one that contains the attributes
 	// above)
 	Events []Event `json:"events"`
+	// Data is copied from IBCBasicResult, contracts don't set it
+	Data []byte `json:"-"`
 }
 
 // This is the return value for the majority of the ibc handlers.
@@ -71,6 +71,7 @@ type SubMsg struct {
 	GasLimit        *uint64   `json:"gas_limit,omitempty"`
 	ReplyOn         replyOn   `json:"reply_on"`
 	WasMsgEncrypted bool      `json:"was_msg_encrypted"`
+	Payload         []byte    `json:"payload,omitempty"`
 }
 
 type Reply struct {
@@ -78,6 +79,7 @@ type Reply struct {
 	Result              SubMsgResult `json:"result"`
 	WasOrigMsgEncrypted bool         `json:"was_orig_msg_encrypted"`
 	IsEncrypted         bool         `json:"is_encrypted"`
+	Payload             []byte       `json:"payload,omitempty"`
 }
 
 // SubcallResult is the raw response we return from the sdk -> reply after executing a SubMsg.
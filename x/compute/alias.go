@@ -128,4 +128,5 @@ type (
 	QueryHandler               = keeper.QueryHandler
 	CustomQuerier              = keeper.CustomQuerier
 	QueryPlugins               = keeper.QueryPlugins
+	StateQuerier               = keeper.StateQuerier
 )
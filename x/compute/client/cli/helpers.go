@@ -1,15 +1,27 @@
 package cli
 
-import "fmt"
+import (
+	"fmt"
+
+	wasmUtils "github.com/scrtlabs/SecretNetwork/x/compute/client/utils"
+)
+
+// parseEncryptedBlob splits an envelope into its version, nonce, sender public key and ciphertext.
+// The ciphertext of XChaCha20-Poly1305 envelopes starts with its own 24 byte nonce, which
+// WASMContext.DecryptEnvelope splits from it.
+func parseEncryptedBlob(blob []byte) (wasmUtils.EnvelopeVersion, []byte, []byte, []byte, error) {
+	version, blob, err := wasmUtils.SplitEnvelopeVersion(blob)
+	if err != nil {
+		return 0, nil, nil, nil, err
+	}
 
-func parseEncryptedBlob(blob []byte) ([]byte, []byte, []byte, error) {
 	if len(blob) < 64 {
-		return nil, nil, nil, fmt.Errorf("input must be > 64 bytes. Got %d", len(blob))
+		return 0, nil, nil, nil, fmt.Errorf("input must be > 64 bytes. Got %d", len(blob))
 	}
 
 	nonce := blob[0:32]
 	originalTxSenderPubkey := blob[32:64]
 	ciphertextInput := blob[64:]
 
-	return nonce, originalTxSenderPubkey, ciphertextInput, nil
+	return version, nonce, originalTxSenderPubkey, ciphertextInput, nil
 }
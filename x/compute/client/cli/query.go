@@ -52,6 +52,8 @@ func GetQueryCmd() *cobra.Command {
 		GetCmdCodeHashByCodeID(),
 		CmdDecryptText(),
 		GetCmdGetContractHistory(),
+		GetCmdExportUserState(),
+		GetCmdTxFailureReport(),
 	)
 	return queryCmd
 }
@@ -314,7 +316,7 @@ func CmdDecryptText() *cobra.Command {
 				return fmt.Errorf("error while trying to decode the encrypted output data from base64: %w", err)
 			}
 
-			nonce, originalTxSenderPubkey, ciphertextInput, err := parseEncryptedBlob(dataCipherBz)
+			version, nonce, originalTxSenderPubkey, ciphertextInput, err := parseEncryptedBlob(dataCipherBz)
 			if err != nil {
 				return fmt.Errorf("error while parsing encrypted blob: %w", err)
 			}
@@ -329,12 +331,12 @@ func CmdDecryptText() *cobra.Command {
 				return fmt.Errorf("cannot decrypt, not original tx sender")
 			}
 
-			dataPlaintextB64Bz, err := wasmCtx.Decrypt(ciphertextInput, nonce)
+			dataPlaintextB64Bz, err := wasmCtx.DecryptEnvelope(ciphertextInput, nonce, version)
 			if err != nil {
 				return fmt.Errorf("error while trying to decrypt the output data: %w", err)
 			}
 
-			fmt.Printf("Decrypted data: %s\n", dataPlaintextB64Bz)
+			fmt.Printf("Decrypted data: %s\n", wasmUtils.TrimOutputPadding(dataPlaintextB64Bz))
 			return nil
 		},
 	}
@@ -380,6 +382,7 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 				PlaintextError: "",
 			}
 			nonces := make([][]byte, len(txInputs))
+			versions := make([]wasmUtils.EnvelopeVersion, len(txInputs))
 
 			for i, tx := range txInputs {
 				var encryptedInput []byte
@@ -399,7 +402,7 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 				}
 
 				if encryptedInput != nil {
-					nonce, originalTxSenderPubkey, ciphertextInput, err := parseEncryptedBlob(encryptedInput)
+					version, nonce, originalTxSenderPubkey, ciphertextInput, err := parseEncryptedBlob(encryptedInput)
 					if err != nil {
 						return fmt.Errorf("can't parse encrypted blob: %w", err)
 					}
@@ -410,7 +413,7 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 
 					var plaintextInput []byte
 					if len(ciphertextInput) > 0 {
-						plaintextInput, err = wasmCtx.Decrypt(ciphertextInput, nonce)
+						plaintextInput, err = wasmCtx.DecryptEnvelope(ciphertextInput, nonce, version)
 						if err != nil {
 							return fmt.Errorf("error while trying to decrypt the tx input: %w", err)
 						}
@@ -418,6 +421,7 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 
 					answers.Answers[i].Input = string(plaintextInput)
 					nonces[i] = nonce
+					versions[i] = version
 				}
 			}
 
@@ -458,14 +462,14 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 							continue
 						}
 
-						dataPlaintextB64Bz, err := wasmCtx.Decrypt(dataField, nonces[i])
+						dataPlaintextB64Bz, err := wasmCtx.DecryptEnvelope(dataField, nonces[i], versions[i])
 						if err != nil {
 							continue
 						}
-						dataPlaintextB64 := string(dataPlaintextB64Bz)
-						answers.Answers[i].OutputData = dataPlaintextB64
+						dataPlaintextB64Bz = wasmUtils.TrimOutputPadding(dataPlaintextB64Bz)
+						answers.Answers[i].OutputData = string(dataPlaintextB64Bz)
 
-						dataPlaintext, err := base64.StdEncoding.DecodeString(dataPlaintextB64)
+						dataPlaintext, err := wasmUtils.DecodeOutput(dataPlaintextB64Bz)
 						if err != nil {
 							continue
 						}
@@ -490,8 +494,8 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 										continue
 									}
 
-									for _, nonce := range nonces {
-										keyPlaintext, err := wasmCtx.Decrypt(keyCiphertext, nonce)
+									for j, nonce := range nonces {
+										keyPlaintext, err := wasmCtx.DecryptEnvelope(keyCiphertext, nonce, versions[j])
 										if err != nil {
 											continue
 										}
@@ -507,8 +511,8 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 									if err != nil {
 										continue
 									}
-									for _, nonce := range nonces {
-										valuePlaintext, err := wasmCtx.Decrypt(valueCiphertext, nonce)
+									for j, nonce := range nonces {
+										valuePlaintext, err := wasmCtx.DecryptEnvelope(valueCiphertext, nonce, versions[j])
 										if err != nil {
 											continue
 										}
@@ -527,7 +531,7 @@ func GetQueryDecryptTxCmd() *cobra.Command {
 
 			if types.IsEncryptedErrorCode(result.Code) && types.ContainsEncryptedString(result.RawLog) {
 				for i, nonce := range nonces {
-					stdErr, err := wasmCtx.DecryptError(result.RawLog, nonce)
+					stdErr, err := wasmCtx.DecryptError(result.RawLog, nonce, versions[i])
 					if err != nil {
 						continue
 					}
@@ -631,12 +635,12 @@ func QueryWithData(contractAddress sdk.AccAddress, queryData []byte, cliCtx clie
 	if err != nil {
 		return err
 	}
-	nonce, _, _, _ := parseEncryptedBlob(queryData) //nolint:dogsled // Ignoring error since we just encrypted it
+	_, nonce, _, _, _ := parseEncryptedBlob(queryData) //nolint:dogsled // Ignoring error since we just encrypted it
 
 	res, _, err := cliCtx.QueryWithData(route, queryData)
 	if err != nil {
 		if types.ErrContainsQueryError(err) {
-			errorPlainBz, err := wasmCtx.DecryptError(err.Error(), nonce)
+			errorPlainBz, err := wasmCtx.DecryptError(err.Error(), nonce, wasmUtils.EnvelopeUnversioned)
 			if err != nil {
 				return err
 			}
@@ -663,7 +667,7 @@ func QueryWithData(contractAddress sdk.AccAddress, queryData []byte, cliCtx clie
 		}
 	}
 
-	decodedResp, err := base64.StdEncoding.DecodeString(string(resDecrypted))
+	decodedResp, err := wasmUtils.DecodeOutput(resDecrypted)
 	if err != nil {
 		return err
 	}
@@ -715,6 +719,127 @@ func asciiDecodeString(s string) ([]byte, error) {
 	return []byte(s), nil
 }
 
+// GetCmdExportUserState exports the state entries of a user of a contract
+func GetCmdExportUserState() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "export-user-state [bech32_address] [request_file]",
+		Short: "Export the state entries of a user of a contract",
+		Long: "Exports the state entries of a user of a contract, given a request signed by both the user and the admin of the contract. " +
+			"Prints the base64 of the export, which is encrypted for the user public key of the request.",
+		Args: cobra.ExactArgs(2),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			clientCtx, err := client.GetClientQueryContext(cmd)
+			if err != nil {
+				return err
+			}
+
+			addr, err := sdk.AccAddressFromBech32(args[0])
+			if err != nil {
+				return err
+			}
+
+			request, err := os.ReadFile(args[1])
+			if err != nil {
+				return err
+			}
+			if !json.Valid(request) {
+				return errors.New("request must be json")
+			}
+
+			route := fmt.Sprintf("custom/%s/%s/%s", types.QuerierRoute, keeper.QueryExportUserState, addr.String())
+			res, _, err := clientCtx.QueryWithData(route, request)
+			if err != nil {
+				return err
+			}
+
+			fmt.Println(base64.StdEncoding.EncodeToString(res))
+			return nil
+		},
+	}
+
+	flags.AddQueryFlagsToCmd(cmd)
+	return cmd
+}
+
+// GetCmdTxFailureReport prints the reason a transaction of the sender failed for
+func GetCmdTxFailureReport() *cobra.Command {
+	cmd := &cobra.Command{
+		Use:   "tx-failure-report [hash]",
+		Short: "Query why a transaction of mine failed in the enclave",
+		Long: "Queries the failure report the enclave kept for a failed transaction, and decrypts it if I'm the tx sender. " +
+			"Reports are only kept for the recent blocks, in the memory of the node that executed them, " +
+			"so they are lost when the node restarts and must be queried from a node that executed the transaction.",
+		Args: cobra.ExactArgs(1),
+		RunE: func(cmd *cobra.Command, args []string) error {
+			clientCtx, err := client.GetClientQueryContext(cmd)
+			if err != nil {
+				return err
+			}
+
+			txHash, err := hex.DecodeString(args[0])
+			if err != nil {
+				return fmt.Errorf("tx hash must be hex: %w", err)
+			}
+
+			result, err := authtx.QueryTx(clientCtx, args[0])
+			if err != nil {
+				return err
+			}
+			if result.Empty() {
+				return fmt.Errorf("no transaction found with hash %s", args[0])
+			}
+
+			var encryptedInput []byte
+			for _, msg := range result.GetTx().GetMsgs() {
+				switch msg := msg.(type) {
+				case *types.MsgExecuteContract:
+					encryptedInput = msg.Msg
+				case *types.MsgInstantiateContract:
+					encryptedInput = msg.InitMsg
+				}
+				if encryptedInput != nil {
+					break
+				}
+			}
+			if encryptedInput == nil {
+				return fmt.Errorf("transaction %s has no encrypted message", args[0])
+			}
+
+			_, nonce, originalTxSenderPubkey, _, err := parseEncryptedBlob(encryptedInput)
+			if err != nil {
+				return fmt.Errorf("can't parse encrypted blob: %w", err)
+			}
+
+			wasmCtx := wasmUtils.WASMContext{CLIContext: clientCtx}
+			_, myPubkey, err := wasmCtx.GetTxSenderKeyPair()
+			if err != nil {
+				return fmt.Errorf("error in GetTxSenderKeyPair: %w", err)
+			}
+			if !bytes.Equal(originalTxSenderPubkey, myPubkey) {
+				return fmt.Errorf("cannot decrypt, not original tx sender")
+			}
+
+			route := fmt.Sprintf("custom/%s/%s/%s/%s/%s", types.QuerierRoute, keeper.QueryTxFailureReport,
+				hex.EncodeToString(txHash), hex.EncodeToString(nonce), hex.EncodeToString(originalTxSenderPubkey))
+			res, _, err := clientCtx.Query(route)
+			if err != nil {
+				return err
+			}
+
+			report, err := wasmCtx.Decrypt(res, nonce)
+			if err != nil {
+				return fmt.Errorf("error while trying to decrypt the failure report: %w", err)
+			}
+
+			fmt.Println(string(report))
+			return nil
+		},
+	}
+
+	flags.AddQueryFlagsToCmd(cmd)
+	return cmd
+}
+
 // GetCmdGetContractHistory prints the code history for a given contract
 func GetCmdGetContractHistory() *cobra.Command {
 	cmd := &cobra.Command{
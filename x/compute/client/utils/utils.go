@@ -21,6 +21,7 @@ import (
 	"google.golang.org/grpc/encoding/proto"
 
 	"github.com/miscreant/miscreant.go"
+	"golang.org/x/crypto/chacha20poly1305"
 	"golang.org/x/crypto/curve25519"
 	"golang.org/x/crypto/hkdf"
 )
@@ -136,6 +137,45 @@ var hkdfSalt = []byte{
 	0xc1, 0xa1, 0x2e, 0xa6, 0x37, 0xd7, 0xe9, 0x6d,
 }
 
+// EnvelopeVersion is the version of the envelope of an encrypted message, which selects the cipher
+// suite of the message and of the outputs of its execution, see EnvelopeVersion in the enclave
+type EnvelopeVersion byte
+
+const (
+	// EnvelopeUnversioned is the original envelope, without a version, encrypted with AES-SIV
+	EnvelopeUnversioned       EnvelopeVersion = 0
+	EnvelopeAesSiv            EnvelopeVersion = 1
+	EnvelopeXChaCha20Poly1305 EnvelopeVersion = 2
+)
+
+// Versioned envelopes start with envelopeVersionedMagic and their version
+var envelopeVersionedMagic = []byte("\xffsecret_envelope/")
+
+// xchachaKeyInfo derives the XChaCha20-Poly1305 key of an envelope from its AES-SIV key
+const xchachaKeyInfo = "xchacha20poly1305"
+
+// xchachaCipherKeyInfo derives the key of the cipher from the XChaCha20-Poly1305 key
+const xchachaCipherKeyInfo = "xchacha20poly1305_cipher"
+
+// SplitEnvelopeVersion splits the version from the start of an envelope
+func SplitEnvelopeVersion(envelope []byte) (EnvelopeVersion, []byte, error) {
+	if !bytes.HasPrefix(envelope, envelopeVersionedMagic) {
+		return EnvelopeUnversioned, envelope, nil
+	}
+
+	rest := envelope[len(envelopeVersionedMagic):]
+	if len(rest) == 0 {
+		return 0, nil, fmt.Errorf("envelope has no version")
+	}
+
+	switch version := EnvelopeVersion(rest[0]); version {
+	case EnvelopeAesSiv, EnvelopeXChaCha20Poly1305:
+		return version, rest[1:], nil
+	default:
+		return 0, nil, fmt.Errorf("envelope version %d is not supported", version)
+	}
+}
+
 func (ctx WASMContext) getConsensusIoPubKey() ([]byte, error) {
 	var masterIoKey regtypes.Key
 	if ctx.TestMasterIOKey.Bytes != nil { // TODO check length?
@@ -235,8 +275,14 @@ func (ctx WASMContext) Encrypt(plaintext []byte) ([]byte, error) {
 	return encryptData(txEncryptionKey, txSenderPubKey, plaintext, nonce)
 }
 
-// Decrypt decrypts
+// Decrypt decrypts what was encrypted in an envelope without a version
 func (ctx WASMContext) Decrypt(ciphertext []byte, nonce []byte) ([]byte, error) {
+	return ctx.DecryptEnvelope(ciphertext, nonce, EnvelopeUnversioned)
+}
+
+// DecryptEnvelope decrypts a message, or an output of its execution, in the cipher suite of the
+// version of its envelope
+func (ctx WASMContext) DecryptEnvelope(ciphertext []byte, nonce []byte, version EnvelopeVersion) ([]byte, error) {
 	if len(ciphertext) == 0 {
 		return []byte{}, nil
 	}
@@ -251,6 +297,10 @@ func (ctx WASMContext) Decrypt(ciphertext []byte, nonce []byte) ([]byte, error)
 		return nil, err
 	}
 
+	if version == EnvelopeXChaCha20Poly1305 {
+		return decryptXChaCha(txEncryptionKey, ciphertext)
+	}
+
 	cipher, err := miscreant.NewAESCMACSIV(txEncryptionKey)
 	if err != nil {
 		return nil, err
@@ -259,9 +309,48 @@ func (ctx WASMContext) Decrypt(ciphertext []byte, nonce []byte) ([]byte, error)
 	return cipher.Open(nil, ciphertext, []byte{})
 }
 
+// decryptXChaCha decrypts like the enclave, whose XChaCha20-Poly1305 ciphertexts are their 24
+// byte nonce followed by the ciphertext and the tag, encrypted with a key derived from the AES-SIV
+// key of the envelope
+func decryptXChaCha(txEncryptionKey []byte, ciphertext []byte) ([]byte, error) {
+	if len(ciphertext) < chacha20poly1305.NonceSizeX {
+		return nil, fmt.Errorf("ciphertext of %d bytes has no nonce", len(ciphertext))
+	}
+
+	envelopeKey, err := deriveKey(txEncryptionKey, []byte(xchachaKeyInfo))
+	if err != nil {
+		return nil, err
+	}
+	cipherKey, err := deriveKey(envelopeKey, []byte(xchachaCipherKeyInfo))
+	if err != nil {
+		return nil, err
+	}
+
+	aead, err := chacha20poly1305.NewX(cipherKey)
+	if err != nil {
+		return nil, err
+	}
+
+	nonce, sealed := ciphertext[:chacha20poly1305.NonceSizeX], ciphertext[chacha20poly1305.NonceSizeX:]
+	return aead.Open(nil, nonce, sealed, nil)
+}
+
+// deriveKey derives a key from another like the enclave does, with the info appended to the key
+func deriveKey(key []byte, info []byte) ([]byte, error) {
+	ikm := append(append([]byte{}, key...), info...)
+	kdfFunc := hkdf.New(sha256.New, ikm, hkdfSalt, []byte{})
+
+	derivedKey := make([]byte, 32)
+	if _, err := io.ReadFull(kdfFunc, derivedKey); err != nil {
+		return nil, err
+	}
+
+	return derivedKey, nil
+}
+
 var re = regexp.MustCompile("encrypted: (.+?):")
 
-func (ctx WASMContext) DecryptError(errString string, nonce []byte) (json.RawMessage, error) {
+func (ctx WASMContext) DecryptError(errString string, nonce []byte, version EnvelopeVersion) (json.RawMessage, error) {
 	regexMatch := re.FindStringSubmatch(errString)
 	if len(regexMatch) != 2 {
 		return nil, fmt.Errorf("got an error finding base64 of the error: regexMatch '%v' should have a length of 2. error: %v", regexMatch, errString)
@@ -273,7 +362,7 @@ func (ctx WASMContext) DecryptError(errString string, nonce []byte) (json.RawMes
 		return nil, fmt.Errorf("got an error decoding base64 of the error: %w", err)
 	}
 
-	errorPlainBz, err := ctx.Decrypt(errorCipherBz, nonce)
+	errorPlainBz, err := ctx.DecryptEnvelope(errorCipherBz, nonce, version)
 	if err != nil {
 		return nil, fmt.Errorf("got an error decrypting the error: %w", err)
 	}
@@ -281,6 +370,22 @@ func (ctx WASMContext) DecryptError(errString string, nonce []byte) (json.RawMes
 	return errorPlainBz, nil
 }
 
+// outputPaddingByte must match PADDING_BYTE in the enclave, which pads the plaintexts of encrypted
+// outputs up to the size of their bucket with it
+const outputPaddingByte = " "
+
+// TrimOutputPadding removes the padding from the plaintext of an encrypted output. JSON outputs,
+// like errors, can be parsed with the padding, but base64 outputs can't.
+func TrimOutputPadding(plaintext []byte) []byte {
+	return bytes.TrimRight(plaintext, outputPaddingByte)
+}
+
+// DecodeOutput decodes the base64 plaintext of an encrypted output, like the data of an execution
+// or the result of a query, without its padding
+func DecodeOutput(plaintext []byte) ([]byte, error) {
+	return base64.StdEncoding.DecodeString(string(TrimOutputPadding(plaintext)))
+}
+
 func encryptData(aesEncryptionKey []byte, txSenderPubKey []byte, plaintext []byte, nonce []byte) ([]byte, error) {
 	cipher, err := miscreant.NewAESCMACSIV(aesEncryptionKey)
 	if err != nil {
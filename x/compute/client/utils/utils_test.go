@@ -1,6 +1,8 @@
 package utils
 
 import (
+	"bytes"
+	"encoding/hex"
 	"os"
 	"path/filepath"
 	"testing"
@@ -67,3 +69,57 @@ func TestGzipIt(t *testing.T) {
 	require.NoError(t, err)
 	require.Equal(t, originalGzipData, strToGzip)
 }
+
+func TestSplitEnvelopeVersion(t *testing.T) {
+	body := bytes.Repeat([]byte{1}, 64)
+
+	version, rest, err := SplitEnvelopeVersion(body)
+	require.NoError(t, err)
+	require.Equal(t, EnvelopeUnversioned, version)
+	require.Equal(t, body, rest)
+
+	version, rest, err = SplitEnvelopeVersion(append([]byte("\xffsecret_envelope/\x02"), body...))
+	require.NoError(t, err)
+	require.Equal(t, EnvelopeXChaCha20Poly1305, version)
+	require.Equal(t, body, rest)
+
+	_, _, err = SplitEnvelopeVersion(append([]byte("\xffsecret_envelope/\xff"), body...))
+	require.Error(t, err)
+	_, _, err = SplitEnvelopeVersion([]byte("\xffsecret_envelope/"))
+	require.Error(t, err)
+}
+
+func TestDecryptXChaCha(t *testing.T) {
+	// Encrypted like the enclave encrypts outputs in XChaCha20-Poly1305 envelopes, with the
+	// AES-SIV key of the envelope set to 32 bytes of 1
+	txEncryptionKey := bytes.Repeat([]byte{1}, 32)
+	ciphertext, err := hex.DecodeString("1651633e2ece3fc15499a88eb3f830bb9a4175d08b80f8df5efdda00694bb68c1d7dd08e0fe46451c077d56dad7b3c357e115707a9bd3431a3")
+	require.NoError(t, err)
+
+	plaintext, err := decryptXChaCha(txEncryptionKey, ciphertext)
+	require.NoError(t, err)
+	require.Equal(t, []byte(`{"ok":"aGVsbG8="}`), plaintext)
+
+	ciphertext[len(ciphertext)-1] ^= 1
+	_, err = decryptXChaCha(txEncryptionKey, ciphertext)
+	require.Error(t, err)
+	_, err = decryptXChaCha(txEncryptionKey, ciphertext[:23])
+	require.Error(t, err)
+}
+
+func TestDecodeOutput(t *testing.T) {
+	// `{"balance":"108"}` padded to the smallest bucket, like the enclave pads outputs
+	padded := append([]byte("eyJiYWxhbmNlIjoiMTA4In0="), bytes.Repeat([]byte(" "), 40)...)
+
+	decoded, err := DecodeOutput(padded)
+	require.NoError(t, err)
+	require.Equal(t, `{"balance":"108"}`, string(decoded))
+
+	decoded, err = DecodeOutput([]byte("eyJiYWxhbmNlIjoiMTA4In0="))
+	require.NoError(t, err)
+	require.Equal(t, `{"balance":"108"}`, string(decoded))
+
+	decoded, err = DecodeOutput(bytes.Repeat([]byte(" "), 64))
+	require.NoError(t, err)
+	require.Empty(t, decoded)
+}
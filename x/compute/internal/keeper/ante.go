@@ -21,6 +21,7 @@ func NewCountTXDecorator(storeKey sdk.StoreKey) *CountTXDecorator {
 // global rollback behavior instead of keeping state in the handler itself.
 // The ante handler passes the counter value via sdk.Context upstream. See `types.TXCounter(ctx)` to read the value.
 // Simulations don't get a tx counter value assigned.
+// It also starts counting the contract executions of the tx, see `types.NextExecution(ctx)`.
 func (a CountTXDecorator) AnteHandle(ctx sdk.Context, tx sdk.Tx, simulate bool, next sdk.AnteHandler) (sdk.Context, error) {
 	if simulate {
 		return next(ctx, tx, simulate)
@@ -40,7 +41,7 @@ func (a CountTXDecorator) AnteHandle(ctx sdk.Context, tx sdk.Tx, simulate bool,
 	// store next counter value for current height
 	store.Set(types.TXCounterPrefix, encodeHeightCounter(currentHeight, txCounter+1))
 
-	return next(types.WithTXCounter(ctx, txCounter), tx, simulate)
+	return next(types.WithExecutionCounter(types.WithTXCounter(ctx, txCounter)), tx, simulate)
 }
 
 func encodeHeightCounter(height int64, counter uint32) []byte {
@@ -0,0 +1,174 @@
+package keeper
+
+import (
+	"encoding/json"
+	"fmt"
+
+	sdk "github.com/cosmos/cosmos-sdk/types"
+	sdktx "github.com/cosmos/cosmos-sdk/types/tx"
+	abci "github.com/tendermint/tendermint/abci/types"
+	"google.golang.org/protobuf/encoding/protowire"
+
+	wasmTypes "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
+)
+
+// The group module stores its proposals and policies in ORM tables:
+//
+//	0x20 | policy address       -> GroupPolicyInfo
+//	0x30 | proposal id (u64 BE) -> Proposal
+//
+// The module isn't part of the app, so its messages and state are decoded here, and only the
+// fields that the enclave verifies are read.
+const (
+	groupStoreKey          = "group"
+	groupExecTypeURL       = "/cosmos.group.v1.MsgExec"
+	groupPolicyTablePrefix = byte(0x20)
+	proposalTablePrefix    = byte(0x30)
+)
+
+// groupExec is a MsgExec { proposal_id = 1, executor = 2 }
+type groupExec struct {
+	ProposalID uint64
+	Executor   string
+}
+
+// protoFields calls onField with the number, type and value of every field of a protobuf message
+func protoFields(bz []byte, onField func(num protowire.Number, typ protowire.Type, value []byte)) error {
+	for len(bz) > 0 {
+		num, typ, n := protowire.ConsumeTag(bz)
+		if n < 0 {
+			return protowire.ParseError(n)
+		}
+		bz = bz[n:]
+
+		n = protowire.ConsumeFieldValue(num, typ, bz)
+		if n < 0 {
+			return protowire.ParseError(n)
+		}
+		value := bz[:n]
+		if typ == protowire.BytesType {
+			value, _ = protowire.ConsumeBytes(value)
+		}
+		onField(num, typ, value)
+		bz = bz[n:]
+	}
+	return nil
+}
+
+// groupExecs returns the MsgExec messages of a transaction
+func groupExecs(txBytes []byte) []groupExec {
+	var rawTx sdktx.TxRaw
+	if err := rawTx.Unmarshal(txBytes); err != nil {
+		return nil
+	}
+	var body sdktx.TxBody
+	if err := body.Unmarshal(rawTx.BodyBytes); err != nil {
+		return nil
+	}
+
+	var execs []groupExec
+	for _, msg := range body.Messages {
+		if msg.TypeUrl != groupExecTypeURL {
+			continue
+		}
+
+		var exec groupExec
+		err := protoFields(msg.Value, func(num protowire.Number, typ protowire.Type, value []byte) {
+			switch {
+			case num == 1 && typ == protowire.VarintType:
+				exec.ProposalID, _ = protowire.ConsumeVarint(value)
+			case num == 2 && typ == protowire.BytesType:
+				exec.Executor = string(value)
+			}
+		})
+		if err == nil {
+			execs = append(execs, exec)
+		}
+	}
+	return execs
+}
+
+// proposalGroupPolicy returns the group_policy_address = 2 of a Proposal
+func proposalGroupPolicy(proposal []byte) (sdk.AccAddress, error) {
+	var policy string
+	err := protoFields(proposal, func(num protowire.Number, typ protowire.Type, value []byte) {
+		if num == 2 && typ == protowire.BytesType {
+			policy = string(value)
+		}
+	})
+	if err != nil {
+		return nil, err
+	}
+	return sdk.AccAddressFromBech32(policy)
+}
+
+// proveGroupKey proves a key of the group store, in the state that the header of the block of ctx
+// commits to
+func (k Keeper) proveGroupKey(ctx sdk.Context, key []byte) (json.RawMessage, []byte, error) {
+	res := k.stateQuerier.querier.Query(abci.RequestQuery{
+		Path:   fmt.Sprintf("/%s/key", groupStoreKey),
+		Data:   key,
+		Height: ctx.BlockHeight() - 1,
+		Prove:  true,
+	})
+	if res.Code != 0 {
+		return nil, nil, fmt.Errorf("%s", res.Log)
+	}
+
+	proof, err := readProofFromOps(res.ProofOps)
+	if err != nil {
+		return nil, nil, err
+	}
+	bz, err := json.Marshal(proof)
+	if err != nil {
+		panic(err)
+	}
+	return bz, res.Value, nil
+}
+
+// groupProposalProofs proves the proposals of the group policy that the transaction of ctx
+// executes, and the policy. Group policies don't sign, so the executor of the proposals signs the
+// messages that the policy sends, and it's returned too. If the transaction doesn't execute a
+// proposal of the policy, the executor is nil.
+func (k Keeper) groupProposalProofs(ctx sdk.Context, policy sdk.AccAddress) ([]wasmTypes.GroupProposalProof, sdk.AccAddress) {
+	if k.stateQuerier.querier == nil || ctx.BlockHeight() <= 1 {
+		return nil, nil
+	}
+
+	var proofs []wasmTypes.GroupProposalProof
+	var executor sdk.AccAddress
+	for _, exec := range groupExecs(ctx.TxBytes()) {
+		proposalKey := append([]byte{proposalTablePrefix}, sdk.Uint64ToBigEndian(exec.ProposalID)...)
+		proposalProof, proposal, err := k.proveGroupKey(ctx, proposalKey)
+		if err != nil || proposal == nil {
+			moduleLogger(ctx).Error("failed to prove a group proposal", "proposal", exec.ProposalID, "err", err)
+			continue
+		}
+		proposalPolicy, err := proposalGroupPolicy(proposal)
+		if err != nil || !proposalPolicy.Equals(policy) {
+			continue
+		}
+
+		policyProof, _, err := k.proveGroupKey(ctx, append([]byte{groupPolicyTablePrefix}, policy...))
+		if err != nil {
+			moduleLogger(ctx).Error("failed to prove a group policy", "policy", policy.String(), "err", err)
+			continue
+		}
+		execExecutor, err := sdk.AccAddressFromBech32(exec.Executor)
+		if err != nil {
+			continue
+		}
+
+		proofs = append(proofs, wasmTypes.GroupProposalProof{
+			ProposalID:  exec.ProposalID,
+			Proposal:    proposalProof,
+			GroupPolicy: policyProof,
+		})
+		// The enclave verifies the signature of the executor of the first of them
+		if executor == nil {
+			executor = execExecutor
+		}
+	}
+
+	return proofs, executor
+}
@@ -0,0 +1,84 @@
+package keeper
+
+import (
+	"bytes"
+	"encoding/json"
+	"testing"
+
+	codectypes "github.com/cosmos/cosmos-sdk/codec/types"
+	"github.com/cosmos/cosmos-sdk/store"
+	sdk "github.com/cosmos/cosmos-sdk/types"
+	sdktx "github.com/cosmos/cosmos-sdk/types/tx"
+	"github.com/stretchr/testify/require"
+	"github.com/tendermint/tendermint/libs/log"
+	tmproto "github.com/tendermint/tendermint/proto/tendermint/types"
+	dbm "github.com/tendermint/tm-db"
+	"google.golang.org/protobuf/encoding/protowire"
+)
+
+func groupExecTx(t *testing.T, proposalID uint64, executor sdk.AccAddress) []byte {
+	exec := protowire.AppendTag(nil, 1, protowire.VarintType)
+	exec = protowire.AppendVarint(exec, proposalID)
+	exec = protowire.AppendTag(exec, 2, protowire.BytesType)
+	exec = protowire.AppendString(exec, executor.String())
+
+	body := sdktx.TxBody{Messages: []*codectypes.Any{
+		{TypeUrl: "/cosmos.bank.v1beta1.MsgSend"},
+		{TypeUrl: groupExecTypeURL, Value: exec},
+	}}
+	bodyBytes, err := body.Marshal()
+	require.NoError(t, err)
+	txBytes, err := (&sdktx.TxRaw{BodyBytes: bodyBytes}).Marshal()
+	require.NoError(t, err)
+	return txBytes
+}
+
+func TestGroupProposalProofs(t *testing.T) {
+	db := dbm.NewMemDB()
+	ms := store.NewCommitMultiStore(db)
+	groupKey := sdk.NewKVStoreKey(groupStoreKey)
+	ms.MountStoreWithDB(groupKey, sdk.StoreTypeIAVL, db)
+	require.NoError(t, ms.LoadLatestVersion())
+
+	policy := sdk.AccAddress(bytes.Repeat([]byte{3}, 32))
+	other := sdk.AccAddress(bytes.Repeat([]byte{4}, 32))
+	executor := sdk.AccAddress(bytes.Repeat([]byte{5}, 20))
+
+	proposal := protowire.AppendTag(nil, 1, protowire.VarintType)
+	proposal = protowire.AppendVarint(proposal, 7)
+	proposal = protowire.AppendTag(proposal, 2, protowire.BytesType)
+	proposal = protowire.AppendString(proposal, policy.String())
+	ms.GetKVStore(groupKey).Set(append([]byte{proposalTablePrefix}, sdk.Uint64ToBigEndian(7)...), proposal)
+	ms.GetKVStore(groupKey).Set(append([]byte{groupPolicyTablePrefix}, policy...), []byte("policy"))
+	ms.Commit()
+
+	querier, ok := ms.(StateQuerier)
+	require.True(t, ok)
+	keeper := Keeper{stateQuerier: &stateQuerierHolder{querier: querier}}
+	ctx := sdk.NewContext(ms, tmproto.Header{Height: 2}, false, log.NewNopLogger()).
+		WithTxBytes(groupExecTx(t, 7, executor))
+
+	// The executor of the proposal signs for the policy
+	proofs, signer := keeper.groupProposalProofs(ctx, policy)
+	require.Equal(t, executor, signer)
+	require.Len(t, proofs, 1)
+	require.Equal(t, uint64(7), proofs[0].ProposalID)
+
+	var proposalProof, policyProof readProof
+	require.NoError(t, json.Unmarshal(proofs[0].Proposal, &proposalProof))
+	require.Equal(t, proposal, proposalProof.StoreProof.Exist.Value)
+	require.Equal(t, []byte(groupStoreKey), proposalProof.MultistoreProof.Key)
+	require.NoError(t, json.Unmarshal(proofs[0].GroupPolicy, &policyProof))
+	require.Equal(t, []byte("policy"), policyProof.StoreProof.Exist.Value)
+
+	// Proposals of other policies, of proposals that don't exist, and transactions without MsgExec
+	proofs, signer = keeper.groupProposalProofs(ctx, other)
+	require.Nil(t, signer)
+	require.Empty(t, proofs)
+	proofs, signer = keeper.groupProposalProofs(ctx.WithTxBytes(groupExecTx(t, 8, executor)), policy)
+	require.Nil(t, signer)
+	require.Empty(t, proofs)
+	proofs, signer = keeper.groupProposalProofs(ctx.WithTxBytes([]byte{}), policy)
+	require.Nil(t, signer)
+	require.Empty(t, proofs)
+}
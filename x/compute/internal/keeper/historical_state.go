@@ -0,0 +1,209 @@
+package keeper
+
+import (
+	"encoding/json"
+	"fmt"
+
+	ics23 "github.com/confio/ics23/go"
+	"github.com/cosmos/cosmos-sdk/store/prefix"
+	storetypes "github.com/cosmos/cosmos-sdk/store/types"
+	sdk "github.com/cosmos/cosmos-sdk/types"
+	paramstypes "github.com/cosmos/cosmos-sdk/x/params/types"
+	abci "github.com/tendermint/tendermint/abci/types"
+	tmcrypto "github.com/tendermint/tendermint/proto/tendermint/crypto"
+
+	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
+)
+
+// StateQuerier answers queries of the committed state with proofs, like the CommitMultiStore of
+// the app does
+type StateQuerier interface {
+	LastCommitID() storetypes.CommitID
+	Query(req abci.RequestQuery) abci.ResponseQuery
+}
+
+// stateQuerierHolder is shared by the copies of the keeper, since the app can only hand its
+// multistore to the keeper after creating it
+type stateQuerierHolder struct {
+	querier StateQuerier
+}
+
+// SetStateQuerier sets the committed state that queries at past heights prove their reads against
+func (k Keeper) SetStateQuerier(querier StateQuerier) {
+	k.stateQuerier.querier = querier
+}
+
+// queryHeight returns the height of a query if it reads the state of a past block, rather than
+// the latest one
+func (k Keeper) queryHeight(ctx sdk.Context) *uint64 {
+	querier := k.stateQuerier.querier
+	if querier == nil || ctx.BlockHeight() <= 0 || ctx.BlockHeight() >= querier.LastCommitID().Version {
+		return nil
+	}
+
+	height := uint64(ctx.BlockHeight())
+	return &height
+}
+
+// customQueryKeyProof proves the responder key of custom queries in the compute params, in the
+// state that the header of the block of ctx commits to, or returns nil if it can't be proven
+func (k Keeper) customQueryKeyProof(ctx sdk.Context) json.RawMessage {
+	querier := k.stateQuerier.querier
+	if querier == nil || ctx.BlockHeight() <= 1 {
+		return nil
+	}
+
+	res := querier.Query(abci.RequestQuery{
+		Path:   fmt.Sprintf("/%s/key", paramstypes.StoreKey),
+		Data:   append([]byte(types.ModuleName+"/"), types.KeyCustomQueryResponderKey...),
+		Height: ctx.BlockHeight() - 1,
+		Prove:  true,
+	})
+	if res.Code != 0 {
+		moduleLogger(ctx).Error("failed to prove the custom query responder key", "height", ctx.BlockHeight()-1, "log", res.Log)
+		return nil
+	}
+
+	proof, err := readProofFromOps(res.ProofOps)
+	if err != nil {
+		moduleLogger(ctx).Error("failed to prove the custom query responder key", "height", ctx.BlockHeight()-1, "err", err)
+		return nil
+	}
+	bz, err := json.Marshal(proof)
+	if err != nil {
+		panic(err)
+	}
+	return bz
+}
+
+// provingStore answers the reads of a query at a past height with their proofs, which the enclave
+// verifies against the app hash of that height and takes the values from
+type provingStore struct {
+	prefix.Store
+	querier   StateQuerier
+	keyPrefix []byte
+	height    int64
+}
+
+func (k Keeper) newProvingStore(ctx sdk.Context, store prefix.Store, contractAddress sdk.AccAddress) provingStore {
+	return provingStore{
+		Store:     store,
+		querier:   k.stateQuerier.querier,
+		keyPrefix: types.GetContractStorePrefixKey(contractAddress),
+		height:    ctx.BlockHeight(),
+	}
+}
+
+// Get returns the JSON of the proof of the value of the key
+func (s provingStore) Get(key []byte) []byte {
+	// The read is metered like any other
+	s.Store.Get(key)
+
+	res := s.querier.Query(abci.RequestQuery{
+		Path:   fmt.Sprintf("/%s/key", types.StoreKey),
+		Data:   append(append([]byte{}, s.keyPrefix...), key...),
+		Height: s.height,
+		Prove:  true,
+	})
+	if res.Code != 0 {
+		panic(fmt.Sprintf("failed to prove a read at height %d: %s", s.height, res.Log))
+	}
+
+	proof, err := readProofFromOps(res.ProofOps)
+	if err != nil {
+		panic(fmt.Sprintf("failed to prove a read at height %d: %s", s.height, err))
+	}
+	bz, err := json.Marshal(proof)
+	if err != nil {
+		panic(err)
+	}
+	return bz
+}
+
+// The proofs as the enclave reads them, see state_proofs.rs
+
+type innerOp struct {
+	Prefix []byte `json:"prefix"`
+	Suffix []byte `json:"suffix"`
+}
+
+type existenceProof struct {
+	Key        []byte    `json:"key"`
+	Value      []byte    `json:"value"`
+	LeafPrefix []byte    `json:"leaf_prefix"`
+	Path       []innerOp `json:"path"`
+}
+
+type nonExistenceProof struct {
+	Key   []byte          `json:"key"`
+	Left  *existenceProof `json:"left"`
+	Right *existenceProof `json:"right"`
+}
+
+type keyProof struct {
+	Exist    *existenceProof    `json:"exist,omitempty"`
+	Nonexist *nonExistenceProof `json:"nonexist,omitempty"`
+}
+
+type readProof struct {
+	StoreProof      keyProof       `json:"store_proof"`
+	MultistoreProof existenceProof `json:"multistore_proof"`
+}
+
+func convertExistenceProof(proof *ics23.ExistenceProof) *existenceProof {
+	if proof == nil {
+		return nil
+	}
+
+	path := make([]innerOp, len(proof.Path))
+	for i, inner := range proof.Path {
+		path[i] = innerOp{Prefix: inner.Prefix, Suffix: inner.Suffix}
+	}
+	return &existenceProof{
+		Key:        proof.Key,
+		Value:      proof.Value,
+		LeafPrefix: proof.GetLeaf().GetPrefix(),
+		Path:       path,
+	}
+}
+
+// readProofFromOps converts the ICS-23 proofs of a key in the IAVL tree of a store and of the
+// root of the store in the multistore
+func readProofFromOps(ops *tmcrypto.ProofOps) (*readProof, error) {
+	if ops == nil || len(ops.Ops) != 2 {
+		return nil, fmt.Errorf("expected the proofs of the key and of the store")
+	}
+
+	var storeProof, multistoreProof ics23.CommitmentProof
+	if err := storeProof.Unmarshal(ops.Ops[0].Data); err != nil {
+		return nil, err
+	}
+	if err := multistoreProof.Unmarshal(ops.Ops[1].Data); err != nil {
+		return nil, err
+	}
+
+	proof := readProof{}
+	switch {
+	case storeProof.GetExist() != nil:
+		proof.StoreProof.Exist = convertExistenceProof(storeProof.GetExist())
+	case storeProof.GetNonexist() != nil:
+		nonexist := storeProof.GetNonexist()
+		proof.StoreProof.Nonexist = &nonExistenceProof{
+			Key:   nonexist.Key,
+			Left:  convertExistenceProof(nonexist.Left),
+			Right: convertExistenceProof(nonexist.Right),
+		}
+	default:
+		return nil, fmt.Errorf("unsupported proof of the key")
+	}
+
+	storeRoot := convertExistenceProof(multistoreProof.GetExist())
+	if storeRoot == nil {
+		return nil, fmt.Errorf("unsupported proof of the store")
+	}
+	// The leaves of the multistore have no prefix besides the leaf prefix itself
+	storeRoot.LeafPrefix = []byte{}
+	proof.MultistoreProof = *storeRoot
+
+	return &proof, nil
+}
@@ -0,0 +1,82 @@
+package keeper
+
+import (
+	"bytes"
+	"encoding/json"
+	"testing"
+
+	"github.com/cosmos/cosmos-sdk/store"
+	sdk "github.com/cosmos/cosmos-sdk/types"
+	"github.com/stretchr/testify/require"
+	abci "github.com/tendermint/tendermint/abci/types"
+	"github.com/tendermint/tendermint/libs/log"
+	tmproto "github.com/tendermint/tendermint/proto/tendermint/types"
+	dbm "github.com/tendermint/tm-db"
+
+	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
+)
+
+func TestQueriesAtPastHeightsProveTheirReads(t *testing.T) {
+	db := dbm.NewMemDB()
+	ms := store.NewCommitMultiStore(db)
+	computeKey := sdk.NewKVStoreKey(types.StoreKey)
+	ms.MountStoreWithDB(computeKey, sdk.StoreTypeIAVL, db)
+	ms.MountStoreWithDB(sdk.NewKVStoreKey("other"), sdk.StoreTypeIAVL, db)
+	require.NoError(t, ms.LoadLatestVersion())
+
+	contract := sdk.AccAddress(bytes.Repeat([]byte{1}, 20))
+	contractKey := func(key string) []byte {
+		return append(types.GetContractStorePrefixKey(contract), key...)
+	}
+	ms.GetKVStore(computeKey).Set(contractKey("a"), []byte("1"))
+	ms.GetKVStore(computeKey).Set(contractKey("c"), []byte("3"))
+	ms.Commit()
+	ms.GetKVStore(computeKey).Set(contractKey("a"), []byte("2"))
+	ms.Commit()
+
+	querier, ok := ms.(StateQuerier)
+	require.True(t, ok)
+	keeper := Keeper{stateQuerier: &stateQuerierHolder{querier: querier}}
+
+	// Only queries before the last committed height are at a past height
+	latestCtx := sdk.NewContext(ms, tmproto.Header{Height: 2}, false, log.NewNopLogger())
+	require.Nil(t, keeper.queryHeight(latestCtx))
+	pastCtx := sdk.NewContext(ms, tmproto.Header{Height: 1}, false, log.NewNopLogger())
+	require.Equal(t, uint64(1), *keeper.queryHeight(pastCtx))
+	require.Nil(t, Keeper{stateQuerier: &stateQuerierHolder{}}.queryHeight(pastCtx))
+
+	prove := func(key string) *readProof {
+		res := querier.Query(abci.RequestQuery{
+			Path:   "/compute/key",
+			Data:   contractKey(key),
+			Height: 1,
+			Prove:  true,
+		})
+		require.Zero(t, res.Code, res.Log)
+		proof, err := readProofFromOps(res.ProofOps)
+		require.NoError(t, err)
+		require.Equal(t, []byte(types.StoreKey), proof.MultistoreProof.Key)
+		require.Empty(t, proof.MultistoreProof.LeafPrefix)
+		return proof
+	}
+
+	// The value of the key at that height, not the latest one
+	proof := prove("a")
+	require.NotNil(t, proof.StoreProof.Exist)
+	require.Nil(t, proof.StoreProof.Nonexist)
+	require.Equal(t, []byte("1"), proof.StoreProof.Exist.Value)
+
+	// A key without a value is proven by its neighbors
+	proof = prove("b")
+	require.Nil(t, proof.StoreProof.Exist)
+	require.Equal(t, contractKey("a"), proof.StoreProof.Nonexist.Left.Key)
+	require.Equal(t, contractKey("c"), proof.StoreProof.Nonexist.Right.Key)
+
+	// The JSON only has the variant of the proof, like the enum of the enclave
+	bz, err := json.Marshal(proof)
+	require.NoError(t, err)
+	var fields map[string]map[string]json.RawMessage
+	require.NoError(t, json.Unmarshal(bz, &fields))
+	require.Contains(t, fields["store_proof"], "nonexist")
+	require.NotContains(t, fields["store_proof"], "exist")
+}
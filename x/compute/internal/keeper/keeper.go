@@ -7,6 +7,7 @@ import (
 	"encoding/hex"
 	"encoding/json"
 	"fmt"
+	"net"
 	"path/filepath"
 	"strconv"
 	"strings"
@@ -18,6 +19,7 @@ import (
 	portkeeper "github.com/cosmos/ibc-go/v4/modules/core/05-port/keeper"
 	wasmTypes "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
 	"golang.org/x/crypto/ripemd160" //nolint:staticcheck
+	"google.golang.org/grpc/peer"
 
 	"github.com/cosmos/cosmos-sdk/telemetry"
 
@@ -31,6 +33,7 @@ import (
 	distrkeeper "github.com/cosmos/cosmos-sdk/x/distribution/keeper"
 	govkeeper "github.com/cosmos/cosmos-sdk/x/gov/keeper"
 	mintkeeper "github.com/cosmos/cosmos-sdk/x/mint/keeper"
+	paramtypes "github.com/cosmos/cosmos-sdk/x/params/types"
 	stakingkeeper "github.com/cosmos/cosmos-sdk/x/staking/keeper"
 	"github.com/tendermint/tendermint/libs/log"
 
@@ -81,8 +84,9 @@ type Keeper struct {
 	queryGasLimit uint64
 	HomeDir       string
 	// authZPolicy   AuthorizationPolicy
-	// paramSpace    subspace.Subspace
+	paramSpace     paramtypes.Subspace
 	LastMsgManager *baseapp.LastMsgMarkerContainer
+	stateQuerier   *stateQuerierHolder
 }
 
 func moduleLogger(ctx sdk.Context) log.Logger {
@@ -98,6 +102,7 @@ func NewKeeper(
 	cdc codec.Codec,
 	legacyAmino codec.LegacyAmino,
 	storeKey sdk.StoreKey,
+	paramSpace paramtypes.Subspace,
 	accountKeeper authkeeper.AccountKeeper,
 	bankKeeper bankkeeper.Keeper,
 	govKeeper govkeeper.Keeper,
@@ -119,11 +124,15 @@ func NewKeeper(
 	customPlugins *QueryPlugins,
 	lastMsgManager *baseapp.LastMsgMarkerContainer,
 ) Keeper {
-	wasmer, err := wasm.NewWasmer(filepath.Join(homeDir, "wasm"), supportedFeatures, wasmConfig.CacheSize, wasmConfig.EnclaveCacheSize)
+	wasmer, err := wasm.NewWasmer(filepath.Join(homeDir, "wasm"), supportedFeatures, wasmConfig.CacheSize, wasmConfig.EnclaveCacheSize, wasmConfig.StateAuditMode, wasmConfig.ExecutionDeadlineMillis, wasmConfig.ExecutionReceipts, wasmConfig.ExecutionTracing, wasmConfig.PinnedCodeCacheSize*1024*1024)
 	if err != nil {
 		panic(err)
 	}
 
+	if !paramSpace.HasKeyTable() {
+		paramSpace = paramSpace.WithKeyTable(types.ParamKeyTable())
+	}
+
 	keeper := Keeper{
 		storeKey:         storeKey,
 		cdc:              cdc,
@@ -145,7 +154,9 @@ func NewKeeper(
 		),
 		queryGasLimit:  wasmConfig.SmartQueryGasLimit,
 		HomeDir:        homeDir,
+		paramSpace:     paramSpace,
 		LastMsgManager: lastMsgManager,
+		stateQuerier:   &stateQuerierHolder{},
 	}
 	keeper.queryPlugins = DefaultQueryPlugins(govKeeper, distKeeper, mintKeeper, bankKeeper, stakingKeeper, queryRouter, &keeper, channelKeeper).Merge(customPlugins)
 
@@ -156,6 +167,26 @@ func (k Keeper) GetLastMsgMarkerContainer() *baseapp.LastMsgMarkerContainer {
 	return k.LastMsgManager
 }
 
+// AcknowledgeWriteIntents tells the enclave that the block whose transactions were delivered last
+// is committed, see wasm.Wasmer.AcknowledgeWriteIntents
+func (k Keeper) AcknowledgeWriteIntents() {
+	k.wasmer.AcknowledgeWriteIntents()
+}
+
+// GetParams returns the compute params, with the defaults of those that were never set
+func (k Keeper) GetParams(ctx sdk.Context) types.Params {
+	params := types.DefaultParams()
+	for _, pair := range params.ParamSetPairs() {
+		k.paramSpace.GetIfExists(ctx, pair.Key, pair.Value)
+	}
+	return params
+}
+
+// SetParams sets the compute params
+func (k Keeper) SetParams(ctx sdk.Context, params types.Params) {
+	k.paramSpace.SetParamSet(ctx, &params)
+}
+
 // Create uploads and compiles a WASM contract, returning a short identifier for the contract
 func (k Keeper) Create(ctx sdk.Context, creator sdk.AccAddress, wasmCode []byte, source string, builder string) (codeID uint64, err error) {
 	wasmCode, err = uncompress(wasmCode)
@@ -340,6 +371,50 @@ func (k Keeper) GetTxInfo(ctx sdk.Context, sender sdk.AccAddress) ([]byte, sdktx
 	return signBytes, signMode, modeInfoBytes, pkBytes, parsedTx.Signatures[pkIndex], nil
 }
 
+// GetOtherSignersInfo returns the sign bytes and signatures of the signers of the tx other than the sender,
+// e.g. a fee payer, so that the enclave can verify every signature of the tx and not just the sender's
+func (k Keeper) GetOtherSignersInfo(ctx sdk.Context, sender sdk.AccAddress) ([]wasmTypes.SignerSigInfo, error) {
+	var rawTx sdktx.TxRaw
+	err := k.cdc.Unmarshal(ctx.TxBytes(), &rawTx)
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrSigFailed, fmt.Sprintf("Unable to decode raw transaction from bytes: %s", err.Error()))
+	}
+
+	// Only the auth info is needed, and unlike the body it can always be decoded
+	var txAuthInfo sdktx.AuthInfo
+	err = k.cdc.Unmarshal(rawTx.AuthInfoBytes, &txAuthInfo)
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrSigFailed, fmt.Sprintf("Unable to decode transaction auth info from bytes: %s", err.Error()))
+	}
+
+	tx := authtx.WrapTx(&sdktx.Tx{AuthInfo: &txAuthInfo, Signatures: rawTx.Signatures}).GetTx()
+	pubKeys, err := tx.GetPubKeys()
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrSigFailed, fmt.Sprintf("Unable to retrieve pubkeys from tx: %s", err.Error()))
+	}
+
+	var otherSigners []wasmTypes.SignerSigInfo
+	for _, pubKey := range pubKeys {
+		signer := sdk.AccAddress(pubKey.Address())
+		if signer.Equals(sender) {
+			continue
+		}
+
+		signBytes, signMode, _, pkBytes, signature, err := k.GetTxInfo(ctx, signer)
+		if err != nil {
+			return nil, err
+		}
+		otherSigners = append(otherSigners, wasmTypes.SignerSigInfo{
+			SignBytes: signBytes,
+			SignMode:  signMode.String(),
+			PublicKey: pkBytes,
+			Signature: signature,
+		})
+	}
+
+	return otherSigners, nil
+}
+
 func V010MsgToV1SubMsg(contractAddress string, msg v010wasmTypes.CosmosMsg) (v1wasmTypes.SubMsg, error) {
 	if !isValidV010Msg(msg) {
 		return v1wasmTypes.SubMsg{}, fmt.Errorf("exactly one message type is supported: %+v", msg)
@@ -413,17 +488,32 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator, admin sdk.A
 	modeInfoBytes := []byte{}
 	pkBytes := []byte{}
 	signerSig := []byte{}
+	var otherSigners []wasmTypes.SignerSigInfo
 	var initError error
 
+	var groupProposalProofs []wasmTypes.GroupProposalProof
+
 	// If no callback signature - we should send the actual msg sender sign bytes and signature
 	if callbackSig == nil {
-		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, initError = k.GetTxInfo(ctx, creator)
+		// Group policies don't sign, the executor of their proposal signs for them
+		signer := creator
+		if proofs, executor := k.groupProposalProofs(ctx, creator); executor != nil {
+			groupProposalProofs, signer = proofs, executor
+		}
+
+		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, initError = k.GetTxInfo(ctx, signer)
+		if initError != nil {
+			return nil, nil, initError
+		}
+
+		otherSigners, initError = k.GetOtherSignersInfo(ctx, signer)
 		if initError != nil {
 			return nil, nil, initError
 		}
 	}
 
 	sigInfo := types.NewSigInfo(ctx.TxBytes(), signBytes, signMode, modeInfoBytes, pkBytes, signerSig, callbackSig)
+	sigInfo.OtherSigners = otherSigners
 
 	// create contract address
 
@@ -478,6 +568,9 @@ func (k Keeper) Instantiate(ctx sdk.Context, codeID uint64, creator, admin sdk.A
 		},
 		random,
 	)
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.CustomQueryKeyProof = k.customQueryKeyProof(ctx)
+	env.GroupProposalProofs = groupProposalProofs
 
 	// create prefixed data store
 	// 0x03 | contractAddress (sdk.AccAddress)
@@ -601,17 +694,32 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	modeInfoBytes := []byte{}
 	pkBytes := []byte{}
 	signerSig := []byte{}
+	var otherSigners []wasmTypes.SignerSigInfo
 	var err error
 
+	var groupProposalProofs []wasmTypes.GroupProposalProof
+
 	// If no callback signature - we should send the actual msg sender sign bytes and signature
 	if callbackSig == nil {
-		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, caller)
+		// Group policies don't sign, the executor of their proposal signs for them
+		signer := caller
+		if proofs, executor := k.groupProposalProofs(ctx, caller); executor != nil {
+			groupProposalProofs, signer = proofs, executor
+		}
+
+		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, signer)
+		if err != nil {
+			return nil, err
+		}
+
+		otherSigners, err = k.GetOtherSignersInfo(ctx, signer)
 		if err != nil {
 			return nil, err
 		}
 	}
 
 	sigInfo := types.NewSigInfo(ctx.TxBytes(), signBytes, signMode, modeInfoBytes, pkBytes, signerSig, callbackSig)
+	sigInfo.OtherSigners = otherSigners
 
 	contractInfo, codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
 	if err != nil {
@@ -638,6 +746,9 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	}
 
 	env := types.NewEnv(ctx, caller, coins, contractAddress, contractKey, random)
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.CustomQueryKeyProof = k.customQueryKeyProof(ctx)
+	env.GroupProposalProofs = groupProposalProofs
 
 	// prepare querier
 	querier := QueryHandler{
@@ -646,8 +757,9 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		Caller:  contractAddress,
 	}
 
-	response, gasUsed, execErr := k.wasmer.Execute(codeInfo.CodeHash, env, msg, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx), sigInfo, handleType)
+	response, receipt, gasUsed, execErr := k.wasmer.Execute(codeInfo.CodeHash, env, msg, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx), sigInfo, handleType)
 	consumeGas(ctx, gasUsed)
+	emitExecutionReceipt(ctx, contractAddress, receipt)
 
 	if execErr != nil {
 		var result sdk.Result
@@ -699,15 +811,35 @@ func (k Keeper) Execute(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 
 // QuerySmart queries the smart contract itself.
 func (k Keeper) QuerySmart(ctx sdk.Context, contractAddr sdk.AccAddress, req []byte, useDefaultGasLimit bool) ([]byte, error) {
-	return k.querySmartImpl(ctx, contractAddr, req, useDefaultGasLimit, 1)
+	// Queries are served by the node when they run in a query context, which is a check context,
+	// rather than in a transaction that is delivered
+	var origin wasmTypes.QueryOrigin
+	if ctx.IsCheckTx() {
+		origin = wasmTypes.QueryOriginNode
+	}
+	return k.querySmartImpl(ctx, contractAddr, req, useDefaultGasLimit, 1, origin)
+}
+
+// queryConnection returns the address of the client of a gRPC query without its port, which the
+// enclave limits the failures to decrypt queries per. Queries of other endpoints have none.
+func queryConnection(ctx sdk.Context) string {
+	p, ok := peer.FromContext(ctx.Context())
+	if !ok || p.Addr == nil {
+		return ""
+	}
+	host, _, err := net.SplitHostPort(p.Addr.String())
+	if err != nil {
+		return p.Addr.String()
+	}
+	return host
 }
 
 // QuerySmartRecursive queries the smart contract itself. This should only be called when running inside another query recursively.
 func (k Keeper) querySmartRecursive(ctx sdk.Context, contractAddr sdk.AccAddress, req []byte, queryDepth uint32, useDefaultGasLimit bool) ([]byte, error) {
-	return k.querySmartImpl(ctx, contractAddr, req, useDefaultGasLimit, queryDepth)
+	return k.querySmartImpl(ctx, contractAddr, req, useDefaultGasLimit, queryDepth, wasmTypes.QueryOriginContract)
 }
 
-func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress, req []byte, useDefaultGasLimit bool, queryDepth uint32) ([]byte, error) {
+func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress, req []byte, useDefaultGasLimit bool, queryDepth uint32, origin wasmTypes.QueryOrigin) ([]byte, error) {
 	defer telemetry.MeasureSince(time.Now(), "compute", "keeper", "query")
 
 	if useDefaultGasLimit {
@@ -741,9 +873,22 @@ func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress,
 		contractKey,
 		[]byte{0}, /* empty because it's unused in queries */
 	)
+	params.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	params.CustomQueryKeyProof = k.customQueryKeyProof(ctx)
 	params.QueryDepth = queryDepth
+	params.QueryOrigin = origin
+	if origin == wasmTypes.QueryOriginNode {
+		params.QueryConnection = queryConnection(ctx)
+	}
+
+	// A query at a past height reads the state with proofs, which the enclave verifies against
+	// the app hash of that height
+	var store wasm.KVStore = prefixStore
+	if params.QueryHeight = k.queryHeight(ctx); params.QueryHeight != nil {
+		store = k.newProvingStore(ctx, prefixStore, contractAddress)
+	}
 
-	queryResult, gasUsed, qErr := k.wasmer.Query(codeInfo.CodeHash, params, req, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx))
+	queryResult, gasUsed, qErr := k.wasmer.Query(codeInfo.CodeHash, params, req, store, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx))
 	consumeGas(ctx, gasUsed)
 
 	telemetry.SetGauge(float32(gasUsed), "compute", "keeper", "query", contractAddress.String(), "gasUsed")
@@ -754,6 +899,61 @@ func (k Keeper) querySmartImpl(ctx sdk.Context, contractAddress sdk.AccAddress,
 	return queryResult, nil
 }
 
+// ExportUserState exports the state entries of a user of a contract. The enclave only exports
+// them if both the user and the admin of the contract signed the request, and encrypts the
+// export for the user.
+func (k Keeper) ExportUserState(ctx sdk.Context, contractAddress sdk.AccAddress, request []byte) ([]byte, error) {
+	ctx = ctx.WithGasMeter(sdk.NewGasMeter(k.queryGasLimit))
+
+	contractInfo, codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
+	if err != nil {
+		return nil, err
+	}
+
+	if contractInfo.Admin == "" {
+		return nil, sdkerrors.Wrap(types.ErrQueryFailed, "contract has no admin to sign the export")
+	}
+	admin, err := sdk.AccAddressFromBech32(contractInfo.Admin)
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrQueryFailed, err.Error())
+	}
+
+	contractKey, err := k.GetContractKey(ctx, contractAddress)
+	if err != nil {
+		return nil, err
+	}
+
+	querier := QueryHandler{
+		Ctx:     ctx,
+		Plugins: k.queryPlugins,
+		Caller:  contractAddress,
+	}
+
+	env := types.NewEnv(ctx, sdk.AccAddress{}, sdk.NewCoins(), contractAddress, contractKey, []byte{0})
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.ContractCodeHash = hex.EncodeToString(codeInfo.CodeHash)
+
+	export, gasUsed, err := k.wasmer.ExportUserState(codeInfo.CodeHash, env, admin, contractInfo.AdminProof, request, prefixStore, cosmwasmAPI, querier, gasMeter(ctx), gasForContract(ctx))
+	consumeGas(ctx, gasUsed)
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrQueryFailed, err.Error())
+	}
+	return export, nil
+}
+
+// GetTxFailureReport returns the failure report of a transaction that failed in one of the recent
+// blocks, encrypted with the nonce and user public key of its message. The reports are only kept
+// in the memory of the enclave of the node that executed the transaction, so they are lost when the
+// node restarts, and nodes that didn't execute the block (e.g. ones that state synced past it) don't
+// have them. They aren't part of consensus, and should be queried from the same node.
+func (k Keeper) GetTxFailureReport(txHash []byte, nonce []byte, userPublicKey []byte) ([]byte, error) {
+	report, err := k.wasmer.GetTxFailureReport(txHash, nonce, userPublicKey)
+	if err != nil {
+		return nil, sdkerrors.Wrap(types.ErrQueryFailed, err.Error())
+	}
+	return report, nil
+}
+
 // We don't use this function since we have an encrypted state. It's here for upstream compatibility
 // QueryRaw returns the contract's state for give key. For a `nil` key a empty slice result is returned.
 func (k Keeper) QueryRaw(ctx sdk.Context, contractAddress sdk.AccAddress, key []byte) []types.Model {
@@ -1029,6 +1229,21 @@ func consumeGas(ctx sdk.Context, gas uint64) {
 	}
 }
 
+// emitExecutionReceipt returns the receipt the enclave signed for an execution in the events of
+// the transaction. The enclave only signs receipts when the node enables them, so the events of
+// nodes differ, which is fine as events aren't part of consensus.
+func emitExecutionReceipt(ctx sdk.Context, contractAddress sdk.AccAddress, receipt []byte) {
+	if len(receipt) == 0 {
+		return
+	}
+
+	ctx.EventManager().EmitEvent(sdk.NewEvent(
+		types.EventTypeExecutionReceipt,
+		sdk.NewAttribute(types.AttributeKeyContractAddr, contractAddress.String()),
+		sdk.NewAttribute(types.AttributeKeyReceipt, string(receipt)),
+	))
+}
+
 // generates a contract address from codeID + instanceID
 func (k Keeper) generateContractAddress(ctx sdk.Context, codeID uint64, creator sdk.AccAddress) sdk.AccAddress {
 	instanceID := k.autoIncrementID(ctx, types.KeyLastInstanceID)
@@ -1150,8 +1365,23 @@ func (h ContractResponseHandler) Handle(ctx sdk.Context, contractAddr sdk.AccAdd
 	return result, nil
 }
 
+// envelopeVersionedMagic starts the encrypted messages whose envelope has a version, see
+// EnvelopeVersion in the enclave
+var envelopeVersionedMagic = []byte("\xffsecret_envelope/")
+
+// replyEnvelopeHeader is the start of the original message that replies are sent in: the version
+// of its envelope, if it has one, so that the reply is decrypted in the same cipher suite as the
+// submessages, and the nonce and the public key of the sender
+func replyEnvelopeHeader(ogTx []byte) []byte {
+	headerLen := 64
+	if bytes.HasPrefix(ogTx, envelopeVersionedMagic) {
+		headerLen += len(envelopeVersionedMagic) + 1
+	}
+	return append([]byte{}, ogTx[:headerLen]...)
+}
+
 // reply is only called from keeper internal functions (dispatchSubmessages) after processing the submessage
-func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1wasmTypes.Reply, ogTx []byte, ogSigInfo wasmTypes.SigInfo) ([]byte, error) {
+func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1wasmTypes.Reply, ogTx []byte, ogSigInfo wasmTypes.SigInfo, revertedExecution *uint32) ([]byte, error) {
 	contractInfo, codeInfo, prefixStore, err := k.contractInstance(ctx, contractAddress)
 	if err != nil {
 		return nil, err
@@ -1168,6 +1398,9 @@ func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1w
 	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
 
 	env := types.NewEnv(ctx, contractAddress, sdk.Coins{}, contractAddress, contractKey, random)
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.CustomQueryKeyProof = k.customQueryKeyProof(ctx)
+	env.RevertedExecution = revertedExecution
 
 	// prepare querier
 	querier := QueryHandler{
@@ -1177,14 +1410,15 @@ func (k Keeper) reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1w
 	}
 
 	marshaledReply, err := json.Marshal(reply)
-	marshaledReply = append(ogTx[0:64], marshaledReply...)
+	marshaledReply = append(replyEnvelopeHeader(ogTx), marshaledReply...)
 
 	if err != nil {
 		return nil, err
 	}
 
-	response, gasUsed, execErr := k.wasmer.Execute(codeInfo.CodeHash, env, marshaledReply, prefixStore, cosmwasmAPI, querier, ctx.GasMeter(), gasForContract(ctx), ogSigInfo, wasmTypes.HandleTypeReply)
+	response, receipt, gasUsed, execErr := k.wasmer.Execute(codeInfo.CodeHash, env, marshaledReply, prefixStore, cosmwasmAPI, querier, ctx.GasMeter(), gasForContract(ctx), ogSigInfo, wasmTypes.HandleTypeReply)
 	consumeGas(ctx, gasUsed)
+	emitExecutionReceipt(ctx, contractAddress, receipt)
 
 	if execErr != nil {
 		return nil, sdkerrors.Wrap(types.ErrReplyFailed, execErr.Error())
@@ -1229,16 +1463,31 @@ func (k Keeper) UpdateContractAdmin(ctx sdk.Context, contractAddress, caller, ne
 	modeInfoBytes := []byte{}
 	pkBytes := []byte{}
 	signerSig := []byte{}
+	var otherSigners []wasmTypes.SignerSigInfo
+
+	var groupProposalProofs []wasmTypes.GroupProposalProof
 
 	// If no callback signature - we should send the actual msg sender sign bytes and signature
 	if callbackSig == nil {
-		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, caller)
+		// Group policies don't sign, the executor of their proposal signs for them
+		signer := caller
+		if proofs, executor := k.groupProposalProofs(ctx, caller); executor != nil {
+			groupProposalProofs, signer = proofs, executor
+		}
+
+		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, signer)
+		if err != nil {
+			return err
+		}
+
+		otherSigners, err = k.GetOtherSignersInfo(ctx, signer)
 		if err != nil {
 			return err
 		}
 	}
 
 	sigInfo := types.NewSigInfo(ctx.TxBytes(), signBytes, signMode, modeInfoBytes, pkBytes, signerSig, callbackSig)
+	sigInfo.OtherSigners = otherSigners
 
 	contractKey, err := k.GetContractKey(ctx, contractAddress)
 	if err != nil {
@@ -1246,6 +1495,8 @@ func (k Keeper) UpdateContractAdmin(ctx sdk.Context, contractAddress, caller, ne
 	}
 
 	env := types.NewEnv(ctx, caller, sdk.Coins{}, contractAddress, contractKey, nil)
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.GroupProposalProofs = groupProposalProofs
 
 	currentAdminAddress, err := sdk.AccAddressFromBech32(contractInfo.Admin)
 	if err != nil {
@@ -1292,17 +1543,32 @@ func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 	modeInfoBytes := []byte{}
 	pkBytes := []byte{}
 	signerSig := []byte{}
+	var otherSigners []wasmTypes.SignerSigInfo
 	var err error
 
+	var groupProposalProofs []wasmTypes.GroupProposalProof
+
 	// If no callback signature - we should send the actual msg sender sign bytes and signature
 	if callbackSig == nil {
-		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, caller)
+		// Group policies don't sign, the executor of their proposal signs for them
+		signer := caller
+		if proofs, executor := k.groupProposalProofs(ctx, caller); executor != nil {
+			groupProposalProofs, signer = proofs, executor
+		}
+
+		signBytes, signMode, modeInfoBytes, pkBytes, signerSig, err = k.GetTxInfo(ctx, signer)
+		if err != nil {
+			return nil, err
+		}
+
+		otherSigners, err = k.GetOtherSignersInfo(ctx, signer)
 		if err != nil {
 			return nil, err
 		}
 	}
 
 	sigInfo := types.NewSigInfo(ctx.TxBytes(), signBytes, signMode, modeInfoBytes, pkBytes, signerSig, callbackSig)
+	sigInfo.OtherSigners = otherSigners
 
 	contractInfo, _, prefixStore, err := k.contractInstance(ctx, contractAddress)
 	if err != nil {
@@ -1335,20 +1601,36 @@ func (k Keeper) Migrate(ctx sdk.Context, contractAddress sdk.AccAddress, caller
 		return nil, err
 	}
 
-	if contractInfo.Admin != caller.String() {
+	// A contract can migrate itself without being its admin, if the upgrade policy it set allows
+	// the new code. The enclave checks the policy.
+	isSelfMigration := callbackSig != nil && caller.Equals(contractAddress)
+	if !isSelfMigration && contractInfo.Admin != caller.String() {
 		return nil, sdkerrors.Wrap(types.ErrMigrationFailed, "requires migrate from admin")
 	}
 
 	random := k.GetRandomSeed(ctx, ctx.BlockHeight())
 
+	oldCodeInfo, err := k.GetCodeInfo(ctx, contractInfo.CodeID)
+	if err != nil {
+		return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidRequest, sdkerrors.Wrap(err, "unknown code").Error())
+	}
+
 	env := types.NewEnv(ctx, caller, sdk.Coins{}, contractAddress, contractKey, random)
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.CustomQueryKeyProof = k.customQueryKeyProof(ctx)
+	env.GroupProposalProofs = groupProposalProofs
+	env.OldCodeHash = hex.EncodeToString(oldCodeInfo.CodeHash)
 
 	adminProof := contractInfo.AdminProof
 	admin := contractInfo.Admin
 
-	adminAddr, err := sdk.AccAddressFromBech32(admin)
-	if err != nil {
-		return nil, sdkerrors.Wrap(types.ErrMigrationFailed, err.Error())
+	// Contracts that migrate themselves may have no admin
+	var adminAddr sdk.AccAddress
+	if admin != "" || !isSelfMigration {
+		adminAddr, err = sdk.AccAddressFromBech32(admin)
+		if err != nil {
+			return nil, sdkerrors.Wrap(types.ErrMigrationFailed, err.Error())
+		}
 	}
 
 	// prepare querier
@@ -1,6 +1,7 @@
 package keeper
 
 import (
+	"encoding/hex"
 	"encoding/json"
 	"fmt"
 	"reflect"
@@ -22,6 +23,8 @@ const (
 	QueryContractKey          = "contract-key"
 	QueryContractHash         = "contract-hash"
 	QueryContractHashByCodeID = "contract-hash-by-id"
+	QueryExportUserState      = "export-user-state"
+	QueryTxFailureReport      = "tx-failure-report"
 )
 
 const QueryMethodContractStateSmart = "smart"
@@ -99,6 +102,24 @@ func NewLegacyQuerier(keeper Keeper) sdk.Querier {
 			if err != nil {
 				return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, err.Error())
 			}
+		case QueryExportUserState:
+			addr, err := sdk.AccAddressFromBech32(path[1])
+			if err != nil {
+				return nil, sdkerrors.Wrap(sdkerrors.ErrInvalidAddress, err.Error())
+			}
+			return keeper.ExportUserState(ctx, addr, req.Data)
+		case QueryTxFailureReport:
+			if len(path) < 4 {
+				return nil, sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, fmt.Sprintf("%s too few arguments (wanted 4): %v", QueryTxFailureReport, path))
+			}
+			var args [3][]byte
+			for i, arg := range path[1:4] {
+				args[i], err = hex.DecodeString(arg)
+				if err != nil {
+					return nil, sdkerrors.Wrapf(types.ErrInvalid, "%s: %s", QueryTxFailureReport, err.Error())
+				}
+			}
+			return keeper.GetTxFailureReport(args[0], args[1], args[2])
 		default:
 			return nil, sdkerrors.Wrap(sdkerrors.ErrUnknownRequest, fmt.Sprintf("unknown data query endpoint %s", path[0]))
 		}
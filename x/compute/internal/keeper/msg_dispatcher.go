@@ -28,7 +28,7 @@ type Messenger interface {
 
 // Replyer is a subset of keeper that can handle replies to submessages
 type Replyer interface {
-	reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1wasmTypes.Reply, ogTx []byte, ogSigInfo wasmTypes.SigInfo) ([]byte, error)
+	reply(ctx sdk.Context, contractAddress sdk.AccAddress, reply v1wasmTypes.Reply, ogTx []byte, ogSigInfo wasmTypes.SigInfo, revertedExecution *uint32) ([]byte, error)
 	GetLastMsgMarkerContainer() *baseapp.LastMsgMarkerContainer
 }
 
@@ -209,6 +209,9 @@ func (d MessageDispatcher) DispatchSubmessages(ctx sdk.Context, contractAddr sdk
 			return nil, sdkerrors.Wrap(types.ErrInvalid, "ReplyOn value")
 		}
 
+		// the executions of the submessage start here, in case its transient entries are reverted
+		firstExecution, hasExecutions := types.NextExecution(ctx)
+
 		// first, we build a sub-context which we can use inside the submessages
 		subCtx, commit := ctx.CacheContext()
 		em := sdk.NewEventManager()
@@ -293,6 +296,7 @@ func (d MessageDispatcher) DispatchSubmessages(ctx sdk.Context, contractAddr sdk
 			Result:              result,
 			WasOrigMsgEncrypted: msg.WasMsgEncrypted,
 			IsEncrypted:         false,
+			Payload:             msg.Payload,
 		}
 
 		// we can ignore any result returned as there is nothing to do with the data
@@ -342,7 +346,13 @@ func (d MessageDispatcher) DispatchSubmessages(ctx sdk.Context, contractAddr sdk
 			replySigInfo.CallbackSignature = dataWithInternalReplyInfo.InternaReplyEnclaveSig
 		}
 
-		rspData, err := d.keeper.reply(ctx, contractAddr, reply, ogTx, replySigInfo)
+		// on failure the enclave reverts the transient entries of the submessage, like the state
+		var revertedExecution *uint32
+		if reply.Result.Err != "" && hasExecutions {
+			revertedExecution = &firstExecution
+		}
+
+		rspData, err := d.keeper.reply(ctx, contractAddr, reply, ogTx, replySigInfo, revertedExecution)
 		switch {
 		case err != nil:
 			return nil, err
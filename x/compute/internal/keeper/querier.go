@@ -77,7 +77,8 @@ func (q GrpcQuerier) QuerySecretContract(c context.Context, req *types.QuerySecr
 		return nil, err
 	}
 
-	ctx := sdk.UnwrapSDKContext(c).WithGasMeter(sdk.NewGasMeter(q.keeper.queryGasLimit))
+	// The context of the gRPC call carries the connection of the client, see queryConnection
+	ctx := sdk.UnwrapSDKContext(c).WithGasMeter(sdk.NewGasMeter(q.keeper.queryGasLimit)).WithContext(c)
 
 	response, err := q.keeper.QuerySmart(ctx, contractAddress, req.Query, false)
 	switch {
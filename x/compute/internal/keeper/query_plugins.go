@@ -1,6 +1,9 @@
 package keeper
 
 import (
+	"crypto/ed25519"
+	"crypto/sha256"
+	"encoding/binary"
 	"encoding/json"
 	"fmt"
 	"strings"
@@ -444,6 +447,39 @@ func NoCustomQuerier(sdk.Context, json.RawMessage) ([]byte, error) {
 	return nil, wasmTypes.UnsupportedRequest{Kind: "custom"}
 }
 
+// customQueryResponseDomain must match RESPONSE_DOMAIN in the enclave
+const customQueryResponseDomain = "secret_custom_query_response"
+
+// SignedCustomResponse is the answer to a custom query the enclave expects once the
+// CustomQueryResponderKey compute param is set
+type SignedCustomResponse struct {
+	Response  []byte `json:"response"`
+	Signature []byte `json:"signature"`
+}
+
+// SignedCustomQuerier signs the answers of a custom querier with the key whose public key is the
+// CustomQueryResponderKey compute param, so the enclave can verify they weren't changed before
+// they reach the contract. Answers are bound to the query and to the height of the block.
+// Errors are not signed.
+func SignedCustomQuerier(querier CustomQuerier, key ed25519.PrivateKey) CustomQuerier {
+	return func(ctx sdk.Context, request json.RawMessage) ([]byte, error) {
+		res, err := querier(ctx, request)
+		if err != nil {
+			return nil, err
+		}
+
+		requestHash := sha256.Sum256(request)
+		signBytes := append([]byte(customQueryResponseDomain), make([]byte, 8)...)
+		binary.BigEndian.PutUint64(signBytes[len(customQueryResponseDomain):], uint64(ctx.BlockHeight()))
+		signBytes = append(signBytes, requestHash[:]...)
+		signBytes = append(signBytes, res...)
+		return json.Marshal(SignedCustomResponse{
+			Response:  res,
+			Signature: ed25519.Sign(key, signBytes),
+		})
+	}
+}
+
 func StakingQuerier(keeper stakingkeeper.Keeper, distKeeper distrkeeper.Keeper) func(ctx sdk.Context, request *wasmTypes.StakingQuery) ([]byte, error) {
 	return func(ctx sdk.Context, request *wasmTypes.StakingQuery) ([]byte, error) {
 		if request.BondedDenom != nil {
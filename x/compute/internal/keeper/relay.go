@@ -48,6 +48,8 @@ func (k Keeper) ibcContractCall(ctx sdk.Context,
 		contractKey,
 		random,
 	)
+	env.ComputeParams = k.GetParams(ctx).EnclaveParams()
+	env.CustomQueryKeyProof = k.customQueryKeyProof(ctx)
 
 	// prepare querier
 	querier := QueryHandler{
@@ -56,8 +58,9 @@ func (k Keeper) ibcContractCall(ctx sdk.Context,
 	}
 
 	gas := gasForContract(ctx)
-	res, gasUsed, err := k.wasmer.Execute(codeInfo.CodeHash, env, msgBz, prefixStore, cosmwasmAPI, querier, ctx.GasMeter(), gas, sigInfo, callType)
+	res, receipt, gasUsed, err := k.wasmer.Execute(codeInfo.CodeHash, env, msgBz, prefixStore, cosmwasmAPI, querier, ctx.GasMeter(), gas, sigInfo, callType)
 	consumeGas(ctx, gasUsed)
+	emitExecutionReceipt(ctx, contractAddress, receipt)
 
 	return res, err
 }
@@ -329,6 +332,6 @@ func (k Keeper) OnTimeoutPacket(
 func (k Keeper) handleIBCBasicContractResponse(ctx sdk.Context, addr sdk.AccAddress, ibcPortID string, inputMsg []byte, res *v1types.IBCBasicResponse) error {
 	sigInfo := types.NewSigInfo([]byte{}, []byte{}, sdktxsigning.SignMode_SIGN_MODE_DIRECT, []byte{}, []byte{}, []byte{}, nil)
 
-	_, err := k.handleContractResponse(ctx, addr, ibcPortID, res.Messages, res.Attributes, res.Events, nil, inputMsg, sigInfo)
+	_, err := k.handleContractResponse(ctx, addr, ibcPortID, res.Messages, res.Attributes, res.Events, res.Data, inputMsg, sigInfo)
 	return err
 }
@@ -8,11 +8,16 @@ import (
 	"math"
 	"os"
 	"path/filepath"
+	"strings"
 	"testing"
 
 	"github.com/stretchr/testify/require"
 
 	sdk "github.com/cosmos/cosmos-sdk/types"
+
+	cosmwasm "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
+	wasmUtils "github.com/scrtlabs/SecretNetwork/x/compute/client/utils"
+	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
 )
 
 func TestQueryInputParamError(t *testing.T) {
@@ -723,3 +728,73 @@ func TestV1QueryV010Contract(t *testing.T) {
 	require.Empty(t, err)
 	require.Equal(t, uint32(190), binary.BigEndian.Uint32(data))
 }
+
+// requirePaddedOutput checks that the plaintext of an encrypted output is padded to a bucket
+// size, and returns it decoded
+func requirePaddedOutput(t *testing.T, plaintext []byte) []byte {
+	require.Contains(t, []int{64, 128, 256, 512, 1024, 2048, 4096, 8192}, len(plaintext))
+	require.True(t, strings.HasSuffix(string(plaintext), " "), "output %q is not padded", plaintext)
+
+	decoded, err := wasmUtils.DecodeOutput(plaintext)
+	require.NoError(t, err)
+	return decoded
+}
+
+func TestOutputPadding(t *testing.T) {
+	ctx, keeper, codeID, codeHash, walletA, privKeyA, walletB, _ := setupTest(t, filepath.Join(".", contractPath, "erc20.wasm"), sdk.NewCoins())
+
+	initMsg := fmt.Sprintf(`{"decimals":10,"initial_balances":[{"address":"%s","amount":"108"},{"address":"%s","amount":"53"}],"name":"ReuvenPersonalRustCoin","symbol":"RPRC"}`, walletA.String(), walletB.String())
+	_, _, contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, nil, privKeyA, initMsg, true, false, defaultGasForTests)
+	require.Empty(t, initErr)
+
+	t.Run("query", func(t *testing.T) {
+		msg := types.SecretMsg{
+			CodeHash: []byte(codeHash),
+			Msg:      []byte(fmt.Sprintf(`{"balance":{"address":"%s"}}`, walletA.String())),
+		}
+		queryBz, err := wasmCtx.Encrypt(msg.Serialize())
+		require.NoError(t, err)
+
+		resultCipherBz, err := keeper.QuerySmart(ctx, contractAddress, queryBz, false)
+		require.NoError(t, err)
+		resultPlainBz, err := wasmCtx.Decrypt(resultCipherBz, queryBz[0:32])
+		require.NoError(t, err)
+
+		require.JSONEq(t, `{"balance":"108"}`, string(requirePaddedOutput(t, resultPlainBz)))
+
+		// The helpers of the other tests decode padded outputs the same way
+		result, qErr := queryHelper(t, keeper, ctx, contractAddress, fmt.Sprintf(`{"balance":{"address":"%s"}}`, walletA.String()), true, false, defaultGasForTests)
+		require.Empty(t, qErr)
+		require.JSONEq(t, `{"balance":"108"}`, result)
+	})
+
+	t.Run("execute", func(t *testing.T) {
+		for _, testContract := range testContracts {
+			t.Run(testContract.CosmWasmVersion, func(t *testing.T) {
+				ctx, keeper, codeID, codeHash, walletA, privKeyA, _, _ := setupTest(t, testContract.WasmFilePath, sdk.NewCoins())
+
+				_, _, contractAddress, _, initErr := initHelper(t, keeper, ctx, codeID, walletA, nil, privKeyA, `{"nop":{}}`, true, testContract.IsCosmWasmV1, defaultGasForTests)
+				require.Empty(t, initErr)
+
+				msg := types.SecretMsg{
+					CodeHash: []byte(codeHash),
+					Msg:      []byte(`{"unicode_data":{}}`),
+				}
+				execMsgBz, err := wasmCtx.Encrypt(msg.Serialize())
+				require.NoError(t, err)
+
+				ctx = PrepareExecSignedTx(t, keeper, ctx, walletA, privKeyA, execMsgBz, contractAddress, sdk.NewCoins())
+				execResult, err := keeper.Execute(ctx, contractAddress, walletA, execMsgBz, sdk.NewCoins(), nil, cosmwasm.HandleTypeExecute)
+				require.NoError(t, err)
+
+				dataPlainBz, err := wasmCtx.Decrypt(execResult.Data, execMsgBz[0:32])
+				require.NoError(t, err)
+				require.Equal(t, "🍆🥑🍄", string(requirePaddedOutput(t, dataPlainBz)))
+
+				_, _, data, _, _, execErr := execHelper(t, keeper, ctx, contractAddress, walletA, privKeyA, `{"unicode_data":{}}`, true, testContract.IsCosmWasmV1, defaultGasForTests, 0)
+				require.Empty(t, execErr)
+				require.Equal(t, "🍆🥑🍄", string(data))
+			})
+		}
+	})
+}
@@ -29,6 +29,7 @@ import (
 	v010cosmwasm "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types/v010"
 	v010wasmTypes "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types/v010"
 	v1wasmTypes "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types/v1"
+	wasmUtils "github.com/scrtlabs/SecretNetwork/x/compute/client/utils"
 	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
 )
 
@@ -279,7 +280,7 @@ func getDecryptedData(t *testing.T, data []byte, nonce []byte) []byte {
 	dataPlaintextBase64, err := wasmCtx.Decrypt(data, nonce)
 	require.NoError(t, err)
 
-	dataPlaintext, err := base64.StdEncoding.DecodeString(string(dataPlaintextBase64))
+	dataPlaintext, err := wasmUtils.DecodeOutput(dataPlaintextBase64)
 	require.NoError(t, err)
 
 	return dataPlaintext
@@ -429,7 +430,7 @@ func queryHelperImpl(
 	resultPlainBz, err := wasmCtx.Decrypt(resultCipherBz, nonce)
 	require.NoError(t, err)
 
-	resultBz, err := base64.StdEncoding.DecodeString(string(resultPlainBz))
+	resultBz, err := wasmUtils.DecodeOutput(resultPlainBz)
 	require.NoError(t, err)
 
 	return string(resultBz), cosmwasm.StdError{}
@@ -0,0 +1,118 @@
+package keeper
+
+import (
+	"encoding/hex"
+	"encoding/json"
+
+	sdk "github.com/cosmos/cosmos-sdk/types"
+
+	"github.com/scrtlabs/SecretNetwork/x/compute/internal/types"
+)
+
+// storageCountPagesPerBlock bounds the pages of raw entries that CountStorageUsage counts at the
+// end of each block, so that it takes about as long as a few executions
+const storageCountPagesPerBlock = 8
+
+// storageCountResult is what the enclave returns for a page of the count
+type storageCountResult struct {
+	// The usage of the contract is counted, so the next contract can be counted
+	Done bool `json:"done"`
+	// The count can't run yet, e.g. while the state of the contract is re-encrypted
+	Waiting bool `json:"waiting"`
+}
+
+// CountStorageUsage counts the storage usage of the contracts that stored entries before the
+// enclave accounted it, so that their storage quota can be enforced. Counting all the entries of a
+// large contract in one execution could take more gas than it has, so the chain counts them at the
+// end of blocks instead, a bounded number of pages at a time. Contracts are counted one after the
+// other, in the order of their addresses, from the cursor in the store. Contracts that are
+// instantiated meanwhile start counted, so nothing is left to count once the cursor gets past the
+// last contract.
+func (k Keeper) CountStorageUsage(ctx sdk.Context) {
+	store := ctx.KVStore(k.storeKey)
+	if store.Has(types.KeyStorageCountDone) {
+		return
+	}
+
+	cursor := store.Get(types.KeyStorageCountCursor)
+	for pages := 0; pages < storageCountPagesPerBlock; pages++ {
+		contractAddress := k.nextContract(ctx, cursor)
+		if contractAddress == nil {
+			store.Delete(types.KeyStorageCountCursor)
+			store.Set(types.KeyStorageCountDone, []byte{1})
+			ctx.Logger().Info("counted the storage usage of all contracts")
+			return
+		}
+
+		result, err := k.countStoragePage(ctx, contractAddress)
+		if err != nil {
+			// The count of this contract can't run, so it's left uncounted rather than stopping
+			// the count of the others
+			ctx.Logger().Error("failed to count the storage usage of contract", "contract", contractAddress.String(), "err", err)
+			result.Done = true
+		}
+		if result.Waiting {
+			break
+		}
+		if result.Done {
+			cursor = append(contractAddress, 0)
+		} else {
+			cursor = contractAddress
+		}
+	}
+
+	store.Set(types.KeyStorageCountCursor, cursor)
+}
+
+// nextContract returns the address of the first contract from the key start, or nil if there's
+// none
+func (k Keeper) nextContract(ctx sdk.Context, start []byte) sdk.AccAddress {
+	store := ctx.KVStore(k.storeKey)
+	iter := store.Iterator(append(types.ContractKeyPrefix, start...), sdk.PrefixEndBytes(types.ContractKeyPrefix))
+	defer iter.Close()
+
+	if !iter.Valid() {
+		return nil
+	}
+	return append(sdk.AccAddress{}, iter.Key()[len(types.ContractKeyPrefix):]...)
+}
+
+// countStoragePage counts a page of the storage usage of a contract in the enclave. The writes of
+// a page that fails are dropped.
+func (k Keeper) countStoragePage(ctx sdk.Context, contractAddress sdk.AccAddress) (storageCountResult, error) {
+	cacheCtx, commit := ctx.CacheContext()
+	cacheCtx = cacheCtx.WithGasMeter(sdk.NewGasMeter(k.queryGasLimit))
+
+	_, codeInfo, prefixStore, err := k.contractInstance(cacheCtx, contractAddress)
+	if err != nil {
+		return storageCountResult{}, err
+	}
+
+	contractKey, err := k.GetContractKey(cacheCtx, contractAddress)
+	if err != nil {
+		return storageCountResult{}, err
+	}
+
+	querier := QueryHandler{
+		Ctx:     cacheCtx,
+		Plugins: k.queryPlugins,
+		Caller:  contractAddress,
+	}
+
+	env := types.NewEnv(cacheCtx, sdk.AccAddress{}, sdk.NewCoins(), contractAddress, contractKey, []byte{0})
+	env.ComputeParams = k.GetParams(cacheCtx).EnclaveParams()
+	env.ContractCodeHash = hex.EncodeToString(codeInfo.CodeHash)
+
+	res, _, err := k.wasmer.CountStorageUsage(codeInfo.CodeHash, env, prefixStore, cosmwasmAPI, querier, gasMeter(cacheCtx), gasForContract(cacheCtx))
+	if err != nil {
+		return storageCountResult{}, err
+	}
+
+	var result storageCountResult
+	if err := json.Unmarshal(res, &result); err != nil {
+		return storageCountResult{}, err
+	}
+
+	commit()
+	return result, nil
+}
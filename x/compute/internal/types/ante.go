@@ -9,6 +9,7 @@ type contextKey int
 const (
 	// private type creates an interface key for Context that cannot be accessed by any other package
 	contextKeyTXCount contextKey = iota
+	contextKeyExecutionCounter
 )
 
 // WithTXCounter stores a transaction counter value in the context
@@ -22,3 +23,29 @@ func TXCounter(ctx sdk.Context) (uint32, bool) {
 	val, ok := ctx.Value(contextKeyTXCount).(uint32)
 	return val, ok
 }
+
+// WithExecutionCounter stores a counter of the contract executions of a transaction in the context
+func WithExecutionCounter(ctx sdk.Context) sdk.Context {
+	return ctx.WithValue(contextKeyExecutionCounter, new(uint32))
+}
+
+// NextExecution returns the position that the next contract execution of the transaction gets,
+// and false when there's no counter, like for external queries or simulations
+func NextExecution(ctx sdk.Context) (uint32, bool) {
+	counter, ok := ctx.Value(contextKeyExecutionCounter).(*uint32)
+	if !ok {
+		return 0, false
+	}
+	return *counter, true
+}
+
+// takeExecution returns the position of a contract execution of the transaction, and counts it
+func takeExecution(ctx sdk.Context) (uint32, bool) {
+	counter, ok := ctx.Value(contextKeyExecutionCounter).(*uint32)
+	if !ok {
+		return 0, false
+	}
+	execution := *counter
+	*counter++
+	return execution, true
+}
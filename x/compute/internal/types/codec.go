@@ -6,6 +6,8 @@ import (
 	"github.com/cosmos/cosmos-sdk/codec/types"
 	cryptocodec "github.com/cosmos/cosmos-sdk/crypto/codec"
 	sdk "github.com/cosmos/cosmos-sdk/types"
+	txtypes "github.com/cosmos/cosmos-sdk/types/tx"
+	gogotypes "github.com/gogo/protobuf/types"
 )
 
 // RegisterCodec registers the account types and interface
@@ -28,6 +30,13 @@ func RegisterInterfaces(registry types.InterfaceRegistry) {
 		&MsgUpdateAdmin{},
 		&MsgClearAdmin{},
 	)
+
+	// The proofs of chain state that transactions carry for the oracle queries of contracts, as
+	// non-critical extension options. The enclave reads the JSON encoded OracleProof in the value.
+	registry.RegisterImplementations(
+		(*txtypes.TxExtensionOptionI)(nil),
+		&gogotypes.BytesValue{},
+	)
 }
 
 // ModuleCdc generic sealed codec to be used throughout module
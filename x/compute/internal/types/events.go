@@ -15,6 +15,7 @@ const (
 	EventTypeSudo                = "sudo"
 	EventTypeReply               = "reply"
 	EventTypeUpdateContractAdmin = "update_contract_admin"
+	EventTypeExecutionReceipt    = "execution_receipt"
 )
 
 // event attributes returned from contract execution
@@ -25,4 +26,5 @@ const (
 	AttributeKeyCodeID       = "code_id"
 	AttributeKeySigner       = "signer"
 	AttributeKeyNewAdmin     = "new_admin_address"
+	AttributeKeyReceipt      = "receipt"
 )
@@ -37,6 +37,11 @@ var (
 
 	KeyLastCodeID     = append(SequenceKeyPrefix, []byte("lastCodeId")...)
 	KeyLastInstanceID = append(SequenceKeyPrefix, []byte("lastContractId")...)
+
+	// The contract whose storage usage is being counted, see Keeper.CountStorageUsage
+	KeyStorageCountCursor = append(SequenceKeyPrefix, []byte("storageCountCursor")...)
+	// Set once the storage usage of all the contracts is counted
+	KeyStorageCountDone = append(SequenceKeyPrefix, []byte("storageCountDone")...)
 )
 
 // GetCodeKey constructs the key for retreiving the ID for the WASM code
@@ -0,0 +1,298 @@
+package types
+
+import (
+	"crypto/ed25519"
+	"encoding/hex"
+	"encoding/json"
+	"fmt"
+
+	"filippo.io/edwards25519"
+	paramtypes "github.com/cosmos/cosmos-sdk/x/params/types"
+
+	wasmTypes "github.com/scrtlabs/SecretNetwork/go-cosmwasm/types"
+)
+
+// Keys of the compute params in the params store
+var (
+	KeyGasCosts                   = []byte("GasCosts")
+	KeyMemoryLimits               = []byte("MemoryLimits")
+	KeyOutputPadding              = []byte("OutputPadding")
+	KeyCallbackBindingSequences   = []byte("CallbackBindingSequences")
+	KeyAdminRegistry              = []byte("AdminRegistry")
+	KeyPlaintextAttributeDenylist = []byte("PlaintextAttributeDenylist")
+	KeyCustomQueryResponderKey    = []byte("CustomQueryResponderKey")
+)
+
+const (
+	// MaxMemoryPagesCeiling is the largest memory limit the enclave accepts, in 64 KiB pages
+	MaxMemoryPagesCeiling = 512
+	// MaxPaddingBucketSize is the largest padding bucket the enclave accepts, in bytes
+	MaxPaddingBucketSize = 1024 * 1024
+)
+
+var _ paramtypes.ParamSet = (*Params)(nil)
+
+// Params are the parameters of the compute module that configure the enclave. Each one is the
+// JSON of a table of the enclave, which is passed to every execution in its env. An empty
+// parameter keeps the defaults of the enclave.
+type Params struct {
+	// GasCosts reprices the entries of the enclave's WasmCosts, e.g. {"external_ed25519_verify":2000}
+	GasCosts string `json:"gas_costs" yaml:"gas_costs"`
+	// MemoryLimits are the limits of the memory of contract instances in 64 KiB pages, e.g.
+	// {"max_memory_pages":192,"code_overrides":{"<hex code hash>":480}}
+	MemoryLimits string `json:"memory_limits" yaml:"memory_limits"`
+	// OutputPadding are the buckets the encrypted outputs of contracts are padded to, in bytes, e.g.
+	// {"bucket_sizes":[256,1024,4096],"large_output_multiple":4096}. The coarse buckets are set
+	// with "coarse_bucket_sizes" and "coarse_large_output_multiple".
+	OutputPadding string `json:"output_padding" yaml:"output_padding"`
+	// CallbackBindingSequences are the next sequences of the transfer channels at the upgrade that
+	// bound the ibc-hooks callbacks of outgoing transfers to the transfers sent by the contract, e.g.
+	// {"channel-0":1234}. The callbacks of earlier packets are delivered without a binding.
+	CallbackBindingSequences string `json:"callback_binding_sequences" yaml:"callback_binding_sequences"`
+	// AdminRegistry is the signed registry of the admins and system contracts that were added after
+	// the hardcoded lists of the enclave were frozen, with the version, root, signature and proven
+	// entries of the registry. The enclave ignores a registry that isn't signed by a registry signer.
+	AdminRegistry string `json:"admin_registry" yaml:"admin_registry"`
+	// PlaintextAttributeDenylist replaces the attribute keys that contracts may not emit in the
+	// outputs of messages that aren't encrypted, e.g. {"denied_keys":["amount","recipient"]}.
+	// Keys are compared case-insensitively.
+	PlaintextAttributeDenylist string `json:"plaintext_attribute_denylist" yaml:"plaintext_attribute_denylist"`
+	// CustomQueryResponderKey is the hex of the Ed25519 key that must sign the answers to the custom
+	// queries of contracts, see keeper.SignedCustomQuerier. Unlike the other params it isn't passed
+	// in the env: the enclave reads it from a proof of the params store, see customQueryKeyProof.
+	CustomQueryResponderKey string `json:"custom_query_responder_key" yaml:"custom_query_responder_key"`
+}
+
+// ParamKeyTable returns the key table of the compute params
+func ParamKeyTable() paramtypes.KeyTable {
+	return paramtypes.NewKeyTable().RegisterParamSet(&Params{})
+}
+
+// DefaultParams leaves every table of the enclave at its defaults
+func DefaultParams() Params {
+	return Params{}
+}
+
+// ParamSetPairs implements paramtypes.ParamSet
+func (p *Params) ParamSetPairs() paramtypes.ParamSetPairs {
+	return paramtypes.ParamSetPairs{
+		paramtypes.NewParamSetPair(KeyGasCosts, &p.GasCosts, validateJSONObject),
+		paramtypes.NewParamSetPair(KeyMemoryLimits, &p.MemoryLimits, validateMemoryLimits),
+		paramtypes.NewParamSetPair(KeyOutputPadding, &p.OutputPadding, validateOutputPadding),
+		paramtypes.NewParamSetPair(KeyCallbackBindingSequences, &p.CallbackBindingSequences, validateCallbackBindingSequences),
+		paramtypes.NewParamSetPair(KeyAdminRegistry, &p.AdminRegistry, validateJSONObject),
+		paramtypes.NewParamSetPair(KeyPlaintextAttributeDenylist, &p.PlaintextAttributeDenylist, validatePlaintextAttributeDenylist),
+		paramtypes.NewParamSetPair(KeyCustomQueryResponderKey, &p.CustomQueryResponderKey, validateCustomQueryResponderKey),
+	}
+}
+
+// Validate checks that every parameter is empty or valid
+func (p Params) Validate() error {
+	if err := validateJSONObject(p.GasCosts); err != nil {
+		return err
+	}
+	if err := validateMemoryLimits(p.MemoryLimits); err != nil {
+		return err
+	}
+	if err := validateOutputPadding(p.OutputPadding); err != nil {
+		return err
+	}
+	if err := validateCallbackBindingSequences(p.CallbackBindingSequences); err != nil {
+		return err
+	}
+	if err := validateJSONObject(p.AdminRegistry); err != nil {
+		return err
+	}
+	if err := validatePlaintextAttributeDenylist(p.PlaintextAttributeDenylist); err != nil {
+		return err
+	}
+	return validateCustomQueryResponderKey(p.CustomQueryResponderKey)
+}
+
+// EnclaveParams returns the params as they are passed to the enclave in the env
+func (p Params) EnclaveParams() *wasmTypes.ComputeParams {
+	return &wasmTypes.ComputeParams{
+		GasCosts:                   rawJSON(p.GasCosts),
+		MemoryLimits:               rawJSON(p.MemoryLimits),
+		OutputPadding:              rawJSON(p.OutputPadding),
+		CallbackBindingSequences:   rawJSON(p.CallbackBindingSequences),
+		AdminRegistry:              rawJSON(p.AdminRegistry),
+		PlaintextAttributeDenylist: rawJSON(p.PlaintextAttributeDenylist),
+	}
+}
+
+func rawJSON(param string) json.RawMessage {
+	if param == "" {
+		return nil
+	}
+	return json.RawMessage(param)
+}
+
+func validateJSONObject(i interface{}) error {
+	param, ok := i.(string)
+	if !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	if param == "" {
+		return nil
+	}
+
+	var object map[string]json.RawMessage
+	if err := json.Unmarshal([]byte(param), &object); err != nil {
+		return fmt.Errorf("parameter is not a JSON object: %w", err)
+	}
+	return nil
+}
+
+// validateMemoryLimits checks the limits like the enclave does, so that governance can't set
+// limits that fail every execution
+func validateMemoryLimits(i interface{}) error {
+	if err := validateJSONObject(i); err != nil {
+		return err
+	}
+	param := i.(string)
+	if param == "" {
+		return nil
+	}
+
+	var limits struct {
+		MaxMemoryPages *uint32           `json:"max_memory_pages"`
+		CodeOverrides  map[string]uint32 `json:"code_overrides"`
+	}
+	if err := json.Unmarshal([]byte(param), &limits); err != nil {
+		return fmt.Errorf("invalid memory limits: %w", err)
+	}
+
+	pages := make([]uint32, 0, len(limits.CodeOverrides)+1)
+	if limits.MaxMemoryPages != nil {
+		pages = append(pages, *limits.MaxMemoryPages)
+	}
+	for codeHash, codePages := range limits.CodeOverrides {
+		if _, err := hex.DecodeString(codeHash); err != nil || len(codeHash) != 64 {
+			return fmt.Errorf("invalid code hash in memory limits: %s", codeHash)
+		}
+		pages = append(pages, codePages)
+	}
+	for _, p := range pages {
+		if p == 0 || p > MaxMemoryPagesCeiling {
+			return fmt.Errorf("memory limit of %d pages is not between 1 and %d", p, MaxMemoryPagesCeiling)
+		}
+	}
+	return nil
+}
+
+// validateOutputPadding checks the buckets like the enclave does: the sizes of each set of buckets
+// must be ascending, and no size or multiple can be 0 or larger than MaxPaddingBucketSize
+func validateOutputPadding(i interface{}) error {
+	if err := validateJSONObject(i); err != nil {
+		return err
+	}
+	param := i.(string)
+	if param == "" {
+		return nil
+	}
+
+	var padding struct {
+		BucketSizes               *[]uint64 `json:"bucket_sizes"`
+		LargeOutputMultiple       *uint64   `json:"large_output_multiple"`
+		CoarseBucketSizes         *[]uint64 `json:"coarse_bucket_sizes"`
+		CoarseLargeOutputMultiple *uint64   `json:"coarse_large_output_multiple"`
+	}
+	if err := json.Unmarshal([]byte(param), &padding); err != nil {
+		return fmt.Errorf("invalid output padding: %w", err)
+	}
+
+	var sizes []uint64
+	for _, buckets := range []*[]uint64{padding.BucketSizes, padding.CoarseBucketSizes} {
+		if buckets == nil {
+			continue
+		}
+		if len(*buckets) == 0 {
+			return fmt.Errorf("output padding has no buckets")
+		}
+		for i, size := range *buckets {
+			if i > 0 && size <= (*buckets)[i-1] {
+				return fmt.Errorf("output padding buckets are not ascending: %v", *buckets)
+			}
+		}
+		sizes = append(sizes, *buckets...)
+	}
+	for _, multiple := range []*uint64{padding.LargeOutputMultiple, padding.CoarseLargeOutputMultiple} {
+		if multiple != nil {
+			sizes = append(sizes, *multiple)
+		}
+	}
+	for _, size := range sizes {
+		if size == 0 || size > MaxPaddingBucketSize {
+			return fmt.Errorf("output padding of %d bytes is not between 1 and %d", size, MaxPaddingBucketSize)
+		}
+	}
+	return nil
+}
+
+// validateCallbackBindingSequences checks that the sequences are a JSON object of channel ids to
+// sequences, like the enclave reads them
+func validateCallbackBindingSequences(i interface{}) error {
+	if err := validateJSONObject(i); err != nil {
+		return err
+	}
+	param := i.(string)
+	if param == "" {
+		return nil
+	}
+
+	var sequences map[string]uint64
+	if err := json.Unmarshal([]byte(param), &sequences); err != nil {
+		return fmt.Errorf("invalid callback binding sequences: %w", err)
+	}
+	return nil
+}
+
+// validatePlaintextAttributeDenylist checks the denylist like the enclave does: it must list its
+// keys in "denied_keys", and no key can be empty
+func validatePlaintextAttributeDenylist(i interface{}) error {
+	if err := validateJSONObject(i); err != nil {
+		return err
+	}
+	param := i.(string)
+	if param == "" {
+		return nil
+	}
+
+	var denylist struct {
+		DeniedKeys *[]string `json:"denied_keys"`
+	}
+	if err := json.Unmarshal([]byte(param), &denylist); err != nil {
+		return fmt.Errorf("invalid plaintext attribute denylist: %w", err)
+	}
+	if denylist.DeniedKeys == nil {
+		return fmt.Errorf("plaintext attribute denylist has no denied_keys")
+	}
+	for _, key := range *denylist.DeniedKeys {
+		if key == "" {
+			return fmt.Errorf("plaintext attribute denylist has an empty key")
+		}
+	}
+	return nil
+}
+
+// validateCustomQueryResponderKey checks the key like the enclave does, since the enclave fails
+// every custom query while the key is invalid
+func validateCustomQueryResponderKey(i interface{}) error {
+	param, ok := i.(string)
+	if !ok {
+		return fmt.Errorf("invalid parameter type: %T", i)
+	}
+	if param == "" {
+		return nil
+	}
+
+	key, err := hex.DecodeString(param)
+	if err != nil || len(key) != ed25519.PublicKeySize {
+		return fmt.Errorf("custom query responder key is not the hex of an Ed25519 key: %s", param)
+	}
+	if _, err := new(edwards25519.Point).SetBytes(key); err != nil {
+		return fmt.Errorf("custom query responder key is not a point of Ed25519: %s", param)
+	}
+	return nil
+}
@@ -0,0 +1,101 @@
+package types
+
+import (
+	"encoding/json"
+	"strings"
+	"testing"
+
+	"github.com/stretchr/testify/require"
+)
+
+func TestParamsValidate(t *testing.T) {
+	specs := map[string]struct {
+		params   Params
+		expError bool
+	}{
+		"defaults":    {params: DefaultParams()},
+		"gas costs":   {params: Params{GasCosts: `{"regular":2}`}},
+		"not json":    {params: Params{GasCosts: `regular=2`}, expError: true},
+		"not object":  {params: Params{GasCosts: `[2]`}, expError: true},
+		"json string": {params: Params{GasCosts: `"{}"`}, expError: true},
+		"memory limits": {
+			params: Params{MemoryLimits: `{"max_memory_pages":256,"code_overrides":{"` + strings.Repeat("ab", 32) + `":480}}`},
+		},
+		"memory limit above ceiling": {
+			params:   Params{MemoryLimits: `{"max_memory_pages":513}`},
+			expError: true,
+		},
+		"zero memory limit": {
+			params:   Params{MemoryLimits: `{"code_overrides":{"` + strings.Repeat("ab", 32) + `":0}}`},
+			expError: true,
+		},
+		"memory limit of invalid code hash": {
+			params:   Params{MemoryLimits: `{"code_overrides":{"abc":256}}`},
+			expError: true,
+		},
+		"output padding": {
+			params: Params{OutputPadding: `{"bucket_sizes":[256,1024],"coarse_large_output_multiple":32768}`},
+		},
+		"output padding not ascending": {
+			params:   Params{OutputPadding: `{"bucket_sizes":[1024,256]}`},
+			expError: true,
+		},
+		"output padding without buckets": {
+			params:   Params{OutputPadding: `{"coarse_bucket_sizes":[]}`},
+			expError: true,
+		},
+		"output padding above max": {
+			params:   Params{OutputPadding: `{"large_output_multiple":2097152}`},
+			expError: true,
+		},
+		"callback binding sequences": {
+			params: Params{CallbackBindingSequences: `{"channel-0":1234}`},
+		},
+		"negative callback binding sequence": {
+			params:   Params{CallbackBindingSequences: `{"channel-0":-1}`},
+			expError: true,
+		},
+		"plaintext attribute denylist": {
+			params: Params{PlaintextAttributeDenylist: `{"denied_keys":["balance","owner"]}`},
+		},
+		"plaintext attribute denylist without keys": {
+			params:   Params{PlaintextAttributeDenylist: `{"keys":["balance"]}`},
+			expError: true,
+		},
+		"plaintext attribute denylist with empty key": {
+			params:   Params{PlaintextAttributeDenylist: `{"denied_keys":[""]}`},
+			expError: true,
+		},
+		"custom query responder key": {
+			params: Params{CustomQueryResponderKey: "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"},
+		},
+		"custom query responder key of the wrong length": {
+			params:   Params{CustomQueryResponderKey: "d75a980182b10ab7"},
+			expError: true,
+		},
+		"custom query responder key that is not hex": {
+			params:   Params{CustomQueryResponderKey: "not a key"},
+			expError: true,
+		},
+	}
+	for msg, spec := range specs {
+		t.Run(msg, func(t *testing.T) {
+			err := spec.params.Validate()
+			if spec.expError {
+				require.Error(t, err)
+			} else {
+				require.NoError(t, err)
+			}
+		})
+	}
+}
+
+func TestEnclaveParams(t *testing.T) {
+	bz, err := json.Marshal(DefaultParams().EnclaveParams())
+	require.NoError(t, err)
+	require.Equal(t, `{}`, string(bz))
+
+	bz, err = json.Marshal(Params{GasCosts: `{"regular":2}`}.EnclaveParams())
+	require.NoError(t, err)
+	require.Equal(t, `{"gas_costs":{"regular":2}}`, string(bz))
+}
@@ -20,6 +20,7 @@ const (
 	defaultLRUCacheSize        = uint64(0)
 	defaultEnclaveLRUCacheSize = uint16(100)
 	defaultQueryGasLimit       = uint64(10_000_000)
+	defaultPinnedCodeCacheSize = uint64(64)
 )
 
 func (m Model) ValidateBasic() error {
@@ -162,6 +163,10 @@ func NewEnv(ctx sdk.Context, creator sdk.AccAddress, deposit sdk.Coins, contract
 			Index: txCounter,
 			Hash:  txhash,
 		}
+		env.DeliverTx = !ctx.IsCheckTx()
+	}
+	if execution, ok := takeExecution(ctx); ok {
+		env.Execution = execution
 	}
 
 	return env
@@ -241,17 +246,23 @@ func contractSDKEventAttributes(customAttributes []wasmTypesV010.LogAttribute, c
 
 // WasmConfig is the extra config required for wasm
 type WasmConfig struct {
-	SmartQueryGasLimit uint64
-	CacheSize          uint64
-	EnclaveCacheSize   uint16
+	SmartQueryGasLimit      uint64
+	CacheSize               uint64
+	EnclaveCacheSize        uint16
+	StateAuditMode          bool
+	ExecutionDeadlineMillis uint64
+	ExecutionReceipts       bool
+	ExecutionTracing        bool
+	PinnedCodeCacheSize     uint64
 }
 
 // DefaultWasmConfig returns the default settings for WasmConfig
 func DefaultWasmConfig() *WasmConfig {
 	return &WasmConfig{
-		SmartQueryGasLimit: defaultQueryGasLimit,
-		CacheSize:          defaultLRUCacheSize,
-		EnclaveCacheSize:   defaultEnclaveLRUCacheSize,
+		SmartQueryGasLimit:  defaultQueryGasLimit,
+		CacheSize:           defaultLRUCacheSize,
+		EnclaveCacheSize:    defaultEnclaveLRUCacheSize,
+		PinnedCodeCacheSize: defaultPinnedCodeCacheSize,
 	}
 }
 
@@ -305,6 +316,15 @@ func GetConfig(appOpts servertypes.AppOptions) *WasmConfig {
 		config.EnclaveCacheSize = enclaveCacheSize
 	}
 
+	config.StateAuditMode = cast.ToBool(appOpts.Get("wasm.state-audit-mode"))
+	config.ExecutionDeadlineMillis = cast.ToUint64(appOpts.Get("wasm.execution-deadline-ms"))
+	config.ExecutionReceipts = cast.ToBool(appOpts.Get("wasm.execution-receipts"))
+	config.ExecutionTracing = cast.ToBool(appOpts.Get("wasm.execution-tracing"))
+
+	if appOpts.Get("wasm.pinned-code-cache-size") != nil {
+		config.PinnedCodeCacheSize = cast.ToUint64(appOpts.Get("wasm.pinned-code-cache-size"))
+	}
+
 	return config
 }
 
@@ -321,6 +341,32 @@ contract-memory-cache-size = "{{ .WASMConfig.CacheSize }}"
 
 # The WASM VM memory cache size in number of cached modules. Can safely go up to 15, but not recommended for validators
 contract-memory-enclave-cache-size = "{{ .WASMConfig.EnclaveCacheSize }}"
+
+# Record the encrypted keys and sizes of the state written by every execution in a sealed log
+# inside the enclave, for debugging the state growth of contracts. Slows down execution.
+state-audit-mode = "{{ .WASMConfig.StateAuditMode }}"
+
+# Abort the contract queries this node serves that run for longer than this many milliseconds,
+# regardless of their remaining gas. Transactions, and the queries contracts make while they
+# execute, are only limited by their gas, so that every node reaches the same result.
+# 0 disables the deadline.
+execution-deadline-ms = "{{ .WASMConfig.ExecutionDeadlineMillis }}"
+
+# Sign a receipt of every successful execution of a delivered transaction with a key that every
+# enclave of the network derives from the consensus seed, so that off-chain verifiers can prove
+# that an output came out of an enclave. The receipts are in the execution_receipt events of the
+# transactions, and the key is printed by "secretd execution-receipt-key". Receipts don't affect
+# consensus.
+execution-receipts = "{{ .WASMConfig.ExecutionReceipts }}"
+
+# Log the time every contract execution spends in each of its stages (verifying the params,
+# decrypting the message, setting up the engine, executing the contract, ...) as JSON under the
+# "enclave_trace" target. Doesn't affect consensus.
+execution-tracing = "{{ .WASMConfig.ExecutionTracing }}"
+
+# The memory, in MiB, that the enclave keeps large contract binaries pinned in, so that they aren't
+# copied into it on every call. Doesn't affect consensus. 0 disables pinning.
+pinned-code-cache-size = "{{ .WASMConfig.PinnedCodeCacheSize }}"
 `
 
 // ZeroSender is a valid 20 byte canonical address that's used to bypass the x/compute checks
@@ -202,6 +202,18 @@ func (am AppModule) BeginBlock(ctx sdk.Context, beginBlock abci.RequestBeginBloc
 		}
 
 		am.keeper.SetRandomSeed(ctx, random)
+
+		// The enclave refuses the executions of the block until it verified its max gas against
+		// the consensus hash of the header, so the node can't go on without it
+		params := ctx.ConsensusParams()
+		if params == nil || params.Block == nil {
+			panic(fmt.Sprintf("no consensus params for block %d", beginBlock.Header.Height))
+		}
+		err = api.SubmitBlockGasLimit(uint64(beginBlock.Header.Height), params.Block.MaxBytes, params.Block.MaxGas)
+		if err != nil {
+			ctx.Logger().Error("Failed to submit block gas limit")
+			panic(err)
+		}
 	} else {
 		println("No random got from TM header")
 	}
@@ -209,7 +221,12 @@ func (am AppModule) BeginBlock(ctx sdk.Context, beginBlock abci.RequestBeginBloc
 
 // EndBlock returns the end blocker for the compute module. It returns no validator
 // updates.
-func (AppModule) EndBlock(_ sdk.Context, _ abci.RequestEndBlock) []abci.ValidatorUpdate {
+func (am AppModule) EndBlock(ctx sdk.Context, _ abci.RequestEndBlock) []abci.ValidatorUpdate {
+	am.keeper.CountStorageUsage(ctx)
+
+	if err := api.EndBlock(uint64(ctx.BlockHeight())); err != nil {
+		ctx.Logger().Error("Failed to end block in the enclave", "err", err)
+	}
 	return []abci.ValidatorUpdate{}
 }
 
@@ -5,4 +5,7 @@ const (
 	StoreKey       = "hooks-for-ibc" // not using the module name because of collisions with key "ibc"
 	IBCCallbackKey = "ibc_callback"
 	SenderPrefix   = "ibc-wasm-hook-intermediary"
+
+	// NftTransferPortID is the port of ICS-721 NFT transfers
+	NftTransferPortID = "nft-transfer"
 )
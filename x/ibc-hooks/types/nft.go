@@ -0,0 +1,32 @@
+package types
+
+import "encoding/json"
+
+// NonFungibleTokenPacketData is the packet data of ICS-721 NFT transfers.
+// See https://github.com/cosmos/ibc/tree/main/spec/app/ics-721-nft-transfer
+type NonFungibleTokenPacketData struct {
+	ClassID   string   `json:"classId"`
+	ClassURI  string   `json:"classUri,omitempty"`
+	ClassData string   `json:"classData,omitempty"`
+	TokenIDs  []string `json:"tokenIds"`
+	TokenURIs []string `json:"tokenUris,omitempty"`
+	TokenData []string `json:"tokenData,omitempty"`
+	Sender    string   `json:"sender"`
+	Receiver  string   `json:"receiver"`
+	Memo      string   `json:"memo,omitempty"`
+}
+
+// ReceiveIbcNfts describes the NFTs a contract received through ibc-hooks, next to the message in
+// the memo of the packet. The enclave verifies it against the packet.
+type ReceiveIbcNfts struct {
+	// ClassID is the local class ID of the NFTs
+	ClassID  string          `json:"class_id"`
+	TokenIDs []string        `json:"token_ids"`
+	Sender   string          `json:"sender"`
+	Msg      json.RawMessage `json:"msg"`
+}
+
+// IbcHooksIncomingNftTransferMsg is the message contracts are executed with when they receive NFTs
+type IbcHooksIncomingNftTransferMsg struct {
+	ReceiveIbcNfts ReceiveIbcNfts `json:"receive_ibc_nfts"`
+}
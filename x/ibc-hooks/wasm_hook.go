@@ -46,6 +46,9 @@ func (h WasmHooks) OnRecvPacketOverride(im IBCMiddleware, ctx sdk.Context, packe
 		// Not configured
 		return im.App.OnRecvPacket(ctx, packet, relayer)
 	}
+	if packet.GetDestPort() == types.NftTransferPortID {
+		return h.onRecvNftPacketOverride(im, ctx, packet, relayer)
+	}
 	isIcs20, data := isIcs20Packet(packet)
 	if !isIcs20 {
 		return im.App.OnRecvPacket(ctx, packet, relayer)
@@ -115,6 +118,66 @@ func (h WasmHooks) OnRecvPacketOverride(im IBCMiddleware, ctx sdk.Context, packe
 	return channeltypes.NewResultAcknowledgement(bz)
 }
 
+// onRecvNftPacketOverride is the ICS-721 counterpart of OnRecvPacketOverride. The contract is executed
+// with the NFTs it received next to the message in the memo, and the enclave verifies both against the packet.
+func (h WasmHooks) onRecvNftPacketOverride(im IBCMiddleware, ctx sdk.Context, packet channeltypes.Packet, relayer sdk.AccAddress) ibcexported.Acknowledgement {
+	var data types.NonFungibleTokenPacketData
+	if err := json.Unmarshal(packet.GetData(), &data); err != nil {
+		return im.App.OnRecvPacket(ctx, packet, relayer)
+	}
+
+	// Validate the memo
+	isWasmRouted, contractAddr, msgBytes, err := ValidateAndParseMemo(data.Memo, data.Receiver)
+	if !isWasmRouted {
+		return im.App.OnRecvPacket(ctx, packet, relayer)
+	}
+	if err != nil {
+		return NewEmitErrorAcknowledgement(ctx, types.ErrMsgValidation, err.Error())
+	}
+	if msgBytes == nil || contractAddr == nil { // This should never happen
+		return NewEmitErrorAcknowledgement(ctx, types.ErrMsgValidation)
+	}
+
+	// Unlike funds, the NFTs are received by the contract itself, which is the receiver of the packet
+	ack := im.App.OnRecvPacket(ctx, packet, relayer)
+	if !ack.Success() {
+		return ack
+	}
+
+	receiveMsg := types.IbcHooksIncomingNftTransferMsg{
+		ReceiveIbcNfts: types.ReceiveIbcNfts{
+			ClassID:  ExtractClassIDFromPacketOnRecv(packet, data.ClassID),
+			TokenIDs: data.TokenIDs,
+			Sender:   data.Sender,
+			Msg:      msgBytes,
+		},
+	}
+	receiveMsgBytes, err := json.Marshal(receiveMsg)
+	if err != nil {
+		return NewEmitErrorAcknowledgement(ctx, types.ErrMarshaling, err.Error())
+	}
+
+	// Execute the contract
+	execMsg := compute.MsgExecuteContract{
+		// Sender is ignored by the enclave, the contract sees a null msg.sender
+		Sender:   compute.ZeroSender,
+		Contract: contractAddr,
+		Msg:      receiveMsgBytes,
+	}
+	response, err := h.execWasmMsg(ctx, &execMsg, computetypes.HandleTypeIbcWasmHooksIncomingNftTransfer)
+	if err != nil {
+		return NewEmitErrorAcknowledgement(ctx, types.ErrWasmError, err.Error())
+	}
+
+	fullAck := ContractAck{ContractResult: response.Data, IbcAck: ack.Acknowledgement()}
+	bz, err := json.Marshal(fullAck)
+	if err != nil {
+		return NewEmitErrorAcknowledgement(ctx, types.ErrBadResponse, err.Error())
+	}
+
+	return channeltypes.NewResultAcknowledgement(bz)
+}
+
 func (h WasmHooks) execWasmMsg(ctx sdk.Context, execMsg *compute.MsgExecuteContract, handleType computetypes.HandleType) (*sdk.Result, error) {
 	if err := execMsg.ValidateBasic(); err != nil {
 		return nil, fmt.Errorf(types.ErrBadExecutionMsg, err.Error())
@@ -489,6 +552,25 @@ func MustExtractDenomFromPacketOnRecv(packet ibcexported.PacketI) string {
 	return denom
 }
 
+// ExtractClassIDFromPacketOnRecv returns the class ID of the NFTs of an ICS-721 packet as represented in the
+// local chain. Class IDs are traced like the denoms of ICS-20.
+func ExtractClassIDFromPacketOnRecv(packet ibcexported.PacketI, classID string) string {
+	if transfertypes.ReceiverChainIsSource(packet.GetSourcePort(), packet.GetSourceChannel(), classID) {
+		// remove prefix added by sender chain
+		voucherPrefix := transfertypes.GetDenomPrefix(packet.GetSourcePort(), packet.GetSourceChannel())
+		unprefixedClassID := classID[len(voucherPrefix):]
+
+		classTrace := transfertypes.ParseDenomTrace(unprefixedClassID)
+		if classTrace.Path != "" {
+			return classTrace.IBCDenom()
+		}
+		return unprefixedClassID
+	}
+
+	prefixedClassID := transfertypes.GetDenomPrefix(packet.GetDestPort(), packet.GetDestChannel()) + classID
+	return transfertypes.ParseDenomTrace(prefixedClassID).IBCDenom()
+}
+
 // IsAckError checks an IBC acknowledgement to see if it's an error.
 // This is a replacement for ack.Success() which is currently not working on some circumstances
 func IsAckError(acknowledgement []byte) bool {